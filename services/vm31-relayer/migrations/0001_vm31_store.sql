@@ -0,0 +1,40 @@
+-- vm31-relayer Postgres store (`postgres` feature).
+-- Records are stored as the same JSON the Redis backend writes, so both
+-- backends share one wire format.
+
+CREATE TABLE IF NOT EXISTS vm31_batches (
+    id          TEXT PRIMARY KEY,
+    record      JSONB NOT NULL,
+    updated_at  BIGINT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS vm31_notes (
+    commitment  TEXT PRIMARY KEY,
+    record      JSONB NOT NULL,
+    -- true while merkle_root is all zeros (awaiting backfill)
+    pending     BOOLEAN NOT NULL,
+    created_at  BIGINT NOT NULL
+);
+
+-- Partial index: list_pending_notes only touches pending rows, so it stays
+-- cheap with millions of finalized notes.
+CREATE INDEX IF NOT EXISTS vm31_notes_pending_idx
+    ON vm31_notes (created_at) WHERE pending;
+
+CREATE TABLE IF NOT EXISTS vm31_idempotency (
+    key         TEXT PRIMARY KEY,
+    result      TEXT NOT NULL,
+    expires_at  BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS vm31_idempotency_expires_idx
+    ON vm31_idempotency (expires_at);
+
+CREATE TABLE IF NOT EXISTS vm31_rate_limits (
+    key         TEXT PRIMARY KEY,
+    count       INTEGER NOT NULL,
+    expires_at  BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS vm31_rate_limits_expires_idx
+    ON vm31_rate_limits (expires_at);
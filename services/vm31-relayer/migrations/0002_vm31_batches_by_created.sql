@@ -0,0 +1,3 @@
+-- Newest-first batch listing (`list_batches`).
+CREATE INDEX IF NOT EXISTS vm31_batches_created_idx
+    ON vm31_batches (((record->>'created_at')::BIGINT) DESC, id DESC);
@@ -0,0 +1,122 @@
+//! Alerts on sources that keep hitting rate limits.
+//!
+//! Rejections are counted per API key and per client IP over a fixed window.
+//! When either crosses the threshold, one alert is logged under the
+//! `vm31_relayer::abuse_alert` target (route it to a separate sink for paging).
+//! API keys are only ever logged as a truncated SHA-256 digest.
+
+use dashmap::DashMap;
+use sha2::{Digest, Sha256};
+use tracing::warn;
+
+use crate::store::now_epoch;
+
+/// Upper bound on tracked sources; beyond this, new sources are not tracked
+/// until expired windows are evicted.
+const MAX_TRACKED_SOURCES: usize = 10_000;
+
+struct Window {
+    count: u32,
+    started: u64,
+    alerted: bool,
+}
+
+pub struct AbuseTracker {
+    threshold: u32,
+    window_secs: u64,
+    sources: DashMap<String, Window>,
+}
+
+impl AbuseTracker {
+    pub fn new(threshold: u32, window_secs: u64) -> Self {
+        Self {
+            threshold,
+            window_secs,
+            sources: DashMap::new(),
+        }
+    }
+
+    /// Records a rate-limit rejection and alerts once per window when the key
+    /// or the IP exceeds the threshold.
+    pub fn record_rejection(&self, api_key: Option<&str>, client_ip: &str) {
+        let now = now_epoch();
+        let key_hash = api_key.map(hash_key);
+        let mut crossed = false;
+        if let Some(h) = &key_hash {
+            crossed |= self.bump(format!("key:{h}"), now);
+        }
+        crossed |= self.bump(format!("ip:{client_ip}"), now);
+        if crossed {
+            warn!(
+                target: "vm31_relayer::abuse_alert",
+                key_hash = key_hash.as_deref().unwrap_or("-"),
+                client_ip = %client_ip,
+                threshold = self.threshold,
+                window_secs = self.window_secs,
+                "repeated rate-limit rejections (abuse or misconfigured client)"
+            );
+        }
+    }
+
+    /// Returns `true` the first time a source crosses the threshold in its window.
+    fn bump(&self, source: String, now: u64) -> bool {
+        if self.sources.len() >= MAX_TRACKED_SOURCES && !self.sources.contains_key(&source) {
+            self.evict_expired(now);
+            if self.sources.len() >= MAX_TRACKED_SOURCES {
+                return false;
+            }
+        }
+        let mut w = self.sources.entry(source).or_insert(Window {
+            count: 0,
+            started: now,
+            alerted: false,
+        });
+        if now.saturating_sub(w.started) >= self.window_secs {
+            *w = Window {
+                count: 0,
+                started: now,
+                alerted: false,
+            };
+        }
+        w.count = w.count.saturating_add(1);
+        if w.count >= self.threshold && !w.alerted {
+            w.alerted = true;
+            return true;
+        }
+        false
+    }
+
+    fn evict_expired(&self, now: u64) {
+        self.sources
+            .retain(|_, w| now.saturating_sub(w.started) < self.window_secs);
+    }
+}
+
+fn hash_key(api_key: &str) -> String {
+    let digest = Sha256::digest(api_key.as_bytes());
+    hex::encode(&digest[..8])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alerts_once_per_window() {
+        let tracker = AbuseTracker::new(3, 60);
+        let now = now_epoch();
+        assert!(!tracker.bump("ip:1.2.3.4".into(), now));
+        assert!(!tracker.bump("ip:1.2.3.4".into(), now));
+        assert!(tracker.bump("ip:1.2.3.4".into(), now));
+        assert!(!tracker.bump("ip:1.2.3.4".into(), now));
+        // New window resets the count and re-arms the alert
+        assert!(!tracker.bump("ip:1.2.3.4".into(), now + 60));
+    }
+
+    #[test]
+    fn test_key_hash_does_not_contain_key() {
+        let h = hash_key("super-secret-api-key");
+        assert_eq!(h.len(), 16);
+        assert!(!h.contains("secret"));
+    }
+}
@@ -0,0 +1,86 @@
+//! Note amounts and their two-limb encoding.
+//!
+//! Notes carry amounts as `amount_lo + amount_hi * 2^31`, each limb 31 bits.
+//! All summing and comparing of note amounts goes through `NoteAmount` so
+//! overflow is an explicit `None`, never a wrapped value or a panic.
+
+/// Bits per amount limb.
+const LIMB_BITS: u32 = 31;
+const LIMB_MASK: u64 = (1u64 << LIMB_BITS) - 1;
+
+/// Maximum amount: (2^31 - 1) + (2^31 - 1) * 2^31  (matches stwo-ml MAX_NOTE_AMOUNT)
+pub const MAX_NOTE_AMOUNT: u64 = LIMB_MASK + LIMB_MASK * (1u64 << LIMB_BITS);
+
+/// An amount in `0..=MAX_NOTE_AMOUNT`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct NoteAmount(u64);
+
+impl NoteAmount {
+    pub const ZERO: NoteAmount = NoteAmount(0);
+
+    /// Returns `None` above `MAX_NOTE_AMOUNT`.
+    pub fn new(value: u64) -> Option<Self> {
+        (value <= MAX_NOTE_AMOUNT).then_some(Self(value))
+    }
+
+    /// Decodes `(amount_lo, amount_hi)`. Returns `None` if either limb
+    /// exceeds 31 bits.
+    pub fn from_limbs(lo: u32, hi: u32) -> Option<Self> {
+        if u64::from(lo) > LIMB_MASK || u64::from(hi) > LIMB_MASK {
+            return None;
+        }
+        Some(Self(u64::from(lo) | (u64::from(hi) << LIMB_BITS)))
+    }
+
+    /// Encodes as `(amount_lo, amount_hi)`.
+    pub fn to_limbs(self) -> (u32, u32) {
+        ((self.0 & LIMB_MASK) as u32, (self.0 >> LIMB_BITS) as u32)
+    }
+
+    pub fn get(self) -> u64 {
+        self.0
+    }
+
+    /// Sum, or `None` if it would exceed `MAX_NOTE_AMOUNT`.
+    pub fn checked_add(self, other: Self) -> Option<Self> {
+        self.0.checked_add(other.0).and_then(Self::new)
+    }
+
+    /// Difference, or `None` if `other > self`.
+    pub fn checked_sub(self, other: Self) -> Option<Self> {
+        self.0.checked_sub(other.0).map(Self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_max_amount_boundary() {
+        assert_eq!(MAX_NOTE_AMOUNT, (1u64 << 62) - 1);
+        let max = NoteAmount::new(MAX_NOTE_AMOUNT).unwrap();
+        assert!(NoteAmount::new(MAX_NOTE_AMOUNT + 1).is_none());
+        assert_eq!(max.to_limbs(), (LIMB_MASK as u32, LIMB_MASK as u32));
+        assert_eq!(NoteAmount::from_limbs(LIMB_MASK as u32, LIMB_MASK as u32), Some(max));
+        assert!(NoteAmount::from_limbs(1 << 31, 0).is_none());
+    }
+
+    #[test]
+    fn test_limb_roundtrip() {
+        let a = NoteAmount::new(5 * (1u64 << 31) + 7).unwrap();
+        assert_eq!(a.to_limbs(), (7, 5));
+        let (lo, hi) = a.to_limbs();
+        assert_eq!(NoteAmount::from_limbs(lo, hi), Some(a));
+    }
+
+    #[test]
+    fn test_overflow_is_none() {
+        let max = NoteAmount::new(MAX_NOTE_AMOUNT).unwrap();
+        let one = NoteAmount::new(1).unwrap();
+        assert!(max.checked_add(one).is_none());
+        assert_eq!(max.checked_add(NoteAmount::ZERO), Some(max));
+        assert!(one.checked_sub(max).is_none());
+        assert_eq!(max.checked_sub(max), Some(NoteAmount::ZERO));
+    }
+}
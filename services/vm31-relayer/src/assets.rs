@@ -0,0 +1,161 @@
+//! On-chain asset registry checks.
+//!
+//! Denominations are keyed by asset id, and those ids must match the order
+//! assets were registered with `VM31Pool.register_asset()`. At startup the
+//! pool is asked for `get_asset_token(id)` for each id until the first
+//! unregistered one (zero address), and the result is compared with the ids
+//! the relayer has denominations for.
+//!
+//! SECURITY: sncast args come from config (pool address, RPC URL) and
+//! integer ids. Never pass user-controlled strings to Command args.
+
+use serde::Serialize;
+use tokio::process::Command;
+
+/// An asset registered in the pool.
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct OnChainAsset {
+    pub asset_id: u32,
+    /// Token contract address (0x-prefixed felt).
+    pub token: String,
+}
+
+/// A disagreement between the local denomination table and the pool.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AssetMismatch {
+    /// Denominations are configured for an id the pool has not registered.
+    NotRegistered(u32),
+    /// The pool has an asset the relayer has no denominations for, so
+    /// deposits of it are not restricted to standard amounts.
+    NoDenominations(u32),
+}
+
+impl std::fmt::Display for AssetMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AssetMismatch::NotRegistered(id) => {
+                write!(f, "asset {id} has denominations but is not registered in the pool")
+            }
+            AssetMismatch::NoDenominations(id) => {
+                write!(f, "pool asset {id} has no denominations configured")
+            }
+        }
+    }
+}
+
+/// Fetches registered assets, probing ids `0..max_assets`.
+pub async fn fetch_pool_assets(
+    rpc_url: &str,
+    pool_contract: &str,
+    max_assets: u32,
+) -> Result<Vec<OnChainAsset>, String> {
+    let mut assets = Vec::new();
+    for asset_id in 0..max_assets {
+        let token = call_get_asset_token(rpc_url, pool_contract, asset_id).await?;
+        if is_zero_felt(&token) {
+            break;
+        }
+        assets.push(OnChainAsset { asset_id, token });
+    }
+    Ok(assets)
+}
+
+async fn call_get_asset_token(
+    rpc_url: &str,
+    pool_contract: &str,
+    asset_id: u32,
+) -> Result<String, String> {
+    let output = Command::new("sncast")
+        .args([
+            "call",
+            "--contract-address",
+            pool_contract,
+            "--function",
+            "get_asset_token",
+            "--calldata",
+            &asset_id.to_string(),
+            "--url",
+            rpc_url,
+        ])
+        .output()
+        .await
+        .map_err(|e| format!("sncast spawn failed: {e}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "get_asset_token({asset_id}) failed with exit code {:?}",
+            output.status.code()
+        ));
+    }
+    parse_call_response(&String::from_utf8_lossy(&output.stdout))
+        .ok_or_else(|| format!("get_asset_token({asset_id}): unrecognized sncast output"))
+}
+
+/// Extracts the first felt from the `response` line of `sncast call` output
+/// (`response: [0x...]` or `Response: 0x...`).
+fn parse_call_response(stdout: &str) -> Option<String> {
+    let line = stdout
+        .lines()
+        .find(|l| l.trim_start().to_ascii_lowercase().starts_with("response"))?;
+    let value = line.split_once(':')?.1;
+    let start = value.find("0x")?;
+    let felt: String = value[start + 2..]
+        .chars()
+        .take_while(|c| c.is_ascii_hexdigit())
+        .collect();
+    (!felt.is_empty()).then(|| format!("0x{}", felt.to_ascii_lowercase()))
+}
+
+fn is_zero_felt(felt: &str) -> bool {
+    felt.trim_start_matches("0x").chars().all(|c| c == '0')
+}
+
+/// Compares the ids the relayer has denominations for with the pool's registry.
+pub fn check_mapping(onchain: &[OnChainAsset], local_ids: &[u32]) -> Vec<AssetMismatch> {
+    let registered = |id: u32| onchain.iter().any(|a| a.asset_id == id);
+    let mut mismatches: Vec<_> = local_ids
+        .iter()
+        .filter(|&&id| !registered(id))
+        .map(|&id| AssetMismatch::NotRegistered(id))
+        .collect();
+    mismatches.extend(
+        onchain
+            .iter()
+            .filter(|a| !local_ids.contains(&a.asset_id))
+            .map(|a| AssetMismatch::NoDenominations(a.asset_id)),
+    );
+    mismatches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_call_response_formats() {
+        assert_eq!(
+            parse_call_response("command: call\nresponse: [0x049D3657]\n").as_deref(),
+            Some("0x049d3657")
+        );
+        assert_eq!(
+            parse_call_response("Success: Call completed\n\nResponse:     0x0\n").as_deref(),
+            Some("0x0")
+        );
+        assert_eq!(parse_call_response("error: contract not found"), None);
+        assert!(is_zero_felt("0x0000"));
+        assert!(!is_zero_felt("0x01"));
+    }
+
+    #[test]
+    fn test_check_mapping_reports_both_directions() {
+        let onchain = [
+            OnChainAsset { asset_id: 0, token: "0x1".into() },
+            OnChainAsset { asset_id: 1, token: "0x2".into() },
+            OnChainAsset { asset_id: 5, token: "0x3".into() },
+        ];
+        assert_eq!(
+            check_mapping(&onchain, &[0, 1, 2]),
+            vec![AssetMismatch::NotRegistered(2), AssetMismatch::NoDenominations(5)]
+        );
+        assert!(check_mapping(&onchain[..2], &[0, 1]).is_empty());
+    }
+}
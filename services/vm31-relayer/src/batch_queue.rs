@@ -1,23 +1,451 @@
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
+use rand::rngs::StdRng;
 use rand::seq::SliceRandom;
-use rand::thread_rng;
+use rand::{thread_rng, Rng, SeedableRng};
+use serde::Serialize;
 use tokio::sync::{mpsc, Mutex};
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 
 use stwo_ml::privacy::tx_builder::PendingTx;
 
+use crate::denominations::Denominations;
+use crate::metrics::{FlushTrigger, Metrics};
+use crate::queue_wal::{QueueWal, WalEntry};
+use crate::routes::{SubmitRequest, WithdrawalRecipient};
+use crate::store::now_epoch;
+use crate::telemetry::{self, SpanLink};
+
 /// A queued transaction with its assigned batch ID and enqueue time.
 struct QueuedTx {
     tx: PendingTx,
     enqueued_at: Instant,
+    /// API-key-scoped client reference digest, if the client supplied one.
+    client_ref: Option<String>,
+    /// Submission idempotency key, so the client can cancel before a flush.
+    idempotency_key: Option<String>,
+    /// Digest of the submitting API key; only that key may cancel the tx.
+    submitter: Option<String>,
+    /// A withdrawal's payout and credit addresses.
+    recipient: Option<WithdrawalRecipient>,
+    /// This tx's encoded WAL line, kept to rewrite the log after a removal.
+    wal_line: Option<String>,
+    /// The submit span it was queued under; empty after a WAL restore.
+    link: SpanLink,
+}
+
+/// Transaction kinds with independently configurable flush deadlines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum TxKind {
+    Deposit = 0,
+    Withdraw = 1,
+    Transfer = 2,
+}
+
+impl TxKind {
+    pub fn of(tx: &PendingTx) -> Self {
+        match tx {
+            PendingTx::Deposit { .. } => TxKind::Deposit,
+            PendingTx::Withdraw { .. } => TxKind::Withdraw,
+            PendingTx::Transfer { .. } => TxKind::Transfer,
+        }
+    }
+}
+
+pub(crate) fn asset_of(tx: &PendingTx) -> u32 {
+    match tx {
+        PendingTx::Deposit { asset_id, .. }
+        | PendingTx::Withdraw { asset_id, .. }
+        | PendingTx::Transfer { asset_id, .. } => *asset_id,
+    }
+}
+
+/// A set of pending txs that flush together. A `None` field spans every
+/// value: the whole queue is one bucket unless per-asset or per-kind mode
+/// splits it by asset id or `TxKind`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+struct Bucket {
+    asset: Option<u32>,
+    kind: Option<TxKind>,
+}
+
+impl Bucket {
+    fn of(tx: &PendingTx, per_asset: bool, per_kind: bool) -> Self {
+        Self {
+            asset: per_asset.then(|| asset_of(tx)),
+            kind: per_kind.then(|| TxKind::of(tx)),
+        }
+    }
+}
+
+/// The buckets present in `pending`, by ascending asset id, then kind.
+fn buckets(pending: &[QueuedTx], per_asset: bool, per_kind: bool) -> Vec<Bucket> {
+    let keys: BTreeSet<Bucket> = pending.iter().map(|q| Bucket::of(&q.tx, per_asset, per_kind)).collect();
+    keys.into_iter().collect()
+}
+
+fn in_bucket(q: &QueuedTx, bucket: Bucket) -> bool {
+    bucket.asset.is_none_or(|a| asset_of(&q.tx) == a) && bucket.kind.is_none_or(|k| TxKind::of(&q.tx) == k)
+}
+
+/// Removes and returns a bucket's txs, keeping the rest in order.
+fn take_bucket(pending: &mut Vec<QueuedTx>, bucket: Bucket) -> Vec<QueuedTx> {
+    let (taken, kept) = std::mem::take(pending)
+        .into_iter()
+        .partition(|q| in_bucket(q, bucket));
+    *pending = kept;
+    taken
+}
+
+/// Flush deadlines for one transaction kind.
+#[derive(Debug, Clone, Copy)]
+struct Deadlines {
+    timeout: Duration,
+    max_wait: Duration,
+}
+
+/// Decides whether the pending set should flush at `now`.
+///
+/// Each queued tx is measured against its own kind's deadlines, so the
+/// effective timeout is the minimum over the kinds present: one withdrawal
+/// pulls the whole batch forward, while a deposit-only set waits for the
+/// (longer) deposit timeout. `min_batch_size`, and `min_deposit_ratio`
+/// when set, still gate timeout flushes; only a max-wait breach flushes a
+/// set that falls short of them.
+///
+/// With wall-clock alignment, `aligned_boundary` is `Some(crossed)` and
+/// replaces the relative timeouts: a timeout flush only happens when a
+/// boundary was just crossed. Max-wait stays relative to enqueue time.
+///
+/// Returns `Some(max_wait_triggered_below_min)` when a flush is due.
+fn flush_due<'a>(
+    pending: impl IntoIterator<Item = &'a QueuedTx>,
+    now: Instant,
+    deadlines: &[Deadlines; 3],
+    min_batch_size: usize,
+    min_deposit_ratio: Option<f64>,
+    aligned_boundary: Option<bool>,
+) -> Option<bool> {
+    let mut len = 0;
+    let mut kinds = [0usize; 3];
+    let mut timeout_reached = false;
+    let mut max_wait_reached = false;
+    for q in pending {
+        len += 1;
+        let kind = TxKind::of(&q.tx);
+        kinds[kind as usize] += 1;
+        let waited = now.saturating_duration_since(q.enqueued_at);
+        let d = deadlines[kind as usize];
+        timeout_reached |= waited >= d.timeout;
+        max_wait_reached |= waited >= d.max_wait;
+    }
+    if len == 0 {
+        return None;
+    }
+    if let Some(crossed) = aligned_boundary {
+        timeout_reached = crossed;
+    }
+    let has_min = len >= min_batch_size && deposit_ratio_met(kinds, min_deposit_ratio);
+
+    // Flush if:
+    // 1. Normal timeout + enough txs for mixing, OR
+    // 2. Max wait ceiling exceeded (flush even with fewer txs)
+    ((timeout_reached && has_min) || max_wait_reached).then_some(max_wait_reached && !has_min)
+}
+
+/// Txs of each `TxKind` in `pending`, indexed by kind.
+fn kind_counts<'a>(pending: impl IntoIterator<Item = &'a QueuedTx>) -> [usize; 3] {
+    let mut kinds = [0usize; 3];
+    for q in pending {
+        kinds[TxKind::of(&q.tx) as usize] += 1;
+    }
+    kinds
+}
+
+/// Whether a set with these `kind_counts` holds at least `ratio` deposits
+/// per withdrawal. Transfers count as neither; no ratio always passes.
+fn deposit_ratio_met(kinds: [usize; 3], ratio: Option<f64>) -> bool {
+    ratio.is_none_or(|r| {
+        kinds[TxKind::Deposit as usize] as f64 >= r * kinds[TxKind::Withdraw as usize] as f64
+    })
+}
+
+/// Uniform random delay in `0..=max`.
+fn jitter_delay(max: Duration) -> Duration {
+    if max.is_zero() {
+        return Duration::ZERO;
+    }
+    thread_rng().gen_range(Duration::ZERO..=max)
+}
+
+/// Size-flush threshold, drawn from `max_size - size_jitter ..= max_size`.
+fn draw_size_target(max_size: usize, size_jitter: usize) -> usize {
+    if size_jitter == 0 {
+        return max_size;
+    }
+    thread_rng().gen_range(max_size - size_jitter..=max_size)
+}
+
+/// The earliest max-wait deadline among `pending`.
+fn max_wait_deadline<'a>(
+    pending: impl IntoIterator<Item = &'a QueuedTx>,
+    deadlines: &[Deadlines; 3],
+) -> Option<Instant> {
+    pending
+        .into_iter()
+        .map(|q| q.enqueued_at + deadlines[TxKind::of(&q.tx) as usize].max_wait)
+        .min()
+}
+
+/// What the timeout loop does with one bucket on a tick.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BucketTick {
+    Idle,
+    /// A flush was decided; seal once the jitter delay ends at this instant.
+    Wait(Instant),
+    /// Seal now; `true` when forced below min size by max wait.
+    Seal(bool),
+}
+
+/// Decides a bucket's tick. Once `flush_due` fires, sealing is delayed by a
+/// random `0..=jitter` (or until `scheduled`, if an earlier tick already
+/// drew one), but never past the first tx's max-wait deadline.
+fn tick_bucket<'a, I>(
+    pending: I,
+    now: Instant,
+    deadlines: &[Deadlines; 3],
+    min_batch_size: usize,
+    min_deposit_ratio: Option<f64>,
+    aligned_boundary: Option<bool>,
+    scheduled: Option<Instant>,
+    jitter: Duration,
+) -> BucketTick
+where
+    I: IntoIterator<Item = &'a QueuedTx> + Clone,
+{
+    let due_now = flush_due(pending.clone(), now, deadlines, min_batch_size, min_deposit_ratio, aligned_boundary);
+    let Some(seal_at) = scheduled.or_else(|| due_now.map(|_| now + jitter_delay(jitter))) else {
+        return BucketTick::Idle;
+    };
+    let seal_at = max_wait_deadline(pending.clone(), deadlines).map_or(seal_at, |d| seal_at.min(d));
+    if now < seal_at {
+        return BucketTick::Wait(seal_at);
+    }
+    // A decided flush stays due across an aligned boundary's later ticks,
+    // but is dropped if cancellations took the bucket below min size.
+    let recheck = || {
+        flush_due(pending, now, deadlines, min_batch_size, min_deposit_ratio, aligned_boundary.map(|_| true))
+    };
+    match due_now.or_else(recheck) {
+        Some(max_wait_triggered) => BucketTick::Seal(max_wait_triggered),
+        None => BucketTick::Idle,
+    }
+}
+
+/// Flushed txs whose waits feed `QueueStats::avg_wait_ms`.
+const WAIT_WINDOW: usize = 1024;
+
+/// Snapshot of queue depth and flush behaviour, for tuning
+/// `VM31_MIN_BATCH_SIZE` / `VM31_MAX_BATCH_WAIT_SECS` (in `/status`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct QueueStats {
+    pub depth: usize,
+    /// How long the oldest queued tx has waited (`None` when empty).
+    pub oldest_age_ms: Option<u64>,
+    /// Flushes forced by a max-wait ceiling, typically below min batch size.
+    pub max_wait_flushes: u64,
+    /// Size-, timeout- and force-triggered flushes.
+    pub normal_flushes: u64,
+    /// Mean enqueue-to-flush wait over the last `WAIT_WINDOW` flushed txs.
+    pub avg_wait_ms: Option<u64>,
+}
+
+/// Flush counters and recent waits behind `QueueStats`.
+#[derive(Default)]
+struct FlushStats {
+    max_wait_flushes: u64,
+    normal_flushes: u64,
+    waits: VecDeque<Duration>,
+    wait_sum: Duration,
+}
+
+impl FlushStats {
+    /// Records a drained batch. Call before `from_queued` consumes it.
+    fn record(&mut self, queued: &[QueuedTx], now: Instant, max_wait: bool) {
+        if max_wait {
+            self.max_wait_flushes += 1;
+        } else {
+            self.normal_flushes += 1;
+        }
+        for q in queued {
+            let wait = now.saturating_duration_since(q.enqueued_at);
+            if self.waits.len() == WAIT_WINDOW {
+                if let Some(old) = self.waits.pop_front() {
+                    self.wait_sum -= old;
+                }
+            }
+            self.waits.push_back(wait);
+            self.wait_sum += wait;
+        }
+    }
+
+    fn avg_wait(&self) -> Option<Duration> {
+        (!self.waits.is_empty()).then(|| self.wait_sum / self.waits.len() as u32)
+    }
+}
+
+/// Result of `BatchQueue::force_flush`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ForceFlushOutcome {
+    /// One batch per flushed bucket (several only in per-asset or per-kind mode).
+    Flushed(Vec<String>),
+    /// Nothing was queued.
+    Empty,
+    /// Txs are queued, but no bucket reaches `min_batch_size`.
+    BelowMinSize { pending: usize, min_batch_size: usize },
+    /// Another flush (size, timeout or force) drained the queue while this
+    /// call waited for the lock, so there was nothing left to flush.
+    ConcurrentlyDrained,
+}
+
+impl ForceFlushOutcome {
+    /// The flushed batch IDs; empty for every other outcome.
+    pub fn batch_ids(&self) -> &[String] {
+        match self {
+            ForceFlushOutcome::Flushed(ids) => ids,
+            _ => &[],
+        }
+    }
+}
+
+/// Entropy source for the batch shuffle.
+///
+/// The shuffle is what unlinks submission order from batch position, so
+/// production must use a CSPRNG. `Seeded` reproduces every permutation from
+/// its seed and exists only for tests.
+pub enum ShuffleRng {
+    /// `rand::thread_rng()`: ChaCha12, seeded and periodically reseeded from
+    /// the OS. The default.
+    Os,
+    /// `StdRng` from a fixed seed (`VM31_SHUFFLE_SEED`). TEST ONLY.
+    Seeded { seed: u64, rng: std::sync::Mutex<StdRng> },
+}
+
+// Compile-time guard: the production source must stay a `CryptoRng`.
+const _: () = {
+    const fn assert_crypto_rng<R: rand::CryptoRng>() {}
+    assert_crypto_rng::<rand::rngs::ThreadRng>();
+};
+
+impl ShuffleRng {
+    pub fn seeded(seed: u64) -> Self {
+        Self::Seeded {
+            seed,
+            rng: std::sync::Mutex::new(StdRng::seed_from_u64(seed)),
+        }
+    }
+
+    /// False for a seeded source, whose permutations anyone with the seed
+    /// can recompute.
+    pub fn is_secure(&self) -> bool {
+        matches!(self, Self::Os)
+    }
+
+    /// Human-readable source, reported in `/status`.
+    pub fn describe(&self) -> String {
+        match self {
+            Self::Os => "thread_rng (ChaCha12, OS-seeded CSPRNG)".into(),
+            Self::Seeded { seed, .. } => format!("seeded StdRng (seed {seed}, TEST ONLY)"),
+        }
+    }
+
+    fn shuffle<T>(&self, items: &mut [T]) {
+        match self {
+            Self::Os => items.shuffle(&mut thread_rng()),
+            Self::Seeded { rng, .. } => items.shuffle(&mut *rng.lock().unwrap_or_else(|e| e.into_inner())),
+        }
+    }
+}
+
+/// A tx handed back by the prover, with its client ref, idempotency key,
+/// submitter digest and withdrawal recipient (see `BatchQueue::requeue`).
+pub type DeclinedTx = (PendingTx, Option<String>, Option<String>, Option<String>, Option<WithdrawalRecipient>);
+
+/// Result of `BatchQueue::remove_by_key`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Removal {
+    /// Removed; this many txs remain queued.
+    Removed(usize),
+    /// Queued under that key by a different API key, and left in place.
+    NotOwner,
+    /// No pending tx has that key (never queued, or already flushed).
+    NotQueued,
 }
 
 /// A flushed batch ready for proving.
 pub struct ReadyBatch {
     pub batch_id: String,
+    /// The asset every tx shares, in per-asset mode.
+    pub asset_id: Option<u32>,
     pub transactions: Vec<PendingTx>,
+    /// Scoped client references, index-aligned with `transactions`.
+    pub client_refs: Vec<Option<String>>,
+    /// Submission idempotency keys, index-aligned with `transactions`.
+    pub idempotency_keys: Vec<Option<String>>,
+    /// Submitter digests, index-aligned with `transactions`.
+    pub submitters: Vec<Option<String>>,
+    /// Withdrawal recipients, index-aligned with `transactions`.
+    pub recipients: Vec<Option<WithdrawalRecipient>>,
+    /// Root span of the batch's trace, linked to its transactions' submits.
+    pub span: tracing::Span,
+}
+
+impl ReadyBatch {
+    /// Shuffles the drained entries (Fisher-Yates, drawing from `rng`) and
+    /// splits them into a batch. Client refs, idempotency keys and recipients
+    /// are shuffled together with their transactions. In deterministic mode the
+    /// submission order is kept.
+    fn from_queued(
+        batch_id: String,
+        asset_id: Option<u32>,
+        mut queued: Vec<QueuedTx>,
+        deterministic: bool,
+        rng: &ShuffleRng,
+    ) -> Self {
+        if !deterministic {
+            rng.shuffle(&mut queued);
+        }
+        let mut transactions = Vec::with_capacity(queued.len());
+        let mut client_refs = Vec::with_capacity(queued.len());
+        let mut idempotency_keys = Vec::with_capacity(queued.len());
+        let mut submitters = Vec::with_capacity(queued.len());
+        let mut recipients = Vec::with_capacity(queued.len());
+        let mut links = Vec::with_capacity(queued.len());
+        for q in queued {
+            transactions.push(q.tx);
+            client_refs.push(q.client_ref);
+            idempotency_keys.push(q.idempotency_key);
+            submitters.push(q.submitter);
+            recipients.push(q.recipient);
+            links.push(q.link);
+        }
+        let assets: BTreeSet<u32> = transactions.iter().map(asset_of).collect();
+        let asset_ids = assets.iter().map(u32::to_string).collect::<Vec<_>>().join(",");
+        let span = telemetry::batch_span(&batch_id, &asset_ids, transactions.len(), links);
+        Self {
+            batch_id,
+            asset_id,
+            transactions,
+            client_refs,
+            idempotency_keys,
+            submitters,
+            recipients,
+            span,
+        }
+    }
 }
 
 /// Accumulates `PendingTx` items and flushes when either the size threshold
@@ -26,13 +454,42 @@ pub struct ReadyBatch {
 pub struct BatchQueue {
     pending: Arc<Mutex<Vec<QueuedTx>>>,
     max_size: usize,
-    timeout: Duration,
     /// Minimum transactions required for a timeout-triggered flush.
     /// Prevents single-tx batches that offer zero mixing.
     min_batch_size: usize,
-    /// Hard ceiling on how long any transaction can wait in queue.
-    /// Prevents indefinite queueing when min_batch_size is not met.
-    max_wait: Duration,
+    /// Deposits required per withdrawal before a size or timeout flush.
+    min_deposit_ratio: Option<f64>,
+    /// Timeout and hard max-wait ceiling per `TxKind`. All kinds start at
+    /// the queue-wide values; see `set_kind_deadlines`.
+    deadlines: [Deadlines; 3],
+    /// When set, timeout flushes happen only at wall-clock multiples of this
+    /// many seconds instead of relative to enqueue time.
+    flush_align_secs: Option<u64>,
+    /// Upper bound of the random delay between a timeout flush falling due
+    /// and the batch being sealed.
+    flush_jitter: Duration,
+    /// Size flushes fire at a random size up to this many below `max_size`.
+    size_jitter: usize,
+    /// The current size-flush threshold, redrawn after each size flush.
+    size_target: AtomicUsize,
+    /// TEST ONLY: skip shuffling so batch contents are predictable.
+    deterministic: bool,
+    /// Entropy for the shuffle (a CSPRNG unless a test seeds it).
+    shuffle_rng: Arc<ShuffleRng>,
+    /// Keep one sub-queue per asset id, each flushing on its own size and
+    /// deadlines, so no batch mixes assets.
+    per_asset: bool,
+    /// Likewise one sub-queue per `TxKind`, so deposits, withdrawals and
+    /// transfers never share a batch.
+    per_kind: bool,
+    /// Bumped (under the `pending` lock) by every flush, so `force_flush`
+    /// can tell that another flush ran while it waited for the lock.
+    flush_seq: Arc<AtomicU64>,
+    /// Flush counters and waits for `stats`.
+    flush_stats: Arc<std::sync::Mutex<FlushStats>>,
+    metrics: Option<Arc<Metrics>>,
+    /// Durable copy of the pending set (see `queue_wal`).
+    wal: Option<Arc<QueueWal>>,
     trigger_tx: mpsc::Sender<ReadyBatch>,
 }
 
@@ -48,6 +505,12 @@ impl BatchQueue {
         Self::with_min_batch(max_size, timeout_secs, channel_buffer, 1, 300)
     }
 
+    /// False once the prover has dropped its receiver (it exited or
+    /// panicked), after which flushed batches have nowhere to go.
+    pub fn prover_connected(&self) -> bool {
+        !self.trigger_tx.is_closed()
+    }
+
     /// Creates a `BatchQueue` with configurable minimum batch size and max wait.
     ///
     /// - `min_batch_size`: Timeout flush only fires if `len >= min_batch_size`.
@@ -61,164 +524,499 @@ impl BatchQueue {
         max_batch_wait_secs: u64,
     ) -> (Self, mpsc::Receiver<ReadyBatch>) {
         let (trigger_tx, trigger_rx) = mpsc::channel(channel_buffer);
+        let deadlines = Deadlines {
+            timeout: Duration::from_secs(timeout_secs),
+            max_wait: Duration::from_secs(max_batch_wait_secs),
+        };
         let queue = Self {
             pending: Arc::new(Mutex::new(Vec::with_capacity(max_size))),
             max_size,
-            timeout: Duration::from_secs(timeout_secs),
             min_batch_size: min_batch_size.max(1),
-            max_wait: Duration::from_secs(max_batch_wait_secs),
+            min_deposit_ratio: None,
+            deadlines: [deadlines; 3],
+            flush_align_secs: None,
+            flush_jitter: Duration::ZERO,
+            size_jitter: 0,
+            size_target: AtomicUsize::new(max_size),
+            deterministic: false,
+            shuffle_rng: Arc::new(ShuffleRng::Os),
+            per_asset: false,
+            per_kind: false,
+            flush_seq: Arc::new(AtomicU64::new(0)),
+            flush_stats: Arc::new(std::sync::Mutex::new(FlushStats::default())),
+            metrics: None,
+            wal: None,
             trigger_tx,
         };
         (queue, trigger_rx)
     }
 
-    /// Fisher-Yates shuffle a transaction vec for privacy.
-    fn shuffle_txs(txs: &mut Vec<PendingTx>) {
-        txs.shuffle(&mut thread_rng());
+    /// Overrides the timeout and max-wait for one transaction kind, e.g. to
+    /// flush withdrawals sooner than deposits. Must be called before
+    /// `spawn_timeout_loop`.
+    pub fn set_kind_deadlines(&mut self, kind: TxKind, timeout_secs: u64, max_wait_secs: u64) {
+        self.deadlines[kind as usize] = Deadlines {
+            timeout: Duration::from_secs(timeout_secs),
+            max_wait: Duration::from_secs(max_wait_secs),
+        };
+    }
+
+    /// Aligns timeout flushes to wall-clock boundaries (e.g. 60 = on the
+    /// minute). Min-batch-size still applies at each boundary and max-wait
+    /// still flushes at any time. Must be called before `spawn_timeout_loop`.
+    pub fn set_flush_alignment(&mut self, align_secs: Option<u64>) {
+        self.flush_align_secs = align_secs.filter(|&a| a > 0);
+    }
+
+    /// Randomizes flush timing: a due timeout flush is sealed after a random
+    /// `0..=jitter_secs` delay (never past a tx's max wait), and size flushes
+    /// fire at a random size in `max_size - size_jitter ..= max_size`. Must
+    /// be called before `spawn_timeout_loop`.
+    pub fn set_flush_jitter(&mut self, jitter_secs: u64, size_jitter: usize) {
+        self.flush_jitter = Duration::from_secs(jitter_secs);
+        self.size_jitter = size_jitter.min(self.max_size.saturating_sub(1));
+        *self.size_target.get_mut() = draw_size_target(self.max_size, self.size_jitter);
+    }
+
+    /// Holds size and timeout flushes until a bucket has at least `ratio`
+    /// deposits per withdrawal (transfers count as neither), so
+    /// withdrawal-heavy batches wait for deposits. `max_wait` and `max_size`
+    /// stay hard ceilings: a bucket that never gets there is still flushed
+    /// at its max wait, or once it is full, so withdrawals never hang.
+    /// Force flushes ignore the ratio. Must be called before
+    /// `spawn_timeout_loop`.
+    pub fn set_min_deposit_ratio(&mut self, ratio: Option<f64>) {
+        self.min_deposit_ratio = ratio.filter(|r| *r > 0.0);
+    }
+
+    /// Partitions the queue by asset id: each asset's txs are batched (and
+    /// shuffled) only with each other, against `max_size`, `min_batch_size`
+    /// and the deadlines separately. Must be called before `spawn_timeout_loop`.
+    pub fn set_per_asset(&mut self, enabled: bool) {
+        self.per_asset = enabled;
+    }
+
+    /// Partitions the queue by `TxKind`, like `set_per_asset` does by asset
+    /// (the two combine). Keeps an observer from correlating a deposit and a
+    /// withdrawal settled in the same proof. Must be called before
+    /// `spawn_timeout_loop`.
+    pub fn set_per_kind(&mut self, enabled: bool) {
+        self.per_kind = enabled;
+    }
+
+    /// Counts flushes by trigger. Must be called before `spawn_timeout_loop`.
+    pub fn set_metrics(&mut self, metrics: Arc<Metrics>) {
+        self.metrics = Some(metrics);
+    }
+
+    /// Logs accepted txs to `wal` until they are flushed. Must be called
+    /// before `spawn_timeout_loop`; call `restore_from_wal` to replay it.
+    pub fn set_wal(&mut self, wal: QueueWal) {
+        self.wal = Some(Arc::new(wal));
+    }
+
+    /// Replays txs left in the WAL by a previous run, in their original
+    /// order and with their original enqueue times. Returns how many were
+    /// restored. The WAL keeps them until they are flushed.
+    pub async fn restore_from_wal(&self) -> Result<usize, String> {
+        let Some(wal) = &self.wal else {
+            return Ok(0);
+        };
+        let entries = wal
+            .load()
+            .await
+            .map_err(|e| format!("read {}: {e}", wal.path().display()))?;
+        let now_instant = Instant::now();
+        let now = now_epoch();
+        // Already checked against the live denomination table when accepted
+        let unrestricted = Denominations::unrestricted();
+        let mut pending = self.pending.lock().await;
+        for entry in entries {
+            let converted = entry
+                .tx
+                .validate_and_convert(&unrestricted)
+                .and_then(|tx| Ok((tx, entry.tx.withdrawal_recipient()?)));
+            let (tx, recipient) = match converted {
+                Ok(converted) => converted,
+                Err(e) => {
+                    warn!(error = %e, "dropping invalid queue WAL entry");
+                    continue;
+                }
+            };
+            let waited = Duration::from_secs(now.saturating_sub(entry.enqueued_at));
+            let wal_line = wal.encode(&entry).ok();
+            pending.push(QueuedTx {
+                tx,
+                enqueued_at: now_instant.checked_sub(waited).unwrap_or(now_instant),
+                client_ref: entry.scoped_client_ref,
+                idempotency_key: entry.idempotency_key,
+                submitter: entry.submitter,
+                recipient,
+                wal_line,
+                link: SpanLink::default(),
+            });
+        }
+        // Rewrite so dropped or torn lines don't linger
+        persist_pending(Some(wal.as_ref()), &pending).await;
+        Ok(pending.len())
+    }
+
+    fn record_drain(&self, queued: &[QueuedTx], max_wait: bool) {
+        lock_stats(&self.flush_stats).record(queued, Instant::now(), max_wait);
+    }
+
+    fn record_flush(&self, trigger: FlushTrigger) {
+        if let Some(m) = &self.metrics {
+            m.record_flush(trigger);
+        }
+    }
+
+    /// TEST ONLY: keep submission order instead of shuffling.
+    /// Must be called before `spawn_timeout_loop`.
+    pub fn set_deterministic(&mut self, enabled: bool) {
+        self.deterministic = enabled;
+    }
+
+    /// Replaces the shuffle's entropy source. Must be called before
+    /// `spawn_timeout_loop`.
+    pub fn set_shuffle_rng(&mut self, rng: ShuffleRng) {
+        self.shuffle_rng = Arc::new(rng);
+    }
+
+    /// The shuffle's entropy source.
+    pub fn shuffle_rng(&self) -> &ShuffleRng {
+        &self.shuffle_rng
     }
 
     /// Adds a transaction to the queue.
     ///
-    /// If the queue reaches `max_size`, it is immediately flushed and the
-    /// batch ID is returned. Otherwise, the tx is held until timeout.
-    /// Returns `(batch_id_if_flushed, queue_len)`.
+    /// If the queue (or, in per-asset or per-kind mode, the tx's bucket)
+    /// reaches `max_size`, it is immediately flushed and the batch ID is
+    /// returned. Otherwise, the tx is held until timeout.
+    /// Returns `(batch_id_if_flushed, queue_len)`; `queue_len` counts the
+    /// tx's bucket only in per-asset or per-kind mode.
     pub async fn push(&self, tx: PendingTx) -> (Option<String>, usize) {
+        self.push_with_ref(tx, None).await
+    }
+
+    /// Like `push`, carrying an opaque scoped client reference with the tx.
+    pub async fn push_with_ref(
+        &self,
+        tx: PendingTx,
+        client_ref: Option<String>,
+    ) -> (Option<String>, usize) {
+        self.push_keyed(tx, client_ref, None, None, None).await
+    }
+
+    /// Like `push_with_ref`, also recording the submission's idempotency key
+    /// and submitter so that key can withdraw the tx with `remove_by_key`
+    /// until it is flushed, and a withdrawal's recipients for the relay flow.
+    pub async fn push_keyed(
+        &self,
+        tx: PendingTx,
+        client_ref: Option<String>,
+        idempotency_key: Option<String>,
+        submitter: Option<String>,
+        recipient: Option<WithdrawalRecipient>,
+    ) -> (Option<String>, usize) {
         let mut pending = self.pending.lock().await;
+        let wal_line = match &self.wal {
+            Some(wal) => append_to_wal(wal, &tx, &client_ref, &idempotency_key, &submitter, &recipient).await,
+            None => None,
+        };
+        let bucket = Bucket::of(&tx, self.per_asset, self.per_kind);
         pending.push(QueuedTx {
             tx,
             enqueued_at: Instant::now(),
+            client_ref,
+            idempotency_key,
+            submitter,
+            recipient,
+            wal_line,
+            link: SpanLink::current(),
         });
-        let len = pending.len();
+        let len = pending.iter().filter(|q| in_bucket(q, bucket)).count();
+        // Short of the deposit ratio, keep filling up to the hard max size
+        let composition_ok = || {
+            let kinds = kind_counts(pending.iter().filter(|q| in_bucket(q, bucket)));
+            len >= self.max_size || deposit_ratio_met(kinds, self.min_deposit_ratio)
+        };
 
-        if len >= self.max_size {
+        if len >= self.size_target.load(Ordering::Relaxed) && composition_ok() {
+            self.size_target
+                .store(draw_size_target(self.max_size, self.size_jitter), Ordering::Relaxed);
             let batch_id = Uuid::new_v4().to_string();
-            let mut txs: Vec<PendingTx> = pending.drain(..).map(|q| q.tx).collect();
-            Self::shuffle_txs(&mut txs);
-            info!(batch_id = %batch_id, tx_count = txs.len(), "batch queue size-triggered flush (shuffled)");
-            if self
-                .trigger_tx
-                .send(ReadyBatch {
-                    batch_id: batch_id.clone(),
-                    transactions: txs,
-                })
-                .await
-                .is_err()
+            let queued = take_bucket(&mut pending, bucket);
+            self.flush_seq.fetch_add(1, Ordering::Relaxed);
+            self.record_drain(&queued, false);
+            let ready = ReadyBatch::from_queued(batch_id.clone(), bucket.asset, queued, self.deterministic, &self.shuffle_rng);
+            persist_pending(self.wal.as_deref(), &pending).await;
+            // Send without the lock: a paused prover with a full channel must
+            // not stall every other submit, status and cancel call
+            drop(pending);
+            info!(batch_id = %batch_id, asset_id = ?bucket.asset, kind = ?bucket.kind, tx_count = ready.transactions.len(), "batch queue size-triggered flush (shuffled)");
+            if self.trigger_tx.send(ready).await.is_err()
             {
                 error!(batch_id = %batch_id, "batch channel closed: size-triggered batch dropped");
+            } else {
+                self.record_flush(FlushTrigger::Size);
             }
             return (Some(batch_id), 0);
         }
         (None, len)
     }
 
+    /// Puts txs the prover declined back in the queue, with their client refs,
+    /// idempotency keys and recipients. Their deadlines restart, and they never trigger
+    /// a size flush themselves: declined txs wait for new traffic or a
+    /// timeout rather than being re-sealed into the same batch at once.
+    /// Returns the queue length.
+    pub async fn requeue(&self, txs: Vec<DeclinedTx>) -> usize {
+        let mut pending = self.pending.lock().await;
+        for (tx, client_ref, idempotency_key, submitter, recipient) in txs {
+            let wal_line = match &self.wal {
+                Some(wal) => append_to_wal(wal, &tx, &client_ref, &idempotency_key, &submitter, &recipient).await,
+                None => None,
+            };
+            pending.push(QueuedTx {
+                tx,
+                enqueued_at: Instant::now(),
+                client_ref,
+                idempotency_key,
+                submitter,
+                recipient,
+                wal_line,
+                link: SpanLink::default(),
+            });
+        }
+        pending.len()
+    }
+
+    /// Removes the tx `submitter` queued with `idempotency_key`, keeping the
+    /// order of the rest. A tx queued by another submitter stays queued.
+    ///
+    /// Takes the same lock as every flush, so a tx is either removed here or
+    /// part of exactly one batch, never both.
+    pub async fn remove_by_key(&self, idempotency_key: &str, submitter: &str) -> Removal {
+        let mut pending = self.pending.lock().await;
+        let Some(idx) = pending
+            .iter()
+            .position(|q| q.idempotency_key.as_deref() == Some(idempotency_key))
+        else {
+            return Removal::NotQueued;
+        };
+        if pending[idx].submitter.as_deref() != Some(submitter) {
+            return Removal::NotOwner;
+        }
+        pending.remove(idx);
+        persist_pending(self.wal.as_deref(), &pending).await;
+        Removal::Removed(pending.len())
+    }
+
     /// Returns the current number of pending transactions.
     pub async fn pending_count(&self) -> usize {
         self.pending.lock().await.len()
     }
 
+    /// Current depth, oldest wait, flush-type counts and average wait.
+    pub async fn stats(&self) -> QueueStats {
+        let (depth, oldest) = {
+            let pending = self.pending.lock().await;
+            (pending.len(), pending.iter().map(|q| q.enqueued_at).min())
+        };
+        let now = Instant::now();
+        let flush = lock_stats(&self.flush_stats);
+        QueueStats {
+            depth,
+            oldest_age_ms: oldest.map(|t| now.saturating_duration_since(t).as_millis() as u64),
+            max_wait_flushes: flush.max_wait_flushes,
+            normal_flushes: flush.normal_flushes,
+            avg_wait_ms: flush.avg_wait().map(|d| d.as_millis() as u64),
+        }
+    }
+
     /// Forcibly flushes the queue. Enforces min_batch_size to prevent
     /// single-tx batches that defeat mixing privacy.
-    /// Returns `None` if the queue is empty or below min_batch_size.
-    pub async fn force_flush(&self) -> Option<String> {
-        let mut pending = self.pending.lock().await;
-        if pending.is_empty() {
-            return None;
-        }
-        // Enforce min_batch_size even for force flushes — a 1-tx batch
-        // provides zero anonymity set, defeating the privacy guarantee.
-        if pending.len() < self.min_batch_size {
-            info!(
-                pending = pending.len(),
-                min = self.min_batch_size,
-                "force_flush rejected: below min_batch_size"
-            );
-            return None;
+    /// In per-asset or per-kind mode each bucket is flushed separately, and
+    /// only buckets holding at least min_batch_size txs.
+    ///
+    /// A size- or timeout-triggered flush can win the lock first; the outcome
+    /// then says `ConcurrentlyDrained` rather than `Empty`/`BelowMinSize`, so
+    /// callers can tell their txs were batched by that other flush.
+    pub async fn force_flush(&self) -> ForceFlushOutcome {
+        let seen = self.flush_seq.load(Ordering::Relaxed);
+        let (batches, not_flushed) = {
+            let mut pending = self.pending.lock().await;
+            let raced = self.flush_seq.load(Ordering::Relaxed) != seen;
+            let mut batches = Vec::new();
+            for bucket in buckets(&pending, self.per_asset, self.per_kind) {
+                let count = pending.iter().filter(|q| in_bucket(q, bucket)).count();
+                // Enforce min_batch_size even for force flushes — a 1-tx batch
+                // provides zero anonymity set, defeating the privacy guarantee.
+                if count < self.min_batch_size {
+                    info!(
+                        asset_id = ?bucket.asset,
+                        kind = ?bucket.kind,
+                        pending = count,
+                        min = self.min_batch_size,
+                        "force_flush rejected: below min_batch_size"
+                    );
+                    continue;
+                }
+                let batch_id = Uuid::new_v4().to_string();
+                let queued = take_bucket(&mut pending, bucket);
+                self.flush_seq.fetch_add(1, Ordering::Relaxed);
+                self.record_drain(&queued, false);
+                let ready = ReadyBatch::from_queued(batch_id, bucket.asset, queued, self.deterministic, &self.shuffle_rng);
+                info!(batch_id = %ready.batch_id, asset_id = ?bucket.asset, kind = ?bucket.kind, tx_count = ready.transactions.len(), "batch queue force-flushed (shuffled)");
+                batches.push(ready);
+            }
+            if !batches.is_empty() {
+                persist_pending(self.wal.as_deref(), &pending).await;
+            }
+            let not_flushed = if raced {
+                ForceFlushOutcome::ConcurrentlyDrained
+            } else if pending.is_empty() {
+                ForceFlushOutcome::Empty
+            } else {
+                ForceFlushOutcome::BelowMinSize {
+                    pending: pending.len(),
+                    min_batch_size: self.min_batch_size,
+                }
+            };
+            (batches, not_flushed)
+        };
+
+        // Sent after releasing the lock, like the timeout loop's flushes
+        let mut flushed = Vec::new();
+        for ready in batches {
+            let batch_id = ready.batch_id.clone();
+            if self.trigger_tx.send(ready).await.is_err() {
+                error!(batch_id = %batch_id, "batch channel closed: force-flushed batch dropped");
+                continue;
+            }
+            self.record_flush(FlushTrigger::Force);
+            flushed.push(batch_id);
         }
-        let batch_id = Uuid::new_v4().to_string();
-        let mut txs: Vec<PendingTx> = pending.drain(..).map(|q| q.tx).collect();
-        Self::shuffle_txs(&mut txs);
-        info!(batch_id = %batch_id, tx_count = txs.len(), "batch queue force-flushed (shuffled)");
-        if self
-            .trigger_tx
-            .send(ReadyBatch {
-                batch_id: batch_id.clone(),
-                transactions: txs,
-            })
-            .await
-            .is_err()
-        {
-            error!(batch_id = %batch_id, "batch channel closed: force-flushed batch dropped");
-            return None;
+        if !flushed.is_empty() {
+            ForceFlushOutcome::Flushed(flushed)
+        } else {
+            if not_flushed == ForceFlushOutcome::ConcurrentlyDrained {
+                info!("force_flush found the queue already drained by a concurrent flush");
+            }
+            not_flushed
         }
-        Some(batch_id)
     }
 
     /// Spawns a background task that periodically checks for timeout-based flushes.
     ///
     /// Respects `min_batch_size`: a normal timeout flush only fires if the queue
     /// has at least `min_batch_size` items. However, `max_wait` is an absolute
-    /// ceiling — if any transaction has waited longer than `max_wait`, the queue
-    /// flushes regardless to prevent indefinite queueing.
+    /// ceiling — if any transaction has waited longer than its kind's `max_wait`,
+    /// the queue flushes regardless to prevent indefinite queueing. Deadlines
+    /// are per `TxKind` (see `flush_due`); the whole pending set is flushed and
+    /// shuffled together, so a short withdrawal deadline also moves any queued
+    /// deposits into that batch. In per-asset or per-kind mode all of this
+    /// applies to each bucket on its own (per kind, each kind keeps its own
+    /// deadlines).
     ///
     /// This should be called once at startup. The task runs until the sender
     /// is dropped or the runtime shuts down.
     pub fn spawn_timeout_loop(&self) {
         let pending = Arc::clone(&self.pending);
-        let timeout = self.timeout;
+        let deadlines = self.deadlines;
         let min_batch_size = self.min_batch_size;
-        let max_wait = self.max_wait;
+        let min_deposit_ratio = self.min_deposit_ratio;
+        let deterministic = self.deterministic;
+        let shuffle_rng = Arc::clone(&self.shuffle_rng);
+        let per_asset = self.per_asset;
+        let per_kind = self.per_kind;
+        let flush_seq = Arc::clone(&self.flush_seq);
+        let flush_stats = Arc::clone(&self.flush_stats);
+        let flush_align_secs = self.flush_align_secs;
+        let flush_jitter = self.flush_jitter;
+        let metrics = self.metrics.clone();
+        let wal = self.wal.clone();
         let trigger_tx = self.trigger_tx.clone();
 
         tokio::spawn(async move {
             let mut interval = tokio::time::interval(Duration::from_secs(1));
+            let mut last_slot: Option<u64> = None;
+            // Buckets already due, waiting out their jitter delay
+            let mut scheduled: HashMap<Bucket, Instant> = HashMap::new();
             loop {
                 interval.tick().await;
 
+                // In aligned mode, a boundary is "crossed" on the first tick of
+                // each new wall-clock slot (never on the very first tick).
+                let aligned_boundary = flush_align_secs.map(|align| {
+                    let slot = now_epoch() / align;
+                    let crossed = last_slot.is_some_and(|l| l != slot);
+                    last_slot = Some(slot);
+                    crossed
+                });
+
                 // Hold the lock for the entire check-and-drain to prevent
                 // a TOCTOU race where another task drains between our check
                 // and our drain.
-                let batch = {
+                let batches = {
                     let mut guard = pending.lock().await;
-                    if guard.is_empty() {
-                        None
-                    } else {
-                        let oldest_elapsed = guard
-                            .first()
-                            .map(|oldest| oldest.enqueued_at.elapsed())
-                            .unwrap_or_default();
-                        let timeout_reached = oldest_elapsed >= timeout;
-                        let max_wait_reached = oldest_elapsed >= max_wait;
-                        let has_min = guard.len() >= min_batch_size;
-
-                        // Flush if:
-                        // 1. Normal timeout + enough txs for mixing, OR
-                        // 2. Max wait ceiling exceeded (flush even with fewer txs)
-                        let should_flush = (timeout_reached && has_min) || max_wait_reached;
-
-                        if should_flush {
-                            let batch_id = Uuid::new_v4().to_string();
-                            let mut txs: Vec<PendingTx> = guard.drain(..).map(|q| q.tx).collect();
-                            txs.shuffle(&mut thread_rng());
-                            debug!(
-                                batch_id = %batch_id,
-                                tx_count = txs.len(),
-                                max_wait_triggered = max_wait_reached && !has_min,
-                                "batch queue timeout-triggered flush (shuffled)"
-                            );
-                            Some(ReadyBatch {
-                                batch_id,
-                                transactions: txs,
-                            })
+                    let now = Instant::now();
+                    let mut batches = Vec::new();
+                    let mut still_scheduled = HashMap::new();
+                    for bucket in buckets(&guard, per_asset, per_kind) {
+                        let tick = tick_bucket(
+                            guard.iter().filter(|q| in_bucket(q, bucket)),
+                            now,
+                            &deadlines,
+                            min_batch_size,
+                            min_deposit_ratio,
+                            aligned_boundary,
+                            scheduled.get(&bucket).copied(),
+                            flush_jitter,
+                        );
+                        let max_wait_triggered = match tick {
+                            BucketTick::Idle => continue,
+                            BucketTick::Wait(seal_at) => {
+                                still_scheduled.insert(bucket, seal_at);
+                                continue;
+                            }
+                            BucketTick::Seal(max_wait_triggered) => max_wait_triggered,
+                        };
+                        let batch_id = Uuid::new_v4().to_string();
+                        let queued = take_bucket(&mut guard, bucket);
+                        flush_seq.fetch_add(1, Ordering::Relaxed);
+                        lock_stats(&flush_stats).record(&queued, now, max_wait_triggered);
+                        let ready = ReadyBatch::from_queued(batch_id, bucket.asset, queued, deterministic, &shuffle_rng);
+                        debug!(
+                            batch_id = %ready.batch_id,
+                            asset_id = ?bucket.asset,
+                            kind = ?bucket.kind,
+                            tx_count = ready.transactions.len(),
+                            max_wait_triggered,
+                            "batch queue timeout-triggered flush (shuffled)"
+                        );
+                        let trigger = if max_wait_triggered {
+                            FlushTrigger::MaxWait
                         } else {
-                            None
-                        }
+                            FlushTrigger::Timeout
+                        };
+                        batches.push((ready, trigger));
+                    }
+                    scheduled = still_scheduled;
+                    if !batches.is_empty() {
+                        persist_pending(wal.as_deref(), &guard).await;
                     }
+                    batches
                 };
 
-                if let Some(ready) = batch {
+                for (ready, trigger) in batches {
                     if trigger_tx.send(ready).await.is_err() {
                         // Receiver dropped, exit loop
-                        break;
+                        return;
+                    }
+                    if let Some(m) = &metrics {
+                        m.record_flush(trigger);
                     }
                 }
             }
@@ -227,6 +1025,49 @@ impl BatchQueue {
 
 }
 
+fn lock_stats(stats: &std::sync::Mutex<FlushStats>) -> std::sync::MutexGuard<'_, FlushStats> {
+    stats.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Appends a newly queued tx to the WAL, returning its encoded line.
+/// A failed write is logged; the tx stays queued, just not durably.
+async fn append_to_wal(
+    wal: &QueueWal,
+    tx: &PendingTx,
+    client_ref: &Option<String>,
+    idempotency_key: &Option<String>,
+    submitter: &Option<String>,
+    recipient: &Option<WithdrawalRecipient>,
+) -> Option<String> {
+    let entry = WalEntry {
+        enqueued_at: now_epoch(),
+        tx: SubmitRequest::from(tx).with_withdrawal_recipient(recipient.as_ref()),
+        scoped_client_ref: client_ref.clone(),
+        idempotency_key: idempotency_key.clone(),
+        submitter: submitter.clone(),
+    };
+    let line = match wal.encode(&entry) {
+        Ok(line) => line,
+        Err(e) => {
+            error!(error = %e, "failed to encode queue WAL entry");
+            return None;
+        }
+    };
+    if let Err(e) = wal.append(&line).await {
+        error!(path = %wal.path().display(), error = %e, "queue WAL append failed; tx will not survive a restart");
+    }
+    Some(line)
+}
+
+/// Rewrites the WAL to hold exactly `pending` (after a flush or removal).
+async fn persist_pending(wal: Option<&QueueWal>, pending: &[QueuedTx]) {
+    let Some(wal) = wal else { return };
+    let lines = pending.iter().filter_map(|q| q.wal_line.as_deref());
+    if let Err(e) = wal.rewrite(lines).await {
+        error!(path = %wal.path().display(), error = %e, "queue WAL rewrite failed");
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -239,6 +1080,7 @@ mod tests {
             asset_id: 1,
             recipient_pubkey: zero4,
             recipient_viewing_key: zero4,
+            blinding: zero4,
         }
     }
 
@@ -261,13 +1103,724 @@ mod tests {
     async fn test_force_flush() {
         let (queue, mut rx) = BatchQueue::new(16, 3600, 8);
         queue.push(make_dummy_deposit()).await;
-        let batch_id = queue.force_flush().await;
-        assert!(batch_id.is_some());
+        let outcome = queue.force_flush().await;
+        assert_eq!(outcome.batch_ids().len(), 1);
+
+        let ready = rx.try_recv().unwrap();
+        assert_eq!(ready.transactions.len(), 1);
+        assert_eq!(ready.asset_id, None);
+
+        // Empty queue flushes nothing
+        assert_eq!(queue.force_flush().await, ForceFlushOutcome::Empty);
+    }
+
+    #[tokio::test]
+    async fn test_force_flush_below_min_size() {
+        let (queue, _rx) = BatchQueue::with_min_batch(16, 3600, 8, 3, 300);
+        queue.push(make_dummy_deposit()).await;
+        assert_eq!(
+            queue.force_flush().await,
+            ForceFlushOutcome::BelowMinSize { pending: 1, min_batch_size: 3 }
+        );
+        assert_eq!(queue.pending_count().await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_force_flush_reports_concurrent_drain() {
+        let (queue, mut rx) = BatchQueue::new(2, 3600, 8);
+        let queue = Arc::new(queue);
+        queue.push(make_dummy_deposit()).await;
+
+        // Hold the lock so the size-triggered push queues on it first and
+        // the force flush second (tokio's mutex is FIFO).
+        let guard = queue.pending.lock().await;
+        let pusher = tokio::spawn({
+            let queue = Arc::clone(&queue);
+            async move { queue.push(make_dummy_deposit()).await }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        let forcer = tokio::spawn({
+            let queue = Arc::clone(&queue);
+            async move { queue.force_flush().await }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        drop(guard);
+
+        assert!(pusher.await.unwrap().0.is_some());
+        assert_eq!(forcer.await.unwrap(), ForceFlushOutcome::ConcurrentlyDrained);
+        assert_eq!(rx.try_recv().unwrap().transactions.len(), 2);
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_force_flush_racing_size_flush() {
+        for _ in 0..200 {
+            let (queue, mut rx) = BatchQueue::new(2, 3600, 8);
+            let queue = Arc::new(queue);
+            queue.push(make_dummy_deposit()).await;
+
+            let pusher = tokio::spawn({
+                let queue = Arc::clone(&queue);
+                async move { queue.push(make_dummy_deposit()).await }
+            });
+            let outcome = queue.force_flush().await;
+            let (size_flushed, _) = pusher.await.unwrap();
+
+            // Each tx lands in exactly one non-empty batch
+            let mut batched = 0;
+            while let Ok(ready) = rx.try_recv() {
+                assert!(!ready.transactions.is_empty());
+                batched += ready.transactions.len();
+            }
+            batched += queue.pending_count().await;
+            assert_eq!(batched, 2);
+            match outcome {
+                ForceFlushOutcome::Flushed(ids) => assert_eq!(ids.len(), 1),
+                // Empty if the size flush finished before force_flush began
+                ForceFlushOutcome::ConcurrentlyDrained | ForceFlushOutcome::Empty => {
+                    assert!(size_flushed.is_some())
+                }
+                other => panic!("unexpected outcome {other:?}"),
+            }
+        }
+    }
+
+    fn deposit_of(asset_id: u32) -> PendingTx {
+        let mut tx = make_dummy_deposit();
+        if let PendingTx::Deposit { asset_id: a, .. } = &mut tx {
+            *a = asset_id;
+        }
+        tx
+    }
+
+    #[tokio::test]
+    async fn test_per_asset_batches_stay_separate() {
+        let (mut queue, mut rx) = BatchQueue::new(2, 3600, 8);
+        queue.set_per_asset(true);
+
+        // 3 txs exceed max_size overall, but each asset has its own bucket
+        assert_eq!(queue.push(deposit_of(1)).await, (None, 1));
+        assert_eq!(queue.push(deposit_of(2)).await, (None, 1));
+        let (flushed, _) = queue.push(deposit_of(1)).await;
+        assert!(flushed.is_some());
+
+        let first = rx.try_recv().unwrap();
+        assert_eq!(first.asset_id, Some(1));
+        assert_eq!(first.transactions.len(), 2);
+        assert!(first.transactions.iter().all(|tx| asset_of(tx) == 1));
+        assert_eq!(queue.pending_count().await, 1);
+
+        let (flushed, _) = queue.push(deposit_of(2)).await;
+        assert!(flushed.is_some());
+        let second = rx.try_recv().unwrap();
+        assert_eq!(second.asset_id, Some(2));
+        assert_eq!(second.transactions.len(), 2);
+        assert!(second.transactions.iter().all(|tx| asset_of(tx) == 2));
+        assert_ne!(first.batch_id, second.batch_id);
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_per_asset_force_flush_one_batch_per_asset() {
+        let (mut queue, mut rx) = BatchQueue::with_min_batch(16, 3600, 8, 2, 300);
+        queue.set_per_asset(true);
+        for asset in [1, 2, 1, 2, 3] {
+            queue.push(deposit_of(asset)).await;
+        }
+
+        let outcome = queue.force_flush().await;
+        assert_eq!(outcome.batch_ids().len(), 2);
+        let assets: Vec<_> = (0..2).map(|_| rx.try_recv().unwrap().asset_id).collect();
+        assert_eq!(assets, vec![Some(1), Some(2)]);
+        // Asset 3 is below min_batch_size and stays queued
+        assert_eq!(queue.pending_count().await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_per_kind_deposit_and_withdrawal_batch_separately() {
+        let (mut queue, mut rx) = BatchQueue::with_min_batch(16, 3600, 8, 1, 300);
+        queue.set_per_kind(true);
+        queue.push(make_dummy_deposit()).await;
+        queue.push(make_dummy_withdraw()).await;
+
+        let outcome = queue.force_flush().await;
+        assert_eq!(outcome.batch_ids().len(), 2);
+        let first = rx.try_recv().unwrap();
+        let second = rx.try_recv().unwrap();
+        assert_ne!(first.batch_id, second.batch_id);
+        let kinds: Vec<Vec<TxKind>> = [&first, &second]
+            .iter()
+            .map(|b| b.transactions.iter().map(TxKind::of).collect())
+            .collect();
+        assert_eq!(kinds, vec![vec![TxKind::Deposit], vec![TxKind::Withdraw]]);
+        assert_eq!(queue.pending_count().await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_per_kind_size_flush_counts_own_kind_only() {
+        let (mut queue, mut rx) = BatchQueue::new(2, 3600, 8);
+        queue.set_per_kind(true);
+
+        // 3 txs exceed max_size overall, but each kind has its own bucket
+        assert_eq!(queue.push(make_dummy_deposit()).await, (None, 1));
+        assert_eq!(queue.push(make_dummy_withdraw()).await, (None, 1));
+        let (flushed, _) = queue.push(make_dummy_withdraw()).await;
+        assert!(flushed.is_some());
+
+        let batch = rx.try_recv().unwrap();
+        assert!(batch.transactions.iter().all(|tx| TxKind::of(tx) == TxKind::Withdraw));
+        assert_eq!(batch.transactions.len(), 2);
+        assert_eq!(queue.pending_count().await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_balanced_queue_size_flushes_under_ratio() {
+        let (mut queue, mut rx) = BatchQueue::new(4, 3600, 8);
+        queue.set_min_deposit_ratio(Some(1.0));
+
+        assert_eq!(queue.push(make_dummy_withdraw()).await, (None, 1));
+        assert_eq!(queue.push(make_dummy_deposit()).await, (None, 2));
+        assert_eq!(queue.push(make_dummy_withdraw()).await, (None, 3));
+        let (flushed, _) = queue.push(make_dummy_deposit()).await;
+        assert!(flushed.is_some());
+
+        let batch = rx.try_recv().unwrap();
+        let deposits = batch.transactions.iter().filter(|tx| TxKind::of(tx) == TxKind::Deposit).count();
+        assert_eq!((batch.transactions.len(), deposits), (4, 2));
+        assert_eq!(queue.pending_count().await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_withdrawal_heavy_queue_fills_to_max_size_under_ratio() {
+        let (mut queue, mut rx) = BatchQueue::new(4, 3600, 8);
+        // Size target anywhere in 1..=4: the ratio holds every jittered flush
+        queue.set_flush_jitter(0, 3);
+        queue.set_min_deposit_ratio(Some(1.0));
+
+        for expected_len in 1..=3 {
+            assert_eq!(queue.push(make_dummy_withdraw()).await, (None, expected_len));
+        }
+        // ...but max_size is still a hard ceiling
+        let (flushed, _) = queue.push(make_dummy_withdraw()).await;
+        assert!(flushed.is_some());
+        assert_eq!(rx.try_recv().unwrap().transactions.len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_flushes_counted_by_trigger() {
+        let (mut queue, mut rx) = BatchQueue::new(2, 3600, 8);
+        let metrics = Arc::new(Metrics::new());
+        queue.set_metrics(Arc::clone(&metrics));
+
+        queue.push(make_dummy_deposit()).await;
+        queue.push(make_dummy_deposit()).await;
+        queue.push(make_dummy_deposit()).await;
+        assert_eq!(queue.force_flush().await.batch_ids().len(), 1);
+        assert_eq!(queue.force_flush().await, ForceFlushOutcome::Empty);
+        assert_eq!(rx.try_recv().unwrap().transactions.len(), 2);
+        assert_eq!(rx.try_recv().unwrap().transactions.len(), 1);
+
+        let text = metrics.render(queue.pending_count().await);
+        assert!(text.contains("vm31_queue_depth 0\n"));
+        assert!(text.contains("vm31_batches_flushed_total{trigger=\"size\"} 1\n"));
+        assert!(text.contains("vm31_batches_flushed_total{trigger=\"force\"} 1\n"));
+        assert!(text.contains("vm31_batches_flushed_total{trigger=\"timeout\"} 0\n"));
+    }
+
+    fn make_dummy_withdraw() -> PendingTx {
+        use stwo_ml::crypto::commitment::Note;
+        use stwo_ml::crypto::merkle_m31::MerklePath;
+        use stwo_ml::prelude::M31;
+        let zero4 = [M31::from_u32_unchecked(0); 4];
+        PendingTx::Withdraw {
+            amount: 1000,
+            asset_id: 1,
+            note: Note {
+                owner_pubkey: zero4,
+                asset_id: M31::from_u32_unchecked(1),
+                amount_lo: M31::from_u32_unchecked(1000),
+                amount_hi: M31::from_u32_unchecked(0),
+                blinding: zero4,
+            },
+            spending_key: zero4,
+            merkle_path: MerklePath { siblings: vec![], index: 0 },
+            merkle_root: [M31::from_u32_unchecked(1); 8],
+            withdrawal_binding: [M31::from_u32_unchecked(1); 8],
+        }
+    }
+
+    /// A reference "now" far enough ahead that `now - waited` never underflows.
+    fn test_now() -> Instant {
+        Instant::now() + Duration::from_secs(3600)
+    }
+
+    fn queued(tx: PendingTx, now: Instant, waited_secs: u64) -> QueuedTx {
+        QueuedTx {
+            tx,
+            enqueued_at: now - Duration::from_secs(waited_secs),
+            client_ref: None,
+            idempotency_key: None,
+            submitter: None,
+            recipient: None,
+            wal_line: None,
+            link: SpanLink::default(),
+        }
+    }
+
+    /// Deposits: 60s timeout / 300s max wait. Withdrawals: 5s / 30s.
+    fn mixed_deadlines() -> [Deadlines; 3] {
+        let slow = Deadlines { timeout: Duration::from_secs(60), max_wait: Duration::from_secs(300) };
+        let fast = Deadlines { timeout: Duration::from_secs(5), max_wait: Duration::from_secs(30) };
+        let mut d = [slow; 3];
+        d[TxKind::Withdraw as usize] = fast;
+        d
+    }
+
+    #[test]
+    fn test_deposit_only_set_waits_for_deposit_timeout() {
+        let now = test_now();
+        let d = mixed_deadlines();
+        let pending: Vec<_> = (0..3).map(|_| queued(make_dummy_deposit(), now, 10)).collect();
+        assert_eq!(flush_due(&pending, now, &d, 3, None, None), None);
+
+        let pending: Vec<_> = (0..3).map(|_| queued(make_dummy_deposit(), now, 60)).collect();
+        assert_eq!(flush_due(&pending, now, &d, 3, None, None), Some(false));
+    }
+
+    #[test]
+    fn test_withdrawal_pulls_mixed_batch_forward() {
+        let now = test_now();
+        let d = mixed_deadlines();
+        let pending = vec![
+            queued(make_dummy_deposit(), now, 10),
+            queued(make_dummy_deposit(), now, 8),
+            queued(make_dummy_withdraw(), now, 5),
+        ];
+        assert_eq!(flush_due(&pending, now, &d, 3, None, None), Some(false));
+
+        // Withdrawal timeout alone doesn't override min_batch_size...
+        assert_eq!(flush_due(&pending[2..], now, &d, 3, None, None), None);
+        // ...but its shorter max wait does.
+        let lone = vec![queued(make_dummy_withdraw(), now, 30)];
+        assert_eq!(flush_due(&lone, now, &d, 3, None, None), Some(true));
+    }
+
+    #[test]
+    fn test_withdrawal_only_set_waits_for_max_wait_under_ratio() {
+        let now = test_now();
+        let d = mixed_deadlines();
+        let ratio = Some(1.0);
+        let pending: Vec<_> = (0..3).map(|_| queued(make_dummy_withdraw(), now, 10)).collect();
+        // Past the withdrawal timeout and at min size, but no deposits
+        assert_eq!(flush_due(&pending, now, &d, 3, None, None), Some(false));
+        assert_eq!(flush_due(&pending, now, &d, 3, ratio, None), None);
+
+        let pending: Vec<_> = (0..3).map(|_| queued(make_dummy_withdraw(), now, 30)).collect();
+        assert_eq!(flush_due(&pending, now, &d, 3, ratio, None), Some(true));
+    }
+
+    #[test]
+    fn test_aligned_mode_flushes_only_at_boundaries() {
+        let now = test_now();
+        let d = mixed_deadlines();
+        // Past the relative deposit timeout, but no boundary crossed yet
+        let pending: Vec<_> = (0..3).map(|_| queued(make_dummy_deposit(), now, 90)).collect();
+        assert_eq!(flush_due(&pending, now, &d, 3, None, Some(false)), None);
+        assert_eq!(flush_due(&pending, now, &d, 3, None, Some(true)), Some(false));
+
+        // Boundary respects min_batch_size; max-wait still fires off-boundary
+        let fresh = vec![queued(make_dummy_deposit(), now, 1)];
+        assert_eq!(flush_due(&fresh, now, &d, 3, None, Some(true)), None);
+        let old = vec![queued(make_dummy_deposit(), now, 300)];
+        assert_eq!(flush_due(&old, now, &d, 3, None, Some(false)), Some(true));
+    }
+
+    /// Ticks once a second from `start` until the bucket seals, returning
+    /// the seconds elapsed.
+    fn seconds_until_sealed(
+        pending: &[QueuedTx],
+        start: Instant,
+        deadlines: &[Deadlines; 3],
+        jitter: Duration,
+        aligned: impl Fn(u64) -> Option<bool>,
+    ) -> u64 {
+        let mut scheduled = None;
+        for t in 0..1_000 {
+            let now = start + Duration::from_secs(t);
+            match tick_bucket(pending, now, deadlines, 1, None, aligned(t), scheduled, jitter) {
+                BucketTick::Seal(_) => return t,
+                BucketTick::Wait(at) => scheduled = Some(at),
+                BucketTick::Idle => scheduled = None,
+            }
+        }
+        panic!("bucket never sealed");
+    }
+
+    #[test]
+    fn test_flush_jitter_delays_within_bounds() {
+        let start = test_now();
+        let d = [Deadlines { timeout: Duration::from_secs(5), max_wait: Duration::from_secs(300) }; 3];
+        let pending = vec![queued(make_dummy_deposit(), start, 0)];
+
+        assert_eq!(seconds_until_sealed(&pending, start, &d, Duration::ZERO, |_| None), 5);
+        let sealed: BTreeSet<u64> = (0..50)
+            .map(|_| seconds_until_sealed(&pending, start, &d, Duration::from_secs(10), |_| None))
+            .collect();
+        assert!(sealed.iter().all(|t| (5..=15).contains(t)), "{sealed:?}");
+        assert!(sealed.len() > 1, "jitter should vary flush times: {sealed:?}");
+
+        // A flush decided on an aligned boundary still seals after it
+        let at_boundary = |t| Some(t == 6);
+        for _ in 0..20 {
+            let t = seconds_until_sealed(&pending, start, &d, Duration::from_secs(10), at_boundary);
+            assert!((6..=16).contains(&t), "{t}");
+        }
+    }
+
+    #[test]
+    fn test_flush_jitter_never_exceeds_max_wait() {
+        let start = test_now();
+        let d = [Deadlines { timeout: Duration::from_secs(5), max_wait: Duration::from_secs(8) }; 3];
+        let pending = vec![queued(make_dummy_deposit(), start, 0)];
+        for _ in 0..50 {
+            let t = seconds_until_sealed(&pending, start, &d, Duration::from_secs(600), |_| None);
+            assert!((5..=8).contains(&t), "{t}");
+        }
+
+        // A later tx with a shorter max wait pulls an already-scheduled seal in
+        let now = test_now();
+        let scheduled = Some(now + Duration::from_secs(500));
+        let mixed = vec![queued(make_dummy_deposit(), now, 100), queued(make_dummy_withdraw(), now, 30)];
+        assert_eq!(
+            tick_bucket(&mixed, now, &mixed_deadlines(), 1, None, None, scheduled, Duration::from_secs(600)),
+            BucketTick::Seal(false)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_size_jitter_varies_batch_sizes() {
+        let (mut queue, mut rx) = BatchQueue::new(10, 3600, 256);
+        queue.set_flush_jitter(0, 3);
+        for _ in 0..400 {
+            queue.push(make_dummy_deposit()).await;
+        }
+        let mut sizes = BTreeSet::new();
+        while let Ok(ready) = rx.try_recv() {
+            sizes.insert(ready.transactions.len());
+        }
+        assert!(sizes.iter().all(|n| (7..=10).contains(n)), "{sizes:?}");
+        assert!(sizes.len() > 1, "size jitter should vary batch sizes: {sizes:?}");
+    }
+
+    #[tokio::test]
+    async fn test_client_refs_stay_aligned_after_shuffle() {
+        let (queue, mut rx) = BatchQueue::new(8, 3600, 8);
+        for amount in [1u64, 2, 3, 4] {
+            let mut tx = make_dummy_deposit();
+            if let PendingTx::Deposit { amount: ref mut a, .. } = tx {
+                *a = amount;
+            }
+            queue.push_with_ref(tx, Some(format!("ref-{amount}"))).await;
+        }
+        assert_eq!(queue.force_flush().await.batch_ids().len(), 1);
 
         let ready = rx.try_recv().unwrap();
+        assert_eq!(ready.client_refs.len(), 4);
+        for (tx, client_ref) in ready.transactions.iter().zip(&ready.client_refs) {
+            let PendingTx::Deposit { amount, .. } = tx else { unreachable!() };
+            assert_eq!(client_ref.as_deref(), Some(format!("ref-{amount}").as_str()));
+        }
+    }
+
+    /// Backdates every queued tx by `secs`.
+    async fn age_pending(queue: &BatchQueue, secs: u64) {
+        for q in queue.pending.lock().await.iter_mut() {
+            q.enqueued_at -= Duration::from_secs(secs);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stats_oldest_age_and_depth() {
+        let (queue, _rx) = BatchQueue::new(8, 3600, 8);
+        let empty = queue.stats().await;
+        assert_eq!((empty.depth, empty.oldest_age_ms, empty.avg_wait_ms), (0, None, None));
+
+        queue.push(make_dummy_deposit()).await;
+        age_pending(&queue, 40).await;
+        queue.push(make_dummy_deposit()).await;
+
+        let stats = queue.stats().await;
+        assert_eq!(stats.depth, 2);
+        let age = stats.oldest_age_ms.unwrap();
+        assert!((40_000..41_000).contains(&age), "{age}");
+    }
+
+    #[tokio::test]
+    async fn test_stats_flush_type_counters_and_avg_wait() {
+        // min batch 3 and a 30s max wait: one old tx can only go by max-wait
+        let (queue, mut rx) = BatchQueue::with_min_batch(2, 3600, 8, 3, 30);
+        queue.push(make_dummy_deposit()).await;
+        age_pending(&queue, 60).await;
+        queue.spawn_timeout_loop();
+        let ready = tokio::time::timeout(Duration::from_secs(5), rx.recv()).await.unwrap().unwrap();
         assert_eq!(ready.transactions.len(), 1);
 
-        // Empty queue returns None
-        assert!(queue.force_flush().await.is_none());
+        // A size-triggered flush counts as normal
+        queue.push(make_dummy_deposit()).await;
+        queue.push(make_dummy_deposit()).await;
+        rx.recv().await.unwrap();
+
+        let stats = queue.stats().await;
+        assert_eq!((stats.max_wait_flushes, stats.normal_flushes), (1, 1));
+        assert_eq!(stats.depth, 0);
+        // (60s + ~0 + ~0) / 3
+        let avg = stats.avg_wait_ms.unwrap();
+        assert!((20_000..21_000).contains(&avg), "{avg}");
+    }
+
+    #[tokio::test]
+    async fn test_seeded_shuffle_is_reproducible() {
+        async fn flushed_order(seed: u64) -> Vec<Option<String>> {
+            let (mut queue, mut rx) = BatchQueue::new(64, 3600, 8);
+            queue.set_shuffle_rng(ShuffleRng::seeded(seed));
+            assert!(!queue.shuffle_rng().is_secure());
+            for i in 0..16 {
+                queue.push_keyed(make_dummy_deposit(), None, Some(format!("k{i}")), None, None).await;
+            }
+            queue.force_flush().await;
+            rx.try_recv().unwrap().idempotency_keys
+        }
+        let first = flushed_order(7).await;
+        assert_eq!(first, flushed_order(7).await);
+        let submitted: Vec<_> = (0..16).map(|i| Some(format!("k{i}"))).collect();
+        assert_ne!(first, submitted);
+    }
+
+    #[test]
+    fn test_default_shuffle_rng_is_secure() {
+        let (queue, _rx) = BatchQueue::new(8, 3600, 8);
+        assert!(queue.shuffle_rng().is_secure());
+        assert!(queue.shuffle_rng().describe().contains("CSPRNG"));
+    }
+
+    #[tokio::test]
+    async fn test_requeue_never_size_flushes() {
+        let (queue, mut rx) = BatchQueue::new(2, 3600, 8);
+        let declined = (0..3)
+            .map(|i| (make_dummy_deposit(), None, Some(format!("k{i}")), Some("alice".into()), None))
+            .collect();
+        assert_eq!(queue.requeue(declined).await, 3);
+        assert!(rx.try_recv().is_err());
+
+        // Requeued txs keep their keys and ride the next flush
+        assert_eq!(queue.remove_by_key("k1", "alice").await, Removal::Removed(2));
+        assert!(queue.push(make_dummy_deposit()).await.0.is_some());
+        assert_eq!(rx.try_recv().unwrap().transactions.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_remove_by_key_keeps_order() {
+        let (mut queue, mut rx) = BatchQueue::new(8, 3600, 8);
+        queue.set_deterministic(true);
+        for key in ["a", "b", "c"] {
+            queue.push_keyed(make_dummy_deposit(), None, Some(key.into()), Some("alice".into()), None).await;
+        }
+        queue.push(make_dummy_deposit()).await;
+
+        assert_eq!(queue.remove_by_key("b", "alice").await, Removal::Removed(3));
+        assert_eq!(queue.remove_by_key("b", "alice").await, Removal::NotQueued);
+        assert_eq!(queue.remove_by_key("unknown", "alice").await, Removal::NotQueued);
+
+        assert_eq!(queue.force_flush().await.batch_ids().len(), 1);
+        let ready = rx.try_recv().unwrap();
+        assert_eq!(
+            ready.idempotency_keys,
+            vec![Some("a".into()), Some("c".into()), None]
+        );
+        // Flushed txs can no longer be cancelled
+        assert_eq!(queue.remove_by_key("a", "alice").await, Removal::NotQueued);
+    }
+
+    #[tokio::test]
+    async fn test_cancel_from_another_key_leaves_tx_queued() {
+        let (queue, mut rx) = BatchQueue::new(8, 3600, 8);
+        queue.push_keyed(make_dummy_deposit(), None, Some("k".into()), Some("alice".into()), None).await;
+        // Replayed and approved txs have no submitter: no key can cancel them
+        queue.push_keyed(make_dummy_deposit(), None, Some("replay".into()), None, None).await;
+
+        assert_eq!(queue.remove_by_key("k", "mallory").await, Removal::NotOwner);
+        assert_eq!(queue.remove_by_key("replay", "mallory").await, Removal::NotOwner);
+        assert_eq!(queue.pending_count().await, 2);
+
+        assert_eq!(queue.remove_by_key("k", "alice").await, Removal::Removed(1));
+        queue.force_flush().await;
+        assert_eq!(rx.try_recv().unwrap().idempotency_keys, vec![Some("replay".into())]);
+    }
+
+    #[tokio::test]
+    async fn test_wal_restores_pending_in_order() {
+        let path = std::env::temp_dir().join(format!("vm31-queue-wal-{}.jsonl", Uuid::new_v4()));
+        {
+            let (mut queue, _rx) = BatchQueue::new(8, 3600, 8);
+            queue.set_wal(QueueWal::new(&path));
+            for amount in [3u64, 1, 2] {
+                let mut tx = make_dummy_deposit();
+                if let PendingTx::Deposit { amount: ref mut a, .. } = tx {
+                    *a = amount;
+                }
+                queue
+                    .push_keyed(tx, Some(format!("ref-{amount}")), Some(format!("key-{amount}")), Some("alice".into()), None)
+                    .await;
+            }
+            let recipient = WithdrawalRecipient { payout: "0xa1".into(), credit: "0xc1".into() };
+            queue.push_keyed(make_dummy_withdraw(), None, None, None, Some(recipient)).await;
+            assert_eq!(queue.remove_by_key("key-1", "alice").await, Removal::Removed(3));
+            // The removal rewrote the log through a renamed temp file
+            let mut tmp = path.clone().into_os_string();
+            tmp.push(".tmp");
+            assert!(!std::path::Path::new(&tmp).exists());
+            // Dropped without flushing, as in a crash
+        }
+
+        let (mut queue, mut rx) = BatchQueue::new(8, 3600, 8);
+        queue.set_deterministic(true);
+        queue.set_wal(QueueWal::new(&path));
+        assert_eq!(queue.restore_from_wal().await.unwrap(), 3);
+        assert_eq!(queue.force_flush().await.batch_ids().len(), 1);
+
+        let ready = rx.try_recv().unwrap();
+        let kinds: Vec<_> = ready
+            .transactions
+            .iter()
+            .map(|tx| match tx {
+                PendingTx::Deposit { amount, .. } => format!("deposit-{amount}"),
+                PendingTx::Withdraw { .. } => "withdraw".into(),
+                PendingTx::Transfer { .. } => "transfer".into(),
+            })
+            .collect();
+        assert_eq!(kinds, vec!["deposit-3", "deposit-2", "withdraw"]);
+        assert_eq!(
+            ready.client_refs,
+            vec![Some("ref-3".into()), Some("ref-2".into()), None]
+        );
+        assert_eq!(ready.idempotency_keys[1].as_deref(), Some("key-2"));
+        assert_eq!(ready.submitters[1].as_deref(), Some("alice"));
+        assert_eq!(
+            ready.recipients,
+            vec![None, None, Some(WithdrawalRecipient { payout: "0xa1".into(), credit: "0xc1".into() })]
+        );
+
+        // The flush emptied the WAL
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "");
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_wal_sealed_with_storage_key() {
+        use stwo_ml::prelude::M31;
+        let path = std::env::temp_dir().join(format!("vm31-queue-wal-{}.jsonl", Uuid::new_v4()));
+        let mut tx = make_dummy_withdraw();
+        if let PendingTx::Withdraw { ref mut spending_key, .. } = tx {
+            *spending_key = [M31::from_u32_unchecked(1_234_567); 4];
+        }
+        let recipient = WithdrawalRecipient { payout: "0xa1".into(), credit: "0xc1".into() };
+        {
+            let (mut queue, _rx) = BatchQueue::new(8, 3600, 8);
+            queue.set_wal(QueueWal::new(&path).with_encryption(Some(&[7; 32])));
+            queue.push_keyed(tx.clone(), None, Some("k".into()), None, Some(recipient.clone())).await;
+        }
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(content.contains("\"sealed\""), "{content}");
+        assert!(!content.contains("spending_key") && !content.contains("1234567"), "{content}");
+
+        // Without the key the sealed entries are refused, not silently dropped
+        let (mut keyless, _rx) = BatchQueue::new(8, 3600, 8);
+        keyless.set_wal(QueueWal::new(&path));
+        assert!(keyless.restore_from_wal().await.is_err());
+
+        let (mut queue, mut rx) = BatchQueue::new(8, 3600, 8);
+        queue.set_wal(QueueWal::new(&path).with_encryption(Some(&[7; 32])));
+        assert_eq!(queue.restore_from_wal().await.unwrap(), 1);
+        queue.force_flush().await;
+        let ready = rx.try_recv().unwrap();
+        assert!(matches!(
+            &ready.transactions[0],
+            PendingTx::Withdraw { spending_key, .. } if spending_key[0].0 == 1_234_567
+        ));
+        let _ = std::fs::remove_file(&path);
+
+        // A plaintext WAL from before the key was set is read, then sealed
+        {
+            let (mut queue, _rx) = BatchQueue::new(8, 3600, 8);
+            queue.set_wal(QueueWal::new(&path));
+            queue.push_keyed(tx, None, Some("k".into()), None, Some(recipient)).await;
+        }
+        assert!(std::fs::read_to_string(&path).unwrap().contains("spending_key"));
+        let (mut queue, _rx) = BatchQueue::new(8, 3600, 8);
+        queue.set_wal(QueueWal::new(&path).with_encryption(Some(&[7; 32])));
+        assert_eq!(queue.restore_from_wal().await.unwrap(), 1);
+        assert!(!std::fs::read_to_string(&path).unwrap().contains("spending_key"));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_cancel_racing_size_flush() {
+        for _ in 0..50 {
+            let (queue, mut rx) = BatchQueue::new(2, 3600, 8);
+            let queue = Arc::new(queue);
+            queue.push_keyed(make_dummy_deposit(), None, Some("a".into()), Some("alice".into()), None).await;
+
+            let pusher = {
+                let queue = Arc::clone(&queue);
+                tokio::spawn(async move {
+                    queue.push_keyed(make_dummy_deposit(), None, Some("b".into()), Some("alice".into()), None).await
+                })
+            };
+            let removed = queue.remove_by_key("a", "alice").await;
+            let (flushed, _) = pusher.await.unwrap();
+
+            match removed {
+                // Cancelled first: "b" alone stays queued, nothing flushed
+                Removal::Removed(remaining) => {
+                    assert!(remaining <= 1);
+                    assert!(flushed.is_none());
+                    assert!(rx.try_recv().is_err());
+                    assert_eq!(queue.pending_count().await, 1);
+                }
+                // Flushed first: "a" went out with the batch
+                Removal::NotQueued | Removal::NotOwner => {
+                    assert_eq!(removed, Removal::NotQueued);
+                    assert!(flushed.is_some());
+                    let ready = rx.try_recv().unwrap();
+                    assert!(ready.idempotency_keys.contains(&Some("a".into())));
+                    assert_eq!(queue.pending_count().await, 0);
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_deterministic_mode_keeps_submission_order() {
+        let (mut queue, mut rx) = BatchQueue::new(8, 3600, 8);
+        queue.set_deterministic(true);
+        for amount in 1u64..=6 {
+            let mut tx = make_dummy_deposit();
+            if let PendingTx::Deposit { amount: ref mut a, .. } = tx {
+                *a = amount;
+            }
+            queue.push(tx).await;
+        }
+        assert_eq!(queue.force_flush().await.batch_ids().len(), 1);
+
+        let ready = rx.try_recv().unwrap();
+        let amounts: Vec<u64> = ready
+            .transactions
+            .iter()
+            .map(|tx| match tx {
+                PendingTx::Deposit { amount, .. } => *amount,
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(amounts, vec![1, 2, 3, 4, 5, 6]);
     }
 }
@@ -1,7 +1,11 @@
+use std::collections::HashMap;
 use std::time::Duration;
 use tokio::process::Command;
 use tracing::{debug, error, info, warn};
 
+#[cfg(feature = "native-bridge")]
+use crate::bridge_native::NativeBridge;
+
 /// Maximum retries for bridge calls (idempotent, safe to retry).
 const MAX_BRIDGE_RETRIES: u32 = 3;
 /// Base backoff between retries.
@@ -18,7 +22,49 @@ fn opaque_ref(input: &str) -> String {
     format!("{:08x}", (state >> 32) ^ (state & 0xFFFFFFFF))
 }
 
-/// Handles withdrawal -> ConfidentialTransfer bridging via sncast invoke.
+/// How bridge invokes are submitted (`VM31_BRIDGE_BACKEND`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BridgeBackendKind {
+    /// Spawn `sncast invoke` (the default).
+    Sncast,
+    /// Sign and send over JSON-RPC with starknet-rs (`native-bridge` feature).
+    Native,
+}
+
+impl std::str::FromStr for BridgeBackendKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sncast" => Ok(Self::Sncast),
+            "native" => Ok(Self::Native),
+            other => Err(format!("unknown bridge backend '{other}' (expected native or sncast)")),
+        }
+    }
+}
+
+/// True if a failure carries the contract's idempotent rejection.
+pub(crate) fn is_already_bridged(detail: &str) -> bool {
+    let lower = detail.to_ascii_lowercase();
+    lower.contains("already bridged") || lower.contains("bridge_key exists")
+}
+
+/// Coarse, non-leaking category for a failed invoke.
+pub(crate) fn failure_category(detail: &str) -> &'static str {
+    let lower = detail.to_ascii_lowercase();
+    if lower.contains("nonce") {
+        "nonce conflict"
+    } else if lower.contains("insufficient") || lower.contains("balance") {
+        "insufficient gas"
+    } else if lower.contains("timeout") || lower.contains("connection") {
+        "rpc timeout"
+    } else {
+        "invocation failed"
+    }
+}
+
+/// Handles withdrawal -> ConfidentialTransfer bridging via sncast invoke,
+/// or natively over JSON-RPC when a `NativeBridge` is attached.
 ///
 /// The bridge contract's `bridge_withdrawal_to_confidential` is relayer-only
 /// and idempotent (checks bridge_key not already processed).
@@ -28,7 +74,13 @@ fn opaque_ref(input: &str) -> String {
 pub struct BridgeService {
     account: String,
     rpc_url: String,
+    /// Default bridge contract for assets without an override.
     bridge_contract: String,
+    /// Per-asset bridge contract overrides.
+    asset_contracts: HashMap<u32, String>,
+    /// Sends invokes over JSON-RPC instead of spawning sncast.
+    #[cfg(feature = "native-bridge")]
+    native: Option<NativeBridge>,
 }
 
 impl BridgeService {
@@ -37,9 +89,33 @@ impl BridgeService {
             account,
             rpc_url,
             bridge_contract,
+            asset_contracts: HashMap::new(),
+            #[cfg(feature = "native-bridge")]
+            native: None,
         }
     }
 
+    /// Submits through `native` instead of sncast.
+    #[cfg(feature = "native-bridge")]
+    pub fn with_native(mut self, native: NativeBridge) -> Self {
+        self.native = Some(native);
+        self
+    }
+
+    /// Routes withdrawals of the given assets to their own bridge contracts.
+    pub fn with_asset_contracts(mut self, asset_contracts: HashMap<u32, String>) -> Self {
+        self.asset_contracts = asset_contracts;
+        self
+    }
+
+    /// Bridge contract for `asset_id`, falling back to the default contract.
+    fn contract_for(&self, asset_id: u32) -> &str {
+        self.asset_contracts
+            .get(&asset_id)
+            .map(String::as_str)
+            .unwrap_or(&self.bridge_contract)
+    }
+
     /// Calls `bridge_withdrawal_to_confidential` on-chain with retries.
     ///
     /// This is idempotent: the contract rejects duplicate bridge_keys,
@@ -48,6 +124,7 @@ impl BridgeService {
         &self,
         batch_id: &str,
         withdrawal_idx: u32,
+        asset_id: u32,
     ) -> Result<String, BridgeError> {
         // Validate batch_id is a UUID (internal invariant — defense in depth)
         if !batch_id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
@@ -55,7 +132,7 @@ impl BridgeService {
         }
 
         for attempt in 0..MAX_BRIDGE_RETRIES {
-            match self.try_bridge(batch_id, withdrawal_idx).await {
+            match self.try_bridge(batch_id, withdrawal_idx, asset_id).await {
                 Ok(result) => return Ok(result),
                 Err(BridgeError::AlreadyBridged) => return Ok("already_bridged".into()),
                 Err(e) if attempt < MAX_BRIDGE_RETRIES - 1 => {
@@ -89,6 +166,7 @@ impl BridgeService {
         &self,
         batch_id: &str,
         withdrawal_idx: u32,
+        asset_id: u32,
     ) -> Result<String, BridgeError> {
         info!(
             batch_id = %batch_id,
@@ -96,11 +174,33 @@ impl BridgeService {
             "invoking bridge_withdrawal_to_confidential"
         );
 
+        #[cfg(feature = "native-bridge")]
+        if let Some(native) = &self.native {
+            let tx_hash = native
+                .invoke(self.contract_for(asset_id), batch_id, withdrawal_idx)
+                .await;
+            if matches!(tx_hash, Err(BridgeError::AlreadyBridged)) {
+                debug!(
+                    batch_id = %batch_id,
+                    wd_ref = %opaque_ref(&format!("{batch_id}:{withdrawal_idx}")),
+                    "withdrawal already bridged (idempotent)"
+                );
+            }
+            let tx_hash = tx_hash?;
+            info!(
+                batch_id = %batch_id,
+                wd_ref = %opaque_ref(&format!("{batch_id}:{withdrawal_idx}")),
+                tx_ref = %opaque_ref(&tx_hash),
+                "bridge call submitted (native)"
+            );
+            return Ok(tx_hash);
+        }
+
         let output = Command::new("sncast")
             .args([
                 "invoke",
                 "--contract-address",
-                &self.bridge_contract,
+                self.contract_for(asset_id),
                 "--function",
                 "bridge_withdrawal_to_confidential",
                 "--calldata",
@@ -119,7 +219,7 @@ impl BridgeService {
 
         if !output.status.success() {
             // Check idempotent rejection (already bridged)
-            if stderr.contains("already bridged") || stderr.contains("bridge_key exists") {
+            if is_already_bridged(&stderr) {
                 debug!(
                     batch_id = %batch_id,
                     wd_ref = %opaque_ref(&format!("{batch_id}:{withdrawal_idx}")),
@@ -137,16 +237,7 @@ impl BridgeService {
                 "bridge sncast failed (details in server logs)"
             );
             // Categorize without leaking details
-            let category = if stderr.contains("nonce") {
-                "nonce conflict"
-            } else if stderr.contains("insufficient") || stderr.contains("balance") {
-                "insufficient gas"
-            } else if stderr.contains("timeout") || stderr.contains("connection") {
-                "rpc timeout"
-            } else {
-                "invocation failed"
-            };
-            return Err(BridgeError::OnChain(category.into()));
+            return Err(BridgeError::OnChain(failure_category(&stderr).into()));
         }
 
         // Extract tx hash from sncast output
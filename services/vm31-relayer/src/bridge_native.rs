@@ -0,0 +1,199 @@
+//! Native bridge submission over JSON-RPC (`VM31_BRIDGE_BACKEND=native`).
+//!
+//! Builds, signs and sends the `bridge_withdrawal_to_confidential` invoke with
+//! starknet-rs instead of spawning sncast, so there is no binary to install,
+//! the tx hash comes back as a field, and failures arrive as typed errors.
+//! Batch submission itself still goes through sncast under the same account;
+//! `NativeBridge::connect` refuses a key that doesn't control that account.
+
+use starknet::accounts::{Account, AccountError, ExecutionEncoding, SingleOwnerAccount};
+use starknet::core::chain_id;
+use starknet::core::types::{BlockId, BlockTag, Call, Felt, FunctionCall};
+use starknet::core::utils::get_selector_from_name;
+use starknet::providers::jsonrpc::HttpTransport;
+use starknet::providers::{JsonRpcClient, Provider, Url};
+use starknet::signers::{LocalWallet, SigningKey};
+
+use crate::bridge::{failure_category, is_already_bridged, BridgeError};
+
+const ENTRYPOINT: &str = "bridge_withdrawal_to_confidential";
+
+pub struct NativeBridge {
+    account: SingleOwnerAccount<JsonRpcClient<HttpTransport>, LocalWallet>,
+}
+
+impl NativeBridge {
+    /// Connects to `rpc_url` as `account_address`, signing with `private_key`.
+    ///
+    /// Fails unless the account's on-chain `get_public_key` matches the key,
+    /// so bridge invokes can't silently run under a different account than
+    /// batch submission.
+    pub async fn connect(
+        rpc_url: &str,
+        network: &str,
+        account_address: &str,
+        private_key: &[u8; 32],
+    ) -> Result<Self, String> {
+        let url = Url::parse(rpc_url).map_err(|e| format!("invalid rpc url: {e}"))?;
+        let provider = JsonRpcClient::new(HttpTransport::new(url));
+        let address = Felt::from_hex(account_address)
+            .map_err(|_| "STARKNET_ACCOUNT must be a 0x account address for the native backend".to_string())?;
+        let signing_key = SigningKey::from_secret_scalar(Felt::from_bytes_be(private_key));
+        let public_key = signing_key.verifying_key().scalar();
+
+        let on_chain = provider
+            .call(
+                FunctionCall {
+                    contract_address: address,
+                    entry_point_selector: selector("get_public_key")?,
+                    calldata: vec![],
+                },
+                BlockId::Tag(BlockTag::Latest),
+            )
+            .await
+            .map_err(|e| format!("get_public_key on account failed: {e}"))?;
+        if on_chain.first() != Some(&public_key) {
+            return Err("VM31_RELAYER_ACCOUNT_KEY does not control STARKNET_ACCOUNT".into());
+        }
+
+        let chain = if network == "mainnet" { chain_id::MAINNET } else { chain_id::SEPOLIA };
+        let mut account = SingleOwnerAccount::new(
+            provider,
+            LocalWallet::from(signing_key),
+            address,
+            chain,
+            ExecutionEncoding::New,
+        );
+        // Nonces must account for our own not-yet-accepted invokes
+        account.set_block_id(BlockId::Tag(BlockTag::Pending));
+        Ok(Self { account })
+    }
+
+    /// Sends the invoke and returns the transaction hash (0x, 64 hex digits).
+    pub async fn invoke(
+        &self,
+        contract: &str,
+        batch_id: &str,
+        withdrawal_idx: u32,
+    ) -> Result<String, BridgeError> {
+        let to = Felt::from_hex(contract)
+            .map_err(|_| BridgeError::Validation("invalid bridge contract address".into()))?;
+        let call = Call {
+            to,
+            selector: selector(ENTRYPOINT).map_err(BridgeError::Validation)?,
+            calldata: encode_calldata(batch_id, withdrawal_idx)?,
+        };
+        match self.account.execute_v3(vec![call]).send().await {
+            Ok(result) => Ok(format!("{:#066x}", result.transaction_hash)),
+            Err(e) => Err(classify(&e)),
+        }
+    }
+}
+
+/// Calldata for `(batch_id: felt252, withdrawal_idx: u32)`. The on-chain
+/// batch id is accepted as 0x-prefixed hex or decimal.
+pub fn encode_calldata(batch_id: &str, withdrawal_idx: u32) -> Result<Vec<Felt>, BridgeError> {
+    let invalid = || BridgeError::Validation("batch_id is not a felt252".into());
+    let batch = if let Some(hex) = batch_id.strip_prefix("0x") {
+        if hex.is_empty() || hex.len() > 64 {
+            return Err(invalid());
+        }
+        Felt::from_hex(batch_id).map_err(|_| invalid())?
+    } else if !batch_id.is_empty() && batch_id.bytes().all(|b| b.is_ascii_digit()) {
+        Felt::from_dec_str(batch_id).map_err(|_| invalid())?
+    } else {
+        return Err(invalid());
+    };
+    Ok(vec![batch, Felt::from(withdrawal_idx)])
+}
+
+fn selector(name: &str) -> Result<Felt, String> {
+    get_selector_from_name(name).map_err(|e| format!("selector {name}: {e}"))
+}
+
+/// Maps an execution failure onto `BridgeError`. The contract's revert
+/// reason ('Already bridged') travels inside the provider error data, so
+/// the whole error is matched, not just its top-level message.
+fn classify<S: std::fmt::Debug>(err: &AccountError<S>) -> BridgeError {
+    let detail = format!("{err:?}");
+    if is_already_bridged(&detail) {
+        return BridgeError::AlreadyBridged;
+    }
+    // SECURITY: full detail stays in server logs (see `BridgeService::try_bridge`)
+    tracing::error!(error = %detail, "native bridge invoke failed");
+    BridgeError::OnChain(failure_category(&detail).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_calldata_hex_and_decimal_agree() {
+        let hex = encode_calldata("0x2a", 7).unwrap();
+        let dec = encode_calldata("42", 7).unwrap();
+        assert_eq!(hex, dec);
+        assert_eq!(hex, vec![Felt::from(42u32), Felt::from(7u32)]);
+    }
+
+    #[test]
+    fn test_calldata_full_width_batch_id() {
+        let id = format!("0x{}", "7".repeat(62));
+        let calldata = encode_calldata(&id, u32::MAX).unwrap();
+        assert_eq!(calldata[0], Felt::from_hex(&id).unwrap());
+        assert_eq!(calldata[1], Felt::from(u32::MAX));
+    }
+
+    #[test]
+    fn test_calldata_rejects_non_felts() {
+        for bad in ["", "0x", "0xzz", "3f2a-11", &format!("0x{}", "1".repeat(65))] {
+            assert!(
+                matches!(encode_calldata(bad, 0), Err(BridgeError::Validation(_))),
+                "{bad}"
+            );
+        }
+    }
+}
+
+/// End-to-end against a local devnet (`--features devnet-tests`). Needs a
+/// deployed bridge whose relayer is the test account:
+/// `VM31_DEVNET_RPC_URL`, `VM31_DEVNET_ACCOUNT`, `VM31_DEVNET_ACCOUNT_KEY`,
+/// `VM31_DEVNET_BRIDGE_CONTRACT`, `VM31_DEVNET_BATCH_ID` (a finalized batch).
+#[cfg(all(test, feature = "devnet-tests"))]
+mod devnet_tests {
+    use super::*;
+
+    fn var(name: &str) -> String {
+        std::env::var(name).unwrap_or_else(|_| panic!("{name} must be set for devnet tests"))
+    }
+
+    #[tokio::test]
+    async fn test_devnet_invoke_then_already_bridged() {
+        let key_hex = var("VM31_DEVNET_ACCOUNT_KEY");
+        let key: [u8; 32] = hex::decode(key_hex.trim_start_matches("0x"))
+            .expect("key hex")
+            .try_into()
+            .expect("32-byte key");
+        let bridge = NativeBridge::connect(&var("VM31_DEVNET_RPC_URL"), "sepolia", &var("VM31_DEVNET_ACCOUNT"), &key)
+            .await
+            .expect("connect");
+        let contract = var("VM31_DEVNET_BRIDGE_CONTRACT");
+        let batch_id = var("VM31_DEVNET_BATCH_ID");
+
+        let tx_hash = bridge.invoke(&contract, &batch_id, 0).await.expect("first invoke");
+        assert!(tx_hash.starts_with("0x") && tx_hash.len() == 66, "{tx_hash}");
+        assert!(matches!(
+            bridge.invoke(&contract, &batch_id, 0).await,
+            Err(BridgeError::AlreadyBridged)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_devnet_rejects_foreign_key() {
+        let err = NativeBridge::connect(&var("VM31_DEVNET_RPC_URL"), "sepolia", &var("VM31_DEVNET_ACCOUNT"), &[1; 32])
+            .await
+            .err()
+            .expect("foreign key accepted");
+        assert!(err.contains("does not control"), "{err}");
+    }
+}
@@ -0,0 +1,272 @@
+//! Circuit breaker for pool RPC calls.
+//!
+//! When the Starknet RPC is degraded, every input check and tree sync would
+//! otherwise block for the full RPC timeout and add load to an endpoint that
+//! is already struggling. After `threshold` consecutive failures within
+//! `window`, the breaker opens and calls fail fast for `cooldown`. The first
+//! call after the cooldown is let through as a probe (half-open): success
+//! closes the breaker, failure reopens it for another cooldown.
+//!
+//! One breaker is shared by the prover's input checks (and `/validate`) and
+//! the tree sync loop, so either one noticing the outage spares the other.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    Closed,
+    Open { until: Instant },
+    /// Cooldown over; `probing` while the single probe call is in flight.
+    HalfOpen { probing: bool },
+}
+
+struct Inner {
+    phase: Phase,
+    /// Failures in a row, and when the first of them happened.
+    consecutive_failures: u32,
+    streak_started: Option<Instant>,
+    /// Times the breaker has opened since startup.
+    trips: u64,
+}
+
+/// Breaker state, as reported by `/status`.
+#[derive(Debug, Serialize)]
+pub struct BreakerStatus {
+    /// `closed`, `open` or `half_open`.
+    pub state: &'static str,
+    pub consecutive_failures: u32,
+    pub trips: u64,
+    /// Seconds until an open breaker lets a probe through.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_after_secs: Option<u64>,
+}
+
+pub struct CircuitBreaker {
+    threshold: u32,
+    window: Duration,
+    cooldown: Duration,
+    inner: Mutex<Inner>,
+}
+
+/// Permission for one call. Report its outcome with `record`; a permit
+/// dropped without one (e.g. the call panicked) says nothing about the
+/// endpoint and just frees the probe slot.
+pub struct BreakerPermit<'a> {
+    breaker: &'a CircuitBreaker,
+    probe: bool,
+    recorded: bool,
+}
+
+impl CircuitBreaker {
+    /// Opens after `threshold` consecutive failures within `window`, for `cooldown`.
+    pub fn new(threshold: u32, window: Duration, cooldown: Duration) -> Self {
+        Self {
+            threshold: threshold.max(1),
+            window,
+            cooldown,
+            inner: Mutex::new(Inner {
+                phase: Phase::Closed,
+                consecutive_failures: 0,
+                streak_started: None,
+                trips: 0,
+            }),
+        }
+    }
+
+    /// Asks to make a call. Fails with the seconds until the next probe
+    /// while the breaker is open or a probe is already in flight.
+    pub fn try_acquire(&self) -> Result<BreakerPermit<'_>, u64> {
+        self.try_acquire_at(Instant::now())
+    }
+
+    fn try_acquire_at(&self, now: Instant) -> Result<BreakerPermit<'_>, u64> {
+        let mut inner = self.lock();
+        let probe = match inner.phase {
+            Phase::Closed => false,
+            Phase::Open { until } if now < until => {
+                return Err(secs_ceil(until - now));
+            }
+            Phase::Open { .. } | Phase::HalfOpen { probing: false } => {
+                inner.phase = Phase::HalfOpen { probing: true };
+                true
+            }
+            Phase::HalfOpen { probing: true } => return Err(secs_ceil(self.cooldown)),
+        };
+        Ok(BreakerPermit { breaker: self, probe, recorded: false })
+    }
+
+    fn record_at(&self, ok: bool, probe: bool, now: Instant) {
+        let mut inner = self.lock();
+        if ok {
+            // A call started before the breaker opened proves nothing now
+            if !probe && inner.phase != Phase::Closed {
+                return;
+            }
+            if probe {
+                tracing::info!(trips = inner.trips, "pool RPC probe succeeded; circuit closed");
+            }
+            inner.phase = Phase::Closed;
+            inner.consecutive_failures = 0;
+            inner.streak_started = None;
+            return;
+        }
+        // A streak older than the window starts over
+        if inner.streak_started.is_none_or(|start| now.duration_since(start) > self.window) {
+            inner.consecutive_failures = 0;
+            inner.streak_started = Some(now);
+        }
+        inner.consecutive_failures = inner.consecutive_failures.saturating_add(1);
+        let tripped = inner.phase == Phase::Closed && inner.consecutive_failures >= self.threshold;
+        if probe || tripped {
+            inner.phase = Phase::Open { until: now + self.cooldown };
+            inner.trips += 1;
+            tracing::warn!(
+                consecutive_failures = inner.consecutive_failures,
+                cooldown_secs = self.cooldown.as_secs(),
+                "pool RPC failing; circuit open"
+            );
+        }
+    }
+
+    fn release_probe(&self) {
+        let mut inner = self.lock();
+        if inner.phase == (Phase::HalfOpen { probing: true }) {
+            inner.phase = Phase::HalfOpen { probing: false };
+        }
+    }
+
+    pub fn status(&self) -> BreakerStatus {
+        self.status_at(Instant::now())
+    }
+
+    fn status_at(&self, now: Instant) -> BreakerStatus {
+        let inner = self.lock();
+        let (state, retry_after_secs) = match inner.phase {
+            Phase::Closed => ("closed", None),
+            Phase::Open { until } if now < until => ("open", Some(secs_ceil(until - now))),
+            Phase::Open { .. } | Phase::HalfOpen { .. } => ("half_open", None),
+        };
+        BreakerStatus {
+            state,
+            consecutive_failures: inner.consecutive_failures,
+            trips: inner.trips,
+            retry_after_secs,
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|p| p.into_inner())
+    }
+}
+
+impl BreakerPermit<'_> {
+    /// Reports whether the RPC call succeeded.
+    pub fn record(self, ok: bool) {
+        self.record_at(ok, Instant::now());
+    }
+
+    fn record_at(mut self, ok: bool, now: Instant) {
+        self.recorded = true;
+        self.breaker.record_at(ok, self.probe, now);
+    }
+}
+
+impl Drop for BreakerPermit<'_> {
+    fn drop(&mut self) {
+        if self.probe && !self.recorded {
+            self.breaker.release_probe();
+        }
+    }
+}
+
+fn secs_ceil(d: Duration) -> u64 {
+    d.as_millis().div_ceil(1000).max(1) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fail(b: &CircuitBreaker, now: Instant) {
+        b.try_acquire_at(now).unwrap().record_at(false, now);
+    }
+
+    #[test]
+    fn test_trips_after_consecutive_failures_then_recovers() {
+        let b = CircuitBreaker::new(3, Duration::from_secs(60), Duration::from_secs(30));
+        let t0 = Instant::now();
+        fail(&b, t0);
+        fail(&b, t0);
+        assert_eq!(b.status_at(t0).state, "closed");
+        fail(&b, t0 + Duration::from_secs(1));
+
+        // Open: calls fail fast for the cooldown
+        let status = b.status_at(t0 + Duration::from_secs(1));
+        assert_eq!((status.state, status.trips), ("open", 1));
+        assert_eq!(b.try_acquire_at(t0 + Duration::from_secs(11)).err(), Some(20));
+
+        // Half-open: one probe at a time
+        let after = t0 + Duration::from_secs(31);
+        let probe = b.try_acquire_at(after).unwrap();
+        assert!(probe.probe);
+        assert_eq!(b.status_at(after).state, "half_open");
+        assert!(b.try_acquire_at(after).is_err());
+
+        // A successful probe closes it
+        probe.record_at(true, after);
+        let status = b.status_at(after);
+        assert_eq!((status.state, status.consecutive_failures), ("closed", 0));
+        assert!(!b.try_acquire_at(after).unwrap().probe);
+    }
+
+    #[test]
+    fn test_failed_probe_reopens() {
+        let b = CircuitBreaker::new(1, Duration::from_secs(60), Duration::from_secs(30));
+        let t0 = Instant::now();
+        fail(&b, t0);
+        let after = t0 + Duration::from_secs(30);
+        fail(&b, after);
+        let status = b.status_at(after);
+        assert_eq!((status.state, status.trips, status.retry_after_secs), ("open", 2, Some(30)));
+    }
+
+    #[test]
+    fn test_failures_outside_window_and_successes_reset_streak() {
+        let b = CircuitBreaker::new(3, Duration::from_secs(10), Duration::from_secs(30));
+        let t0 = Instant::now();
+        fail(&b, t0);
+        fail(&b, t0);
+        // Too late to join the streak: it starts over
+        fail(&b, t0 + Duration::from_secs(11));
+        assert_eq!(b.status_at(t0 + Duration::from_secs(11)).consecutive_failures, 1);
+
+        let t12 = t0 + Duration::from_secs(12);
+        fail(&b, t12);
+        b.try_acquire_at(t12).unwrap().record_at(true, t12);
+        fail(&b, t0 + Duration::from_secs(13));
+        assert_eq!(b.status_at(t0 + Duration::from_secs(13)).state, "closed");
+    }
+
+    #[test]
+    fn test_late_success_does_not_close_an_open_breaker() {
+        let b = CircuitBreaker::new(1, Duration::from_secs(60), Duration::from_secs(30));
+        let t0 = Instant::now();
+        let slow = b.try_acquire_at(t0).unwrap();
+        fail(&b, t0);
+        slow.record_at(true, t0 + Duration::from_secs(1));
+        assert_eq!(b.status_at(t0 + Duration::from_secs(1)).state, "open");
+    }
+
+    #[test]
+    fn test_unrecorded_probe_frees_the_slot() {
+        let b = CircuitBreaker::new(1, Duration::from_secs(60), Duration::from_secs(30));
+        let t0 = Instant::now();
+        fail(&b, t0);
+        let after = t0 + Duration::from_secs(30);
+        drop(b.try_acquire_at(after).unwrap());
+        assert!(b.try_acquire_at(after).unwrap().probe);
+    }
+}
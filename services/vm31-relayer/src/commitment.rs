@@ -0,0 +1,130 @@
+//! Deposit note lookup keys.
+//!
+//! The relayer stores each deposit's note under a key derived from the note
+//! fields, and clients compute the same key to fetch their note's path from
+//! `/merkle-path/{commitment}`. The derivation is a wire contract: changing
+//! it orphans every note already stored.
+
+use sha2::{Digest, Sha256};
+
+/// Key for a deposit note: lowercase hex SHA-256 over the 44-byte
+/// concatenation, every field little-endian and without separators, of
+///
+/// | bytes  | field                          |
+/// |--------|--------------------------------|
+/// | 0..16  | `owner_pubkey[0..4]`, `u32` each |
+/// | 16..20 | `asset_id`, `u32`              |
+/// | 20..28 | `amount`, `u64`                |
+/// | 28..44 | `blinding[0..4]`, `u32` each   |
+///
+/// `blinding` is the one the proven output note actually carries. A deposit
+/// whose proven note can't be matched keeps the all-zero placeholder.
+pub fn commitment_key_from_fields(
+    owner_pubkey: [u32; 4],
+    asset_id: u32,
+    amount: u64,
+    blinding: [u32; 4],
+) -> String {
+    let mut hasher = Sha256::new();
+    for v in owner_pubkey {
+        hasher.update(v.to_le_bytes());
+    }
+    hasher.update(asset_id.to_le_bytes());
+    hasher.update(amount.to_le_bytes());
+    for v in blinding {
+        hasher.update(v.to_le_bytes());
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_known_vectors() {
+        let vectors: [([u32; 4], u32, u64, [u32; 4], &str); 5] = [
+            (
+                [0; 4],
+                0,
+                0,
+                [0; 4],
+                "85759b3811ff7dc47b03792ac85317be51431a3f9e01dcafce317ed736a391b0",
+            ),
+            // Zero blinding: the placeholder a deposit keeps when its proven
+            // output can't be matched
+            (
+                [1, 2, 3, 4],
+                0,
+                100_000,
+                [0; 4],
+                "51525fd87f73d563aaeb0d2489513687c8aca559a6474822b3aefe6eea087064",
+            ),
+            (
+                [1, 2, 3, 4],
+                0,
+                100_000,
+                [5, 6, 7, 8],
+                "e6f8bfd8e78158fb81ee05586033641099c3b6780c2840573a424a634ae5cf31",
+            ),
+            (
+                [1, 2, 3, 4],
+                1,
+                100_000,
+                [0; 4],
+                "eaa0a6c79b0083eb9620d93d63911338541a2e6af7a8eb99ffbf41f10314c44e",
+            ),
+            // Large limbs and an amount above 32 bits pin byte order and width
+            (
+                [0x7fff_fffe, 1, 0xdead_beef, 0x1234_5678],
+                3,
+                (1 << 40) + 7,
+                [9; 4],
+                "8994b11606cdb2c936970f4bb2d5eb9a33c05978434424d9cf46a8fbccbab0fc",
+            ),
+        ];
+        for (owner, asset_id, amount, blinding, expected) in vectors {
+            assert_eq!(
+                commitment_key_from_fields(owner, asset_id, amount, blinding),
+                expected,
+                "{owner:?} {asset_id} {amount} {blinding:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_hashes_little_endian_concatenation() {
+        let mut bytes = Vec::new();
+        for v in [0x0102_0304u32, 5, 6, 7] {
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        bytes.extend_from_slice(&2u32.to_le_bytes());
+        bytes.extend_from_slice(&0x0a0b_0c0d_0e0f_1011u64.to_le_bytes());
+        for v in [8u32, 9, 10, 11] {
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        assert_eq!(bytes.len(), 44);
+        assert_eq!(bytes[..4], [0x04, 0x03, 0x02, 0x01]);
+        assert_eq!(
+            commitment_key_from_fields([0x0102_0304, 5, 6, 7], 2, 0x0a0b_0c0d_0e0f_1011, [8, 9, 10, 11]),
+            format!("{:x}", Sha256::digest(&bytes))
+        );
+    }
+
+    #[test]
+    fn test_every_field_changes_the_key() {
+        let base = commitment_key_from_fields([1, 2, 3, 4], 0, 100_000, [5, 6, 7, 8]);
+        assert_eq!(base.len(), 64);
+        assert!(base.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b)));
+        for changed in [
+            commitment_key_from_fields([1, 2, 3, 5], 0, 100_000, [5, 6, 7, 8]),
+            commitment_key_from_fields([1, 2, 3, 4], 1, 100_000, [5, 6, 7, 8]),
+            commitment_key_from_fields([1, 2, 3, 4], 0, 100_001, [5, 6, 7, 8]),
+            commitment_key_from_fields([1, 2, 3, 4], 0, 100_000, [5, 6, 7, 9]),
+            // Same bytes in a different field order
+            commitment_key_from_fields([4, 3, 2, 1], 0, 100_000, [5, 6, 7, 8]),
+        ] {
+            assert_ne!(changed, base);
+        }
+    }
+}
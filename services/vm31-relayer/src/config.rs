@@ -1,5 +1,13 @@
+use std::collections::{BTreeMap, HashMap};
 use std::env;
 
+use crate::bridge::BridgeBackendKind;
+use crate::denominations::Denominations;
+use crate::denylist::PubkeyDenylist;
+use crate::prover::{AnonSetMode, CommitmentMapping, DuplicateNotePolicy};
+use crate::routes::MAX_MERKLE_DEPTH;
+use crate::store::RateLimitAlgo;
+
 #[derive(Debug, Clone)]
 pub struct RelayerConfig {
     // Server
@@ -8,47 +16,287 @@ pub struct RelayerConfig {
 
     // Starknet
     pub rpc_url: String,
+    /// Extra RPC endpoints and relative weights for read-only pool calls
+    /// (validation, tree sync). `rpc_url` gets weight 1 unless listed.
+    pub rpc_weights: Vec<(String, u32)>,
+    /// Backup RPC URLs, in failover order, for validation and tree sync.
+    /// Also used to cross-verify synced roots.
+    pub verify_rpc_urls: Vec<String>,
+    /// Consecutive pool RPC failures, within `rpc_breaker_window_secs`, that
+    /// open the circuit breaker (default: 5, 0 = no breaker).
+    pub rpc_breaker_threshold: u32,
+    pub rpc_breaker_window_secs: u64,
+    /// Seconds an open breaker fails calls fast before probing (default: 30).
+    pub rpc_breaker_cooldown_secs: u64,
     pub network: String,
+    /// sncast account used for every signed call: batch submission
+    /// (`SncastVm31Backend`) and bridge invokes (`BridgeService`). With the
+    /// native bridge backend this must be the account address, and
+    /// `relayer_account_key` is checked against it at startup so proving and
+    /// bridging can't diverge onto different accounts.
     pub account: String,
+    /// How bridge invokes are sent: sncast (default) or native JSON-RPC.
+    pub bridge_backend: BridgeBackendKind,
+    /// Stark private key for `account`, required by the native backend.
+    pub relayer_account_key: Option<[u8; 32]>,
     pub verifier_contract: String,
     pub pool_contract: String,
     pub bridge_contract: String,
+    /// Per-asset bridge contract overrides (asset_id → address).
+    /// Assets not in the map use `bridge_contract`.
+    pub bridge_contracts: HashMap<u32, String>,
     pub ct_contract: String,
 
     // Batch
     pub batch_max_size: usize,
     pub batch_timeout_secs: u64,
     pub chunk_size: u32,
+    /// When the prover channel closes, prove batches still buffered (true, default)
+    /// or mark them Failed for resubmission (false), dead-lettering their
+    /// transactions when `dead_letter_enabled`.
+    pub prover_drain_on_close: bool,
+    /// Seconds to wait at shutdown for the prover to finish its active and
+    /// flushed batches before exiting anyway (default: 300).
+    pub shutdown_grace_secs: u64,
+    /// Exit after a prover stage panics, once the batch is marked Failed
+    /// (default: false, keep serving).
+    pub prover_exit_on_panic: bool,
+    /// Keep the transactions of batches that fail processing for replay via
+    /// `/dlq` (default: true).
+    pub dead_letter_enabled: bool,
+    /// Extra attempts for each batch status update before giving up (default: 3).
+    pub status_update_retries: u32,
+    /// Extra attempts for a flushed batch's initial record before its txs
+    /// are moved to the dead-letter store (default: 3).
+    pub batch_save_retries: u32,
+    /// Extra attempts for a batch's on-chain submission after a transient
+    /// RPC failure (default: 3).
+    pub submit_max_retries: u32,
+    /// Append-only JSONL file for terminal batch outcomes the store failed to
+    /// record (default: vm31-recovery.jsonl).
+    pub recovery_log_path: String,
+    /// Append-only log of queued txs, replayed on startup so a restart
+    /// doesn't lose unflushed submissions (unset = in-memory only).
+    pub queue_wal_path: Option<String>,
+    /// Serve per-stage latency percentiles at `/stats` (default: false).
+    pub latency_stats_enabled: bool,
+    /// Window after which latency histograms are reset; 0 = never (default: 3600).
+    pub latency_window_secs: u64,
     /// Minimum transactions required for timeout-triggered flush (default: 3).
     /// Prevents single-tx batches that offer zero privacy mixing.
     pub min_batch_size: usize,
     /// Maximum seconds any transaction can wait in queue (default: 300).
     /// Hard ceiling to prevent indefinite queueing when min_batch_size is not met.
     pub max_batch_wait_secs: u64,
+    /// Per-type `(timeout_secs, max_wait_secs)` overrides. Each defaults to the
+    /// queue-wide values; a batch flushes on the earliest deadline among the
+    /// tx types it holds (e.g. a short withdrawal timeout flushes queued deposits too).
+    pub deposit_batch_deadlines: (u64, u64),
+    pub withdraw_batch_deadlines: (u64, u64),
+    pub transfer_batch_deadlines: (u64, u64),
+    /// Flush on wall-clock multiples of this many seconds instead of relative
+    /// timeouts (unset = relative, the default). PRIVACY: aligned flush times
+    /// are predictable to observers, but relayers sharing a window can pool
+    /// their anonymity sets.
+    pub flush_align_secs: Option<u64>,
+    /// Seal a due timeout flush after a random 0..=N second delay, capped at
+    /// the batch's max wait (default: 0 = no jitter).
+    pub flush_jitter_secs: u64,
+    /// Size flushes fire at a random size up to this many txs below
+    /// `batch_max_size` (default: 0 = always at max size).
+    pub batch_size_jitter: usize,
+    /// Fewest txs of one kind and asset a batch should carry when it is
+    /// proven (default: 0 = unchecked).
+    pub min_anon_set: usize,
+    /// Whether a batch short of `min_anon_set` is only logged or has the
+    /// short groups returned to the queue (default: lenient).
+    pub anon_set_mode: AnonSetMode,
+    /// Keep one sub-queue per asset id so batches never mix assets. Each
+    /// asset flushes on its own size and deadlines (smaller anonymity sets
+    /// for low-volume assets).
+    pub batch_per_asset: bool,
+    /// Keep one sub-queue per tx kind so deposits, withdrawals and transfers
+    /// never share a batch (and proof). Combines with `batch_per_asset`.
+    pub batch_segregate_by_kind: bool,
+    /// Deposits a batch must carry per withdrawal before it size- or
+    /// timeout-flushes (unset = no ratio). Max wait and max size remain hard
+    /// ceilings, so withdrawals are delayed but never stranded.
+    pub min_deposit_withdraw_ratio: Option<f64>,
+    /// TEST ONLY: disable batch shuffling so batches keep submission order.
+    /// Destroys ordering privacy — refused in release builds unless
+    /// VM31_DANGEROUS_ALLOW_DETERMINISTIC=true, and always refused on mainnet.
+    pub deterministic: bool,
+    /// TEST ONLY: seed the batch shuffle so permutations are reproducible.
+    /// Same guards as `deterministic`; the shuffle is a CSPRNG otherwise.
+    pub shuffle_seed: Option<u64>,
 
     // Auth
-    pub api_keys: Vec<String>,
+    pub api_keys: ApiKeys,
+    /// Keys for the admin endpoints, from `VM31_ADMIN_KEYS`. When unset, the
+    /// admin endpoints are closed (403) to every key. In superset mode
+    /// (`VM31_ADMIN_KEY_MODE`) these keys are also merged into `api_keys`.
+    pub admin_keys: Option<ApiKeys>,
+
+    // Quarantine (manual review of flagged submissions)
+    /// API keys whose submissions are always held for review.
+    pub quarantine_keys: Vec<String>,
+    /// Hold submissions with `amount >= threshold` for review.
+    pub quarantine_amount_threshold: Option<u64>,
+    /// Hold submissions for these asset IDs for review.
+    pub quarantine_asset_ids: Vec<u32>,
+
+    // Deposit limits
+    /// Per-asset single-deposit maximum and per-key daily deposit volume cap
+    /// (asset_id → limits). Assets not in the map are unlimited.
+    pub deposit_limits: HashMap<u32, DepositLimit>,
 
     // ECIES encryption for relayer submissions
     /// X25519 private key for decrypting ECIES envelopes (32 bytes, hex-encoded).
     /// Generated via `openssl rand -hex 32` and set as VM31_RELAYER_PRIVKEY.
     pub relayer_private_key: Option<[u8; 32]>,
+    /// The key being rotated out (VM31_RELAYER_PRIVKEY_PREV). Envelopes that
+    /// fail to open under the current key are retried with it, so clients
+    /// still holding the old public key keep working during the overlap.
+    pub relayer_private_key_prev: Option<[u8; 32]>,
     /// When false, reject plaintext submissions (mainnet mode).
     /// When true, accept both encrypted and plaintext (migration mode).
     pub legacy_plaintext_allowed: bool,
+    /// DEV ONLY: serve `POST /encrypt-check`, which reports whether an ECIES
+    /// envelope decrypts and parses (no plaintext returned). Refused on mainnet.
+    pub encrypt_check_enabled: bool,
+    /// Serve the OpenAPI description at `GET /openapi.json` (default: false).
+    pub openapi_enabled: bool,
+    /// Serve `/admin/store/export` and `/admin/store/import` (default: false).
+    pub store_transfer_enabled: bool,
+    /// Body limit for `/admin/store/import` (default: 512 MiB).
+    pub store_import_max_bytes: usize,
+    /// Serve `POST /submit/stream` for NDJSON submissions (default: false).
+    pub submit_stream_enabled: bool,
+    /// Body limit for `/submit/stream` (default: 8 MiB).
+    pub submit_stream_max_bytes: usize,
+    /// Fetch the pool's registered assets at startup, warn on mismatches with
+    /// the denomination table and serve them at `GET /assets` (default: false).
+    pub asset_discovery_enabled: bool,
+    /// Highest number of asset ids probed during discovery (default: 16).
+    pub asset_probe_limit: u32,
+    /// Serve Prometheus metrics at `GET /metrics` (default: false).
+    pub metrics_enabled: bool,
+    /// Listen address for the metrics endpoint, kept off the public API
+    /// port (default: 127.0.0.1:9090).
+    pub metrics_addr: std::net::SocketAddr,
+    /// Depth of the pool's Merkle tree; submitted paths must have exactly
+    /// this many siblings (default: 32).
+    pub merkle_depth: usize,
+    /// When true, reject submissions with obviously-wrong key relationships
+    /// (e.g. a spending key equal to the note's public key).
+    pub strict_key_validation: bool,
+    /// When true, reject transfers whose inputs equal the amount exactly
+    /// (zero-value change note); otherwise they are accepted with a warning.
+    pub reject_zero_change: bool,
+    /// Reject a withdrawal whose `withdrawal_binding` was already used by a
+    /// different submission within the idempotency window, so a reused
+    /// binding salt can't make two withdrawals linkable.
+    pub unique_withdrawal_bindings: bool,
+    /// Seconds a withdrawal binding is remembered after `/submit` accepts
+    /// it; a second withdrawal with the same binding in that window is
+    /// rejected before proving (default: 300, 0 = off).
+    pub binding_replay_window_secs: u64,
+    /// Inclusive range of `recipient_pubkey[0]` values reserved for
+    /// relayer-controlled decoy notes. Client deposits and transfers to a key
+    /// in this range are rejected so decoy funds can't be claimed or mixed in.
+    pub decoy_pubkey_range: Option<(u32, u32)>,
+    /// Recipient pubkeys that deposits and transfers may not pay to, loaded
+    /// from `VM31_PUBKEY_DENYLIST_PATH` as `(path, initial list)`. Reloaded
+    /// on SIGHUP (see `denylist`).
+    pub pubkey_denylist: Option<(String, PubkeyDenylist)>,
+    /// Deposit denomination whitelists per asset, from `VM31_DENOMINATIONS_PATH`
+    /// (JSON) or the built-in table when unset, plus any legacy grace set
+    /// from `VM31_LEGACY_DENOMINATIONS`.
+    pub denominations: Denominations,
+    /// Asset IDs accepted in submissions, for the tx and its input notes
+    /// (empty = any canonical M31 asset ID).
+    pub allowed_asset_ids: Vec<u32>,
+    /// Maximum age of an ECIES envelope in seconds (default: 3600).
+    /// Seen `(ephemeral_pubkey, nonce)` pairs are remembered for this long.
+    pub envelope_max_age_secs: u64,
+    /// Upper bound on remembered `(ephemeral_pubkey, nonce)` pairs (default: 100000).
+    pub nonce_guard_max_entries: usize,
+    /// When true, the nonce guard is backed by Redis so seen pairs survive restarts.
+    /// Requires REDIS_URL.
+    pub nonce_guard_persistent: bool,
+    /// Envelopes whose sealed `timestamp` is further than this from the
+    /// relayer clock are rejected (default: 0 = timestamps not checked).
+    pub ecies_max_skew_secs: u64,
+    /// Reject envelopes without a sealed `timestamp` (default: false, so
+    /// clients that don't send one keep working). Needs `ecies_max_skew_secs`.
+    pub ecies_require_timestamp: bool,
 
-    // Encrypted note storage
-    /// AES-256 key for encrypting NoteRecord values at rest (32 bytes, hex-encoded).
+    // Encrypted record storage
+    /// AES-256 key for encrypting batch and note records at rest (32 bytes, hex-encoded).
     pub storage_key: Option<[u8; 32]>,
 
     // Redis (optional)
     pub redis_url: Option<String>,
+    /// Fail startup if Redis is configured but unreachable (default: false).
+    pub redis_required: bool,
+    /// On mid-operation Redis failures, keep serving from in-memory state
+    /// instead of failing the request (default: true).
+    pub redis_degrade_on_error: bool,
+
+    // Postgres (optional)
+    /// Postgres write-through for batches and notes (`VM31_POSTGRES_URL`).
+    /// Mutually exclusive with `redis_url`.
+    pub postgres_url: Option<String>,
+
+    // Store eviction
+    /// Entries evicted in one pass at or above which an info-level
+    /// memory-pressure summary is logged (default: 1000).
+    pub eviction_pressure_threshold: usize,
 
     // Rate limiting
     pub rate_limit_per_min: u32,
+    /// fixed_window (default) or token_bucket with a burst of
+    /// `VM31_RATE_LIMIT_BURST` times each limit.
+    pub rate_limit_algo: RateLimitAlgo,
+    /// Maximum concurrent in-flight `/submit` requests per API key (default: 8).
+    pub max_inflight_per_key: usize,
+    /// Maximum open `/batch/{id}/events` streams per API key (default: 4).
+    pub batch_event_streams_per_key: usize,
+    /// Drop a `/batch/{id}/events` stream whose client hasn't read an event
+    /// or keep-alive ping within this many seconds (default: 60).
+    pub stream_idle_timeout_secs: u64,
+    /// Optional per-minute limit keyed by decrypted content (API key, tx type,
+    /// asset). Disabled when unset. PRIVACY: this runs on plaintext after ECIES
+    /// decryption, so it trades some of the encrypted-submission privacy model
+    /// for content-aware throttling.
+    pub content_rate_limit_per_min: Option<u32>,
+    /// Per-minute `POST /validate` dry runs per API key, separate from the
+    /// submit budget (default: 3x `rate_limit_per_min`).
+    pub validate_rate_limit_per_min: u32,
+    /// Per-minute merkle path lookups per API key; a `POST /merkle-paths`
+    /// call counts once per commitment (default: 600).
+    pub merkle_path_rate_limit_per_min: u32,
+    /// `POST /submit` response time is rounded up to a multiple of this many
+    /// milliseconds so it doesn't reveal the tx type or submission path
+    /// (default: 25, 0 disables).
+    pub submit_timing_target_ms: u64,
+    /// `max-age` for `GET /batch/{id}` responses on finalized or failed
+    /// batches, sent with an ETag and `immutable` (default: 86400; 0 sends
+    /// `no-cache` for every status).
+    pub batch_cache_max_age_secs: u64,
+    /// Rate-limit rejections per key or IP within `abuse_alert_window_secs`
+    /// that trigger an abuse alert log. Disabled when unset.
+    pub abuse_alert_threshold: Option<u32>,
+    /// Window for counting rate-limit rejections (default: 300).
+    pub abuse_alert_window_secs: u64,
 
     // CORS
     pub allowed_origins: Vec<String>,
+    /// When true, every allowed origin must be https:// (default: true).
+    pub cors_require_https: bool,
+    /// When true, http://localhost / 127.0.0.1 / [::1] origins are accepted
+    /// even if `cors_require_https` is set (default: false).
+    pub cors_allow_localhost: bool,
 
     // Trusted proxy IPs for X-Forwarded-For validation.
     // When non-empty, X-Forwarded-For is only trusted if the request came from one of these IPs.
@@ -58,12 +306,69 @@ pub struct RelayerConfig {
     // Tree sync
     pub tree_cache_path: Option<String>,
     pub tree_sync_interval_secs: u64,
+    /// Base backoff for re-attempting a pending note that failed to match (default: 15).
+    pub backfill_backoff_base_secs: u64,
+    /// Ceiling on the per-note backfill backoff (default: 3600).
+    pub backfill_backoff_max_secs: u64,
+    /// Pending notes backfilled per tree lock; the lock is released between
+    /// pages so proof lookups aren't stalled by a large backlog (default: 256).
+    pub backfill_page_size: usize,
+    /// Seconds after which a pending note without a commitment digest is
+    /// reported as `stuck` instead of `pending_sync` (default: 3600).
+    pub stuck_note_threshold_secs: u64,
+    /// Refuse on-demand merkle proofs when the last successful tree sync is
+    /// older than this, reporting `stale` instead (default: 300; 0 = no limit).
+    pub max_proof_staleness_secs: u64,
+    /// Recent verified roots the prover accepts without an `is_known_root`
+    /// RPC: local tree roots and roots the RPC already confirmed (default: 32;
+    /// 0 disables the fast path).
+    pub local_root_cache_size: usize,
+    /// Roots the pool contract keeps in its root history (default: 100). A
+    /// cached root is dropped once the local tree has grown this many leaves
+    /// past it.
+    pub root_history_size: u64,
+    /// Serve merkle paths only while the local root is confirmed on-chain,
+    /// and accept withdrawal/transfer roots only from the local root cache
+    /// (default: false). Requires `local_root_cache_size > 0`.
+    pub confirmed_roots_only: bool,
+    /// Nullifiers confirmed spent that the prover rejects without an
+    /// `is_nullifier_spent` RPC (default: 10000; 0 disables the cache).
+    pub nullifier_cache_size: usize,
+    /// Seconds a batch proof is kept for reuse by a replay of the same
+    /// transactions (default: 0 = no proof cache).
+    pub proof_cache_ttl_secs: u64,
+    /// OTLP collector that batch lifecycle traces are exported to
+    /// (e.g. http://localhost:4317). Needs the `otel` feature.
+    pub otlp_endpoint: Option<String>,
+    /// How proven output commitments are assigned to deposit notes:
+    /// by_note (default) or positional.
+    pub commitment_mapping: CommitmentMapping,
+    /// A new deposit note whose key another batch's note already has:
+    /// disambiguate (default, tracked as `{commitment}-{batch_id}`) or reject.
+    pub duplicate_note_policy: DuplicateNotePolicy,
+    /// Index deposit notes by owner pubkey and serve
+    /// `GET /notes/by-owner/{pubkey}` (default: false).
+    pub note_owner_index: bool,
 }
 
 impl RelayerConfig {
     pub fn from_env() -> Result<Self, ConfigError> {
         let rpc_url = require_env("STARKNET_RPC_URL")?;
-        validate_rpc_url(&rpc_url)?;
+        validate_rpc_url(&rpc_url, "STARKNET_RPC_URL")?;
+        let rpc_weights = parse_rpc_weights()?;
+        let verify_rpc_urls = match env::var("VM31_VERIFY_RPC_URLS") {
+            Ok(raw) => parse_verify_rpc_urls(&raw)?,
+            Err(_) => Vec::new(),
+        };
+        let rpc_breaker_threshold: u32 = parse_env_or("VM31_RPC_BREAKER_THRESHOLD", 5)?;
+        let rpc_breaker_window_secs: u64 = parse_env_or("VM31_RPC_BREAKER_WINDOW_SECS", 60)?;
+        if rpc_breaker_window_secs == 0 {
+            return Err(ConfigError::Invalid("VM31_RPC_BREAKER_WINDOW_SECS".into(), "must be > 0".into()));
+        }
+        let rpc_breaker_cooldown_secs: u64 = parse_env_or("VM31_RPC_BREAKER_COOLDOWN_SECS", 30)?;
+        if rpc_breaker_cooldown_secs == 0 {
+            return Err(ConfigError::Invalid("VM31_RPC_BREAKER_COOLDOWN_SECS".into(), "must be > 0".into()));
+        }
 
         let network = env::var("STARKNET_NETWORK").unwrap_or_else(|_| "sepolia".into());
         if network != "mainnet" && network != "sepolia" {
@@ -74,26 +379,107 @@ impl RelayerConfig {
         }
 
         let account = require_env("STARKNET_ACCOUNT")?;
+        // A 0x value is an account address; anything else is an sncast account name
+        if account.starts_with("0x") {
+            validate_felt_address(&account, "STARKNET_ACCOUNT")?;
+        }
+        let bridge_backend: BridgeBackendKind = env::var("VM31_BRIDGE_BACKEND")
+            .unwrap_or_else(|_| "sncast".into())
+            .parse()
+            .map_err(|e| ConfigError::Invalid("VM31_BRIDGE_BACKEND".into(), e))?;
+        let relayer_account_key = parse_hex_key_32("VM31_RELAYER_ACCOUNT_KEY")?;
+        if bridge_backend == BridgeBackendKind::Native {
+            if !cfg!(feature = "native-bridge") {
+                return Err(ConfigError::Invalid(
+                    "VM31_BRIDGE_BACKEND".into(),
+                    "native backend requires building with --features native-bridge".into(),
+                ));
+            }
+            if !account.starts_with("0x") {
+                return Err(ConfigError::Invalid(
+                    "STARKNET_ACCOUNT".into(),
+                    "native bridge backend needs the account address, not an sncast account name".into(),
+                ));
+            }
+            if relayer_account_key.is_none() {
+                return Err(ConfigError::Missing("VM31_RELAYER_ACCOUNT_KEY (required by VM31_BRIDGE_BACKEND=native)".into()));
+            }
+        }
         let verifier_contract = require_env("VM31_VERIFIER_CONTRACT")?;
-        validate_hex(&verifier_contract, "VM31_VERIFIER_CONTRACT")?;
+        validate_felt_address(&verifier_contract, "VM31_VERIFIER_CONTRACT")?;
         let pool_contract = require_env("VM31_POOL_CONTRACT")?;
-        validate_hex(&pool_contract, "VM31_POOL_CONTRACT")?;
+        validate_felt_address(&pool_contract, "VM31_POOL_CONTRACT")?;
         let bridge_contract = require_env("VM31_BRIDGE_CONTRACT")?;
-        validate_hex(&bridge_contract, "VM31_BRIDGE_CONTRACT")?;
+        validate_felt_address(&bridge_contract, "VM31_BRIDGE_CONTRACT")?;
+        let bridge_contracts = parse_bridge_contracts()?;
+        let deposit_limits = parse_deposit_limits()?;
         let ct_contract = require_env("VM31_CT_CONTRACT")?;
-        validate_hex(&ct_contract, "VM31_CT_CONTRACT")?;
+        validate_felt_address(&ct_contract, "VM31_CT_CONTRACT")?;
 
-        let api_keys_raw = require_env("VM31_API_KEYS")?;
-        let api_keys: Vec<String> = api_keys_raw
+        let mut api_keys = ApiKeys::parse(&require_env("VM31_API_KEYS")?)?;
+        let admin_keys = match env::var("VM31_ADMIN_KEYS") {
+            Ok(raw) if !raw.trim().is_empty() => Some(ApiKeys::parse_admin(&raw)?),
+            _ => None,
+        };
+        let admin_key_mode: AdminKeyMode = env::var("VM31_ADMIN_KEY_MODE")
+            .unwrap_or_else(|_| "separate".into())
+            .parse()
+            .map_err(|e| ConfigError::Invalid("VM31_ADMIN_KEY_MODE".into(), e))?;
+        if let (Some(admin), AdminKeyMode::Superset) = (&admin_keys, admin_key_mode) {
+            api_keys.merge(admin);
+        }
+
+        let quarantine_keys: Vec<String> = env::var("VM31_QUARANTINE_KEYS")
+            .unwrap_or_default()
             .split(',')
             .map(|s| s.trim().to_string())
             .filter(|s| !s.is_empty())
             .collect();
-        if api_keys.is_empty() {
-            return Err(ConfigError::Missing("VM31_API_KEYS (no valid keys found)".into()));
-        }
+        let quarantine_amount_threshold: Option<u64> =
+            match env::var("VM31_QUARANTINE_AMOUNT_THRESHOLD") {
+                Ok(v) if !v.is_empty() => Some(v.parse().map_err(|_| {
+                    ConfigError::Invalid(
+                        "VM31_QUARANTINE_AMOUNT_THRESHOLD".into(),
+                        format!("could not parse '{v}'"),
+                    )
+                })?),
+                _ => None,
+            };
+        let quarantine_asset_ids = env::var("VM31_QUARANTINE_ASSET_IDS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .map(|s| {
+                s.parse::<u32>().map_err(|_| {
+                    ConfigError::Invalid(
+                        "VM31_QUARANTINE_ASSET_IDS".into(),
+                        format!("could not parse '{s}'"),
+                    )
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
 
         let redis_url = env::var("REDIS_URL").ok().filter(|s| !s.is_empty());
+        let redis_required: bool = env::var("VM31_REDIS_REQUIRED")
+            .map(|v| v == "true" || v == "1")
+            .unwrap_or(false);
+        if redis_required && redis_url.is_none() {
+            return Err(ConfigError::Invalid(
+                "VM31_REDIS_REQUIRED".into(),
+                "requires REDIS_URL to be set".into(),
+            ));
+        }
+        let redis_degrade_on_error: bool = env::var("VM31_REDIS_DEGRADE")
+            .map(|v| v == "true" || v == "1")
+            .unwrap_or(true);
+        let postgres_url = env::var("VM31_POSTGRES_URL").ok().filter(|s| !s.is_empty());
+        if postgres_url.is_some() && redis_url.is_some() {
+            return Err(ConfigError::Invalid(
+                "VM31_POSTGRES_URL".into(),
+                "cannot be combined with REDIS_URL; pick one write-through backend".into(),
+            ));
+        }
 
         let allowed_origins = env::var("VM31_ALLOWED_ORIGINS")
             .unwrap_or_default()
@@ -102,6 +488,16 @@ impl RelayerConfig {
             .filter(|s| !s.is_empty())
             .collect::<Vec<_>>();
 
+        let cors_require_https: bool = env::var("VM31_CORS_REQUIRE_HTTPS")
+            .map(|v| v == "true" || v == "1")
+            .unwrap_or(true);
+        let cors_allow_localhost: bool = env::var("VM31_CORS_ALLOW_LOCALHOST")
+            .map(|v| v == "true" || v == "1")
+            .unwrap_or(false);
+        for origin in &allowed_origins {
+            validate_origin(origin, cors_require_https, cors_allow_localhost)?;
+        }
+
         let batch_max_size: usize = parse_env_or("VM31_BATCH_MAX_SIZE", 16)?;
         if batch_max_size == 0 {
             return Err(ConfigError::Invalid("VM31_BATCH_MAX_SIZE".into(), "must be > 0".into()));
@@ -114,10 +510,116 @@ impl RelayerConfig {
         if chunk_size == 0 {
             return Err(ConfigError::Invalid("VM31_CHUNK_SIZE".into(), "must be > 0".into()));
         }
+        let prover_drain_on_close: bool = env::var("VM31_PROVER_DRAIN_ON_CLOSE")
+            .map(|v| v == "true" || v == "1")
+            .unwrap_or(true);
+        let shutdown_grace_secs: u64 = parse_env_or("VM31_SHUTDOWN_GRACE_SECS", 300)?;
+        if shutdown_grace_secs == 0 {
+            return Err(ConfigError::Invalid("VM31_SHUTDOWN_GRACE_SECS".into(), "must be > 0".into()));
+        }
+        let prover_exit_on_panic: bool = env::var("VM31_PROVER_EXIT_ON_PANIC")
+            .map(|v| v == "true" || v == "1")
+            .unwrap_or(false);
+        let dead_letter_enabled: bool = env::var("VM31_DEAD_LETTER")
+            .map(|v| v == "true" || v == "1")
+            .unwrap_or(true);
+        let status_update_retries: u32 = parse_env_or("VM31_STATUS_UPDATE_RETRIES", 3)?;
+        let batch_save_retries: u32 = parse_env_or("VM31_BATCH_SAVE_RETRIES", 3)?;
+        let submit_max_retries: u32 = parse_env_or("VM31_SUBMIT_MAX_RETRIES", 3)?;
+        let queue_wal_path = env::var("VM31_QUEUE_WAL_PATH").ok().filter(|s| !s.is_empty());
+        let recovery_log_path = env::var("VM31_RECOVERY_LOG")
+            .ok()
+            .filter(|s| !s.is_empty())
+            .unwrap_or_else(|| "vm31-recovery.jsonl".into());
+        let latency_stats_enabled: bool = env::var("VM31_LATENCY_STATS")
+            .map(|v| v == "true" || v == "1")
+            .unwrap_or(false);
+        let latency_window_secs: u64 = parse_env_or("VM31_LATENCY_WINDOW_SECS", 3600)?;
         let rate_limit_per_min: u32 = parse_env_or("VM31_RATE_LIMIT", 30)?;
         if rate_limit_per_min == 0 {
             return Err(ConfigError::Invalid("VM31_RATE_LIMIT".into(), "must be > 0".into()));
         }
+        let rate_limit_algo = match env::var("VM31_RATE_LIMIT_ALGO").as_deref() {
+            Err(_) | Ok("") | Ok("fixed_window") => RateLimitAlgo::FixedWindow,
+            Ok("token_bucket") => {
+                let burst: f64 = parse_env_or("VM31_RATE_LIMIT_BURST", 1.0)?;
+                if !burst.is_finite() || burst < 1.0 {
+                    return Err(ConfigError::Invalid("VM31_RATE_LIMIT_BURST".into(), "must be >= 1.0".into()));
+                }
+                RateLimitAlgo::TokenBucket { burst }
+            }
+            Ok(other) => {
+                return Err(ConfigError::Invalid(
+                    "VM31_RATE_LIMIT_ALGO".into(),
+                    format!("unknown algorithm '{other}' (expected fixed_window or token_bucket)"),
+                ));
+            }
+        };
+        let validate_rate_limit_per_min: u32 =
+            parse_env_or("VM31_VALIDATE_RATE_LIMIT", rate_limit_per_min.saturating_mul(3))?;
+        if validate_rate_limit_per_min == 0 {
+            return Err(ConfigError::Invalid("VM31_VALIDATE_RATE_LIMIT".into(), "must be > 0".into()));
+        }
+        let merkle_path_rate_limit_per_min: u32 = parse_env_or("VM31_MERKLE_PATH_RATE_LIMIT", 600)?;
+        if merkle_path_rate_limit_per_min == 0 {
+            return Err(ConfigError::Invalid("VM31_MERKLE_PATH_RATE_LIMIT".into(), "must be > 0".into()));
+        }
+        let submit_timing_target_ms: u64 = parse_env_or("VM31_SUBMIT_TIMING_TARGET_MS", 25)?;
+        let batch_cache_max_age_secs: u64 = parse_env_or("VM31_BATCH_CACHE_MAX_AGE_SECS", 86_400)?;
+        let content_rate_limit_per_min: Option<u32> =
+            match env::var("VM31_CONTENT_RATE_LIMIT") {
+                Ok(v) if !v.is_empty() => {
+                    let limit: u32 = v.parse().map_err(|_| {
+                        ConfigError::Invalid(
+                            "VM31_CONTENT_RATE_LIMIT".into(),
+                            format!("could not parse '{v}'"),
+                        )
+                    })?;
+                    if limit == 0 {
+                        return Err(ConfigError::Invalid(
+                            "VM31_CONTENT_RATE_LIMIT".into(),
+                            "must be > 0 (unset to disable)".into(),
+                        ));
+                    }
+                    Some(limit)
+                }
+                _ => None,
+            };
+        let abuse_alert_threshold: Option<u32> = match env::var("VM31_ABUSE_ALERT_THRESHOLD") {
+            Ok(v) if !v.is_empty() => {
+                let threshold: u32 = v.parse().map_err(|_| {
+                    ConfigError::Invalid(
+                        "VM31_ABUSE_ALERT_THRESHOLD".into(),
+                        format!("could not parse '{v}'"),
+                    )
+                })?;
+                if threshold == 0 {
+                    return Err(ConfigError::Invalid(
+                        "VM31_ABUSE_ALERT_THRESHOLD".into(),
+                        "must be > 0 (unset to disable)".into(),
+                    ));
+                }
+                Some(threshold)
+            }
+            _ => None,
+        };
+        let abuse_alert_window_secs: u64 = parse_env_or("VM31_ABUSE_ALERT_WINDOW_SECS", 300)?;
+        if abuse_alert_window_secs == 0 {
+            return Err(ConfigError::Invalid("VM31_ABUSE_ALERT_WINDOW_SECS".into(), "must be > 0".into()));
+        }
+        let max_inflight_per_key: usize = parse_env_or("VM31_MAX_INFLIGHT_PER_KEY", 8)?;
+        if max_inflight_per_key == 0 {
+            return Err(ConfigError::Invalid("VM31_MAX_INFLIGHT_PER_KEY".into(), "must be > 0".into()));
+        }
+
+        let batch_event_streams_per_key: usize = parse_env_or("VM31_BATCH_EVENT_STREAMS_PER_KEY", 4)?;
+        if batch_event_streams_per_key == 0 {
+            return Err(ConfigError::Invalid("VM31_BATCH_EVENT_STREAMS_PER_KEY".into(), "must be > 0".into()));
+        }
+        let stream_idle_timeout_secs: u64 = parse_env_or("VM31_STREAM_IDLE_TIMEOUT_SECS", 60)?;
+        if stream_idle_timeout_secs == 0 {
+            return Err(ConfigError::Invalid("VM31_STREAM_IDLE_TIMEOUT_SECS".into(), "must be > 0".into()));
+        }
 
         let min_batch_size: usize = parse_env_or("VM31_MIN_BATCH_SIZE", 3)?;
         if min_batch_size == 0 {
@@ -128,15 +630,230 @@ impl RelayerConfig {
             return Err(ConfigError::Invalid("VM31_MAX_BATCH_WAIT_SECS".into(), "must be > 0".into()));
         }
 
+        let deposit_batch_deadlines =
+            parse_type_deadlines("DEPOSIT", batch_timeout_secs, max_batch_wait_secs)?;
+        let withdraw_batch_deadlines =
+            parse_type_deadlines("WITHDRAW", batch_timeout_secs, max_batch_wait_secs)?;
+        let transfer_batch_deadlines =
+            parse_type_deadlines("TRANSFER", batch_timeout_secs, max_batch_wait_secs)?;
+
+        let flush_align_secs: Option<u64> = match env::var("VM31_FLUSH_ALIGN_SECS") {
+            Ok(v) if !v.is_empty() => {
+                let secs: u64 = v.parse().map_err(|_| {
+                    ConfigError::Invalid("VM31_FLUSH_ALIGN_SECS".into(), format!("could not parse '{v}'"))
+                })?;
+                if secs == 0 {
+                    return Err(ConfigError::Invalid(
+                        "VM31_FLUSH_ALIGN_SECS".into(),
+                        "must be > 0 (unset for relative timeouts)".into(),
+                    ));
+                }
+                Some(secs)
+            }
+            _ => None,
+        };
+
+        let flush_jitter_secs: u64 = parse_env_or("VM31_FLUSH_JITTER_SECS", 0)?;
+        let batch_size_jitter: usize = parse_env_or("VM31_BATCH_SIZE_JITTER", 0)?;
+        if batch_size_jitter >= batch_max_size {
+            return Err(ConfigError::Invalid(
+                "VM31_BATCH_SIZE_JITTER".into(),
+                format!("must be < VM31_BATCH_MAX_SIZE ({batch_max_size})"),
+            ));
+        }
+        // A floor above the smallest size flush could never be met by one kind
+        let min_anon_set: usize = parse_env_or("VM31_MIN_ANON_SET", 0)?;
+        if min_anon_set > batch_max_size - batch_size_jitter {
+            return Err(ConfigError::Invalid(
+                "VM31_MIN_ANON_SET".into(),
+                format!(
+                    "must be <= VM31_BATCH_MAX_SIZE - VM31_BATCH_SIZE_JITTER ({})",
+                    batch_max_size - batch_size_jitter
+                ),
+            ));
+        }
+        let anon_set_mode: AnonSetMode = env::var("VM31_ANON_SET_MODE")
+            .unwrap_or_else(|_| "lenient".into())
+            .parse()
+            .map_err(|e| ConfigError::Invalid("VM31_ANON_SET_MODE".into(), e))?;
+
+        let batch_per_asset = env::var("VM31_BATCH_PER_ASSET")
+            .map(|v| v == "true" || v == "1")
+            .unwrap_or(false);
+        let batch_segregate_by_kind = env::var("VM31_BATCH_SEGREGATE_BY_KIND")
+            .map(|v| v == "true" || v == "1")
+            .unwrap_or(false);
+        let min_deposit_withdraw_ratio: f64 = parse_env_or("VM31_MIN_DEPOSIT_WITHDRAW_RATIO", 0.0)?;
+        if !min_deposit_withdraw_ratio.is_finite() || min_deposit_withdraw_ratio < 0.0 {
+            return Err(ConfigError::Invalid(
+                "VM31_MIN_DEPOSIT_WITHDRAW_RATIO".into(),
+                "must be a finite number >= 0".into(),
+            ));
+        }
+        // Withdrawal-only buckets could never meet the ratio
+        if min_deposit_withdraw_ratio > 0.0 && batch_segregate_by_kind {
+            return Err(ConfigError::Invalid(
+                "VM31_MIN_DEPOSIT_WITHDRAW_RATIO".into(),
+                "cannot be combined with VM31_BATCH_SEGREGATE_BY_KIND".into(),
+            ));
+        }
+        let min_deposit_withdraw_ratio = Some(min_deposit_withdraw_ratio).filter(|r| *r > 0.0);
+
+        let deterministic: bool = env::var("VM31_DETERMINISTIC")
+            .map(|v| v == "true" || v == "1")
+            .unwrap_or(false);
+        if deterministic {
+            require_test_network("VM31_DETERMINISTIC", &network)?;
+        }
+        reject_deterministic_jitter(deterministic, flush_jitter_secs, batch_size_jitter)?;
+        let shuffle_seed: Option<u64> = match env::var("VM31_SHUFFLE_SEED") {
+            Ok(v) if !v.trim().is_empty() => {
+                require_test_network("VM31_SHUFFLE_SEED", &network)?;
+                Some(v.trim().parse().map_err(|_| {
+                    ConfigError::Invalid("VM31_SHUFFLE_SEED".into(), "must be a u64".into())
+                })?)
+            }
+            _ => None,
+        };
+
         // ECIES relayer private key (optional, enables encrypted submissions)
         let relayer_private_key = parse_hex_key_32("VM31_RELAYER_PRIVKEY")?;
+        let relayer_private_key_prev = parse_hex_key_32("VM31_RELAYER_PRIVKEY_PREV")?;
+        if relayer_private_key_prev.is_some() && relayer_private_key.is_none() {
+            return Err(ConfigError::Invalid(
+                "VM31_RELAYER_PRIVKEY_PREV".into(),
+                "requires VM31_RELAYER_PRIVKEY (the new key)".into(),
+            ));
+        }
+        let encrypt_check_enabled: bool = env::var("VM31_ENCRYPT_CHECK")
+            .map(|v| v == "true" || v == "1")
+            .unwrap_or(false);
+        if encrypt_check_enabled && network == "mainnet" {
+            return Err(ConfigError::Invalid(
+                "VM31_ENCRYPT_CHECK".into(),
+                "debug endpoint cannot be enabled on mainnet".into(),
+            ));
+        }
+        let openapi_enabled: bool = env::var("VM31_OPENAPI")
+            .map(|v| v == "true" || v == "1")
+            .unwrap_or(false);
+        let store_transfer_enabled: bool = env::var("VM31_STORE_TRANSFER")
+            .map(|v| v == "true" || v == "1")
+            .unwrap_or(false);
+        let store_import_max_bytes: usize =
+            parse_env_or("VM31_STORE_IMPORT_MAX_BYTES", 512 * 1024 * 1024)?;
+        let submit_stream_enabled: bool = env::var("VM31_SUBMIT_STREAM")
+            .map(|v| v == "true" || v == "1")
+            .unwrap_or(false);
+        let submit_stream_max_bytes: usize =
+            parse_env_or("VM31_SUBMIT_STREAM_MAX_BYTES", 8 * 1024 * 1024)?;
+        let denominations = match env::var("VM31_DENOMINATIONS_PATH") {
+            Ok(path) if !path.is_empty() => {
+                let json = std::fs::read_to_string(&path).map_err(|e| {
+                    ConfigError::Invalid("VM31_DENOMINATIONS_PATH".into(), format!("cannot read {path}: {e}"))
+                })?;
+                Denominations::from_json(&json)
+                    .map_err(|e| ConfigError::Invalid("VM31_DENOMINATIONS_PATH".into(), e))?
+            }
+            _ => Denominations::builtin(),
+        };
+        let denominations = match env::var("VM31_LEGACY_DENOMINATIONS") {
+            Ok(json) if !json.trim().is_empty() => denominations
+                .with_legacy_json(&json)
+                .map_err(|e| ConfigError::Invalid("VM31_LEGACY_DENOMINATIONS".into(), e))?,
+            _ => denominations,
+        };
+        let allowed_asset_ids = parse_allowed_asset_ids()?;
+        let asset_discovery_enabled: bool = env::var("VM31_ASSET_DISCOVERY")
+            .map(|v| v == "true" || v == "1")
+            .unwrap_or(false);
+        let asset_probe_limit: u32 = parse_env_or("VM31_ASSET_PROBE_LIMIT", 16)?;
+        if asset_probe_limit == 0 {
+            return Err(ConfigError::Invalid("VM31_ASSET_PROBE_LIMIT".into(), "must be > 0".into()));
+        }
+        let metrics_enabled: bool = env::var("VM31_METRICS_ENABLED")
+            .map(|v| v == "true" || v == "1")
+            .unwrap_or(false);
+        let metrics_addr_raw =
+            env::var("VM31_METRICS_ADDR").unwrap_or_else(|_| "127.0.0.1:9090".into());
+        let metrics_addr: std::net::SocketAddr = metrics_addr_raw.parse().map_err(|_| {
+            ConfigError::Invalid(
+                "VM31_METRICS_ADDR".into(),
+                format!("could not parse '{metrics_addr_raw}' as host:port"),
+            )
+        })?;
         let legacy_plaintext_allowed: bool = env::var("VM31_ALLOW_PLAINTEXT")
             .map(|v| v == "true" || v == "1")
             .unwrap_or(true); // Default true during migration
 
+        let merkle_depth: usize = parse_env_or("VM31_MERKLE_DEPTH", MAX_MERKLE_DEPTH)?;
+        if merkle_depth == 0 || merkle_depth > MAX_MERKLE_DEPTH {
+            return Err(ConfigError::Invalid(
+                "VM31_MERKLE_DEPTH".into(),
+                format!("must be 1..={MAX_MERKLE_DEPTH}"),
+            ));
+        }
+        let strict_key_validation: bool = env::var("VM31_STRICT_KEY_VALIDATION")
+            .map(|v| v == "true" || v == "1")
+            .unwrap_or(false);
+        let reject_zero_change: bool = env::var("VM31_REJECT_ZERO_CHANGE")
+            .map(|v| v == "true" || v == "1")
+            .unwrap_or(false);
+        let unique_withdrawal_bindings: bool = env::var("VM31_UNIQUE_BINDINGS")
+            .map(|v| v == "true" || v == "1")
+            .unwrap_or(false);
+        let binding_replay_window_secs: u64 = parse_env_or("VM31_BINDING_REPLAY_WINDOW_SECS", 300)?;
+        let decoy_pubkey_range = parse_decoy_pubkey_range()?;
+        let pubkey_denylist = match env::var("VM31_PUBKEY_DENYLIST_PATH") {
+            Ok(path) if !path.is_empty() => {
+                let list = PubkeyDenylist::load(&path)
+                    .map_err(|e| ConfigError::Invalid("VM31_PUBKEY_DENYLIST_PATH".into(), e))?;
+                Some((path, list))
+            }
+            _ => None,
+        };
+
+        let envelope_max_age_secs: u64 = parse_env_or("VM31_ENVELOPE_MAX_AGE_SECS", 3600)?;
+        if envelope_max_age_secs == 0 {
+            return Err(ConfigError::Invalid("VM31_ENVELOPE_MAX_AGE_SECS".into(), "must be > 0".into()));
+        }
+        let nonce_guard_max_entries: usize = parse_env_or("VM31_NONCE_GUARD_MAX_ENTRIES", 100_000)?;
+        if nonce_guard_max_entries == 0 {
+            return Err(ConfigError::Invalid("VM31_NONCE_GUARD_MAX_ENTRIES".into(), "must be > 0".into()));
+        }
+        let nonce_guard_persistent: bool = env::var("VM31_NONCE_GUARD_PERSIST")
+            .map(|v| v == "true" || v == "1")
+            .unwrap_or(false);
+        if nonce_guard_persistent && redis_url.is_none() {
+            return Err(ConfigError::Invalid(
+                "VM31_NONCE_GUARD_PERSIST".into(),
+                "requires REDIS_URL to be set".into(),
+            ));
+        }
+        let ecies_max_skew_secs: u64 = parse_env_or("VM31_ECIES_MAX_SKEW_SECS", 0)?;
+        // An envelope is accepted for up to 2 × skew; the nonce guard must
+        // remember it that long or it could be replayed inside the window
+        if ecies_max_skew_secs.saturating_mul(2) > envelope_max_age_secs {
+            return Err(ConfigError::Invalid(
+                "VM31_ECIES_MAX_SKEW_SECS".into(),
+                format!("must be at most half of VM31_ENVELOPE_MAX_AGE_SECS ({envelope_max_age_secs})"),
+            ));
+        }
+        let ecies_require_timestamp: bool = env::var("VM31_ECIES_REQUIRE_TIMESTAMP")
+            .map(|v| v == "true" || v == "1")
+            .unwrap_or(false);
+        if ecies_require_timestamp && ecies_max_skew_secs == 0 {
+            return Err(ConfigError::Invalid(
+                "VM31_ECIES_REQUIRE_TIMESTAMP".into(),
+                "requires VM31_ECIES_MAX_SKEW_SECS > 0".into(),
+            ));
+        }
+
         // Storage encryption key (optional, enables at-rest encryption)
         let storage_key = parse_hex_key_32("VM31_STORAGE_KEY")?;
 
+        let eviction_pressure_threshold: usize = parse_env_or("VM31_EVICTION_PRESSURE_THRESHOLD", 1000)?;
+
         let trusted_proxies = env::var("VM31_TRUSTED_PROXIES")
             .unwrap_or_default()
             .split(',')
@@ -149,6 +866,60 @@ impl RelayerConfig {
         if tree_sync_interval_secs == 0 {
             return Err(ConfigError::Invalid("VM31_TREE_SYNC_INTERVAL".into(), "must be > 0".into()));
         }
+        let local_root_cache_size: usize = parse_env_or("VM31_LOCAL_ROOT_CACHE", 32)?;
+        let root_history_size: u64 = parse_env_or("VM31_ROOT_HISTORY_SIZE", 100)?;
+        if root_history_size == 0 {
+            return Err(ConfigError::Invalid("VM31_ROOT_HISTORY_SIZE".into(), "must be > 0".into()));
+        }
+        let confirmed_roots_only: bool = env::var("VM31_CONFIRMED_ROOTS_ONLY")
+            .map(|v| v == "true" || v == "1")
+            .unwrap_or(false);
+        if confirmed_roots_only && local_root_cache_size == 0 {
+            return Err(ConfigError::Invalid(
+                "VM31_CONFIRMED_ROOTS_ONLY".into(),
+                "requires VM31_LOCAL_ROOT_CACHE > 0".into(),
+            ));
+        }
+        let nullifier_cache_size: usize = parse_env_or("VM31_NULLIFIER_CACHE_SIZE", 10_000)?;
+        let proof_cache_ttl_secs: u64 = parse_env_or("VM31_PROOF_CACHE_TTL", 0)?;
+        let otlp_endpoint = env::var("VM31_OTLP_ENDPOINT").ok().filter(|s| !s.is_empty());
+        let commitment_mapping: CommitmentMapping = env::var("VM31_COMMITMENT_MAPPING")
+            .unwrap_or_else(|_| "by_note".into())
+            .parse()
+            .map_err(|e| ConfigError::Invalid("VM31_COMMITMENT_MAPPING".into(), e))?;
+        let duplicate_note_policy: DuplicateNotePolicy = env::var("VM31_DUPLICATE_NOTE_POLICY")
+            .unwrap_or_else(|_| "disambiguate".into())
+            .parse()
+            .map_err(|e| ConfigError::Invalid("VM31_DUPLICATE_NOTE_POLICY".into(), e))?;
+        let note_owner_index = env::var("VM31_NOTE_OWNER_INDEX")
+            .map(|v| v == "true" || v == "1")
+            .unwrap_or(false);
+        let backfill_backoff_base_secs: u64 = parse_env_or("VM31_BACKFILL_BACKOFF_BASE_SECS", 15)?;
+        if backfill_backoff_base_secs == 0 {
+            return Err(ConfigError::Invalid("VM31_BACKFILL_BACKOFF_BASE_SECS".into(), "must be > 0".into()));
+        }
+        let backfill_backoff_max_secs: u64 = parse_env_or("VM31_BACKFILL_BACKOFF_MAX_SECS", 3600)?;
+        if backfill_backoff_max_secs < backfill_backoff_base_secs {
+            return Err(ConfigError::Invalid(
+                "VM31_BACKFILL_BACKOFF_MAX_SECS".into(),
+                "must be >= VM31_BACKFILL_BACKOFF_BASE_SECS".into(),
+            ));
+        }
+        let backfill_page_size: usize = parse_env_or("VM31_BACKFILL_PAGE_SIZE", 256)?;
+        if backfill_page_size == 0 {
+            return Err(ConfigError::Invalid("VM31_BACKFILL_PAGE_SIZE".into(), "must be > 0".into()));
+        }
+        let max_proof_staleness_secs: u64 = parse_env_or("VM31_MAX_PROOF_STALENESS_SECS", 300)?;
+        if max_proof_staleness_secs != 0 && max_proof_staleness_secs < tree_sync_interval_secs {
+            return Err(ConfigError::Invalid(
+                "VM31_MAX_PROOF_STALENESS_SECS".into(),
+                "must be >= VM31_TREE_SYNC_INTERVAL (or 0 to disable)".into(),
+            ));
+        }
+        let stuck_note_threshold_secs: u64 = parse_env_or("VM31_STUCK_NOTE_THRESHOLD_SECS", 3600)?;
+        if stuck_note_threshold_secs == 0 {
+            return Err(ConfigError::Invalid("VM31_STUCK_NOTE_THRESHOLD_SECS".into(), "must be > 0".into()));
+        }
 
         Ok(Self {
             host: env::var("VM31_HOST").unwrap_or_else(|_| "0.0.0.0".into()),
@@ -157,45 +928,324 @@ impl RelayerConfig {
                 .parse()
                 .map_err(|_| ConfigError::Invalid("VM31_PORT".into(), "must be a valid port number".into()))?,
             rpc_url,
+            rpc_weights,
+            verify_rpc_urls,
+            rpc_breaker_threshold,
+            rpc_breaker_window_secs,
+            rpc_breaker_cooldown_secs,
             network,
             account,
+            bridge_backend,
+            relayer_account_key,
             verifier_contract,
             pool_contract,
             bridge_contract,
+            bridge_contracts,
             ct_contract,
             batch_max_size,
             batch_timeout_secs,
             chunk_size,
+            prover_drain_on_close,
+            shutdown_grace_secs,
+            prover_exit_on_panic,
+            dead_letter_enabled,
+            status_update_retries,
+            batch_save_retries,
+            submit_max_retries,
+            recovery_log_path,
+            queue_wal_path,
+            latency_stats_enabled,
+            latency_window_secs,
             min_batch_size,
             max_batch_wait_secs,
+            deposit_batch_deadlines,
+            withdraw_batch_deadlines,
+            transfer_batch_deadlines,
+            flush_align_secs,
+            flush_jitter_secs,
+            batch_size_jitter,
+            min_anon_set,
+            anon_set_mode,
+            batch_per_asset,
+            batch_segregate_by_kind,
+            min_deposit_withdraw_ratio,
+            deterministic,
+            shuffle_seed,
             api_keys,
+            admin_keys,
+            quarantine_keys,
+            quarantine_amount_threshold,
+            quarantine_asset_ids,
+            deposit_limits,
             relayer_private_key,
+            relayer_private_key_prev,
             legacy_plaintext_allowed,
+            encrypt_check_enabled,
+            openapi_enabled,
+            store_transfer_enabled,
+            store_import_max_bytes,
+            submit_stream_enabled,
+            submit_stream_max_bytes,
+            asset_discovery_enabled,
+            asset_probe_limit,
+            metrics_enabled,
+            metrics_addr,
+            merkle_depth,
+            strict_key_validation,
+            reject_zero_change,
+            unique_withdrawal_bindings,
+            binding_replay_window_secs,
+            decoy_pubkey_range,
+            pubkey_denylist,
+            denominations,
+            allowed_asset_ids,
+            envelope_max_age_secs,
+            nonce_guard_max_entries,
+            nonce_guard_persistent,
+            ecies_max_skew_secs,
+            ecies_require_timestamp,
             storage_key,
             redis_url,
+            redis_required,
+            redis_degrade_on_error,
+            postgres_url,
+            eviction_pressure_threshold,
             rate_limit_per_min,
+            rate_limit_algo,
+            content_rate_limit_per_min,
+            validate_rate_limit_per_min,
+            merkle_path_rate_limit_per_min,
+            submit_timing_target_ms,
+            batch_cache_max_age_secs,
+            abuse_alert_threshold,
+            abuse_alert_window_secs,
+            max_inflight_per_key,
+            batch_event_streams_per_key,
+            stream_idle_timeout_secs,
             allowed_origins,
+            cors_require_https,
+            cors_allow_localhost,
             trusted_proxies,
             tree_cache_path,
             tree_sync_interval_secs,
+            backfill_backoff_base_secs,
+            backfill_backoff_max_secs,
+            backfill_page_size,
+            stuck_note_threshold_secs,
+            max_proof_staleness_secs,
+            local_root_cache_size,
+            root_history_size,
+            confirmed_roots_only,
+            nullifier_cache_size,
+            proof_cache_ttl_secs,
+            otlp_endpoint,
+            commitment_mapping,
+            duplicate_note_policy,
+            note_owner_index,
         })
     }
 
-    /// Constant-time API key validation to prevent timing side-channel attacks.
-    pub fn is_api_key_valid(&self, key: &str) -> bool {
+}
+
+/// Whether admin keys also work on the regular API (`VM31_ADMIN_KEY_MODE`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdminKeyMode {
+    /// Admin keys only open the admin endpoints (the default).
+    Separate,
+    /// Admin keys also act as API keys with every scope.
+    Superset,
+}
+
+impl std::str::FromStr for AdminKeyMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "separate" => Ok(Self::Separate),
+            "superset" => Ok(Self::Superset),
+            other => Err(format!("unknown admin key mode '{other}' (expected separate or superset)")),
+        }
+    }
+}
+
+/// An operation an API key may be allowed to perform.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Scope {
+    /// Submit, cancel and encrypt-check transactions.
+    Submit,
+    /// Batch, note, client_ref and stats lookups.
+    Read,
+    /// `/prove`, plus the admin endpoints when `VM31_ADMIN_KEYS` is unset.
+    ForceProve,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 3] = [Scope::Submit, Scope::Read, Scope::ForceProve];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Scope::Submit => "submit",
+            Scope::Read => "read",
+            Scope::ForceProve => "force_prove",
+        }
+    }
+}
+
+/// A configured API key with its allowed operations.
+#[derive(Debug, Clone)]
+pub struct ApiKeyConfig {
+    pub key: String,
+    pub scopes: Vec<Scope>,
+    /// Multiplies every per-key rate limit (1.0 = the configured default).
+    pub rate_multiplier: f64,
+}
+
+impl ApiKeyConfig {
+    pub fn allows(&self, scope: Scope) -> bool {
+        self.scopes.contains(&scope)
+    }
+
+    /// `base` scaled by this key's multiplier, never below 1.
+    pub fn rate_limit(&self, base: u32) -> u32 {
+        ((base as f64 * self.rate_multiplier).round() as u32).max(1)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ApiKeys(Vec<ApiKeyConfig>);
+
+impl ApiKeys {
+    /// Parses `VM31_API_KEYS`: either a comma-separated list (every scope at
+    /// the default rate) or a JSON object of key → `{"scopes": [...],
+    /// "rate_multiplier": 2.0}`.
+    pub fn parse(raw: &str) -> Result<Self, ConfigError> {
+        const NAME: &str = "VM31_API_KEYS";
+        let raw = raw.trim();
+        let keys: Vec<ApiKeyConfig> = if raw.starts_with('{') {
+            #[derive(serde::Deserialize)]
+            #[serde(deny_unknown_fields)]
+            struct Entry {
+                scopes: Vec<Scope>,
+                #[serde(default)]
+                rate_multiplier: Option<f64>,
+            }
+            let map: BTreeMap<String, Entry> = serde_json::from_str(raw).map_err(|e| {
+                ConfigError::Invalid(NAME.into(), format!("must be a comma-separated list or a JSON object of key → scopes: {e}"))
+            })?;
+            let mut keys = Vec::with_capacity(map.len());
+            for (key, entry) in map {
+                let rate_multiplier = entry.rate_multiplier.unwrap_or(1.0);
+                if !(rate_multiplier.is_finite() && rate_multiplier > 0.0) {
+                    return Err(ConfigError::Invalid(NAME.into(), "rate_multiplier must be > 0".into()));
+                }
+                if key.trim().is_empty() || entry.scopes.is_empty() {
+                    return Err(ConfigError::Invalid(NAME.into(), "every key needs a name and at least one scope".into()));
+                }
+                keys.push(ApiKeyConfig { key, scopes: entry.scopes, rate_multiplier });
+            }
+            keys
+        } else {
+            raw.split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(|key| ApiKeyConfig {
+                    key: key.to_string(),
+                    scopes: Scope::ALL.to_vec(),
+                    rate_multiplier: 1.0,
+                })
+                .collect()
+        };
+        if keys.is_empty() {
+            return Err(ConfigError::Missing("VM31_API_KEYS (no valid keys found)".into()));
+        }
+        Ok(Self(keys))
+    }
+
+    /// Parses `VM31_ADMIN_KEYS`, a comma-separated list of keys.
+    pub fn parse_admin(raw: &str) -> Result<Self, ConfigError> {
+        let keys: Vec<ApiKeyConfig> = raw
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(|key| ApiKeyConfig {
+                key: key.to_string(),
+                scopes: Scope::ALL.to_vec(),
+                rate_multiplier: 1.0,
+            })
+            .collect();
+        if keys.is_empty() {
+            return Err(ConfigError::Invalid("VM31_ADMIN_KEYS".into(), "no valid keys found".into()));
+        }
+        Ok(Self(keys))
+    }
+
+    /// Adds `other`'s keys that aren't already configured here.
+    pub fn merge(&mut self, other: &ApiKeys) {
+        for key in &other.0 {
+            if self.authenticate(&key.key).is_none() {
+                self.0.push(key.clone());
+            }
+        }
+    }
+
+    /// Constant-time API key lookup to prevent timing side-channel attacks.
+    pub fn authenticate(&self, key: &str) -> Option<&ApiKeyConfig> {
         use subtle::ConstantTimeEq;
         let key_bytes = key.as_bytes();
-        for valid_key in &self.api_keys {
-            let valid_bytes = valid_key.as_bytes();
+        for valid in &self.0 {
+            let valid_bytes = valid.key.as_bytes();
             // Length check first (leaks length but not content — acceptable for API keys)
-            if key_bytes.len() == valid_bytes.len()
-                && key_bytes.ct_eq(valid_bytes).into()
-            {
-                return true;
+            if key_bytes.len() == valid_bytes.len() && key_bytes.ct_eq(valid_bytes).into() {
+                return Some(valid);
             }
         }
-        false
+        None
+    }
+}
+
+/// Refuses a test-only ordering option on mainnet, and in release builds
+/// unless VM31_DANGEROUS_ALLOW_DETERMINISTIC acknowledges it.
+fn require_test_network(name: &str, network: &str) -> Result<(), ConfigError> {
+    let dangerous_ack = env::var("VM31_DANGEROUS_ALLOW_DETERMINISTIC")
+        .map(|v| v == "true" || v == "1")
+        .unwrap_or(false);
+    if network == "mainnet" {
+        return Err(ConfigError::Invalid(
+            name.into(),
+            "test-only mode cannot be enabled on mainnet".into(),
+        ));
+    }
+    if !cfg!(debug_assertions) && !dangerous_ack {
+        return Err(ConfigError::Invalid(
+            name.into(),
+            "test-only mode refused in release builds (set VM31_DANGEROUS_ALLOW_DETERMINISTIC=true to override)".into(),
+        ));
     }
+    Ok(())
+}
+
+/// Refuses flush and size jitter in deterministic mode: both draw from the
+/// thread RNG, so batch boundaries would not be reproducible.
+fn reject_deterministic_jitter(
+    deterministic: bool,
+    flush_jitter_secs: u64,
+    batch_size_jitter: usize,
+) -> Result<(), ConfigError> {
+    if !deterministic {
+        return Ok(());
+    }
+    let name = if flush_jitter_secs > 0 {
+        "VM31_FLUSH_JITTER_SECS"
+    } else if batch_size_jitter > 0 {
+        "VM31_BATCH_SIZE_JITTER"
+    } else {
+        return Ok(());
+    };
+    Err(ConfigError::Invalid(
+        name.into(),
+        "cannot be combined with VM31_DETERMINISTIC".into(),
+    ))
 }
 
 fn require_env(name: &str) -> Result<String, ConfigError> {
@@ -219,6 +1269,26 @@ fn parse_env_or<T: std::str::FromStr>(name: &str, default: T) -> Result<T, Confi
     }
 }
 
+/// Parses `VM31_{kind}_TIMEOUT_SECS` / `VM31_{kind}_MAX_WAIT_SECS`, falling back
+/// to the queue-wide batch timeout and max wait.
+fn parse_type_deadlines(
+    kind: &str,
+    default_timeout: u64,
+    default_max_wait: u64,
+) -> Result<(u64, u64), ConfigError> {
+    let timeout_var = format!("VM31_{kind}_TIMEOUT_SECS");
+    let max_wait_var = format!("VM31_{kind}_MAX_WAIT_SECS");
+    let timeout: u64 = parse_env_or(&timeout_var, default_timeout)?;
+    if timeout == 0 {
+        return Err(ConfigError::Invalid(timeout_var, "must be > 0".into()));
+    }
+    let max_wait: u64 = parse_env_or(&max_wait_var, default_max_wait)?;
+    if max_wait == 0 {
+        return Err(ConfigError::Invalid(max_wait_var, "must be > 0".into()));
+    }
+    Ok((timeout, max_wait))
+}
+
 fn parse_hex_key_32(env_name: &str) -> Result<Option<[u8; 32]>, ConfigError> {
     match env::var(env_name) {
         Ok(v) if !v.is_empty() => {
@@ -240,7 +1310,150 @@ fn parse_hex_key_32(env_name: &str) -> Result<Option<[u8; 32]>, ConfigError> {
     }
 }
 
-fn validate_rpc_url(url: &str) -> Result<(), ConfigError> {
+/// Parses `VM31_BRIDGE_CONTRACTS`, a JSON object mapping asset IDs to bridge
+/// contract addresses, e.g. `{"0":"0x123...","3":"0x456..."}`.
+fn parse_bridge_contracts() -> Result<HashMap<u32, String>, ConfigError> {
+    const NAME: &str = "VM31_BRIDGE_CONTRACTS";
+    let raw = match env::var(NAME) {
+        Ok(v) if !v.trim().is_empty() => v,
+        _ => return Ok(HashMap::new()),
+    };
+    let map: HashMap<String, String> = serde_json::from_str(&raw).map_err(|e| {
+        ConfigError::Invalid(NAME.into(), format!("must be a JSON object of asset_id → address: {e}"))
+    })?;
+    let mut out = HashMap::with_capacity(map.len());
+    for (asset, address) in map {
+        let asset_id: u32 = asset.trim().parse().map_err(|_| {
+            ConfigError::Invalid(NAME.into(), format!("asset id '{asset}' is not a u32"))
+        })?;
+        validate_felt_address(&address, &format!("{NAME}[{asset_id}]"))?;
+        out.insert(asset_id, address);
+    }
+    Ok(out)
+}
+
+/// Deposit limits for one asset. Either bound may be left unset.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DepositLimit {
+    /// Largest amount a single deposit may carry.
+    #[serde(default)]
+    pub max_amount: Option<u64>,
+    /// Most an API key may deposit in one UTC day.
+    #[serde(default)]
+    pub daily_cap: Option<u64>,
+}
+
+/// Parses `VM31_DEPOSIT_LIMITS`, a JSON object mapping asset IDs to limits,
+/// e.g. `{"0":{"max_amount":1000000,"daily_cap":50000000}}`.
+fn parse_deposit_limits() -> Result<HashMap<u32, DepositLimit>, ConfigError> {
+    const NAME: &str = "VM31_DEPOSIT_LIMITS";
+    let raw = match env::var(NAME) {
+        Ok(v) if !v.trim().is_empty() => v,
+        _ => return Ok(HashMap::new()),
+    };
+    let map: HashMap<String, DepositLimit> = serde_json::from_str(&raw).map_err(|e| {
+        ConfigError::Invalid(NAME.into(), format!("must be a JSON object of asset_id → limits: {e}"))
+    })?;
+    let mut out = HashMap::with_capacity(map.len());
+    for (asset, limit) in map {
+        let asset_id: u32 = asset.trim().parse().map_err(|_| {
+            ConfigError::Invalid(NAME.into(), format!("asset id '{asset}' is not a u32"))
+        })?;
+        let field = format!("{NAME}[{asset_id}]");
+        if limit.max_amount == Some(0) || limit.daily_cap == Some(0) {
+            return Err(ConfigError::Invalid(field, "limits must be > 0".into()));
+        }
+        if let (Some(max), Some(cap)) = (limit.max_amount, limit.daily_cap) {
+            if max > cap {
+                return Err(ConfigError::Invalid(field, "max_amount must be <= daily_cap".into()));
+            }
+        }
+        out.insert(asset_id, limit);
+    }
+    Ok(out)
+}
+
+/// Parses `VM31_ALLOWED_ASSET_IDS` as a comma list of canonical M31 values.
+fn parse_allowed_asset_ids() -> Result<Vec<u32>, ConfigError> {
+    const NAME: &str = "VM31_ALLOWED_ASSET_IDS";
+    env::var(NAME)
+        .unwrap_or_default()
+        .split(',')
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .map(|s| match s.parse::<u32>() {
+            Ok(id) if id < M31_MODULUS => Ok(id),
+            Ok(_) => Err(ConfigError::Invalid(
+                NAME.into(),
+                format!("'{s}' must be < M31 modulus ({M31_MODULUS})"),
+            )),
+            Err(_) => Err(ConfigError::Invalid(NAME.into(), format!("could not parse '{s}'"))),
+        })
+        .collect()
+}
+
+/// Parses `VM31_DECOY_PUBKEY_RANGE` as `start-end` (inclusive, decimal M31 values).
+fn parse_decoy_pubkey_range() -> Result<Option<(u32, u32)>, ConfigError> {
+    const NAME: &str = "VM31_DECOY_PUBKEY_RANGE";
+    let raw = match env::var(NAME) {
+        Ok(v) if !v.trim().is_empty() => v,
+        _ => return Ok(None),
+    };
+    let (start, end) = raw
+        .trim()
+        .split_once('-')
+        .and_then(|(a, b)| Some((a.trim().parse::<u32>().ok()?, b.trim().parse::<u32>().ok()?)))
+        .ok_or_else(|| ConfigError::Invalid(NAME.into(), format!("expected 'start-end', got '{raw}'")))?;
+    if start > end {
+        return Err(ConfigError::Invalid(NAME.into(), "start must be <= end".into()));
+    }
+    if end >= M31_MODULUS {
+        return Err(ConfigError::Invalid(
+            NAME.into(),
+            format!("end must be < M31 modulus ({M31_MODULUS})"),
+        ));
+    }
+    Ok(Some((start, end)))
+}
+
+/// Parses `VM31_RPC_WEIGHTS` as a JSON object of RPC URL → weight (> 0).
+fn parse_rpc_weights() -> Result<Vec<(String, u32)>, ConfigError> {
+    const NAME: &str = "VM31_RPC_WEIGHTS";
+    let raw = match env::var(NAME) {
+        Ok(v) if !v.trim().is_empty() => v,
+        _ => return Ok(Vec::new()),
+    };
+    let map: BTreeMap<String, u32> = serde_json::from_str(&raw).map_err(|e| {
+        ConfigError::Invalid(NAME.into(), format!("must be a JSON object of rpc url → weight: {e}"))
+    })?;
+    let mut out = Vec::with_capacity(map.len());
+    for (i, (url, weight)) in map.into_iter().enumerate() {
+        // Entries are named by index: URLs often embed API keys
+        validate_rpc_url(&url, &format!("{NAME}[{i}]"))?;
+        if weight == 0 {
+            return Err(ConfigError::Invalid(format!("{NAME}[{i}]"), "weight must be > 0".into()));
+        }
+        out.push((url, weight));
+    }
+    Ok(out)
+}
+
+/// Parses `VM31_VERIFY_RPC_URLS` as comma-separated RPC URLs, keeping order.
+fn parse_verify_rpc_urls(raw: &str) -> Result<Vec<String>, ConfigError> {
+    const NAME: &str = "VM31_VERIFY_RPC_URLS";
+    let mut out: Vec<String> = Vec::new();
+    for (i, url) in raw.split(',').map(str::trim).filter(|u| !u.is_empty()).enumerate() {
+        // Entries are named by index: URLs often embed API keys
+        validate_rpc_url(url, &format!("{NAME}[{i}]"))?;
+        if !out.iter().any(|u| u == url) {
+            out.push(url.to_string());
+        }
+    }
+    Ok(out)
+}
+
+fn validate_rpc_url(url: &str, env_name: &str) -> Result<(), ConfigError> {
     let lower = url.to_lowercase();
     if lower.starts_with("https://") {
         return Ok(());
@@ -255,16 +1468,58 @@ fn validate_rpc_url(url: &str) -> Result<(), ConfigError> {
             return Ok(());
         }
         return Err(ConfigError::Invalid(
-            "STARKNET_RPC_URL".into(),
+            env_name.into(),
             "must use HTTPS for non-localhost URLs".into(),
         ));
     }
     Err(ConfigError::Invalid(
-        "STARKNET_RPC_URL".into(),
+        env_name.into(),
         "must start with https:// (or http:// for localhost)".into(),
     ))
 }
 
+/// Validates a CORS origin: `scheme://host[:port]` with no path, query, or
+/// trailing slash (browsers send origins in exactly that form, so anything
+/// else would never match and silently block the frontend).
+fn validate_origin(origin: &str, require_https: bool, allow_localhost: bool) -> Result<(), ConfigError> {
+    let invalid = |reason: &str| {
+        ConfigError::Invalid("VM31_ALLOWED_ORIGINS".into(), format!("'{origin}': {reason}"))
+    };
+
+    let (scheme, rest) = origin
+        .split_once("://")
+        .ok_or_else(|| invalid("missing scheme (expected https://host)"))?;
+    if rest.is_empty() {
+        return Err(invalid("missing host"));
+    }
+    if rest.contains(['/', '?', '#', '@']) {
+        return Err(invalid("must not contain a path, query, fragment, or userinfo"));
+    }
+    if !rest
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | ':' | '[' | ']'))
+    {
+        return Err(invalid("host contains invalid characters"));
+    }
+
+    let host = rest.to_lowercase();
+    let is_localhost = host == "localhost"
+        || host.starts_with("localhost:")
+        || host == "127.0.0.1"
+        || host.starts_with("127.0.0.1:")
+        || host.starts_with("[::1]");
+
+    match scheme {
+        "https" => Ok(()),
+        "http" if !require_https => Ok(()),
+        "http" if allow_localhost && is_localhost => Ok(()),
+        "http" => Err(invalid(
+            "must use https:// (set VM31_CORS_ALLOW_LOCALHOST=true for local http origins)",
+        )),
+        _ => Err(invalid("scheme must be https or http")),
+    }
+}
+
 fn validate_hex(value: &str, name: &str) -> Result<(), ConfigError> {
     let s = value.strip_prefix("0x").unwrap_or(value);
     if s.is_empty() || !s.chars().all(|c| c.is_ascii_hexdigit()) {
@@ -276,6 +1531,33 @@ fn validate_hex(value: &str, name: &str) -> Result<(), ConfigError> {
     Ok(())
 }
 
+/// M31 field modulus: 2^31 - 1
+pub(crate) const M31_MODULUS: u32 = 0x7FFF_FFFF;
+
+/// Starknet field prime P = 2^251 + 17·2^192 + 1, as 64 hex digits.
+pub(crate) const FELT_PRIME_HEX: &str = "0800000000000011000000000000000000000000000000000000000000000001";
+
+/// Hex check plus Starknet felt bounds: at most 64 hex digits and < P.
+fn validate_felt_address(value: &str, name: &str) -> Result<(), ConfigError> {
+    validate_hex(value, name)?;
+    let digits = value.strip_prefix("0x").unwrap_or(value);
+    if digits.len() > 64 {
+        return Err(ConfigError::Invalid(
+            name.into(),
+            format!("too long for a Starknet address ({} hex digits, max 64)", digits.len()),
+        ));
+    }
+    // Equal-length lowercase hex compares like the numbers it encodes
+    let padded = format!("{:0>64}", digits.to_ascii_lowercase());
+    if padded.as_str() >= FELT_PRIME_HEX {
+        return Err(ConfigError::Invalid(
+            name.into(),
+            "out of range: must be below the Starknet field prime (2^251 + 17·2^192 + 1)".into(),
+        ));
+    }
+    Ok(())
+}
+
 #[derive(Debug)]
 pub enum ConfigError {
     Missing(String),
@@ -294,3 +1576,114 @@ impl std::fmt::Display for ConfigError {
 }
 
 impl std::error::Error for ConfigError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_api_keys_plain_list_grants_all_scopes() {
+        let keys = ApiKeys::parse(" key1, key2 ,").unwrap();
+        let k = keys.authenticate("key2").unwrap();
+        assert!(Scope::ALL.iter().all(|s| k.allows(*s)));
+        assert_eq!(k.rate_limit(30), 30);
+        assert!(keys.authenticate("key3").is_none());
+    }
+
+    #[test]
+    fn test_api_keys_json_scopes_and_multiplier() {
+        let keys = ApiKeys::parse(
+            r#"{"dash": {"scopes": ["read"]}, "partner": {"scopes": ["submit", "read"], "rate_multiplier": 2.5}}"#,
+        )
+        .unwrap();
+        let dash = keys.authenticate("dash").unwrap();
+        assert!(dash.allows(Scope::Read) && !dash.allows(Scope::Submit));
+        let partner = keys.authenticate("partner").unwrap();
+        assert!(!partner.allows(Scope::ForceProve));
+        assert_eq!(partner.rate_limit(30), 75);
+        assert_eq!(partner.rate_limit(0), 1);
+    }
+
+    #[test]
+    fn test_admin_keys_superset_merge() {
+        let mut api = ApiKeys::parse(r#"{"dash": {"scopes": ["read"]}}"#).unwrap();
+        let admin = ApiKeys::parse_admin("ops, dash").unwrap();
+        assert!(ApiKeys::parse_admin(" , ").is_err());
+        api.merge(&admin);
+        assert!(api.authenticate("ops").unwrap().allows(Scope::Submit));
+        // An existing API key keeps its own scopes
+        assert!(!api.authenticate("dash").unwrap().allows(Scope::Submit));
+        assert_eq!("superset".parse(), Ok(AdminKeyMode::Superset));
+        assert!("both".parse::<AdminKeyMode>().is_err());
+    }
+
+    #[test]
+    fn test_api_keys_rejects_bad_json() {
+        for raw in [
+            "",
+            r#"{"k": {"scopes": []}}"#,
+            r#"{"k": {"scopes": ["admin"]}}"#,
+            r#"{"k": {"scopes": ["read"], "rate_multiplier": 0}}"#,
+            r#"{"k": {"scopes": ["read"], "rate": 2}}"#,
+        ] {
+            assert!(ApiKeys::parse(raw).is_err(), "{raw}");
+        }
+    }
+
+    #[test]
+    fn test_deterministic_refuses_jitter() {
+        assert!(reject_deterministic_jitter(true, 0, 0).is_ok());
+        assert!(reject_deterministic_jitter(false, 5, 3).is_ok());
+        let err = reject_deterministic_jitter(true, 5, 0).unwrap_err();
+        assert!(matches!(&err, ConfigError::Invalid(name, _) if name == "VM31_FLUSH_JITTER_SECS"));
+        let err = reject_deterministic_jitter(true, 0, 3).unwrap_err();
+        assert!(matches!(&err, ConfigError::Invalid(name, _) if name == "VM31_BATCH_SIZE_JITTER"));
+    }
+
+    #[test]
+    fn test_verify_rpc_urls_keep_order_and_require_https() {
+        let urls = parse_verify_rpc_urls(" https://b.example/rpc, https://a.example ,,https://b.example/rpc").unwrap();
+        assert_eq!(urls, vec!["https://b.example/rpc", "https://a.example"]);
+        assert!(parse_verify_rpc_urls("").unwrap().is_empty());
+        assert!(parse_verify_rpc_urls("http://localhost:5050").is_ok());
+
+        let err = parse_verify_rpc_urls("https://a.example,http://b.example/KEY").unwrap_err();
+        // Named by index so the key in the URL stays out of logs
+        assert!(matches!(&err, ConfigError::Invalid(name, _) if name == "VM31_VERIFY_RPC_URLS[1]"));
+        assert!(!err.to_string().contains("KEY"));
+    }
+
+    #[test]
+    fn test_felt_address_accepts_in_range() {
+        assert!(validate_felt_address("0x1", "X").is_ok());
+        assert!(validate_felt_address(
+            "0x0800000000000011000000000000000000000000000000000000000000000000",
+            "X"
+        )
+        .is_ok());
+        assert!(validate_felt_address(
+            "0x049D36570D4E46F48E99674BD3FCC84644DDD6B96F7C741B1562B82F9E004DC7",
+            "X"
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn test_felt_address_rejects_over_length() {
+        let long = format!("0x{}", "0".repeat(64) + "1");
+        let err = validate_felt_address(&long, "VM31_POOL_CONTRACT").unwrap_err();
+        assert!(err.to_string().contains("too long"), "{err}");
+    }
+
+    #[test]
+    fn test_felt_address_rejects_out_of_range() {
+        for v in [
+            "0x0800000000000011000000000000000000000000000000000000000000000001",
+            "0x800000000000011000000000000000000000000000000000000000000000002",
+            "0xffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff",
+        ] {
+            let err = validate_felt_address(v, "STARKNET_ACCOUNT").unwrap_err();
+            assert!(err.to_string().contains("out of range"), "{v}: {err}");
+        }
+    }
+}
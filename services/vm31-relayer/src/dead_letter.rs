@@ -0,0 +1,71 @@
+//! Dead-letter records for batches that failed in `process_batch`.
+//!
+//! A failed batch's transactions are kept in the `SubmitRequest` wire shape
+//! (as in the queue WAL) with the failure reason, so admins can list them at
+//! `GET /dlq` and re-enqueue them with `POST /dlq/{batch_id}/replay`.
+//!
+//! SECURITY: entries contain spending keys, like the queue WAL. With
+//! VM31_STORAGE_KEY set the store seals them, in memory and in Redis.
+
+use serde::{Deserialize, Serialize};
+
+use stwo_ml::privacy::tx_builder::PendingTx;
+
+use crate::routes::{SubmitRequest, WithdrawalRecipient};
+
+/// Maximum dead-lettered batches held before new failures are refused.
+pub const MAX_DEAD_LETTERS: usize = 1024;
+
+/// A failed batch's transactions and why it failed.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeadLetter {
+    pub batch_id: String,
+    pub reason: String,
+    pub failed_at: u64,
+    pub transactions: Vec<SubmitRequest>,
+    /// Scoped client references, index-aligned with `transactions`.
+    #[serde(default)]
+    pub client_refs: Vec<Option<String>>,
+}
+
+/// Listing metadata. Excludes the transactions themselves.
+#[derive(Debug, Clone, Serialize)]
+pub struct DeadLetterSummary {
+    pub batch_id: String,
+    pub reason: String,
+    pub failed_at: u64,
+    pub tx_count: usize,
+}
+
+impl DeadLetter {
+    pub fn new(
+        batch_id: &str,
+        reason: String,
+        failed_at: u64,
+        txs: &[PendingTx],
+        client_refs: Vec<Option<String>>,
+        recipients: &[Option<WithdrawalRecipient>],
+    ) -> Self {
+        let recipient = |i: usize| recipients.get(i).and_then(Option::as_ref);
+        Self {
+            batch_id: batch_id.to_string(),
+            reason,
+            failed_at,
+            transactions: txs
+                .iter()
+                .enumerate()
+                .map(|(i, tx)| SubmitRequest::from(tx).with_withdrawal_recipient(recipient(i)))
+                .collect(),
+            client_refs,
+        }
+    }
+
+    pub fn summary(&self) -> DeadLetterSummary {
+        DeadLetterSummary {
+            batch_id: self.batch_id.clone(),
+            reason: self.reason.clone(),
+            failed_at: self.failed_at,
+            tx_count: self.transactions.len(),
+        }
+    }
+}
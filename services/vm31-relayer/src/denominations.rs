@@ -0,0 +1,236 @@
+//! Deposit denomination whitelists, keyed by asset id.
+//!
+//! All deposits MUST use one of the standard denominations for their asset to
+//! prevent exact-amount correlation attacks (privacy gap #7). The built-in
+//! table below is used unless `VM31_DENOMINATIONS_PATH` points at a JSON file
+//! of the form `{"<asset_id>": [amount, ...], ...}` (amounts in base units),
+//! which replaces it entirely.
+//!
+//! `VM31_LEGACY_DENOMINATIONS` (same JSON shape, inline) lists amounts that
+//! were removed from the table but are still accepted, with a warning, while
+//! clients migrate. Drop the variable once the logs show no more use.
+//!
+//! Built-in asset ID mapping (from VM31Pool.register_asset()):
+//!   0 = wBTC (8 decimals), 1 = SAGE (18 decimals), 2 = ETH (18 decimals),
+//!   3 = STRK (18 decimals), 4 = USDC (6 decimals)
+
+use std::collections::BTreeMap;
+
+/// BTC denominations (8 decimals, base unit = satoshi)
+pub const BTC_DENOMINATIONS: [u64; 6] = [
+    50_000,      // 0.0005 BTC
+    100_000,     // 0.001 BTC
+    500_000,     // 0.005 BTC
+    1_000_000,   // 0.01 BTC
+    5_000_000,   // 0.05 BTC
+    10_000_000,  // 0.1 BTC
+];
+
+/// ETH denominations (18 decimals, base unit = wei)
+const ETH_DENOMINATIONS: [u64; 6] = [
+    1_000_000_000_000_000,      // 0.001 ETH
+    5_000_000_000_000_000,      // 0.005 ETH
+    10_000_000_000_000_000,     // 0.01 ETH
+    50_000_000_000_000_000,     // 0.05 ETH
+    100_000_000_000_000_000,    // 0.1 ETH
+    500_000_000_000_000_000,    // 0.5 ETH
+];
+
+/// STRK denominations (18 decimals)
+const STRK_DENOMINATIONS: [u64; 6] = [
+    1_000_000_000_000_000_000,    // 1 STRK
+    5_000_000_000_000_000_000,    // 5 STRK
+    10_000_000_000_000_000_000,   // 10 STRK (u64 max is ~18.4 STRK)
+    50_000_000_000_000_000,       // 0.05 STRK
+    100_000_000_000_000_000,      // 0.1 STRK
+    500_000_000_000_000_000,      // 0.5 STRK
+];
+
+/// USDC denominations (6 decimals, base unit = micro-USDC)
+const USDC_DENOMINATIONS: [u64; 6] = [
+    1_000_000,     // 1 USDC
+    5_000_000,     // 5 USDC
+    10_000_000,    // 10 USDC
+    50_000_000,    // 50 USDC
+    100_000_000,   // 100 USDC
+    500_000_000,   // 500 USDC
+];
+
+/// SAGE denominations (18 decimals)
+const SAGE_DENOMINATIONS: [u64; 6] = [
+    100_000_000_000_000_000,     // 0.1 SAGE
+    500_000_000_000_000_000,     // 0.5 SAGE
+    1_000_000_000_000_000_000,   // 1 SAGE
+    5_000_000_000_000_000_000,   // 5 SAGE
+    10_000_000_000_000_000,      // 0.01 SAGE
+    50_000_000_000_000_000,      // 0.05 SAGE
+];
+
+/// Backward-compatible alias
+pub const BTC_DENOMINATIONS_SATS: [u64; 6] = BTC_DENOMINATIONS;
+
+/// Per-asset denomination whitelists.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Denominations {
+    current: BTreeMap<u32, Vec<u64>>,
+    /// Deprecated amounts still accepted during a migration window.
+    legacy: BTreeMap<u32, Vec<u64>>,
+}
+
+/// How a deposit amount matched the table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DenominationMatch {
+    /// A current denomination, or an asset without a whitelist.
+    Standard,
+    /// Only in the legacy grace set.
+    Legacy,
+    Rejected,
+}
+
+impl Denominations {
+    /// The compiled-in table.
+    pub fn builtin() -> Self {
+        Self::from_table(BTreeMap::from([
+            (0, BTC_DENOMINATIONS.to_vec()),
+            (1, SAGE_DENOMINATIONS.to_vec()),
+            (2, ETH_DENOMINATIONS.to_vec()),
+            (3, STRK_DENOMINATIONS.to_vec()),
+            (4, USDC_DENOMINATIONS.to_vec()),
+        ]))
+    }
+
+    /// An empty table: every amount is accepted. Used to re-parse txs that
+    /// were validated against the live table when first accepted.
+    pub fn unrestricted() -> Self {
+        Self::from_table(BTreeMap::new())
+    }
+
+    fn from_table(current: BTreeMap<u32, Vec<u64>>) -> Self {
+        Self {
+            current,
+            legacy: BTreeMap::new(),
+        }
+    }
+
+    /// Parses a JSON table. Every asset needs at least one denomination and
+    /// every denomination must be a non-zero integer that fits in `u64`.
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        parse_table(json).map(Self::from_table)
+    }
+
+    /// Adds a legacy grace set (same JSON shape as the table). Every legacy
+    /// asset must also have current denominations, since an asset without a
+    /// whitelist accepts any amount anyway.
+    pub fn with_legacy_json(mut self, json: &str) -> Result<Self, String> {
+        let legacy = parse_table(json)?;
+        if let Some(id) = legacy.keys().find(|id| !self.current.contains_key(id)) {
+            return Err(format!("asset {id} has legacy but no current denominations"));
+        }
+        self.legacy = legacy;
+        Ok(self)
+    }
+
+    /// Whitelist for `asset_id`; `None` means deposits are unrestricted.
+    pub fn for_asset(&self, asset_id: u32) -> Option<&[u64]> {
+        self.current.get(&asset_id).map(Vec::as_slice)
+    }
+
+    /// Classifies a deposit amount for `asset_id`.
+    pub fn check(&self, asset_id: u32, amount: u64) -> DenominationMatch {
+        match self.for_asset(asset_id) {
+            None => DenominationMatch::Standard,
+            Some(denoms) if denoms.contains(&amount) => DenominationMatch::Standard,
+            Some(_) if self.legacy.get(&asset_id).is_some_and(|l| l.contains(&amount)) => {
+                DenominationMatch::Legacy
+            }
+            Some(_) => DenominationMatch::Rejected,
+        }
+    }
+
+    /// Asset ids with a whitelist, ascending.
+    pub fn asset_ids(&self) -> impl Iterator<Item = u32> + '_ {
+        self.current.keys().copied()
+    }
+}
+
+/// Parses `{"<asset_id>": [amount, ...]}`.
+fn parse_table(json: &str) -> Result<BTreeMap<u32, Vec<u64>>, String> {
+    let raw: BTreeMap<String, Vec<serde_json::Number>> =
+        serde_json::from_str(json).map_err(|e| format!("invalid JSON: {e}"))?;
+    let mut table = BTreeMap::new();
+    for (key, amounts) in raw {
+        let asset_id: u32 = key
+            .parse()
+            .map_err(|_| format!("asset id '{key}' is not a u32"))?;
+        if amounts.is_empty() {
+            return Err(format!("asset {asset_id} has no denominations"));
+        }
+        let mut denoms = Vec::with_capacity(amounts.len());
+        for n in amounts {
+            match n.as_u64() {
+                Some(0) => return Err(format!("asset {asset_id}: denomination must be non-zero")),
+                Some(v) => denoms.push(v),
+                None => return Err(format!("asset {asset_id}: denomination {n} does not fit in u64")),
+            }
+        }
+        table.insert(asset_id, denoms);
+    }
+    Ok(table)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_table() {
+        let d = Denominations::builtin();
+        assert_eq!(d.asset_ids().collect::<Vec<_>>(), vec![0, 1, 2, 3, 4]);
+        assert_eq!(d.for_asset(0), Some(&BTC_DENOMINATIONS[..]));
+        assert!(d.for_asset(3).unwrap().contains(&10_000_000_000_000_000_000));
+        assert_eq!(d.for_asset(99), None);
+    }
+
+    #[test]
+    fn test_custom_asset_replaces_builtin() {
+        let d = Denominations::from_json(r#"{"0": [100000], "7": [1000, 5000]}"#).unwrap();
+        assert_eq!(d.for_asset(7), Some(&[1000, 5000][..]));
+        assert_eq!(d.for_asset(0), Some(&[100000][..]));
+        // Assets missing from the file are unrestricted
+        assert_eq!(d.for_asset(4), None);
+    }
+
+    #[test]
+    fn test_rejects_invalid_denominations() {
+        for (json, expected) in [
+            (r#"{"1": [0]}"#, "non-zero"),
+            (r#"{"1": [18446744073709551616]}"#, "does not fit in u64"),
+            (r#"{"1": [-5]}"#, "does not fit in u64"),
+            (r#"{"1": [1.5]}"#, "does not fit in u64"),
+            (r#"{"1": []}"#, "no denominations"),
+            (r#"{"btc": [1]}"#, "not a u32"),
+        ] {
+            let err = Denominations::from_json(json).unwrap_err();
+            assert!(err.contains(expected), "{json}: {err}");
+        }
+    }
+
+    #[test]
+    fn test_legacy_amounts_accepted() {
+        let d = Denominations::builtin()
+            .with_legacy_json(r#"{"0": [20000000]}"#)
+            .unwrap();
+        assert_eq!(d.check(0, 100_000), DenominationMatch::Standard);
+        assert_eq!(d.check(0, 20_000_000), DenominationMatch::Legacy);
+        assert_eq!(d.check(0, 20_000_001), DenominationMatch::Rejected);
+        // Legacy sets are per asset
+        assert_eq!(d.check(4, 20_000_000), DenominationMatch::Rejected);
+        assert_eq!(d.check(99, 20_000_001), DenominationMatch::Standard);
+        assert_eq!(d.for_asset(0), Some(&BTC_DENOMINATIONS[..]));
+
+        let err = Denominations::builtin()
+            .with_legacy_json(r#"{"99": [1]}"#)
+            .unwrap_err();
+        assert!(err.contains("no current denominations"), "{err}");
+    }
+}
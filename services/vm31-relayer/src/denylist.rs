@@ -0,0 +1,141 @@
+//! Recipient pubkey denylist for compliance screening.
+//!
+//! `VM31_PUBKEY_DENYLIST_PATH` points at a text file with one hex-encoded
+//! `[u32; 4]` pubkey per line: 32 hex digits, each limb as 8 big-endian
+//! digits, optionally `0x`-prefixed. Blank lines and `#` comments are
+//! ignored. Deposits and transfers to a listed recipient are rejected.
+//!
+//! The list is re-read on SIGHUP. A file that fails to parse leaves the
+//! previous list in force.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use arc_swap::ArcSwap;
+
+/// One loaded version of the denylist.
+#[derive(Debug, Clone, Default)]
+pub struct PubkeyDenylist {
+    keys: HashSet<[u32; 4]>,
+}
+
+impl PubkeyDenylist {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut keys = HashSet::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let key = parse_pubkey(line).ok_or_else(|| {
+                format!("line {}: expected 32 hex digits, got '{line}'", i + 1)
+            })?;
+            keys.insert(key);
+        }
+        Ok(Self { keys })
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("cannot read {path}: {e}"))?;
+        Self::parse(&text)
+    }
+
+    pub fn contains(&self, pubkey: &[u32; 4]) -> bool {
+        self.keys.contains(pubkey)
+    }
+
+    pub fn entry_count(&self) -> usize {
+        self.keys.len()
+    }
+}
+
+/// Parses a pubkey in the file's hex form (also used by `/notes/by-owner`).
+pub(crate) fn parse_pubkey(s: &str) -> Option<[u32; 4]> {
+    let hex = s.strip_prefix("0x").unwrap_or(s);
+    if hex.len() != 32 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let mut key = [0u32; 4];
+    for (limb, chunk) in key.iter_mut().zip(hex.as_bytes().chunks(8)) {
+        *limb = u32::from_str_radix(std::str::from_utf8(chunk).ok()?, 16).ok()?;
+    }
+    Some(key)
+}
+
+/// The hex form `parse_pubkey` reads: 32 lowercase digits, no prefix.
+pub(crate) fn pubkey_hex(key: &[u32; 4]) -> String {
+    key.iter().map(|limb| format!("{limb:08x}")).collect()
+}
+
+/// The denylist in force, swapped atomically when the file is reloaded.
+pub struct SharedDenylist {
+    path: String,
+    current: ArcSwap<PubkeyDenylist>,
+}
+
+impl SharedDenylist {
+    pub fn new(path: String, initial: PubkeyDenylist) -> Self {
+        Self {
+            path,
+            current: ArcSwap::from_pointee(initial),
+        }
+    }
+
+    pub fn current(&self) -> Arc<PubkeyDenylist> {
+        self.current.load_full()
+    }
+
+    /// Re-reads the file, returning the new entry count. On error the
+    /// previous list stays in force.
+    pub fn reload(&self) -> Result<usize, String> {
+        let list = PubkeyDenylist::load(&self.path)?;
+        let len = list.entry_count();
+        self.current.store(Arc::new(list));
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LISTED: &str = "0000000100000002000000030000000a";
+
+    #[test]
+    fn test_parse_hex_pubkeys_with_comments() {
+        let list = PubkeyDenylist::parse(&format!("# sanctions list\n\n{LISTED}\n0x7ffffffe000000000000000000000001  # ofac\n")).unwrap();
+        assert_eq!(list.entry_count(), 2);
+        assert!(list.contains(&[1, 2, 3, 10]));
+        assert!(list.contains(&[0x7fff_fffe, 0, 0, 1]));
+        assert!(!list.contains(&[1, 2, 3, 4]));
+
+        let err = PubkeyDenylist::parse("00000001000000020000000300000004\nnot-a-key").unwrap_err();
+        assert!(err.starts_with("line 2"), "{err}");
+        assert!(PubkeyDenylist::parse("0000000100000002000000030000000").is_err());
+    }
+
+    #[test]
+    fn test_pubkey_hex_round_trips() {
+        assert_eq!(pubkey_hex(&[1, 2, 3, 10]), LISTED);
+        assert_eq!(parse_pubkey(&pubkey_hex(&[0x7fff_fffe, 0, 0, 1])), Some([0x7fff_fffe, 0, 0, 1]));
+    }
+
+    #[test]
+    fn test_reload_blocks_new_entries_and_keeps_list_on_error() {
+        let path = std::env::temp_dir().join(format!("vm31-denylist-{}.txt", uuid::Uuid::new_v4()));
+        std::fs::write(&path, format!("{LISTED}\n")).unwrap();
+        let path_str = path.to_str().unwrap().to_string();
+        let denylist = SharedDenylist::new(path_str.clone(), PubkeyDenylist::load(&path_str).unwrap());
+        assert!(!denylist.current().contains(&[5, 6, 7, 8]));
+
+        std::fs::write(&path, format!("{LISTED}\n00000005000000060000000700000008\n")).unwrap();
+        assert_eq!(denylist.reload().unwrap(), 2);
+        assert!(denylist.current().contains(&[5, 6, 7, 8]));
+
+        std::fs::write(&path, "garbage\n").unwrap();
+        assert!(denylist.reload().is_err());
+        assert!(denylist.current().contains(&[5, 6, 7, 8]));
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,375 @@
+//! Versioned ECIES schemes for encrypted submissions.
+//!
+//! Every version shares the envelope layout (`EncryptedSubmitRequest`),
+//! x25519 ECDH and HKDF-SHA256; only the AEAD differs. The HKDF info string
+//! is `obelysk-ecies-v{n}`, so a shared secret never yields the same key
+//! under two schemes.
+//!
+//! The plaintext may carry a `timestamp` (unix seconds) beside the
+//! SubmitRequest fields. Being inside the AEAD, it can't be altered without
+//! failing decryption; `TimestampPolicy` bounds how far it may be from the
+//! relayer clock, which stops replays after the nonce guard has expired.
+
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::Aes256Gcm;
+use chacha20poly1305::ChaCha20Poly1305;
+use hkdf::Hkdf;
+use sha2::Sha256;
+use x25519_dalek::{PublicKey as X25519PublicKey, StaticSecret};
+
+use crate::config::RelayerConfig;
+use crate::error::AppError;
+use crate::routes::EncryptedSubmitRequest;
+
+/// Stage at which opening an ECIES envelope failed.
+#[derive(Debug)]
+pub enum EnvelopeError {
+    /// Bad version, hex, lengths or base64.
+    Malformed(String),
+    /// AEAD authentication failed (wrong key or tampered ciphertext).
+    Decryption,
+    /// The sealed timestamp is missing or outside the allowed clock skew.
+    Timestamp(String),
+    Internal(String),
+}
+
+impl EnvelopeError {
+    /// Generic category exposed by `/encrypt-check`.
+    pub fn category(&self) -> &'static str {
+        match self {
+            EnvelopeError::Malformed(_) => "malformed_envelope",
+            EnvelopeError::Decryption => "decryption_failed",
+            EnvelopeError::Timestamp(_) => "invalid_timestamp",
+            EnvelopeError::Internal(_) => "internal",
+        }
+    }
+}
+
+impl From<EnvelopeError> for AppError {
+    fn from(e: EnvelopeError) -> Self {
+        match e {
+            EnvelopeError::Malformed(msg) | EnvelopeError::Timestamp(msg) => AppError::BadRequest(msg),
+            EnvelopeError::Decryption => AppError::BadRequest(
+                "ECIES decryption failed (bad key or tampered ciphertext)".into(),
+            ),
+            EnvelopeError::Internal(msg) => AppError::Internal(msg),
+        }
+    }
+}
+
+/// Freshness rule for the timestamp sealed in an envelope's plaintext.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TimestampPolicy {
+    /// 0 = timestamps are not checked.
+    pub max_skew_secs: u64,
+    /// Reject envelopes that carry no timestamp.
+    pub required: bool,
+}
+
+impl TimestampPolicy {
+    pub fn from_config(config: &RelayerConfig) -> Self {
+        Self {
+            max_skew_secs: config.ecies_max_skew_secs,
+            required: config.ecies_require_timestamp,
+        }
+    }
+
+    /// Accepts `timestamp` if it is within `max_skew_secs` of `now`, either way.
+    pub fn check(&self, timestamp: Option<u64>, now: u64) -> Result<(), EnvelopeError> {
+        if self.max_skew_secs == 0 {
+            return Ok(());
+        }
+        let Some(ts) = timestamp else {
+            return if self.required {
+                Err(EnvelopeError::Timestamp("encrypted payload must include a timestamp".into()))
+            } else {
+                Ok(())
+            };
+        };
+        if ts.abs_diff(now) > self.max_skew_secs {
+            let when = if ts < now { "expired" } else { "future-dated" };
+            return Err(EnvelopeError::Timestamp(format!(
+                "envelope {when}: timestamp is more than {}s from the relayer clock",
+                self.max_skew_secs
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// One envelope version.
+pub trait EciesScheme: Send + Sync {
+    fn version(&self) -> u8;
+    /// Algorithm identifier advertised at `/public-key`.
+    fn algorithm(&self) -> &'static str;
+    /// Returns the plaintext of `envelope`.
+    fn decrypt(
+        &self,
+        secret: &StaticSecret,
+        envelope: &EncryptedSubmitRequest,
+    ) -> Result<Vec<u8>, EnvelopeError>;
+}
+
+/// v1: AES-256-GCM.
+pub struct AesGcmV1;
+
+/// v2: ChaCha20-Poly1305, for clients without AES hardware acceleration.
+pub struct ChaChaPolyV2;
+
+impl EciesScheme for AesGcmV1 {
+    fn version(&self) -> u8 {
+        1
+    }
+
+    fn algorithm(&self) -> &'static str {
+        "x25519-aes256gcm-hkdf-sha256"
+    }
+
+    fn decrypt(
+        &self,
+        secret: &StaticSecret,
+        envelope: &EncryptedSubmitRequest,
+    ) -> Result<Vec<u8>, EnvelopeError> {
+        let parts = EnvelopeParts::parse(secret, envelope, self.version())?;
+        let cipher = Aes256Gcm::new_from_slice(&parts.key)
+            .map_err(|_| EnvelopeError::Internal("AES key init failed".into()))?;
+        cipher
+            .decrypt(aes_gcm::Nonce::from_slice(&parts.nonce), parts.ciphertext.as_ref())
+            .map_err(|_| EnvelopeError::Decryption)
+    }
+}
+
+impl EciesScheme for ChaChaPolyV2 {
+    fn version(&self) -> u8 {
+        2
+    }
+
+    fn algorithm(&self) -> &'static str {
+        "x25519-chacha20poly1305-hkdf-sha256"
+    }
+
+    fn decrypt(
+        &self,
+        secret: &StaticSecret,
+        envelope: &EncryptedSubmitRequest,
+    ) -> Result<Vec<u8>, EnvelopeError> {
+        let parts = EnvelopeParts::parse(secret, envelope, self.version())?;
+        let cipher = ChaCha20Poly1305::new_from_slice(&parts.key)
+            .map_err(|_| EnvelopeError::Internal("ChaCha20 key init failed".into()))?;
+        cipher
+            .decrypt(
+                chacha20poly1305::Nonce::from_slice(&parts.nonce),
+                parts.ciphertext.as_ref(),
+            )
+            .map_err(|_| EnvelopeError::Decryption)
+    }
+}
+
+/// Registered schemes, in version order.
+pub static SCHEMES: &[&dyn EciesScheme] = &[&AesGcmV1, &ChaChaPolyV2];
+
+/// Looks up the scheme for an envelope version.
+pub fn scheme_for(version: u8) -> Result<&'static dyn EciesScheme, EnvelopeError> {
+    SCHEMES
+        .iter()
+        .copied()
+        .find(|s| s.version() == version)
+        .ok_or_else(|| EnvelopeError::Malformed(format!("unsupported ECIES version: {version}")))
+}
+
+/// HKDF info string for a version.
+fn hkdf_info(version: u8) -> String {
+    format!("obelysk-ecies-v{version}")
+}
+
+/// Decoded envelope fields and the derived 32-byte AEAD key.
+struct EnvelopeParts {
+    key: [u8; 32],
+    nonce: [u8; 12],
+    ciphertext: Vec<u8>,
+}
+
+impl EnvelopeParts {
+    fn parse(
+        secret: &StaticSecret,
+        envelope: &EncryptedSubmitRequest,
+        version: u8,
+    ) -> Result<Self, EnvelopeError> {
+        // Parse ephemeral public key
+        let epk_bytes: [u8; 32] = hex::decode(&envelope.ephemeral_pubkey)
+            .map_err(|_| EnvelopeError::Malformed("invalid ephemeral_pubkey hex".into()))?
+            .try_into()
+            .map_err(|_| EnvelopeError::Malformed("ephemeral_pubkey must be 32 bytes".into()))?;
+        let ephemeral_pk = X25519PublicKey::from(epk_bytes);
+
+        // ECDH shared secret → HKDF-SHA256 with a per-version info string
+        let shared_secret = secret.diffie_hellman(&ephemeral_pk);
+        let hk = Hkdf::<Sha256>::new(None, shared_secret.as_bytes());
+        let mut key = [0u8; 32];
+        hk.expand(hkdf_info(version).as_bytes(), &mut key)
+            .map_err(|_| EnvelopeError::Internal("HKDF expand failed".into()))?;
+
+        let nonce: [u8; 12] = hex::decode(&envelope.nonce)
+            .map_err(|_| EnvelopeError::Malformed("invalid nonce hex".into()))?
+            .try_into()
+            .map_err(|_| EnvelopeError::Malformed("nonce must be 12 bytes".into()))?;
+
+        use base64::Engine;
+        let ciphertext = base64::engine::general_purpose::STANDARD
+            .decode(&envelope.ciphertext)
+            .map_err(|_| EnvelopeError::Malformed("invalid ciphertext base64".into()))?;
+
+        Ok(Self {
+            key,
+            nonce,
+            ciphertext,
+        })
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::routes::{RelayerKeys, SubmitRequest};
+    use base64::Engine;
+
+    /// Test client: seals `plaintext` to `relayer_public` under `version`.
+    pub(crate) fn seal(
+        relayer_public: &X25519PublicKey,
+        version: u8,
+        plaintext: &[u8],
+    ) -> EncryptedSubmitRequest {
+        let ephemeral = StaticSecret::random_from_rng(rand::thread_rng());
+        let shared = ephemeral.diffie_hellman(relayer_public);
+        let mut key = [0u8; 32];
+        Hkdf::<Sha256>::new(None, shared.as_bytes())
+            .expand(hkdf_info(version).as_bytes(), &mut key)
+            .unwrap();
+        let nonce = [9u8; 12];
+        let ciphertext = match version {
+            2 => ChaCha20Poly1305::new_from_slice(&key)
+                .unwrap()
+                .encrypt(chacha20poly1305::Nonce::from_slice(&nonce), plaintext)
+                .unwrap(),
+            _ => Aes256Gcm::new_from_slice(&key)
+                .unwrap()
+                .encrypt(aes_gcm::Nonce::from_slice(&nonce), plaintext)
+                .unwrap(),
+        };
+        EncryptedSubmitRequest {
+            ephemeral_pubkey: hex::encode(X25519PublicKey::from(&ephemeral).as_bytes()),
+            ciphertext: base64::engine::general_purpose::STANDARD.encode(ciphertext),
+            nonce: hex::encode(nonce),
+            version,
+        }
+    }
+
+    #[test]
+    fn test_round_trip_all_versions() {
+        let secret = StaticSecret::from([7u8; 32]);
+        let public = X25519PublicKey::from(&secret);
+        for scheme in SCHEMES {
+            let envelope = seal(&public, scheme.version(), b"hello");
+            let opened = scheme_for(envelope.version)
+                .unwrap()
+                .decrypt(&secret, &envelope)
+                .unwrap();
+            assert_eq!(opened, b"hello");
+        }
+    }
+
+    #[test]
+    fn test_versions_derive_distinct_keys() {
+        let secret = StaticSecret::from([7u8; 32]);
+        let public = X25519PublicKey::from(&secret);
+        // A v1 envelope relabelled as v2 must not open (different HKDF info + AEAD)
+        let mut envelope = seal(&public, 1, b"hello");
+        envelope.version = 2;
+        assert!(matches!(
+            scheme_for(2).unwrap().decrypt(&secret, &envelope),
+            Err(EnvelopeError::Decryption)
+        ));
+    }
+
+    #[test]
+    fn test_unknown_version_is_bad_request() {
+        let secret = StaticSecret::from([7u8; 32]);
+        let envelope = seal(&X25519PublicKey::from(&secret), 3, b"{}");
+        assert_eq!(scheme_for(3).unwrap_err().category(), "malformed_envelope");
+        match envelope.decrypt(&RelayerKeys::new([7u8; 32], None), TimestampPolicy::default()) {
+            Err(AppError::BadRequest(msg)) => assert!(msg.contains("unsupported ECIES version")),
+            other => panic!("expected BadRequest, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_rotation_opens_envelopes_for_current_and_previous_key() {
+        let (old, new) = ([7u8; 32], [8u8; 32]);
+        let public = |k: [u8; 32]| X25519PublicKey::from(&StaticSecret::from(k));
+        let deposit = br#"{"type":"deposit","amount":100000,"asset_id":0,"recipient_pubkey":[1,2,3,4],"recipient_viewing_key":[5,6,7,8]}"#;
+        let rotating = RelayerKeys::new(new, Some(old));
+        for scheme in SCHEMES {
+            for key in [new, old] {
+                let envelope = seal(&public(key), scheme.version(), deposit);
+                assert!(matches!(envelope.decrypt(&rotating, TimestampPolicy::default()), Ok(SubmitRequest::Deposit { .. })));
+            }
+        }
+
+        // Once the previous key is dropped, old-key envelopes stop opening
+        let envelope = seal(&public(old), 1, deposit);
+        assert!(matches!(
+            envelope.decrypt(&RelayerKeys::new(new, None), TimestampPolicy::default()),
+            Err(AppError::BadRequest(msg)) if msg.contains("decryption failed")
+        ));
+
+        // A key that is neither fails the same way, not as an internal error
+        let envelope = seal(&public([9u8; 32]), 2, deposit);
+        assert!(matches!(
+            envelope.decrypt(&rotating, TimestampPolicy::default()),
+            Err(AppError::BadRequest(msg)) if msg.contains("decryption failed")
+        ));
+    }
+
+    fn deposit_at(timestamp: Option<u64>) -> Vec<u8> {
+        let mut body = serde_json::json!({
+            "type": "deposit",
+            "amount": 100000,
+            "asset_id": 0,
+            "recipient_pubkey": [1, 2, 3, 4],
+            "recipient_viewing_key": [5, 6, 7, 8],
+        });
+        if let Some(ts) = timestamp {
+            body["timestamp"] = ts.into();
+        }
+        serde_json::to_vec(&body).unwrap()
+    }
+
+    #[test]
+    fn test_sealed_timestamp_must_be_within_skew() {
+        let key = [7u8; 32];
+        let public = X25519PublicKey::from(&StaticSecret::from(key));
+        let keys = RelayerKeys::new(key, None);
+        let policy = TimestampPolicy { max_skew_secs: 300, required: false };
+        let now = crate::store::now_epoch();
+        let open = |ts| seal(&public, 1, &deposit_at(ts)).decrypt(&keys, policy);
+
+        // Fresh, and within the skew either way
+        assert!(matches!(open(Some(now)), Ok(SubmitRequest::Deposit { .. })));
+        assert!(open(Some(now - 200)).is_ok());
+        assert!(open(Some(now + 200)).is_ok());
+        // Expired and future-dated
+        assert!(matches!(open(Some(now - 3600)), Err(AppError::BadRequest(msg)) if msg.contains("expired")));
+        assert!(matches!(open(Some(now + 3600)), Err(AppError::BadRequest(msg)) if msg.contains("future-dated")));
+
+        // Timestamp-less clients keep working unless one is required
+        assert!(open(None).is_ok());
+        let strict = TimestampPolicy { required: true, ..policy };
+        let envelope = seal(&public, 1, &deposit_at(None));
+        assert!(matches!(
+            envelope.decrypt(&keys, strict),
+            Err(AppError::BadRequest(msg)) if msg.contains("must include a timestamp")
+        ));
+        // With no skew configured, even a stale timestamp is ignored
+        assert!(seal(&public, 2, &deposit_at(Some(0))).decrypt(&keys, TimestampPolicy::default()).is_ok());
+    }
+}
@@ -1,4 +1,4 @@
-use axum::http::StatusCode;
+use axum::http::{header, StatusCode};
 use axum::response::{IntoResponse, Response};
 use serde_json::json;
 use tracing::error;
@@ -7,9 +7,19 @@ use tracing::error;
 pub enum AppError {
     BadRequest(String),
     NotFound(String),
+    Conflict(String),
     Unauthorized,
-    RateLimited,
-    BatchFull,
+    /// Authenticated, but the key lacks the scope for this operation.
+    Forbidden,
+    /// Carries the seconds until the limit resets, sent as `Retry-After`.
+    RateLimited(Option<u64>),
+    /// Carries an estimate of when the queue drains, sent as `Retry-After`.
+    BatchFull(Option<u64>),
+    /// The relayer is shutting down and takes no new submissions.
+    ShuttingDown,
+    /// Pool RPC calls are failing fast while the circuit breaker is open.
+    /// Carries the seconds until it lets a probe through.
+    RpcUnavailable(Option<u64>),
     ProverError(String),
     RelayerError(String),
     BridgeError(String),
@@ -17,13 +27,17 @@ pub enum AppError {
 }
 
 impl AppError {
-    fn status_code(&self) -> StatusCode {
+    pub fn status_code(&self) -> StatusCode {
         match self {
             AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
             AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Conflict(_) => StatusCode::CONFLICT,
             AppError::Unauthorized => StatusCode::UNAUTHORIZED,
-            AppError::RateLimited => StatusCode::TOO_MANY_REQUESTS,
-            AppError::BatchFull => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::Forbidden => StatusCode::FORBIDDEN,
+            AppError::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+            AppError::BatchFull(_) => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::ShuttingDown => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::RpcUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
             AppError::ProverError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             AppError::RelayerError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             AppError::BridgeError(_) => StatusCode::INTERNAL_SERVER_ERROR,
@@ -31,13 +45,17 @@ impl AppError {
         }
     }
 
-    fn error_code(&self) -> &'static str {
+    pub fn error_code(&self) -> &'static str {
         match self {
             AppError::BadRequest(_) => "BAD_REQUEST",
             AppError::NotFound(_) => "NOT_FOUND",
+            AppError::Conflict(_) => "CONFLICT",
             AppError::Unauthorized => "UNAUTHORIZED",
-            AppError::RateLimited => "RATE_LIMITED",
-            AppError::BatchFull => "BATCH_FULL",
+            AppError::Forbidden => "FORBIDDEN",
+            AppError::RateLimited(_) => "RATE_LIMITED",
+            AppError::BatchFull(_) => "BATCH_FULL",
+            AppError::ShuttingDown => "SHUTTING_DOWN",
+            AppError::RpcUnavailable(_) => "RPC_UNAVAILABLE",
             AppError::ProverError(_) => "PROVER_ERROR",
             AppError::RelayerError(_) => "RELAYER_ERROR",
             AppError::BridgeError(_) => "BRIDGE_ERROR",
@@ -45,15 +63,39 @@ impl AppError {
         }
     }
 
+    /// Seconds a client should wait before retrying, when known.
+    fn retry_after(&self) -> Option<u64> {
+        match self {
+            AppError::RateLimited(secs) | AppError::BatchFull(secs) | AppError::RpcUnavailable(secs) => *secs,
+            _ => None,
+        }
+    }
+
+    /// The sanitized JSON error body sent to clients.
+    pub fn body(&self) -> serde_json::Value {
+        let mut body = json!({
+            "error": self.public_message(),
+            "code": self.error_code(),
+        });
+        if let Some(secs) = self.retry_after() {
+            body["retry_after_secs"] = secs.into();
+        }
+        body
+    }
+
     /// Returns the sanitized message shown to clients.
     /// Internal details are logged server-side only.
     fn public_message(&self) -> &'static str {
         match self {
             AppError::BadRequest(_) => "invalid request",
             AppError::NotFound(_) => "not found",
+            AppError::Conflict(_) => "conflicting operation in progress",
             AppError::Unauthorized => "unauthorized",
-            AppError::RateLimited => "rate limited",
-            AppError::BatchFull => "service at capacity, try again later",
+            AppError::Forbidden => "operation not permitted for this API key",
+            AppError::RateLimited(_) => "rate limited",
+            AppError::BatchFull(_) => "service at capacity, try again later",
+            AppError::ShuttingDown => "service shutting down, try again later",
+            AppError::RpcUnavailable(_) => "chain RPC unavailable, try again later",
             AppError::ProverError(_) => "processing failed",
             AppError::RelayerError(_) => "submission failed",
             AppError::BridgeError(_) => "bridge operation failed",
@@ -67,9 +109,13 @@ impl std::fmt::Display for AppError {
         match self {
             AppError::BadRequest(msg) => write!(f, "bad request: {msg}"),
             AppError::NotFound(msg) => write!(f, "not found: {msg}"),
+            AppError::Conflict(msg) => write!(f, "conflict: {msg}"),
             AppError::Unauthorized => write!(f, "unauthorized"),
-            AppError::RateLimited => write!(f, "rate limited"),
-            AppError::BatchFull => write!(f, "batch queue is full"),
+            AppError::Forbidden => write!(f, "forbidden"),
+            AppError::RateLimited(_) => write!(f, "rate limited"),
+            AppError::BatchFull(_) => write!(f, "batch queue is full"),
+            AppError::ShuttingDown => write!(f, "relayer is shutting down"),
+            AppError::RpcUnavailable(_) => write!(f, "pool RPC circuit open"),
             AppError::ProverError(msg) => write!(f, "prover error: {msg}"),
             AppError::RelayerError(msg) => write!(f, "relayer error: {msg}"),
             AppError::BridgeError(msg) => write!(f, "bridge error: {msg}"),
@@ -78,11 +124,11 @@ impl std::fmt::Display for AppError {
     }
 }
 
-impl IntoResponse for AppError {
-    fn into_response(self) -> Response {
-        // Log the full error server-side for debugging.
-        // SECURITY: Never expose internal details to clients.
-        match &self {
+impl AppError {
+    /// Logs the full error server-side for debugging.
+    /// SECURITY: Never expose internal details to clients.
+    pub fn log(&self) {
+        match self {
             AppError::ProverError(_)
             | AppError::RelayerError(_)
             | AppError::BridgeError(_)
@@ -91,12 +137,17 @@ impl IntoResponse for AppError {
             }
             _ => {}
         }
+    }
+}
 
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        self.log();
         let status = self.status_code();
-        let body = json!({
-            "error": self.public_message(),
-            "code": self.error_code(),
-        });
-        (status, axum::Json(body)).into_response()
+        let body = self.body();
+        match self.retry_after() {
+            Some(secs) => (status, [(header::RETRY_AFTER, secs.to_string())], axum::Json(body)).into_response(),
+            None => (status, axum::Json(body)).into_response(),
+        }
     }
 }
@@ -0,0 +1,179 @@
+//! Rolling latency percentiles for the prover pipeline.
+//!
+//! Each stage keeps a fixed-resolution, log-bucketed histogram (HDR-style,
+//! ~10% relative precision) so memory stays constant regardless of batch
+//! volume. Histograms are cleared when the configured window elapses.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+
+/// Number of buckets per histogram. With 10% growth this spans 1ms to well
+/// beyond any realistic stage duration.
+const BUCKETS: usize = 256;
+const GROWTH: f64 = 1.1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Validation,
+    Proving,
+    Submission,
+    Total,
+}
+
+impl Stage {
+    const ALL: [Stage; 4] = [Stage::Validation, Stage::Proving, Stage::Submission, Stage::Total];
+
+    fn name(self) -> &'static str {
+        match self {
+            Stage::Validation => "validation",
+            Stage::Proving => "proving",
+            Stage::Submission => "submission",
+            Stage::Total => "total",
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Histogram {
+    counts: [u64; BUCKETS],
+    total: u64,
+    max_ms: u64,
+}
+
+impl Histogram {
+    fn new() -> Self {
+        Self {
+            counts: [0; BUCKETS],
+            total: 0,
+            max_ms: 0,
+        }
+    }
+
+    fn bucket_for(ms: u64) -> usize {
+        if ms <= 1 {
+            return 0;
+        }
+        ((ms as f64).ln() / GROWTH.ln()).ceil().min((BUCKETS - 1) as f64) as usize
+    }
+
+    fn upper_bound(bucket: usize) -> u64 {
+        GROWTH.powi(bucket as i32).ceil() as u64
+    }
+
+    fn record(&mut self, ms: u64) {
+        self.counts[Self::bucket_for(ms)] += 1;
+        self.total += 1;
+        self.max_ms = self.max_ms.max(ms);
+    }
+
+    /// Upper bound of the bucket holding the `q` quantile, clamped to the
+    /// largest observed value.
+    fn percentile(&self, q: f64) -> Option<u64> {
+        if self.total == 0 {
+            return None;
+        }
+        let rank = ((q * self.total as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (i, &c) in self.counts.iter().enumerate() {
+            seen += c;
+            if seen >= rank {
+                return Some(Self::upper_bound(i).min(self.max_ms));
+            }
+        }
+        Some(self.max_ms)
+    }
+
+    fn snapshot(&self) -> Value {
+        json!({
+            "count": self.total,
+            "p50_ms": self.percentile(0.50),
+            "p95_ms": self.percentile(0.95),
+            "p99_ms": self.percentile(0.99),
+            "max_ms": (self.total > 0).then_some(self.max_ms),
+        })
+    }
+}
+
+struct Window {
+    started: Instant,
+    stages: [Histogram; Stage::ALL.len()],
+}
+
+/// Per-stage latency summaries shared between the prover and `/stats`.
+pub struct LatencyStats {
+    window: Duration,
+    inner: Mutex<Window>,
+}
+
+impl LatencyStats {
+    /// `window_secs == 0` keeps accumulating forever.
+    pub fn new(window_secs: u64) -> Self {
+        Self {
+            window: Duration::from_secs(window_secs),
+            inner: Mutex::new(Window {
+                started: Instant::now(),
+                stages: std::array::from_fn(|_| Histogram::new()),
+            }),
+        }
+    }
+
+    fn roll(&self, w: &mut Window) {
+        if !self.window.is_zero() && w.started.elapsed() >= self.window {
+            w.started = Instant::now();
+            w.stages = std::array::from_fn(|_| Histogram::new());
+        }
+    }
+
+    pub fn record(&self, stage: Stage, elapsed: Duration) {
+        let mut w = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        self.roll(&mut w);
+        w.stages[stage as usize].record(elapsed.as_millis() as u64);
+    }
+
+    pub fn snapshot(&self) -> Value {
+        let mut w = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        self.roll(&mut w);
+        let mut stages = serde_json::Map::new();
+        for stage in Stage::ALL {
+            stages.insert(stage.name().into(), w.stages[stage as usize].snapshot());
+        }
+        json!({
+            "window_secs": self.window.as_secs(),
+            "window_age_secs": w.started.elapsed().as_secs(),
+            "stages": stages,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentiles_within_bucket_precision() {
+        let mut h = Histogram::new();
+        for ms in 1..=1000u64 {
+            h.record(ms);
+        }
+        let p50 = h.percentile(0.50).unwrap() as f64;
+        let p99 = h.percentile(0.99).unwrap() as f64;
+        assert!((p50 - 500.0).abs() / 500.0 <= 0.1, "p50 = {p50}");
+        assert!((p99 - 990.0).abs() / 990.0 <= 0.1, "p99 = {p99}");
+        assert_eq!(h.percentile(1.0), Some(1000));
+    }
+
+    #[test]
+    fn test_empty_window_reports_no_percentiles() {
+        let stats = LatencyStats::new(60);
+        let snap = stats.snapshot();
+        assert_eq!(snap["stages"]["proving"]["count"], 0);
+        assert!(snap["stages"]["proving"]["p50_ms"].is_null());
+
+        stats.record(Stage::Proving, Duration::from_millis(1200));
+        let snap = stats.snapshot();
+        assert_eq!(snap["stages"]["proving"]["count"], 1);
+        assert_eq!(snap["stages"]["proving"]["p99_ms"], 1200);
+    }
+}
@@ -1,10 +1,32 @@
+mod abuse;
+mod amount;
+mod assets;
 mod batch_queue;
 mod bridge;
+#[cfg(feature = "native-bridge")]
+mod bridge_native;
+mod circuit_breaker;
+mod commitment;
 mod config;
+mod dead_letter;
+mod denominations;
+mod denylist;
+mod ecies;
 mod error;
+mod latency;
+mod metrics;
+mod nullifier_cache;
+mod openapi;
+mod proof_cache;
 mod prover;
+mod quarantine;
+mod queue_wal;
+mod recovery;
+mod rpc_pool;
 mod routes;
+mod snapshot;
 mod store;
+mod telemetry;
 mod tree_sync_service;
 
 use std::net::SocketAddr;
@@ -14,6 +36,7 @@ use std::time::Duration;
 use axum::http::{header, HeaderValue};
 use axum::Router;
 use tower_http::cors::{AllowOrigin, CorsLayer};
+use tower_http::decompression::RequestDecompressionLayer;
 use tower_http::limit::RequestBodyLimitLayer;
 use tower_http::set_header::SetResponseHeaderLayer;
 use tower_http::trace::TraceLayer;
@@ -22,23 +45,31 @@ use tracing::{error, info, warn};
 use stwo_ml::privacy::pool_client::PoolClientConfig;
 use stwo_ml::privacy::relayer::SncastVm31Backend;
 
-use crate::batch_queue::BatchQueue;
-use crate::bridge::BridgeService;
+use crate::abuse::AbuseTracker;
+use crate::batch_queue::{BatchQueue, ShuffleRng, TxKind};
+use crate::bridge::{BridgeBackendKind, BridgeService};
 use crate::config::RelayerConfig;
-use crate::prover::ProverService;
-use crate::routes::AppState;
-use crate::tree_sync_service::TreeSyncService;
+use crate::denylist::SharedDenylist;
+use crate::latency::LatencyStats;
+use crate::metrics::Metrics;
+use crate::nullifier_cache::SpentNullifiers;
+use crate::proof_cache::ProofCache;
+use crate::queue_wal::QueueWal;
+use crate::prover::{ProverPause, ProverService, Shutdown};
+use crate::quarantine::Quarantine;
+use crate::recovery::RecoveryLog;
+use crate::circuit_breaker::CircuitBreaker;
+use crate::rpc_pool::RpcPool;
+use crate::routes::{AppState, StreamConnections};
+use crate::tree_sync_service::{KnownRoots, TreeSyncService};
+
+/// Request body limit, applied after any gzip decompression.
+const MAX_BODY_BYTES: usize = 100 * 1024;
+/// Limit on the body as sent, compressed or not.
+const MAX_WIRE_BODY_BYTES: usize = 256 * 1024;
 
 #[tokio::main]
 async fn main() {
-    // Initialize tracing (env-filter: RUST_LOG=vm31_relayer=debug,info)
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "vm31_relayer=info,tower_http=info".into()),
-        )
-        .init();
-
     // Load and validate config
     let config = match RelayerConfig::from_env() {
         Ok(c) => c,
@@ -48,6 +79,9 @@ async fn main() {
         }
     };
 
+    // Initialize tracing, exporting spans when VM31_OTLP_ENDPOINT is set
+    let _telemetry = telemetry::init(config.otlp_endpoint.as_deref());
+
     // CORS: require explicit origins in release builds
     if config.allowed_origins.is_empty() {
         if cfg!(debug_assertions) {
@@ -69,6 +103,7 @@ async fn main() {
         min_batch_size = config.min_batch_size,
         max_batch_wait_secs = config.max_batch_wait_secs,
         redis = config.redis_url.is_some(),
+        postgres = config.postgres_url.is_some(),
         ecies = config.relayer_private_key.is_some(),
         encrypted_storage = config.storage_key.is_some(),
         plaintext_allowed = config.legacy_plaintext_allowed,
@@ -77,8 +112,15 @@ async fn main() {
     );
 
     // Build store with optional Redis write-through + at-rest encryption
-    let store = store::build_store(&config);
-    store.spawn_eviction_task();
+    let store = match store::build_store(&config).await {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("[vm31-relayer] FATAL: {e}");
+            std::process::exit(1);
+        }
+    };
+    info!(backend = store.backend_name(), "store initialized");
+    store.spawn_eviction_task(config.eviction_pressure_threshold);
 
     // Hydrate in-memory maps from Redis on startup (crash recovery)
     #[cfg(feature = "redis")]
@@ -97,25 +139,115 @@ async fn main() {
         }
     }
 
+    // Same for the Postgres write-through
+    #[cfg(feature = "postgres")]
+    if config.postgres_url.is_some() {
+        match store.load_from_postgres().await {
+            Ok((batches, notes)) => {
+                info!(batches, notes, "restored state from Postgres");
+            }
+            Err(e) => {
+                warn!(error = %e, "failed to load from Postgres, starting with empty state");
+            }
+        }
+    }
+
     if config.storage_key.is_some() {
-        info!("note storage encryption enabled (VM31_STORAGE_KEY configured)");
+        info!("batch/note storage encryption enabled (VM31_STORAGE_KEY configured)");
     }
     if config.relayer_private_key.is_some() {
         info!("ECIES submission encryption enabled (VM31_RELAYER_PRIVKEY configured)");
     }
+    if config.relayer_private_key_prev.is_some() {
+        info!("ECIES key rotation in progress: VM31_RELAYER_PRIVKEY_PREV still accepted");
+    }
     if !config.legacy_plaintext_allowed {
         info!("plaintext submissions DISABLED (mainnet mode)");
     }
+    if config.nonce_guard_persistent {
+        if cfg!(feature = "redis") {
+            info!(
+                max_age_secs = config.envelope_max_age_secs,
+                max_entries = config.nonce_guard_max_entries,
+                "ECIES nonce guard persisted to Redis"
+            );
+        } else {
+            warn!("VM31_NONCE_GUARD_PERSIST set but built without the redis feature — nonce guard is in-memory only");
+        }
+    }
 
     // Build batch queue with privacy-enhancing min batch size
-    let (queue, rx) = BatchQueue::with_min_batch(
+    let (mut queue, rx) = BatchQueue::with_min_batch(
         config.batch_max_size,
         config.batch_timeout_secs,
         32,
         config.min_batch_size,
         config.max_batch_wait_secs,
     );
+    for (kind, (timeout, max_wait)) in [
+        (TxKind::Deposit, config.deposit_batch_deadlines),
+        (TxKind::Withdraw, config.withdraw_batch_deadlines),
+        (TxKind::Transfer, config.transfer_batch_deadlines),
+    ] {
+        queue.set_kind_deadlines(kind, timeout, max_wait);
+        if (timeout, max_wait) != (config.batch_timeout_secs, config.max_batch_wait_secs) {
+            info!(?kind, timeout_secs = timeout, max_wait_secs = max_wait, "per-type batch deadlines");
+        }
+    }
+    if let Some(align) = config.flush_align_secs {
+        info!(align_secs = align, "batch timeout flushes aligned to wall-clock boundaries");
+        queue.set_flush_alignment(Some(align));
+    }
+    if config.flush_jitter_secs > 0 || config.batch_size_jitter > 0 {
+        info!(
+            flush_jitter_secs = config.flush_jitter_secs,
+            batch_size_jitter = config.batch_size_jitter,
+            "batch flush timing and size jittered"
+        );
+        queue.set_flush_jitter(config.flush_jitter_secs, config.batch_size_jitter);
+    }
+    if config.batch_per_asset {
+        info!("batch queue partitioned per asset id");
+        queue.set_per_asset(true);
+    }
+    if config.batch_segregate_by_kind {
+        info!("batch queue partitioned by tx kind (deposit / withdraw / transfer)");
+        queue.set_per_kind(true);
+    }
+    if let Some(ratio) = config.min_deposit_withdraw_ratio {
+        info!(ratio, "batch flushes held until deposits:withdrawals >= ratio (max wait still applies)");
+        queue.set_min_deposit_ratio(Some(ratio));
+    }
+    if config.deterministic {
+        warn!("VM31_DETERMINISTIC enabled — batch shuffling DISABLED (test mode, no ordering privacy)");
+        queue.set_deterministic(true);
+    }
+    if let Some(seed) = config.shuffle_seed {
+        warn!(seed, "VM31_SHUFFLE_SEED set — batch shuffle is reproducible (test mode, no ordering privacy)");
+        queue.set_shuffle_rng(ShuffleRng::seeded(seed));
+    }
+    // Backstop to the config checks: a mainnet shuffle must draw from a CSPRNG
+    if config.network == "mainnet" && !queue.shuffle_rng().is_secure() {
+        error!("refusing to start: mainnet batch shuffle is not using a CSPRNG");
+        std::process::exit(1);
+    }
+    info!(rng = %queue.shuffle_rng().describe(), "batch shuffle entropy source");
+    let metrics = config.metrics_enabled.then(|| Arc::new(Metrics::new()));
+    if let Some(m) = &metrics {
+        queue.set_metrics(m.clone());
+    }
+    if let Some(path) = &config.queue_wal_path {
+        queue.set_wal(QueueWal::new(path).with_encryption(config.storage_key.as_ref()));
+        match queue.restore_from_wal().await {
+            Ok(restored) => info!(path = %path, restored, "batch queue WAL enabled"),
+            Err(e) => {
+                error!(path = %path, error = %e, "failed to replay batch queue WAL");
+                std::process::exit(1);
+            }
+        }
+    }
     queue.spawn_timeout_loop();
+    let queue = Arc::new(queue);
     info!(
         min_batch_size = config.min_batch_size,
         max_batch_wait_secs = config.max_batch_wait_secs,
@@ -135,31 +267,116 @@ async fn main() {
         rpc_url: config.rpc_url.clone(),
         pool_address: config.pool_contract.clone(),
         network: config.network.clone(),
-        verify_rpc_urls: vec![],
+        verify_rpc_urls: config.verify_rpc_urls.clone(),
     };
 
     // Build BridgeService
-    let bridge = BridgeService::new(
+    let mut bridge = BridgeService::new(
         config.account.clone(),
         config.rpc_url.clone(),
         config.bridge_contract.clone(),
-    );
+    )
+    .with_asset_contracts(config.bridge_contracts.clone());
+    if config.bridge_backend == BridgeBackendKind::Native {
+        // Presence checked in RelayerConfig::from_env
+        let key = config.relayer_account_key.as_ref().expect("VM31_RELAYER_ACCOUNT_KEY");
+        bridge = attach_native_bridge(bridge, &config, key).await;
+    }
+    for (asset_id, contract) in &config.bridge_contracts {
+        info!(asset_id, contract = %contract, "per-asset bridge contract configured");
+    }
 
     let tree_pool_config = PoolClientConfig {
         rpc_url: config.rpc_url.clone(),
         pool_address: config.pool_contract.clone(),
         network: config.network.clone(),
-        verify_rpc_urls: vec![],
+        verify_rpc_urls: config.verify_rpc_urls.clone(),
     };
 
+    if !config.verify_rpc_urls.is_empty() {
+        info!(backups = config.verify_rpc_urls.len(), "RPC failover and root cross-verification enabled");
+    }
+
+    // Weighted endpoint selection for validation and tree-sync reads
+    let rpc_pool = (!config.rpc_weights.is_empty()).then(|| {
+        let pool = Arc::new(RpcPool::new(&config.rpc_url, &config.rpc_weights));
+        info!(endpoints = pool.status().len(), "weighted RPC endpoint selection enabled");
+        pool
+    });
+
+    // Shared by validation and tree sync so either can shed load for both
+    let rpc_breaker = (config.rpc_breaker_threshold > 0).then(|| {
+        Arc::new(CircuitBreaker::new(
+            config.rpc_breaker_threshold,
+            Duration::from_secs(config.rpc_breaker_window_secs),
+            Duration::from_secs(config.rpc_breaker_cooldown_secs),
+        ))
+    });
+
+    let latency = config
+        .latency_stats_enabled
+        .then(|| Arc::new(LatencyStats::new(config.latency_window_secs)));
+
     // Build ProverService and spawn batch processor (keep handle for graceful shutdown)
-    let prover = ProverService::new(
+    let mut prover = ProverService::new(
         backend,
         prover_pool_config,
         store.clone(),
         config.chunk_size,
         bridge,
+    )
+    .with_drain_on_close(config.prover_drain_on_close)
+    .with_exit_on_panic(config.prover_exit_on_panic)
+    .with_dead_letter(config.dead_letter_enabled)
+    .with_batch_save_retries(config.batch_save_retries)
+    .with_submit_retries(config.submit_max_retries)
+    .with_commitment_mapping(config.commitment_mapping)
+    .with_duplicate_note_policy(config.duplicate_note_policy)
+    .with_owner_index(config.note_owner_index)
+    .with_min_anon_set(config.min_anon_set, config.anon_set_mode, queue.clone())
+    .with_status_recovery(
+        config.status_update_retries,
+        RecoveryLog::new(&config.recovery_log_path),
     );
+    info!(
+        retries = config.status_update_retries,
+        recovery_log = %config.recovery_log_path,
+        "batch status recovery configured"
+    );
+    let prover_pause = Arc::new(ProverPause::new());
+    prover = prover.with_pause(prover_pause.clone());
+    let shutdown = Arc::new(Shutdown::new());
+    prover = prover.with_shutdown(shutdown.clone());
+
+    // Roots verified by tree sync, consulted by the prover before RPC
+    let known_roots =
+        Arc::new(KnownRoots::new(config.local_root_cache_size).with_root_history(config.root_history_size));
+    if config.local_root_cache_size > 0 {
+        prover = prover
+            .with_known_roots(known_roots.clone())
+            .with_confirmed_roots_only(config.confirmed_roots_only);
+    }
+    if config.nullifier_cache_size > 0 {
+        prover = prover.with_spent_nullifiers(Arc::new(SpentNullifiers::new(config.nullifier_cache_size)));
+    }
+    if config.proof_cache_ttl_secs > 0 {
+        prover = prover.with_proof_cache(Arc::new(ProofCache::new(config.proof_cache_ttl_secs)));
+        info!(ttl_secs = config.proof_cache_ttl_secs, "proof cache enabled for replayed batches");
+    }
+    if let Some(stats) = &latency {
+        prover = prover.with_latency_stats(stats.clone());
+        info!(window_secs = config.latency_window_secs, "latency stats enabled at /stats");
+    }
+    if let Some(m) = &metrics {
+        prover = prover.with_metrics(m.clone());
+    }
+    if let Some(pool) = &rpc_pool {
+        prover = prover.with_rpc_pool(pool.clone());
+    }
+    if let Some(breaker) = &rpc_breaker {
+        prover = prover.with_circuit_breaker(breaker.clone());
+    }
+    let input_checks = prover.input_checks();
     let prover_handle = tokio::spawn(async move {
         prover.run(rx).await;
     });
@@ -170,8 +387,20 @@ async fn main() {
         store.clone(),
         config.tree_cache_path.clone(),
         config.tree_sync_interval_secs,
+        (config.backfill_backoff_base_secs, config.backfill_backoff_max_secs),
     ) {
         Ok(ts) => {
+            let mut ts = ts
+                .with_known_roots(known_roots)
+                .with_max_proof_staleness(config.max_proof_staleness_secs)
+                .with_confirmed_roots_only(config.confirmed_roots_only)
+                .with_backfill_page_size(config.backfill_page_size);
+            if let Some(pool) = &rpc_pool {
+                ts = ts.with_rpc_pool(pool.clone());
+            }
+            if let Some(breaker) = &rpc_breaker {
+                ts = ts.with_circuit_breaker(breaker.clone());
+            }
             let ts = Arc::new(ts);
             let ts_clone = Arc::clone(&ts);
             tokio::spawn(async move { ts_clone.run().await });
@@ -187,11 +416,21 @@ async fn main() {
     let cors = if config.allowed_origins.is_empty() {
         CorsLayer::permissive()
     } else {
-        let origins: Vec<_> = config
-            .allowed_origins
-            .iter()
-            .filter_map(|o| o.parse().ok())
-            .collect();
+        // Origins were validated in RelayerConfig::from_env; a parse failure here
+        // is still fatal rather than silently dropping the origin.
+        let mut origins: Vec<HeaderValue> = Vec::with_capacity(config.allowed_origins.len());
+        for origin in &config.allowed_origins {
+            match origin.parse() {
+                Ok(v) => {
+                    info!(origin = %origin, "CORS origin allowed");
+                    origins.push(v);
+                }
+                Err(_) => {
+                    eprintln!("[vm31-relayer] FATAL: invalid CORS origin '{origin}'");
+                    std::process::exit(1);
+                }
+            }
+        }
         CorsLayer::new()
             .allow_origin(AllowOrigin::list(origins))
             .allow_methods([axum::http::Method::GET, axum::http::Method::POST])
@@ -199,26 +438,146 @@ async fn main() {
                 header::CONTENT_TYPE,
                 header::AUTHORIZATION,
                 "x-api-key".parse().unwrap(),
+                "idempotency-key".parse().unwrap(),
             ])
     };
 
+    // Cross-check the denomination table against the pool's asset registry
+    let assets = if config.asset_discovery_enabled {
+        match assets::fetch_pool_assets(&config.rpc_url, &config.pool_contract, config.asset_probe_limit).await {
+            Ok(list) => {
+                let local_ids: Vec<u32> = config.denominations.asset_ids().collect();
+                for mismatch in assets::check_mapping(&list, &local_ids) {
+                    warn!(%mismatch, "asset registry mismatch");
+                }
+                info!(count = list.len(), "pool asset registry loaded");
+                Some(list)
+            }
+            Err(e) => {
+                warn!(error = %e, "failed to fetch pool asset registry; GET /assets unavailable");
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    let abuse = config.abuse_alert_threshold.map(|threshold| {
+        info!(
+            threshold,
+            window_secs = config.abuse_alert_window_secs,
+            "rate-limit abuse alerts enabled (target vm31_relayer::abuse_alert)"
+        );
+        AbuseTracker::new(threshold, config.abuse_alert_window_secs)
+    });
+
+    let pubkey_denylist = config.pubkey_denylist.clone().map(|(path, list)| {
+        info!(entries = list.entry_count(), "recipient pubkey denylist loaded (SIGHUP reloads)");
+        let denylist = Arc::new(SharedDenylist::new(path, list));
+        spawn_denylist_reload(denylist.clone());
+        denylist
+    });
+
     // Build router with state for ConnectInfo extraction
     let state = Arc::new(AppState {
         queue,
         store,
         config: config.clone(),
         tree_sync,
+        quarantine: Quarantine::new(),
+        latency,
+        prover_pause,
+        shutdown,
+        abuse,
+        metrics,
+        assets,
+        rpc_pool,
+        rpc_breaker,
+        input_checks,
+        pubkey_denylist,
+        stream_connections: Arc::new(StreamConnections::default()),
     });
 
-    let app = Router::new()
+    // Metrics get their own listener so they stay off the public interface
+    if config.metrics_enabled {
+        let metrics_app = Router::new()
+            .route("/metrics", axum::routing::get(routes::metrics))
+            .with_state(state.clone());
+        let metrics_listener = tokio::net::TcpListener::bind(config.metrics_addr)
+            .await
+            .expect("failed to bind metrics address");
+        info!(addr = %config.metrics_addr, "prometheus metrics at /metrics");
+        tokio::spawn(async move {
+            if let Err(e) = axum::serve(metrics_listener, metrics_app).await {
+                error!(error = %e, "metrics server failed");
+            }
+        });
+    }
+
+    let mut router = Router::new()
         .route("/health", axum::routing::get(routes::health))
+        .route("/health/live", axum::routing::get(routes::health))
+        .route("/health/ready", axum::routing::get(routes::ready))
+        .route("/ready", axum::routing::get(routes::ready))
         .route("/status", axum::routing::get(routes::status))
+        .route("/stats", axum::routing::get(routes::stats))
         .route("/public-key", axum::routing::get(routes::public_key))
+        .route("/assets", axum::routing::get(routes::assets))
         .route("/submit", axum::routing::post(routes::submit))
+        .route("/validate", axum::routing::post(routes::validate))
+        .route("/submit/{idempotency_key}", axum::routing::delete(routes::cancel_submission))
         .route("/batch/{id}", axum::routing::get(routes::get_batch))
+        .route("/batch/{id}/events", axum::routing::get(routes::batch_events))
+        .route("/batch/{id}/backfill", axum::routing::post(routes::batch_backfill))
+        .route("/batches", axum::routing::get(routes::list_batches))
+        .route("/client-ref/{client_ref}", axum::routing::get(routes::get_client_ref))
         .route("/prove", axum::routing::post(routes::force_prove))
         .route("/merkle-path/{commitment}", axum::routing::get(routes::get_merkle_path))
-        .layer(RequestBodyLimitLayer::new(100 * 1024)) // 100KB
+        .route("/merkle-paths", axum::routing::post(routes::get_merkle_paths))
+        .route("/tree/resync", axum::routing::post(routes::tree_resync))
+        .route("/tree/backfill", axum::routing::post(routes::tree_backfill))
+        .route("/quarantine", axum::routing::get(routes::list_quarantine))
+        .route("/quarantine/{id}/approve", axum::routing::post(routes::approve_quarantined))
+        .route("/quarantine/{id}/reject", axum::routing::post(routes::reject_quarantined))
+        .route("/dlq", axum::routing::get(routes::list_dead_letters))
+        .route("/dlq/{batch_id}/replay", axum::routing::post(routes::replay_dead_letter))
+        .route("/admin/prover/pause", axum::routing::post(routes::pause_prover))
+        .route("/admin/prover/resume", axum::routing::post(routes::resume_prover));
+    if config.admin_keys.is_none() {
+        warn!("VM31_ADMIN_KEYS not set — admin endpoints (quarantine, DLQ, prover pause, tree and store maintenance) answer 403");
+    }
+    if config.encrypt_check_enabled {
+        warn!("VM31_ENCRYPT_CHECK enabled — POST /encrypt-check is a debug endpoint, disable in production");
+        router = router.route("/encrypt-check", axum::routing::post(routes::encrypt_check));
+    }
+    if config.openapi_enabled {
+        router = router.route("/openapi.json", axum::routing::get(routes::openapi));
+    }
+    if config.note_owner_index {
+        warn!("VM31_NOTE_OWNER_INDEX enabled — deposit notes are linked to their owner pubkey at rest");
+        router = router.route("/notes/by-owner/{pubkey}", axum::routing::get(routes::notes_by_owner));
+    }
+    router = with_body_limits(router);
+    if config.submit_stream_enabled {
+        info!(max_bytes = config.submit_stream_max_bytes, "POST /submit/stream enabled");
+        // Lines are read incrementally, so the stream gets its own ceiling
+        router = router.merge(
+            Router::new()
+                .route("/submit/stream", axum::routing::post(routes::submit_stream))
+                .layer(RequestBodyLimitLayer::new(config.submit_stream_max_bytes)),
+        );
+    }
+    if config.store_transfer_enabled {
+        warn!("VM31_STORE_TRANSFER enabled — /admin/store/export and /admin/store/import are served");
+        // Added after the body limits so snapshots get their own ceiling
+        router = router.merge(
+            Router::new()
+                .route("/admin/store/export", axum::routing::get(routes::export_store))
+                .route("/admin/store/import", axum::routing::post(routes::import_store))
+                .layer(RequestBodyLimitLayer::new(config.store_import_max_bytes)),
+        );
+    }
+    let app = router
         .layer(cors)
         .layer(TraceLayer::new_for_http())
         // Security headers (matching audit-relay pattern)
@@ -264,15 +623,53 @@ async fn main() {
     // After HTTP server stops accepting new requests, wait for prover
     // to finish processing any in-flight batches before exiting.
     info!("HTTP server stopped, waiting for prover to finish in-flight batches");
-    match tokio::time::timeout(Duration::from_secs(300), prover_handle).await {
-        Ok(Ok(())) => info!("prover shut down cleanly"),
-        Ok(Err(e)) => error!(error = %e, "prover task panicked during shutdown"),
-        Err(_) => warn!("prover shutdown timed out after 300s, forcing exit"),
-    }
+    await_prover(prover_handle, Duration::from_secs(config.shutdown_grace_secs)).await;
 
     info!("vm31-relayer shut down");
 }
 
+/// Waits up to `grace` for the prover task to return after the queue was
+/// drained. Returns `false` if it was still busy, leaving the batch it was
+/// proving or submitting in `Proving`/`Submitting`.
+async fn await_prover(prover_handle: tokio::task::JoinHandle<()>, grace: Duration) -> bool {
+    match tokio::time::timeout(grace, prover_handle).await {
+        Ok(Ok(())) => {
+            info!("prover shut down cleanly");
+            true
+        }
+        Ok(Err(e)) => {
+            error!(error = %e, "prover task panicked during shutdown");
+            true
+        }
+        Err(_) => {
+            warn!(grace_secs = grace.as_secs(), "prover shutdown timed out (VM31_SHUTDOWN_GRACE_SECS), forcing exit");
+            false
+        }
+    }
+}
+
+/// Re-reads the pubkey denylist on every SIGHUP.
+fn spawn_denylist_reload(denylist: Arc<SharedDenylist>) {
+    #[cfg(unix)]
+    tokio::spawn(async move {
+        let mut hangup = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()) {
+            Ok(s) => s,
+            Err(e) => {
+                warn!(error = %e, "failed to listen for SIGHUP, pubkey denylist will not reload");
+                return;
+            }
+        };
+        while hangup.recv().await.is_some() {
+            match denylist.reload() {
+                Ok(entries) => info!(entries, "received SIGHUP, pubkey denylist reloaded"),
+                Err(e) => warn!(error = %e, "pubkey denylist reload failed, keeping the previous list"),
+            }
+        }
+    });
+    #[cfg(not(unix))]
+    let _ = denylist;
+}
+
 async fn shutdown_signal(state: Arc<AppState>) {
     let ctrl_c = async {
         tokio::signal::ctrl_c()
@@ -295,17 +692,168 @@ async fn shutdown_signal(state: Arc<AppState>) {
         _ = ctrl_c => info!("received SIGINT, shutting down"),
         _ = terminate => info!("received SIGTERM, shutting down"),
     }
+    drain_for_shutdown(&state).await;
+}
 
-    // Drain the batch queue before shutdown.
-    // This sends remaining txs to the prover channel, which the prover
-    // will process before its channel closes and it exits.
+/// Refuses new submissions, flushes the batch queue into the prover channel,
+/// then tells the prover nothing more is coming. It proves what it holds
+/// (per `VM31_PROVER_DRAIN_ON_CLOSE`) and returns; `main` waits for that up
+/// to `VM31_SHUTDOWN_GRACE_SECS`.
+async fn drain_for_shutdown(state: &AppState) {
+    state.shutdown.begin();
     let pending = state.queue.pending_count().await;
     if pending > 0 {
         info!(pending, "draining batch queue before shutdown");
-        if let Some(batch_id) = state.queue.force_flush().await {
+        for batch_id in state.queue.force_flush().await.batch_ids() {
             info!(batch_id = %batch_id, pending, "flushed pending transactions");
-        } else {
-            warn!(pending, "force_flush rejected (below min_batch_size), transactions will be lost on shutdown");
         }
+        let left = state.queue.pending_count().await;
+        if left > 0 {
+            warn!(pending = left, "force_flush rejected (below min_batch_size), transactions will be lost on shutdown");
+        }
+    }
+    state.shutdown.queue_drained();
+}
+
+/// Switches bridge invokes to the native JSON-RPC backend, exiting if the
+/// key can't be verified against the account.
+#[cfg(feature = "native-bridge")]
+async fn attach_native_bridge(bridge: BridgeService, config: &RelayerConfig, key: &[u8; 32]) -> BridgeService {
+    match bridge_native::NativeBridge::connect(&config.rpc_url, &config.network, &config.account, key).await {
+        Ok(native) => {
+            info!("bridge invokes submitted natively over JSON-RPC");
+            bridge.with_native(native)
+        }
+        Err(e) => {
+            error!(error = %e, "failed to initialize native bridge backend");
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(not(feature = "native-bridge"))]
+async fn attach_native_bridge(_: BridgeService, _: &RelayerConfig, _: &[u8; 32]) -> BridgeService {
+    unreachable!("VM31_BRIDGE_BACKEND=native is rejected by RelayerConfig::from_env without the native-bridge feature")
+}
+
+/// Accepts `Content-Encoding: gzip` bodies. The body as sent is capped at
+/// `MAX_WIRE_BODY_BYTES` and the decompressed stream at `MAX_BODY_BYTES`,
+/// counted while inflating so a zip bomb is cut off at the limit (413).
+/// Other encodings are rejected with 415.
+fn with_body_limits<S: Clone + Send + Sync + 'static>(router: Router<S>) -> Router<S> {
+    router
+        .layer(RequestBodyLimitLayer::new(MAX_BODY_BYTES))
+        .layer(RequestDecompressionLayer::new())
+        .layer(RequestBodyLimitLayer::new(MAX_WIRE_BODY_BYTES))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use axum::Json;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+    use tower::ServiceExt;
+
+    fn app() -> Router {
+        with_body_limits(
+            Router::new()
+                .route("/json", axum::routing::post(|Json(v): Json<serde_json::Value>| async move { Json(v) }))
+                .route(
+                    "/envelope",
+                    axum::routing::post(|Json(e): Json<routes::EncryptedSubmitRequest>| async move { e.ciphertext }),
+                ),
+        )
+    }
+
+    fn gzip(body: &[u8]) -> Vec<u8> {
+        let mut enc = GzEncoder::new(Vec::new(), Compression::best());
+        enc.write_all(body).unwrap();
+        enc.finish().unwrap()
+    }
+
+    async fn post(path: &str, encoding: Option<&str>, body: Vec<u8>) -> StatusCode {
+        let mut req = Request::post(path)
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(header::CONTENT_LENGTH, body.len());
+        if let Some(encoding) = encoding {
+            req = req.header(header::CONTENT_ENCODING, encoding);
+        }
+        app().oneshot(req.body(Body::from(body)).unwrap()).await.unwrap().status()
+    }
+
+    /// A JSON object whose string field pads it to about `len` bytes.
+    fn padded_json(len: usize) -> Vec<u8> {
+        serde_json::to_vec(&serde_json::json!({ "pad": "a".repeat(len) })).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_gzip_body_accepted() {
+        let body = padded_json(90 * 1024);
+        assert_eq!(post("/json", Some("gzip"), gzip(&body)).await, StatusCode::OK);
+        assert_eq!(post("/json", None, body).await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_limit_applies_to_decompressed_size() {
+        // Compresses to a few hundred bytes but inflates past 100KB
+        let bomb = gzip(&padded_json(MAX_BODY_BYTES + 1));
+        assert!(bomb.len() < 4096);
+        assert_eq!(post("/json", Some("gzip"), bomb).await, StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(
+            post("/json", None, padded_json(MAX_BODY_BYTES + 1)).await,
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+    }
+
+    #[tokio::test]
+    async fn test_wire_size_cap_and_unsupported_encoding() {
+        let oversized = vec![b' '; MAX_WIRE_BODY_BYTES + 1];
+        assert_eq!(post("/json", Some("gzip"), oversized).await, StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(
+            post("/json", Some("br"), padded_json(16)).await,
+            StatusCode::UNSUPPORTED_MEDIA_TYPE
+        );
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_waits_for_slow_prover_within_grace() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        // A prover still proving its batch when the signal arrives
+        let slow_prover = |finalized: Arc<AtomicBool>| {
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(200)).await;
+                finalized.store(true, Ordering::SeqCst);
+            })
+        };
+
+        let finalized = Arc::new(AtomicBool::new(false));
+        let handle = slow_prover(finalized.clone());
+        assert!(await_prover(handle, Duration::from_secs(10)).await);
+        assert!(finalized.load(Ordering::SeqCst));
+
+        // Past the grace period the relayer exits without it
+        let finalized = Arc::new(AtomicBool::new(false));
+        let handle = slow_prover(finalized.clone());
+        assert!(!await_prover(handle, Duration::from_millis(10)).await);
+        assert!(!finalized.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_gzip_ecies_envelope() {
+        use base64::Engine;
+        let ciphertext = base64::engine::general_purpose::STANDARD.encode([7u8; 4096]);
+        let envelope = serde_json::to_vec(&routes::EncryptedSubmitRequest {
+            ephemeral_pubkey: "11".repeat(32),
+            ciphertext,
+            nonce: "22".repeat(12),
+            version: 1,
+        })
+        .unwrap();
+        assert_eq!(post("/envelope", Some("gzip"), gzip(&envelope)).await, StatusCode::OK);
     }
 }
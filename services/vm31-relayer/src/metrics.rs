@@ -0,0 +1,214 @@
+//! Prometheus metrics for the batch pipeline.
+//!
+//! Counters and histograms are plain atomics updated by the queue, prover and
+//! `/submit`; `render` encodes them in the Prometheus text exposition format.
+//! Only served when `VM31_METRICS_ENABLED` is set, on its own bind address.
+
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Why a batch left the queue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlushTrigger {
+    Size = 0,
+    Timeout = 1,
+    Force = 2,
+    MaxWait = 3,
+}
+
+impl FlushTrigger {
+    const ALL: [FlushTrigger; 4] = [
+        FlushTrigger::Size,
+        FlushTrigger::Timeout,
+        FlushTrigger::Force,
+        FlushTrigger::MaxWait,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            FlushTrigger::Size => "size",
+            FlushTrigger::Timeout => "timeout",
+            FlushTrigger::Force => "force",
+            FlushTrigger::MaxWait => "max_wait",
+        }
+    }
+}
+
+/// Proving duration bucket upper bounds, in seconds.
+const PROVING_BUCKETS: [f64; 10] = [1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0, 600.0, 1800.0];
+
+/// Submission outcome labels: `accepted` plus one per `AppError::error_code`.
+const OUTCOMES: [&str; 12] = [
+    "accepted",
+    "BAD_REQUEST",
+    "NOT_FOUND",
+    "CONFLICT",
+    "UNAUTHORIZED",
+    "RATE_LIMITED",
+    "BATCH_FULL",
+    "SHUTTING_DOWN",
+    "PROVER_ERROR",
+    "RELAYER_ERROR",
+    "BRIDGE_ERROR",
+    "INTERNAL_ERROR",
+];
+
+/// Prover stages that run in `spawn_blocking` and can panic.
+const PANIC_STAGES: [&str; 3] = ["validation", "proving", "submission"];
+
+#[derive(Default)]
+struct Histogram {
+    /// Non-cumulative counts per bucket; the last slot is `+Inf`.
+    buckets: [AtomicU64; PROVING_BUCKETS.len() + 1],
+    count: AtomicU64,
+    sum_micros: AtomicU64,
+}
+
+impl Histogram {
+    fn observe(&self, elapsed: Duration) {
+        let secs = elapsed.as_secs_f64();
+        let idx = PROVING_BUCKETS
+            .iter()
+            .position(|&b| secs <= b)
+            .unwrap_or(PROVING_BUCKETS.len());
+        self.buckets[idx].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_micros
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+    }
+}
+
+#[derive(Default)]
+pub struct Metrics {
+    batches_flushed: [AtomicU64; FlushTrigger::ALL.len()],
+    proving: Histogram,
+    submissions: [AtomicU64; OUTCOMES.len()],
+    prover_panics: [AtomicU64; PANIC_STAGES.len()],
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record_flush(&self, trigger: FlushTrigger) {
+        self.batches_flushed[trigger as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_proving(&self, elapsed: Duration) {
+        self.proving.observe(elapsed);
+    }
+
+    /// `outcome` is `"accepted"` or an `AppError` code; unknown values are ignored.
+    pub fn record_submission(&self, outcome: &str) {
+        if let Some(i) = OUTCOMES.iter().position(|&o| o == outcome) {
+            self.submissions[i].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// `stage` is one of the prover's blocking stages; unknown values are ignored.
+    pub fn record_panic(&self, stage: &str) {
+        if let Some(i) = PANIC_STAGES.iter().position(|&s| s == stage) {
+            self.prover_panics[i].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Prometheus text exposition format (version 0.0.4).
+    pub fn render(&self, queue_depth: usize) -> String {
+        let mut out = String::new();
+
+        out.push_str("# HELP vm31_queue_depth Transactions waiting in the batch queue.\n");
+        out.push_str("# TYPE vm31_queue_depth gauge\n");
+        let _ = writeln!(out, "vm31_queue_depth {queue_depth}");
+
+        out.push_str("# HELP vm31_batches_flushed_total Batches flushed from the queue, by trigger.\n");
+        out.push_str("# TYPE vm31_batches_flushed_total counter\n");
+        for trigger in FlushTrigger::ALL {
+            let _ = writeln!(
+                out,
+                "vm31_batches_flushed_total{{trigger=\"{}\"}} {}",
+                trigger.label(),
+                self.batches_flushed[trigger as usize].load(Ordering::Relaxed)
+            );
+        }
+
+        out.push_str("# HELP vm31_proving_duration_seconds Batch proof generation time.\n");
+        out.push_str("# TYPE vm31_proving_duration_seconds histogram\n");
+        let mut cumulative = 0;
+        for (i, bound) in PROVING_BUCKETS.iter().enumerate() {
+            cumulative += self.proving.buckets[i].load(Ordering::Relaxed);
+            let _ = writeln!(
+                out,
+                "vm31_proving_duration_seconds_bucket{{le=\"{bound}\"}} {cumulative}"
+            );
+        }
+        cumulative += self.proving.buckets[PROVING_BUCKETS.len()].load(Ordering::Relaxed);
+        let _ = writeln!(out, "vm31_proving_duration_seconds_bucket{{le=\"+Inf\"}} {cumulative}");
+        let sum = self.proving.sum_micros.load(Ordering::Relaxed) as f64 / 1e6;
+        let _ = writeln!(out, "vm31_proving_duration_seconds_sum {sum}");
+        let _ = writeln!(
+            out,
+            "vm31_proving_duration_seconds_count {}",
+            self.proving.count.load(Ordering::Relaxed)
+        );
+
+        out.push_str("# HELP vm31_submissions_total POST /submit outcomes, by result.\n");
+        out.push_str("# TYPE vm31_submissions_total counter\n");
+        for (i, outcome) in OUTCOMES.iter().enumerate() {
+            let _ = writeln!(
+                out,
+                "vm31_submissions_total{{outcome=\"{outcome}\"}} {}",
+                self.submissions[i].load(Ordering::Relaxed)
+            );
+        }
+
+        out.push_str("# HELP vm31_prover_panics_total Panics in blocking prover stages, by stage.\n");
+        out.push_str("# TYPE vm31_prover_panics_total counter\n");
+        for (i, stage) in PANIC_STAGES.iter().enumerate() {
+            let _ = writeln!(
+                out,
+                "vm31_prover_panics_total{{stage=\"{stage}\"}} {}",
+                self.prover_panics[i].load(Ordering::Relaxed)
+            );
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_encoding() {
+        let m = Metrics::new();
+        m.record_flush(FlushTrigger::Timeout);
+        m.record_proving(Duration::from_millis(3_000));
+        m.record_proving(Duration::from_secs(4_000));
+        m.record_submission("accepted");
+        m.record_submission("RATE_LIMITED");
+        m.record_submission("not-an-outcome");
+        m.record_panic("proving");
+
+        let text = m.render(7);
+        assert!(text.contains("# TYPE vm31_queue_depth gauge\nvm31_queue_depth 7\n"));
+        assert!(text.contains("vm31_batches_flushed_total{trigger=\"timeout\"} 1\n"));
+        assert!(text.contains("vm31_batches_flushed_total{trigger=\"size\"} 0\n"));
+        // Buckets are cumulative; the 4000s sample only lands in +Inf
+        assert!(text.contains("vm31_proving_duration_seconds_bucket{le=\"2.5\"} 0\n"));
+        assert!(text.contains("vm31_proving_duration_seconds_bucket{le=\"5\"} 1\n"));
+        assert!(text.contains("vm31_proving_duration_seconds_bucket{le=\"1800\"} 1\n"));
+        assert!(text.contains("vm31_proving_duration_seconds_bucket{le=\"+Inf\"} 2\n"));
+        assert!(text.contains("vm31_proving_duration_seconds_sum 4003\n"));
+        assert!(text.contains("vm31_proving_duration_seconds_count 2\n"));
+        assert!(text.contains("vm31_submissions_total{outcome=\"accepted\"} 1\n"));
+        assert!(text.contains("vm31_submissions_total{outcome=\"RATE_LIMITED\"} 1\n"));
+        assert!(text.contains("vm31_prover_panics_total{stage=\"proving\"} 1\n"));
+        assert!(text.contains("vm31_prover_panics_total{stage=\"validation\"} 0\n"));
+        // Every sample line is `name{labels} value`
+        for line in text.lines().filter(|l| !l.starts_with('#')) {
+            assert_eq!(line.split(' ').count(), 2, "bad sample line: {line}");
+        }
+    }
+}
@@ -0,0 +1,106 @@
+//! Bounded LRU of nullifiers confirmed spent on-chain.
+//!
+//! Validation consults it before the `is_nullifier_spent` RPC, so a resubmitted
+//! spend is rejected without a round trip. Only spent results are cached —
+//! spent is final, while an unspent nullifier can be spent by the next batch.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+struct Lru {
+    /// Nullifier → recency tick.
+    entries: HashMap<[u32; 8], u64>,
+    /// Recency tick → nullifier; the first entry is evicted first.
+    by_tick: BTreeMap<u64, [u32; 8]>,
+    tick: u64,
+}
+
+impl Lru {
+    fn touch(&mut self, nullifier: [u32; 8]) {
+        self.tick += 1;
+        if let Some(old) = self.entries.insert(nullifier, self.tick) {
+            self.by_tick.remove(&old);
+        }
+        self.by_tick.insert(self.tick, nullifier);
+    }
+}
+
+pub struct SpentNullifiers {
+    capacity: usize,
+    lru: Mutex<Lru>,
+}
+
+impl SpentNullifiers {
+    /// A cache of at most `capacity` nullifiers (0 disables it).
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            lru: Mutex::new(Lru {
+                entries: HashMap::new(),
+                by_tick: BTreeMap::new(),
+                tick: 0,
+            }),
+        }
+    }
+
+    /// Records `nullifier` as spent, evicting the least recently used entry
+    /// beyond capacity.
+    pub fn insert(&self, nullifier: [u32; 8]) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut lru = self.lru.lock().unwrap_or_else(|e| e.into_inner());
+        lru.touch(nullifier);
+        if lru.entries.len() > self.capacity {
+            if let Some((_, evicted)) = lru.by_tick.pop_first() {
+                lru.entries.remove(&evicted);
+            }
+        }
+    }
+
+    /// True if `nullifier` is known spent. A hit refreshes its recency.
+    /// Safe to call from `spawn_blocking`.
+    pub fn contains(&self, nullifier: &[u32; 8]) -> bool {
+        let mut lru = self.lru.lock().unwrap_or_else(|e| e.into_inner());
+        if !lru.entries.contains_key(nullifier) {
+            return false;
+        }
+        lru.touch(*nullifier);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let cache = SpentNullifiers::new(2);
+        cache.insert([1; 8]);
+        cache.insert([2; 8]);
+        // Touching [1] makes [2] the eviction candidate
+        assert!(cache.contains(&[1; 8]));
+        cache.insert([3; 8]);
+        assert!(cache.contains(&[1; 8]) && cache.contains(&[3; 8]));
+        assert!(!cache.contains(&[2; 8]));
+    }
+
+    #[test]
+    fn test_reinsert_does_not_grow() {
+        let cache = SpentNullifiers::new(2);
+        for _ in 0..3 {
+            cache.insert([7; 8]);
+        }
+        cache.insert([8; 8]);
+        // Repeats took one slot, so both still fit
+        assert!(cache.contains(&[7; 8]) && cache.contains(&[8; 8]));
+    }
+
+    #[test]
+    fn test_zero_capacity_disables() {
+        let cache = SpentNullifiers::new(0);
+        cache.insert([1; 8]);
+        assert!(!cache.contains(&[1; 8]));
+    }
+}
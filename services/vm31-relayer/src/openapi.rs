@@ -0,0 +1,877 @@
+//! Hand-written OpenAPI 3.0 description of the relayer's HTTP API.
+//!
+//! Served at `GET /openapi.json` when `VM31_OPENAPI` is enabled. Request
+//! schemas mirror the serde types in `routes.rs`; the tests below serialize
+//! those types and fail if a field is added or renamed without updating the
+//! spec.
+
+use serde_json::{json, Value};
+
+/// Fixed-length array of M31 field elements / u32 limbs.
+fn u32_array(len: usize, description: &str) -> Value {
+    json!({
+        "type": "array",
+        "items": { "type": "integer", "format": "uint32" },
+        "minItems": len,
+        "maxItems": len,
+        "description": description,
+    })
+}
+
+fn client_ref() -> Value {
+    json!({
+        "type": "string",
+        "description": "Opaque client reference for tracking the tx through batching",
+    })
+}
+
+fn schemas() -> Value {
+    json!({
+        "NoteJson": {
+            "type": "object",
+            "required": ["owner_pubkey", "asset_id", "amount_lo", "amount_hi", "blinding"],
+            "properties": {
+                "owner_pubkey": u32_array(4, "Note owner public key"),
+                "asset_id": { "type": "integer", "format": "uint32", "maximum": 2147483646 },
+                "amount_lo": { "type": "integer", "format": "uint32", "description": "Low 31 bits of the amount" },
+                "amount_hi": { "type": "integer", "format": "uint32", "description": "High 31 bits of the amount" },
+                "blinding": u32_array(4, "Note blinding factor"),
+            },
+        },
+        "MerklePathJson": {
+            "type": "object",
+            "required": ["siblings", "index"],
+            "properties": {
+                "siblings": { "type": "array", "items": u32_array(8, "Sibling digest") },
+                "index": { "type": "integer", "minimum": 0 },
+            },
+        },
+        "InputNoteJson": {
+            "type": "object",
+            "required": ["note", "spending_key", "merkle_path"],
+            "properties": {
+                "note": { "$ref": "#/components/schemas/NoteJson" },
+                "spending_key": u32_array(4, "Spending key for the input note"),
+                "merkle_path": { "$ref": "#/components/schemas/MerklePathJson" },
+            },
+        },
+        "DepositRequest": {
+            "type": "object",
+            "required": ["type", "amount", "asset_id", "recipient_pubkey", "recipient_viewing_key"],
+            "properties": {
+                "type": { "type": "string", "enum": ["deposit"] },
+                "amount": { "type": "integer", "format": "uint64" },
+                "asset_id": { "type": "integer", "format": "uint32", "maximum": 2147483646 },
+                "recipient_pubkey": u32_array(4, "Recipient public key"),
+                "recipient_viewing_key": u32_array(4, "Recipient viewing key"),
+                "blinding": {
+                    "allOf": [u32_array(4, "Note blinding factor (M31); generated by the relayer when omitted")],
+                    "nullable": true,
+                },
+                "client_ref": client_ref(),
+            },
+        },
+        "WithdrawRequest": {
+            "type": "object",
+            "required": [
+                "type", "amount", "asset_id", "note", "spending_key",
+                "merkle_path", "merkle_root", "withdrawal_binding",
+                "payout_recipient", "credit_recipient"
+            ],
+            "properties": {
+                "type": { "type": "string", "enum": ["withdraw"] },
+                "amount": { "type": "integer", "format": "uint64" },
+                "asset_id": { "type": "integer", "format": "uint32", "maximum": 2147483646 },
+                "note": { "$ref": "#/components/schemas/NoteJson" },
+                "spending_key": u32_array(4, "Spending key for the note"),
+                "merkle_path": { "$ref": "#/components/schemas/MerklePathJson" },
+                "merkle_root": u32_array(8, "Pool merkle root the path proves against"),
+                "withdrawal_binding": u32_array(8, "Binding to the payout recipient"),
+                "binding_salt": {
+                    "allOf": [u32_array(8, "Random salt for the withdrawal binding")],
+                    "nullable": true,
+                },
+                "payout_recipient": {
+                    "type": "string",
+                    "pattern": "^(0x)?[0-9a-fA-F]{1,64}$",
+                    "description": "Starknet address paid the withdrawn tokens; the binding must commit to it",
+                },
+                "credit_recipient": {
+                    "type": "string",
+                    "pattern": "^(0x)?[0-9a-fA-F]{1,64}$",
+                    "description": "Starknet address credited when the withdrawal is bridged",
+                },
+                "client_ref": client_ref(),
+            },
+        },
+        "TransferRequest": {
+            "type": "object",
+            "required": [
+                "type", "amount", "asset_id", "recipient_pubkey", "recipient_viewing_key",
+                "sender_viewing_key", "input_notes", "merkle_root"
+            ],
+            "properties": {
+                "type": { "type": "string", "enum": ["transfer"] },
+                "amount": { "type": "integer", "format": "uint64" },
+                "asset_id": { "type": "integer", "format": "uint32", "maximum": 2147483646 },
+                "recipient_pubkey": u32_array(4, "Recipient public key"),
+                "recipient_viewing_key": u32_array(4, "Recipient viewing key"),
+                "sender_viewing_key": u32_array(4, "Sender viewing key (for change)"),
+                "input_notes": {
+                    "type": "array",
+                    "items": { "$ref": "#/components/schemas/InputNoteJson" },
+                    "minItems": 2,
+                    "maxItems": 2,
+                },
+                "merkle_root": u32_array(8, "Pool merkle root the paths prove against"),
+                "client_ref": client_ref(),
+            },
+        },
+        "SubmitRequest": {
+            "oneOf": [
+                { "$ref": "#/components/schemas/DepositRequest" },
+                { "$ref": "#/components/schemas/WithdrawRequest" },
+                { "$ref": "#/components/schemas/TransferRequest" },
+            ],
+            "discriminator": { "propertyName": "type" },
+        },
+        "EncryptedSubmitRequest": {
+            "type": "object",
+            "required": ["ephemeral_pubkey", "ciphertext", "nonce", "version"],
+            "properties": {
+                "ephemeral_pubkey": { "type": "string", "description": "Ephemeral x25519 public key (32 bytes, hex)" },
+                "ciphertext": { "type": "string", "description": "AEAD ciphertext of a SubmitRequest, optionally with a `timestamp` field (unix seconds) checked against VM31_ECIES_MAX_SKEW_SECS (base64)" },
+                "nonce": { "type": "string", "description": "AEAD nonce (12 bytes, hex)" },
+                "version": {
+                    "type": "integer",
+                    "format": "uint8",
+                    "description": "Scheme version listed under `versions` at /public-key",
+                },
+            },
+        },
+        "SubmitBody": {
+            "oneOf": [
+                { "$ref": "#/components/schemas/EncryptedSubmitRequest" },
+                { "$ref": "#/components/schemas/SubmitRequest" },
+            ],
+        },
+        "SubmitResponse": {
+            "type": "object",
+            "properties": {
+                "status": { "type": "string", "enum": ["queued", "batch_triggered", "queued_for_review", "duplicate"] },
+                "batch_id": { "type": "string", "nullable": true },
+                "queue_position": { "type": "integer", "nullable": true },
+                "idempotency_key": { "type": "string", "description": "Key for duplicate detection and cancellation; derived from the Idempotency-Key header when one was sent" },
+                "client_idempotency_key": { "type": "string", "nullable": true, "description": "Echo of the Idempotency-Key header" },
+                "client_ref": { "type": "string", "nullable": true },
+                "blinding": {
+                    "allOf": [u32_array(4, "Deposit note blinding, as supplied or generated; store it to reconstruct the note. A duplicate repeats a generated one")],
+                    "nullable": true,
+                },
+                "cached_result": { "type": "object", "description": "Original response (duplicate only)" },
+            },
+        },
+        "ValidationReport": {
+            "type": "object",
+            "required": ["valid", "errors"],
+            "properties": {
+                "valid": { "type": "boolean" },
+                "errors": { "type": "array", "items": { "$ref": "#/components/schemas/CheckFailure" } },
+            },
+        },
+        "CheckFailure": {
+            "type": "object",
+            "required": ["error", "code"],
+            "description": "A failed check. Decoy-range and denylisted recipients report INVALID_REQUEST",
+            "properties": {
+                "error": { "type": "string" },
+                "code": {
+                    "type": "string",
+                    "enum": [
+                        "INVALID_FIELDS", "INVALID_RECIPIENT", "ASSET_NOT_ALLOWED", "INVALID_MERKLE_PATH",
+                        "ZERO_CHANGE", "KEY_MISMATCH", "UNKNOWN_ROOT", "NULLIFIER_SPENT", "INVALID_REQUEST",
+                    ],
+                },
+            },
+        },
+        "BatchStatus": {
+            "type": "string",
+            "enum": ["pending", "proving", "submitting", "finalized", "failed"],
+        },
+        "BatchResponse": {
+            "type": "object",
+            "properties": {
+                "id": { "type": "string" },
+                "status": { "$ref": "#/components/schemas/BatchStatus" },
+                "tx_count": { "type": "integer" },
+                "proof_hash": { "type": "string", "nullable": true },
+                "batch_id_onchain": { "type": "string", "nullable": true },
+                "tx_hash": { "type": "string", "nullable": true },
+                "created_at": { "type": "integer", "format": "uint64" },
+                "error": { "type": "string", "nullable": true },
+            },
+        },
+        "ClientRefResponse": {
+            "type": "object",
+            "properties": {
+                "client_ref": { "type": "string" },
+                "batch_id": { "type": "string" },
+                "status": { "$ref": "#/components/schemas/BatchStatus" },
+                "tx_count": { "type": "integer" },
+                "batch_id_onchain": { "type": "string", "nullable": true },
+                "tx_hash": { "type": "string", "nullable": true },
+                "created_at": { "type": "integer", "format": "uint64" },
+                "error": { "type": "string", "nullable": true },
+            },
+        },
+        "MerklePathResponse": {
+            "type": "object",
+            "description": "`merkle_path` and `merkle_root` are null unless the note is included in a synced tree; `status` is then one of `pending_sync`, `stuck`, `stale` or `unconfirmed` (the local root is not yet known on-chain).",
+            "properties": {
+                "commitment": { "type": "string" },
+                "merkle_path": {
+                    "allOf": [{ "$ref": "#/components/schemas/MerklePathJson" }],
+                    "nullable": true,
+                },
+                "merkle_root": {
+                    "allOf": [u32_array(8, "Merkle root")],
+                    "nullable": true,
+                },
+                "batch_id": { "type": "string" },
+                "created_at": { "type": "integer", "format": "uint64" },
+                "status": { "type": "string", "enum": ["pending_sync", "stuck", "stale", "unconfirmed"] },
+                "tree_sync_age_secs": { "type": "integer", "nullable": true },
+                "verifiable": {
+                    "type": "object",
+                    "description": "Self-contained verification bundle (only with `?verifiable=true`)",
+                },
+            },
+        },
+        "NotesByOwnerResponse": {
+            "type": "object",
+            "properties": {
+                "owner": { "type": "string", "description": "Owner pubkey (32 hex digits)" },
+                "notes": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "commitment": { "type": "string", "description": "Note key, as for /merkle-path" },
+                            "batch_id": { "type": "string" },
+                            "created_at": { "type": "integer", "format": "uint64" },
+                            "status": { "type": "string", "enum": ["synced", "pending_sync", "stuck"] },
+                        },
+                    },
+                },
+            },
+        },
+        "PublicKeyResponse": {
+            "type": "object",
+            "properties": {
+                "public_key": { "type": "string", "description": "Relayer x25519 public key (hex)" },
+                "version": { "type": "integer" },
+                "algorithm": { "type": "string", "enum": ["x25519-aes256gcm-hkdf-sha256"] },
+                "versions": {
+                    "type": "array",
+                    "description": "All supported envelope versions",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "version": { "type": "integer" },
+                            "algorithm": { "type": "string" },
+                        },
+                    },
+                },
+            },
+        },
+        "StatusResponse": {
+            "type": "object",
+            "properties": {
+                "pending_transactions": { "type": "integer" },
+                "batch_max_size": { "type": "integer" },
+                "batch_timeout_secs": { "type": "integer" },
+                "queue_stats": {
+                    "type": "object",
+                    "description": "Queue depth and flush behaviour, for tuning min batch size and max wait",
+                    "properties": {
+                        "depth": { "type": "integer" },
+                        "oldest_age_ms": { "type": "integer", "nullable": true },
+                        "max_wait_flushes": { "type": "integer" },
+                        "normal_flushes": { "type": "integer" },
+                        "avg_wait_ms": { "type": "integer", "nullable": true, "description": "Over the last 1024 flushed txs" },
+                    },
+                },
+                "prover_paused": { "type": "boolean" },
+                "shuffle": {
+                    "type": "object",
+                    "description": "Batch shuffle entropy source; secure is false only for a test seed",
+                    "properties": {
+                        "enabled": { "type": "boolean" },
+                        "rng": { "type": "string" },
+                        "secure": { "type": "boolean" },
+                    },
+                },
+                "store_backend": { "type": "string" },
+                "rpc_breaker": {
+                    "type": "object",
+                    "nullable": true,
+                    "description": "Pool RPC circuit breaker; null when disabled",
+                    "properties": {
+                        "state": { "type": "string", "enum": ["closed", "open", "half_open"] },
+                        "consecutive_failures": { "type": "integer" },
+                        "trips": { "type": "integer" },
+                        "retry_after_secs": { "type": "integer", "description": "While open: seconds until a probe is let through" },
+                    },
+                },
+                "eviction": { "type": "object" },
+            },
+            "additionalProperties": true,
+        },
+        "Error": {
+            "type": "object",
+            "required": ["error", "code"],
+            "properties": {
+                "error": { "type": "string" },
+                "code": { "type": "string" },
+                "retry_after_secs": { "type": "integer", "description": "Same value as the Retry-After header, when sent" },
+            },
+        },
+    })
+}
+
+fn ok(schema: &str) -> Value {
+    json!({
+        "description": "OK",
+        "content": { "application/json": { "schema": { "$ref": format!("#/components/schemas/{schema}") } } },
+    })
+}
+
+fn ok_object() -> Value {
+    json!({
+        "description": "OK",
+        "content": { "application/json": { "schema": { "type": "object" } } },
+    })
+}
+
+fn error(description: &str) -> Value {
+    json!({
+        "description": description,
+        "content": { "application/json": { "schema": { "$ref": "#/components/schemas/Error" } } },
+    })
+}
+
+/// An error response that may carry a `Retry-After` hint.
+fn retryable_error(description: &str) -> Value {
+    let mut response = error(description);
+    response["headers"] = json!({
+        "Retry-After": { "description": "Seconds to wait before retrying", "schema": { "type": "integer" } },
+    });
+    response
+}
+
+fn path_param(name: &str) -> Value {
+    json!({ "name": name, "in": "path", "required": true, "schema": { "type": "string" } })
+}
+
+fn paths() -> Value {
+    let authed = json!([{ "apiKey": [] }, { "bearer": [] }]);
+    json!({
+        "/health": {
+            "get": { "summary": "Liveness probe (alias of /health/live)", "security": [], "responses": { "200": ok_object() } },
+        },
+        "/health/live": {
+            "get": { "summary": "Liveness probe; 200 while the process is up", "security": [], "responses": { "200": ok_object() } },
+        },
+        "/health/ready": {
+            "get": {
+                "summary": "Readiness probe: prover channel open, tree sync running, store reachable and an RPC endpoint healthy (VM31_RPC_WEIGHTS); 503 with per-subsystem `checks` otherwise",
+                "security": [],
+                "responses": { "200": ok_object(), "503": ok_object() },
+            },
+        },
+        "/ready": {
+            "get": {
+                "summary": "Readiness probe (alias of /health/ready)",
+                "security": [],
+                "responses": { "200": ok_object(), "503": ok_object() },
+            },
+        },
+        "/status": {
+            "get": { "summary": "Queue and store status", "security": [], "responses": { "200": ok("StatusResponse") } },
+        },
+        "/stats": {
+            "get": {
+                "summary": "Open stream connections, and prover latency percentiles (null unless VM31_LATENCY_STATS)",
+                "security": [],
+                "responses": { "200": ok_object() },
+            },
+        },
+        "/public-key": {
+            "get": {
+                "summary": "Relayer ECIES public key for encrypted submissions",
+                "security": [],
+                "responses": { "200": ok("PublicKeyResponse"), "404": error("Encryption not configured") },
+            },
+        },
+        "/assets": {
+            "get": {
+                "summary": "Assets registered in the pool and their deposit denominations (requires VM31_ASSET_DISCOVERY)",
+                "security": [],
+                "responses": { "200": ok_object(), "404": error("Asset list unavailable") },
+            },
+        },
+        "/submit": {
+            "post": {
+                "summary": "Submit a deposit, withdrawal or transfer (plaintext or ECIES envelope)",
+                "security": authed,
+                "parameters": [{
+                    "name": "Idempotency-Key",
+                    "in": "header",
+                    "required": false,
+                    "schema": { "type": "string", "maxLength": 128, "pattern": "^[A-Za-z0-9._:-]+$" },
+                    "description": "Client-chosen key, scoped to the API key, that replaces the payload hash for duplicate detection",
+                }],
+                "requestBody": {
+                    "required": true,
+                    "description": "May be sent with `Content-Encoding: gzip`",
+                    "content": { "application/json": { "schema": { "$ref": "#/components/schemas/SubmitBody" } } },
+                },
+                "responses": {
+                    "200": ok("SubmitResponse"),
+                    "400": error("Invalid request"),
+                    "401": error("Missing or invalid API key"),
+                    "403": error("API key lacks the submit scope"),
+                    "409": error("Withdrawal binding reused by another submission or replayed within VM31_BINDING_REPLAY_WINDOW_SECS"),
+                    "413": { "description": "Body over 256 KiB as sent or 100 KiB decompressed" },
+                    "415": { "description": "Content-Encoding other than gzip" },
+                    "429": retryable_error("Rate limited"),
+                    "503": retryable_error("Batch queue full"),
+                },
+            },
+        },
+        "/submit/stream": {
+            "post": {
+                "summary": "Submit many transactions as NDJSON, one per line, with per-line results streamed back (VM31_SUBMIT_STREAM)",
+                "security": authed,
+                "requestBody": {
+                    "required": true,
+                    "description": "Each line is a SubmitBody, handled as by POST /submit",
+                    "content": { "application/x-ndjson": { "schema": { "type": "string" } } },
+                },
+                "responses": {
+                    "200": {
+                        "description": "One JSON object per line: a SubmitResponse or Error plus `line` and `http_status`",
+                        "content": { "application/x-ndjson": { "schema": { "type": "string" } } },
+                    },
+                    "401": error("Missing or invalid API key"),
+                    "403": error("API key lacks the submit scope"),
+                },
+            },
+        },
+        "/validate": {
+            "post": {
+                "summary": "Dry-run a submission: field, denomination, Merkle root and nullifier checks, without queueing",
+                "security": authed,
+                "requestBody": {
+                    "required": true,
+                    "content": { "application/json": { "schema": { "$ref": "#/components/schemas/SubmitBody" } } },
+                },
+                "responses": {
+                    "200": ok("ValidationReport"),
+                    "400": error("Envelope could not be opened, or plaintext disabled"),
+                    "401": error("Missing or invalid API key"),
+                    "403": error("API key lacks the submit scope"),
+                    "429": retryable_error("Rate limited"),
+                    "500": error("Pool checks unavailable"),
+                    "503": retryable_error("Pool RPC circuit open (RPC_UNAVAILABLE)"),
+                },
+            },
+        },
+        "/submit/{idempotency_key}": {
+            "delete": {
+                "summary": "Cancel a queued submission before its batch is flushed",
+                "security": authed,
+                "parameters": [path_param("idempotency_key")],
+                "responses": {
+                    "200": ok_object(),
+                    "404": error("Unknown idempotency key, or queued by another API key"),
+                    "409": error("Already flushed into a batch or already cancelled"),
+                },
+            },
+        },
+        "/batch/{id}": {
+            "get": {
+                "summary": "Batch status; finalized/failed batches carry an ETag and an immutable Cache-Control",
+                "security": authed,
+                "parameters": [path_param("id")],
+                "responses": {
+                    "200": ok("BatchResponse"),
+                    "304": { "description": "If-None-Match matched the batch's ETag" },
+                    "404": error("Unknown batch"),
+                },
+            },
+        },
+        "/batch/{id}/events": {
+            "get": {
+                "summary": "Server-Sent Events stream of the batch's status: the current status, then each transition, closing after finalized or failed",
+                "security": authed,
+                "parameters": [path_param("id")],
+                "responses": {
+                    "200": {
+                        "description": "`status` events with data `{batch_id, status}`",
+                        "content": { "text/event-stream": { "schema": { "type": "string" } } },
+                    },
+                    "400": error("Unknown batch or malformed id"),
+                    "429": error("Too many open event streams for this API key"),
+                },
+            },
+        },
+        "/batches": {
+            "get": {
+                "summary": "Batches newest first, with cursor pagination",
+                "security": authed,
+                "parameters": [
+                    {
+                        "name": "status",
+                        "in": "query",
+                        "required": false,
+                        "schema": { "$ref": "#/components/schemas/BatchStatus" },
+                    },
+                    {
+                        "name": "limit",
+                        "in": "query",
+                        "required": false,
+                        "schema": { "type": "integer", "minimum": 1, "maximum": 100, "default": 50 },
+                    },
+                    {
+                        "name": "cursor",
+                        "in": "query",
+                        "required": false,
+                        "schema": { "type": "string" },
+                        "description": "`next_cursor` from the previous page",
+                    },
+                ],
+                "responses": {
+                    "200": {
+                        "description": "OK",
+                        "content": { "application/json": { "schema": {
+                            "type": "object",
+                            "properties": {
+                                "batches": { "type": "array", "items": { "$ref": "#/components/schemas/BatchResponse" } },
+                                "next_cursor": { "type": "string", "nullable": true },
+                            },
+                        } } },
+                    },
+                    "400": error("Invalid query"),
+                },
+            },
+        },
+        "/client-ref/{client_ref}": {
+            "get": {
+                "summary": "Batch status by client reference",
+                "security": authed,
+                "parameters": [path_param("client_ref")],
+                "responses": { "200": ok("ClientRefResponse"), "404": error("Unknown client reference") },
+            },
+        },
+        "/prove": {
+            "post": {
+                "summary": "Flush the pending queue into a batch (one per asset in per-asset mode) now; `status` is flushed, empty, below_min_size or concurrently_drained",
+                "security": authed,
+                "responses": { "200": ok_object(), "403": error("API key lacks the force_prove scope") },
+            },
+        },
+        "/merkle-path/{commitment}": {
+            "get": {
+                "summary": "Merkle inclusion path for a note commitment",
+                "security": authed,
+                "parameters": [
+                    path_param("commitment"),
+                    {
+                        "name": "verifiable",
+                        "in": "query",
+                        "required": false,
+                        "schema": { "type": "boolean", "default": false },
+                    },
+                ],
+                "responses": {
+                    "200": ok("MerklePathResponse"),
+                    "400": error("Invalid commitment"),
+                    "404": error("Unknown commitment"),
+                    "429": retryable_error("Rate limited (VM31_MERKLE_PATH_RATE_LIMIT)"),
+                },
+            },
+        },
+        "/merkle-paths": {
+            "post": {
+                "summary": "Merkle inclusion paths for up to 100 commitments; counts once per commitment against the lookup rate limit",
+                "security": authed,
+                "parameters": [
+                    {
+                        "name": "verifiable",
+                        "in": "query",
+                        "required": false,
+                        "schema": { "type": "boolean", "default": false },
+                    },
+                ],
+                "requestBody": {
+                    "required": true,
+                    "content": { "application/json": { "schema": {
+                        "type": "array",
+                        "items": { "type": "string" },
+                        "minItems": 1,
+                        "maxItems": 100,
+                    } } },
+                },
+                "responses": {
+                    "200": {
+                        "description": "Results in request order; unindexed commitments report `pending_sync`",
+                        "content": { "application/json": { "schema": {
+                            "type": "array",
+                            "items": { "$ref": "#/components/schemas/MerklePathResponse" },
+                        } } },
+                    },
+                    "400": error("Empty or oversized list, or an invalid commitment"),
+                    "429": retryable_error("Rate limited (VM31_MERKLE_PATH_RATE_LIMIT)"),
+                },
+            },
+        },
+        "/notes/by-owner/{pubkey}": {
+            "get": {
+                "summary": "Notes indexed under an owner pubkey (admin; requires VM31_NOTE_OWNER_INDEX)",
+                "security": authed,
+                "parameters": [path_param("pubkey")],
+                "responses": {
+                    "200": ok("NotesByOwnerResponse"),
+                    "400": error("Invalid pubkey (expected 32 hex digits)"),
+                    "429": retryable_error("Rate limited"),
+                },
+            },
+        },
+        "/tree/resync": {
+            "post": { "summary": "Force a tree sync (admin)", "security": authed, "responses": { "200": ok_object() } },
+        },
+        "/tree/backfill": {
+            "post": { "summary": "Backfill pending note paths (admin)", "security": authed, "responses": { "200": ok_object() } },
+        },
+        "/batch/{id}/backfill": {
+            "post": {
+                "summary": "Sync the tree and backfill one finalized batch's note paths (admin)",
+                "security": authed,
+                "parameters": [path_param("id")],
+                "responses": {
+                    "200": ok_object(),
+                    "404": error("Unknown batch or tree sync disabled"),
+                    "409": error("Batch not finalized, or a sync is already running"),
+                },
+            },
+        },
+        "/quarantine": {
+            "get": { "summary": "List quarantined submissions (admin)", "security": authed, "responses": { "200": ok_object() } },
+        },
+        "/quarantine/{id}/approve": {
+            "post": {
+                "summary": "Release a quarantined submission into the queue (admin)",
+                "security": authed,
+                "parameters": [path_param("id")],
+                "responses": { "200": ok("SubmitResponse"), "404": error("Unknown quarantine id") },
+            },
+        },
+        "/quarantine/{id}/reject": {
+            "post": {
+                "summary": "Drop a quarantined submission (admin)",
+                "security": authed,
+                "parameters": [path_param("id")],
+                "responses": { "200": ok_object(), "404": error("Unknown quarantine id") },
+            },
+        },
+        "/dlq": {
+            "get": {
+                "summary": "List batches kept after failing proving or submission (admin)",
+                "security": authed,
+                "responses": { "200": ok_object() },
+            },
+        },
+        "/dlq/{batch_id}/replay": {
+            "post": {
+                "summary": "Re-enqueue a dead-lettered batch's transactions under fresh idempotency keys (admin)",
+                "security": authed,
+                "parameters": [path_param("batch_id")],
+                "responses": {
+                    "200": ok_object(),
+                    "400": error("A stored transaction no longer validates"),
+                    "404": error("No dead-lettered batch with that id"),
+                    "503": retryable_error("Batch queue full"),
+                },
+            },
+        },
+        "/admin/prover/pause": {
+            "post": { "summary": "Pause batch proving (admin)", "security": authed, "responses": { "200": ok_object() } },
+        },
+        "/admin/prover/resume": {
+            "post": { "summary": "Resume batch proving (admin)", "security": authed, "responses": { "200": ok_object() } },
+        },
+        "/admin/store/export": {
+            "get": {
+                "summary": "NDJSON snapshot of the store (admin, VM31_STORE_TRANSFER)",
+                "security": authed,
+                "responses": {
+                    "200": {
+                        "description": "One JSON object per line: header, records, end",
+                        "content": { "application/x-ndjson": { "schema": { "type": "string" } } },
+                    },
+                },
+            },
+        },
+        "/admin/store/import": {
+            "post": {
+                "summary": "Load a snapshot into an empty store (admin, VM31_STORE_TRANSFER)",
+                "security": authed,
+                "requestBody": {
+                    "required": true,
+                    "content": { "application/x-ndjson": { "schema": { "type": "string" } } },
+                },
+                "responses": {
+                    "200": ok_object(),
+                    "400": error("Invalid, newer or truncated snapshot"),
+                    "409": error("Store is not empty"),
+                },
+            },
+        },
+        "/encrypt-check": {
+            "post": {
+                "summary": "DEV ONLY: check that an ECIES envelope decrypts and parses (requires VM31_ENCRYPT_CHECK)",
+                "security": authed,
+                "requestBody": {
+                    "required": true,
+                    "content": { "application/json": { "schema": { "$ref": "#/components/schemas/EncryptedSubmitRequest" } } },
+                },
+                "responses": { "200": ok_object() },
+            },
+        },
+        "/openapi.json": {
+            "get": { "summary": "This document", "security": [], "responses": { "200": ok_object() } },
+        },
+    })
+}
+
+/// The full OpenAPI document.
+pub fn spec() -> Value {
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "vm31-relayer",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "Batches VM31 privacy pool transactions, proves them and submits them on-chain.",
+        },
+        "paths": paths(),
+        "components": {
+            "schemas": schemas(),
+            "securitySchemes": {
+                "apiKey": { "type": "apiKey", "in": "header", "name": "x-api-key" },
+                "bearer": { "type": "http", "scheme": "bearer" },
+            },
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routes::{EncryptedSubmitRequest, InputNoteJson, MerklePathJson, NoteJson, SubmitRequest};
+
+    fn note() -> NoteJson {
+        NoteJson {
+            owner_pubkey: [1; 4],
+            asset_id: 0,
+            amount_lo: 10,
+            amount_hi: 0,
+            blinding: [2; 4],
+        }
+    }
+
+    fn path() -> MerklePathJson {
+        MerklePathJson { siblings: vec![[3; 8]], index: 0 }
+    }
+
+    /// Every serialized field must be in the schema, and every required
+    /// schema field must be serialized.
+    fn assert_matches_schema(value: &Value, schema_name: &str) {
+        let spec = spec();
+        let schema = &spec["components"]["schemas"][schema_name];
+        let props = schema["properties"].as_object().unwrap();
+        let obj = value.as_object().unwrap();
+        for key in obj.keys() {
+            assert!(props.contains_key(key), "{schema_name}: field `{key}` missing from spec");
+        }
+        for req in schema["required"].as_array().unwrap() {
+            let req = req.as_str().unwrap();
+            assert!(obj.contains_key(req), "{schema_name}: spec requires unknown field `{req}`");
+        }
+    }
+
+    #[test]
+    fn test_request_schemas_match_serde_types() {
+        let deposit = SubmitRequest::Deposit {
+            amount: 1,
+            asset_id: 0,
+            recipient_pubkey: [1; 4],
+            recipient_viewing_key: [2; 4],
+            blinding: Some([3; 4]),
+            client_ref: Some("ref".into()),
+        };
+        let withdraw = SubmitRequest::Withdraw {
+            amount: 1,
+            asset_id: 0,
+            note: note(),
+            spending_key: [4; 4],
+            merkle_path: path(),
+            merkle_root: [5; 8],
+            withdrawal_binding: [6; 8],
+            binding_salt: Some([7; 8]),
+            payout_recipient: Some("0xa1".into()),
+            credit_recipient: Some("0xc1".into()),
+            client_ref: Some("ref".into()),
+        };
+        let input = || InputNoteJson {
+            note: note(),
+            spending_key: [4; 4],
+            merkle_path: path(),
+        };
+        let transfer = SubmitRequest::Transfer {
+            amount: 1,
+            asset_id: 0,
+            recipient_pubkey: [1; 4],
+            recipient_viewing_key: [2; 4],
+            sender_viewing_key: [3; 4],
+            input_notes: [input(), input()],
+            merkle_root: [5; 8],
+            client_ref: Some("ref".into()),
+        };
+        let envelope = EncryptedSubmitRequest {
+            ephemeral_pubkey: String::new(),
+            ciphertext: String::new(),
+            nonce: String::new(),
+            version: 1,
+        };
+
+        assert_matches_schema(&serde_json::to_value(deposit).unwrap(), "DepositRequest");
+        assert_matches_schema(&serde_json::to_value(withdraw).unwrap(), "WithdrawRequest");
+        assert_matches_schema(&serde_json::to_value(transfer).unwrap(), "TransferRequest");
+        assert_matches_schema(&serde_json::to_value(envelope).unwrap(), "EncryptedSubmitRequest");
+        assert_matches_schema(&serde_json::to_value(note()).unwrap(), "NoteJson");
+        assert_matches_schema(&serde_json::to_value(path()).unwrap(), "MerklePathJson");
+        assert_matches_schema(&serde_json::to_value(input()).unwrap(), "InputNoteJson");
+    }
+
+    #[test]
+    fn test_refs_resolve() {
+        let spec = spec();
+        let text = spec.to_string();
+        let schemas = spec["components"]["schemas"].as_object().unwrap();
+        for part in text.split("#/components/schemas/").skip(1) {
+            let name: String = part.chars().take_while(|c| c.is_ascii_alphanumeric()).collect();
+            assert!(schemas.contains_key(&name), "dangling $ref to {name}");
+        }
+    }
+}
@@ -0,0 +1,202 @@
+//! Short-lived cache of batch proofs, so replaying an identical batch (e.g.
+//! during incident recovery) reuses the proof instead of paying for STARK
+//! proving again.
+//!
+//! Batches are shuffled before proving, so entries are keyed by the sorted
+//! per-transaction digests. Each entry remembers the order its transactions
+//! were proven in; a hit hands that order back so the batch can be rearranged
+//! to match the proof's outputs.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use sha2::{Digest, Sha256};
+use stwo_ml::privacy::tx_builder::{PendingTx, ProvenTransaction};
+
+use crate::routes::SubmitRequest;
+
+/// Proofs are large; a replay only needs the last few batches.
+const MAX_CACHED_PROOFS: usize = 16;
+
+struct Cached<T> {
+    /// Transaction digests in proven order.
+    order: Vec<String>,
+    proof: Arc<T>,
+    expires_at: Instant,
+}
+
+/// A cached proof and how to line a batch up with it.
+pub struct CacheHit<T> {
+    pub proof: Arc<T>,
+    /// `order[i]` is the index in the looked-up batch of the i-th proven tx.
+    order: Vec<usize>,
+}
+
+impl<T> CacheHit<T> {
+    /// Rearranges batch-aligned `items` into the proof's order.
+    pub fn reorder<U>(&self, items: Vec<U>) -> Vec<U> {
+        let mut slots: Vec<Option<U>> = items.into_iter().map(Some).collect();
+        self.order.iter().filter_map(|&i| slots[i].take()).collect()
+    }
+
+    fn in_order(&self) -> bool {
+        self.order.iter().enumerate().all(|(i, &j)| i == j)
+    }
+}
+
+pub struct ProofCache<T = ProvenTransaction> {
+    ttl: Duration,
+    entries: Mutex<HashMap<String, Cached<T>>>,
+}
+
+/// Canonical digest of each transaction, index-aligned with `txs`.
+pub fn tx_keys(txs: &[PendingTx]) -> Vec<String> {
+    txs.iter().map(|tx| SubmitRequest::from(tx).idempotency_key()).collect()
+}
+
+/// Order-independent key of a transaction set.
+fn set_key(keys: &[String]) -> String {
+    let mut sorted: Vec<&String> = keys.iter().collect();
+    sorted.sort();
+    let mut h = Sha256::new();
+    h.update(b"vm31-proof-cache-v1");
+    for k in sorted {
+        h.update(k.as_bytes());
+        h.update(b"\0");
+    }
+    format!("{:x}", h.finalize())
+}
+
+impl<T> ProofCache<T> {
+    /// Keeps each proof for `ttl_secs` after it was produced.
+    pub fn new(ttl_secs: u64) -> Self {
+        Self {
+            ttl: Duration::from_secs(ttl_secs),
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Unexpired proof of the same transaction set as `keys`, in any order.
+    pub fn lookup(&self, keys: &[String]) -> Option<CacheHit<T>> {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let set = set_key(keys);
+        let entry = entries.get(&set)?;
+        if entry.expires_at <= Instant::now() {
+            entries.remove(&set);
+            return None;
+        }
+        // Match each proven digest to the first unused equal one in `keys`,
+        // so identical transactions keep their relative order
+        let mut used = vec![false; keys.len()];
+        let mut order = Vec::with_capacity(keys.len());
+        for proven in &entry.order {
+            let i = (0..keys.len()).find(|&i| !used[i] && &keys[i] == proven)?;
+            used[i] = true;
+            order.push(i);
+        }
+        Some(CacheHit {
+            proof: entry.proof.clone(),
+            order,
+        })
+    }
+
+    /// Records `proof` for transactions `keys`, given in proven order.
+    pub fn insert(&self, keys: Vec<String>, proof: Arc<T>) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        entries.retain(|_, e| e.expires_at > now);
+        if entries.len() >= MAX_CACHED_PROOFS {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, e)| e.expires_at)
+                .map(|(k, _)| k.clone());
+            if let Some(k) = oldest {
+                entries.remove(&k);
+            }
+        }
+        entries.insert(
+            set_key(&keys),
+            Cached {
+                order: keys,
+                proof,
+                expires_at: now + self.ttl,
+            },
+        );
+    }
+
+    /// Returns the cached proof of `keys` if it was proven in exactly this
+    /// order, otherwise runs `prove` and caches the result.
+    pub async fn get_or_prove<E, Fut>(
+        &self,
+        keys: Vec<String>,
+        prove: impl FnOnce() -> Fut,
+    ) -> Result<Arc<T>, E>
+    where
+        Fut: Future<Output = Result<T, E>>,
+    {
+        if let Some(hit) = self.lookup(&keys) {
+            if hit.in_order() {
+                return Ok(hit.proof);
+            }
+        }
+        let proof = Arc::new(prove().await?);
+        self.insert(keys, proof.clone());
+        Ok(proof)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn keys(ks: &[&str]) -> Vec<String> {
+        ks.iter().map(|k| k.to_string()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_hit_skips_prove_and_other_set_misses() {
+        let cache: ProofCache<u32> = ProofCache::new(60);
+        let calls = AtomicU32::new(0);
+        let prove = |v: u32| {
+            let calls = &calls;
+            move || async move {
+                calls.fetch_add(1, Ordering::Relaxed);
+                Ok::<_, String>(v)
+            }
+        };
+
+        let first = cache.get_or_prove(keys(&["a", "b"]), prove(1)).await.unwrap();
+        let again = cache.get_or_prove(keys(&["a", "b"]), prove(2)).await.unwrap();
+        assert_eq!((*first, *again), (1, 1));
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+
+        let other = cache.get_or_prove(keys(&["a", "c"]), prove(3)).await.unwrap();
+        assert_eq!(*other, 3);
+        assert_eq!(calls.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn test_shuffled_replay_hits_and_reorders() {
+        let cache: ProofCache<u32> = ProofCache::new(60);
+        cache.insert(keys(&["a", "b", "c"]), Arc::new(7));
+
+        let replay = keys(&["c", "a", "b"]);
+        let hit = cache.lookup(&replay).expect("same set in another order");
+        assert_eq!(*hit.proof, 7);
+        assert_eq!(hit.reorder(replay), keys(&["a", "b", "c"]));
+        assert_eq!(hit.reorder(vec!["ref-c", "ref-a", "ref-b"]), vec!["ref-a", "ref-b", "ref-c"]);
+
+        assert!(cache.lookup(&keys(&["a", "b"])).is_none());
+        assert!(cache.lookup(&keys(&["a", "b", "b"])).is_none());
+    }
+
+    #[test]
+    fn test_expired_entries_miss() {
+        let cache: ProofCache<u32> = ProofCache::new(0);
+        cache.insert(keys(&["a"]), Arc::new(1));
+        assert!(cache.lookup(&keys(&["a"])).is_none());
+    }
+}
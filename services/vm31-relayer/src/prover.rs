@@ -1,7 +1,9 @@
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use sha2::{Sha256, Digest};
-use tokio::sync::mpsc;
-use tracing::{error, info, warn};
+use std::time::Duration;
+use rand::Rng;
+use tokio::sync::{mpsc, Notify};
+use tracing::{error, info, warn, Instrument};
 
 /// Produce a short opaque reference for log entries.
 /// FNV-1a hash folded to 32 bits — non-reversible, sufficient for log correlation.
@@ -14,6 +16,9 @@ fn opaque_ref(input: &str) -> String {
     format!("{:08x}", (state >> 32) ^ (state & 0xFFFFFFFF))
 }
 
+use stwo_ml::crypto::commitment::Note;
+use stwo_ml::crypto::merkle_m31::Digest as NoteCommitment;
+use stwo_ml::prelude::M31;
 use stwo_ml::privacy::pool_client::{PoolClient, PoolClientConfig};
 use stwo_ml::privacy::relayer::{
     hash_batch_public_inputs_for_cairo, run_vm31_relayer_flow, RelayOutcome, SncastVm31Backend,
@@ -21,11 +26,24 @@ use stwo_ml::privacy::relayer::{
 };
 use stwo_ml::privacy::tx_builder::{PendingTx, ProvenTransaction, TxBuilder};
 
-use crate::batch_queue::ReadyBatch;
+use crate::amount::NoteAmount;
+use crate::batch_queue::{asset_of, BatchQueue, ReadyBatch, TxKind};
+use crate::commitment::commitment_key_from_fields;
 use crate::bridge::BridgeService;
+use crate::circuit_breaker::CircuitBreaker;
+use crate::dead_letter::DeadLetter;
+use crate::latency::{LatencyStats, Stage};
+use crate::metrics::Metrics;
+use crate::nullifier_cache::SpentNullifiers;
+use crate::recovery::{RecoveryEntry, RecoveryLog};
+use crate::routes::{CheckFailure, WithdrawalRecipient};
+use crate::rpc_pool::{with_failover, RpcPool};
+use crate::proof_cache::{self, ProofCache};
+use crate::tree_sync_service::KnownRoots;
+use crate::telemetry::{self, BatchStage};
 use crate::store::{
-    BatchRecord, BatchStatus, BatchStore, InMemoryStore, MerklePathRecord, NoteRecord, NoteStore,
-    StatusUpdate,
+    now_epoch, BatchRecord, BatchStatus, BatchStore, DeadLetterStore, IdempotencyStore, InMemoryStore, MerklePathRecord, NoteRecord, NoteStore,
+    OwnerIndexStore, StatusUpdate, StoreError,
 };
 
 /// Deposit note info extracted before the proving step (which moves txs).
@@ -34,22 +52,291 @@ struct DepositNoteInfo {
     asset_id: u32,
     amount: u64,
     blinding: [u32; 4],
+    client_ref: Option<String>,
 }
 
 impl DepositNoteInfo {
-    /// Compute a deterministic commitment key via SHA-256 of note fields.
-    /// Collision-resistant — safe for use as a database key.
+    /// True if `note` is this deposit's output (owner, asset, amount and
+    /// blinding agree).
+    fn matches(&self, note: &Note) -> bool {
+        note.owner_pubkey.map(|m| m.0) == self.owner_pubkey
+            && note.asset_id.0 == self.asset_id
+            && note.blinding.map(|m| m.0) == self.blinding
+            && NoteAmount::from_limbs(note.amount_lo.0, note.amount_hi.0).map(NoteAmount::get)
+                == Some(self.amount)
+    }
+
+    /// The note's store key (see `commitment::commitment_key_from_fields`).
     fn commitment_key(&self) -> String {
-        let mut hasher = Sha256::new();
-        for &v in &self.owner_pubkey {
-            hasher.update(v.to_le_bytes());
+        commitment_key_from_fields(self.owner_pubkey, self.asset_id, self.amount, self.blinding)
+    }
+}
+
+/// How proven output commitments are assigned to deposits
+/// (`VM31_COMMITMENT_MAPPING`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommitmentMapping {
+    /// Trust output order to follow tx order (checked only by count).
+    Positional,
+    /// Check each output's note against the deposit, searching the other
+    /// outputs when it is out of position (the default).
+    ByNote,
+}
+
+impl std::str::FromStr for CommitmentMapping {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "positional" => Ok(Self::Positional),
+            "by_note" => Ok(Self::ByNote),
+            other => Err(format!("unknown commitment mapping '{other}' (expected by_note or positional)")),
+        }
+    }
+}
+
+/// What to do when a new deposit note's key is already tracked for another
+/// batch (`VM31_DUPLICATE_NOTE_POLICY`). Identical note fields give the same
+/// key, e.g. when a client reuses deposit parameters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicateNotePolicy {
+    /// Keep the first note and don't track the new one.
+    Reject,
+    /// Track the new note under `{commitment}-{batch_id}` (the default).
+    Disambiguate,
+}
+
+impl std::str::FromStr for DuplicateNotePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reject" => Ok(Self::Reject),
+            "disambiguate" => Ok(Self::Disambiguate),
+            other => Err(format!("unknown duplicate note policy '{other}' (expected disambiguate or reject)")),
+        }
+    }
+}
+
+/// What the prover does with a batch in which some kind/asset group has
+/// fewer than the minimum anonymity set (`VM31_ANON_SET_MODE`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnonSetMode {
+    /// Log the shortfall and prove the batch anyway (the default).
+    Lenient,
+    /// Return the short groups' txs to the queue and prove the rest.
+    Strict,
+}
+
+impl std::str::FromStr for AnonSetMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lenient" => Ok(Self::Lenient),
+            "strict" => Ok(Self::Strict),
+            other => Err(format!("unknown anonymity set mode '{other}' (expected lenient or strict)")),
+        }
+    }
+}
+
+/// A group of same-kind, same-asset txs in a batch smaller than the minimum
+/// anonymity set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ThinAnonSet {
+    kind: TxKind,
+    asset_id: u32,
+    count: usize,
+}
+
+/// Groups `txs` by kind and asset and returns those with fewer than `min`
+/// members. A withdrawal is only hidden among the other withdrawals of its
+/// asset, however many deposits share the batch.
+fn thin_anon_sets(txs: &[PendingTx], min: usize) -> Vec<ThinAnonSet> {
+    let mut groups: std::collections::BTreeMap<(u8, u32), (TxKind, usize)> = Default::default();
+    for tx in txs {
+        let kind = TxKind::of(tx);
+        groups.entry((kind as u8, asset_of(tx))).or_insert((kind, 0)).1 += 1;
+    }
+    groups
+        .into_iter()
+        .filter(|(_, (_, count))| *count < min)
+        .map(|((_, asset_id), (kind, count))| ThinAnonSet { kind, asset_id, count })
+        .collect()
+}
+
+/// Operator switch that halts proving without stopping ingestion.
+///
+/// While paused the prover stops taking batches off the channel, so flushed
+/// batches wait in its buffer; once the buffer is full, flushes (and the
+/// submissions that trigger them) wait for the prover to resume.
+#[derive(Default)]
+pub struct ProverPause {
+    paused: AtomicBool,
+    resumed: Notify,
+}
+
+impl ProverPause {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    /// Returns `false` if the prover was already paused.
+    pub fn pause(&self) -> bool {
+        !self.paused.swap(true, Ordering::SeqCst)
+    }
+
+    /// Returns `false` if the prover was not paused.
+    pub fn resume(&self) -> bool {
+        let was_paused = self.paused.swap(false, Ordering::SeqCst);
+        self.resumed.notify_waiters();
+        was_paused
+    }
+
+    async fn wait_until_resumed(&self) {
+        loop {
+            // Register before checking so a resume in between isn't missed.
+            let resumed = self.resumed.notified();
+            if !self.is_paused() {
+                return;
+            }
+            resumed.await;
+        }
+    }
+}
+
+/// Coordinated shutdown, shared by the signal handler, `/submit` and the
+/// prover.
+///
+/// `begin` makes `/submit` refuse new transactions (503). Once the queue has
+/// been flushed, `queue_drained` tells the prover nothing more is coming: it
+/// finishes the batch in hand, handles what is still buffered (see
+/// `with_drain_on_close`) and returns, even though queue handles still hold
+/// the channel open.
+#[derive(Default)]
+pub struct Shutdown {
+    started: AtomicBool,
+    drained: AtomicBool,
+    drained_notify: Notify,
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns `false` if shutdown had already begun.
+    pub fn begin(&self) -> bool {
+        !self.started.swap(true, Ordering::SeqCst)
+    }
+
+    pub fn is_started(&self) -> bool {
+        self.started.load(Ordering::SeqCst)
+    }
+
+    /// Releases the prover to finish up; implies `begin`.
+    pub fn queue_drained(&self) {
+        self.started.store(true, Ordering::SeqCst);
+        self.drained.store(true, Ordering::SeqCst);
+        self.drained_notify.notify_waiters();
+    }
+
+    async fn wait_until_drained(&self) {
+        loop {
+            // Register before checking so a notify in between isn't missed.
+            let drained = self.drained_notify.notified();
+            if self.drained.load(Ordering::SeqCst) {
+                return;
+            }
+            drained.await;
+        }
+    }
+}
+
+/// Pool-side input checks: Merkle roots are known and input nullifiers
+/// unspent. Run by the prover before proving and by `POST /validate`.
+#[derive(Clone)]
+pub struct InputChecks {
+    pool_config: PoolClientConfig,
+    known_roots: Option<Arc<KnownRoots>>,
+    spent_nullifiers: Option<Arc<SpentNullifiers>>,
+    rpc_pool: Option<Arc<RpcPool>>,
+    rpc_breaker: Option<Arc<CircuitBreaker>>,
+    confirmed_roots_only: bool,
+}
+
+/// Outcome of `InputChecks::run`.
+#[derive(Debug)]
+pub enum InputCheck {
+    Passed,
+    /// The inputs were checked and failed `check`, for this reason.
+    Rejected { check: CheckFailure, reason: String },
+    /// The checks could not complete (RPC failure or task panic).
+    Unavailable(ProverError),
+}
+
+impl InputChecks {
+    /// Runs the checks on a blocking thread, as `PoolClient` RPC is synchronous.
+    /// Fails fast with `RpcUnavailable` while the circuit breaker is open.
+    pub async fn run(&self, txs: Vec<PendingTx>) -> InputCheck {
+        let permit = match self.rpc_breaker.as_deref().map(CircuitBreaker::try_acquire) {
+            Some(Err(retry_after_secs)) => {
+                return InputCheck::Unavailable(ProverError::RpcUnavailable { retry_after_secs });
+            }
+            Some(Ok(permit)) => Some(permit),
+            None => None,
+        };
+        let endpoint = self.rpc_pool.as_ref().map(|pool| pool.pick());
+        let pool_cfg = match (&self.rpc_pool, endpoint) {
+            (Some(pool), Some(idx)) => pool.config_for(idx, &self.pool_config),
+            _ => self.pool_config.clone(),
+        };
+        let known_roots = self.known_roots.clone();
+        let spent_nullifiers = self.spent_nullifiers.clone();
+        let confirmed_roots_only = self.confirmed_roots_only;
+        let joined = tokio::task::spawn_blocking(move || {
+            // Backups in `verify_rpc_urls` are tried in order if the RPC fails
+            with_failover(&pool_cfg, |cfg| {
+                let pool_client = PoolClient::new(cfg);
+                let rpc_ok = std::cell::Cell::new(true);
+                let result = ProverService::validate_inputs_blocking(
+                    &pool_client,
+                    known_roots.as_deref(),
+                    confirmed_roots_only,
+                    spent_nullifiers.as_deref(),
+                    &txs,
+                    &rpc_ok,
+                );
+                (result, rpc_ok.get())
+            })
+        })
+        .await;
+        let outcome = match joined {
+            Ok(outcome) => outcome,
+            Err(e) => return InputCheck::Unavailable(join_failure("validation", e, ProverError::Validation)),
+        };
+        if let (Some(pool), Some(idx)) = (&self.rpc_pool, endpoint) {
+            pool.record(idx, outcome.primary_ok());
         }
-        hasher.update(self.asset_id.to_le_bytes());
-        hasher.update(self.amount.to_le_bytes());
-        for &v in &self.blinding {
-            hasher.update(v.to_le_bytes());
+        let (result, rpc_ok) = (outcome.value, outcome.rpc_ok);
+        if let Some(permit) = permit {
+            permit.record(rpc_ok);
+        }
+        match result {
+            Ok(()) => InputCheck::Passed,
+            Err(ProverError::InputRejected { check, reason }) if rpc_ok => {
+                InputCheck::Rejected { check, reason }
+            }
+            Err(ProverError::Validation(reason)) if rpc_ok => InputCheck::Rejected {
+                check: CheckFailure::InvalidRequest,
+                reason,
+            },
+            Err(e) => InputCheck::Unavailable(e),
         }
-        format!("{:x}", hasher.finalize())
     }
 }
 
@@ -60,6 +347,52 @@ pub struct ProverService {
     store: Arc<InMemoryStore>,
     relayer_config: Vm31RelayerConfig,
     bridge: BridgeService,
+    /// On channel close, prove batches still buffered in the channel (true)
+    /// or record them as failed so they're visible for resubmission (false).
+    drain_on_close: bool,
+    /// Extra attempts for each store status update before giving up.
+    status_retries: u32,
+    /// Extra attempts for a batch's initial record before dead-lettering it.
+    batch_save_retries: u32,
+    /// Extra attempts for the on-chain submission after a transient failure.
+    submit_retries: u32,
+    /// Fewest txs of one kind and asset a batch should carry (0 = unchecked).
+    min_anon_set: usize,
+    /// In strict mode, where txs short of `min_anon_set` are returned to.
+    anon_set_requeue: Option<Arc<BatchQueue>>,
+    /// Where terminal outcomes go when the store can't record them.
+    recovery_log: Option<RecoveryLog>,
+    /// Rolling per-stage latency summaries (exposed at `/stats`).
+    latency: Option<Arc<LatencyStats>>,
+    /// Prometheus proving-duration histogram (exposed at `/metrics`).
+    metrics: Option<Arc<Metrics>>,
+    /// Exit the process after a blocking stage panics, once the batch is
+    /// marked Failed, so a supervisor restarts with fresh state.
+    exit_on_panic: bool,
+    /// Roots verified by the local tree sync; checked before `is_known_root` RPC.
+    known_roots: Option<Arc<KnownRoots>>,
+    /// Nullifiers confirmed spent; checked before `is_nullifier_spent` RPC.
+    spent_nullifiers: Option<Arc<SpentNullifiers>>,
+    /// Spreads validation RPC calls across weighted endpoints.
+    rpc_pool: Option<Arc<RpcPool>>,
+    /// Fails validation fast while pool RPC is down (shared with tree sync).
+    rpc_breaker: Option<Arc<CircuitBreaker>>,
+    /// How proven output commitments are matched back to deposits.
+    commitment_mapping: CommitmentMapping,
+    /// Handling of note keys already tracked for another batch.
+    duplicate_notes: DuplicateNotePolicy,
+    /// Index saved deposit notes by owner pubkey (`VM31_NOTE_OWNER_INDEX`).
+    owner_index: bool,
+    /// Maintenance pause shared with the admin endpoints.
+    pause: Arc<ProverPause>,
+    /// Stops the run loop once the queue is drained at shutdown.
+    shutdown: Arc<Shutdown>,
+    /// Keep failed batches' transactions for replay (see `dead_letter`).
+    dead_letter: bool,
+    /// Accept only roots in `known_roots` (see `with_confirmed_roots_only`).
+    confirmed_roots_only: bool,
+    /// Recent proofs reused for identical replayed batches.
+    proof_cache: Option<Arc<ProofCache>>,
 }
 
 impl ProverService {
@@ -79,74 +412,478 @@ impl ProverService {
                 ..Default::default()
             },
             bridge,
+            drain_on_close: true,
+            status_retries: 0,
+            batch_save_retries: 0,
+            submit_retries: 0,
+            min_anon_set: 0,
+            anon_set_requeue: None,
+            recovery_log: None,
+            latency: None,
+            metrics: None,
+            exit_on_panic: false,
+            known_roots: None,
+            spent_nullifiers: None,
+            rpc_pool: None,
+            rpc_breaker: None,
+            commitment_mapping: CommitmentMapping::ByNote,
+            duplicate_notes: DuplicateNotePolicy::Disambiguate,
+            owner_index: false,
+            pause: Arc::new(ProverPause::new()),
+            shutdown: Arc::new(Shutdown::new()),
+            dead_letter: false,
+            confirmed_roots_only: false,
+            proof_cache: None,
+        }
+    }
+
+    /// Shares a pause switch with the admin API.
+    pub fn with_pause(mut self, pause: Arc<ProverPause>) -> Self {
+        self.pause = pause;
+        self
+    }
+
+    /// Returns from `run` once `shutdown` reports the queue drained.
+    pub fn with_shutdown(mut self, shutdown: Arc<Shutdown>) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
+    /// Moves the transactions of batches that fail processing to the
+    /// dead-letter store instead of dropping them. Batches whose initial
+    /// record can't be saved are dead-lettered either way.
+    pub fn with_dead_letter(mut self, enabled: bool) -> Self {
+        self.dead_letter = enabled;
+        self
+    }
+
+    /// Checks Merkle roots against `known_roots` first, falling back to RPC.
+    pub fn with_known_roots(mut self, known_roots: Arc<KnownRoots>) -> Self {
+        self.known_roots = Some(known_roots);
+        self
+    }
+
+    /// Accepts a withdrawal or transfer root only if it is in `known_roots`:
+    /// a root of the local tree that the pool confirmed as known. Roots the
+    /// chain knows but the local tree never reached (local tree behind), or
+    /// that the local tree reached before the chain did (local tree ahead),
+    /// are rejected without an RPC. Needs `with_known_roots`.
+    pub fn with_confirmed_roots_only(mut self, enabled: bool) -> Self {
+        self.confirmed_roots_only = enabled;
+        self
+    }
+
+    /// Rejects known-spent nullifiers from `cache` without an RPC, and
+    /// records nullifiers seen spent or finalized into it.
+    pub fn with_spent_nullifiers(mut self, cache: Arc<SpentNullifiers>) -> Self {
+        self.spent_nullifiers = Some(cache);
+        self
+    }
+
+    /// Sets how deposit digests are read from the proven outputs.
+    pub fn with_commitment_mapping(mut self, mapping: CommitmentMapping) -> Self {
+        self.commitment_mapping = mapping;
+        self
+    }
+
+    /// Sets how a note key already tracked for another batch is handled.
+    pub fn with_duplicate_note_policy(mut self, policy: DuplicateNotePolicy) -> Self {
+        self.duplicate_notes = policy;
+        self
+    }
+
+    /// Records each saved deposit note under its owner pubkey, for
+    /// `GET /notes/by-owner/{pubkey}`.
+    pub fn with_owner_index(mut self, enabled: bool) -> Self {
+        self.owner_index = enabled;
+        self
+    }
+
+    /// Reuses a cached proof when a batch holds the same transactions as a
+    /// recently proven one, in any order.
+    pub fn with_proof_cache(mut self, cache: Arc<ProofCache>) -> Self {
+        self.proof_cache = Some(cache);
+        self
+    }
+
+    /// Runs input validation against an endpoint picked from `pool`.
+    pub fn with_rpc_pool(mut self, pool: Arc<RpcPool>) -> Self {
+        self.rpc_pool = Some(pool);
+        self
+    }
+
+    /// Gates validation RPC behind `breaker`.
+    pub fn with_circuit_breaker(mut self, breaker: Arc<CircuitBreaker>) -> Self {
+        self.rpc_breaker = Some(breaker);
+        self
+    }
+
+    /// Records per-stage latencies into `stats`.
+    pub fn with_latency_stats(mut self, stats: Arc<LatencyStats>) -> Self {
+        self.latency = Some(stats);
+        self
+    }
+
+    /// Records proving durations into `metrics`.
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Exits the process after a stage panic (see `exit_on_panic`).
+    pub fn with_exit_on_panic(mut self, exit_on_panic: bool) -> Self {
+        self.exit_on_panic = exit_on_panic;
+        self
+    }
+
+    fn record_latency(&self, stage: Stage, started: std::time::Instant) {
+        if let Some(stats) = &self.latency {
+            stats.record(stage, started.elapsed());
+        }
+    }
+
+    /// The pool-side input checks this prover runs, for `POST /validate`.
+    pub fn input_checks(&self) -> InputChecks {
+        InputChecks {
+            pool_config: self.pool_config.clone(),
+            known_roots: self.known_roots.clone(),
+            spent_nullifiers: self.spent_nullifiers.clone(),
+            rpc_pool: self.rpc_pool.clone(),
+            rpc_breaker: self.rpc_breaker.clone(),
+            confirmed_roots_only: self.confirmed_roots_only,
         }
     }
 
-    /// Runs the batch processor loop, consuming from the mpsc channel.
+    /// Retries status updates with backoff and records terminal outcomes the
+    /// store couldn't persist to `recovery_log`.
+    pub fn with_status_recovery(mut self, retries: u32, recovery_log: RecoveryLog) -> Self {
+        self.status_retries = retries;
+        self.recovery_log = Some(recovery_log);
+        self
+    }
+
+    /// Retries a batch's initial `save_batch` up to `retries` extra times.
+    pub fn with_batch_save_retries(mut self, retries: u32) -> Self {
+        self.batch_save_retries = retries;
+        self
+    }
+
+    /// Retries the on-chain submission up to `retries` extra times when it
+    /// fails transiently (see `is_transient_submit_error`).
+    pub fn with_submit_retries(mut self, retries: u32) -> Self {
+        self.submit_retries = retries;
+        self
+    }
+
+    /// Checks each batch for kind/asset groups with fewer than `min` txs. In
+    /// strict mode their txs go back to `queue`; otherwise they are logged.
+    pub fn with_min_anon_set(mut self, min: usize, mode: AnonSetMode, queue: Arc<BatchQueue>) -> Self {
+        self.min_anon_set = min;
+        self.anon_set_requeue = (mode == AnonSetMode::Strict).then_some(queue);
+        self
+    }
+
+    /// Sets how buffered batches are handled when the channel closes.
+    pub fn with_drain_on_close(mut self, drain_on_close: bool) -> Self {
+        self.drain_on_close = drain_on_close;
+        self
+    }
+
+    /// Runs the batch processor loop, consuming from the mpsc channel until it
+    /// closes or shutdown reports the queue drained. A batch already being
+    /// handled always runs to completion first.
     pub async fn run(self, mut rx: mpsc::Receiver<ReadyBatch>) {
         info!("prover service started, waiting for batches");
-        while let Some(ready) = rx.recv().await {
-            let batch_id = ready.batch_id.clone();
-            info!(batch_id = %batch_id, tx_count = ready.transactions.len(), "processing batch");
+        loop {
+            if self.pause.is_paused() {
+                warn!(buffered = rx.len(), "prover paused, batches are buffering");
+                tokio::select! {
+                    _ = self.pause.wait_until_resumed() => info!(buffered = rx.len(), "prover resumed"),
+                    _ = self.shutdown.wait_until_drained() => break,
+                }
+            }
+            let ready = tokio::select! {
+                biased;
+                _ = self.shutdown.wait_until_drained() => None,
+                ready = rx.recv() => ready,
+            };
+            let Some(ready) = ready else {
+                break;
+            };
+            // A pause that landed while we were waiting on `recv` still
+            // applies: hold the batch unproven until resume, and treat it like
+            // a buffered batch if shutdown finishes first.
+            if self.pause.is_paused() {
+                warn!(batch_id = %ready.batch_id, "prover paused, holding received batch");
+                let resumed = tokio::select! {
+                    _ = self.pause.wait_until_resumed() => true,
+                    _ = self.shutdown.wait_until_drained() => false,
+                };
+                if !resumed {
+                    self.record_dropped(ready).await;
+                    break;
+                }
+                info!(batch_id = %ready.batch_id, "prover resumed");
+            }
+            let span = ready.span.clone();
+            self.handle_batch(ready).instrument(span).await;
+        }
 
-            if let Err(e) = self.process_batch(&batch_id, ready.transactions).await {
-                error!(batch_id = %batch_id, error = %e, "batch processing failed");
-                // Ensure batch is marked Failed on ANY error path, preventing
-                // batches stuck in "Proving" or "Submitting" forever.
-                if let Err(store_err) = self
-                    .store
-                    .update_status(
-                        &batch_id,
-                        BatchStatus::Failed,
-                        StatusUpdate {
-                            error: Some(e.to_string()),
-                            ..Default::default()
-                        },
-                    )
-                    .await
-                {
-                    error!(
-                        batch_id = %batch_id,
-                        original_error = %e,
-                        store_error = %store_err,
-                        "failed to mark batch as Failed (store unreachable)"
-                    );
+        // `recv` only returns `None` once the buffer is empty, but shutdown
+        // stops the loop as soon as the queue has drained, with flushed
+        // batches possibly still buffered. Never exit leaving them there. A
+        // prover paused by an operator doesn't prove them on the way out.
+        rx.close();
+        let drain = self.drain_on_close && !self.pause.is_paused();
+        let mut leftover = 0usize;
+        while let Ok(ready) = rx.try_recv() {
+            leftover += 1;
+            if drain {
+                let span = ready.span.clone();
+                self.handle_batch(ready).instrument(span).await;
+            } else {
+                self.record_dropped(ready).await;
+            }
+        }
+        if leftover > 0 {
+            warn!(leftover, drained = drain, "handled buffered batches after channel close");
+        }
+        warn!("prover service stopped");
+    }
+
+    async fn handle_batch(&self, ready: ReadyBatch) {
+        let Some(ready) = self.enforce_anon_set(ready).await else {
+            return;
+        };
+        let batch_id = ready.batch_id.clone();
+        info!(batch_id = %batch_id, asset_id = ?ready.asset_id, tx_count = ready.transactions.len(), "processing batch");
+        // The txs are already off the queue: if the batch can't be recorded,
+        // keep them in the dead-letter store rather than drop them.
+        let record = BatchRecord::new(batch_id.clone(), ready.transactions.len());
+        if let Err(e) = self.save_batch_with_retry(&batch_id, &record).await {
+            error!(batch_id = %batch_id, error = %e, "failed to save batch record");
+            let e = ProverError::Store(format!("initial batch save failed: {e}"));
+            self.record_dead_letter(&batch_id, &ready.transactions, ready.client_refs, &ready.recipients, e.to_string())
+                .await;
+            return;
+        }
+
+        // process_batch consumes the txs; keep a copy in case they need dead-lettering
+        let dead_letter_copy = self
+            .dead_letter
+            .then(|| (ready.transactions.clone(), ready.client_refs.clone(), ready.recipients.clone()));
+
+        if let Err(e) = self
+            .process_batch(
+                &batch_id,
+                ready.transactions,
+                ready.client_refs,
+                &ready.idempotency_keys,
+                ready.recipients,
+            )
+            .await
+        {
+            if let ProverError::Panic { stage, message } = &e {
+                error!(
+                    batch_id = %batch_id,
+                    stage,
+                    panic = %message,
+                    "batch processing panicked"
+                );
+                if let Some(m) = &self.metrics {
+                    m.record_panic(stage);
                 }
+            } else {
+                error!(batch_id = %batch_id, error = %e, "batch processing failed");
+            }
+            // Ensure batch is marked Failed on ANY error path, preventing
+            // batches stuck in "Proving" or "Submitting" forever.
+            let update = StatusUpdate {
+                error: Some(e.to_string()),
+                ..Default::default()
+            };
+            if let Err(store_err) = self
+                .update_status_with_retry(&batch_id, BatchStatus::Failed, update.clone())
+                .await
+            {
+                error!(
+                    batch_id = %batch_id,
+                    original_error = %e,
+                    store_error = %store_err,
+                    "failed to mark batch as Failed (store unreachable)"
+                );
+                self.record_recovery(&batch_id, BatchStatus::Failed, update, &store_err)
+                    .await;
+            }
+            if let Some((txs, client_refs, recipients)) = dead_letter_copy {
+                self.record_dead_letter(&batch_id, &txs, client_refs, &recipients, e.to_string()).await;
+            }
+            if self.exit_on_panic && matches!(e, ProverError::Panic { .. }) {
+                error!(batch_id = %batch_id, "exiting after prover panic (VM31_PROVER_EXIT_ON_PANIC)");
+                std::process::exit(1);
+            }
+        }
+    }
+
+    /// Checks the batch against `min_anon_set` before anything is recorded
+    /// for it. In strict mode txs in short groups are returned to the queue
+    /// and the rest proven; `None` when no tx is left.
+    async fn enforce_anon_set(&self, mut ready: ReadyBatch) -> Option<ReadyBatch> {
+        let thin = thin_anon_sets(&ready.transactions, self.min_anon_set);
+        if thin.is_empty() {
+            return Some(ready);
+        }
+        for group in &thin {
+            warn!(
+                batch_id = %ready.batch_id,
+                kind = ?group.kind,
+                asset_id = group.asset_id,
+                count = group.count,
+                min = self.min_anon_set,
+                "batch below minimum anonymity set"
+            );
+        }
+        let Some(queue) = &self.anon_set_requeue else {
+            return Some(ready);
+        };
+        let is_thin = |tx: &PendingTx| {
+            let (kind, asset_id) = (TxKind::of(tx), asset_of(tx));
+            thin.iter().any(|g| g.kind == kind && g.asset_id == asset_id)
+        };
+
+        // Client refs, keys, submitters and recipients are index-aligned with the txs
+        let client_refs = std::mem::take(&mut ready.client_refs).into_iter().chain(std::iter::repeat(None));
+        let keys = std::mem::take(&mut ready.idempotency_keys).into_iter().chain(std::iter::repeat(None));
+        let submitters = std::mem::take(&mut ready.submitters).into_iter().chain(std::iter::repeat(None));
+        let recipients = std::mem::take(&mut ready.recipients).into_iter().chain(std::iter::repeat(None));
+        let mut declined = Vec::new();
+        let txs = std::mem::take(&mut ready.transactions);
+        let aligned = txs.into_iter().zip(client_refs).zip(keys).zip(submitters).zip(recipients);
+        for ((((tx, client_ref), key), submitter), recipient) in aligned {
+            if is_thin(&tx) {
+                declined.push((tx, client_ref, key, submitter, recipient));
+            } else {
+                ready.transactions.push(tx);
+                ready.client_refs.push(client_ref);
+                ready.idempotency_keys.push(key);
+                ready.submitters.push(submitter);
+                ready.recipients.push(recipient);
+            }
+        }
+        let requeued = declined.len();
+        let pending = queue.requeue(declined).await;
+        warn!(
+            batch_id = %ready.batch_id,
+            requeued,
+            kept = ready.transactions.len(),
+            pending,
+            "txs below minimum anonymity set returned to the queue (VM31_ANON_SET_MODE=strict)"
+        );
+        (!ready.transactions.is_empty()).then_some(ready)
+    }
+
+    /// Stores a failed batch's transactions under `dlq:{batch_id}` so an
+    /// admin can inspect and replay them.
+    async fn record_dead_letter(
+        &self,
+        batch_id: &str,
+        txs: &[PendingTx],
+        client_refs: Vec<Option<String>>,
+        recipients: &[Option<WithdrawalRecipient>],
+        reason: String,
+    ) {
+        let entry = DeadLetter::new(batch_id, reason, now_epoch(), txs, client_refs, recipients);
+        match self.store.save_dead_letter(entry).await {
+            Ok(()) => warn!(batch_id = %batch_id, tx_count = txs.len(), "failed batch moved to the dead-letter queue"),
+            Err(e) => error!(
+                batch_id = %batch_id,
+                error = %e,
+                "failed to dead-letter batch; its transactions are lost"
+            ),
+        }
+    }
+
+    /// Records a batch that won't be proven as Failed so clients polling
+    /// `/batch/{id}` learn to resubmit instead of waiting forever. With the
+    /// dead-letter queue enabled its transactions are kept there for replay.
+    async fn record_dropped(&self, ready: ReadyBatch) {
+        let mut record = BatchRecord::new(ready.batch_id.clone(), ready.transactions.len());
+        record.status = BatchStatus::Failed;
+        record.error = Some("dropped during shutdown; resubmit".into());
+        self.record_idempotency(&ready.batch_id, &ready.idempotency_keys)
+            .await;
+        if self.dead_letter {
+            let reason = "dropped during shutdown".to_string();
+            self.record_dead_letter(&ready.batch_id, &ready.transactions, ready.client_refs, &ready.recipients, reason)
+                .await;
+        }
+        if let Err(e) = self.store.save_batch(&ready.batch_id, &record).await {
+            error!(batch_id = %ready.batch_id, error = %e, "failed to record dropped batch");
+        } else {
+            warn!(batch_id = %ready.batch_id, tx_count = ready.transactions.len(), "batch dropped during shutdown");
+        }
+    }
+
+    /// Points each submission's idempotency entry at its batch, so duplicates
+    /// and cancellation attempts can report where the tx went.
+    async fn record_idempotency(&self, batch_id: &str, keys: &[Option<String>]) {
+        for key in keys.iter().flatten() {
+            if let Err(e) = self.store.update_result(key, batch_id).await {
+                warn!(batch_id = %batch_id, error = %e, "failed to record batch id for submission");
             }
         }
-        warn!("prover service channel closed, shutting down");
     }
 
     async fn process_batch(
         &self,
         batch_id: &str,
-        txs: Vec<PendingTx>,
+        mut txs: Vec<PendingTx>,
+        mut client_refs: Vec<Option<String>>,
+        idempotency_keys: &[Option<String>],
+        mut recipients: Vec<Option<WithdrawalRecipient>>,
     ) -> Result<(), ProverError> {
+        let batch_started = std::time::Instant::now();
         let tx_count = txs.len();
 
-        // Save initial batch record
-        let record = BatchRecord::new(batch_id.to_string(), tx_count);
-        self.store
-            .save_batch(batch_id, &record)
-            .await
-            .map_err(|e| ProverError::Store(e.to_string()))?;
+        // The initial batch record was saved by handle_batch
+        for scoped_ref in client_refs.iter().flatten() {
+            self.store.index_client_ref(scoped_ref, batch_id);
+        }
+        self.record_idempotency(batch_id, idempotency_keys).await;
 
         // ── Step 1: Validate inputs (PoolClient calls are synchronous RPC) ──
-        {
-            let pool_cfg = self.pool_config.clone();
-            let txs_ref = txs.clone();
-            tokio::task::spawn_blocking(move || {
-                let pool_client = PoolClient::new(pool_cfg);
-                Self::validate_inputs_blocking(&pool_client, &txs_ref)
-            })
-                .await
-                .map_err(|e| ProverError::Validation(format!("task join error: {e}")))?
-                ?;
+        let stage_started = std::time::Instant::now();
+        match telemetry::in_stage(BatchStage::Validate, tx_count, self.input_checks().run(txs.clone())).await {
+            InputCheck::Passed => {}
+            InputCheck::Rejected { reason, .. } => return Err(ProverError::Validation(reason)),
+            InputCheck::Unavailable(e) => return Err(e),
+        }
+        self.record_latency(Stage::Validation, stage_started);
+
+        // A replay of a recently proven batch reuses its proof; line the
+        // batch up with the order that proof was made in.
+        let mut tx_keys = Vec::new();
+        let mut reused_proof = false;
+        if let Some(cache) = &self.proof_cache {
+            tx_keys = proof_cache::tx_keys(&txs);
+            if let Some(hit) = cache.lookup(&tx_keys) {
+                txs = hit.reorder(txs);
+                client_refs = hit.reorder(client_refs);
+                recipients.resize(txs.len(), None);
+                recipients = hit.reorder(recipients);
+                tx_keys = hit.reorder(tx_keys);
+                reused_proof = true;
+            }
         }
 
         // ── Step 2: Extract withdrawal recipients + deposit note info before proving ──
-        let withdrawal_recipients = Self::extract_withdrawal_recipients(&txs);
-        let deposit_notes = Self::extract_deposit_notes(&txs);
+        let withdrawal_recipients = Self::extract_withdrawal_recipients(&txs, &recipients)?;
+        let withdrawal_assets = Self::extract_withdrawal_assets(&txs);
+        let deposit_notes = Self::extract_deposit_notes(&txs, &client_refs);
+        let batch_nullifiers = match self.spent_nullifiers {
+            Some(_) => Self::extract_nullifiers(&txs),
+            None => Vec::new(),
+        };
 
         // Capture tx kinds before the proving closure moves txs.
         // Used in Step 7 to map ProvenTransaction.new_commitments → deposit digests.
@@ -161,15 +898,19 @@ impl ProverService {
             .collect();
 
         // Update status to Proving
-        self.store
-            .update_status(batch_id, BatchStatus::Proving, StatusUpdate::default())
+        self.update_status_with_retry(batch_id, BatchStatus::Proving, StatusUpdate::default())
             .await
             .map_err(|e| ProverError::Store(e.to_string()))?;
 
         // ── Step 3: Build + Prove via TxBuilder (CPU-bound, offload) ────────
         // TxBuilder::prove() handles witness construction AND STARK proving.
-        info!(batch_id = %batch_id, "starting STARK proof generation");
-        let proven = {
+        if reused_proof {
+            info!(batch_id = %batch_id, "reusing cached proof of an identical batch");
+        } else {
+            info!(batch_id = %batch_id, "starting STARK proof generation");
+        }
+        let stage_started = std::time::Instant::now();
+        let prove = move || async move {
             tokio::task::spawn_blocking(move || {
                 let mut builder = TxBuilder::new();
                 for tx in txs {
@@ -179,8 +920,15 @@ impl ProverService {
                             asset_id,
                             recipient_pubkey,
                             recipient_viewing_key,
+                            blinding,
                         } => {
-                            builder.deposit(amount, asset_id, recipient_pubkey, recipient_viewing_key)?;
+                            builder.deposit(
+                                amount,
+                                asset_id,
+                                recipient_pubkey,
+                                recipient_viewing_key,
+                                blinding,
+                            )?;
                         }
                         PendingTx::Withdraw {
                             amount,
@@ -225,10 +973,23 @@ impl ProverService {
                 builder.prove()
             })
             .await
-            .map_err(|e| ProverError::Proving(format!("task join error: {e}")))?
-            .map_err(|e| ProverError::Proving(e.to_string()))?
+            .map_err(|e| join_failure("proving", e, ProverError::Proving))?
+            .map_err(|e| ProverError::Proving(e.to_string()))
         };
-        info!(batch_id = %batch_id, "proof generation complete");
+        let proven = telemetry::in_stage(BatchStage::Prove, tx_count, async {
+            match &self.proof_cache {
+                Some(cache) => cache.get_or_prove(tx_keys, prove).await,
+                None => prove().await.map(Arc::new),
+            }
+        })
+        .await?;
+        if !reused_proof {
+            self.record_latency(Stage::Proving, stage_started);
+            if let Some(m) = &self.metrics {
+                m.record_proving(stage_started.elapsed());
+            }
+            info!(batch_id = %batch_id, "proof generation complete");
+        }
 
         // Compute proof hash for on-chain binding
         let proof_hash_m31 = hash_batch_public_inputs_for_cairo(&proven.proof.public_inputs)
@@ -241,34 +1002,43 @@ impl ProverService {
                 .collect::<String>()
         );
 
-        self.store
-            .update_status(
-                batch_id,
-                BatchStatus::Submitting,
-                StatusUpdate {
-                    proof_hash: Some(proof_hash.clone()),
-                    ..Default::default()
-                },
-            )
-            .await
-            .map_err(|e| ProverError::Store(e.to_string()))?;
+        self.update_status_with_retry(
+            batch_id,
+            BatchStatus::Submitting,
+            StatusUpdate {
+                proof_hash: Some(proof_hash.clone()),
+                ..Default::default()
+            },
+        )
+        .await
+        .map_err(|e| ProverError::Store(e.to_string()))?;
 
         // ── Step 4: On-chain submission (5-step idempotent flow, blocking sncast) ─
+        // Idempotent, so a transient failure re-runs it with the same inputs.
         info!(batch_id = %batch_id, "submitting to chain");
-        let outcome: RelayOutcome = {
-            let backend = self.backend.clone();
-            let pub_inputs = proven.proof.public_inputs.clone();
-            let ph = proof_hash.clone();
-            let wr = withdrawal_recipients.clone();
-            let rc = self.relayer_config.clone();
-            tokio::task::spawn_blocking(move || {
-                run_vm31_relayer_flow(&backend, &pub_inputs, &ph, &wr, &rc)
-            })
-            .await
-            .map_err(|e| ProverError::Relayer(format!("task join error: {e}")))?
-            .map_err(|e| ProverError::Relayer(format!("{e}")))?
-        };
+        let stage_started = std::time::Instant::now();
+        let outcome: RelayOutcome = telemetry::in_stage(
+            BatchStage::Submit,
+            tx_count,
+            retry_submission(batch_id, self.submit_retries, SUBMIT_RETRY_BASE, || {
+                let backend = self.backend.clone();
+                let pub_inputs = proven.proof.public_inputs.clone();
+                let ph = proof_hash.clone();
+                let wr = withdrawal_recipients.clone();
+                let rc = self.relayer_config.clone();
+                async move {
+                    tokio::task::spawn_blocking(move || {
+                        run_vm31_relayer_flow(&backend, &pub_inputs, &ph, &wr, &rc)
+                    })
+                    .await
+                    .map_err(|e| join_failure("submission", e, ProverError::Relayer))?
+                    .map_err(|e| ProverError::Relayer(format!("{e}")))
+                }
+            }),
+        )
+        .await?;
 
+        self.record_latency(Stage::Submission, stage_started);
         info!(
             batch_id = %batch_id,
             chain_ref = %opaque_ref(&outcome.batch_id),
@@ -283,49 +1053,77 @@ impl ProverService {
                 has_withdrawals = true,
                 "bridging withdrawals"
             );
-            for (idx, _) in withdrawal_recipients.payout.iter().enumerate() {
-                if let Err(e) = self.bridge.bridge_withdrawal(
-                    &outcome.batch_id,
-                    idx as u32,
-                ).await {
-                    // Non-fatal: log and continue. Bridge is idempotent and can be retried.
-                    warn!(
-                        batch_id = %batch_id,
-                        wd_ref = %opaque_ref(&format!("{}:{}", outcome.batch_id, idx)),
-                        error = %e,
-                        "bridge call failed (idempotent, can retry)"
-                    );
+            let payouts = withdrawal_recipients.payout.len();
+            telemetry::in_stage(BatchStage::Bridge, payouts, async {
+                for idx in 0..payouts {
+                    let asset_id = withdrawal_assets.get(idx).copied().unwrap_or_default();
+                    if let Err(e) = self.bridge.bridge_withdrawal(
+                        &outcome.batch_id,
+                        idx as u32,
+                        asset_id,
+                    ).await {
+                        // Non-fatal: log and continue. Bridge is idempotent and can be retried.
+                        warn!(
+                            batch_id = %batch_id,
+                            wd_ref = %opaque_ref(&format!("{}:{}", outcome.batch_id, idx)),
+                            error = %e,
+                            "bridge call failed (idempotent, can retry)"
+                        );
+                    }
                 }
-            }
+            })
+            .await;
         }
 
         // ── Step 6: Finalize record ─────────────────────────────────────────
-        self.store
-            .update_status(
-                batch_id,
-                BatchStatus::Finalized,
-                StatusUpdate {
-                    batch_id_onchain: Some(outcome.batch_id),
-                    tx_hash: Some(outcome.proof_hash),
-                    ..Default::default()
-                },
-            )
+        // The batch is already on-chain: a store failure here must not turn
+        // into a Failed record. Persist the true outcome for reconciliation.
+        let finalized = StatusUpdate {
+            proof_hash: Some(proof_hash),
+            batch_id_onchain: Some(outcome.batch_id),
+            tx_hash: Some(outcome.proof_hash),
+            ..Default::default()
+        };
+        if let Err(e) = self
+            .update_status_with_retry(batch_id, BatchStatus::Finalized, finalized.clone())
             .await
-            .map_err(|e| ProverError::Store(e.to_string()))?;
+        {
+            error!(
+                batch_id = %batch_id,
+                error = %e,
+                "failed to record Finalized status; writing to recovery log"
+            );
+            self.record_recovery(batch_id, BatchStatus::Finalized, finalized, &e)
+                .await;
+        }
+        // The batch is on-chain, so its input nullifiers are now spent
+        if let Some(cache) = &self.spent_nullifiers {
+            for nullifier in batch_nullifiers {
+                cache.insert(nullifier);
+            }
+        }
 
         // ── Step 7: Store note records for deposit notes ──────────────────
         // Extract Poseidon2-M31 commitment digests from the proven transaction.
         // new_commitments contains (NoteCommitment, Note) for every output note
-        // in the batch. We map them to deposits using the tx_kinds ordering.
-        let deposit_digests = Self::extract_deposit_digests(&tx_kinds, &proven);
+        // in the batch. We map them to deposits using the tx_kinds ordering,
+        // checked against each output's note (see `CommitmentMapping`).
+        let deposit_digests = Self::extract_deposit_digests(
+            self.commitment_mapping,
+            &tx_kinds,
+            &deposit_notes,
+            &proven.new_commitments,
+        );
 
         let now = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .unwrap_or_default()
             .as_secs();
         for (idx, note_info) in deposit_notes.iter().enumerate() {
-            let commitment = note_info.commitment_key();
-            let digest = deposit_digests.get(idx).copied();
+            let digest = deposit_digests.get(idx).copied().flatten();
+            let Some(commitment) = self.note_key(note_info.commitment_key(), batch_id).await else {
+                continue;
+            };
             if digest.is_some() {
                 info!(
                     batch_id = %batch_id,
@@ -344,6 +1142,9 @@ impl ProverService {
                 created_at: now,
                 commitment_digest: digest,
                 note_index_in_batch: idx,
+                client_ref: note_info.client_ref.clone(),
+                backfill_attempts: 0,
+                last_backfill_attempt: 0,
             };
             if let Err(e) = self.store.save_note(&commitment, &record).await {
                 warn!(
@@ -352,16 +1153,174 @@ impl ProverService {
                     error = %e,
                     "failed to save note record (non-fatal)"
                 );
+                continue;
+            }
+            if self.owner_index {
+                if let Err(e) = self.store.index_note_owner(&note_info.owner_pubkey, &commitment).await {
+                    warn!(
+                        batch_id = %batch_id,
+                        note_ref = %opaque_ref(&commitment),
+                        error = %e,
+                        "failed to index note owner (non-fatal)"
+                    );
+                }
             }
         }
 
+        self.record_latency(Stage::Total, batch_started);
         info!(batch_id = %batch_id, "batch finalized");
         Ok(())
     }
 
+    /// Store key for a new deposit note of `batch_id`, or `None` to skip it,
+    /// when `commitment` is already tracked for a different batch. Saving
+    /// under the plain key would overwrite that note's batch and path.
+    async fn note_key(&self, commitment: String, batch_id: &str) -> Option<String> {
+        // A read failure falls through to the save, which reports it
+        let Ok(Some(existing)) = self.store.get_note(&commitment).await else {
+            return Some(commitment);
+        };
+        if existing.batch_id == batch_id {
+            return Some(commitment);
+        }
+        match self.duplicate_notes {
+            DuplicateNotePolicy::Reject => {
+                warn!(
+                    batch_id = %batch_id,
+                    note_ref = %opaque_ref(&commitment),
+                    "note already tracked for another batch; not tracking the new one"
+                );
+                None
+            }
+            DuplicateNotePolicy::Disambiguate => {
+                warn!(
+                    batch_id = %batch_id,
+                    note_ref = %opaque_ref(&commitment),
+                    "note already tracked for another batch; tracking under a batch-qualified key"
+                );
+                Some(format!("{commitment}-{batch_id}"))
+            }
+        }
+    }
+
+    /// Saves a new batch record, retrying transient store failures with
+    /// the same backoff as status updates.
+    async fn save_batch_with_retry(&self, batch_id: &str, record: &BatchRecord) -> Result<(), StoreError> {
+        let mut attempt = 0u32;
+        loop {
+            match self.store.save_batch(batch_id, record).await {
+                Ok(()) => return Ok(()),
+                Err(e) if attempt < self.batch_save_retries => {
+                    let delay = retry_delay(attempt);
+                    attempt += 1;
+                    warn!(batch_id = %batch_id, attempt, error = %e, "batch save failed, retrying in {delay:?}");
+                    tokio::time::sleep(delay).await;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Updates batch status, retrying transient store failures with
+    /// exponential backoff (see `retry_delay`).
+    async fn update_status_with_retry(
+        &self,
+        batch_id: &str,
+        status: BatchStatus,
+        update: StatusUpdate,
+    ) -> Result<(), StoreError> {
+        let mut attempt = 0u32;
+        loop {
+            match self
+                .store
+                .update_status(batch_id, status.clone(), update.clone())
+                .await
+            {
+                Ok(()) => return Ok(()),
+                Err(e) if attempt < self.status_retries => {
+                    let delay = retry_delay(attempt);
+                    attempt += 1;
+                    warn!(
+                        batch_id = %batch_id,
+                        status = ?status,
+                        attempt,
+                        error = %e,
+                        "status update failed, retrying in {delay:?}"
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    async fn record_recovery(
+        &self,
+        batch_id: &str,
+        status: BatchStatus,
+        update: StatusUpdate,
+        store_error: &StoreError,
+    ) {
+        let Some(log) = &self.recovery_log else {
+            return;
+        };
+        log.append(RecoveryEntry {
+            batch_id: batch_id.to_string(),
+            status,
+            proof_hash: update.proof_hash,
+            batch_id_onchain: update.batch_id_onchain,
+            tx_hash: update.tx_hash,
+            error: update.error,
+            store_error: store_error.to_string(),
+            recorded_at: 0,
+        })
+        .await;
+    }
+
     /// Validates nullifiers and Merkle roots against the pool contract.
     /// This is a blocking function (synchronous RPC calls) — must run in spawn_blocking.
-    fn validate_inputs_blocking(pool_client: &PoolClient, txs: &[PendingTx]) -> Result<(), ProverError> {
+    /// Roots already verified by the local tree sync skip the `is_known_root` RPC;
+    /// the contract remains the authority for anything not known locally.
+    /// With `confirmed_roots_only`, `known_roots` is the only authority.
+    /// Likewise nullifiers in `spent_nullifiers` are rejected without RPC.
+    /// `rpc_ok` is cleared when an RPC call itself fails (as opposed to the
+    /// input being rejected), for endpoint health tracking.
+    fn validate_inputs_blocking(
+        pool_client: &PoolClient,
+        known_roots: Option<&KnownRoots>,
+        confirmed_roots_only: bool,
+        spent_nullifiers: Option<&SpentNullifiers>,
+        txs: &[PendingTx],
+        rpc_ok: &std::cell::Cell<bool>,
+    ) -> Result<(), ProverError> {
+        let rpc_failed = |check: &str, e: &dyn std::fmt::Display| {
+            rpc_ok.set(false);
+            ProverError::Validation(format!("{check}: {e}"))
+        };
+        let root_known = |root: &[M31; 8]| {
+            Self::root_known(known_roots, confirmed_roots_only, root, || {
+                pool_client
+                    .is_known_root(root)
+                    .map_err(|e| rpc_failed("root check", &e))
+            })
+        };
+        let spent = |nullifier: &[M31; 8]| {
+            Self::nullifier_spent(spent_nullifiers, nullifier, || {
+                pool_client
+                    .is_nullifier_spent(nullifier)
+                    .map_err(|e| rpc_failed("nullifier check", &e))
+            })
+        };
+        Self::check_inputs(txs, root_known, spent)
+    }
+
+    /// Rejects withdrawals and transfers whose Merkle root is unknown or whose
+    /// input nullifiers are spent, as answered by `root_known` and `spent`.
+    fn check_inputs(
+        txs: &[PendingTx],
+        root_known: impl Fn(&[M31; 8]) -> Result<bool, ProverError>,
+        spent: impl Fn(&[M31; 8]) -> Result<bool, ProverError>,
+    ) -> Result<(), ProverError> {
         for tx in txs {
             match tx {
                 PendingTx::Withdraw {
@@ -370,20 +1329,17 @@ impl ProverService {
                     spending_key,
                     ..
                 } => {
-                    if !pool_client
-                        .is_known_root(merkle_root)
-                        .map_err(|e| ProverError::Validation(format!("root check: {e}")))?
-                    {
-                        return Err(ProverError::Validation(
-                            "unknown Merkle root in withdrawal".into(),
-                        ));
+                    if !root_known(merkle_root)? {
+                        return Err(ProverError::InputRejected {
+                            check: CheckFailure::UnknownRoot,
+                            reason: "unknown Merkle root in withdrawal".into(),
+                        });
                     }
-                    let nullifier = note.nullifier(spending_key);
-                    if pool_client
-                        .is_nullifier_spent(&nullifier)
-                        .map_err(|e| ProverError::Validation(format!("nullifier check: {e}")))?
-                    {
-                        return Err(ProverError::Validation("nullifier already spent".into()));
+                    if spent(&note.nullifier(spending_key))? {
+                        return Err(ProverError::InputRejected {
+                            check: CheckFailure::NullifierSpent,
+                            reason: "nullifier already spent".into(),
+                        });
                     }
                 }
                 PendingTx::Transfer {
@@ -391,25 +1347,18 @@ impl ProverService {
                     input_notes,
                     ..
                 } => {
-                    if !pool_client
-                        .is_known_root(merkle_root)
-                        .map_err(|e| ProverError::Validation(format!("root check: {e}")))?
-                    {
-                        return Err(ProverError::Validation(
-                            "unknown Merkle root in transfer".into(),
-                        ));
+                    if !root_known(merkle_root)? {
+                        return Err(ProverError::InputRejected {
+                            check: CheckFailure::UnknownRoot,
+                            reason: "unknown Merkle root in transfer".into(),
+                        });
                     }
                     for (note, sk, _) in input_notes {
-                        let nullifier = note.nullifier(sk);
-                        if pool_client
-                            .is_nullifier_spent(&nullifier)
-                            .map_err(|e| {
-                                ProverError::Validation(format!("nullifier check: {e}"))
-                            })?
-                        {
-                            return Err(ProverError::Validation(
-                                "nullifier already spent in transfer".into(),
-                            ));
+                        if spent(&note.nullifier(sk))? {
+                            return Err(ProverError::InputRejected {
+                                check: CheckFailure::NullifierSpent,
+                                reason: "nullifier already spent in transfer".into(),
+                            });
                         }
                     }
                 }
@@ -419,48 +1368,160 @@ impl ProverService {
         Ok(())
     }
 
-    /// Maps ProvenTransaction.new_commitments to deposit-only digests using tx ordering.
+    /// Answers from `cache` when it holds the root. Otherwise asks `rpc`,
+    /// unless `confirmed_only`, in which case the root is unknown. A root the
+    /// RPC confirms is cached; unknown answers never are.
+    fn root_known(
+        cache: Option<&KnownRoots>,
+        confirmed_only: bool,
+        root: &[M31; 8],
+        rpc: impl FnOnce() -> Result<bool, ProverError>,
+    ) -> Result<bool, ProverError> {
+        if cache.is_some_and(|c| c.contains(&root.map(|m| m.0))) {
+            return Ok(true);
+        }
+        if confirmed_only {
+            return Ok(false);
+        }
+        let known = rpc()?;
+        if let (true, Some(cache)) = (known, cache) {
+            cache.insert(root.map(|m| m.0));
+        }
+        Ok(known)
+    }
+
+    /// Answers from `cache` when the nullifier is known spent, otherwise asks
+    /// `rpc` and caches a spent answer. Unspent answers are never cached.
+    fn nullifier_spent(
+        cache: Option<&SpentNullifiers>,
+        nullifier: &[M31; 8],
+        rpc: impl FnOnce() -> Result<bool, ProverError>,
+    ) -> Result<bool, ProverError> {
+        let key = nullifier.map(|m| m.0);
+        if cache.is_some_and(|c| c.contains(&key)) {
+            return Ok(true);
+        }
+        let spent = rpc()?;
+        if spent {
+            if let Some(c) = cache {
+                c.insert(key);
+            }
+        }
+        Ok(spent)
+    }
+
+    /// Input nullifiers of every withdrawal and transfer in `txs`.
+    fn extract_nullifiers(txs: &[PendingTx]) -> Vec<[u32; 8]> {
+        let mut out = Vec::new();
+        for tx in txs {
+            match tx {
+                PendingTx::Withdraw { note, spending_key, .. } => {
+                    out.push(note.nullifier(spending_key).map(|m| m.0));
+                }
+                PendingTx::Transfer { input_notes, .. } => {
+                    out.extend(input_notes.iter().map(|(note, sk, _)| note.nullifier(sk).map(|m| m.0)));
+                }
+                PendingTx::Deposit { .. } => {}
+            }
+        }
+        out
+    }
+
+    /// Maps `ProvenTransaction.new_commitments` to one digest per deposit
+    /// (aligned with `deposits`; `None` where no digest can be trusted).
     ///
     /// Each tx type produces a known number of output commitments:
     /// - Deposit: 1 (the shielded note)
     /// - Withdraw: 0
     /// - Transfer: 2 (recipient + change)
     ///
-    /// By replaying the tx_kinds, we can extract exactly the deposit commitments.
-    fn extract_deposit_digests(tx_kinds: &[u8], proven: &ProvenTransaction) -> Vec<[u32; 8]> {
-        let mut digests = Vec::new();
-        let mut ci = 0usize; // commitment index into proven.new_commitments
+    /// Replaying `tx_kinds` gives each deposit's expected position. If the
+    /// output count doesn't match that replay, positions can't be trusted.
+    /// In `ByNote` mode each output's note must also match the deposit
+    /// (owner, asset, amount, blinding). A mismatched deposit takes the only unclaimed
+    /// matching output, or gets `None` when the match is missing or ambiguous.
+    fn extract_deposit_digests(
+        mapping: CommitmentMapping,
+        tx_kinds: &[u8],
+        deposits: &[DepositNoteInfo],
+        outputs: &[(NoteCommitment, Note)],
+    ) -> Vec<Option<[u32; 8]>> {
+        let mut positions = Vec::with_capacity(deposits.len());
+        let mut expected = 0usize;
         for &kind in tx_kinds {
             match kind {
                 0 => {
-                    // Deposit: 1 new commitment
-                    if ci < proven.new_commitments.len() {
-                        let (ref c, _) = proven.new_commitments[ci];
-                        digests.push([
-                            c[0].0, c[1].0, c[2].0, c[3].0,
-                            c[4].0, c[5].0, c[6].0, c[7].0,
-                        ]);
-                    }
-                    ci += 1;
+                    positions.push(expected);
+                    expected += 1;
                 }
                 1 => {} // Withdraw: 0 new commitments
-                2 => {
-                    ci += 2; // Transfer: 2 new commitments (recipient + change)
-                }
+                2 => expected += 2, // Transfer: 2 new commitments (recipient + change)
                 _ => {}
             }
         }
-        digests
+        let counts_match = expected == outputs.len() && positions.len() == deposits.len();
+        if !counts_match {
+            error!(
+                expected_outputs = expected,
+                actual_outputs = outputs.len(),
+                deposits = deposits.len(),
+                "proven output commitments don't match the batch's tx kinds"
+            );
+        }
+        let digest = |i: usize| outputs[i].0.map(|m| m.0);
+
+        match mapping {
+            CommitmentMapping::Positional => {
+                if !counts_match {
+                    return vec![None; deposits.len()];
+                }
+                positions.into_iter().map(|i| Some(digest(i))).collect()
+            }
+            CommitmentMapping::ByNote => {
+                let mut claimed = vec![false; outputs.len()];
+                let mut digests = vec![None; deposits.len()];
+                let mut unresolved = Vec::new();
+                for (k, deposit) in deposits.iter().enumerate() {
+                    match positions.get(k) {
+                        Some(&i) if counts_match && deposit.matches(&outputs[i].1) && !claimed[i] => {
+                            claimed[i] = true;
+                            digests[k] = Some(digest(i));
+                        }
+                        _ => unresolved.push(k),
+                    }
+                }
+                for k in unresolved {
+                    let mut candidates = (0..outputs.len())
+                        .filter(|&i| !claimed[i] && deposits[k].matches(&outputs[i].1));
+                    match (candidates.next(), candidates.next()) {
+                        (Some(i), None) => {
+                            warn!(deposit_index = k, output_index = i, "deposit output found out of position");
+                            claimed[i] = true;
+                            digests[k] = Some(digest(i));
+                        }
+                        (None, _) => warn!(deposit_index = k, "no proven output matches deposit"),
+                        (Some(_), Some(_)) => {
+                            warn!(deposit_index = k, "several proven outputs match deposit; leaving digest unset")
+                        }
+                    }
+                }
+                digests
+            }
+        }
     }
 
     /// Extracts deposit note info before the proving step (which moves txs).
-    fn extract_deposit_notes(txs: &[PendingTx]) -> Vec<DepositNoteInfo> {
+    fn extract_deposit_notes(
+        txs: &[PendingTx],
+        client_refs: &[Option<String>],
+    ) -> Vec<DepositNoteInfo> {
         let mut notes = Vec::new();
-        for tx in txs {
+        for (i, tx) in txs.iter().enumerate() {
             if let PendingTx::Deposit {
                 amount,
                 asset_id,
                 recipient_pubkey,
+                blinding,
                 ..
             } = tx
             {
@@ -473,59 +1534,909 @@ impl ProverService {
                     ],
                     asset_id: *asset_id,
                     amount: *amount,
-                    blinding: [0, 0, 0, 0], // Blinding is generated server-side by TxBuilder
+                    blinding: blinding.map(|m| m.0),
+                    client_ref: client_refs.get(i).cloned().flatten(),
                 });
             }
         }
         notes
     }
 
-    /// Extracts withdrawal recipients from the pending transactions.
-    /// Called before proving since we need this info for the relay flow.
-    fn extract_withdrawal_recipients(txs: &[PendingTx]) -> WithdrawalRecipients {
+    /// Asset ID of each withdrawal, in the same order as the withdrawal recipients.
+    fn extract_withdrawal_assets(txs: &[PendingTx]) -> Vec<u32> {
+        txs.iter()
+            .filter_map(|tx| match tx {
+                PendingTx::Withdraw { asset_id, .. } => Some(*asset_id),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Collects each withdrawal's submitted payout and credit addresses, in
+    /// withdrawal order. Called before proving since the relay flow needs
+    /// them: it recomputes each binding digest (payout, credit, asset,
+    /// amount, idx; see relayer.rs compute_withdrawal_binding_digest()) from
+    /// these addresses and checks it against the proven `withdrawal_binding`,
+    /// so a withdrawal can't be paid anywhere its owner didn't bind.
+    ///
+    /// `recipients` is index-aligned with `txs`. A withdrawal without one
+    /// (queued before recipients were required) fails the batch here rather
+    /// than after proving.
+    fn extract_withdrawal_recipients(
+        txs: &[PendingTx],
+        recipients: &[Option<WithdrawalRecipient>],
+    ) -> Result<WithdrawalRecipients, ProverError> {
         let mut payout_recipients = Vec::new();
         let mut credit_recipients = Vec::new();
 
-        for tx in txs {
-            if let PendingTx::Withdraw {
-                withdrawal_binding, ..
-            } = tx
-            {
-                // The binding digest encodes (payout, credit, asset, amount, idx)
-                // See relayer.rs compute_withdrawal_binding_digest()
-                let binding_hex = format!(
-                    "0x{}",
-                    withdrawal_binding
-                        .iter()
-                        .map(|m| format!("{:08x}", m.0))
-                        .collect::<String>()
-                );
-                payout_recipients.push(binding_hex.clone());
-                credit_recipients.push(binding_hex);
+        for (i, tx) in txs.iter().enumerate() {
+            if let PendingTx::Withdraw { .. } = tx {
+                let recipient = recipients.get(i).and_then(Option::as_ref).ok_or_else(|| {
+                    ProverError::Validation(format!("withdrawal at batch index {i} has no payout recipient"))
+                })?;
+                payout_recipients.push(recipient.payout.clone());
+                credit_recipients.push(recipient.credit.clone());
             }
         }
 
-        WithdrawalRecipients::new(payout_recipients, credit_recipients)
+        Ok(WithdrawalRecipients::new(payout_recipients, credit_recipients))
     }
 }
 
 #[derive(Debug)]
 pub enum ProverError {
     Validation(String),
+    /// `check_inputs` rejected a tx; `check` is the client-safe code for it.
+    InputRejected { check: CheckFailure, reason: String },
     Proving(String),
     Relayer(String),
     Store(String),
+    /// A blocking stage panicked. `message` is the panic payload; it is
+    /// logged but kept out of the client-visible batch error.
+    Panic { stage: &'static str, message: String },
+    /// Input checks were skipped because the pool RPC circuit is open.
+    RpcUnavailable { retry_after_secs: u64 },
+}
+
+/// Maps a `spawn_blocking` join failure, separating panics from cancellation.
+fn join_failure(
+    stage: &'static str,
+    e: tokio::task::JoinError,
+    other: fn(String) -> ProverError,
+) -> ProverError {
+    if e.is_panic() {
+        ProverError::Panic {
+            stage,
+            message: panic_message(e.into_panic()),
+        }
+    } else {
+        other(format!("task join error: {e}"))
+    }
+}
+
+fn panic_message(payload: Box<dyn std::any::Any + Send>) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "non-string panic payload".into()
+    }
 }
 
 impl std::fmt::Display for ProverError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            ProverError::Validation(msg) => write!(f, "validation: {msg}"),
+            ProverError::Validation(msg) | ProverError::InputRejected { reason: msg, .. } => {
+                write!(f, "validation: {msg}")
+            }
             ProverError::Proving(msg) => write!(f, "proving: {msg}"),
             ProverError::Relayer(msg) => write!(f, "relayer: {msg}"),
             ProverError::Store(msg) => write!(f, "store: {msg}"),
+            // Once the proof is built the batch may have reached the chain
+            // before the panic; clients must check before resubmitting.
+            ProverError::Panic { stage: "submission", .. } => write!(
+                f,
+                "internal error: submission panicked; the batch may already be on-chain, check its status before resubmitting"
+            ),
+            ProverError::Panic { stage, .. } => {
+                write!(f, "internal error: {stage} panicked; resubmit")
+            }
+            ProverError::RpcUnavailable { retry_after_secs } => {
+                write!(f, "validation: pool RPC unavailable, retry in {retry_after_secs}s")
+            }
         }
     }
 }
 
 impl std::error::Error for ProverError {}
+
+/// Backoff before store retry `attempt + 1`: 250ms, 500ms, ... capped at 4s.
+fn retry_delay(attempt: u32) -> std::time::Duration {
+    std::time::Duration::from_millis((250u64 << attempt.min(4)).min(4_000))
+}
+
+/// First on-chain submission backoff; doubled per retry.
+const SUBMIT_RETRY_BASE: Duration = Duration::from_secs(2);
+/// Ceiling on a single submission backoff, before jitter.
+const SUBMIT_RETRY_MAX: Duration = Duration::from_secs(30);
+
+/// Submission failures that mean the proof itself was refused; resubmitting
+/// it can't succeed.
+const PERMANENT_SUBMIT_ERRORS: &[&str] = &[
+    "proof rejected",
+    "invalid proof",
+    "verification failed",
+    "not verified",
+];
+
+/// Submission failures from the RPC node or transaction sequencing that a
+/// later attempt can get past.
+const TRANSIENT_SUBMIT_ERRORS: &[&str] = &[
+    "timeout",
+    "timed out",
+    "connection",
+    "nonce",
+    "rate limit",
+    "too many requests",
+    "temporarily",
+    "unavailable",
+    "502",
+    "503",
+    "504",
+];
+
+/// Whether a failed submission is worth retrying. Unrecognized errors are
+/// not: failing fast beats resending a transaction that will revert again.
+fn is_transient_submit_error(e: &ProverError) -> bool {
+    let ProverError::Relayer(msg) = e else {
+        return false;
+    };
+    let msg = msg.to_ascii_lowercase();
+    !PERMANENT_SUBMIT_ERRORS.iter().any(|m| msg.contains(m))
+        && TRANSIENT_SUBMIT_ERRORS.iter().any(|m| msg.contains(m))
+}
+
+/// Backoff before submission retry `attempt + 1`: `base * 2^attempt`, capped
+/// at `SUBMIT_RETRY_MAX`, plus up to half again of random jitter so relayers
+/// hitting the same node outage don't retry in lockstep.
+fn submit_retry_delay(base: Duration, attempt: u32) -> Duration {
+    let delay = base.saturating_mul(1 << attempt.min(16)).min(SUBMIT_RETRY_MAX);
+    delay + rand::thread_rng().gen_range(Duration::ZERO..=delay / 2)
+}
+
+/// Runs `submit` until it succeeds, fails permanently, or `retries` extra
+/// attempts are used up.
+async fn retry_submission<T, F, Fut>(
+    batch_id: &str,
+    retries: u32,
+    base: Duration,
+    mut submit: F,
+) -> Result<T, ProverError>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<T, ProverError>>,
+{
+    let mut attempt = 0u32;
+    loop {
+        match submit().await {
+            Ok(out) => return Ok(out),
+            Err(e) if attempt < retries && is_transient_submit_error(&e) => {
+                let delay = submit_retry_delay(base, attempt);
+                attempt += 1;
+                warn!(
+                    batch_id = %batch_id,
+                    attempt,
+                    error = %e,
+                    "on-chain submission failed, retrying in {delay:?}"
+                );
+                tokio::time::sleep(delay).await;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::batch_queue::Removal;
+    use std::cell::Cell;
+
+    fn m31<const N: usize>(v: [u32; N]) -> [M31; N] {
+        v.map(M31::from_u32_unchecked)
+    }
+
+    fn deposit_info(owner: u32, amount: u64) -> DepositNoteInfo {
+        DepositNoteInfo {
+            owner_pubkey: [owner; 4],
+            asset_id: 0,
+            amount,
+            blinding: [9; 4],
+            client_ref: None,
+        }
+    }
+
+    fn output(owner: u32, amount: u64, digest: u32) -> (NoteCommitment, Note) {
+        let (lo, hi) = NoteAmount::new(amount).unwrap().to_limbs();
+        let note = Note {
+            owner_pubkey: m31([owner; 4]),
+            asset_id: M31::from_u32_unchecked(0),
+            amount_lo: M31::from_u32_unchecked(lo),
+            amount_hi: M31::from_u32_unchecked(hi),
+            blinding: m31([9; 4]),
+        };
+        (m31([digest; 8]), note)
+    }
+
+    /// Shuffled batch: transfer, deposit A, withdraw, deposit B, transfer.
+    fn mixed_batch() -> (Vec<u8>, Vec<DepositNoteInfo>) {
+        (vec![2, 0, 1, 0, 2], vec![deposit_info(1, 100), deposit_info(2, 200)])
+    }
+
+    #[test]
+    fn test_deposit_digests_in_tx_order() {
+        let (kinds, deposits) = mixed_batch();
+        let outputs = [
+            output(7, 5, 70),
+            output(8, 5, 80),
+            output(1, 100, 10),
+            output(2, 200, 20),
+            output(7, 6, 71),
+            output(8, 6, 81),
+        ];
+        for mapping in [CommitmentMapping::ByNote, CommitmentMapping::Positional] {
+            let digests = ProverService::extract_deposit_digests(mapping, &kinds, &deposits, &outputs);
+            assert_eq!(digests, vec![Some([10; 8]), Some([20; 8])], "{mapping:?}");
+        }
+    }
+
+    #[test]
+    fn test_deposit_digests_reordered_outputs() {
+        let (kinds, deposits) = mixed_batch();
+        // Prover emitted the deposits first and swapped them
+        let outputs = [
+            output(2, 200, 20),
+            output(1, 100, 10),
+            output(7, 5, 70),
+            output(8, 5, 80),
+            output(7, 6, 71),
+            output(8, 6, 81),
+        ];
+        let by_note =
+            ProverService::extract_deposit_digests(CommitmentMapping::ByNote, &kinds, &deposits, &outputs);
+        assert_eq!(by_note, vec![Some([10; 8]), Some([20; 8])]);
+        // Positional mode can't see the reorder
+        let positional =
+            ProverService::extract_deposit_digests(CommitmentMapping::Positional, &kinds, &deposits, &outputs);
+        assert_eq!(positional, vec![Some([70; 8]), Some([80; 8])]);
+    }
+
+    #[test]
+    fn test_deposit_digests_count_mismatch_and_ambiguity() {
+        let (kinds, deposits) = mixed_batch();
+        let short = [output(1, 100, 10), output(2, 200, 20)];
+        assert_eq!(
+            ProverService::extract_deposit_digests(CommitmentMapping::Positional, &kinds, &deposits, &short),
+            vec![None, None]
+        );
+        assert_eq!(
+            ProverService::extract_deposit_digests(CommitmentMapping::ByNote, &kinds, &deposits, &short),
+            vec![Some([10; 8]), Some([20; 8])]
+        );
+
+        // A transfer output identical to deposit A, with A out of position
+        let outputs = [
+            output(1, 100, 70),
+            output(8, 5, 80),
+            output(3, 300, 99),
+            output(2, 200, 20),
+            output(1, 100, 10),
+            output(8, 6, 81),
+        ];
+        assert_eq!(
+            ProverService::extract_deposit_digests(CommitmentMapping::ByNote, &kinds, &deposits, &outputs),
+            vec![None, Some([20; 8])]
+        );
+    }
+
+    #[test]
+    fn test_deposit_note_keyed_by_submitted_blinding() {
+        use crate::denominations::Denominations;
+        use crate::routes::SubmitRequest;
+
+        let denominations = Denominations::builtin();
+        let deposit = |blinding| SubmitRequest::Deposit {
+            amount: 100_000,
+            asset_id: 0,
+            recipient_pubkey: [1, 2, 3, 4],
+            recipient_viewing_key: [5, 6, 7, 8],
+            blinding,
+            client_ref: None,
+        };
+        let notes = |req: &SubmitRequest| {
+            let txs = vec![req.validate_and_convert(&denominations).unwrap()];
+            ProverService::extract_deposit_notes(&txs, &[None]).remove(0)
+        };
+
+        // A client-supplied blinding keys the note, the same on every conversion
+        let supplied = notes(&deposit(Some([9; 4])));
+        assert_eq!(supplied.blinding, [9; 4]);
+        assert_eq!(supplied.commitment_key(), notes(&deposit(Some([9; 4]))).commitment_key());
+
+        // A server-generated one is fixed at validation and survives the wire form
+        let tx = deposit(None).validate_and_convert(&denominations).unwrap();
+        let generated = ProverService::extract_deposit_notes(std::slice::from_ref(&tx), &[None]).remove(0);
+        let replayed = notes(&SubmitRequest::from(&tx));
+        assert_eq!(replayed.blinding, generated.blinding);
+        assert_eq!(replayed.commitment_key(), generated.commitment_key());
+        assert_ne!(generated.commitment_key(), supplied.commitment_key());
+    }
+
+    #[test]
+    fn test_spent_nullifier_served_from_cache() {
+        let cache = SpentNullifiers::new(8);
+        let nullifier = [M31::from_u32_unchecked(5); 8];
+        let calls = Cell::new(0);
+        let rpc = |spent: bool| {
+            let calls = &calls;
+            move || {
+                calls.set(calls.get() + 1);
+                Ok(spent)
+            }
+        };
+
+        assert!(ProverService::nullifier_spent(Some(&cache), &nullifier, rpc(true)).unwrap());
+        assert_eq!(calls.get(), 1);
+        // Second check is answered by the cache without touching the pool client
+        assert!(ProverService::nullifier_spent(Some(&cache), &nullifier, rpc(true)).unwrap());
+        assert_eq!(calls.get(), 1);
+    }
+
+    #[test]
+    fn test_unspent_nullifier_not_cached() {
+        let cache = SpentNullifiers::new(8);
+        let nullifier = [M31::from_u32_unchecked(6); 8];
+        let calls = Cell::new(0);
+        for _ in 0..2 {
+            let spent = ProverService::nullifier_spent(Some(&cache), &nullifier, || {
+                calls.set(calls.get() + 1);
+                Ok(false)
+            });
+            assert!(!spent.unwrap());
+        }
+        assert_eq!(calls.get(), 2);
+        assert!(!cache.contains(&nullifier.map(|m| m.0)));
+
+        // RPC failures propagate and cache nothing
+        let err = ProverService::nullifier_spent(Some(&cache), &nullifier, || {
+            Err(ProverError::Validation("nullifier check: timeout".into()))
+        });
+        assert!(err.is_err());
+        assert!(!cache.contains(&nullifier.map(|m| m.0)));
+    }
+
+    /// A prover whose validation fails without RPC (known root, cached
+    /// spent nullifier), with a withdrawal that trips it.
+    fn failing_prover(store: Arc<InMemoryStore>) -> (ProverService, PendingTx) {
+        use crate::denominations::Denominations;
+        use crate::routes::{MerklePathJson, NoteJson, SubmitRequest};
+
+        let req = SubmitRequest::Withdraw {
+            amount: 100_000,
+            asset_id: 0,
+            note: NoteJson { owner_pubkey: [1, 2, 3, 4], asset_id: 0, amount_lo: 100_000, amount_hi: 0, blinding: [5; 4] },
+            spending_key: [9, 10, 11, 12],
+            merkle_path: MerklePathJson { siblings: vec![[1; 8]], index: 0 },
+            merkle_root: [7; 8],
+            withdrawal_binding: [8; 8],
+            binding_salt: None,
+            payout_recipient: Some("0xa1".into()),
+            credit_recipient: Some("0xc1".into()),
+            client_ref: None,
+        };
+        let tx = req.validate_and_convert(&Denominations::builtin()).unwrap();
+        let known_roots = Arc::new(KnownRoots::new(4));
+        known_roots.insert([7; 8]);
+        let spent = Arc::new(SpentNullifiers::new(4));
+        for nullifier in ProverService::extract_nullifiers(std::slice::from_ref(&tx)) {
+            spent.insert(nullifier);
+        }
+        let pool_config = PoolClientConfig {
+            rpc_url: "http://127.0.0.1:1".into(),
+            pool_address: "0x1".into(),
+            network: "sepolia".into(),
+            verify_rpc_urls: vec![],
+        };
+        let prover = ProverService::new(
+            SncastVm31Backend::new("test", "http://127.0.0.1:1", "0x2", "0x1"),
+            pool_config,
+            store,
+            1,
+            BridgeService::new("test".into(), "http://127.0.0.1:1".into(), "0x3".into()),
+        )
+        .with_known_roots(known_roots)
+        .with_spent_nullifiers(spent);
+        (prover, tx)
+    }
+
+    fn ready(batch_id: &str, tx: PendingTx) -> ReadyBatch {
+        ReadyBatch {
+            batch_id: batch_id.into(),
+            asset_id: None,
+            transactions: vec![tx],
+            client_refs: vec![Some("ref".into())],
+            idempotency_keys: vec![Some("orig".into())],
+            submitters: vec![Some("alice".into())],
+            recipients: vec![Some(recipient("0xa1", "0xc1"))],
+            span: tracing::Span::none(),
+        }
+    }
+
+    fn recipient(payout: &str, credit: &str) -> WithdrawalRecipient {
+        WithdrawalRecipient { payout: payout.into(), credit: credit.into() }
+    }
+
+    #[test]
+    fn test_withdrawal_recipients_follow_withdrawal_order() {
+        let (_, withdraw) = failing_prover(Arc::new(InMemoryStore::new()));
+        let (_, other) = failing_prover(Arc::new(InMemoryStore::new()));
+        let txs = vec![deposit(0), withdraw, deposit(0), other];
+        let recipients = vec![
+            None,
+            Some(recipient("0xa1", "0xc1")),
+            None,
+            Some(recipient("0xa2", "0xc2")),
+        ];
+
+        let wr = ProverService::extract_withdrawal_recipients(&txs, &recipients).unwrap();
+        assert_eq!(wr.payout, vec!["0xa1".to_string(), "0xa2".into()]);
+        assert_eq!(wr.credit, vec!["0xc1".to_string(), "0xc2".into()]);
+
+        // A withdrawal without recipients never reaches the relay flow
+        let err = ProverService::extract_withdrawal_recipients(&txs, &recipients[..2]).unwrap_err();
+        assert!(err.to_string().contains("batch index 3 has no payout recipient"), "{err}");
+    }
+
+    fn deposit(asset_id: u32) -> PendingTx {
+        use stwo_ml::prelude::M31;
+        let zero4 = [M31::from_u32_unchecked(0); 4];
+        PendingTx::Deposit {
+            amount: 1000,
+            asset_id,
+            recipient_pubkey: zero4,
+            recipient_viewing_key: zero4,
+            blinding: zero4,
+        }
+    }
+
+    #[test]
+    fn test_thin_anon_sets_counts_per_kind_and_asset() {
+        let (_, withdraw) = failing_prover(Arc::new(InMemoryStore::new()));
+        let txs = vec![deposit(0), deposit(0), deposit(0), withdraw, deposit(1)];
+
+        assert!(thin_anon_sets(&txs, 0).is_empty());
+        assert!(thin_anon_sets(&txs, 1).is_empty());
+        // Three deposits of asset 0 don't cover the lone withdrawal
+        assert_eq!(
+            thin_anon_sets(&txs, 2),
+            vec![
+                ThinAnonSet { kind: TxKind::Deposit, asset_id: 1, count: 1 },
+                ThinAnonSet { kind: TxKind::Withdraw, asset_id: 0, count: 1 },
+            ]
+        );
+        assert_eq!(thin_anon_sets(&txs, 4).len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_strict_anon_set_requeues_only_short_groups() {
+        let store = Arc::new(InMemoryStore::new());
+        let (prover, withdraw) = failing_prover(store.clone());
+        let (queue, _rx) = BatchQueue::new(16, 3600, 8);
+        let queue = Arc::new(queue);
+        let prover = prover.with_min_anon_set(2, AnonSetMode::Strict, queue.clone());
+
+        let mut batch = ready("b-anon", withdraw);
+        batch.transactions.extend([deposit(0), deposit(0)]);
+        // Short refs/keys are padded rather than misaligned
+        let kept = prover.enforce_anon_set(batch).await.unwrap();
+        assert_eq!(kept.transactions.len(), 2);
+        assert!(kept.transactions.iter().all(|tx| TxKind::of(tx) == TxKind::Deposit));
+        assert_eq!(kept.idempotency_keys, vec![None, None]);
+        assert_eq!(queue.pending_count().await, 1);
+        assert_eq!(queue.remove_by_key("orig", "alice").await, Removal::Removed(0));
+
+        // Nothing left to prove: no record is written for the batch
+        let (_, withdraw) = failing_prover(store.clone());
+        prover.handle_batch(ready("b-lone", withdraw)).await;
+        assert!(store.get_batch("b-lone").await.unwrap().is_none());
+        assert_eq!(queue.pending_count().await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_lenient_anon_set_keeps_batch() {
+        let (prover, withdraw) = failing_prover(Arc::new(InMemoryStore::new()));
+        let (queue, _rx) = BatchQueue::new(16, 3600, 8);
+        let queue = Arc::new(queue);
+        let prover = prover.with_min_anon_set(2, AnonSetMode::Lenient, queue.clone());
+
+        let kept = prover.enforce_anon_set(ready("b-1", withdraw)).await.unwrap();
+        assert_eq!(kept.transactions.len(), 1);
+        assert_eq!(queue.pending_count().await, 0);
+        assert_eq!("strict".parse::<AnonSetMode>(), Ok(AnonSetMode::Strict));
+        assert!("off".parse::<AnonSetMode>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_run_returns_after_shutdown_drains_buffered_batch() {
+        let store = Arc::new(InMemoryStore::new());
+        let (prover, tx) = failing_prover(store.clone());
+        let shutdown = Arc::new(Shutdown::new());
+        let (batches, rx) = mpsc::channel(4);
+        let run = tokio::spawn(prover.with_shutdown(shutdown.clone()).run(rx));
+
+        // Flushed at shutdown; the queue still holds the sender open
+        batches.send(ready("b-1", tx)).await.unwrap();
+        assert!(shutdown.begin());
+        shutdown.queue_drained();
+        tokio::time::timeout(std::time::Duration::from_secs(10), run)
+            .await
+            .expect("prover kept waiting for batches after shutdown")
+            .unwrap();
+
+        let record = store.get_batch("b-1").await.unwrap().unwrap();
+        assert_eq!(record.status, BatchStatus::Failed);
+        assert!(record.error.unwrap().contains("nullifier already spent"));
+        assert!(batches.is_closed());
+    }
+
+    #[tokio::test]
+    async fn test_paused_prover_drops_buffered_batch_at_shutdown() {
+        let store = Arc::new(InMemoryStore::new());
+        let (prover, tx) = failing_prover(store.clone());
+        let (pause, shutdown) = (Arc::new(ProverPause::new()), Arc::new(Shutdown::new()));
+        pause.pause();
+        let (batches, rx) = mpsc::channel(4);
+        batches.send(ready("b-1", tx)).await.unwrap();
+        let run = tokio::spawn(prover.with_pause(pause).with_shutdown(shutdown.clone()).run(rx));
+
+        shutdown.queue_drained();
+        tokio::time::timeout(std::time::Duration::from_secs(10), run).await.unwrap().unwrap();
+        let record = store.get_batch("b-1").await.unwrap().unwrap();
+        assert_eq!(record.error.as_deref(), Some("dropped during shutdown; resubmit"));
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_drains_buffered_batches() {
+        use crate::store::DeadLetterStore;
+
+        for drain in [true, false] {
+            let store = Arc::new(InMemoryStore::new());
+            let (prover, tx) = failing_prover(store.clone());
+            let shutdown = Arc::new(Shutdown::new());
+            let (batches, rx) = mpsc::channel(4);
+            batches.send(ready("b-1", tx.clone())).await.unwrap();
+            batches.send(ready("b-2", tx)).await.unwrap();
+            // Drained before the prover takes anything: both batches are
+            // still buffered when the loop exits
+            shutdown.queue_drained();
+            let prover = prover.with_shutdown(shutdown).with_drain_on_close(drain).with_dead_letter(true);
+            tokio::time::timeout(std::time::Duration::from_secs(10), prover.run(rx)).await.unwrap();
+
+            for id in ["b-1", "b-2"] {
+                let record = store.get_batch(id).await.unwrap().unwrap();
+                assert_eq!(record.status, BatchStatus::Failed);
+                let error = record.error.unwrap();
+                if drain {
+                    // Proven (and rejected by validation), not dropped
+                    assert!(error.contains("nullifier already spent"), "{error}");
+                } else {
+                    assert_eq!(error, "dropped during shutdown; resubmit");
+                }
+            }
+            let mut dead: Vec<_> = store
+                .list_dead_letters()
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|d| (d.batch_id, d.tx_count))
+                .collect();
+            dead.sort();
+            assert_eq!(dead, vec![("b-1".into(), 1), ("b-2".into(), 1)], "drain={drain}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_batch_received_after_pause_is_held_until_resume() {
+        let store = Arc::new(InMemoryStore::new());
+        let (prover, tx) = failing_prover(store.clone());
+        let (pause, shutdown) = (Arc::new(ProverPause::new()), Arc::new(Shutdown::new()));
+        let (batches, rx) = mpsc::channel(4);
+        let run = tokio::spawn(prover.with_pause(pause.clone()).with_shutdown(shutdown.clone()).run(rx));
+        // Let the prover park on `recv` before pausing.
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+        pause.pause();
+        batches.send(ready("b-1", tx)).await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        assert!(store.get_batch("b-1").await.unwrap().is_none());
+
+        pause.resume();
+        drop(batches);
+        tokio::time::timeout(std::time::Duration::from_secs(10), run).await.unwrap().unwrap();
+        let record = store.get_batch("b-1").await.unwrap().unwrap();
+        assert!(record.error.unwrap().contains("nullifier already spent"));
+    }
+
+    #[tokio::test]
+    async fn test_failed_batch_is_dead_lettered_and_replayable() {
+        use crate::batch_queue::BatchQueue;
+        use crate::store::DeadLetterStore;
+
+        let store = Arc::new(InMemoryStore::new());
+        let (prover, tx) = failing_prover(store.clone());
+        prover.with_dead_letter(true).handle_batch(ready("b-1", tx)).await;
+
+        let record = store.get_batch("b-1").await.unwrap().unwrap();
+        assert_eq!(record.status, BatchStatus::Failed);
+        let list = store.list_dead_letters().await.unwrap();
+        assert_eq!(list.len(), 1);
+        assert_eq!((list[0].batch_id.as_str(), list[0].tx_count), ("b-1", 1));
+        assert!(list[0].reason.contains("nullifier already spent"), "{}", list[0].reason);
+
+        let (queue, _rx) = BatchQueue::new(16, 3600, 8);
+        let entry = store.take_dead_letter("b-1").await.unwrap().unwrap();
+        assert_eq!(entry.transactions[0].withdrawal_recipient().unwrap(), Some(recipient("0xa1", "0xc1")));
+        store.save_dead_letter(entry).await.unwrap();
+        let replayed = crate::routes::replay_dead_letter_into(&store, &queue, "b-1").await.unwrap();
+        let keys = replayed["idempotency_keys"].as_array().unwrap();
+        assert_eq!(keys.len(), 1);
+        assert_ne!(keys[0], "orig");
+        assert_eq!(queue.pending_count().await, 1);
+        assert!(store.list_dead_letters().await.unwrap().is_empty());
+        // Replaying twice finds nothing
+        assert!(crate::routes::replay_dead_letter_into(&store, &queue, "b-1").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_initial_save_retried_then_dead_lettered() {
+        use crate::store::DeadLetterStore;
+
+        // One transient failure is absorbed by the retry
+        let store = Arc::new(InMemoryStore::new());
+        store.failing_batch_saves.store(1, Ordering::Relaxed);
+        let (prover, tx) = failing_prover(store.clone());
+        prover.with_batch_save_retries(1).handle_batch(ready("b-1", tx)).await;
+        assert!(store.get_batch("b-1").await.unwrap().is_some());
+        assert!(store.list_dead_letters().await.unwrap().is_empty());
+
+        // Exhausted retries keep the txs even with dead-lettering of failed
+        // batches turned off
+        let store = Arc::new(InMemoryStore::new());
+        store.failing_batch_saves.store(2, Ordering::Relaxed);
+        let (prover, tx) = failing_prover(store.clone());
+        prover.with_batch_save_retries(1).handle_batch(ready("b-2", tx)).await;
+        assert!(store.get_batch("b-2").await.unwrap().is_none());
+        let list = store.list_dead_letters().await.unwrap();
+        assert_eq!((list.len(), list[0].tx_count), (1, 1));
+        assert!(list[0].reason.contains("initial batch save failed"), "{}", list[0].reason);
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_reports_without_side_effects() {
+        use crate::denominations::Denominations;
+        use crate::routes::{dry_run, CheckFailure, SubmitRequest};
+
+        let (prover, spent_withdrawal) = failing_prover(Arc::new(InMemoryStore::new()));
+        let checks = prover.input_checks();
+        let deposit = |amount| SubmitRequest::Deposit {
+            amount,
+            asset_id: 0,
+            recipient_pubkey: [1, 2, 3, 4],
+            recipient_viewing_key: [5, 6, 7, 8],
+            blinding: None,
+            client_ref: None,
+        };
+        let denominations = Denominations::builtin();
+
+        let fields = |e| (CheckFailure::InvalidFields, e);
+
+        // Deposits have no pool inputs, so they pass without RPC
+        let valid = deposit(100_000).validate_and_convert(&denominations).map_err(fields);
+        assert!(dry_run(&checks, valid).await.unwrap().is_empty());
+
+        let off_table = deposit(12_345).validate_and_convert(&denominations).map_err(fields);
+        assert_eq!(dry_run(&checks, off_table).await.unwrap(), vec![CheckFailure::InvalidFields]);
+
+        let errors = dry_run(&checks, Ok(spent_withdrawal)).await.unwrap();
+        assert_eq!(errors, vec![CheckFailure::NullifierSpent]);
+        // Only the code and a fixed message reach the client
+        assert_eq!(
+            errors[0].body(),
+            serde_json::json!({ "error": "nullifier already spent", "code": "NULLIFIER_SPENT" })
+        );
+    }
+
+    #[test]
+    fn test_check_inputs_rejects_unknown_root_and_spent_nullifier() {
+        let (_, withdrawal) = failing_prover(Arc::new(InMemoryStore::new()));
+        let txs = std::slice::from_ref(&withdrawal);
+
+        assert!(ProverService::check_inputs(txs, |_| Ok(true), |_| Ok(false)).is_ok());
+        let err = ProverService::check_inputs(txs, |_| Ok(false), |_| Ok(false)).unwrap_err();
+        assert!(err.to_string().contains("unknown Merkle root"), "{err}");
+        let err = ProverService::check_inputs(txs, |_| Ok(true), |_| Ok(true)).unwrap_err();
+        assert!(err.to_string().contains("nullifier already spent"), "{err}");
+    }
+
+    #[test]
+    fn test_root_known_confirmed_only_skips_rpc() {
+        let known = KnownRoots::new(4);
+        known.insert([7; 8]);
+        let (confirmed, other) = (m31([7; 8]), m31([8; 8]));
+        let rpc_unexpected = || -> Result<bool, ProverError> { panic!("unexpected RPC") };
+
+        assert!(ProverService::root_known(Some(&known), true, &confirmed, rpc_unexpected).unwrap());
+        // Known on-chain but never a root of the local tree (or not yet
+        // confirmed when the tree reached it): rejected
+        assert!(!ProverService::root_known(Some(&known), true, &other, rpc_unexpected).unwrap());
+        assert!(ProverService::root_known(Some(&known), false, &other, || Ok(true)).unwrap());
+    }
+
+    #[test]
+    fn test_root_known_skips_rpc_for_synced_and_validated_roots() {
+        let known = KnownRoots::new(4);
+        // As published by tree sync
+        known.insert([7; 8]);
+        let (synced, unknown) = (m31([7; 8]), m31([8; 8]));
+        let rpc_unexpected = || -> Result<bool, ProverError> { panic!("unexpected RPC") };
+        assert!(ProverService::root_known(Some(&known), false, &synced, rpc_unexpected).unwrap());
+
+        let calls = Cell::new(0);
+        let rpc = |answer: bool| {
+            let calls = &calls;
+            move || {
+                calls.set(calls.get() + 1);
+                Ok(answer)
+            }
+        };
+        // Unknown roots still hit the RPC, and a rejection isn't cached
+        for _ in 0..2 {
+            assert!(!ProverService::root_known(Some(&known), false, &unknown, rpc(false)).unwrap());
+        }
+        assert_eq!(calls.get(), 2);
+        assert!(!known.contains(&[8; 8]));
+
+        // Once the RPC confirms a root, later checks answer locally
+        assert!(ProverService::root_known(Some(&known), false, &unknown, rpc(true)).unwrap());
+        assert!(ProverService::root_known(Some(&known), false, &unknown, rpc_unexpected).unwrap());
+        assert_eq!(calls.get(), 3);
+
+        // RPC failures propagate and cache nothing
+        let other = m31([9; 8]);
+        let err = ProverService::root_known(Some(&known), false, &other, || {
+            Err(ProverError::Validation("root check: timeout".into()))
+        });
+        assert!(err.is_err());
+        assert!(!known.contains(&[9; 8]));
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_note_key_does_not_overwrite_other_batch() {
+        let store = Arc::new(InMemoryStore::new());
+        let first = NoteRecord {
+            commitment: "c1".into(),
+            merkle_path: MerklePathRecord { siblings: vec![[1; 8]], index: 3 },
+            merkle_root: [2; 8],
+            batch_id: "b-1".into(),
+            created_at: 1,
+            commitment_digest: Some([4; 8]),
+            note_index_in_batch: 0,
+            client_ref: None,
+            backfill_attempts: 0,
+            last_backfill_attempt: 0,
+        };
+        store.save_note("c1", &first).await.unwrap();
+
+        let (prover, _) = failing_prover(store.clone());
+        // Unseen keys and re-saves by the owning batch keep the plain key
+        assert_eq!(prover.note_key("c2".into(), "b-2").await.as_deref(), Some("c2"));
+        assert_eq!(prover.note_key("c1".into(), "b-1").await.as_deref(), Some("c1"));
+        assert_eq!(prover.note_key("c1".into(), "b-2").await.as_deref(), Some("c1-b-2"));
+
+        let prover = prover.with_duplicate_note_policy(DuplicateNotePolicy::Reject);
+        assert_eq!(prover.note_key("c1".into(), "b-2").await, None);
+
+        // The first batch's note is untouched either way
+        let kept = store.get_note("c1").await.unwrap().unwrap();
+        assert_eq!((kept.batch_id.as_str(), kept.merkle_path.index), ("b-1", 3));
+    }
+
+    #[tokio::test]
+    async fn test_join_failure_captures_panic_message() {
+        let err = tokio::task::spawn_blocking(|| -> u32 { panic!("witness index {} out of range", 7) })
+            .await
+            .unwrap_err();
+        match join_failure("proving", err, ProverError::Proving) {
+            ProverError::Panic { stage, message } => {
+                assert_eq!(stage, "proving");
+                assert_eq!(message, "witness index 7 out of range");
+            }
+            other => panic!("expected Panic, got {other:?}"),
+        }
+
+        let err = tokio::task::spawn_blocking(|| std::panic::panic_any(42u8))
+            .await
+            .unwrap_err();
+        let e = join_failure("submission", err, ProverError::Relayer);
+        assert!(matches!(&e, ProverError::Panic { message, .. } if message == "non-string panic payload"));
+        // The panic detail stays server-side, and a post-proof panic doesn't
+        // invite a resubmit of a batch that may already be on-chain
+        assert_eq!(
+            e.to_string(),
+            "internal error: submission panicked; the batch may already be on-chain, check its status before resubmitting"
+        );
+        let e = ProverError::Panic { stage: "proving", message: "boom".into() };
+        assert_eq!(e.to_string(), "internal error: proving panicked; resubmit");
+    }
+
+    /// Mock submission flow failing with `errors` in turn, then succeeding.
+    fn flaky_submit<'a>(
+        errors: &[&str],
+        calls: &'a Cell<usize>,
+    ) -> impl FnMut() -> std::future::Ready<Result<&'static str, ProverError>> + 'a {
+        let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        move || {
+            let n = calls.get();
+            calls.set(n + 1);
+            std::future::ready(match errors.get(n) {
+                Some(msg) => Err(ProverError::Relayer(msg.clone())),
+                None => Ok("finalized"),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_submission_retries_transient_failures() {
+        let calls = Cell::new(0);
+        let errors = ["rpc error: connection refused", "invalid transaction nonce"];
+        let out = retry_submission("b-1", 3, Duration::ZERO, flaky_submit(&errors, &calls)).await;
+        assert_eq!(out.unwrap(), "finalized");
+        assert_eq!(calls.get(), 3);
+
+        // Retries are bounded
+        let calls = Cell::new(0);
+        let errors = ["request timed out"; 5];
+        let out = retry_submission("b-1", 2, Duration::ZERO, flaky_submit(&errors, &calls)).await;
+        assert!(matches!(out, Err(ProverError::Relayer(msg)) if msg.contains("timed out")));
+        assert_eq!(calls.get(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_submission_fails_fast_on_permanent_error() {
+        for error in ["submit_batch_proof: proof rejected by verifier", "execution reverted: invalid proof"] {
+            let calls = Cell::new(0);
+            let out = retry_submission("b-1", 3, Duration::ZERO, flaky_submit(&[error], &calls)).await;
+            assert!(out.is_err());
+            assert_eq!(calls.get(), 1, "{error}");
+        }
+        // Unrecognized failures and panics aren't retried either
+        let calls = Cell::new(0);
+        assert!(retry_submission("b-1", 3, Duration::ZERO, flaky_submit(&["contract error"], &calls))
+            .await
+            .is_err());
+        assert_eq!(calls.get(), 1);
+        let panic = ProverError::Panic { stage: "submission", message: "timeout".into() };
+        assert!(!is_transient_submit_error(&panic));
+    }
+
+    #[test]
+    fn test_submit_retry_delay_doubles_with_bounded_jitter() {
+        let base = Duration::from_secs(2);
+        for (attempt, expected) in [(0, 2), (1, 4), (2, 8), (3, 16), (4, 30), (20, 30)] {
+            let expected = Duration::from_secs(expected);
+            let delay = submit_retry_delay(base, attempt);
+            assert!(delay >= expected && delay <= expected + expected / 2, "{attempt}: {delay:?}");
+        }
+    }
+}
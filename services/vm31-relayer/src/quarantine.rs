@@ -0,0 +1,145 @@
+//! Holding area for flagged submissions awaiting manual review.
+//!
+//! Submissions from flagged API keys, or matching a configured heuristic, are
+//! parked here instead of entering the batch queue. Admins list them and then
+//! approve (enqueue) or reject (drop) each one.
+
+use dashmap::DashMap;
+use serde::Serialize;
+use uuid::Uuid;
+
+use stwo_ml::privacy::tx_builder::PendingTx;
+
+use crate::config::RelayerConfig;
+use crate::routes::WithdrawalRecipient;
+use crate::store::now_epoch;
+
+/// Maximum submissions held for review before new flagged ones are refused.
+pub const MAX_QUARANTINED: usize = 1024;
+
+/// A submission held for review.
+pub struct QuarantinedTx {
+    pub tx: PendingTx,
+    /// API key that submitted it, which may not approve it.
+    pub submitter: String,
+    pub client_ref: Option<String>,
+    pub recipient: Option<WithdrawalRecipient>,
+    pub summary: QuarantineSummary,
+}
+
+/// Review metadata exposed to admins. Deliberately excludes keys, notes and
+/// recipients — only what's needed to judge the flag.
+#[derive(Debug, Clone, Serialize)]
+pub struct QuarantineSummary {
+    pub id: String,
+    pub reason: String,
+    pub tx_type: &'static str,
+    pub asset_id: u32,
+    pub amount: u64,
+    pub created_at: u64,
+}
+
+#[derive(Default)]
+pub struct Quarantine {
+    held: DashMap<String, QuarantinedTx>,
+}
+
+impl Quarantine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns why a submission should be held for review, if at all.
+    pub fn flag_reason(config: &RelayerConfig, api_key: &str, tx: &PendingTx) -> Option<String> {
+        if config.quarantine_keys.iter().any(|k| k == api_key) {
+            return Some("flagged_api_key".into());
+        }
+        let (amount, asset_id) = tx_amount_and_asset(tx);
+        if let Some(threshold) = config.quarantine_amount_threshold {
+            if amount >= threshold {
+                return Some("amount_threshold".into());
+            }
+        }
+        if config.quarantine_asset_ids.contains(&asset_id) {
+            return Some("flagged_asset".into());
+        }
+        None
+    }
+
+    /// True once `MAX_QUARANTINED` submissions are held.
+    pub fn is_full(&self) -> bool {
+        self.held.len() >= MAX_QUARANTINED
+    }
+
+    /// Holds a submission for review. Returns `None` if quarantine is full.
+    pub fn hold(
+        &self,
+        tx: PendingTx,
+        submitter: &str,
+        client_ref: Option<String>,
+        recipient: Option<WithdrawalRecipient>,
+        reason: String,
+    ) -> Option<String> {
+        if self.is_full() {
+            return None;
+        }
+        let id = Uuid::new_v4().to_string();
+        let (amount, asset_id) = tx_amount_and_asset(&tx);
+        let summary = QuarantineSummary {
+            id: id.clone(),
+            reason,
+            tx_type: match tx {
+                PendingTx::Deposit { .. } => "deposit",
+                PendingTx::Withdraw { .. } => "withdraw",
+                PendingTx::Transfer { .. } => "transfer",
+            },
+            asset_id,
+            amount,
+            created_at: now_epoch(),
+        };
+        self.held.insert(
+            id.clone(),
+            QuarantinedTx {
+                tx,
+                submitter: submitter.to_string(),
+                client_ref,
+                recipient,
+                summary,
+            },
+        );
+        Some(id)
+    }
+
+    /// Lists held submissions, oldest first.
+    pub fn list(&self) -> Vec<QuarantineSummary> {
+        let mut out: Vec<QuarantineSummary> =
+            self.held.iter().map(|e| e.value().summary.clone()).collect();
+        out.sort_by_key(|s| s.created_at);
+        out
+    }
+
+    /// Removes and returns a held submission (used by both approve and reject).
+    pub fn take(&self, id: &str) -> Option<QuarantinedTx> {
+        self.held.remove(id).map(|(_, q)| q)
+    }
+
+    /// Whether `api_key` submitted the held submission `id`; `None` if no
+    /// such submission is held.
+    pub fn submitted_by(&self, id: &str, api_key: &str) -> Option<bool> {
+        self.held.get(id).map(|q| q.submitter == api_key)
+    }
+}
+
+fn tx_amount_and_asset(tx: &PendingTx) -> (u64, u32) {
+    match tx {
+        PendingTx::Deposit {
+            amount, asset_id, ..
+        }
+        | PendingTx::Withdraw {
+            amount, asset_id, ..
+        }
+        | PendingTx::Transfer {
+            amount, asset_id, ..
+        } => (*amount, *asset_id),
+    }
+}
@@ -0,0 +1,181 @@
+//! Write-ahead log for the batch queue.
+//!
+//! Every tx accepted into the queue is appended as one JSON line, using the
+//! `SubmitRequest` wire shape, so a restart can replay txs that were queued
+//! but not yet flushed. The file is rewritten with whatever is still pending
+//! after each flush or cancellation, so it only ever holds the live queue.
+//!
+//! SECURITY: entries contain spending keys. The file is created owner-only
+//! (0600 on unix); keep it on a private volume. With VM31_STORAGE_KEY set,
+//! each line is sealed with `StorageEncryption` instead of written as
+//! plaintext. Plaintext lines left by an earlier run are still read.
+
+use std::path::PathBuf;
+
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+use tracing::warn;
+
+use crate::routes::SubmitRequest;
+use crate::store::StorageEncryption;
+
+/// One queued transaction.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WalEntry {
+    /// Epoch seconds when the tx was first queued, so replayed txs keep
+    /// their place against the flush deadlines.
+    pub enqueued_at: u64,
+    pub tx: SubmitRequest,
+    /// API-key-scoped client reference digest (not the raw `client_ref`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scoped_client_ref: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idempotency_key: Option<String>,
+    /// Digest of the submitting API key (not the key itself).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub submitter: Option<String>,
+}
+
+/// A `WalEntry` sealed with the storage key.
+#[derive(Serialize, Deserialize)]
+struct SealedEntry {
+    /// base64 of `nonce || AES-256-GCM ciphertext` (see `StorageEncryption`).
+    sealed: String,
+}
+
+pub struct QueueWal {
+    path: PathBuf,
+    sealing: Option<StorageEncryption>,
+}
+
+impl QueueWal {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into(), sealing: None }
+    }
+
+    /// Seals every line written from now on with `storage_key`.
+    pub fn with_encryption(mut self, storage_key: Option<&[u8; 32]>) -> Self {
+        self.sealing = storage_key.map(StorageEncryption::new);
+        self
+    }
+
+    /// Encodes `entry` as a newline-terminated JSON line, sealed when a
+    /// storage key is configured.
+    pub fn encode(&self, entry: &WalEntry) -> Result<String, String> {
+        let mut line = match &self.sealing {
+            Some(enc) => {
+                let data = enc.seal(entry).map_err(|e| e.to_string())?;
+                let sealed = base64::engine::general_purpose::STANDARD.encode(data);
+                serde_json::to_string(&SealedEntry { sealed })
+            }
+            None => serde_json::to_string(entry),
+        }
+        .map_err(|e| format!("encode: {e}"))?;
+        line.push('\n');
+        Ok(line)
+    }
+
+    fn unseal(&self, sealed: &str) -> Result<WalEntry, String> {
+        let enc = self
+            .sealing
+            .as_ref()
+            .ok_or("sealed entry but VM31_STORAGE_KEY is not configured")?;
+        let data = base64::engine::general_purpose::STANDARD
+            .decode(sealed)
+            .map_err(|_| "invalid base64".to_string())?;
+        enc.open(&data).map_err(|_| "cannot unseal (different storage key?)".to_string())
+    }
+
+    pub fn path(&self) -> &std::path::Path {
+        &self.path
+    }
+
+    /// Appends encoded lines and syncs them to disk.
+    pub async fn append(&self, line: &str) -> std::io::Result<()> {
+        let mut file = self.open(false).await?;
+        file.write_all(line.as_bytes()).await?;
+        file.sync_data().await
+    }
+
+    /// Replaces the log with `lines` (empty after a full flush). The lines go
+    /// to a synced sibling file that is renamed over the log, so a crash
+    /// mid-rewrite leaves either the old log or the new one, never a torn mix.
+    pub async fn rewrite(&self, lines: impl Iterator<Item = &str>) -> std::io::Result<()> {
+        let tmp = self.tmp_path();
+        let mut file = Self::open_path(&tmp, true).await?;
+        for line in lines {
+            file.write_all(line.as_bytes()).await?;
+        }
+        file.sync_all().await?;
+        drop(file);
+        tokio::fs::rename(&tmp, &self.path).await?;
+        self.sync_dir().await
+    }
+
+    /// Reads all entries in append order. A missing file is an empty log;
+    /// undecodable lines (e.g. a write torn by a crash) are skipped. Sealed
+    /// lines that can't be opened are an error rather than dropped txs.
+    pub async fn load(&self) -> std::io::Result<Vec<WalEntry>> {
+        let content = match tokio::fs::read_to_string(&self.path).await {
+            Ok(c) => c,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let mut entries = Vec::new();
+        for (i, line) in content.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            if let Ok(SealedEntry { sealed }) = serde_json::from_str(line) {
+                let entry = self
+                    .unseal(&sealed)
+                    .map_err(|e| std::io::Error::other(format!("line {}: {e}", i + 1)))?;
+                entries.push(entry);
+                continue;
+            }
+            match serde_json::from_str(line) {
+                Ok(entry) => entries.push(entry),
+                Err(e) => warn!(line = i + 1, error = %e, "skipping unreadable queue WAL entry"),
+            }
+        }
+        Ok(entries)
+    }
+
+    /// `<wal path>.tmp`, next to the log so the rename stays on one filesystem.
+    fn tmp_path(&self) -> PathBuf {
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        tmp.into()
+    }
+
+    /// Syncs the log's directory so the rename itself survives a crash.
+    async fn sync_dir(&self) -> std::io::Result<()> {
+        #[cfg(unix)]
+        {
+            let dir = match self.path.parent() {
+                Some(d) if !d.as_os_str().is_empty() => d,
+                _ => std::path::Path::new("."),
+            };
+            tokio::fs::File::open(dir).await?.sync_all().await?;
+        }
+        Ok(())
+    }
+
+    async fn open(&self, truncate: bool) -> std::io::Result<tokio::fs::File> {
+        Self::open_path(&self.path, truncate).await
+    }
+
+    async fn open_path(path: &std::path::Path, truncate: bool) -> std::io::Result<tokio::fs::File> {
+        let mut opts = tokio::fs::OpenOptions::new();
+        opts.create(true);
+        if truncate {
+            opts.write(true).truncate(true);
+        } else {
+            opts.append(true);
+        }
+        #[cfg(unix)]
+        opts.mode(0o600);
+        opts.open(path).await
+    }
+}
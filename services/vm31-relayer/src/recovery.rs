@@ -0,0 +1,71 @@
+//! Append-only recovery log for batch outcomes the store failed to record.
+//!
+//! When a terminal status update can't be written (store outage after the
+//! batch already landed on-chain), the true outcome is appended here as one
+//! JSON object per line so an operator can reconcile `/batch/{id}` later.
+
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+use tracing::error;
+
+use crate::store::{now_epoch, BatchStatus};
+
+/// A batch outcome that still needs to be written to the store.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecoveryEntry {
+    pub batch_id: String,
+    pub status: BatchStatus,
+    pub proof_hash: Option<String>,
+    pub batch_id_onchain: Option<String>,
+    pub tx_hash: Option<String>,
+    pub error: Option<String>,
+    /// The store error that prevented the update.
+    pub store_error: String,
+    pub recorded_at: u64,
+}
+
+pub struct RecoveryLog {
+    path: PathBuf,
+}
+
+impl RecoveryLog {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Appends an entry. Failures are logged with the full outcome so it is
+    /// still recoverable from the process logs.
+    pub async fn append(&self, mut entry: RecoveryEntry) {
+        entry.recorded_at = now_epoch();
+        let line = match serde_json::to_string(&entry) {
+            Ok(mut l) => {
+                l.push('\n');
+                l
+            }
+            Err(e) => {
+                error!(batch_id = %entry.batch_id, error = %e, "failed to encode recovery entry");
+                return;
+            }
+        };
+        let result = async {
+            let mut file = tokio::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)
+                .await?;
+            file.write_all(line.as_bytes()).await?;
+            file.sync_data().await
+        }
+        .await;
+        if let Err(e) = result {
+            error!(
+                path = %self.path.display(),
+                error = %e,
+                entry = %line.trim_end(),
+                "failed to write recovery log — outcome only recorded here"
+            );
+        }
+    }
+}
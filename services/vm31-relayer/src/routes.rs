@@ -1,114 +1,123 @@
-use axum::extract::{ConnectInfo, Path, State};
-use axum::http::{HeaderMap, StatusCode};
-use axum::response::IntoResponse;
+use axum::extract::{ConnectInfo, Path, Query, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::sse::{Event, Sse};
+use axum::response::{IntoResponse, Response};
 use axum::Json;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use std::collections::HashMap;
 use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::StreamExt;
+use tracing::{debug, info, warn, Instrument};
 
 use stwo_ml::prelude::M31;
 use stwo_ml::crypto::commitment::Note;
-use stwo_ml::crypto::merkle_m31::MerklePath;
+use stwo_ml::crypto::merkle_m31::{verify_merkle_proof, MerklePath};
+use stwo_ml::crypto::poseidon2_m31::{N_HALF_FULL_ROUNDS, N_PARTIAL_ROUNDS, RATE, STATE_WIDTH};
 use stwo_ml::privacy::tx_builder::PendingTx;
 
-use aes_gcm::{aead::Aead, Aes256Gcm, KeyInit, Nonce};
-use hkdf::Hkdf;
+use rand::Rng;
 use sha2::{Digest, Sha256};
 use x25519_dalek::{PublicKey as X25519PublicKey, StaticSecret};
 
-use crate::batch_queue::BatchQueue;
-use crate::config::RelayerConfig;
+use crate::abuse::AbuseTracker;
+use crate::assets::OnChainAsset;
+use crate::amount::NoteAmount;
+use crate::batch_queue::{BatchQueue, ForceFlushOutcome, Removal};
+use crate::circuit_breaker::CircuitBreaker;
+use crate::config::{
+    ApiKeyConfig, ApiKeys, DepositLimit, RelayerConfig, Scope, FELT_PRIME_HEX, M31_MODULUS,
+};
+use crate::denominations::{DenominationMatch, Denominations};
+use crate::denylist::{parse_pubkey, pubkey_hex, PubkeyDenylist, SharedDenylist};
+use crate::ecies::{self, EnvelopeError, TimestampPolicy};
 use crate::error::AppError;
-use crate::store::{BatchStore, IdempotencyStore, InMemoryStore, MerklePathRecord, NoteStore, RateLimitStore};
-use crate::tree_sync_service::TreeSyncService;
+use crate::latency::LatencyStats;
+use crate::metrics::Metrics;
+use crate::prover::{InputCheck, InputChecks, ProverError, ProverPause, Shutdown};
+use crate::quarantine::Quarantine;
+use crate::rpc_pool::{EndpointStatus, RpcPool};
+use crate::snapshot;
+use crate::telemetry;
+use crate::store::{
+    BatchCursor, BatchEvent, BatchStatus, BatchStore, DeadLetterStore, IdempotencyStore, InMemoryStore, MerklePathRecord, NonceGuardStore, NoteRecord,
+    NoteStore, OwnerIndexStore, RateLimitStore, VolumeStore, now_epoch, utc_day,
+};
+use crate::tree_sync_service::{ProofResult, TreeSyncError, TreeSyncService};
 
 // ---------------------------------------------------------------------------
 // Constants
 // ---------------------------------------------------------------------------
 
-/// M31 field modulus: 2^31 - 1
-const M31_MODULUS: u32 = 0x7FFF_FFFF;
-
 /// Maximum Merkle tree depth (32 levels → 2^32 leaves)
-const MAX_MERKLE_DEPTH: usize = 32;
-
-/// Maximum amount: (2^31 - 1) + (2^31 - 1) * 2^31  (matches stwo-ml MAX_NOTE_AMOUNT)
-const MAX_NOTE_AMOUNT: u64 = ((1u64 << 31) - 1) + ((1u64 << 31) - 1) * (1u64 << 31);
+pub const MAX_MERKLE_DEPTH: usize = 32;
 
 /// Maximum pending transactions before rejecting new submissions
 pub const MAX_PENDING_TXS: usize = 1024;
 
-/// Standard denomination whitelist per asset (in base units).
-/// All deposits MUST use one of these standard denominations to prevent
-/// exact-amount correlation attacks (privacy gap #7).
-///
-/// Asset ID mapping (from VM31Pool.register_asset()):
-///   0 = wBTC (8 decimals), 1 = SAGE (18 decimals), 2 = ETH (18 decimals),
-///   3 = STRK (18 decimals), 4 = USDC (6 decimals)
-
-/// BTC denominations (8 decimals, base unit = satoshi)
-pub const BTC_DENOMINATIONS: [u64; 6] = [
-    50_000,      // 0.0005 BTC
-    100_000,     // 0.001 BTC
-    500_000,     // 0.005 BTC
-    1_000_000,   // 0.01 BTC
-    5_000_000,   // 0.05 BTC
-    10_000_000,  // 0.1 BTC
-];
-
-/// ETH denominations (18 decimals, base unit = wei)
-const ETH_DENOMINATIONS: [u64; 6] = [
-    1_000_000_000_000_000,      // 0.001 ETH
-    5_000_000_000_000_000,      // 0.005 ETH
-    10_000_000_000_000_000,     // 0.01 ETH
-    50_000_000_000_000_000,     // 0.05 ETH
-    100_000_000_000_000_000,    // 0.1 ETH
-    500_000_000_000_000_000,    // 0.5 ETH
-];
-
-/// STRK denominations (18 decimals)
-const STRK_DENOMINATIONS: [u64; 6] = [
-    1_000_000_000_000_000_000,    // 1 STRK
-    5_000_000_000_000_000_000,    // 5 STRK
-    10_000_000_000_000_000_000,   // 10 STRK (overflow: u64 can't hold this)
-    50_000_000_000_000_000,       // 0.05 STRK
-    100_000_000_000_000_000,      // 0.1 STRK
-    500_000_000_000_000_000,      // 0.5 STRK
-];
-
-/// USDC denominations (6 decimals, base unit = micro-USDC)
-const USDC_DENOMINATIONS: [u64; 6] = [
-    1_000_000,     // 1 USDC
-    5_000_000,     // 5 USDC
-    10_000_000,    // 10 USDC
-    50_000_000,    // 50 USDC
-    100_000_000,   // 100 USDC
-    500_000_000,   // 500 USDC
-];
-
-/// SAGE denominations (18 decimals)
-const SAGE_DENOMINATIONS: [u64; 6] = [
-    100_000_000_000_000_000,     // 0.1 SAGE
-    500_000_000_000_000_000,     // 0.5 SAGE
-    1_000_000_000_000_000_000,   // 1 SAGE
-    5_000_000_000_000_000_000,   // 5 SAGE
-    10_000_000_000_000_000,      // 0.01 SAGE
-    50_000_000_000_000_000,      // 0.05 SAGE
-];
-
-/// Backward-compatible alias
-pub const BTC_DENOMINATIONS_SATS: [u64; 6] = BTC_DENOMINATIONS;
+/// Maximum length of a client-supplied `client_ref`
+const MAX_CLIENT_REF_LEN: usize = 64;
 
 // ---------------------------------------------------------------------------
 // App state (shared via Axum's State extractor)
 // ---------------------------------------------------------------------------
 
 pub struct AppState {
-    pub queue: BatchQueue,
+    pub queue: Arc<BatchQueue>,
     pub store: Arc<InMemoryStore>,
     pub config: RelayerConfig,
     pub tree_sync: Option<Arc<TreeSyncService>>,
+    pub quarantine: Quarantine,
+    /// Prover stage latencies, present when `VM31_LATENCY_STATS` is enabled.
+    pub latency: Option<Arc<LatencyStats>>,
+    pub prover_pause: Arc<ProverPause>,
+    /// Set once a shutdown signal arrives; `/submit` then answers 503.
+    pub shutdown: Arc<Shutdown>,
+    /// Rate-limit abuse alerting, present when `VM31_ABUSE_ALERT_THRESHOLD` is set.
+    pub abuse: Option<AbuseTracker>,
+    /// Prometheus counters, present when `VM31_METRICS_ENABLED` is set.
+    pub metrics: Option<Arc<Metrics>>,
+    /// Pool asset registry fetched at startup (`VM31_ASSET_DISCOVERY`).
+    pub assets: Option<Vec<OnChainAsset>>,
+    /// Weighted read endpoints, present when `VM31_RPC_WEIGHTS` is set.
+    pub rpc_pool: Option<Arc<RpcPool>>,
+    /// Pool RPC circuit breaker, absent when `VM31_RPC_BREAKER_THRESHOLD` is 0.
+    pub rpc_breaker: Option<Arc<CircuitBreaker>>,
+    /// The prover's pool input checks, reused by `POST /validate`.
+    pub input_checks: InputChecks,
+    /// Recipient denylist, present when `VM31_PUBKEY_DENYLIST_PATH` is set.
+    pub pubkey_denylist: Option<Arc<SharedDenylist>>,
+    /// Open `/batch/{id}/events` streams, reported at `/stats`.
+    pub stream_connections: Arc<StreamConnections>,
+}
+
+/// Count of open streaming connections.
+#[derive(Default)]
+pub struct StreamConnections(AtomicUsize);
+
+impl StreamConnections {
+    pub fn count(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Counts a connection until the returned slot is dropped.
+    fn open(self: &Arc<Self>) -> StreamSlot {
+        self.0.fetch_add(1, Ordering::Relaxed);
+        StreamSlot(self.clone())
+    }
+}
+
+/// One open streaming connection, counted in `StreamConnections`.
+struct StreamSlot(Arc<StreamConnections>);
+
+impl Drop for StreamSlot {
+    fn drop(&mut self) {
+        self.0 .0.fetch_sub(1, Ordering::Relaxed);
+    }
 }
 
 // ---------------------------------------------------------------------------
@@ -125,6 +134,14 @@ pub enum SubmitRequest {
         asset_id: u32,
         recipient_pubkey: [u32; 4],
         recipient_viewing_key: [u32; 4],
+        /// Blinding for the deposited note. When absent the relayer generates
+        /// one and returns it in the submit response; the client needs it to
+        /// reconstruct the note.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        blinding: Option<[u32; 4]>,
+        /// Opaque client reference for tracking this tx through batching (see `client_ref()`).
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        client_ref: Option<String>,
     },
     Withdraw {
         amount: u64,
@@ -138,6 +155,16 @@ pub enum SubmitRequest {
         /// H(payout, credit, asset, amount, idx, salt) is not precomputable.
         #[serde(default)]
         binding_salt: Option<[u32; 8]>,
+        /// Starknet address the withdrawn tokens are paid out to. Required on
+        /// `/submit`; `withdrawal_binding` must commit to it.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        payout_recipient: Option<String>,
+        /// Starknet address credited when the withdrawal is bridged to a
+        /// confidential transfer account. Required with `payout_recipient`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        credit_recipient: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        client_ref: Option<String>,
     },
     Transfer {
         amount: u64,
@@ -147,9 +174,20 @@ pub enum SubmitRequest {
         sender_viewing_key: [u32; 4],
         input_notes: [InputNoteJson; 2],
         merkle_root: [u32; 8],
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        client_ref: Option<String>,
     },
 }
 
+/// Where a withdrawal pays out, as validated `0x`-prefixed lowercase felt
+/// hex. Not part of `PendingTx`, so it travels next to the tx through the
+/// queue like the client ref.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WithdrawalRecipient {
+    pub payout: String,
+    pub credit: String,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct NoteJson {
     pub owner_pubkey: [u32; 4],
@@ -174,21 +212,33 @@ pub struct InputNoteJson {
 
 /// ECIES-encrypted submission envelope (privacy gap #1).
 /// The client generates an ephemeral x25519 keypair, performs ECDH with the
-/// relayer's static public key, derives AES-256-GCM key via HKDF-SHA256,
-/// and encrypts the JSON SubmitRequest. The relayer decrypts in the prover's
+/// relayer's static public key, derives an AEAD key via HKDF-SHA256 and
+/// encrypts the JSON SubmitRequest with the cipher selected by `version`
+/// (see `ecies::SCHEMES`). The relayer decrypts in the prover's
 /// spawn_blocking scope; plaintext never persists in memory outside that task.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct EncryptedSubmitRequest {
     /// Ephemeral x25519 public key (32 bytes, hex-encoded)
     pub ephemeral_pubkey: String,
-    /// AES-256-GCM ciphertext (base64-encoded)
+    /// AEAD ciphertext (base64-encoded)
     pub ciphertext: String,
-    /// AES-256-GCM nonce (12 bytes, hex-encoded)
+    /// AEAD nonce (12 bytes, hex-encoded)
     pub nonce: String,
-    /// Protocol version for forward compatibility
+    /// ECIES scheme version: 1 = AES-256-GCM, 2 = ChaCha20-Poly1305
     pub version: u8,
 }
 
+/// Decrypted plaintext of an `EncryptedSubmitRequest`: the SubmitRequest
+/// fields, plus an optional sealing time (unix seconds) checked against
+/// `VM31_ECIES_MAX_SKEW_SECS`.
+#[derive(Debug, Deserialize)]
+pub struct SealedSubmitRequest {
+    #[serde(flatten)]
+    pub request: SubmitRequest,
+    #[serde(default)]
+    pub timestamp: Option<u64>,
+}
+
 /// Unified submission body: either plaintext or encrypted
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(untagged)]
@@ -201,11 +251,12 @@ pub enum SubmitBody {
 // Input validation
 // ---------------------------------------------------------------------------
 
-/// Validates a u32 is within the M31 field (0..2^31-1).
+/// Validates a u32 is a canonical M31 field element (0..=2^31-2).
+/// The modulus itself (2^31-1) is a non-canonical encoding of zero and is rejected.
 fn validate_m31(val: u32, field_name: &str) -> Result<M31, AppError> {
-    if val > M31_MODULUS {
+    if val >= M31_MODULUS {
         return Err(AppError::BadRequest(format!(
-            "{field_name}: value {val} exceeds M31 field modulus"
+            "{field_name}: value {val} is not a canonical M31 element (must be < {M31_MODULUS})"
         )));
     }
     Ok(M31::from_u32_unchecked(val))
@@ -233,44 +284,139 @@ fn validate_m31_8(arr: [u32; 8], field_name: &str) -> Result<[M31; 8], AppError>
     ])
 }
 
-fn validate_amount(amount: u64) -> Result<(), AppError> {
+/// Fresh deposit blinding of canonical M31 limbs, drawn from the OS-seeded
+/// thread RNG.
+fn random_blinding() -> [M31; 4] {
+    let mut rng = rand::thread_rng();
+    [(); 4].map(|_| M31::from_u32_unchecked(rng.gen_range(0..M31_MODULUS)))
+}
+
+/// Blinding of a deposit's note, returned to the client at submission.
+fn deposit_blinding(tx: &PendingTx) -> Option<[u32; 4]> {
+    match tx {
+        PendingTx::Deposit { blinding, .. } => Some(m31s(blinding)),
+        _ => None,
+    }
+}
+
+/// Idempotency-store key for the blinding the relayer drew for a deposit.
+fn drawn_blinding_key(idem_key: &str) -> String {
+    format!("blinding:{idem_key}")
+}
+
+/// Keeps the blinding drawn for a deposit that didn't bring its own, for as
+/// long as its idempotency key, so a retry answered as a duplicate can
+/// return it again. Sealed when `VM31_STORAGE_KEY` is set. Returns the
+/// store key it used.
+async fn remember_drawn_blinding(
+    store: &InMemoryStore,
+    req: &SubmitRequest,
+    tx: &PendingTx,
+    idem_key: &str,
+) -> Result<Option<String>, AppError> {
+    let (SubmitRequest::Deposit { blinding: None, .. }, Some(blinding)) = (req, deposit_blinding(tx)) else {
+        return Ok(None);
+    };
+    let value = match store.storage_encryption() {
+        Some(enc) => hex::encode(enc.seal(&blinding).map_err(|e| AppError::Internal(e.to_string()))?),
+        None => json!(blinding).to_string(),
+    };
+    let key = drawn_blinding_key(idem_key);
+    store
+        .check_and_set(&key, &value)
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+    Ok(Some(key))
+}
+
+/// The blinding `remember_drawn_blinding` kept under `idem_key`, if any.
+async fn drawn_blinding(store: &InMemoryStore, idem_key: &str) -> Option<[u32; 4]> {
+    let value = store.get_result(&drawn_blinding_key(idem_key)).await.ok()??;
+    match store.storage_encryption() {
+        Some(enc) => enc.open(&hex::decode(value).ok()?).ok(),
+        None => serde_json::from_str(&value).ok(),
+    }
+}
+
+/// Validates an 8-limb digest that must not be all zeros.
+/// All-zero is reserved internally as the "no real merkle root" sentinel.
+fn validate_nonzero_m31_8(arr: [u32; 8], field_name: &str) -> Result<[M31; 8], AppError> {
+    if arr == [0; 8] {
+        return Err(AppError::BadRequest(format!(
+            "{field_name} must not be all zeros (reserved sentinel)"
+        )));
+    }
+    validate_m31_8(arr, field_name)
+}
+
+fn validate_amount(amount: u64) -> Result<NoteAmount, AppError> {
     if amount == 0 {
         return Err(AppError::BadRequest("amount must be > 0".into()));
     }
-    if amount > MAX_NOTE_AMOUNT {
-        return Err(AppError::BadRequest("amount exceeds maximum".into()));
+    NoteAmount::new(amount).ok_or_else(|| AppError::BadRequest("amount exceeds maximum".into()))
+}
+
+/// Validates a tx-level `asset_id` against the M31 field, like `note.asset_id`.
+fn validate_asset_id(asset_id: u32) -> Result<u32, AppError> {
+    validate_m31(asset_id, "asset_id").map(|m| m.0)
+}
+
+/// Rejects a tx whose asset, or any input note's asset, is outside
+/// `VM31_ALLOWED_ASSET_IDS` (empty = no restriction).
+fn check_allowed_assets(tx: &PendingTx, allowed: &[u32]) -> Result<(), AppError> {
+    if allowed.is_empty() {
+        return Ok(());
+    }
+    let (asset_id, note_assets) = match tx {
+        PendingTx::Deposit { asset_id, .. } => (*asset_id, vec![]),
+        PendingTx::Withdraw { asset_id, note, .. } => (*asset_id, vec![("note.asset_id", note.asset_id.0)]),
+        PendingTx::Transfer { asset_id, input_notes, .. } => (
+            *asset_id,
+            vec![
+                ("input[0].note.asset_id", input_notes[0].0.asset_id.0),
+                ("input[1].note.asset_id", input_notes[1].0.asset_id.0),
+            ],
+        ),
+    };
+    for (field_name, id) in std::iter::once(("asset_id", asset_id)).chain(note_assets) {
+        if !allowed.contains(&id) {
+            return Err(AppError::BadRequest(format!("{field_name}: asset {id} is not accepted by this relayer")));
+        }
     }
     Ok(())
 }
 
-/// Returns the denomination whitelist for a given asset ID, if any.
-fn denominations_for_asset(asset_id: u32) -> Option<&'static [u64]> {
-    match asset_id {
-        0 => Some(&BTC_DENOMINATIONS),
-        1 => Some(&SAGE_DENOMINATIONS),
-        2 => Some(&ETH_DENOMINATIONS),
-        3 => Some(&STRK_DENOMINATIONS),
-        4 => Some(&USDC_DENOMINATIONS),
-        _ => None, // Unknown asset — no denomination restriction
-    }
+/// Decodes a note's two-limb amount.
+fn note_amount(n: &NoteJson, field_name: &str) -> Result<NoteAmount, AppError> {
+    NoteAmount::from_limbs(n.amount_lo, n.amount_hi)
+        .ok_or_else(|| AppError::BadRequest(format!("{field_name}: amount limbs out of range")))
+}
+
+/// Combined value of a transfer's input notes.
+fn input_total(input_notes: &[InputNoteJson; 2]) -> Result<NoteAmount, AppError> {
+    note_amount(&input_notes[0].note, "input[0].note")?
+        .checked_add(note_amount(&input_notes[1].note, "input[1].note")?)
+        .ok_or_else(|| AppError::BadRequest("input note amounts overflow".into()))
 }
 
 /// Validates that deposits use a standard denomination for the asset.
 /// Unknown assets pass through without restriction (forward-compatible).
-fn validate_denomination(amount: u64, asset_id: u32) -> Result<(), AppError> {
-    if let Some(denoms) = denominations_for_asset(asset_id) {
-        if !denoms.contains(&amount) {
-            return Err(AppError::BadRequest(format!(
-                "Deposits must use standard denominations for asset {asset_id}. Got {amount}"
-            )));
+/// Legacy denominations are accepted with a deprecation warning.
+fn validate_denomination(
+    amount: u64,
+    asset_id: u32,
+    denominations: &Denominations,
+) -> Result<(), AppError> {
+    match denominations.check(asset_id, amount) {
+        DenominationMatch::Standard => Ok(()),
+        DenominationMatch::Legacy => {
+            warn!(asset_id, amount, "deposit uses a legacy denomination (VM31_LEGACY_DENOMINATIONS)");
+            Ok(())
         }
+        DenominationMatch::Rejected => Err(AppError::BadRequest(format!(
+            "Deposits must use standard denominations for asset {asset_id}. Got {amount}"
+        ))),
     }
-    Ok(())
-}
-
-/// Backward-compatible alias for BTC-only validation.
-fn validate_btc_denomination(amount: u64, asset_id: u32) -> Result<(), AppError> {
-    validate_denomination(amount, asset_id)
 }
 
 fn validate_merkle_path(p: &MerklePathJson) -> Result<MerklePath, AppError> {
@@ -291,6 +437,219 @@ fn validate_merkle_path(p: &MerklePathJson) -> Result<MerklePath, AppError> {
     })
 }
 
+/// Requires each transfer input's merkle path to lead from the input note's
+/// commitment to the transfer's one `merkle_root`, hashing up the path with
+/// the pool's Poseidon2-M31 node hash. A path for another root, or with a
+/// wrong sibling, can't be proven and would only fail in the prover.
+fn check_input_roots(tx: &PendingTx) -> Result<(), AppError> {
+    let PendingTx::Transfer { input_notes, merkle_root, .. } = tx else {
+        return Ok(());
+    };
+    for (i, (note, _, path)) in input_notes.iter().enumerate() {
+        if !verify_merkle_proof(merkle_root, &note.commitment(), path) {
+            return Err(AppError::BadRequest(format!(
+                "input_notes[{i}].merkle_path does not lead from the note's commitment to merkle_root"
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Validates a Starknet address: hex (optionally `0x`-prefixed), at most 64
+/// digits, nonzero and below the field prime. Returns it as `0x` + lowercase.
+fn validate_felt_address(value: &str, field_name: &str) -> Result<String, AppError> {
+    let digits = value.strip_prefix("0x").unwrap_or(value);
+    if digits.is_empty() || digits.len() > 64 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(AppError::BadRequest(format!(
+            "{field_name} must be a Starknet address (hex, at most 64 digits)"
+        )));
+    }
+    let digits = digits.to_ascii_lowercase();
+    if digits.chars().all(|c| c == '0') {
+        return Err(AppError::BadRequest(format!("{field_name} must not be zero")));
+    }
+    // Equal-length lowercase hex compares like the numbers it encodes
+    if format!("{digits:0>64}").as_str() >= FELT_PRIME_HEX {
+        return Err(AppError::BadRequest(format!(
+            "{field_name} is not below the Starknet field prime"
+        )));
+    }
+    Ok(format!("0x{digits}"))
+}
+
+fn validate_client_ref(client_ref: &str) -> Result<(), AppError> {
+    if client_ref.is_empty() || client_ref.len() > MAX_CLIENT_REF_LEN {
+        return Err(AppError::BadRequest(format!(
+            "client_ref must be 1..={MAX_CLIENT_REF_LEN} characters"
+        )));
+    }
+    if !client_ref
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'))
+    {
+        return Err(AppError::BadRequest("client_ref contains invalid characters".into()));
+    }
+    Ok(())
+}
+
+/// Request header with a client-chosen `/submit` idempotency key.
+const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+const MAX_IDEMPOTENCY_KEY_LEN: usize = 128;
+
+/// The `Idempotency-Key` header, if sent: 1..=128 characters of
+/// `[A-Za-z0-9-_.:]`.
+fn client_idempotency_key(headers: &HeaderMap) -> Result<Option<&str>, AppError> {
+    let Some(value) = headers.get(IDEMPOTENCY_KEY_HEADER) else {
+        return Ok(None);
+    };
+    let key = value
+        .to_str()
+        .ok()
+        .filter(|k| !k.is_empty() && k.len() <= MAX_IDEMPOTENCY_KEY_LEN)
+        .filter(|k| k.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':')))
+        .ok_or_else(|| {
+            AppError::BadRequest(format!(
+                "Idempotency-Key must be 1..={MAX_IDEMPOTENCY_KEY_LEN} characters of [A-Za-z0-9-_.:]"
+            ))
+        })?;
+    Ok(Some(key))
+}
+
+/// The key a submission dedups on: the client's `Idempotency-Key`, scoped
+/// to its API key, or else `computed` from the payload. The header lets a
+/// retry dedupe even when it re-encrypts or re-serializes the body.
+fn submission_idempotency_key(api_key: &str, client_key: Option<&str>, computed: String) -> String {
+    match client_key {
+        Some(key) => {
+            let mut hasher = Sha256::new();
+            hasher.update(b"vm31-client-idem-v1");
+            hasher.update(api_key.as_bytes());
+            hasher.update(b"\0");
+            hasher.update(key.as_bytes());
+            format!("client:{:x}", hasher.finalize())
+        }
+        None => computed,
+    }
+}
+
+/// Scope a `client_ref` to the submitting API key.
+/// Only this digest is stored, so refs can't be read back or matched by other clients.
+fn scoped_client_ref(api_key: &str, client_ref: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(api_key.as_bytes());
+    hasher.update(b"\0");
+    hasher.update(client_ref.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+/// Digest identifying the API key that queued a tx, so only it can cancel
+/// the tx. The raw key never reaches the queue or its WAL.
+fn submitter_digest(api_key: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(b"vm31-submitter-v1");
+    hasher.update(api_key.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+/// Store key for a digest of a withdrawal's binding, so raw bindings aren't
+/// retained; `None` for other tx types.
+fn withdrawal_binding_key(req: &SubmitRequest) -> Option<String> {
+    let SubmitRequest::Withdraw { withdrawal_binding, .. } = req else {
+        return None;
+    };
+    let mut h = Sha256::new();
+    h.update(b"vm31-binding-v1");
+    hash_u32s(&mut h, withdrawal_binding);
+    Some(format!("binding:{:x}", h.finalize()))
+}
+
+/// Rejects a withdrawal whose binding `/submit` already accepted within the
+/// last `window_secs`, covering the lag before the first withdrawal's
+/// nullifier shows as spent on-chain. A window of 0 disables the check.
+/// Returns the key it recorded, if any.
+fn check_binding_replay(
+    store: &InMemoryStore,
+    req: &SubmitRequest,
+    window_secs: u64,
+    now: u64,
+) -> Result<Option<String>, AppError> {
+    let Some(key) = withdrawal_binding_key(req).filter(|_| window_secs > 0) else {
+        return Ok(None);
+    };
+    if !store.check_and_record_binding(&key, window_secs, now) {
+        return Err(AppError::Conflict(format!(
+            "withdrawal_binding was already submitted in the last {window_secs}s; a withdrawal is relayed once"
+        )));
+    }
+    Ok(Some(key))
+}
+
+/// Reserves a withdrawal's binding in the idempotency store. A binding already
+/// held by a different submission is a conflict: the client reused a salt
+/// (with the same payout, credit, asset, amount and index), and two equal
+/// bindings on-chain would link the withdrawals. Re-reserving under the
+/// same idempotency key is allowed. Returns the key if this call reserved it.
+async fn reserve_withdrawal_binding(
+    store: &InMemoryStore,
+    req: &SubmitRequest,
+    idem_key: &str,
+) -> Result<Option<String>, AppError> {
+    let Some(key) = withdrawal_binding_key(req) else {
+        return Ok(None);
+    };
+    match store
+        .check_and_set(&key, idem_key)
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?
+    {
+        Some(holder) if holder != idem_key => Err(AppError::Conflict(
+            "withdrawal_binding already used by another withdrawal; use a fresh binding_salt".into(),
+        )),
+        Some(_) => Ok(None),
+        None => Ok(Some(key)),
+    }
+}
+
+/// Checks a deposit against its asset's `VM31_DEPOSIT_LIMITS`: the amount
+/// against `max_amount`, then the key's running total for the UTC day of
+/// `now` against `daily_cap`. A deposit within the cap is counted toward it,
+/// and the counted `(key, day, amount)` returned.
+async fn validate_deposit_limits(
+    store: &InMemoryStore,
+    limits: &HashMap<u32, DepositLimit>,
+    api_key: &str,
+    tx: &PendingTx,
+    now: u64,
+) -> Result<Option<(String, u64, u64)>, AppError> {
+    let PendingTx::Deposit { amount, asset_id, .. } = tx else {
+        return Ok(None);
+    };
+    let Some(limit) = limits.get(asset_id) else {
+        return Ok(None);
+    };
+    if let Some(max) = limit.max_amount {
+        if *amount > max {
+            return Err(AppError::BadRequest(format!(
+                "deposit amount {amount} exceeds the asset {asset_id} maximum of {max}"
+            )));
+        }
+    }
+    let Some(cap) = limit.daily_cap else {
+        return Ok(None);
+    };
+    let day = utc_day(now);
+    let key = format!("deposit:{api_key}:{asset_id}");
+    let within = store
+        .check_and_add_volume(&key, day, *amount, cap)
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+    if !within {
+        // The total starts over at the next UTC midnight
+        return Err(AppError::RateLimited(Some((day + 1) * 86_400 - now)));
+    }
+    Ok(Some((key, day, *amount)))
+}
+
 fn validate_note(n: &NoteJson) -> Result<Note, AppError> {
     Ok(Note {
         owner_pubkey: validate_m31_4(n.owner_pubkey, "note.owner_pubkey")?,
@@ -301,26 +660,127 @@ fn validate_note(n: &NoteJson) -> Result<Note, AppError> {
     })
 }
 
+fn m31s<const N: usize>(v: &[M31; N]) -> [u32; N] {
+    v.map(|m| m.0)
+}
+
+impl From<&Note> for NoteJson {
+    fn from(n: &Note) -> Self {
+        Self {
+            owner_pubkey: m31s(&n.owner_pubkey),
+            asset_id: n.asset_id.0,
+            amount_lo: n.amount_lo.0,
+            amount_hi: n.amount_hi.0,
+            blinding: m31s(&n.blinding),
+        }
+    }
+}
+
+impl From<&MerklePath> for MerklePathJson {
+    fn from(p: &MerklePath) -> Self {
+        Self {
+            siblings: p.siblings.iter().map(m31s).collect(),
+            index: p.index,
+        }
+    }
+}
+
+/// Wire form of an already-validated tx (queue WAL). `binding_salt`,
+/// `client_ref` and the withdrawal recipients are not part of `PendingTx` and
+/// come back as `None`; see `with_withdrawal_recipient`.
+impl From<&PendingTx> for SubmitRequest {
+    fn from(tx: &PendingTx) -> Self {
+        match tx {
+            PendingTx::Deposit {
+                amount,
+                asset_id,
+                recipient_pubkey,
+                recipient_viewing_key,
+                blinding,
+            } => SubmitRequest::Deposit {
+                amount: *amount,
+                asset_id: *asset_id,
+                recipient_pubkey: m31s(recipient_pubkey),
+                recipient_viewing_key: m31s(recipient_viewing_key),
+                blinding: Some(m31s(blinding)),
+                client_ref: None,
+            },
+            PendingTx::Withdraw {
+                amount,
+                asset_id,
+                note,
+                spending_key,
+                merkle_path,
+                merkle_root,
+                withdrawal_binding,
+            } => SubmitRequest::Withdraw {
+                amount: *amount,
+                asset_id: *asset_id,
+                note: note.into(),
+                spending_key: m31s(spending_key),
+                merkle_path: merkle_path.into(),
+                merkle_root: m31s(merkle_root),
+                withdrawal_binding: m31s(withdrawal_binding),
+                binding_salt: None,
+                payout_recipient: None,
+                credit_recipient: None,
+                client_ref: None,
+            },
+            PendingTx::Transfer {
+                amount,
+                asset_id,
+                recipient_pubkey,
+                recipient_viewing_key,
+                sender_viewing_key,
+                input_notes,
+                merkle_root,
+            } => SubmitRequest::Transfer {
+                amount: *amount,
+                asset_id: *asset_id,
+                recipient_pubkey: m31s(recipient_pubkey),
+                recipient_viewing_key: m31s(recipient_viewing_key),
+                sender_viewing_key: m31s(sender_viewing_key),
+                input_notes: input_notes.each_ref().map(|(note, spending_key, merkle_path)| {
+                    InputNoteJson {
+                        note: note.into(),
+                        spending_key: m31s(spending_key),
+                        merkle_path: merkle_path.into(),
+                    }
+                }),
+                merkle_root: m31s(merkle_root),
+                client_ref: None,
+            },
+        }
+    }
+}
+
 // ---------------------------------------------------------------------------
 // Conversion with validation
 // ---------------------------------------------------------------------------
 
 impl SubmitRequest {
-    pub fn validate_and_convert(&self) -> Result<PendingTx, AppError> {
+    pub fn validate_and_convert(&self, denominations: &Denominations) -> Result<PendingTx, AppError> {
         match self {
             SubmitRequest::Deposit {
                 amount,
                 asset_id,
                 recipient_pubkey,
                 recipient_viewing_key,
+                blinding,
+                ..
             } => {
                 validate_amount(*amount)?;
-                validate_btc_denomination(*amount, *asset_id)?;
+                let asset_id = validate_asset_id(*asset_id)?;
+                validate_denomination(*amount, asset_id, denominations)?;
                 Ok(PendingTx::Deposit {
                     amount: *amount,
-                    asset_id: *asset_id,
+                    asset_id,
                     recipient_pubkey: validate_m31_4(*recipient_pubkey, "recipient_pubkey")?,
                     recipient_viewing_key: validate_m31_4(*recipient_viewing_key, "recipient_viewing_key")?,
+                    blinding: match blinding {
+                        Some(b) => validate_m31_4(*b, "blinding")?,
+                        None => random_blinding(),
+                    },
                 })
             }
             SubmitRequest::Withdraw {
@@ -334,14 +794,15 @@ impl SubmitRequest {
                 ..
             } => {
                 validate_amount(*amount)?;
+                self.withdrawal_recipient()?;
                 Ok(PendingTx::Withdraw {
                     amount: *amount,
-                    asset_id: *asset_id,
+                    asset_id: validate_asset_id(*asset_id)?,
                     note: validate_note(note)?,
                     spending_key: validate_m31_4(*spending_key, "spending_key")?,
                     merkle_path: validate_merkle_path(merkle_path)?,
-                    merkle_root: validate_m31_8(*merkle_root, "merkle_root")?,
-                    withdrawal_binding: validate_m31_8(*withdrawal_binding, "withdrawal_binding")?,
+                    merkle_root: validate_nonzero_m31_8(*merkle_root, "merkle_root")?,
+                    withdrawal_binding: validate_nonzero_m31_8(*withdrawal_binding, "withdrawal_binding")?,
                 })
             }
             SubmitRequest::Transfer {
@@ -352,13 +813,14 @@ impl SubmitRequest {
                 sender_viewing_key,
                 input_notes,
                 merkle_root,
+                ..
             } => {
                 validate_amount(*amount)?;
                 let in0 = &input_notes[0];
                 let in1 = &input_notes[1];
                 Ok(PendingTx::Transfer {
                     amount: *amount,
-                    asset_id: *asset_id,
+                    asset_id: validate_asset_id(*asset_id)?,
                     recipient_pubkey: validate_m31_4(*recipient_pubkey, "recipient_pubkey")?,
                     recipient_viewing_key: validate_m31_4(*recipient_viewing_key, "recipient_viewing_key")?,
                     sender_viewing_key: validate_m31_4(*sender_viewing_key, "sender_viewing_key")?,
@@ -374,28 +836,277 @@ impl SubmitRequest {
                             validate_merkle_path(&in1.merkle_path)?,
                         ),
                     ],
-                    merkle_root: validate_m31_8(*merkle_root, "merkle_root")?,
+                    merkle_root: validate_nonzero_m31_8(*merkle_root, "merkle_root")?,
                 })
             }
         }
     }
 
+    /// Requires every merkle path to prove membership in a tree of exactly
+    /// `depth` levels: `depth` siblings and a leaf index below `2^depth`.
+    /// A path of any other shape can't verify and would only fail in the
+    /// prover.
+    pub fn validate_path_depths(&self, depth: usize) -> Result<(), AppError> {
+        let paths: Vec<(&MerklePathJson, String)> = match self {
+            SubmitRequest::Deposit { .. } => return Ok(()),
+            SubmitRequest::Withdraw { merkle_path, .. } => vec![(merkle_path, "merkle_path".into())],
+            SubmitRequest::Transfer { input_notes, .. } => input_notes
+                .iter()
+                .enumerate()
+                .map(|(i, n)| (&n.merkle_path, format!("input_notes[{i}].merkle_path")))
+                .collect(),
+        };
+        for (path, field) in paths {
+            if path.siblings.len() != depth {
+                return Err(AppError::BadRequest(format!(
+                    "{field} has {} siblings, expected {depth} for the pool's tree depth",
+                    path.siblings.len()
+                )));
+            }
+            if (path.index as u64).checked_shr(depth as u32).unwrap_or(0) != 0 {
+                return Err(AppError::BadRequest(format!(
+                    "{field} index {} is out of range for tree depth {depth}",
+                    path.index
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Value of a transfer's change note: the input notes' sum minus the
+    /// transfer amount. `None` for deposits and withdrawals. Zero means the
+    /// inputs are spent exactly and the change note carries no value.
+    pub fn transfer_change(&self) -> Result<Option<NoteAmount>, AppError> {
+        let SubmitRequest::Transfer { amount, input_notes, .. } = self else {
+            return Ok(None);
+        };
+        input_total(input_notes)?
+            .checked_sub(validate_amount(*amount)?)
+            .map(Some)
+            .ok_or_else(|| AppError::BadRequest("transfer amount exceeds the sum of input notes".into()))
+    }
+
+    /// Rejects key relationships that are certainly client bugs.
+    ///
+    /// Deliberately conservative: only flags a spending key that is all-zero,
+    /// equals the public key of the note it spends, or is reused as the
+    /// sender's viewing key. Anything else is left to the prover.
+    pub fn validate_key_relationships(&self) -> Result<(), AppError> {
+        fn check_spending_key(sk: &[u32; 4], owner_pubkey: &[u32; 4], field: &str) -> Result<(), AppError> {
+            if *sk == [0; 4] {
+                return Err(AppError::BadRequest(format!("{field} must not be all zeros")));
+            }
+            if sk == owner_pubkey {
+                return Err(AppError::BadRequest(format!(
+                    "{field} equals the note's owner_pubkey — pass the secret spending key, not the public key"
+                )));
+            }
+            Ok(())
+        }
+
+        match self {
+            SubmitRequest::Deposit { .. } => Ok(()),
+            SubmitRequest::Withdraw {
+                note, spending_key, ..
+            } => check_spending_key(spending_key, &note.owner_pubkey, "spending_key"),
+            SubmitRequest::Transfer {
+                sender_viewing_key,
+                input_notes,
+                ..
+            } => {
+                for (i, input) in input_notes.iter().enumerate() {
+                    let field = format!("input[{i}].spending_key");
+                    check_spending_key(&input.spending_key, &input.note.owner_pubkey, &field)?;
+                    if input.spending_key == *sender_viewing_key {
+                        return Err(AppError::BadRequest(format!(
+                            "sender_viewing_key equals {field} — viewing keys must differ from spending keys"
+                        )));
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Rejects deposits and transfers whose `recipient_pubkey[0]` falls in the
+    /// inclusive range reserved for relayer decoy notes.
+    pub fn validate_not_decoy_recipient(&self, (start, end): (u32, u32)) -> Result<(), AppError> {
+        let Some(recipient) = self.recipient_pubkey() else {
+            return Ok(());
+        };
+        if (start..=end).contains(&recipient[0]) {
+            return Err(AppError::BadRequest(
+                "recipient_pubkey is in the reserved decoy key range".into(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Rejects deposits and transfers to a recipient on the denylist.
+    pub fn validate_recipient_permitted(&self, denylist: &PubkeyDenylist) -> Result<(), AppError> {
+        match self.recipient_pubkey() {
+            Some(recipient) if denylist.contains(recipient) => {
+                Err(AppError::BadRequest("recipient not permitted".into()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn recipient_pubkey(&self) -> Option<&[u32; 4]> {
+        match self {
+            SubmitRequest::Deposit { recipient_pubkey, .. }
+            | SubmitRequest::Transfer { recipient_pubkey, .. } => Some(recipient_pubkey),
+            SubmitRequest::Withdraw { .. } => None,
+        }
+    }
+
+    /// A withdrawal's validated payout and credit recipients; `None` for other
+    /// kinds and for withdrawals without them (only `/submit` requires them).
+    pub fn withdrawal_recipient(&self) -> Result<Option<WithdrawalRecipient>, AppError> {
+        let SubmitRequest::Withdraw { payout_recipient, credit_recipient, .. } = self else {
+            return Ok(None);
+        };
+        match (payout_recipient, credit_recipient) {
+            (Some(payout), Some(credit)) => Ok(Some(WithdrawalRecipient {
+                payout: validate_felt_address(payout, "payout_recipient")?,
+                credit: validate_felt_address(credit, "credit_recipient")?,
+            })),
+            (None, None) => Ok(None),
+            _ => Err(AppError::BadRequest(
+                "payout_recipient and credit_recipient must be given together".into(),
+            )),
+        }
+    }
+
+    /// Sets a withdrawal's recipients on its wire form (WAL, dead letters).
+    /// No-op for other kinds.
+    pub fn with_withdrawal_recipient(mut self, recipient: Option<&WithdrawalRecipient>) -> Self {
+        if let SubmitRequest::Withdraw { payout_recipient, credit_recipient, .. } = &mut self {
+            *payout_recipient = recipient.map(|r| r.payout.clone());
+            *credit_recipient = recipient.map(|r| r.credit.clone());
+        }
+        self
+    }
+
+    /// Opaque client-supplied reference, carried through batching so the client
+    /// can find its transaction's outcome. Never used for dedup or keying.
+    pub fn client_ref(&self) -> Option<&str> {
+        match self {
+            SubmitRequest::Deposit { client_ref, .. }
+            | SubmitRequest::Withdraw { client_ref, .. }
+            | SubmitRequest::Transfer { client_ref, .. } => client_ref.as_deref(),
+        }
+    }
+
     /// Compute a deterministic idempotency key from the payload via SHA-256.
     /// Collision-resistant — prevents accidental deduplication of distinct requests.
+    ///
+    /// Hashes an explicit, versioned byte encoding (fixed field order,
+    /// little-endian integers, length-prefixed vectors) rather than serde
+    /// output, so keys stay stable across serde upgrades and struct changes.
+    /// `client_ref` is excluded so it never influences deduplication, and so
+    /// are the withdrawal recipients, which `withdrawal_binding` commits to.
     pub fn idempotency_key(&self) -> String {
-        match serde_json::to_vec(self) {
-            Ok(json) => format!("{:x}", Sha256::digest(&json)),
-            // If serialization fails, generate a unique key so we don't accidentally
-            // deduplicate unrelated requests.
-            Err(_) => {
-                use std::time::SystemTime;
-                let ts = SystemTime::now()
-                    .duration_since(SystemTime::UNIX_EPOCH)
-                    .unwrap_or_default()
-                    .as_nanos();
-                format!("err:{ts:032x}")
+        let mut h = Sha256::new();
+        h.update(b"vm31-idem-v1");
+        match self {
+            SubmitRequest::Deposit {
+                amount,
+                asset_id,
+                recipient_pubkey,
+                recipient_viewing_key,
+                blinding,
+                ..
+            } => {
+                h.update([0u8]);
+                h.update(amount.to_le_bytes());
+                h.update(asset_id.to_le_bytes());
+                hash_u32s(&mut h, recipient_pubkey);
+                hash_u32s(&mut h, recipient_viewing_key);
+                // Appended only when present, so keys of deposits without a
+                // client blinding are unchanged
+                if let Some(b) = blinding {
+                    hash_u32s(&mut h, b);
+                }
+            }
+            SubmitRequest::Withdraw {
+                amount,
+                asset_id,
+                note,
+                spending_key,
+                merkle_path,
+                merkle_root,
+                withdrawal_binding,
+                binding_salt,
+                ..
+            } => {
+                h.update([1u8]);
+                h.update(amount.to_le_bytes());
+                h.update(asset_id.to_le_bytes());
+                note.hash_canonical(&mut h);
+                hash_u32s(&mut h, spending_key);
+                merkle_path.hash_canonical(&mut h);
+                hash_u32s(&mut h, merkle_root);
+                hash_u32s(&mut h, withdrawal_binding);
+                match binding_salt {
+                    Some(salt) => {
+                        h.update([1u8]);
+                        hash_u32s(&mut h, salt);
+                    }
+                    None => h.update([0u8]),
+                }
+            }
+            SubmitRequest::Transfer {
+                amount,
+                asset_id,
+                recipient_pubkey,
+                recipient_viewing_key,
+                sender_viewing_key,
+                input_notes,
+                merkle_root,
+                ..
+            } => {
+                h.update([2u8]);
+                h.update(amount.to_le_bytes());
+                h.update(asset_id.to_le_bytes());
+                hash_u32s(&mut h, recipient_pubkey);
+                hash_u32s(&mut h, recipient_viewing_key);
+                hash_u32s(&mut h, sender_viewing_key);
+                for input in input_notes {
+                    input.note.hash_canonical(&mut h);
+                    hash_u32s(&mut h, &input.spending_key);
+                    input.merkle_path.hash_canonical(&mut h);
+                }
+                hash_u32s(&mut h, merkle_root);
             }
         }
+        format!("{:x}", h.finalize())
+    }
+}
+
+fn hash_u32s(h: &mut Sha256, vals: &[u32]) {
+    for v in vals {
+        h.update(v.to_le_bytes());
+    }
+}
+
+impl NoteJson {
+    fn hash_canonical(&self, h: &mut Sha256) {
+        hash_u32s(h, &self.owner_pubkey);
+        h.update(self.asset_id.to_le_bytes());
+        h.update(self.amount_lo.to_le_bytes());
+        h.update(self.amount_hi.to_le_bytes());
+        hash_u32s(h, &self.blinding);
+    }
+}
+
+impl MerklePathJson {
+    fn hash_canonical(&self, h: &mut Sha256) {
+        h.update((self.siblings.len() as u64).to_le_bytes());
+        for s in &self.siblings {
+            hash_u32s(h, s);
+        }
+        h.update((self.index as u64).to_le_bytes());
     }
 }
 
@@ -421,62 +1132,69 @@ impl EncryptedSubmitRequest {
         format!("enc:{:x}", hasher.finalize())
     }
 
+    /// Key identifying the `(ephemeral_pubkey, nonce)` pair for replay detection.
+    /// Hex is case-normalized so the same bytes can't be replayed with different casing.
+    pub fn nonce_guard_key(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.ephemeral_pubkey.to_ascii_lowercase().as_bytes());
+        hasher.update(b":");
+        hasher.update(self.nonce.to_ascii_lowercase().as_bytes());
+        format!("{:x}", hasher.finalize())
+    }
+
     /// Decrypt the ECIES envelope using the relayer's static X25519 private key.
-    /// Returns the deserialized SubmitRequest.
-    pub fn decrypt(&self, relayer_secret: &StaticSecret) -> Result<SubmitRequest, AppError> {
-        if self.version != 1 {
-            return Err(AppError::BadRequest(format!(
-                "unsupported ECIES version: {}",
-                self.version
-            )));
-        }
+    /// Returns the deserialized SubmitRequest once its sealed timestamp
+    /// passes `timestamps`.
+    pub fn decrypt(&self, keys: &RelayerKeys, timestamps: TimestampPolicy) -> Result<SubmitRequest, AppError> {
+        let plaintext = self.open(keys)?;
 
-        // Parse ephemeral public key
-        let epk_bytes = hex::decode(&self.ephemeral_pubkey).map_err(|_| {
-            AppError::BadRequest("invalid ephemeral_pubkey hex".into())
-        })?;
-        if epk_bytes.len() != 32 {
-            return Err(AppError::BadRequest("ephemeral_pubkey must be 32 bytes".into()));
+        // Deserialize the JSON SubmitRequest
+        let sealed: SealedSubmitRequest = serde_json::from_slice(&plaintext).map_err(|e| {
+            AppError::BadRequest(format!("invalid decrypted payload: {e}"))
+        })?;
+        timestamps.check(sealed.timestamp, now_epoch())?;
+        Ok(sealed.request)
+    }
+
+    /// Opens the envelope with the scheme registered for its version, trying
+    /// the current key and then the previous one. Failures are tagged by
+    /// stage so `/encrypt-check` can report a category without any detail.
+    fn open(&self, keys: &RelayerKeys) -> Result<Vec<u8>, EnvelopeError> {
+        let scheme = ecies::scheme_for(self.version)?;
+        match scheme.decrypt(&keys.current, self) {
+            Err(EnvelopeError::Decryption) => {}
+            opened => {
+                debug!(key = "current", "ECIES envelope opened");
+                return opened;
+            }
         }
-        let mut epk_arr = [0u8; 32];
-        epk_arr.copy_from_slice(&epk_bytes);
-        let ephemeral_pk = X25519PublicKey::from(epk_arr);
+        let previous = keys.previous.as_ref().ok_or(EnvelopeError::Decryption)?;
+        let plaintext = scheme.decrypt(previous, self)?;
+        debug!(key = "previous", "ECIES envelope opened");
+        Ok(plaintext)
+    }
+}
 
-        // ECDH shared secret
-        let shared_secret = relayer_secret.diffie_hellman(&ephemeral_pk);
+/// The relayer's ECIES private keys: the advertised one and, while a
+/// rotation overlaps, the one it replaced.
+pub struct RelayerKeys {
+    current: StaticSecret,
+    previous: Option<StaticSecret>,
+}
 
-        // HKDF-SHA256 to derive AES-256-GCM key
-        let hk = Hkdf::<Sha256>::new(None, shared_secret.as_bytes());
-        let mut aes_key = [0u8; 32];
-        hk.expand(b"obelysk-ecies-v1", &mut aes_key)
-            .map_err(|_| AppError::Internal("HKDF expand failed".into()))?;
+impl RelayerKeys {
+    pub fn new(current: [u8; 32], previous: Option<[u8; 32]>) -> Self {
+        Self {
+            current: StaticSecret::from(current),
+            previous: previous.map(StaticSecret::from),
+        }
+    }
 
-        // Parse nonce
-        let nonce_bytes = hex::decode(&self.nonce).map_err(|_| {
-            AppError::BadRequest("invalid nonce hex".into())
-        })?;
-        if nonce_bytes.len() != 12 {
-            return Err(AppError::BadRequest("nonce must be 12 bytes".into()));
-        }
-        let nonce = Nonce::from_slice(&nonce_bytes);
-
-        // Decode ciphertext from base64
-        use base64::Engine;
-        let ciphertext = base64::engine::general_purpose::STANDARD
-            .decode(&self.ciphertext)
-            .map_err(|_| AppError::BadRequest("invalid ciphertext base64".into()))?;
-
-        // AES-256-GCM decrypt
-        let cipher = Aes256Gcm::new_from_slice(&aes_key)
-            .map_err(|_| AppError::Internal("AES key init failed".into()))?;
-        let plaintext = cipher.decrypt(nonce, ciphertext.as_ref()).map_err(|_| {
-            AppError::BadRequest("ECIES decryption failed (bad key or tampered ciphertext)".into())
+    fn from_config(config: &RelayerConfig) -> Result<Self, AppError> {
+        let current = config.relayer_private_key.ok_or_else(|| {
+            AppError::Internal("ECIES not configured".into())
         })?;
-
-        // Deserialize the JSON SubmitRequest
-        serde_json::from_slice(&plaintext).map_err(|e| {
-            AppError::BadRequest(format!("invalid decrypted payload: {e}"))
-        })
+        Ok(Self::new(current, config.relayer_private_key_prev))
     }
 }
 
@@ -498,12 +1216,49 @@ pub fn extract_api_key(headers: &HeaderMap) -> Option<String> {
         })
 }
 
-pub fn require_auth(headers: &HeaderMap, config: &RelayerConfig) -> Result<String, AppError> {
+/// Authenticates the request, returning the key with its scopes and rate
+/// multiplier. Handlers check their operation with `require_scope`.
+pub fn require_auth(headers: &HeaderMap, config: &RelayerConfig) -> Result<ApiKeyConfig, AppError> {
+    authenticate(headers, &config.api_keys)
+}
+
+fn authenticate(headers: &HeaderMap, keys: &ApiKeys) -> Result<ApiKeyConfig, AppError> {
     let key = extract_api_key(headers).ok_or(AppError::Unauthorized)?;
-    if !config.is_api_key_valid(&key) {
-        return Err(AppError::Unauthorized);
+    keys.authenticate(&key).cloned().ok_or(AppError::Unauthorized)
+}
+
+/// 403 unless the authenticated key may perform `scope`.
+pub fn require_scope(auth: &ApiKeyConfig, scope: Scope) -> Result<(), AppError> {
+    if auth.allows(scope) {
+        Ok(())
+    } else {
+        info!(scope = scope.as_str(), "API key lacks scope");
+        Err(AppError::Forbidden)
     }
-    Ok(key)
+}
+
+/// Builds a `RateLimited` error, feeding the rejection to abuse alerting.
+fn rate_limited(
+    state: &AppState,
+    api_key: Option<&str>,
+    client_ip: &str,
+    retry_after_secs: Option<u64>,
+) -> AppError {
+    if let Some(abuse) = &state.abuse {
+        abuse.record_rejection(api_key, client_ip);
+    }
+    AppError::RateLimited(retry_after_secs)
+}
+
+/// Builds a `BatchFull` error with a `Retry-After` estimate for `pending` txs.
+fn batch_full(config: &RelayerConfig, pending: usize) -> AppError {
+    AppError::BatchFull(Some(queue_drain_secs(pending, config.batch_max_size, config.batch_timeout_secs)))
+}
+
+/// Rough seconds to drain `pending` txs, assuming one full batch per timeout.
+fn queue_drain_secs(pending: usize, batch_max_size: usize, batch_timeout_secs: u64) -> u64 {
+    let batches = pending.div_ceil(batch_max_size.max(1)) as u64;
+    (batches * batch_timeout_secs).max(1)
 }
 
 /// Extract client IP from headers (X-Forwarded-For) or connection info.
@@ -544,6 +1299,8 @@ pub fn extract_client_ip(
 // Handlers
 // ---------------------------------------------------------------------------
 
+/// Liveness: the process is up and serving. Always 200; dependency health
+/// is `ready`'s job.
 pub async fn health() -> impl IntoResponse {
     Json(json!({
         "status": "ok",
@@ -552,15 +1309,128 @@ pub async fn health() -> impl IntoResponse {
     }))
 }
 
+/// Upper bound on the store probe, so a hung backend reads as degraded
+/// rather than stalling the orchestrator's probe.
+const STORE_PROBE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// Readiness: 503 unless the prover is still consuming batches, the tree
+/// sync service is running, the store is reachable and at least one
+/// weighted RPC endpoint (`VM31_RPC_WEIGHTS`) is healthy.
+pub async fn ready(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let store = match tokio::time::timeout(STORE_PROBE_TIMEOUT, state.store.ping()).await {
+        Ok(result) => result.map_err(|e| e.to_string()),
+        Err(_) => Err("probe timed out".into()),
+    };
+    readiness_report(
+        state.queue.prover_connected(),
+        state.tree_sync.is_some(),
+        store,
+        state.rpc_pool.as_ref().map(|pool| pool.status()).unwrap_or_default(),
+    )
+}
+
+fn readiness_report(
+    prover_connected: bool,
+    tree_sync_running: bool,
+    store: Result<(), String>,
+    endpoints: Vec<EndpointStatus>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let rpc_ok = endpoints.is_empty() || endpoints.iter().any(|e| e.healthy);
+    let ready = prover_connected && tree_sync_running && store.is_ok() && rpc_ok;
+    let code = if ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    let check = |ok: bool, failed: &str| if ok { "ok".to_string() } else { failed.to_string() };
+    (
+        code,
+        Json(json!({
+            "status": if ready { "ready" } else { "degraded" },
+            "checks": {
+                "prover_channel": check(prover_connected, "closed"),
+                "tree_sync": check(tree_sync_running, "unavailable"),
+                "store": match &store {
+                    Ok(()) => "ok".to_string(),
+                    Err(e) => format!("unreachable: {e}"),
+                },
+                "rpc_endpoints": check(rpc_ok, "all unhealthy"),
+            },
+            "rpc_endpoints": endpoints,
+        })),
+    )
+}
+
 pub async fn status(State(state): State<Arc<AppState>>) -> impl IntoResponse {
-    let pending = state.queue.pending_count().await;
+    let queue_stats = state.queue.stats().await;
     Json(json!({
-        "pending_transactions": pending,
+        "pending_transactions": queue_stats.depth,
         "batch_max_size": state.config.batch_max_size,
         "batch_timeout_secs": state.config.batch_timeout_secs,
+        "queue_stats": queue_stats,
+        "prover_paused": state.prover_pause.is_paused(),
+        "shuffle": {
+            "enabled": !state.config.deterministic,
+            "rng": state.queue.shuffle_rng().describe(),
+            "secure": state.queue.shuffle_rng().is_secure(),
+        },
+        "store_backend": state.store.backend_name(),
+        "rpc_breaker": state.rpc_breaker.as_ref().map(|b| b.status()),
+        "eviction": {
+            "last": state.store.last_eviction(),
+            "totals": state.store.eviction_metrics.snapshot(),
+        },
     }))
 }
 
+/// Rolling p50/p95/p99 latencies for each prover stage.
+pub async fn stats(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, AppError> {
+    let auth = require_auth(&headers, &state.config)?;
+    require_scope(&auth, Scope::Read)?;
+    Ok(Json(json!({
+        "latency": state.latency.as_ref().map(|l| l.snapshot()),
+        "stream_connections": state.stream_connections.count(),
+    })))
+}
+
+/// Prometheus text-format metrics. Served only on `VM31_METRICS_ADDR`,
+/// without authentication.
+pub async fn metrics(State(state): State<Arc<AppState>>) -> Result<impl IntoResponse, AppError> {
+    let metrics = state
+        .metrics
+        .as_ref()
+        .ok_or_else(|| AppError::NotFound("metrics disabled".into()))?;
+    let body = metrics.render(state.queue.pending_count().await);
+    Ok((
+        [(axum::http::header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        body,
+    ))
+}
+
+/// Assets registered in the pool, with the deposit denominations the
+/// relayer enforces for each (`null` = unrestricted).
+pub async fn assets(State(state): State<Arc<AppState>>) -> Result<impl IntoResponse, AppError> {
+    let assets = state
+        .assets
+        .as_ref()
+        .ok_or_else(|| AppError::NotFound("asset list unavailable".into()))?;
+    let list: Vec<_> = assets
+        .iter()
+        .map(|a| {
+            json!({
+                "asset_id": a.asset_id,
+                "token": a.token,
+                "denominations": state.config.denominations.for_asset(a.asset_id),
+            })
+        })
+        .collect();
+    Ok(Json(json!({ "assets": list })))
+}
+
+/// Serves the OpenAPI description of this API (enabled by `VM31_OPENAPI`).
+pub async fn openapi() -> impl IntoResponse {
+    Json(crate::openapi::spec())
+}
+
 /// Serves the relayer's static X25519 public key for ECIES encryption.
 pub async fn public_key(
     State(state): State<Arc<AppState>>,
@@ -570,10 +1440,16 @@ pub async fn public_key(
     })?;
     let secret = StaticSecret::from(secret_bytes);
     let public = X25519PublicKey::from(&secret);
+    let versions: Vec<_> = ecies::SCHEMES
+        .iter()
+        .map(|s| json!({ "version": s.version(), "algorithm": s.algorithm() }))
+        .collect();
+    // `version`/`algorithm` keep describing v1 for clients predating `versions`
     Ok(Json(json!({
         "public_key": hex::encode(public.as_bytes()),
         "version": 1,
         "algorithm": "x25519-aes256gcm-hkdf-sha256",
+        "versions": versions,
     })))
 }
 
@@ -583,25 +1459,81 @@ pub async fn submit(
     headers: HeaderMap,
     Json(body): Json<SubmitBody>,
 ) -> Result<impl IntoResponse, AppError> {
-    let api_key = require_auth(&headers, &state.config)?;
+    padded_submit(&state, addr, &headers, body).await
+}
+
+/// One submission with metrics and response-time padding, shared by
+/// `/submit` and `/submit/stream`.
+async fn padded_submit(
+    state: &AppState,
+    addr: SocketAddr,
+    headers: &HeaderMap,
+    body: SubmitBody,
+) -> Result<(StatusCode, Json<serde_json::Value>), AppError> {
+    // PRIVACY: pad the whole submission (success or error) so response time
+    // doesn't reveal the submission mode, tx type or validation path.
+    let start = std::time::Instant::now();
+    let result = submit_inner(state, addr, headers, body)
+        .instrument(telemetry::submit_span(headers))
+        .await;
+    if let Some(m) = &state.metrics {
+        m.record_submission(match &result {
+            Ok(_) => "accepted",
+            Err(e) => e.error_code(),
+        });
+    }
+    let target = std::time::Duration::from_millis(state.config.submit_timing_target_ms);
+    let elapsed = start.elapsed();
+    tokio::time::sleep(padded_duration(elapsed, target) - elapsed).await;
+    result
+}
+
+/// Rounds `elapsed` up to the next multiple of `target`, so observers only
+/// learn which bucket a request fell into. A zero target disables padding.
+fn padded_duration(elapsed: std::time::Duration, target: std::time::Duration) -> std::time::Duration {
+    if target.is_zero() {
+        return elapsed;
+    }
+    let buckets = elapsed.as_nanos().div_ceil(target.as_nanos()).max(1);
+    target * buckets as u32
+}
+
+async fn submit_inner(
+    state: &AppState,
+    addr: SocketAddr,
+    headers: &HeaderMap,
+    body: SubmitBody,
+) -> Result<(StatusCode, Json<serde_json::Value>), AppError> {
+    let auth = require_auth(headers, &state.config)?;
+    require_scope(&auth, Scope::Submit)?;
+    if state.shutdown.is_started() {
+        return Err(AppError::ShuttingDown);
+    }
+    let api_key = auth.key.clone();
+    let client_ip = extract_client_ip(headers, Some(addr), &state.config.trusted_proxies);
+
+    // Per-key concurrency limit, held until the handler returns
+    let _inflight = state
+        .store
+        .try_acquire_inflight(&format!("key:{api_key}"), state.config.max_inflight_per_key)
+        .ok_or_else(|| rate_limited(state, Some(&api_key), &client_ip, None))?;
 
     // Per-key rate limit
-    let allowed = state
+    let decision = state
         .store
         .check_rate(
             &format!("key:{api_key}"),
-            state.config.rate_limit_per_min,
+            auth.rate_limit(state.config.rate_limit_per_min),
             60,
         )
         .await
         .map_err(|e| AppError::Internal(e.to_string()))?;
-    if !allowed {
-        return Err(AppError::RateLimited);
+    if !decision.allowed {
+        return Err(rate_limited(state, Some(&api_key), &client_ip, Some(decision.retry_after_secs)));
     }
 
     // Per-IP rate limit (3x key limit as secondary control)
-    let client_ip = extract_client_ip(&headers, Some(addr), &state.config.trusted_proxies);
-    let ip_allowed = state
+    let ip_decision = state
         .store
         .check_rate(
             &format!("ip:{client_ip}"),
@@ -610,29 +1542,26 @@ pub async fn submit(
         )
         .await
         .map_err(|e| AppError::Internal(e.to_string()))?;
-    if !ip_allowed {
-        return Err(AppError::RateLimited);
+    if !ip_decision.allowed {
+        return Err(rate_limited(state, Some(&api_key), &client_ip, Some(ip_decision.retry_after_secs)));
     }
 
     // Queue capacity check
     let pending = state.queue.pending_count().await;
     if pending >= MAX_PENDING_TXS {
-        return Err(AppError::BatchFull);
+        return Err(batch_full(&state.config, pending));
     }
+    let client_idem_key = client_idempotency_key(headers)?;
 
-    // Resolve encrypted or plaintext submission.
-    // PRIVACY: Both paths must take similar wall-clock time to prevent
-    // timing side channels that reveal whether ECIES encryption was used.
-    let submission_start = std::time::Instant::now();
-    let (req, idem_key) = match body {
+    // Resolve encrypted or plaintext submission. Timing is normalized by `submit`.
+    let (req, idem_key, nonce_key) = match body {
         SubmitBody::Encrypted(enc) => {
             let idem_key = enc.idempotency_key();
-            let secret_bytes = state.config.relayer_private_key.ok_or_else(|| {
-                AppError::Internal("ECIES not configured".into())
-            })?;
-            let secret = StaticSecret::from(secret_bytes);
-            let req = enc.decrypt(&secret)?;
-            (req, idem_key)
+            let req = enc.decrypt(
+                &RelayerKeys::from_config(&state.config)?,
+                TimestampPolicy::from_config(&state.config),
+            )?;
+            (req, idem_key, Some(enc.nonce_guard_key()))
         }
         SubmitBody::Plaintext(req) => {
             // Reject plaintext in mainnet mode
@@ -642,19 +1571,13 @@ pub async fn submit(
                 ));
             }
             let idem_key = req.idempotency_key();
-            (req, idem_key)
+            (req, idem_key, None)
         }
     };
-    // Normalize response timing: ensure minimum processing time regardless of path.
-    // ECIES decrypt takes ~1-3ms; this prevents plaintext path from responding
-    // measurably faster and revealing the submission mode to network observers.
-    let elapsed = submission_start.elapsed();
-    let min_processing = std::time::Duration::from_millis(5);
-    if elapsed < min_processing {
-        tokio::time::sleep(min_processing - elapsed).await;
-    }
+    let idem_key = submission_idempotency_key(&api_key, client_idem_key, idem_key);
 
-    // Idempotency check
+    // Idempotency check. Before the nonce guard, so an identical retry of
+    // an envelope gets the duplicate response rather than "nonce reuse".
     if let Some(cached) = state
         .store
         .check_and_set(&idem_key, "pending")
@@ -667,15 +1590,199 @@ pub async fn submit(
                 "status": "duplicate",
                 "cached_result": cached,
                 "idempotency_key": idem_key,
+                "client_idempotency_key": client_idem_key,
+                "blinding": drawn_blinding(&state.store, &idem_key).await,
             })),
         ));
     }
 
-    // Validate and convert JSON → PendingTx (M31 bounds, merkle depth, amounts)
-    let pending_tx = req.validate_and_convert()?;
+    let admitted =
+        admit_submission(state, &auth, &client_ip, &req, &idem_key, client_idem_key, nonce_key.as_deref()).await;
+    release_if_rejected(&state.store, &idem_key, admitted).await
+}
+
+/// Rejects reuse of an (ephemeral_pubkey, nonce) pair within the envelope max-age.
+async fn check_envelope_nonce(state: &AppState, nonce_key: &str) -> Result<(), AppError> {
+    let fresh = state
+        .store
+        .check_and_record_nonce(
+            nonce_key,
+            state.config.envelope_max_age_secs,
+            state.config.nonce_guard_max_entries,
+        )
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+    if !fresh {
+        return Err(AppError::BadRequest("ECIES nonce reuse detected".into()));
+    }
+    Ok(())
+}
+
+/// Releases the idempotency key a rejected submission claimed as "pending",
+/// so a retry (after fixing the request or waiting out a 429) is processed
+/// afresh instead of being answered as a duplicate forever.
+async fn release_if_rejected<T>(
+    store: &InMemoryStore,
+    idem_key: &str,
+    outcome: Result<T, AppError>,
+) -> Result<T, AppError> {
+    if outcome.is_err() {
+        if let Err(e) = store.release(idem_key).await {
+            warn!(error = %e, "could not release the idempotency key of a rejected submission");
+        }
+    }
+    outcome
+}
+
+/// What admitting a submission recorded in the store: its envelope nonce,
+/// its withdrawal binding (reserved, and seen within the replay window),
+/// its counted deposit volume and its drawn blinding. Undone if the
+/// submission is then rejected, so a corrected retry isn't refused for
+/// traces of the failed attempt.
+#[derive(Default)]
+struct Admission {
+    nonce_key: Option<String>,
+    reserved_binding: Option<String>,
+    recent_binding: Option<String>,
+    deposit_volume: Option<(String, u64, u64)>,
+    drawn_blinding: Option<String>,
+}
+
+impl Admission {
+    async fn undo(self, store: &InMemoryStore) {
+        if let Some(key) = &self.nonce_key {
+            if let Err(e) = store.forget_nonce(key).await {
+                warn!(error = %e, "could not forget the envelope nonce of a rejected submission");
+            }
+        }
+        for key in self.reserved_binding.iter().chain(&self.drawn_blinding) {
+            if let Err(e) = store.release(key).await {
+                warn!(error = %e, "could not release an idempotency entry of a rejected submission");
+            }
+        }
+        if let Some(key) = &self.recent_binding {
+            store.forget_binding(key);
+        }
+        if let Some((key, day, amount)) = &self.deposit_volume {
+            if let Err(e) = store.subtract_volume(key, *day, *amount).await {
+                warn!(error = %e, "could not take back the deposit volume of a rejected submission");
+            }
+        }
+    }
+}
+
+/// The checks that record something (envelope nonce, binding reservation
+/// and replay window, deposit volume), then the drawn blinding, noting each
+/// in `taken` as it passes.
+async fn record_admission(
+    state: &AppState,
+    api_key: &str,
+    req: &SubmitRequest,
+    pending_tx: &PendingTx,
+    idem_key: &str,
+    taken: &mut Admission,
+) -> Result<(), AppError> {
+    if let Some(nonce_key) = &taken.nonce_key {
+        if let Err(e) = check_envelope_nonce(state, nonce_key).await {
+            // Not ours to forget: the pair was already recorded
+            taken.nonce_key = None;
+            return Err(e);
+        }
+    }
+    if state.config.unique_withdrawal_bindings {
+        taken.reserved_binding = reserve_withdrawal_binding(&state.store, req, idem_key).await?;
+    }
+    taken.recent_binding =
+        check_binding_replay(&state.store, req, state.config.binding_replay_window_secs, now_epoch())?;
+    taken.deposit_volume = validate_deposit_limits(
+        &state.store,
+        &state.config.deposit_limits,
+        api_key,
+        pending_tx,
+        now_epoch(),
+    )
+    .await?;
+    taken.drawn_blinding = remember_drawn_blinding(&state.store, req, pending_tx, idem_key).await?;
+    Ok(())
+}
+
+/// Validation, content checks and enqueue (or quarantine) for a submission
+/// that has claimed its idempotency key. Checks that record nothing run
+/// first; anything recorded after them is undone if the submission is
+/// still rejected.
+async fn admit_submission(
+    state: &AppState,
+    auth: &ApiKeyConfig,
+    client_ip: &str,
+    req: &SubmitRequest,
+    idem_key: &str,
+    client_idem_key: Option<&str>,
+    nonce_key: Option<&str>,
+) -> Result<(StatusCode, Json<serde_json::Value>), AppError> {
+    let api_key = auth.key.as_str();
+    let pending_tx = validate_submission(state, req)?;
+    // Optional content-aware throttling (opt-in). Runs on the decrypted
+    // submission, after validation and before enqueue or quarantine.
+    if let Some(limit) = state.config.content_rate_limit_per_min {
+        let (tx_type, asset_id) = content_rate_key(&pending_tx);
+        let decision = state
+            .store
+            .check_rate(&format!("content:{api_key}:{tx_type}:{asset_id}"), auth.rate_limit(limit), 60)
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+        if !decision.allowed {
+            return Err(rate_limited(state, Some(api_key), client_ip, Some(decision.retry_after_secs)));
+        }
+    }
+    let client_ref = match req.client_ref() {
+        Some(r) => {
+            validate_client_ref(r)?;
+            Some(scoped_client_ref(api_key, r))
+        }
+        None => None,
+    };
+    let recipient = req.withdrawal_recipient()?;
+    let flag = Quarantine::flag_reason(&state.config, api_key, &pending_tx);
+    if flag.is_some() && state.quarantine.is_full() {
+        return Err(AppError::BatchFull(None));
+    }
+
+    // Last checks before queueing, so only accepted submissions count
+    let mut taken = Admission { nonce_key: nonce_key.map(str::to_string), ..Default::default() };
+    if let Err(e) = record_admission(state, api_key, req, &pending_tx, idem_key, &mut taken).await {
+        taken.undo(&state.store).await;
+        return Err(e);
+    }
+
+    let blinding = deposit_blinding(&pending_tx);
+
+    // Flagged submissions are held for manual review instead of batching.
+    // The flag reason is only visible to admins.
+    if let Some(reason) = flag {
+        if state.quarantine.hold(pending_tx, api_key, client_ref, recipient, reason).is_none() {
+            // Filled up since the check above
+            taken.undo(&state.store).await;
+            return Err(AppError::BatchFull(None));
+        }
+        return Ok((
+            StatusCode::ACCEPTED,
+            Json(json!({
+                "status": "queued_for_review",
+                "batch_id": null,
+                "queue_position": null,
+                "idempotency_key": idem_key,
+                "client_idempotency_key": client_idem_key,
+                "client_ref": req.client_ref(),
+                "blinding": blinding,
+            })),
+        ));
+    }
 
     // Push to batch queue
-    let (batch_id, queue_pos) = state.queue.push(pending_tx).await;
+    let (batch_id, queue_pos) = state
+        .queue
+        .push_keyed(pending_tx, client_ref, Some(idem_key.to_string()), Some(submitter_digest(api_key)), recipient)
+        .await;
 
     Ok((
         StatusCode::ACCEPTED,
@@ -684,171 +1791,2830 @@ pub async fn submit(
             "batch_id": batch_id,
             "queue_position": queue_pos,
             "idempotency_key": idem_key,
+            "client_idempotency_key": client_idem_key,
+            "client_ref": req.client_ref(),
+            "blinding": blinding,
         })),
     ))
 }
 
-pub async fn get_batch(
-    State(state): State<Arc<AppState>>,
-    headers: HeaderMap,
-    Path(id): Path<String>,
-) -> Result<impl IntoResponse, AppError> {
-    require_auth(&headers, &state.config)?;
+/// The check a submission failed, as reported by `/validate`. Codes are
+/// stable and messages client-safe; the detailed reason is only logged, so
+/// the dry run can't be used to probe bounds or the recipient policies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckFailure {
+    /// Malformed fields, out-of-range values or an off-table amount.
+    InvalidFields,
+    /// A withdrawal without valid payout and credit recipients.
+    InvalidRecipient,
+    AssetNotAllowed,
+    /// A Merkle path of the wrong depth or not leading to `merkle_root`.
+    InvalidMerklePath,
+    ZeroChange,
+    /// Keys that don't derive from each other (`VM31_STRICT_KEY_VALIDATION`).
+    KeyMismatch,
+    UnknownRoot,
+    NullifierSpent,
+    /// Any other rejection, including decoy-range and denylisted recipients,
+    /// which must not be told apart from each other or from a generic error.
+    InvalidRequest,
+}
 
-    // Validate batch ID format (UUID)
-    if id.len() > 64 || id.chars().any(|c| !c.is_ascii_alphanumeric() && c != '-') {
-        return Err(AppError::BadRequest("invalid batch id format".into()));
+impl CheckFailure {
+    pub fn code(self) -> &'static str {
+        match self {
+            CheckFailure::InvalidFields => "INVALID_FIELDS",
+            CheckFailure::InvalidRecipient => "INVALID_RECIPIENT",
+            CheckFailure::AssetNotAllowed => "ASSET_NOT_ALLOWED",
+            CheckFailure::InvalidMerklePath => "INVALID_MERKLE_PATH",
+            CheckFailure::ZeroChange => "ZERO_CHANGE",
+            CheckFailure::KeyMismatch => "KEY_MISMATCH",
+            CheckFailure::UnknownRoot => "UNKNOWN_ROOT",
+            CheckFailure::NullifierSpent => "NULLIFIER_SPENT",
+            CheckFailure::InvalidRequest => "INVALID_REQUEST",
+        }
     }
 
-    let record = state
-        .store
-        .get_batch(&id)
-        .await
-        .map_err(|_| AppError::Internal("store error".into()))?
-        .ok_or(AppError::BadRequest("batch not found".into()))?;
+    fn public_message(self) -> &'static str {
+        match self {
+            CheckFailure::InvalidFields => "invalid or out-of-range fields",
+            CheckFailure::InvalidRecipient => "withdrawal recipients missing or invalid",
+            CheckFailure::AssetNotAllowed => "asset not accepted",
+            CheckFailure::InvalidMerklePath => "Merkle path invalid for this root",
+            CheckFailure::ZeroChange => "transfer leaves a zero-value change note",
+            CheckFailure::KeyMismatch => "keys do not match",
+            CheckFailure::UnknownRoot => "unknown Merkle root",
+            CheckFailure::NullifierSpent => "nullifier already spent",
+            CheckFailure::InvalidRequest => "invalid request",
+        }
+    }
 
-    Ok(Json(json!({
-        "id": record.id,
-        "status": record.status,
-        "tx_count": record.tx_count,
-        "proof_hash": record.proof_hash,
-        "batch_id_onchain": record.batch_id_onchain,
-        "tx_hash": record.tx_hash,
-        "created_at": record.created_at,
-        "error": record.error,
-    })))
+    /// `{error, code}`, the shape of `AppError::body`.
+    pub fn body(self) -> serde_json::Value {
+        json!({ "error": self.public_message(), "code": self.code() })
+    }
 }
 
-pub async fn force_prove(
+/// Tags an error with the check that raised it.
+fn failed(check: CheckFailure) -> impl FnOnce(AppError) -> (CheckFailure, AppError) {
+    move |e| (check, e)
+}
+
+/// Validates and converts a submission (M31 bounds, merkle depth, amounts,
+/// denominations and the configured content policies). No side effects.
+fn validate_submission(state: &AppState, req: &SubmitRequest) -> Result<PendingTx, AppError> {
+    check_submission(state, req).map_err(|(_, e)| e)
+}
+
+/// `validate_submission`, with each error tagged by the check that failed.
+fn check_submission(state: &AppState, req: &SubmitRequest) -> Result<PendingTx, (CheckFailure, AppError)> {
+    let config = &state.config;
+    let pending_tx = req
+        .validate_and_convert(&config.denominations)
+        .map_err(failed(CheckFailure::InvalidFields))?;
+    if matches!(req, SubmitRequest::Withdraw { .. })
+        && req.withdrawal_recipient().map_err(failed(CheckFailure::InvalidRecipient))?.is_none()
+    {
+        return Err((
+            CheckFailure::InvalidRecipient,
+            AppError::BadRequest("withdrawals require payout_recipient and credit_recipient".into()),
+        ));
+    }
+    check_allowed_assets(&pending_tx, &config.allowed_asset_ids)
+        .map_err(failed(CheckFailure::AssetNotAllowed))?;
+    req.validate_path_depths(config.merkle_depth)
+        .map_err(failed(CheckFailure::InvalidMerklePath))?;
+    check_input_roots(&pending_tx).map_err(failed(CheckFailure::InvalidMerklePath))?;
+    if req.transfer_change().map_err(failed(CheckFailure::InvalidFields))? == Some(NoteAmount::ZERO) {
+        if config.reject_zero_change {
+            return Err((
+                CheckFailure::ZeroChange,
+                AppError::BadRequest(
+                    "transfer spends its inputs exactly, leaving a zero-value change note".into(),
+                ),
+            ));
+        }
+        warn!("transfer creates a zero-value change note");
+    }
+    if config.strict_key_validation {
+        req.validate_key_relationships().map_err(failed(CheckFailure::KeyMismatch))?;
+    }
+    if let Some(range) = config.decoy_pubkey_range {
+        req.validate_not_decoy_recipient(range)
+            .map_err(failed(CheckFailure::InvalidRequest))?;
+    }
+    if let Some(denylist) = &state.pubkey_denylist {
+        req.validate_recipient_permitted(&denylist.current())
+            .map_err(failed(CheckFailure::InvalidRequest))?;
+    }
+    Ok(pending_tx)
+}
+
+/// Dry run of `/submit`: field and denomination checks plus the pool's
+/// Merkle root and nullifier checks, reported as `{valid, errors}` with one
+/// `{error, code}` per failed check (see `CheckFailure`).
+///
+/// Nothing is queued, and neither the idempotency key nor the envelope
+/// nonce is recorded, so the same submission can still be sent to `/submit`.
+/// Rate-limited on its own, looser budget (`VM31_VALIDATE_RATE_LIMIT`).
+pub async fn validate(
     State(state): State<Arc<AppState>>,
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
     headers: HeaderMap,
+    Json(body): Json<SubmitBody>,
 ) -> Result<impl IntoResponse, AppError> {
-    let api_key = require_auth(&headers, &state.config)?;
-
-    // Stricter rate limit for admin endpoint (1/5 of normal)
-    let allowed = state
+    let auth = require_auth(&headers, &state.config)?;
+    require_scope(&auth, Scope::Submit)?;
+    let client_ip = extract_client_ip(&headers, Some(addr), &state.config.trusted_proxies);
+    let decision = state
         .store
         .check_rate(
-            &format!("prove:{api_key}"),
-            (state.config.rate_limit_per_min / 5).max(1),
+            &format!("validate:{}", auth.key),
+            auth.rate_limit(state.config.validate_rate_limit_per_min),
             60,
         )
         .await
         .map_err(|e| AppError::Internal(e.to_string()))?;
-    if !allowed {
-        return Err(AppError::RateLimited);
+    if !decision.allowed {
+        return Err(rate_limited(&state, Some(&auth.key), &client_ip, Some(decision.retry_after_secs)));
     }
 
-    match state.queue.force_flush().await {
-        Some(batch_id) => Ok(Json(json!({
-            "status": "flushed",
-            "batch_id": batch_id,
-        }))),
-        None => Ok(Json(json!({
-            "status": "empty",
-            "message": "no pending transactions to prove",
-        }))),
+    let req = match body {
+        SubmitBody::Encrypted(enc) => enc.decrypt(
+            &RelayerKeys::from_config(&state.config)?,
+            TimestampPolicy::from_config(&state.config),
+        )?,
+        SubmitBody::Plaintext(req) => {
+            if !state.config.legacy_plaintext_allowed {
+                return Err(AppError::BadRequest(
+                    "plaintext submissions disabled — use ECIES encryption".into(),
+                ));
+            }
+            req
+        }
+    };
+    let errors = dry_run(&state.input_checks, check_submission(&state, &req)).await?;
+    Ok(Json(json!({
+        "valid": errors.is_empty(),
+        "errors": errors.into_iter().map(CheckFailure::body).collect::<Vec<_>>(),
+    })))
+}
+
+/// Runs the pool input checks on a tx that passed field validation.
+/// Returns the checks the submission would fail; field and pool rejections
+/// are reported, anything else (e.g. RPC failure) is an error.
+pub(crate) async fn dry_run(
+    checks: &InputChecks,
+    validated: Result<PendingTx, (CheckFailure, AppError)>,
+) -> Result<Vec<CheckFailure>, AppError> {
+    let pending_tx = match validated {
+        Ok(tx) => tx,
+        Err((check, AppError::BadRequest(reason))) => {
+            debug!(code = check.code(), reason = %reason, "dry run rejected submission");
+            return Ok(vec![check]);
+        }
+        Err((_, e)) => return Err(e),
+    };
+    match checks.run(vec![pending_tx]).await {
+        InputCheck::Passed => Ok(Vec::new()),
+        InputCheck::Rejected { check, reason } => {
+            debug!(code = check.code(), reason = %reason, "dry run rejected submission");
+            Ok(vec![check])
+        }
+        InputCheck::Unavailable(ProverError::RpcUnavailable { retry_after_secs }) => {
+            Err(AppError::RpcUnavailable(Some(retry_after_secs)))
+        }
+        InputCheck::Unavailable(e) => Err(AppError::ProverError(e.to_string())),
     }
 }
 
-pub async fn get_merkle_path(
+/// Longest `/submit/stream` line, matching the decompressed `/submit` body limit.
+const MAX_STREAM_LINE_BYTES: usize = 100 * 1024;
+
+/// Streaming `/submit` for large submissions (`VM31_SUBMIT_STREAM`).
+///
+/// The body is NDJSON with one submission (plaintext or envelope) per line.
+/// Each line runs through `/submit` (auth, per-item rate limits, queue
+/// capacity, padding) as soon as it is read. Its result is streamed back as
+/// one NDJSON line `{line, http_status, ...}`, so neither side buffers the
+/// whole batch. The total body is capped by `VM31_SUBMIT_STREAM_MAX_BYTES`.
+pub async fn submit_stream(
     State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
     headers: HeaderMap,
-    Path(commitment): Path<String>,
+    body: axum::body::Body,
 ) -> Result<impl IntoResponse, AppError> {
-    require_auth(&headers, &state.config)?;
+    let auth = require_auth(&headers, &state.config)?;
+    require_scope(&auth, Scope::Submit)?;
+    // Bounded channel: lines are read only as fast as the client reads results
+    let (tx, rx) = tokio::sync::mpsc::channel::<String>(16);
+    tokio::spawn(stream_submissions(state, addr, headers, body, tx));
+    let body = axum::body::Body::from_stream(
+        ReceiverStream::new(rx).map(Ok::<_, std::convert::Infallible>),
+    );
+    Ok(([(axum::http::header::CONTENT_TYPE, "application/x-ndjson")], body))
+}
 
-    // Validate commitment format (hex string, reasonable length)
-    if commitment.is_empty() || commitment.len() > 128 || commitment.chars().any(|c| !c.is_ascii_hexdigit() && c != '-' && c != '_') {
-        return Err(AppError::BadRequest("invalid commitment format".into()));
+async fn stream_submissions(
+    state: Arc<AppState>,
+    addr: SocketAddr,
+    mut headers: HeaderMap,
+    body: axum::body::Body,
+    out: tokio::sync::mpsc::Sender<String>,
+) {
+    // One header can't key every line; each line dedups on its own payload
+    headers.remove(IDEMPOTENCY_KEY_HEADER);
+    let mut stream = body.into_data_stream();
+    let mut lines = LineSplitter::new(MAX_STREAM_LINE_BYTES);
+    let mut line_no = 0usize;
+    while let Some(chunk) = stream.next().await {
+        let Ok(chunk) = chunk else {
+            // Includes hitting VM31_SUBMIT_STREAM_MAX_BYTES mid-stream
+            let reason = "body failed or exceeded the stream size limit";
+            let _ = out.send(stream_error_line(line_no + 1, reason)).await;
+            return;
+        };
+        for line in lines.push(&chunk) {
+            line_no += 1;
+            if line.trim_ascii().is_empty() {
+                continue;
+            }
+            let result = if line.len() > MAX_STREAM_LINE_BYTES {
+                stream_error_line(line_no, "line too long")
+            } else {
+                submit_stream_line(&state, addr, &headers, line_no, &line).await
+            };
+            if out.send(result).await.is_err() {
+                return; // client went away
+            }
+        }
+        if lines.too_long() {
+            let _ = out.send(stream_error_line(line_no + 1, "line too long")).await;
+            return;
+        }
     }
+    if let Some(line) = lines.finish().filter(|l| !l.trim_ascii().is_empty()) {
+        let _ = out.send(submit_stream_line(&state, addr, &headers, line_no + 1, &line).await).await;
+    }
+}
 
-    // Try the store first
-    let record = state
-        .store
-        .get_note(&commitment)
-        .await
-        .map_err(|_| AppError::Internal("store error".into()))?;
-
-    if let Some(note) = record {
-        // If the note has a real merkle root, return it directly
-        if note.merkle_root != [0; 8] {
-            return Ok((
-                StatusCode::OK,
-                Json(json!({
-                    "commitment": note.commitment,
-                    "merkle_path": {
-                        "siblings": note.merkle_path.siblings,
-                        "index": note.merkle_path.index,
-                    },
-                    "merkle_root": note.merkle_root,
-                    "batch_id": note.batch_id,
-                    "created_at": note.created_at,
-                })),
-            ));
+/// Submits one `/submit/stream` line and renders its result line.
+async fn submit_stream_line(
+    state: &AppState,
+    addr: SocketAddr,
+    headers: &HeaderMap,
+    line_no: usize,
+    line: &[u8],
+) -> String {
+    let result = match serde_json::from_slice::<SubmitBody>(line) {
+        Ok(body) => padded_submit(state, addr, headers, body).await,
+        Err(_) => Err(AppError::BadRequest("line is not a valid submission".into())),
+    };
+    let mut item = match result {
+        Ok((status, Json(mut body))) => {
+            body["http_status"] = status.as_u16().into();
+            body
         }
-
-        // Note exists but merkle root is empty — try on-demand proof via TreeSyncService
-        if let Some(ref ts) = state.tree_sync {
-            if let Some(proof) = ts.get_proof(&commitment).await {
-                // Update the store record with the real proof
-                let mut updated = note.clone();
-                updated.merkle_path = MerklePathRecord {
-                    siblings: proof.siblings.clone(),
-                    index: proof.index,
-                };
-                updated.merkle_root = proof.root;
-                let _ = state.store.save_note(&commitment, &updated).await;
-
-                return Ok((
-                    StatusCode::OK,
-                    Json(json!({
-                        "commitment": updated.commitment,
-                        "merkle_path": {
-                            "siblings": proof.siblings,
-                            "index": proof.index,
-                        },
-                        "merkle_root": proof.root,
-                        "batch_id": updated.batch_id,
-                        "created_at": updated.created_at,
-                    })),
-                ));
-            }
+        Err(e) => {
+            e.log();
+            let mut body = e.body();
+            body["http_status"] = e.status_code().as_u16().into();
+            body
         }
+    };
+    item["line"] = line_no.into();
+    format!("{item}\n")
+}
 
-        // Note exists but proof not available yet — return pending status
-        return Ok((
-            StatusCode::OK,
-            Json(json!({
-                "commitment": note.commitment,
-                "merkle_path": null,
-                "merkle_root": null,
-                "batch_id": note.batch_id,
-                "created_at": note.created_at,
-                "status": "pending_sync",
-            })),
-        ));
+fn stream_error_line(line_no: usize, reason: &str) -> String {
+    let item = json!({
+        "line": line_no,
+        "http_status": StatusCode::BAD_REQUEST.as_u16(),
+        "error": reason,
+        "code": "BAD_REQUEST",
+    });
+    format!("{item}\n")
+}
+
+/// Splits a chunked body into newline-terminated lines, buffering at most
+/// one partial line.
+struct LineSplitter {
+    buf: Vec<u8>,
+    max_line: usize,
+}
+
+impl LineSplitter {
+    fn new(max_line: usize) -> Self {
+        Self { buf: Vec::new(), max_line }
     }
 
-    // No store record — try on-demand from TreeSyncService directly
-    if let Some(ref ts) = state.tree_sync {
-        if let Some(proof) = ts.get_proof(&commitment).await {
-            return Ok((
-                StatusCode::OK,
-                Json(json!({
-                    "commitment": commitment,
-                    "merkle_path": {
-                        "siblings": proof.siblings,
-                        "index": proof.index,
-                    },
-                    "merkle_root": proof.root,
-                    "batch_id": null,
-                    "created_at": null,
-                })),
-            ));
+    /// Appends `chunk` and returns the lines it completed.
+    fn push(&mut self, chunk: &[u8]) -> Vec<Vec<u8>> {
+        self.buf.extend_from_slice(chunk);
+        let mut lines = Vec::new();
+        while let Some(pos) = self.buf.iter().position(|&b| b == b'\n') {
+            lines.push(self.buf.drain(..=pos).collect());
         }
+        lines
+    }
+
+    /// True once the unterminated line exceeds `max_line`.
+    fn too_long(&self) -> bool {
+        self.buf.len() > self.max_line
     }
 
-    Err(AppError::NotFound("note not indexed yet".into()))
+    /// The final line, if the body didn't end with a newline.
+    fn finish(self) -> Option<Vec<u8>> {
+        (!self.buf.is_empty()).then_some(self.buf)
+    }
+}
+
+/// Idempotency result recorded for a submission withdrawn from the queue.
+const CANCELLED_RESULT: &str = "cancelled";
+
+/// Withdraws a queued submission before its batch is flushed. The
+/// idempotency key (returned by `/submit`) identifies the tx, and only the
+/// API key that submitted it can cancel it: others get 404.
+/// Returns 409 once the tx has left the queue.
+pub async fn cancel_submission(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(idem_key): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let auth = require_auth(&headers, &state.config)?;
+    require_scope(&auth, Scope::Submit)?;
+
+    match state.queue.remove_by_key(&idem_key, &submitter_digest(&auth.key)).await {
+        Removal::Removed(remaining) => {
+            state
+                .store
+                .update_result(&idem_key, CANCELLED_RESULT)
+                .await
+                .map_err(|e| AppError::Internal(e.to_string()))?;
+            info!(remaining, "queued submission cancelled");
+            return Ok(Json(json!({
+                "status": "cancelled",
+                "idempotency_key": idem_key,
+                "queue_position": remaining,
+            })));
+        }
+        // Someone else's tx: answered as if the key were unknown
+        Removal::NotOwner => return Err(AppError::NotFound("unknown idempotency key".into())),
+        Removal::NotQueued => {}
+    }
+
+    // Not queued: the prover records the batch id once a flushed batch starts
+    let result = state
+        .store
+        .get_result(&idem_key)
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+    match result.as_deref() {
+        None => Err(AppError::NotFound("unknown idempotency key".into())),
+        Some(CANCELLED_RESULT) => Err(AppError::Conflict("submission already cancelled".into())),
+        Some("pending") => Err(AppError::Conflict(
+            "submission is no longer queued (flushed or held for review)".into(),
+        )),
+        Some(batch_id) => Err(AppError::Conflict(format!(
+            "submission already flushed into batch {batch_id}"
+        ))),
+    }
+}
+
+/// Content attributes used by the post-decrypt rate limit.
+fn content_rate_key(tx: &PendingTx) -> (&'static str, u32) {
+    match tx {
+        PendingTx::Deposit { asset_id, .. } => ("deposit", *asset_id),
+        PendingTx::Withdraw { asset_id, .. } => ("withdraw", *asset_id),
+        PendingTx::Transfer { asset_id, .. } => ("transfer", *asset_id),
+    }
+}
+
+pub async fn get_batch(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let auth = require_auth(&headers, &state.config)?;
+    require_scope(&auth, Scope::Read)?;
+    check_batch_id(&id)?;
+
+    let record = state
+        .store
+        .get_batch(&id)
+        .await
+        .map_err(|_| AppError::Internal("store error".into()))?
+        .ok_or(AppError::BadRequest("batch not found".into()))?;
+
+    let body = json!({
+        "id": record.id,
+        "status": record.status,
+        "tx_count": record.tx_count,
+        "proof_hash": record.proof_hash,
+        "batch_id_onchain": record.batch_id_onchain,
+        "tx_hash": record.tx_hash,
+        "created_at": record.created_at,
+        "error": record.error,
+    });
+    Ok(cached_batch_response(
+        &headers,
+        &record.status,
+        body,
+        state.config.batch_cache_max_age_secs,
+    ))
+}
+
+/// Batch ids are UUIDs; reject anything that couldn't be one before a lookup.
+fn check_batch_id(id: &str) -> Result<(), AppError> {
+    if id.len() > 64 || id.chars().any(|c| !c.is_ascii_alphanumeric() && c != '-') {
+        return Err(AppError::BadRequest("invalid batch id format".into()));
+    }
+    Ok(())
+}
+
+/// Streams a batch's status as Server-Sent Events: a `status` event with
+/// the current status, then one per transition, closing after `finalized`
+/// or `failed`. Open streams per API key are capped by
+/// `VM31_BATCH_EVENT_STREAMS_PER_KEY`; a client that stops reading is
+/// dropped after `VM31_STREAM_IDLE_TIMEOUT_SECS`.
+pub async fn batch_events(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let auth = require_auth(&headers, &state.config)?;
+    require_scope(&auth, Scope::Read)?;
+    check_batch_id(&id)?;
+    let permit = state
+        .store
+        .try_acquire_inflight(&format!("events:{}", auth.key), state.config.batch_event_streams_per_key)
+        .ok_or(AppError::RateLimited(None))?;
+
+    // Subscribe before reading the record so no transition falls in between
+    let events = state.store.subscribe_batch_events();
+    let record = state
+        .store
+        .get_batch(&id)
+        .await
+        .map_err(|_| AppError::Internal("store error".into()))?
+        .ok_or(AppError::BadRequest("batch not found".into()))?;
+
+    // One slot: a ping only fits once the client has read what came before
+    let (tx, rx) = tokio::sync::mpsc::channel::<Option<BatchStatus>>(1);
+    let idle_timeout = Duration::from_secs(state.config.stream_idle_timeout_secs);
+    let slot = state.stream_connections.open();
+    tokio::spawn(forward_batch_events(
+        state.store.clone(),
+        id.clone(),
+        record.status,
+        events,
+        tx,
+        idle_timeout,
+        (permit, slot),
+    ));
+    let stream = ReceiverStream::new(rx).map(move |status| {
+        Ok::<_, std::convert::Infallible>(match status {
+            Some(status) => Event::default()
+                .event("status")
+                .data(json!({ "batch_id": id, "status": status }).to_string()),
+            None => Event::default().comment("ping"),
+        })
+    });
+    Ok(Sse::new(stream))
+}
+
+/// Feeds one `/batch/{id}/events` stream: `current`, then each change, until
+/// a terminal status or the client disconnects. In between it sends a
+/// keep-alive ping (`None`) every third of `idle_timeout`; if the client
+/// hasn't taken an event within `idle_timeout`, the stream is dropped. Holds
+/// the stream's slots (`_slots`) until then.
+async fn forward_batch_events(
+    store: Arc<InMemoryStore>,
+    batch_id: String,
+    current: BatchStatus,
+    mut events: tokio::sync::broadcast::Receiver<BatchEvent>,
+    out: tokio::sync::mpsc::Sender<Option<BatchStatus>>,
+    idle_timeout: Duration,
+    _slots: (tokio::sync::OwnedSemaphorePermit, StreamSlot),
+) {
+    use tokio::sync::broadcast::error::RecvError;
+
+    let send = |item| send_stream_event(&out, item, idle_timeout, &batch_id);
+    let period = (idle_timeout / 3).max(Duration::from_secs(1));
+    let mut ping = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+
+    let mut last = current;
+    if !send(Some(last.clone())).await {
+        return;
+    }
+    while !last.is_terminal() {
+        let status = tokio::select! {
+            _ = out.closed() => return,
+            _ = ping.tick() => {
+                if !send(None).await {
+                    return;
+                }
+                continue;
+            }
+            event = events.recv() => match event {
+                Ok(e) if e.batch_id == batch_id => e.status,
+                Ok(_) => continue,
+                // Missed some events; the record has the latest status
+                Err(RecvError::Lagged(_)) => match store.get_batch(&batch_id).await {
+                    Ok(Some(record)) => record.status,
+                    _ => continue,
+                },
+                Err(RecvError::Closed) => return,
+            },
+        };
+        if status == last {
+            continue;
+        }
+        if !send(Some(status.clone())).await {
+            return;
+        }
+        last = status;
+    }
+}
+
+/// Hands `item` to a `/batch/{id}/events` stream. False if the client is
+/// gone or hasn't read anything for `idle_timeout`.
+async fn send_stream_event(
+    out: &tokio::sync::mpsc::Sender<Option<BatchStatus>>,
+    item: Option<BatchStatus>,
+    idle_timeout: Duration,
+    batch_id: &str,
+) -> bool {
+    use tokio::sync::mpsc::error::SendTimeoutError;
+
+    match out.send_timeout(item, idle_timeout).await {
+        Ok(()) => true,
+        Err(SendTimeoutError::Timeout(_)) => {
+            debug!(batch_id, "dropping idle batch event stream");
+            false
+        }
+        Err(SendTimeoutError::Closed(_)) => false,
+    }
+}
+
+/// Finalized and failed batches never change, so they get a strong ETag
+/// and an immutable `max-age` (304 on a matching `If-None-Match`); anything
+/// still in flight is `no-cache`. `private` because the response is behind
+/// an API key.
+fn cached_batch_response(
+    headers: &HeaderMap,
+    status: &BatchStatus,
+    body: serde_json::Value,
+    max_age_secs: u64,
+) -> Response {
+    let terminal = status.is_terminal();
+    if !terminal || max_age_secs == 0 {
+        return ([(header::CACHE_CONTROL, "no-cache".to_string())], Json(body)).into_response();
+    }
+    let digest = Sha256::digest(body.to_string().as_bytes());
+    let etag = format!("\"{}\"", hex::encode(&digest[..16]));
+    let cache_headers = [
+        (header::CACHE_CONTROL, format!("private, max-age={max_age_secs}, immutable")),
+        (header::ETAG, etag.clone()),
+    ];
+    if if_none_match(headers, &etag) {
+        return (StatusCode::NOT_MODIFIED, cache_headers).into_response();
+    }
+    (cache_headers, Json(body)).into_response()
+}
+
+/// True if any `If-None-Match` entity tag (weak comparison) is `etag`.
+fn if_none_match(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|tag| tag.trim())
+        .any(|tag| tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == etag)
+}
+
+/// Default and maximum page size for `GET /batches`.
+const DEFAULT_BATCH_PAGE: usize = 50;
+const MAX_BATCH_PAGE: usize = 100;
+
+#[derive(Debug, Default, Deserialize)]
+pub struct BatchListQuery {
+    pub status: Option<BatchStatus>,
+    pub limit: Option<usize>,
+    /// `next_cursor` from the previous page.
+    pub cursor: Option<String>,
+}
+
+/// Lists batches newest first, optionally filtered by status.
+pub async fn list_batches(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(query): Query<BatchListQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    let auth = require_auth(&headers, &state.config)?;
+    require_scope(&auth, Scope::Read)?;
+
+    let limit = query.limit.unwrap_or(DEFAULT_BATCH_PAGE).clamp(1, MAX_BATCH_PAGE);
+    let cursor = match &query.cursor {
+        Some(c) => Some(
+            BatchCursor::decode(c).ok_or_else(|| AppError::BadRequest("invalid cursor".into()))?,
+        ),
+        None => None,
+    };
+
+    let records = state
+        .store
+        .list_batches(query.status, cursor.as_ref(), limit)
+        .await
+        .map_err(|_| AppError::Internal("store error".into()))?;
+    // A full page may have more behind it; a short page is the last one
+    let next_cursor = (records.len() == limit)
+        .then(|| records.last().map(|r| BatchCursor::after(r).encode()))
+        .flatten();
+
+    let batches: Vec<_> = records
+        .iter()
+        .map(|record| {
+            json!({
+                "id": record.id,
+                "status": record.status,
+                "tx_count": record.tx_count,
+                "proof_hash": record.proof_hash,
+                "batch_id_onchain": record.batch_id_onchain,
+                "tx_hash": record.tx_hash,
+                "created_at": record.created_at,
+                "error": record.error,
+            })
+        })
+        .collect();
+    Ok(Json(json!({
+        "batches": batches,
+        "next_cursor": next_cursor,
+    })))
+}
+
+/// Looks up the batch a transaction landed in by the caller's own `client_ref`.
+/// Refs are scoped to the API key, so one client can't probe another's refs.
+pub async fn get_client_ref(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(client_ref): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let auth = require_auth(&headers, &state.config)?;
+    require_scope(&auth, Scope::Read)?;
+    let api_key = auth.key;
+    validate_client_ref(&client_ref)?;
+
+    let batch_id = state
+        .store
+        .find_batch_by_client_ref(&scoped_client_ref(&api_key, &client_ref))
+        .ok_or(AppError::NotFound("client_ref not batched yet".into()))?;
+    let record = state
+        .store
+        .get_batch(&batch_id)
+        .await
+        .map_err(|_| AppError::Internal("store error".into()))?
+        .ok_or(AppError::NotFound("batch not found".into()))?;
+
+    Ok(Json(json!({
+        "client_ref": client_ref,
+        "batch_id": record.id,
+        "status": record.status,
+        "tx_count": record.tx_count,
+        "batch_id_onchain": record.batch_id_onchain,
+        "tx_hash": record.tx_hash,
+        "created_at": record.created_at,
+        "error": record.error,
+    })))
+}
+
+pub async fn force_prove(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, AppError> {
+    let auth = require_auth(&headers, &state.config)?;
+    require_scope(&auth, Scope::ForceProve)?;
+
+    // Stricter rate limit for admin endpoint (1/5 of normal)
+    let decision = state
+        .store
+        .check_rate(
+            &format!("prove:{}", auth.key),
+            auth.rate_limit((state.config.rate_limit_per_min / 5).max(1)),
+            60,
+        )
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+    if !decision.allowed {
+        return Err(AppError::RateLimited(Some(decision.retry_after_secs)));
+    }
+
+    match state.queue.force_flush().await {
+        // `batch_id` is the first batch; per-asset queues can flush several.
+        ForceFlushOutcome::Flushed(batch_ids) => Ok(Json(json!({
+            "status": "flushed",
+            "batch_id": batch_ids[0],
+            "batch_ids": batch_ids,
+        }))),
+        ForceFlushOutcome::Empty => Ok(Json(json!({
+            "status": "empty",
+            "message": "no pending transactions to prove",
+        }))),
+        ForceFlushOutcome::BelowMinSize { pending, min_batch_size } => Ok(Json(json!({
+            "status": "below_min_size",
+            "pending": pending,
+            "min_batch_size": min_batch_size,
+            "message": "pending transactions are below the minimum batch size",
+        }))),
+        ForceFlushOutcome::ConcurrentlyDrained => Ok(Json(json!({
+            "status": "concurrently_drained",
+            "message": "a concurrent flush already batched the pending transactions",
+        }))),
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct MerklePathQuery {
+    /// Include a self-contained bundle for client-side root recomputation.
+    #[serde(default)]
+    pub verifiable: bool,
+}
+
+/// Everything a client needs to recompute `root` from `leaf` without trusting
+/// the relayer.
+///
+/// Verification: start with `current = leaf`; for each level `i`, take bit `i`
+/// of `index` (least significant first). If the bit is 0, `current` is the left
+/// child: `current = H(current || siblings[i])`, otherwise
+/// `current = H(siblings[i] || current)`, where `H` is the Poseidon2-M31 node
+/// hash of the pool's tree (reference: stwo-ml `crypto::merkle_m31`). The path
+/// is valid if `current == root`; clients should also confirm `root` with the
+/// pool contract's `is_known_root` before withdrawing against it.
+///
+/// `hash.permutation` gives the Poseidon2 parameters `H` is instantiated with,
+/// read from stwo-ml so the bundle can't drift from the tree it describes.
+///
+/// `leaf` is `null` when the relayer doesn't hold the on-chain commitment digest
+/// (the client must then supply its own).
+fn verifiable_bundle(
+    leaf: Option<[u32; 8]>,
+    siblings: &[[u32; 8]],
+    index: usize,
+    root: [u32; 8],
+) -> serde_json::Value {
+    json!({
+        "leaf": leaf,
+        "siblings": siblings,
+        "index": index,
+        "root": root,
+        "depth": siblings.len(),
+        "hash": {
+            "algorithm": "poseidon2-m31",
+            "field_modulus": M31_MODULUS,
+            "digest_limbs": 8,
+            "node": "H(left || right), 16 M31 inputs -> 8 M31 digest",
+            "index_bit_order": "lsb_first",
+            "permutation": {
+                "width": STATE_WIDTH,
+                "rate": RATE,
+                "full_rounds": 2 * N_HALF_FULL_ROUNDS,
+                "partial_rounds": N_PARTIAL_ROUNDS,
+                "sbox_degree": 5,
+                "round_constants": "stwo-ml crypto::poseidon2_m31 (EXTERNAL_ROUND_CONSTS, INTERNAL_ROUND_CONSTS)",
+            },
+        },
+    })
+}
+
+/// Most commitments a single `POST /merkle-paths` call may look up.
+pub const MAX_MERKLE_PATH_BATCH: usize = 100;
+
+fn check_commitment_format(commitment: &str) -> Result<(), AppError> {
+    // Hex string of reasonable length
+    if commitment.is_empty() || commitment.len() > 128 || commitment.chars().any(|c| !c.is_ascii_hexdigit() && c != '-' && c != '_') {
+        return Err(AppError::BadRequest("invalid commitment format".into()));
+    }
+    Ok(())
+}
+
+/// Charges `cost` lookups against the key's merkle path budget.
+async fn check_merkle_path_rate(
+    state: &AppState,
+    headers: &HeaderMap,
+    addr: SocketAddr,
+    auth: &ApiKeyConfig,
+    cost: usize,
+) -> Result<(), AppError> {
+    let limit = auth.rate_limit(state.config.merkle_path_rate_limit_per_min);
+    // A lookup never costs more than a full window, or it could never pass
+    let cost = (cost as u32).min(limit);
+    let decision = state
+        .store
+        .check_rate_n(&format!("merkle:{}", auth.key), cost, limit, 60)
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+    if !decision.allowed {
+        let client_ip = extract_client_ip(headers, Some(addr), &state.config.trusted_proxies);
+        return Err(rate_limited(state, Some(&auth.key), &client_ip, Some(decision.retry_after_secs)));
+    }
+    Ok(())
+}
+
+/// Resolves merkle paths from stored notes, falling back to on-demand
+/// proofs from the local tree.
+struct PathLookup<'a> {
+    store: &'a InMemoryStore,
+    tree_sync: Option<&'a TreeSyncService>,
+    stuck_note_threshold_secs: u64,
+    max_proof_staleness_secs: u64,
+    verifiable: bool,
+}
+
+impl<'a> PathLookup<'a> {
+    fn new(state: &'a AppState, verifiable: bool) -> Self {
+        Self {
+            store: &state.store,
+            tree_sync: state.tree_sync.as_deref(),
+            stuck_note_threshold_secs: state.config.stuck_note_threshold_secs,
+            max_proof_staleness_secs: state.config.max_proof_staleness_secs,
+            verifiable,
+        }
+    }
+
+    /// One result per commitment: the single-lookup response body, or
+    /// `NotFound` if neither the store nor the tree knows the commitment.
+    /// On-demand proofs for all of them are taken under one tree lock.
+    async fn resolve(&self, commitments: &[String]) -> Result<Vec<Result<serde_json::Value, AppError>>, AppError> {
+        let notes = commitments
+            .iter()
+            .map(|c| self.store.get_note_shared(c))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| AppError::Internal("store error".into()))?;
+
+        // Notes without a real merkle root, and commitments the store doesn't hold
+        let mut proofs: Vec<Option<Result<Option<ProofResult>, TreeSyncError>>> =
+            commitments.iter().map(|_| None).collect();
+        if let Some(ts) = self.tree_sync {
+            let wanted: Vec<usize> = notes
+                .iter()
+                .enumerate()
+                .filter(|(_, note)| note.as_ref().map_or(true, |n| n.merkle_root == [0; 8]))
+                .map(|(i, _)| i)
+                .collect();
+            if !wanted.is_empty() {
+                let hexes: Vec<&str> = wanted.iter().map(|&i| commitments[i].as_str()).collect();
+                match ts.get_proofs(&hexes).await {
+                    Ok(found) => {
+                        for (&i, proof) in wanted.iter().zip(found) {
+                            proofs[i] = Some(Ok(proof));
+                        }
+                    }
+                    Err(e) => {
+                        for &i in &wanted {
+                            proofs[i] = Some(Err(e.clone()));
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut results = Vec::with_capacity(commitments.len());
+        for ((commitment, note), proof) in commitments.iter().zip(notes).zip(proofs) {
+            results.push(self.body(commitment, note.as_deref(), proof).await);
+        }
+        Ok(results)
+    }
+
+    async fn body(
+        &self,
+        commitment: &str,
+        note: Option<&NoteRecord>,
+        proof: Option<Result<Option<ProofResult>, TreeSyncError>>,
+    ) -> Result<serde_json::Value, AppError> {
+        // A note with a real merkle root is returned directly
+        if let Some(note) = note.filter(|n| n.merkle_root != [0; 8]) {
+            let mut body = json!({
+                "commitment": note.commitment,
+                "merkle_path": {
+                    "siblings": note.merkle_path.siblings,
+                    "index": note.merkle_path.index,
+                },
+                "merkle_root": note.merkle_root,
+                "batch_id": note.batch_id,
+                "created_at": note.created_at,
+            });
+            if self.verifiable {
+                body["verifiable"] = verifiable_bundle(
+                    note.commitment_digest,
+                    &note.merkle_path.siblings,
+                    note.merkle_path.index,
+                    note.merkle_root,
+                );
+            }
+            return Ok(body);
+        }
+
+        let proof = match proof {
+            Some(Err(e)) => return Ok(self.stale_tree_body(e, note)),
+            Some(Ok(proof)) => proof,
+            None => None,
+        };
+        if let Some(proof) = proof {
+            let tree_sync_age_secs = self.tree_sync.and_then(|ts| ts.last_sync_age_secs());
+            let mut body = match note {
+                Some(note) => {
+                    // Update the store record with the real proof
+                    let mut updated = note.clone();
+                    updated.merkle_path = MerklePathRecord {
+                        siblings: proof.siblings.clone(),
+                        index: proof.index,
+                    };
+                    updated.merkle_root = proof.root;
+                    let _ = self.store.save_note(commitment, &updated).await;
+                    json!({
+                        "commitment": updated.commitment,
+                        "merkle_path": {
+                            "siblings": proof.siblings,
+                            "index": proof.index,
+                        },
+                        "merkle_root": proof.root,
+                        "batch_id": updated.batch_id,
+                        "created_at": updated.created_at,
+                        "tree_sync_age_secs": tree_sync_age_secs,
+                    })
+                }
+                None => json!({
+                    "commitment": commitment,
+                    "merkle_path": {
+                        "siblings": proof.siblings,
+                        "index": proof.index,
+                    },
+                    "merkle_root": proof.root,
+                    "batch_id": null,
+                    "created_at": null,
+                    "tree_sync_age_secs": tree_sync_age_secs,
+                }),
+            };
+            if self.verifiable {
+                body["verifiable"] =
+                    verifiable_bundle(Some(proof.leaf), &proof.siblings, proof.index, proof.root);
+            }
+            return Ok(body);
+        }
+
+        let Some(note) = note else {
+            return Err(AppError::NotFound("note not indexed yet".into()));
+        };
+
+        // Note can never be backfilled without its on-chain digest — say so
+        // instead of letting clients poll `pending_sync` forever.
+        let now = crate::store::now_epoch();
+        if note.is_stuck(now, self.stuck_note_threshold_secs) {
+            return Ok(json!({
+                "commitment": note.commitment,
+                "merkle_path": null,
+                "merkle_root": null,
+                "batch_id": note.batch_id,
+                "created_at": note.created_at,
+                "status": "stuck",
+                "reason": "missing_commitment_digest",
+                "pending_secs": now.saturating_sub(note.created_at),
+                "guidance": "note cannot be matched to an on-chain commitment; stop polling and contact the relayer operator",
+            }));
+        }
+
+        // Note exists but proof not available yet — return pending status
+        Ok(json!({
+            "commitment": note.commitment,
+            "merkle_path": null,
+            "merkle_root": null,
+            "batch_id": note.batch_id,
+            "created_at": note.created_at,
+            "status": "pending_sync",
+        }))
+    }
+
+    /// Body for an on-demand proof refused because the local tree is stale
+    /// or its root unconfirmed. Clients should retry later rather than build
+    /// a withdrawal against it.
+    fn stale_tree_body(&self, err: TreeSyncError, note: Option<&NoteRecord>) -> serde_json::Value {
+        let (status, last_sync_age_secs) = match err {
+            TreeSyncError::Stale { last_sync_age_secs } => ("stale", last_sync_age_secs),
+            TreeSyncError::Unconfirmed => (
+                "unconfirmed",
+                self.tree_sync.and_then(|ts| ts.last_sync_age_secs()),
+            ),
+            _ => ("stale", None),
+        };
+        json!({
+            "commitment": note.map(|n| n.commitment.as_str()),
+            "merkle_path": null,
+            "merkle_root": null,
+            "batch_id": note.map(|n| n.batch_id.as_str()),
+            "created_at": note.map(|n| n.created_at),
+            "status": status,
+            "tree_sync_age_secs": last_sync_age_secs,
+            "max_staleness_secs": self.max_proof_staleness_secs,
+        })
+    }
+}
+
+pub async fn get_merkle_path(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Path(commitment): Path<String>,
+    Query(query): Query<MerklePathQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    let auth = require_auth(&headers, &state.config)?;
+    require_scope(&auth, Scope::Read)?;
+    check_commitment_format(&commitment)?;
+    check_merkle_path_rate(&state, &headers, addr, &auth, 1).await?;
+
+    let lookup = PathLookup::new(&state, query.verifiable);
+    let result = lookup.resolve(std::slice::from_ref(&commitment)).await?.pop();
+    let body = result.unwrap_or_else(|| Err(AppError::NotFound("note not indexed yet".into())))?;
+    Ok((StatusCode::OK, Json(body)))
+}
+
+/// Looks up several merkle paths at once. Results are index-aligned with
+/// the request; commitments nothing knows about yet report `pending_sync`
+/// instead of failing the whole call.
+pub async fn get_merkle_paths(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Query(query): Query<MerklePathQuery>,
+    Json(commitments): Json<Vec<String>>,
+) -> Result<impl IntoResponse, AppError> {
+    let auth = require_auth(&headers, &state.config)?;
+    require_scope(&auth, Scope::Read)?;
+    if commitments.is_empty() || commitments.len() > MAX_MERKLE_PATH_BATCH {
+        return Err(AppError::BadRequest(format!(
+            "expected 1 to {MAX_MERKLE_PATH_BATCH} commitments"
+        )));
+    }
+    for (i, commitment) in commitments.iter().enumerate() {
+        check_commitment_format(commitment)
+            .map_err(|_| AppError::BadRequest(format!("commitments[{i}]: invalid commitment format")))?;
+    }
+    check_merkle_path_rate(&state, &headers, addr, &auth, commitments.len()).await?;
+
+    let results = merkle_paths(&PathLookup::new(&state, query.verifiable), &commitments).await?;
+    Ok(Json(results))
+}
+
+async fn merkle_paths(
+    lookup: &PathLookup<'_>,
+    commitments: &[String],
+) -> Result<Vec<serde_json::Value>, AppError> {
+    let resolved = lookup.resolve(commitments).await?;
+    commitments
+        .iter()
+        .zip(resolved)
+        .map(|(commitment, result)| match result {
+            Err(AppError::NotFound(_)) => Ok(json!({
+                "commitment": commitment,
+                "merkle_path": null,
+                "merkle_root": null,
+                "batch_id": null,
+                "created_at": null,
+                "status": "pending_sync",
+            })),
+            other => other,
+        })
+        .collect()
+}
+
+/// Store keys and sync status of every note indexed under an owner pubkey
+/// (`VM31_NOTE_OWNER_INDEX`), so an operator can help a wallet that lost its
+/// local state find its notes again. Admin only: a pubkey is public, so any
+/// caller could otherwise list another owner's notes.
+pub async fn notes_by_owner(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(pubkey): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    require_admin(&state, &headers, "notes-by-owner").await?;
+    let owner = parse_pubkey(&pubkey)
+        .ok_or_else(|| AppError::BadRequest("pubkey must be 32 hex digits".into()))?;
+
+    let keys = state
+        .store
+        .notes_by_owner(&owner)
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+    let now = now_epoch();
+    let mut notes = Vec::with_capacity(keys.len());
+    for key in &keys {
+        // The index can outlive a note record dropped by the backend
+        let Some(note) = state.store.get_note_shared(key).map_err(|_| AppError::Internal("store error".into()))? else {
+            continue;
+        };
+        notes.push(json!({
+            "commitment": key,
+            "batch_id": note.batch_id,
+            "created_at": note.created_at,
+            "status": note_sync_status(&note, now, state.config.stuck_note_threshold_secs),
+        }));
+    }
+    Ok(Json(json!({
+        "owner": pubkey_hex(&owner),
+        "notes": notes,
+    })))
+}
+
+/// `synced` once a note has a merkle root, else `stuck` or `pending_sync`
+/// as for `/merkle-path`.
+fn note_sync_status(note: &NoteRecord, now: u64, stuck_after_secs: u64) -> &'static str {
+    if note.merkle_root != [0; 8] {
+        "synced"
+    } else if note.is_stuck(now, stuck_after_secs) {
+        "stuck"
+    } else {
+        "pending_sync"
+    }
+}
+
+/// Admin: sync the local tree with the pool contract, then backfill pending notes.
+/// Returns 409 if a sync or backfill is already running.
+pub async fn tree_resync(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, AppError> {
+    require_admin(&state, &headers, "tree").await?;
+    let ts = state
+        .tree_sync
+        .as_ref()
+        .ok_or_else(|| AppError::NotFound("tree sync service disabled".into()))?;
+    let filled = ts.resync_now().await.map_err(tree_sync_error)?;
+    Ok(Json(json!({
+        "status": "synced",
+        "backfilled": filled,
+    })))
+}
+
+/// Admin: stop taking batches for proving. Submissions keep being accepted
+/// and queued; flushed batches buffer until `/admin/prover/resume`.
+pub async fn pause_prover(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, AppError> {
+    require_admin(&state, &headers, "prover").await?;
+    let changed = state.prover_pause.pause();
+    if changed {
+        info!("prover paused by admin");
+    }
+    Ok(Json(json!({
+        "status": "paused",
+        "changed": changed,
+    })))
+}
+
+/// Admin: resume proving buffered and new batches.
+pub async fn resume_prover(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, AppError> {
+    require_admin(&state, &headers, "prover").await?;
+    let changed = state.prover_pause.resume();
+    if changed {
+        info!("prover resumed by admin");
+    }
+    Ok(Json(json!({
+        "status": "running",
+        "changed": changed,
+    })))
+}
+
+/// Admin: backfill pending notes against the current local tree.
+/// Returns 409 if a sync or backfill is already running.
+pub async fn tree_backfill(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, AppError> {
+    require_admin(&state, &headers, "tree").await?;
+    let ts = state
+        .tree_sync
+        .as_ref()
+        .ok_or_else(|| AppError::NotFound("tree sync service disabled".into()))?;
+    let filled = ts.backfill_now().await.map_err(tree_sync_error)?;
+    Ok(Json(json!({
+        "status": "backfilled",
+        "backfilled": filled,
+    })))
+}
+
+/// Admin: sync the tree now and backfill one finalized batch's notes,
+/// instead of waiting for the next background tick.
+/// Returns 409 if a sync or backfill is already running.
+pub async fn batch_backfill(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(batch_id): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    require_admin(&state, &headers, "tree").await?;
+    let ts = state
+        .tree_sync
+        .as_ref()
+        .ok_or_else(|| AppError::NotFound("tree sync service disabled".into()))?;
+    let record = state
+        .store
+        .get_batch(&batch_id)
+        .await
+        .map_err(|_| AppError::Internal("store error".into()))?
+        .ok_or_else(|| AppError::NotFound(format!("batch {batch_id} not found")))?;
+    if record.status != BatchStatus::Finalized {
+        return Err(AppError::Conflict(format!(
+            "batch {batch_id} is not finalized; its notes are not on-chain yet"
+        )));
+    }
+    let filled = ts
+        .backfill_batch_now(&batch_id)
+        .await
+        .map_err(tree_sync_error)?;
+    Ok(Json(json!({
+        "status": "backfilled",
+        "batch_id": batch_id,
+        "backfilled": filled,
+    })))
+}
+
+/// Admin: streams a snapshot of the whole store as NDJSON (see `snapshot`).
+pub async fn export_store(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, AppError> {
+    require_admin(&state, &headers, "store").await?;
+    // Bounded channel: the exporter waits for the client to read each line
+    let (tx, rx) = tokio::sync::mpsc::channel::<String>(64);
+    let store = state.store.clone();
+    tokio::spawn(async move {
+        match snapshot::export(&store, tx).await {
+            Ok(entries) => info!(entries, "store snapshot exported"),
+            Err(e) => warn!(error = %e, "store snapshot export aborted"),
+        }
+    });
+    let body = axum::body::Body::from_stream(
+        ReceiverStream::new(rx).map(Ok::<_, std::convert::Infallible>),
+    );
+    Ok(([(axum::http::header::CONTENT_TYPE, "application/x-ndjson")], body))
+}
+
+/// Admin: loads a snapshot from `/admin/store/export` into this relayer's
+/// store, which must not hold any batches or notes yet. The body is read
+/// line by line. A failed import leaves the store partially filled;
+/// restart with an empty store before retrying.
+pub async fn import_store(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    body: axum::body::Body,
+) -> Result<impl IntoResponse, AppError> {
+    require_admin(&state, &headers, "store").await?;
+    if !state.store.has_no_records() {
+        return Err(AppError::Conflict(
+            "store already holds batches or notes; import into a fresh store".into(),
+        ));
+    }
+
+    let mut importer = snapshot::Importer::new(&state.store);
+    let mut stream = body.into_data_stream();
+    let mut lines = LineSplitter::new(snapshot::MAX_LINE_BYTES);
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|e| AppError::BadRequest(format!("snapshot body: {e}")))?;
+        for line in lines.push(&chunk) {
+            apply_snapshot_line(&mut importer, &line).await?;
+        }
+        if lines.too_long() {
+            return Err(AppError::BadRequest("snapshot line too long".into()));
+        }
+    }
+    if let Some(line) = lines.finish() {
+        apply_snapshot_line(&mut importer, &line).await?;
+    }
+    let summary = importer
+        .finish()
+        .map_err(|e| AppError::BadRequest(format!("import incomplete: {e}")))?;
+    info!(?summary, "store snapshot imported");
+    Ok(Json(json!({
+        "status": "imported",
+        "imported": summary,
+    })))
+}
+
+async fn apply_snapshot_line(
+    importer: &mut snapshot::Importer<'_>,
+    line: &[u8],
+) -> Result<(), AppError> {
+    let line = std::str::from_utf8(line)
+        .map_err(|_| AppError::BadRequest("snapshot is not valid UTF-8".into()))?;
+    importer
+        .apply(line)
+        .await
+        .map_err(|e| AppError::BadRequest(format!("import failed: {e}")))
+}
+
+/// Authenticates an admin request against `admin_keys` (`VM31_ADMIN_KEYS`).
+/// Without a separate set, any API key with the force_prove scope is
+/// accepted. A valid API key that isn't an admin key gets 403.
+fn authenticate_admin(
+    headers: &HeaderMap,
+    api_keys: &ApiKeys,
+    admin_keys: Option<&ApiKeys>,
+) -> Result<ApiKeyConfig, AppError> {
+    // No admin keys: the admin endpoints are closed. API keys are never
+    // admins by scope, since a plain VM31_API_KEYS list grants every scope.
+    let Some(admin_keys) = admin_keys else {
+        authenticate(headers, api_keys)?;
+        info!("admin endpoint called but VM31_ADMIN_KEYS is not set");
+        return Err(AppError::Forbidden);
+    };
+    authenticate(headers, admin_keys).map_err(|e| match authenticate(headers, api_keys) {
+        Ok(_) => {
+            info!("API key is not an admin key");
+            AppError::Forbidden
+        }
+        Err(_) => e,
+    })
+}
+
+/// Admin auth + admin-rate-limit shared by admin endpoints (same budget as
+/// `/prove`).
+/// `scope` keeps each admin surface on its own rate-limit bucket.
+async fn require_admin(state: &AppState, headers: &HeaderMap, scope: &str) -> Result<ApiKeyConfig, AppError> {
+    let auth = authenticate_admin(headers, &state.config.api_keys, state.config.admin_keys.as_ref())?;
+    let decision = state
+        .store
+        .check_rate(
+            &format!("{scope}:{}", auth.key),
+            auth.rate_limit((state.config.rate_limit_per_min / 5).max(1)),
+            60,
+        )
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+    if !decision.allowed {
+        return Err(AppError::RateLimited(Some(decision.retry_after_secs)));
+    }
+    Ok(auth)
+}
+
+/// Developer aid: reports whether the relayer can open and parse an envelope.
+///
+/// Only pass/fail and a generic category are returned — never plaintext or
+/// parse details — and the envelope is not validated, recorded or queued.
+/// Enabled by `VM31_ENCRYPT_CHECK` (never on mainnet) and tightly rate-limited.
+pub async fn encrypt_check(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(enc): Json<EncryptedSubmitRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let auth = require_auth(&headers, &state.config)?;
+    require_scope(&auth, Scope::Submit)?;
+    let api_key = auth.key.clone();
+    let limit = (state.config.rate_limit_per_min / 10).max(1);
+    let client_ip = extract_client_ip(&headers, Some(addr), &state.config.trusted_proxies);
+    for (key, limit) in [
+        (format!("encrypt-check:{api_key}"), auth.rate_limit(limit)),
+        (format!("encrypt-check-ip:{client_ip}"), limit),
+    ] {
+        let decision = state
+            .store
+            .check_rate(&key, limit, 60)
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+        if !decision.allowed {
+            return Err(rate_limited(&state, Some(&api_key), &client_ip, Some(decision.retry_after_secs)));
+        }
+    }
+
+    let keys = RelayerKeys::from_config(&state.config)?;
+    let timestamps = TimestampPolicy::from_config(&state.config);
+    let (decrypted, deserialized, category) = match enc.open(&keys) {
+        Ok(plaintext) => match serde_json::from_slice::<SealedSubmitRequest>(&plaintext) {
+            Ok(sealed) => match timestamps.check(sealed.timestamp, now_epoch()) {
+                Ok(()) => (true, true, None),
+                Err(e) => (true, true, Some(e.category())),
+            },
+            Err(_) => (true, false, Some("invalid_payload")),
+        },
+        Err(EnvelopeError::Internal(msg)) => return Err(AppError::Internal(msg)),
+        Err(e) => (false, false, Some(e.category())),
+    };
+    Ok(Json(json!({
+        "decrypted": decrypted,
+        "deserialized": deserialized,
+        "error_category": category,
+    })))
+}
+
+fn tree_sync_error(e: TreeSyncError) -> AppError {
+    match e {
+        TreeSyncError::InProgress => AppError::Conflict(e.to_string()),
+        TreeSyncError::Failed(msg) => AppError::Internal(msg),
+        TreeSyncError::Stale { .. } | TreeSyncError::Unconfirmed => {
+            AppError::Internal(e.to_string())
+        }
+    }
+}
+
+/// Admin: list submissions held for review (metadata only).
+pub async fn list_quarantine(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, AppError> {
+    require_admin(&state, &headers, "quarantine").await?;
+    Ok(Json(json!({
+        "quarantined": state.quarantine.list(),
+    })))
+}
+
+/// Admin: release a held submission into the batch queue.
+pub async fn approve_quarantined(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let admin = require_admin(&state, &headers, "quarantine").await?;
+    // A reviewer can't release their own held submissions
+    match state.quarantine.submitted_by(&id, &admin.key) {
+        None => return Err(AppError::NotFound("quarantined submission not found".into())),
+        Some(true) => {
+            info!(quarantine_id = %id, "admin key tried to approve its own quarantined submission");
+            return Err(AppError::Forbidden);
+        }
+        Some(false) => {}
+    }
+    let pending = state.queue.pending_count().await;
+    if pending >= MAX_PENDING_TXS {
+        return Err(batch_full(&state.config, pending));
+    }
+    let held = state
+        .quarantine
+        .take(&id)
+        .ok_or(AppError::NotFound("quarantined submission not found".into()))?;
+    let (batch_id, queue_pos) = state
+        .queue
+        .push_keyed(held.tx, held.client_ref, None, None, held.recipient)
+        .await;
+    info!(quarantine_id = %id, reason = %held.summary.reason, "quarantined submission approved");
+    Ok(Json(json!({
+        "status": if batch_id.is_some() { "batch_triggered" } else { "queued" },
+        "batch_id": batch_id,
+        "queue_position": queue_pos,
+    })))
+}
+
+/// Admin: drop a held submission.
+pub async fn reject_quarantined(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    require_admin(&state, &headers, "quarantine").await?;
+    let held = state
+        .quarantine
+        .take(&id)
+        .ok_or(AppError::NotFound("quarantined submission not found".into()))?;
+    info!(quarantine_id = %id, reason = %held.summary.reason, "quarantined submission rejected");
+    Ok(Json(json!({
+        "status": "rejected",
+        "id": id,
+    })))
+}
+
+/// Admin: list batches whose transactions were kept after a failure.
+pub async fn list_dead_letters(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, AppError> {
+    require_admin(&state, &headers, "dlq").await?;
+    let list = state
+        .store
+        .list_dead_letters()
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+    Ok(Json(json!({ "dead_letters": list })))
+}
+
+/// Admin: re-enqueue a dead-lettered batch's transactions. A failure after
+/// on-chain submission can be ambiguous, so only replay batches known not
+/// to have landed.
+pub async fn replay_dead_letter(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(batch_id): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    require_admin(&state, &headers, "dlq").await?;
+    Ok(Json(replay_dead_letter_into(&state.store, &state.queue, &batch_id).await?))
+}
+
+/// Pushes a dead-lettered batch back into `queue`, each tx under a fresh
+/// idempotency key so the original submissions' keys don't dedup it. The
+/// entry is kept if the queue can't take every tx.
+pub(crate) async fn replay_dead_letter_into(
+    store: &InMemoryStore,
+    queue: &BatchQueue,
+    batch_id: &str,
+) -> Result<serde_json::Value, AppError> {
+    let internal = |e: crate::store::StoreError| AppError::Internal(e.to_string());
+    let entry = store
+        .take_dead_letter(batch_id)
+        .await
+        .map_err(internal)?
+        .ok_or_else(|| AppError::NotFound("no dead-lettered batch with that id".into()))?;
+    // Already checked against the live denomination table when accepted
+    let unrestricted = Denominations::unrestricted();
+    let converted = entry
+        .transactions
+        .iter()
+        .map(|req| Ok((req.validate_and_convert(&unrestricted)?, req.withdrawal_recipient()?)))
+        .collect::<Result<Vec<_>, AppError>>();
+    let txs = match converted {
+        Ok(txs) if queue.pending_count().await + txs.len() <= MAX_PENDING_TXS => txs,
+        rejected => {
+            store.save_dead_letter(entry).await.map_err(internal)?;
+            return Err(rejected.err().unwrap_or(AppError::BatchFull(None)));
+        }
+    };
+
+    let mut idempotency_keys = Vec::with_capacity(txs.len());
+    let mut triggered = Vec::new();
+    for (i, (tx, recipient)) in txs.into_iter().enumerate() {
+        let key = format!("replay:{}", uuid::Uuid::new_v4());
+        store.check_and_set(&key, "pending").await.map_err(internal)?;
+        let client_ref = entry.client_refs.get(i).cloned().flatten();
+        let (flushed, _) = queue.push_keyed(tx, client_ref, Some(key.clone()), None, recipient).await;
+        triggered.extend(flushed);
+        idempotency_keys.push(key);
+    }
+    info!(batch_id = %batch_id, tx_count = idempotency_keys.len(), "dead-lettered batch replayed");
+    Ok(json!({
+        "status": "replayed",
+        "batch_id": batch_id,
+        "idempotency_keys": idempotency_keys,
+        "triggered_batches": triggered,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note_json() -> NoteJson {
+        NoteJson {
+            owner_pubkey: [1, 2, 3, 4],
+            asset_id: 0,
+            amount_lo: 100_000,
+            amount_hi: 0,
+            blinding: [5, 6, 7, 8],
+        }
+    }
+
+    fn withdraw(merkle_root: [u32; 8], withdrawal_binding: [u32; 8]) -> SubmitRequest {
+        SubmitRequest::Withdraw {
+            amount: 100_000,
+            asset_id: 0,
+            note: note_json(),
+            spending_key: [9, 10, 11, 12],
+            merkle_path: MerklePathJson { siblings: vec![[1; 8]], index: 0 },
+            merkle_root,
+            withdrawal_binding,
+            binding_salt: None,
+            payout_recipient: Some("0x0A1".into()),
+            credit_recipient: Some("0xc1".into()),
+            client_ref: None,
+        }
+    }
+
+    fn set_recipients(req: &mut SubmitRequest, payout: Option<&str>, credit: Option<&str>) {
+        if let SubmitRequest::Withdraw { payout_recipient, credit_recipient, .. } = req {
+            *payout_recipient = payout.map(Into::into);
+            *credit_recipient = credit.map(Into::into);
+        }
+    }
+
+    #[test]
+    fn test_withdrawal_recipients_validated_and_normalized() {
+        let req = withdraw([7; 8], [8; 8]);
+        let recipient = req.withdrawal_recipient().unwrap().unwrap();
+        assert_eq!(recipient, WithdrawalRecipient { payout: "0x0a1".into(), credit: "0xc1".into() });
+        assert!(transfer([7; 8]).withdrawal_recipient().unwrap().is_none());
+
+        let mut unset = withdraw([7; 8], [8; 8]);
+        set_recipients(&mut unset, None, None);
+        assert!(unset.withdrawal_recipient().unwrap().is_none());
+        // Still a valid wire form (queue WAL written before recipients existed)
+        unset.validate_and_convert(&Denominations::builtin()).unwrap();
+
+        let zero = format!("0x{}", "0".repeat(64));
+        let prime = format!("0x0800000000000011{}1", "0".repeat(47));
+        for (payout, credit, expected) in [
+            (Some("0xa1"), None, "must be given together"),
+            (Some("0xzz"), Some("0xc1"), "payout_recipient must be a Starknet address"),
+            (Some("0xa1"), Some(""), "credit_recipient must be a Starknet address"),
+            (Some("0x0"), Some("0xc1"), "payout_recipient must not be zero"),
+            (Some("0xa1"), Some(zero.as_str()), "credit_recipient must not be zero"),
+            (Some(prime.as_str()), Some("0xc1"), "not below the Starknet field prime"),
+        ] {
+            let mut req = withdraw([7; 8], [8; 8]);
+            set_recipients(&mut req, payout, credit);
+            let msg = req.withdrawal_recipient().unwrap_err().to_string();
+            assert!(msg.contains(expected), "{payout:?}/{credit:?}: {msg}");
+            assert!(req.validate_and_convert(&Denominations::builtin()).is_err());
+        }
+    }
+
+    #[test]
+    fn test_withdrawal_recipients_survive_wire_form() {
+        let req = withdraw([7; 8], [8; 8]);
+        let recipient = req.withdrawal_recipient().unwrap();
+        let tx = req.validate_and_convert(&Denominations::builtin()).unwrap();
+
+        let wire = SubmitRequest::from(&tx);
+        assert!(wire.withdrawal_recipient().unwrap().is_none());
+        let wire = wire.with_withdrawal_recipient(recipient.as_ref());
+        assert_eq!(wire.withdrawal_recipient().unwrap(), recipient);
+        // The binding commits to the recipients, so they don't change the key
+        assert_eq!(wire.idempotency_key(), req.idempotency_key());
+    }
+
+    fn transfer(merkle_root: [u32; 8]) -> SubmitRequest {
+        let input = || InputNoteJson {
+            note: note_json(),
+            spending_key: [9, 10, 11, 12],
+            merkle_path: MerklePathJson { siblings: vec![[1; 8]], index: 0 },
+        };
+        SubmitRequest::Transfer {
+            amount: 50_000,
+            asset_id: 0,
+            recipient_pubkey: [1; 4],
+            recipient_viewing_key: [2; 4],
+            sender_viewing_key: [3; 4],
+            input_notes: [input(), input()],
+            merkle_root,
+            client_ref: None,
+        }
+    }
+
+    #[test]
+    fn test_transfer_change() {
+        // Two 100_000 inputs, 50_000 sent
+        assert_eq!(
+            transfer([7; 8]).transfer_change().unwrap(),
+            NoteAmount::new(150_000)
+        );
+        let mut exact = transfer([7; 8]);
+        if let SubmitRequest::Transfer { amount, .. } = &mut exact {
+            *amount = 200_000;
+        }
+        assert_eq!(exact.transfer_change().unwrap(), Some(NoteAmount::ZERO));
+        assert!(exact.validate_and_convert(&Denominations::builtin()).is_ok());
+        assert_eq!(withdraw([7; 8], [7; 8]).transfer_change().unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_reused_binding_salt_rejected() {
+        let store = InMemoryStore::new();
+        let first = withdraw([7; 8], [9; 8]);
+        // Same binding (reused salt), otherwise a different withdrawal
+        let mut reused = withdraw([7; 8], [9; 8]);
+        if let SubmitRequest::Withdraw { amount, .. } = &mut reused {
+            *amount = 50_000;
+        }
+        assert_ne!(first.idempotency_key(), reused.idempotency_key());
+
+        reserve_withdrawal_binding(&store, &first, &first.idempotency_key()).await.unwrap();
+        // A retry of the same submission keeps its reservation
+        reserve_withdrawal_binding(&store, &first, &first.idempotency_key()).await.unwrap();
+        assert!(matches!(
+            reserve_withdrawal_binding(&store, &reused, &reused.idempotency_key()).await,
+            Err(AppError::Conflict(_))
+        ));
+
+        let fresh = withdraw([7; 8], [10; 8]);
+        reserve_withdrawal_binding(&store, &fresh, &fresh.idempotency_key()).await.unwrap();
+        // Non-withdrawals carry no binding
+        let t = transfer([7; 8]);
+        reserve_withdrawal_binding(&store, &t, &t.idempotency_key()).await.unwrap();
+    }
+
+    fn deposit(amount: u64, asset_id: u32) -> PendingTx {
+        SubmitRequest::Deposit {
+            amount,
+            asset_id,
+            recipient_pubkey: [1; 4],
+            recipient_viewing_key: [2; 4],
+            blinding: None,
+            client_ref: None,
+        }
+        .validate_and_convert(&Denominations::unrestricted())
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_deposit_limits_per_asset_max_and_daily_cap() {
+        let store = InMemoryStore::new();
+        let limits = HashMap::from([
+            (0, DepositLimit { max_amount: Some(100_000), daily_cap: Some(250_000) }),
+            (1, DepositLimit { max_amount: Some(10_000), daily_cap: None }),
+        ]);
+        // One second before a UTC midnight
+        let now = 20_000 * 86_400 - 1;
+
+        assert!(matches!(
+            validate_deposit_limits(&store, &limits, "k", &deposit(1_000_000, 0), now).await,
+            Err(AppError::BadRequest(_))
+        ));
+        assert!(matches!(
+            validate_deposit_limits(&store, &limits, "k", &deposit(100_000, 1), now).await,
+            Err(AppError::BadRequest(_))
+        ));
+        // Unlisted assets are unlimited
+        validate_deposit_limits(&store, &limits, "k", &deposit(1_000_000, 2), now).await.unwrap();
+
+        for _ in 0..2 {
+            validate_deposit_limits(&store, &limits, "k", &deposit(100_000, 0), now).await.unwrap();
+        }
+        let err = validate_deposit_limits(&store, &limits, "k", &deposit(100_000, 0), now)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AppError::RateLimited(Some(1))), "{err:?}");
+        // The rejected deposit wasn't counted: what's left of the cap still fits
+        validate_deposit_limits(&store, &limits, "k", &deposit(50_000, 0), now).await.unwrap();
+        // Other keys have their own total
+        validate_deposit_limits(&store, &limits, "other", &deposit(100_000, 0), now).await.unwrap();
+        // Withdrawals and transfers aren't deposit volume
+        let w = withdraw([7; 8], [8; 8]).validate_and_convert(&Denominations::builtin()).unwrap();
+        validate_deposit_limits(&store, &limits, "k", &w, now).await.unwrap();
+
+        // The total starts over at the UTC day boundary
+        validate_deposit_limits(&store, &limits, "k", &deposit(100_000, 0), now + 1).await.unwrap();
+    }
+
+    #[test]
+    fn test_binding_replay_rejected_within_window() {
+        let store = InMemoryStore::new();
+        let now = 1_000_000;
+        let first = withdraw([7; 8], [9; 8]);
+        check_binding_replay(&store, &first, 300, now).unwrap();
+        // The same withdrawal again a few seconds later, e.g. under a new
+        // idempotency key, before its nullifier is spent on-chain
+        assert!(matches!(
+            check_binding_replay(&store, &first, 300, now + 5),
+            Err(AppError::Conflict(_))
+        ));
+
+        // Distinct bindings, and txs without one, pass
+        check_binding_replay(&store, &withdraw([7; 8], [10; 8]), 300, now + 5).unwrap();
+        let t = transfer([7; 8]);
+        check_binding_replay(&store, &t, 300, now).unwrap();
+        check_binding_replay(&store, &t, 300, now).unwrap();
+
+        // Remembered for the window only; 0 turns the check off
+        check_binding_replay(&store, &first, 300, now + 300).unwrap();
+        check_binding_replay(&store, &first, 0, now + 301).unwrap();
+    }
+
+    fn headers_with_key(key: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-api-key", key.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_scope_enforcement() {
+        let keys = ApiKeys::parse(
+            r#"{"dash": {"scopes": ["read"]}, "ops": {"scopes": ["read", "force_prove"]}}"#,
+        )
+        .unwrap();
+        let dash = authenticate(&headers_with_key("dash"), &keys).unwrap();
+        assert!(require_scope(&dash, Scope::Read).is_ok());
+        assert!(matches!(require_scope(&dash, Scope::Submit), Err(AppError::Forbidden)));
+        assert!(matches!(require_scope(&dash, Scope::ForceProve), Err(AppError::Forbidden)));
+
+        let ops = authenticate(&headers_with_key("ops"), &keys).unwrap();
+        assert!(require_scope(&ops, Scope::ForceProve).is_ok());
+        assert!(matches!(require_scope(&ops, Scope::Submit), Err(AppError::Forbidden)));
+
+        assert!(matches!(
+            authenticate(&headers_with_key("nope"), &keys),
+            Err(AppError::Unauthorized)
+        ));
+        // Legacy comma-separated keys keep every scope
+        let legacy = ApiKeys::parse("k1,k2").unwrap();
+        let k1 = authenticate(&headers_with_key("k1"), &legacy).unwrap();
+        assert!(Scope::ALL.iter().all(|s| require_scope(&k1, *s).is_ok()));
+    }
+
+    #[test]
+    fn test_line_splitter_across_chunks() {
+        let mut lines = LineSplitter::new(16);
+        assert!(lines.push(b"{\"a\":").is_empty());
+        assert_eq!(lines.push(b"1}\n\n{\"b\""), vec![b"{\"a\":1}\n".to_vec(), b"\n".to_vec()]);
+        assert!(!lines.too_long());
+        assert!(lines.push(b":2}").is_empty());
+        assert_eq!(lines.finish(), Some(b"{\"b\":2}".to_vec()));
+
+        // Only the unterminated remainder counts against the cap
+        let mut lines = LineSplitter::new(4);
+        assert_eq!(lines.push(b"ab\ncdefg").len(), 1);
+        assert!(lines.too_long());
+        assert_eq!(LineSplitter::new(4).finish(), None);
+    }
+
+    #[test]
+    fn test_stream_error_lines_carry_line_and_status() {
+        let item: serde_json::Value = serde_json::from_str(&stream_error_line(3, "line too long")).unwrap();
+        assert_eq!(item["line"], 3);
+        assert_eq!(item["http_status"], 400);
+        assert_eq!(item["code"], "BAD_REQUEST");
+
+        let body = AppError::RateLimited(Some(12)).body();
+        assert_eq!((body["code"].as_str(), body["retry_after_secs"].as_u64()), (Some("RATE_LIMITED"), Some(12)));
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_response_has_retry_after() {
+        let store = InMemoryStore::new();
+        while store.check_rate("key:exhausted", 3, 60).await.unwrap().allowed {}
+        let decision = store.check_rate("key:exhausted", 3, 60).await.unwrap();
+        assert!(!decision.allowed);
+
+        let resp = AppError::RateLimited(Some(decision.retry_after_secs)).into_response();
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        let secs: u64 = resp.headers()[header::RETRY_AFTER].to_str().unwrap().parse().unwrap();
+        assert!((1..=60).contains(&secs));
+
+        // Concurrency rejections have no window to report
+        let resp = AppError::RateLimited(None).into_response();
+        assert!(resp.headers().get(header::RETRY_AFTER).is_none());
+    }
+
+    #[test]
+    fn test_batch_full_retry_after_tracks_queue_depth() {
+        assert_eq!(queue_drain_secs(MAX_PENDING_TXS, 16, 60), 64 * 60);
+        assert_eq!(queue_drain_secs(17, 16, 60), 120);
+        assert_eq!(queue_drain_secs(0, 16, 60), 1);
+
+        let resp = AppError::BatchFull(Some(queue_drain_secs(17, 16, 60))).into_response();
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(resp.headers()[header::RETRY_AFTER], "120");
+    }
+
+    #[test]
+    fn test_admin_keys_separate_from_api_keys() {
+        let api = ApiKeys::parse(r#"{"ops": {"scopes": ["force_prove"]}, "dash": {"scopes": ["read"]}}"#).unwrap();
+        let admin = ApiKeys::parse_admin("root").unwrap();
+        let check = |key: &str, admin_keys| authenticate_admin(&headers_with_key(key), &api, admin_keys);
+
+        // No admin set: admin endpoints are closed, even to force_prove keys
+        assert!(matches!(check("ops", None), Err(AppError::Forbidden)));
+        assert!(matches!(check("dash", None), Err(AppError::Forbidden)));
+        assert!(matches!(check("root", None), Err(AppError::Unauthorized)));
+
+        // Separate set: only admin keys, whatever the API key's scopes
+        assert!(check("root", Some(&admin)).is_ok());
+        assert!(matches!(check("ops", Some(&admin)), Err(AppError::Forbidden)));
+        assert!(matches!(check("nope", Some(&admin)), Err(AppError::Unauthorized)));
+        assert!(authenticate(&headers_with_key("root"), &api).is_err());
+
+        // Superset mode merges admin keys into the API keys
+        let mut merged = api.clone();
+        merged.merge(&admin);
+        assert!(authenticate(&headers_with_key("root"), &merged).is_ok());
+        assert!(authenticate_admin(&headers_with_key("root"), &merged, Some(&admin)).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_cancel_is_scoped_to_the_submitting_key() {
+        let (queue, _rx) = BatchQueue::new(8, 3600, 8);
+        let key = withdraw([7; 8], [8; 8]).idempotency_key();
+        queue
+            .push_keyed(deposit(100_000, 0), None, Some(key.clone()), Some(submitter_digest("key-a")), None)
+            .await;
+
+        // The key is derived from envelope fields anyone can see, so another
+        // client can present it, but not cancel with it
+        assert_eq!(queue.remove_by_key(&key, &submitter_digest("key-b")).await, Removal::NotOwner);
+        assert_eq!(queue.remove_by_key(&key, &submitter_digest("key-a")).await, Removal::Removed(0));
+    }
+
+    #[test]
+    fn test_quarantine_tracks_submitter_for_approval() {
+        let quarantine = Quarantine::new();
+        let id = quarantine
+            .hold(deposit(100_000, 0), "flagged-key", None, None, "flagged_api_key".into())
+            .unwrap();
+        assert_eq!(quarantine.submitted_by(&id, "flagged-key"), Some(true));
+        assert_eq!(quarantine.submitted_by(&id, "root"), Some(false));
+        assert_eq!(quarantine.submitted_by("missing", "root"), None);
+        // Checking the submitter leaves the submission held
+        assert_eq!(quarantine.take(&id).unwrap().submitter, "flagged-key");
+    }
+
+    #[tokio::test]
+    async fn test_per_key_quota_override() {
+        let keys = ApiKeys::parse(
+            r#"{"default": {"scopes": ["submit"]}, "partner": {"scopes": ["submit"], "rate_multiplier": 3}}"#,
+        )
+        .unwrap();
+        let store = InMemoryStore::new();
+        let base = 2;
+        let mut admitted = Vec::new();
+        for key in ["default", "partner"] {
+            let auth = authenticate(&headers_with_key(key), &keys).unwrap();
+            let mut n = 0;
+            while store
+                .check_rate(&format!("key:{}", auth.key), auth.rate_limit(base), 60)
+                .await
+                .unwrap()
+                .allowed
+            {
+                n += 1;
+            }
+            admitted.push(n);
+        }
+        assert_eq!(admitted, vec![2, 6]);
+    }
+
+    fn finalized_body(tx_hash: &str) -> serde_json::Value {
+        json!({ "id": "b1", "status": BatchStatus::Finalized, "tx_hash": tx_hash })
+    }
+
+    #[test]
+    fn test_batch_caching_terminal_etag_and_304() {
+        let resp = cached_batch_response(&HeaderMap::new(), &BatchStatus::Finalized, finalized_body("0x1"), 600);
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()[header::CACHE_CONTROL], "private, max-age=600, immutable");
+        let etag = resp.headers()[header::ETAG].to_str().unwrap().to_string();
+
+        let mut revalidate = HeaderMap::new();
+        revalidate.insert(header::IF_NONE_MATCH, format!("\"other\", W/{etag}").parse().unwrap());
+        let resp = cached_batch_response(&revalidate, &BatchStatus::Finalized, finalized_body("0x1"), 600);
+        assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(resp.headers()[header::ETAG], etag.as_str());
+
+        // Different content, different tag
+        let resp = cached_batch_response(&revalidate, &BatchStatus::Finalized, finalized_body("0x2"), 600);
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_ne!(resp.headers()[header::ETAG], etag.as_str());
+    }
+
+    #[test]
+    fn test_batch_caching_in_flight_or_disabled_is_no_cache() {
+        let mut any = HeaderMap::new();
+        any.insert(header::IF_NONE_MATCH, "*".parse().unwrap());
+        for (status, max_age) in [
+            (BatchStatus::Proving, 600),
+            (BatchStatus::Submitting, 600),
+            (BatchStatus::Failed, 0),
+        ] {
+            let resp = cached_batch_response(&any, &status, finalized_body("0x1"), max_age);
+            assert_eq!(resp.status(), StatusCode::OK, "{status:?}");
+            assert_eq!(resp.headers()[header::CACHE_CONTROL], "no-cache");
+            assert!(resp.headers().get(header::ETAG).is_none());
+        }
+    }
+
+    fn bad_request_message(req: &SubmitRequest) -> String {
+        match req.validate_and_convert(&Denominations::builtin()) {
+            Err(AppError::BadRequest(msg)) => msg,
+            Err(e) => panic!("expected BadRequest, got {e}"),
+            Ok(_) => panic!("expected BadRequest, got Ok"),
+        }
+    }
+
+    #[test]
+    fn test_m31_modulus_is_not_canonical() {
+        assert!(validate_m31(M31_MODULUS - 1, "x").is_ok());
+        match validate_m31(M31_MODULUS, "x") {
+            Err(AppError::BadRequest(msg)) => assert!(msg.contains("canonical"), "{msg}"),
+            other => panic!("expected BadRequest, got {other:?}"),
+        }
+        assert!(validate_m31(u32::MAX, "x").is_err());
+    }
+
+    #[test]
+    fn test_deposit_to_decoy_key_rejected() {
+        let deposit = |first_limb| SubmitRequest::Deposit {
+            amount: 100_000,
+            asset_id: 0,
+            recipient_pubkey: [first_limb, 2, 3, 4],
+            recipient_viewing_key: [5, 6, 7, 8],
+            blinding: None,
+            client_ref: None,
+        };
+        let range = (1_000, 2_000);
+        match deposit(1_500).validate_not_decoy_recipient(range) {
+            Err(AppError::BadRequest(msg)) => assert!(msg.contains("decoy"), "{msg}"),
+            other => panic!("expected BadRequest, got {other:?}"),
+        }
+        assert!(deposit(2_000).validate_not_decoy_recipient(range).is_err());
+        assert!(deposit(999).validate_not_decoy_recipient(range).is_ok());
+        assert!(deposit(2_001).validate_not_decoy_recipient(range).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_batch_events_stream_transitions_then_closes() {
+        use crate::store::{BatchRecord, StatusUpdate};
+
+        let store = Arc::new(InMemoryStore::new());
+        store.save_batch("b-1", &BatchRecord::new("b-1".into(), 2)).await.unwrap();
+        store.save_batch("b-2", &BatchRecord::new("b-2".into(), 1)).await.unwrap();
+
+        let permit = store.try_acquire_inflight("events:k", 1).unwrap();
+        assert!(store.try_acquire_inflight("events:k", 1).is_none(), "cap of one stream");
+
+        let connections = Arc::new(StreamConnections::default());
+        let events = store.subscribe_batch_events();
+        let (tx, mut rx) = tokio::sync::mpsc::channel(8);
+        let forward = tokio::spawn(forward_batch_events(
+            store.clone(),
+            "b-1".into(),
+            BatchStatus::Pending,
+            events,
+            tx,
+            Duration::from_secs(60),
+            (permit, connections.open()),
+        ));
+
+        for (id, status) in [
+            ("b-1", BatchStatus::Proving),
+            ("b-2", BatchStatus::Failed),
+            ("b-1", BatchStatus::Proving),
+            ("b-1", BatchStatus::Submitting),
+            ("b-1", BatchStatus::Finalized),
+        ] {
+            store.update_status(id, status, StatusUpdate::default()).await.unwrap();
+        }
+
+        let mut seen = Vec::new();
+        while let Some(status) = rx.recv().await {
+            seen.extend(status);
+        }
+        assert_eq!(
+            seen,
+            [BatchStatus::Pending, BatchStatus::Proving, BatchStatus::Submitting, BatchStatus::Finalized]
+        );
+        forward.await.unwrap();
+        assert!(store.try_acquire_inflight("events:k", 1).is_some(), "slot released on close");
+        assert_eq!(connections.count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_batch_events_stream_pings_then_reaps_idle_client() {
+        use crate::store::BatchRecord;
+
+        let store = Arc::new(InMemoryStore::new());
+        store.save_batch("b-1", &BatchRecord::new("b-1".into(), 1)).await.unwrap();
+        let connections = Arc::new(StreamConnections::default());
+        let idle = Duration::from_millis(300);
+        let stream = |tx| {
+            forward_batch_events(
+                store.clone(),
+                "b-1".into(),
+                BatchStatus::Pending,
+                store.subscribe_batch_events(),
+                tx,
+                idle,
+                (store.try_acquire_inflight("events:k", 2).unwrap(), connections.open()),
+            )
+        };
+
+        // A client that keeps reading gets keep-alive pings and stays open
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        let live = tokio::spawn(stream(tx));
+        assert_eq!(rx.recv().await, Some(Some(BatchStatus::Pending)));
+        assert_eq!(rx.recv().await, Some(None));
+        assert_eq!(rx.recv().await, Some(None));
+        assert!(!live.is_finished());
+
+        // One that stops reading is dropped, freeing its slots
+        let (tx, _stalled) = tokio::sync::mpsc::channel(1);
+        let stalled = tokio::spawn(stream(tx));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(connections.count(), 2);
+        tokio::time::timeout(Duration::from_secs(5), stalled).await.unwrap().unwrap();
+        assert_eq!(connections.count(), 1);
+
+        drop(rx);
+        tokio::time::timeout(Duration::from_secs(5), live).await.unwrap().unwrap();
+        assert_eq!(connections.count(), 0);
+        assert!(store.try_acquire_inflight("events:k", 2).is_some());
+    }
+
+    #[test]
+    fn test_denylisted_recipient_rejected() {
+        let deposit = |recipient_pubkey| SubmitRequest::Deposit {
+            amount: 100_000,
+            asset_id: 0,
+            recipient_pubkey,
+            recipient_viewing_key: [5, 6, 7, 8],
+            blinding: None,
+            client_ref: None,
+        };
+        let denylist = PubkeyDenylist::parse("00000001000000020000000300000004\n").unwrap();
+        match deposit([1, 2, 3, 4]).validate_recipient_permitted(&denylist) {
+            Err(AppError::BadRequest(msg)) => assert_eq!(msg, "recipient not permitted"),
+            other => panic!("expected BadRequest, got {other:?}"),
+        }
+        assert!(deposit([1, 2, 3, 5]).validate_recipient_permitted(&denylist).is_ok());
+        // Withdrawals have no recipient pubkey to screen
+        assert!(withdraw([7; 8], [7; 8]).validate_recipient_permitted(&denylist).is_ok());
+    }
+
+    #[test]
+    fn test_withdraw_rejects_zero_merkle_root() {
+        let msg = bad_request_message(&withdraw([0; 8], [7; 8]));
+        assert!(msg.contains("merkle_root must not be all zeros"), "{msg}");
+    }
+
+    #[test]
+    fn test_withdraw_rejects_zero_binding() {
+        let msg = bad_request_message(&withdraw([7; 8], [0; 8]));
+        assert!(msg.contains("withdrawal_binding must not be all zeros"), "{msg}");
+    }
+
+    #[test]
+    fn test_transfer_rejects_zero_merkle_root() {
+        let msg = bad_request_message(&transfer([0; 8]));
+        assert!(msg.contains("merkle_root must not be all zeros"), "{msg}");
+    }
+
+    #[test]
+    fn test_idempotency_key_is_pinned() {
+        // Pinned: changing the canonical encoding breaks dedup across deploys.
+        let req = SubmitRequest::Deposit {
+            amount: 100_000,
+            asset_id: 0,
+            recipient_pubkey: [1, 2, 3, 4],
+            recipient_viewing_key: [5, 6, 7, 8],
+            blinding: None,
+            client_ref: None,
+        };
+        assert_eq!(
+            req.idempotency_key(),
+            "55ce1cc4f6b1d54196ea1e8076049a43752f2228d124d9f3844cd71a5d58c2d8"
+        );
+
+        // client_ref never affects the key
+        let with_ref = SubmitRequest::Deposit {
+            amount: 100_000,
+            asset_id: 0,
+            recipient_pubkey: [1, 2, 3, 4],
+            recipient_viewing_key: [5, 6, 7, 8],
+            blinding: None,
+            client_ref: Some("order-42".into()),
+        };
+        assert_eq!(with_ref.idempotency_key(), req.idempotency_key());
+    }
+
+    #[test]
+    fn test_deposit_blinding_client_supplied_or_generated() {
+        let deposit = |blinding| SubmitRequest::Deposit {
+            amount: 100_000,
+            asset_id: 0,
+            recipient_pubkey: [1, 2, 3, 4],
+            recipient_viewing_key: [5, 6, 7, 8],
+            blinding,
+            client_ref: None,
+        };
+        let convert = |req: &SubmitRequest| req.validate_and_convert(&Denominations::builtin());
+
+        // Client-supplied: validated as M31 and carried into the tx as given
+        let tx = convert(&deposit(Some([11, 12, 13, 14]))).unwrap();
+        assert_eq!(deposit_blinding(&tx), Some([11, 12, 13, 14]));
+        let msg = bad_request_message(&deposit(Some([1, M31_MODULUS, 3, 4])));
+        assert!(msg.contains("blinding: value 2147483647 is not a canonical M31 element"), "{msg}");
+        // It's part of the note, so it's part of the key
+        assert_ne!(deposit(Some([11, 12, 13, 14])).idempotency_key(), deposit(None).idempotency_key());
+
+        // Server-generated: canonical, fresh per submission, and returned
+        let generated = deposit_blinding(&convert(&deposit(None)).unwrap()).unwrap();
+        assert!(generated.iter().all(|&limb| limb < M31_MODULUS));
+        assert_ne!(deposit_blinding(&convert(&deposit(None)).unwrap()), Some(generated));
+        assert_eq!(deposit_blinding(&convert(&withdraw([7; 8], [8; 8])).unwrap()), None);
+
+        // The wire form (queue WAL) keeps the blinding the tx was built with
+        let tx = convert(&deposit(None)).unwrap();
+        let wire = SubmitRequest::from(&tx);
+        assert_eq!(deposit_blinding(&convert(&wire).unwrap()), deposit_blinding(&tx));
+    }
+
+    #[tokio::test]
+    async fn test_client_idempotency_key_dedupes_reserialized_retries() {
+        let store = InMemoryStore::new();
+        let mut headers = HeaderMap::new();
+        headers.insert(IDEMPOTENCY_KEY_HEADER, "order-42:attempt".parse().unwrap());
+
+        // The same deposit sent twice with fields reordered, as a plaintext
+        // body and as two differently encrypted envelopes
+        let first: SubmitRequest = serde_json::from_str(
+            r#"{"type":"deposit","amount":100000,"asset_id":0,"recipient_pubkey":[1,2,3,4],"recipient_viewing_key":[5,6,7,8]}"#,
+        )
+        .unwrap();
+        let retry: SubmitRequest = serde_json::from_str(
+            r#"{"recipient_viewing_key":[5,6,7,8],"recipient_pubkey":[1,2,3,4],"asset_id":0,"amount":100000,"type":"deposit"}"#,
+        )
+        .unwrap();
+        let envelope = |nonce: &str| EncryptedSubmitRequest {
+            ephemeral_pubkey: "11".repeat(32),
+            ciphertext: format!("ciphertext-{nonce}"),
+            nonce: nonce.repeat(12),
+            version: 1,
+        };
+
+        let client_key = client_idempotency_key(&headers).unwrap();
+        assert_eq!(client_key, Some("order-42:attempt"));
+        let keys = [
+            submission_idempotency_key("key-a", client_key, first.idempotency_key()),
+            submission_idempotency_key("key-a", client_key, retry.idempotency_key()),
+            submission_idempotency_key("key-a", client_key, envelope("aa").idempotency_key()),
+            submission_idempotency_key("key-a", client_key, envelope("bb").idempotency_key()),
+        ];
+        assert!(keys.iter().all(|k| k == &keys[0]), "{keys:?}");
+        assert!(store.check_and_set(&keys[0], "pending").await.unwrap().is_none());
+        assert_eq!(store.check_and_set(&keys[3], "pending").await.unwrap().as_deref(), Some("pending"));
+
+        // Scoped to the API key: another client's equal header doesn't collide
+        assert_ne!(submission_idempotency_key("key-b", client_key, first.idempotency_key()), keys[0]);
+
+        // Without the header the payload hash is used, as before
+        assert_eq!(client_idempotency_key(&HeaderMap::new()).unwrap(), None);
+        assert_eq!(submission_idempotency_key("key-a", None, first.idempotency_key()), first.idempotency_key());
+        assert_ne!(
+            submission_idempotency_key("key-a", None, envelope("aa").idempotency_key()),
+            submission_idempotency_key("key-a", None, envelope("bb").idempotency_key())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rejected_submission_can_be_retried() {
+        let store = InMemoryStore::new();
+        let key = withdraw([7; 8], [8; 8]).idempotency_key();
+
+        // A 429 after the claim releases the key...
+        assert!(store.check_and_set(&key, "pending").await.unwrap().is_none());
+        let rejected: Result<(), _> =
+            release_if_rejected(&store, &key, Err(AppError::RateLimited(Some(5)))).await;
+        assert!(matches!(rejected, Err(AppError::RateLimited(Some(5)))));
+
+        // ...so the retry is processed and accepted, not answered as a duplicate
+        assert!(store.check_and_set(&key, "pending").await.unwrap().is_none());
+        release_if_rejected(&store, &key, Ok(())).await.unwrap();
+        assert_eq!(store.check_and_set(&key, "pending").await.unwrap().as_deref(), Some("pending"));
+    }
+
+    #[test]
+    fn test_client_idempotency_key_format() {
+        let with = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(IDEMPOTENCY_KEY_HEADER, value.parse().unwrap());
+            client_idempotency_key(&headers).map(|k| k.map(str::to_owned))
+        };
+        assert!(with(&"a".repeat(MAX_IDEMPOTENCY_KEY_LEN)).is_ok());
+        assert!(with(&"a".repeat(MAX_IDEMPOTENCY_KEY_LEN + 1)).is_err());
+        assert!(with("").is_err());
+        assert!(with("has space").is_err());
+        assert!(with("semi;colon").is_err());
+    }
+
+    #[test]
+    fn test_idempotency_key_distinguishes_binding_salt() {
+        let mut a = withdraw([7; 8], [7; 8]);
+        let b = withdraw([7; 8], [7; 8]);
+        if let SubmitRequest::Withdraw { binding_salt, .. } = &mut a {
+            *binding_salt = Some([0; 8]);
+        }
+        assert_ne!(a.idempotency_key(), b.idempotency_key());
+    }
+
+    #[test]
+    fn test_transfer_rejects_mismatched_input_path_depths() {
+        // Both inputs are proven against the one transfer merkle_root, so
+        // each path is held to the tree's depth rather than to the other's
+        let mut req = transfer([7; 8]);
+        if let SubmitRequest::Transfer { input_notes, .. } = &mut req {
+            input_notes[0].merkle_path.siblings = vec![[1; 8]; 20];
+            input_notes[1].merkle_path.siblings = vec![[1; 8]; 3];
+        }
+        let Err(AppError::BadRequest(msg)) = req.validate_path_depths(20) else {
+            panic!("short input path accepted");
+        };
+        assert!(msg.starts_with("input_notes[1].merkle_path has 3 siblings"), "{msg}");
+
+        // Two equally wrong depths are still wrong
+        if let SubmitRequest::Transfer { input_notes, .. } = &mut req {
+            input_notes[0].merkle_path.siblings = vec![[1; 8]; 3];
+        }
+        let Err(AppError::BadRequest(msg)) = req.validate_path_depths(20) else {
+            panic!("matching but short input paths accepted");
+        };
+        assert!(msg.starts_with("input_notes[0]"), "{msg}");
+    }
+
+    #[test]
+    fn test_transfer_input_paths_must_lead_to_merkle_root() {
+        use stwo_ml::crypto::merkle_m31::PoseidonMerkleTreeM31;
+
+        // Both input notes in a depth-4 tree, with their real paths
+        let notes = [note_json(), NoteJson { blinding: [9, 9, 9, 9], ..note_json() }];
+        let mut tree = PoseidonMerkleTreeM31::new(4);
+        for n in &notes {
+            tree.append(validate_note(n).unwrap().commitment());
+        }
+        let root = m31s(&tree.root());
+        let mut req = transfer(root);
+        if let SubmitRequest::Transfer { input_notes, .. } = &mut req {
+            for (i, (input, note)) in input_notes.iter_mut().zip(notes).enumerate() {
+                input.note = note;
+                input.merkle_path = MerklePathJson::from(&tree.prove(i).unwrap());
+            }
+        }
+        let tx = req.validate_and_convert(&Denominations::builtin()).unwrap();
+        req.validate_path_depths(4).unwrap();
+        check_input_roots(&tx).unwrap();
+
+        // The right depth, but one sibling off by a limb
+        let SubmitRequest::Transfer { input_notes, .. } = &mut req else { unreachable!() };
+        let sibling = input_notes[1].merkle_path.siblings[2];
+        input_notes[1].merkle_path.siblings[2][0] = (sibling[0] + 1) % M31_MODULUS;
+        req.validate_path_depths(4).unwrap();
+        let tx = req.validate_and_convert(&Denominations::builtin()).unwrap();
+        let Err(AppError::BadRequest(msg)) = check_input_roots(&tx) else {
+            panic!("path with a wrong sibling accepted");
+        };
+        assert!(msg.starts_with("input_notes[1].merkle_path does not lead"), "{msg}");
+
+        // Valid paths, but for another root
+        if let SubmitRequest::Transfer { input_notes, merkle_root, .. } = &mut req {
+            input_notes[1].merkle_path.siblings[2] = sibling;
+            *merkle_root = [7; 8];
+        }
+        let tx = req.validate_and_convert(&Denominations::builtin()).unwrap();
+        assert!(check_input_roots(&tx).unwrap_err().to_string().contains("input_notes[0]"));
+    }
+
+    #[test]
+    fn test_merkle_path_must_match_tree_depth() {
+        let with_path = |len: usize, index: usize| {
+            let mut req = withdraw([7; 8], [7; 8]);
+            if let SubmitRequest::Withdraw { merkle_path, .. } = &mut req {
+                *merkle_path = MerklePathJson { siblings: vec![[1; 8]; len], index };
+            }
+            req
+        };
+        assert!(with_path(20, 0).validate_path_depths(20).is_ok());
+        assert!(with_path(20, (1 << 20) - 1).validate_path_depths(20).is_ok());
+        assert!(with_path(32, u32::MAX as usize).validate_path_depths(32).is_ok());
+
+        // Under- and over-length paths
+        for len in [19, 21] {
+            let Err(AppError::BadRequest(msg)) = with_path(len, 0).validate_path_depths(20) else {
+                panic!("{len} siblings accepted at depth 20");
+            };
+            assert!(msg.contains("expected 20"), "{msg}");
+        }
+
+        // Leaf index beyond the tree's 2^depth leaves
+        let Err(AppError::BadRequest(msg)) = with_path(20, 1 << 20).validate_path_depths(20) else {
+            panic!("out-of-range index accepted");
+        };
+        assert!(msg.contains("out of range"), "{msg}");
+
+        // Each transfer input is checked
+        let mut req = transfer([7; 8]);
+        if let SubmitRequest::Transfer { input_notes, .. } = &mut req {
+            input_notes[1].merkle_path.index = 2;
+        }
+        let Err(AppError::BadRequest(msg)) = req.validate_path_depths(1) else {
+            panic!("out-of-range input index accepted");
+        };
+        assert!(msg.starts_with("input_notes[1]"), "{msg}");
+    }
+
+    #[test]
+    fn test_out_of_field_asset_id_rejected() {
+        let mut deposit = SubmitRequest::Deposit {
+            amount: 100_000,
+            asset_id: 0,
+            recipient_pubkey: [1; 4],
+            recipient_viewing_key: [2; 4],
+            blinding: None,
+            client_ref: None,
+        };
+        let mut withdraw = withdraw([7; 8], [7; 8]);
+        let mut transfer = transfer([7; 8]);
+        for req in [&mut deposit, &mut withdraw, &mut transfer] {
+            match req {
+                SubmitRequest::Deposit { asset_id, .. }
+                | SubmitRequest::Withdraw { asset_id, .. }
+                | SubmitRequest::Transfer { asset_id, .. } => *asset_id = M31_MODULUS,
+            }
+            let msg = bad_request_message(req);
+            assert!(msg.starts_with("asset_id:") && msg.contains("canonical"), "{msg}");
+        }
+    }
+
+    #[test]
+    fn test_allowed_assets_cover_tx_and_notes() {
+        let tx = transfer([7; 8]).validate_and_convert(&Denominations::builtin()).unwrap();
+        assert!(check_allowed_assets(&tx, &[]).is_ok());
+        assert!(check_allowed_assets(&tx, &[0]).is_ok());
+        assert!(check_allowed_assets(&tx, &[1]).is_err());
+
+        // Tx asset allowed, but an input note carries another asset
+        let mut req = withdraw([7; 8], [7; 8]);
+        if let SubmitRequest::Withdraw { note, .. } = &mut req {
+            note.asset_id = 2;
+        }
+        let tx = req.validate_and_convert(&Denominations::builtin()).unwrap();
+        match check_allowed_assets(&tx, &[0]) {
+            Err(AppError::BadRequest(msg)) => assert!(msg.starts_with("note.asset_id:"), "{msg}"),
+            other => panic!("expected BadRequest, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_nonzero_root_and_binding_accepted() {
+        assert!(withdraw([7; 8], [7; 8]).validate_and_convert(&Denominations::builtin()).is_ok());
+        assert!(transfer([7; 8]).validate_and_convert(&Denominations::builtin()).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_merkle_paths_mixes_resolved_pending_and_unindexed() {
+        use stwo_ml::privacy::pool_client::PoolClientConfig;
+
+        let store = Arc::new(InMemoryStore::new());
+        let note = |commitment: &str, merkle_root: [u32; 8]| NoteRecord {
+            commitment: commitment.into(),
+            merkle_path: MerklePathRecord { siblings: vec![[3; 8]], index: 5 },
+            merkle_root,
+            batch_id: "batch-1".into(),
+            created_at: crate::store::now_epoch(),
+            commitment_digest: None,
+            note_index_in_batch: 0,
+            client_ref: None,
+            backfill_attempts: 0,
+            last_backfill_attempt: 0,
+        };
+        store.save_note("aa01", &note("aa01", [9; 8])).await.unwrap();
+        store.save_note("bb02", &note("bb02", [0; 8])).await.unwrap();
+
+        let cache = std::env::temp_dir().join(format!("vm31-merkle-paths-{}.json", std::process::id()));
+        let tree_sync = TreeSyncService::new(
+            PoolClientConfig {
+                rpc_url: "http://127.0.0.1:1".into(),
+                pool_address: "0x1".into(),
+                network: "sepolia".into(),
+                verify_rpc_urls: vec![],
+            },
+            store.clone(),
+            Some(cache.to_string_lossy().into_owned()),
+            15,
+            (15, 3600),
+        )
+        .unwrap();
+        let lookup = PathLookup {
+            store: &store,
+            tree_sync: Some(&tree_sync),
+            stuck_note_threshold_secs: 3600,
+            max_proof_staleness_secs: 0,
+            verifiable: false,
+        };
+
+        let commitments: Vec<String> = ["aa01", "bb02", "cc03"].map(String::from).to_vec();
+        let results = merkle_paths(&lookup, &commitments).await.unwrap();
+        assert_eq!(results.len(), 3);
+        // Store-backed with a real root
+        assert_eq!(results[0]["merkle_root"], json!([9; 8]));
+        assert_eq!(results[0]["merkle_path"]["index"], 5);
+        // Stored but not in the tree yet
+        assert_eq!(results[1]["status"], "pending_sync");
+        assert_eq!(results[1]["batch_id"], "batch-1");
+        // Unknown to both: pending instead of failing the call
+        assert_eq!(results[2]["commitment"], "cc03");
+        assert_eq!(results[2]["status"], "pending_sync");
+        assert!(results[2]["merkle_path"].is_null());
+
+        // The single lookup keeps its 404 for unindexed commitments
+        let single = lookup.resolve(&commitments[2..]).await.unwrap();
+        assert!(matches!(single.as_slice(), [Err(AppError::NotFound(_))]));
+        let _ = std::fs::remove_file(cache);
+    }
+
+    #[test]
+    fn test_verifiable_bundle_carries_path_and_hash_params() {
+        let siblings = vec![[1; 8], [2; 8], [3; 8]];
+        let bundle = verifiable_bundle(Some([4; 8]), &siblings, 5, [6; 8]);
+        assert_eq!(bundle["leaf"], json!([4; 8]));
+        assert_eq!(bundle["siblings"], json!(siblings));
+        assert_eq!(bundle["index"], 5);
+        assert_eq!(bundle["root"], json!([6; 8]));
+        assert_eq!(bundle["depth"], 3);
+        assert_eq!(bundle["hash"]["algorithm"], "poseidon2-m31");
+        assert_eq!(bundle["hash"]["field_modulus"], M31_MODULUS);
+        assert_eq!(bundle["hash"]["digest_limbs"], 8);
+        assert_eq!(bundle["hash"]["index_bit_order"], "lsb_first");
+        // Changing any of these changes every root; clients must be told
+        assert_eq!(
+            bundle["hash"]["permutation"],
+            json!({
+                "width": 16,
+                "rate": 8,
+                "full_rounds": 8,
+                "partial_rounds": 14,
+                "sbox_degree": 5,
+                "round_constants": "stwo-ml crypto::poseidon2_m31 (EXTERNAL_ROUND_CONSTS, INTERNAL_ROUND_CONSTS)",
+            })
+        );
+
+        // No digest on record: the client supplies its own leaf
+        assert!(verifiable_bundle(None, &siblings, 5, [6; 8])["leaf"].is_null());
+    }
+
+    #[tokio::test]
+    async fn test_merkle_path_includes_bundle_only_when_requested() {
+        let store = Arc::new(InMemoryStore::new());
+        let note = |commitment: &str, commitment_digest: Option<[u32; 8]>| NoteRecord {
+            commitment: commitment.into(),
+            merkle_path: MerklePathRecord { siblings: vec![[3; 8], [4; 8]], index: 2 },
+            merkle_root: [9; 8],
+            batch_id: "batch-1".into(),
+            created_at: crate::store::now_epoch(),
+            commitment_digest,
+            note_index_in_batch: 0,
+            client_ref: None,
+            backfill_attempts: 0,
+            last_backfill_attempt: 0,
+        };
+        store.save_note("aa01", &note("aa01", Some([7; 8]))).await.unwrap();
+        store.save_note("bb02", &note("bb02", None)).await.unwrap();
+        let lookup = |verifiable| PathLookup {
+            store: &store,
+            tree_sync: None,
+            stuck_note_threshold_secs: 3600,
+            max_proof_staleness_secs: 0,
+            verifiable,
+        };
+        let commitments: Vec<String> = ["aa01", "bb02"].map(String::from).to_vec();
+
+        let plain = merkle_paths(&lookup(false), &commitments).await.unwrap();
+        assert!(plain.iter().all(|body| body.get("verifiable").is_none()));
+
+        let results = merkle_paths(&lookup(true), &commitments).await.unwrap();
+        let bundle = &results[0]["verifiable"];
+        assert_eq!(bundle["leaf"], json!([7; 8]));
+        assert_eq!(bundle["siblings"], results[0]["merkle_path"]["siblings"]);
+        assert_eq!(bundle["index"], 2);
+        assert_eq!(bundle["root"], results[0]["merkle_root"]);
+        assert_eq!(bundle["depth"], 2);
+        // The rest of the response is unchanged
+        assert_eq!(results[0]["batch_id"], plain[0]["batch_id"]);
+        assert!(results[1]["verifiable"]["leaf"].is_null());
+        assert_eq!(results[1]["verifiable"]["root"], json!([9; 8]));
+    }
+
+    #[tokio::test]
+    async fn test_readiness_degrades_when_prover_channel_closes() {
+        let (queue, rx) = BatchQueue::new(2, 3600, 8);
+        let (code, _) = readiness_report(queue.prover_connected(), true, Ok(()), vec![]);
+        assert_eq!(code, StatusCode::OK);
+
+        drop(rx);
+        let (code, Json(body)) = readiness_report(queue.prover_connected(), true, Ok(()), vec![]);
+        assert_eq!(code, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["status"], "degraded");
+        assert_eq!(body["checks"]["prover_channel"], "closed");
+        assert_eq!(body["checks"]["store"], "ok");
+
+        // Liveness ignores dependencies
+        assert_eq!(health().await.into_response().status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn test_readiness_reports_each_degraded_subsystem() {
+        let (code, Json(body)) = readiness_report(true, false, Err("connection refused".into()), vec![]);
+        assert_eq!(code, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["checks"]["prover_channel"], "ok");
+        assert_eq!(body["checks"]["tree_sync"], "unavailable");
+        assert_eq!(body["checks"]["store"], "unreachable: connection refused");
+        assert_eq!(body["checks"]["rpc_endpoints"], "ok");
+    }
+
+    #[test]
+    fn test_submit_timing_padding_buckets() {
+        use std::time::Duration;
+        let target = Duration::from_millis(25);
+        assert_eq!(padded_duration(Duration::from_millis(3), target), target);
+        assert_eq!(padded_duration(Duration::from_millis(25), target), target);
+        assert_eq!(padded_duration(Duration::from_millis(26), target), target * 2);
+        let elapsed = Duration::from_millis(7);
+        assert_eq!(padded_duration(elapsed, Duration::ZERO), elapsed);
+    }
+
+    const TEST_RELAYER_KEY: [u8; 32] = [7; 32];
+
+    /// A config from a minimal environment, read once: the environment is
+    /// process-wide and tests run in parallel.
+    fn test_config() -> RelayerConfig {
+        static CONFIG: std::sync::OnceLock<RelayerConfig> = std::sync::OnceLock::new();
+        CONFIG
+            .get_or_init(|| {
+                for (name, value) in [
+                    ("STARKNET_RPC_URL", "https://rpc.example"),
+                    ("STARKNET_ACCOUNT", "relayer"),
+                    ("VM31_VERIFIER_CONTRACT", "0x1"),
+                    ("VM31_POOL_CONTRACT", "0x2"),
+                    ("VM31_BRIDGE_CONTRACT", "0x3"),
+                    ("VM31_CT_CONTRACT", "0x4"),
+                    ("VM31_API_KEYS", "k1"),
+                ] {
+                    std::env::set_var(name, value);
+                }
+                std::env::set_var("VM31_RELAYER_PRIVKEY", hex::encode(TEST_RELAYER_KEY));
+                RelayerConfig::from_env().unwrap()
+            })
+            .clone()
+    }
+
+    fn submit_state(store: Arc<InMemoryStore>, config: RelayerConfig) -> AppState {
+        use stwo_ml::privacy::pool_client::PoolClientConfig;
+        use stwo_ml::privacy::relayer::SncastVm31Backend;
+
+        let pool_config = PoolClientConfig {
+            rpc_url: "http://127.0.0.1:1".into(),
+            pool_address: "0x2".into(),
+            network: "sepolia".into(),
+            verify_rpc_urls: vec![],
+        };
+        let input_checks = crate::prover::ProverService::new(
+            SncastVm31Backend::new("test", "http://127.0.0.1:1", "0x1", "0x2"),
+            pool_config,
+            store.clone(),
+            1,
+            crate::bridge::BridgeService::new("test".into(), "http://127.0.0.1:1".into(), "0x3".into()),
+        )
+        .input_checks();
+        AppState {
+            queue: Arc::new(BatchQueue::new(16, 60, 4).0),
+            store,
+            config,
+            tree_sync: None,
+            quarantine: Quarantine::new(),
+            latency: None,
+            prover_pause: Arc::new(ProverPause::new()),
+            shutdown: Arc::new(Shutdown::new()),
+            abuse: None,
+            metrics: None,
+            assets: None,
+            rpc_pool: None,
+            rpc_breaker: None,
+            input_checks,
+            pubkey_denylist: None,
+            stream_connections: Arc::new(StreamConnections::default()),
+        }
+    }
+
+    #[test]
+    fn test_check_submission_codes_hide_recipient_policies() {
+        let deposit: SubmitRequest = serde_json::from_str(
+            r#"{"type":"deposit","amount":100000,"asset_id":0,"recipient_pubkey":[1,2,3,4],"recipient_viewing_key":[5,6,7,8]}"#,
+        )
+        .unwrap();
+        let check = |state: &AppState| check_submission(state, &deposit).map(|_| ()).map_err(|(c, _)| c);
+        let store = Arc::new(InMemoryStore::new());
+        assert_eq!(check(&submit_state(store.clone(), test_config())), Ok(()));
+
+        let config = RelayerConfig { allowed_asset_ids: vec![5], ..test_config() };
+        assert_eq!(check(&submit_state(store.clone(), config)), Err(CheckFailure::AssetNotAllowed));
+
+        // Decoy-range and denylisted recipients look like any other bad request
+        let config = RelayerConfig { decoy_pubkey_range: Some((1, 1)), ..test_config() };
+        assert_eq!(check(&submit_state(store.clone(), config)), Err(CheckFailure::InvalidRequest));
+        let mut state = submit_state(store, test_config());
+        let denylist = PubkeyDenylist::parse(&pubkey_hex(&[1, 2, 3, 4])).unwrap();
+        state.pubkey_denylist = Some(Arc::new(SharedDenylist::new("unused".into(), denylist)));
+        assert_eq!(check(&state), Err(CheckFailure::InvalidRequest));
+        assert_eq!(
+            CheckFailure::InvalidRequest.body(),
+            json!({ "error": "invalid request", "code": "INVALID_REQUEST" })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rejected_envelope_leaves_no_trace_and_can_be_retried() {
+        let store = Arc::new(InMemoryStore::new());
+        let mut config = test_config();
+        config.denominations = Denominations::unrestricted();
+        let cap = |daily_cap| HashMap::from([(0, DepositLimit { max_amount: None, daily_cap: Some(daily_cap) })]);
+        let capped = submit_state(store.clone(), RelayerConfig { deposit_limits: cap(150_000), ..config.clone() });
+
+        let public = X25519PublicKey::from(&StaticSecret::from(TEST_RELAYER_KEY));
+        let deposit = br#"{"type":"deposit","amount":100000,"asset_id":0,"recipient_pubkey":[1,2,3,4],"recipient_viewing_key":[5,6,7,8]}"#;
+        let mut headers = HeaderMap::new();
+        headers.insert("x-api-key", "k1".parse().unwrap());
+        let addr: SocketAddr = "127.0.0.1:9000".parse().unwrap();
+        let body = |enc: &EncryptedSubmitRequest| {
+            SubmitBody::Encrypted(serde_json::from_value(serde_json::to_value(enc).unwrap()).unwrap())
+        };
+
+        let first = crate::ecies::tests::seal(&public, 1, deposit);
+        let (status, _) = submit_inner(&capped, addr, &headers, body(&first)).await.unwrap();
+        assert_eq!(status, StatusCode::ACCEPTED);
+
+        // Over the daily cap: the envelope's nonce and idempotency key are
+        // recorded before the cap check, and must not outlive the rejection
+        let second = crate::ecies::tests::seal(&public, 1, deposit);
+        assert!(matches!(
+            submit_inner(&capped, addr, &headers, body(&second)).await,
+            Err(AppError::RateLimited(_))
+        ));
+
+        // Once the cap allows it, the same envelope goes through as new
+        let raised = submit_state(store.clone(), RelayerConfig { deposit_limits: cap(300_000), ..config });
+        let (status, Json(resp)) = submit_inner(&raised, addr, &headers, body(&second)).await.unwrap();
+        assert_eq!(status, StatusCode::ACCEPTED);
+        assert_eq!(resp["status"], "queued");
+        assert_eq!(raised.queue.pending_count().await, 2);
+        // Its deposit was counted once, by the accepted attempt
+        let day = utc_day(now_epoch());
+        assert!(store.check_and_add_volume("deposit:k1:0", day, 100_000, 300_000).await.unwrap());
+        assert!(!store.check_and_add_volume("deposit:k1:0", day, 1, 300_000).await.unwrap());
+
+        // An actual replay of the accepted envelope is still caught
+        let (_, Json(resp)) = submit_inner(&raised, addr, &headers, body(&second)).await.unwrap();
+        assert_eq!(resp["status"], "duplicate");
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_deposit_returns_the_drawn_blinding() {
+        let mut config = test_config();
+        config.denominations = Denominations::unrestricted();
+        config.legacy_plaintext_allowed = true;
+        let mut headers = HeaderMap::new();
+        headers.insert("x-api-key", "k1".parse().unwrap());
+        let addr: SocketAddr = "127.0.0.1:9000".parse().unwrap();
+        let deposit = |blinding| {
+            SubmitBody::Plaintext(SubmitRequest::Deposit {
+                amount: 100_000,
+                asset_id: 0,
+                recipient_pubkey: [1, 2, 3, 4],
+                recipient_viewing_key: [5, 6, 7, 8],
+                blinding,
+                client_ref: None,
+            })
+        };
+
+        for storage_key in [None, Some([3u8; 32])] {
+            let store = Arc::new(InMemoryStore::with_encryption(storage_key.as_ref()));
+            let state = submit_state(store.clone(), config.clone());
+
+            let (_, Json(first)) = submit_inner(&state, addr, &headers, deposit(None)).await.unwrap();
+            assert_eq!(first["status"], "queued");
+            let drawn = first["blinding"].clone();
+            assert!(drawn.is_array());
+            // Kept sealed when a storage key is set
+            let idem_key = first["idempotency_key"].as_str().unwrap();
+            let kept = store.get_result(&drawn_blinding_key(idem_key)).await.unwrap().unwrap();
+            assert_eq!(serde_json::from_str::<serde_json::Value>(&kept).is_ok(), storage_key.is_none());
+
+            // The retry can't draw again, so it returns the first draw
+            let (status, Json(retry)) = submit_inner(&state, addr, &headers, deposit(None)).await.unwrap();
+            assert_eq!(status, StatusCode::OK);
+            assert_eq!(retry["status"], "duplicate");
+            assert_eq!(retry["blinding"], drawn);
+
+            // A client's own blinding isn't kept
+            let (_, Json(own)) = submit_inner(&state, addr, &headers, deposit(Some([9; 4]))).await.unwrap();
+            let idem_key = own["idempotency_key"].as_str().unwrap();
+            assert!(store.get_result(&drawn_blinding_key(idem_key)).await.unwrap().is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_admission_undo_forgets_everything_it_recorded() {
+        let store = InMemoryStore::new();
+        let now = now_epoch();
+        let w = withdraw([7; 8], [9; 8]);
+        let binding = withdrawal_binding_key(&w).unwrap();
+        assert!(store.check_and_record_nonce("pair", 3600, 16).await.unwrap());
+        let taken = Admission {
+            nonce_key: Some("pair".into()),
+            reserved_binding: reserve_withdrawal_binding(&store, &w, "idem-1").await.unwrap(),
+            recent_binding: check_binding_replay(&store, &w, 300, now).unwrap(),
+            deposit_volume: Some(("deposit:k:0".into(), utc_day(now), 60)),
+        };
+        assert!(store.check_and_add_volume("deposit:k:0", utc_day(now), 60, 100).await.unwrap());
+        assert_eq!(taken.reserved_binding.as_deref(), Some(binding.as_str()));
+
+        taken.undo(&store).await;
+        assert!(store.check_and_record_nonce("pair", 3600, 16).await.unwrap());
+        assert!(reserve_withdrawal_binding(&store, &w, "idem-2").await.is_ok());
+        assert!(check_binding_replay(&store, &w, 300, now).is_ok());
+        assert!(store.check_and_add_volume("deposit:k:0", utc_day(now), 100, 100).await.unwrap());
+    }
 }
@@ -0,0 +1,373 @@
+//! Weighted selection across RPC endpoints for read-only pool calls.
+//!
+//! Validation and tree-sync calls are spread over the configured endpoints
+//! in proportion to their weights (smooth round-robin, so a 3:1 split
+//! interleaves rather than bursting). Each endpoint keeps a window of recent
+//! call outcomes; one whose error rate crosses `UNHEALTHY_ERROR_RATE` is
+//! skipped for `COOLDOWN` and then retried with a clean window. If every
+//! endpoint is unhealthy, selection falls back to all of them rather than
+//! failing outright.
+//!
+//! Submission (sncast) still uses its own URL; this only picks the endpoint
+//! for `PoolClient` reads. When that endpoint fails, `with_failover` retries
+//! the call against the backup `verify_rpc_urls` in order.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use stwo_ml::privacy::pool_client::PoolClientConfig;
+
+/// Outcomes remembered per endpoint.
+const WINDOW: usize = 20;
+/// Outcomes needed before an endpoint can be marked unhealthy.
+const MIN_SAMPLES: usize = 5;
+const UNHEALTHY_ERROR_RATE: f64 = 0.5;
+/// How long an unhealthy endpoint is skipped.
+const COOLDOWN: Duration = Duration::from_secs(30);
+
+struct Health {
+    /// Recent outcomes, `true` = error. Newest last.
+    window: VecDeque<bool>,
+    unhealthy_until: Option<Instant>,
+    /// Smooth weighted round-robin state.
+    current_weight: i64,
+    calls: u64,
+    errors: u64,
+}
+
+struct Endpoint {
+    url: String,
+    weight: u32,
+    health: Mutex<Health>,
+}
+
+/// Per-endpoint health, as reported by `/ready`.
+#[derive(Debug, Serialize)]
+pub struct EndpointStatus {
+    /// Scheme and host only; RPC URLs often embed API keys.
+    pub endpoint: String,
+    pub weight: u32,
+    pub healthy: bool,
+    /// Error rate over the recent window.
+    pub error_rate: f64,
+    pub calls: u64,
+    pub errors: u64,
+}
+
+pub struct RpcPool {
+    endpoints: Vec<Endpoint>,
+}
+
+impl RpcPool {
+    /// Builds a pool from `(url, weight)` pairs. The primary URL is added
+    /// with weight 1 unless it is already listed; zero weights are skipped.
+    pub fn new(primary: &str, weighted: &[(String, u32)]) -> Self {
+        let mut entries: Vec<(String, u32)> = Vec::with_capacity(weighted.len() + 1);
+        if !weighted.iter().any(|(url, _)| url == primary) {
+            entries.push((primary.to_string(), 1));
+        }
+        entries.extend(weighted.iter().filter(|(_, w)| *w > 0).cloned());
+        let endpoints = entries
+            .into_iter()
+            .map(|(url, weight)| Endpoint {
+                url,
+                weight,
+                health: Mutex::new(Health {
+                    window: VecDeque::with_capacity(WINDOW),
+                    unhealthy_until: None,
+                    current_weight: 0,
+                    calls: 0,
+                    errors: 0,
+                }),
+            })
+            .collect();
+        Self { endpoints }
+    }
+
+    /// Picks the endpoint for the next call and returns its index.
+    pub fn pick(&self) -> usize {
+        self.pick_at(Instant::now())
+    }
+
+    fn pick_at(&self, now: Instant) -> usize {
+        let mut guards: Vec<_> = self
+            .endpoints
+            .iter()
+            .map(|e| e.health.lock().unwrap_or_else(|p| p.into_inner()))
+            .collect();
+        for h in guards.iter_mut() {
+            if h.unhealthy_until.is_some_and(|until| now >= until) {
+                // Cooldown over: retry with a clean window
+                h.unhealthy_until = None;
+                h.window.clear();
+            }
+        }
+        let any_healthy = guards.iter().any(|h| h.unhealthy_until.is_none());
+        let eligible = |h: &Health| !any_healthy || h.unhealthy_until.is_none();
+
+        let mut total = 0i64;
+        let mut best: Option<(usize, i64)> = None;
+        for (i, (endpoint, h)) in self.endpoints.iter().zip(guards.iter_mut()).enumerate() {
+            if !eligible(&**h) {
+                continue;
+            }
+            h.current_weight += endpoint.weight as i64;
+            total += endpoint.weight as i64;
+            if best.is_none_or(|(_, w)| h.current_weight > w) {
+                best = Some((i, h.current_weight));
+            }
+        }
+        let best = best.map_or(0, |(i, _)| i);
+        guards[best].current_weight -= total;
+        best
+    }
+
+    /// `base` with its `rpc_url` replaced by endpoint `idx`.
+    pub fn config_for(&self, idx: usize, base: &PoolClientConfig) -> PoolClientConfig {
+        let mut cfg = base.clone();
+        cfg.rpc_url = self.endpoints[idx].url.clone();
+        cfg
+    }
+
+    /// Records the outcome of a call made against endpoint `idx`.
+    pub fn record(&self, idx: usize, ok: bool) {
+        self.record_at(idx, ok, Instant::now());
+    }
+
+    fn record_at(&self, idx: usize, ok: bool, now: Instant) {
+        let Some(endpoint) = self.endpoints.get(idx) else {
+            return;
+        };
+        let mut h = endpoint.health.lock().unwrap_or_else(|p| p.into_inner());
+        h.calls += 1;
+        if !ok {
+            h.errors += 1;
+        }
+        if h.window.len() == WINDOW {
+            h.window.pop_front();
+        }
+        h.window.push_back(!ok);
+        if h.unhealthy_until.is_none()
+            && h.window.len() >= MIN_SAMPLES
+            && error_rate(&h.window) >= UNHEALTHY_ERROR_RATE
+        {
+            h.unhealthy_until = Some(now + COOLDOWN);
+            tracing::warn!(
+                endpoint = %display_endpoint(&endpoint.url),
+                error_rate = error_rate(&h.window),
+                cooldown_secs = COOLDOWN.as_secs(),
+                "RPC endpoint unhealthy, shedding load"
+            );
+        }
+    }
+
+    /// Health of every endpoint, in configuration order.
+    pub fn status(&self) -> Vec<EndpointStatus> {
+        let now = Instant::now();
+        self.endpoints
+            .iter()
+            .map(|e| {
+                let h = e.health.lock().unwrap_or_else(|p| p.into_inner());
+                EndpointStatus {
+                    endpoint: display_endpoint(&e.url),
+                    weight: e.weight,
+                    healthy: h.unhealthy_until.is_none_or(|until| now >= until),
+                    error_rate: error_rate(&h.window),
+                    calls: h.calls,
+                    errors: h.errors,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Outcome of a call run through `with_failover`.
+pub struct Failover<T> {
+    pub value: T,
+    /// Whether the RPC itself answered on the attempt that produced `value`.
+    pub rpc_ok: bool,
+    /// Attempt that produced `value`: 0 is `base.rpc_url`, `n` is
+    /// `base.verify_rpc_urls[n - 1]`.
+    pub attempt: usize,
+}
+
+impl<T> Failover<T> {
+    /// Whether the primary endpoint answered, for its health record.
+    pub fn primary_ok(&self) -> bool {
+        self.attempt == 0 && self.rpc_ok
+    }
+}
+
+/// Runs `call` against `base.rpc_url`, then against each backup in
+/// `base.verify_rpc_urls` in order, until one reports its RPC healthy
+/// (`call` returns the value and whether the RPC answered). If every
+/// endpoint fails, the last outcome is returned.
+///
+/// A backup attempt cross-verifies against the other backups only, not the
+/// primary that just failed.
+pub fn with_failover<T>(
+    base: &PoolClientConfig,
+    mut call: impl FnMut(PoolClientConfig) -> (T, bool),
+) -> Failover<T> {
+    let (value, rpc_ok) = call(base.clone());
+    let mut outcome = Failover { value, rpc_ok, attempt: 0 };
+    let mut failed = &base.rpc_url;
+    for (i, url) in base.verify_rpc_urls.iter().enumerate() {
+        if outcome.rpc_ok {
+            break;
+        }
+        if *url == base.rpc_url {
+            continue;
+        }
+        tracing::warn!(
+            failed = %display_endpoint(failed),
+            backup = %display_endpoint(url),
+            "pool RPC failed, failing over"
+        );
+        let mut cfg = base.clone();
+        cfg.rpc_url = url.clone();
+        cfg.verify_rpc_urls.retain(|u| u != url);
+        let (value, rpc_ok) = call(cfg);
+        outcome = Failover { value, rpc_ok, attempt: i + 1 };
+        failed = url;
+    }
+    outcome
+}
+
+fn error_rate(window: &VecDeque<bool>) -> f64 {
+    if window.is_empty() {
+        return 0.0;
+    }
+    window.iter().filter(|e| **e).count() as f64 / window.len() as f64
+}
+
+/// `scheme://host[:port]`, dropping any path or query that may carry a key.
+fn display_endpoint(url: &str) -> String {
+    let (scheme, rest) = url.split_once("://").unwrap_or(("", url));
+    let host = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let host = host.rsplit('@').next().unwrap_or_default();
+    if scheme.is_empty() {
+        host.to_string()
+    } else {
+        format!("{scheme}://{host}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(weights: &[(&str, u32)]) -> RpcPool {
+        let weighted: Vec<_> = weights.iter().map(|(u, w)| (u.to_string(), *w)).collect();
+        RpcPool::new(weights[0].0, &weighted)
+    }
+
+    #[test]
+    fn test_distributes_by_weight() {
+        let pool = pool(&[("https://a", 3), ("https://b", 1)]);
+        let picks: Vec<usize> = (0..8).map(|_| pool.pick()).collect();
+        assert_eq!(picks.iter().filter(|&&i| i == 0).count(), 6);
+        // Smooth: the light endpoint is interleaved, not starved until the end
+        assert!(picks[..4].contains(&1));
+    }
+
+    #[test]
+    fn test_primary_added_when_unlisted() {
+        let weighted = vec![("https://b".to_string(), 2)];
+        let pool = RpcPool::new("https://a", &weighted);
+        let status = pool.status();
+        assert_eq!(status.len(), 2);
+        assert_eq!((status[0].weight, status[1].weight), (1, 2));
+    }
+
+    #[test]
+    fn test_sheds_unhealthy_endpoint_until_cooldown() {
+        let pool = pool(&[("https://a", 1), ("https://b", 1)]);
+        let now = Instant::now();
+        for _ in 0..MIN_SAMPLES {
+            pool.record_at(0, false, now);
+        }
+        assert!(!pool.status()[0].healthy);
+        assert!((0..6).all(|_| pool.pick_at(now) == 1));
+
+        // Back in rotation after the cooldown
+        let later = now + COOLDOWN;
+        let picks: Vec<usize> = (0..4).map(|_| pool.pick_at(later)).collect();
+        assert!(picks.contains(&0));
+    }
+
+    #[test]
+    fn test_all_unhealthy_falls_back_to_all() {
+        let pool = pool(&[("https://a", 1), ("https://b", 1)]);
+        let now = Instant::now();
+        for idx in 0..2 {
+            for _ in 0..MIN_SAMPLES {
+                pool.record_at(idx, false, now);
+            }
+        }
+        let picks: Vec<usize> = (0..4).map(|_| pool.pick_at(now)).collect();
+        assert!(picks.contains(&0) && picks.contains(&1));
+    }
+
+    fn failover_config(primary: &str, backups: &[&str]) -> PoolClientConfig {
+        PoolClientConfig {
+            rpc_url: primary.into(),
+            pool_address: "0x1".into(),
+            network: "sepolia".into(),
+            verify_rpc_urls: backups.iter().map(|u| u.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_failover_tries_backups_in_order() {
+        let base = failover_config("https://primary", &["https://b1", "https://b2"]);
+        let mut tried = Vec::new();
+        let outcome = with_failover(&base, |cfg| {
+            tried.push((cfg.rpc_url.clone(), cfg.verify_rpc_urls.clone()));
+            let ok = cfg.rpc_url == "https://b2";
+            (cfg.rpc_url, ok)
+        });
+        assert_eq!((outcome.value.as_str(), outcome.rpc_ok, outcome.attempt), ("https://b2", true, 2));
+        assert!(!outcome.primary_ok());
+        let urls: Vec<&str> = tried.iter().map(|(u, _)| u.as_str()).collect();
+        assert_eq!(urls, ["https://primary", "https://b1", "https://b2"]);
+        // Backups cross-verify against each other, not the failed primary
+        assert_eq!(tried[1].1, ["https://b2"]);
+        assert_eq!(tried[2].1, ["https://b1"]);
+    }
+
+    #[test]
+    fn test_failover_stops_at_healthy_primary() {
+        let base = failover_config("https://primary", &["https://b1"]);
+        let mut calls = 0;
+        let outcome = with_failover(&base, |_| {
+            calls += 1;
+            ((), true)
+        });
+        assert_eq!(calls, 1);
+        assert!(outcome.primary_ok());
+    }
+
+    #[test]
+    fn test_failover_returns_last_failure_and_skips_primary_as_backup() {
+        let base = failover_config("https://primary", &["https://primary", "https://b1"]);
+        let mut tried = Vec::new();
+        let outcome = with_failover(&base, |cfg| {
+            tried.push(cfg.rpc_url.clone());
+            (cfg.rpc_url, false)
+        });
+        assert_eq!(tried, ["https://primary", "https://b1"]);
+        assert_eq!((outcome.value.as_str(), outcome.rpc_ok, outcome.attempt), ("https://b1", false, 2));
+    }
+
+    #[test]
+    fn test_display_endpoint_hides_keys() {
+        assert_eq!(
+            display_endpoint("https://rpc.example.com/v0_7/SECRET?key=x"),
+            "https://rpc.example.com"
+        );
+        assert_eq!(display_endpoint("https://user:pw@host:8545/"), "https://host:8545");
+    }
+}
@@ -0,0 +1,344 @@
+//! Versioned store snapshots, for backups and moving between store backends.
+//!
+//! A snapshot is newline-delimited JSON: a `header` line carrying the format
+//! version, one line per batch, note, idempotency entry and client_ref index
+//! entry, and an `end` line with the entry count so a truncated stream is
+//! detected on import. Lines are produced and consumed one at a time, so
+//! neither side holds the whole snapshot in memory.
+//!
+//! Rate-limit windows, the ECIES nonce guard and in-flight slots are
+//! transient and not exported. When `VM31_STORAGE_KEY` is set, notes are
+//! exported sealed with the storage key and can only be imported by a relayer
+//! with the same key.
+
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use crate::store::{now_epoch, BatchRecord, BatchStore, InMemoryStore, NoteRecord, NoteStore};
+
+/// Current snapshot format. Importers accept this version and older.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// Longest accepted snapshot line on import.
+pub const MAX_LINE_BYTES: usize = 1024 * 1024;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SnapshotLine {
+    Header {
+        version: u32,
+        exported_at: u64,
+        /// Notes are `sealed_note` lines encrypted with the storage key.
+        sealed_notes: bool,
+    },
+    Batch {
+        record: BatchRecord,
+    },
+    Note {
+        commitment: String,
+        record: NoteRecord,
+    },
+    SealedNote {
+        commitment: String,
+        /// base64 of `nonce || AES-256-GCM ciphertext` (see `StorageEncryption`).
+        ciphertext: String,
+    },
+    Idempotency {
+        key: String,
+        result: String,
+        created_at: u64,
+    },
+    ClientRef {
+        scoped_ref: String,
+        batch_id: String,
+    },
+    End {
+        entries: u64,
+    },
+}
+
+impl SnapshotLine {
+    fn encode(&self) -> Result<String, String> {
+        let mut line = serde_json::to_string(self).map_err(|e| format!("encode: {e}"))?;
+        line.push('\n');
+        Ok(line)
+    }
+}
+
+/// Streams a snapshot of `store` into `sink`, one line per message.
+/// Returns the number of entries written (excluding header and end).
+pub async fn export(store: &InMemoryStore, sink: mpsc::Sender<String>) -> Result<u64, String> {
+    let send = |line: SnapshotLine| {
+        let sink = &sink;
+        async move {
+            sink.send(line.encode()?)
+                .await
+                .map_err(|_| "client disconnected".to_string())
+        }
+    };
+    let sealing = store.storage_encryption();
+    send(SnapshotLine::Header {
+        version: SNAPSHOT_VERSION,
+        exported_at: now_epoch(),
+        sealed_notes: sealing.is_some(),
+    })
+    .await?;
+
+    let mut entries = 0u64;
+    for id in store.batch_ids() {
+        // Evicted since the id list was taken
+        let Some(record) = store.get_batch(&id).await.map_err(|e| e.to_string())? else {
+            continue;
+        };
+        send(SnapshotLine::Batch { record }).await?;
+        entries += 1;
+    }
+    for commitment in store.note_commitments() {
+        let Some(record) = store.get_note(&commitment).await.map_err(|e| e.to_string())? else {
+            continue;
+        };
+        let line = match sealing {
+            Some(enc) => SnapshotLine::SealedNote {
+                ciphertext: base64::engine::general_purpose::STANDARD
+                    .encode(enc.encrypt_note(&commitment, &record).map_err(|e| e.to_string())?),
+                commitment,
+            },
+            None => SnapshotLine::Note { commitment, record },
+        };
+        send(line).await?;
+        entries += 1;
+    }
+    for (key, result, created_at) in store.idempotency_entries() {
+        send(SnapshotLine::Idempotency { key, result, created_at }).await?;
+        entries += 1;
+    }
+    for (scoped_ref, batch_id) in store.client_ref_entries() {
+        send(SnapshotLine::ClientRef { scoped_ref, batch_id }).await?;
+        entries += 1;
+    }
+    send(SnapshotLine::End { entries }).await?;
+    Ok(entries)
+}
+
+/// Counts of imported records.
+#[derive(Debug, Default, Serialize, PartialEq, Eq)]
+pub struct ImportSummary {
+    pub batches: u64,
+    pub notes: u64,
+    pub idempotency: u64,
+    pub client_refs: u64,
+}
+
+impl ImportSummary {
+    fn total(&self) -> u64 {
+        self.batches + self.notes + self.idempotency + self.client_refs
+    }
+}
+
+/// Applies snapshot lines to a store in order.
+pub struct Importer<'a> {
+    store: &'a InMemoryStore,
+    header_seen: bool,
+    /// Entry count from the `end` line, once seen.
+    expected: Option<u64>,
+    summary: ImportSummary,
+}
+
+impl<'a> Importer<'a> {
+    pub fn new(store: &'a InMemoryStore) -> Self {
+        Self {
+            store,
+            header_seen: false,
+            expected: None,
+            summary: ImportSummary::default(),
+        }
+    }
+
+    /// Decodes and applies one line. Blank lines are ignored.
+    pub async fn apply(&mut self, line: &str) -> Result<(), String> {
+        if line.trim().is_empty() {
+            return Ok(());
+        }
+        if self.expected.is_some() {
+            return Err("data after end of snapshot".into());
+        }
+        let line: SnapshotLine =
+            serde_json::from_str(line).map_err(|e| format!("invalid snapshot line: {e}"))?;
+        if !self.header_seen {
+            return match line {
+                SnapshotLine::Header { version, .. } if version > SNAPSHOT_VERSION => Err(format!(
+                    "snapshot version {version} is newer than supported version {SNAPSHOT_VERSION}"
+                )),
+                SnapshotLine::Header { .. } => {
+                    self.header_seen = true;
+                    Ok(())
+                }
+                _ => Err("snapshot must start with a header line".into()),
+            };
+        }
+        match line {
+            SnapshotLine::Header { .. } => return Err("duplicate snapshot header".into()),
+            SnapshotLine::Batch { record } => {
+                self.store
+                    .save_batch(&record.id.clone(), &record)
+                    .await
+                    .map_err(|e| e.to_string())?;
+                self.summary.batches += 1;
+            }
+            SnapshotLine::Note { commitment, record } => {
+                self.store
+                    .save_note(&commitment, &record)
+                    .await
+                    .map_err(|e| e.to_string())?;
+                self.summary.notes += 1;
+            }
+            SnapshotLine::SealedNote { commitment, ciphertext } => {
+                let enc = self.store.storage_encryption().ok_or(
+                    "snapshot has sealed notes but VM31_STORAGE_KEY is not configured",
+                )?;
+                let data = base64::engine::general_purpose::STANDARD
+                    .decode(&ciphertext)
+                    .map_err(|_| format!("note {commitment}: invalid base64"))?;
+                let record = enc
+                    .decrypt_note(&commitment, &data)
+                    .map_err(|_| format!("note {commitment}: cannot unseal (different storage key?)"))?;
+                self.store
+                    .save_note(&commitment, &record)
+                    .await
+                    .map_err(|e| e.to_string())?;
+                self.summary.notes += 1;
+            }
+            SnapshotLine::Idempotency { key, result, created_at } => {
+                self.store.restore_idempotency(&key, &result, created_at);
+                self.summary.idempotency += 1;
+            }
+            SnapshotLine::ClientRef { scoped_ref, batch_id } => {
+                self.store.index_client_ref(&scoped_ref, &batch_id);
+                self.summary.client_refs += 1;
+            }
+            SnapshotLine::End { entries } => self.expected = Some(entries),
+        }
+        Ok(())
+    }
+
+    /// Checks the snapshot was complete and returns what was imported.
+    pub fn finish(self) -> Result<ImportSummary, String> {
+        match self.expected {
+            None => Err(format!(
+                "snapshot truncated: no end line after {} entries",
+                self.summary.total()
+            )),
+            Some(n) if n != self.summary.total() => Err(format!(
+                "snapshot end line expects {n} entries, got {}",
+                self.summary.total()
+            )),
+            Some(_) => Ok(self.summary),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::{BatchStatus, IdempotencyStore, MerklePathRecord};
+
+    async fn seeded(store: &InMemoryStore) {
+        let mut batch = BatchRecord::new("batch-1".into(), 2);
+        batch.status = BatchStatus::Finalized;
+        store.save_batch("batch-1", &batch).await.unwrap();
+        let note = NoteRecord {
+            commitment: "c1".into(),
+            merkle_path: MerklePathRecord { siblings: vec![[1; 8]], index: 3 },
+            merkle_root: [2; 8],
+            batch_id: "batch-1".into(),
+            created_at: now_epoch(),
+            commitment_digest: Some([4; 8]),
+            note_index_in_batch: 0,
+            client_ref: Some("scoped".into()),
+            backfill_attempts: 0,
+            last_backfill_attempt: 0,
+        };
+        store.save_note("c1", &note).await.unwrap();
+        store.check_and_set("idem-1", "batch-1").await.unwrap();
+        store.index_client_ref("scoped", "batch-1");
+    }
+
+    async fn export_lines(store: &InMemoryStore) -> Vec<String> {
+        let (tx, mut rx) = mpsc::channel(4);
+        let (written, lines) = tokio::join!(export(store, tx), async {
+            let mut lines = Vec::new();
+            while let Some(line) = rx.recv().await {
+                lines.push(line);
+            }
+            lines
+        });
+        assert_eq!(written.unwrap(), 4);
+        lines
+    }
+
+    async fn import_lines(store: &InMemoryStore, lines: &[String]) -> Result<ImportSummary, String> {
+        let mut importer = Importer::new(store);
+        for line in lines {
+            importer.apply(line).await?;
+        }
+        importer.finish()
+    }
+
+    #[tokio::test]
+    async fn test_round_trip() {
+        let source = InMemoryStore::new();
+        seeded(&source).await;
+        let lines = export_lines(&source).await;
+        assert!(lines[0].contains("\"kind\":\"header\""));
+        assert!(lines.iter().all(|l| l.ends_with('\n')));
+
+        let target = InMemoryStore::new();
+        let summary = import_lines(&target, &lines).await.unwrap();
+        assert_eq!(
+            summary,
+            ImportSummary { batches: 1, notes: 1, idempotency: 1, client_refs: 1 }
+        );
+        let batch = target.get_batch("batch-1").await.unwrap().unwrap();
+        assert_eq!(batch.status, BatchStatus::Finalized);
+        assert_eq!(target.get_note("c1").await.unwrap().unwrap().merkle_path.index, 3);
+        assert_eq!(target.get_result("idem-1").await.unwrap().as_deref(), Some("batch-1"));
+        assert_eq!(target.find_batch_by_client_ref("scoped").as_deref(), Some("batch-1"));
+    }
+
+    #[tokio::test]
+    async fn test_sealed_notes_need_the_same_key() {
+        let source = InMemoryStore::with_encryption(Some(&[7; 32]));
+        seeded(&source).await;
+        let lines = export_lines(&source).await;
+        assert!(lines.iter().any(|l| l.contains("\"kind\":\"sealed_note\"")));
+        assert!(!lines.iter().any(|l| l.contains("commitment_digest")));
+
+        let same_key = InMemoryStore::with_encryption(Some(&[7; 32]));
+        assert_eq!(import_lines(&same_key, &lines).await.unwrap().notes, 1);
+        let other_key = InMemoryStore::with_encryption(Some(&[8; 32]));
+        assert!(import_lines(&other_key, &lines).await.unwrap_err().contains("cannot unseal"));
+        let no_key = InMemoryStore::new();
+        assert!(import_lines(&no_key, &lines).await.unwrap_err().contains("VM31_STORAGE_KEY"));
+    }
+
+    #[tokio::test]
+    async fn test_rejects_newer_version_and_truncation() {
+        let store = InMemoryStore::new();
+        let newer = format!(
+            "{{\"kind\":\"header\",\"version\":{},\"exported_at\":0,\"sealed_notes\":false}}",
+            SNAPSHOT_VERSION + 1
+        );
+        assert!(import_lines(&store, &[newer]).await.unwrap_err().contains("newer"));
+
+        let source = InMemoryStore::new();
+        seeded(&source).await;
+        let mut lines = export_lines(&source).await;
+        lines.pop();
+        let err = import_lines(&InMemoryStore::new(), &lines).await.unwrap_err();
+        assert!(err.contains("truncated"), "{err}");
+
+        let err = import_lines(&InMemoryStore::new(), &lines[1..]).await.unwrap_err();
+        assert!(err.contains("header"), "{err}");
+    }
+}
@@ -1,15 +1,23 @@
 use dashmap::DashMap;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
 use std::sync::Arc;
+#[cfg(any(feature = "redis", feature = "postgres"))]
+use std::sync::atomic::AtomicBool;
 use std::sync::atomic::{AtomicU64, Ordering};
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
-use tracing::{debug, warn};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::{broadcast, OwnedSemaphorePermit, Semaphore};
+use tracing::{debug, info, warn};
 
 use aes_gcm::{aead::Aead, Aes256Gcm, KeyInit, Nonce};
 use hkdf::Hkdf;
 use sha2::Sha256;
 
 use crate::config::RelayerConfig;
+use crate::dead_letter::{DeadLetter, DeadLetterSummary, MAX_DEAD_LETTERS};
+#[cfg(feature = "redis")]
+use crate::denylist::{parse_pubkey, pubkey_hex};
 
 // ---------------------------------------------------------------------------
 // Types
@@ -25,6 +33,24 @@ pub enum BatchStatus {
     Failed,
 }
 
+impl BatchStatus {
+    /// Finalized and failed batches never change again.
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, BatchStatus::Finalized | BatchStatus::Failed)
+    }
+}
+
+/// A batch status write, broadcast to `/batch/{id}/events` subscribers.
+#[derive(Debug, Clone)]
+pub struct BatchEvent {
+    pub batch_id: String,
+    pub status: BatchStatus,
+}
+
+/// Buffered status events; a subscriber further behind sees `Lagged` and
+/// re-reads the batch record instead.
+const BATCH_EVENT_CAPACITY: usize = 1024;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BatchRecord {
     pub id: String,
@@ -56,6 +82,53 @@ impl BatchRecord {
     }
 }
 
+/// Counts from a single eviction pass.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct EvictionSummary {
+    pub idempotency: usize,
+    pub rate_limits: usize,
+    pub batches: usize,
+    pub inflight: usize,
+    /// Epoch seconds when the pass ran.
+    pub at: u64,
+}
+
+impl EvictionSummary {
+    pub fn total(&self) -> usize {
+        self.idempotency + self.rate_limits + self.batches + self.inflight
+    }
+}
+
+/// Cumulative eviction counters since startup.
+#[derive(Default)]
+pub struct EvictionMetrics {
+    pub idempotency: AtomicU64,
+    pub rate_limits: AtomicU64,
+    pub batches: AtomicU64,
+    pub inflight: AtomicU64,
+    pub passes: AtomicU64,
+}
+
+impl EvictionMetrics {
+    fn record(&self, summary: &EvictionSummary) {
+        self.idempotency.fetch_add(summary.idempotency as u64, Ordering::Relaxed);
+        self.rate_limits.fetch_add(summary.rate_limits as u64, Ordering::Relaxed);
+        self.batches.fetch_add(summary.batches as u64, Ordering::Relaxed);
+        self.inflight.fetch_add(summary.inflight as u64, Ordering::Relaxed);
+        self.passes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> serde_json::Value {
+        serde_json::json!({
+            "idempotency": self.idempotency.load(Ordering::Relaxed),
+            "rate_limits": self.rate_limits.load(Ordering::Relaxed),
+            "batches": self.batches.load(Ordering::Relaxed),
+            "inflight": self.inflight.load(Ordering::Relaxed),
+            "passes": self.passes.load(Ordering::Relaxed),
+        })
+    }
+}
+
 // ---------------------------------------------------------------------------
 // Trait definitions
 // ---------------------------------------------------------------------------
@@ -78,6 +151,50 @@ pub trait BatchStore: Send + Sync + 'static {
         status: BatchStatus,
         extra: StatusUpdate,
     ) -> impl std::future::Future<Output = Result<(), StoreError>> + Send;
+
+    /// Returns up to `limit` batches newest first (`created_at` descending,
+    /// ties by id descending), optionally filtered by `status`, starting
+    /// strictly after `cursor`.
+    fn list_batches(
+        &self,
+        status: Option<BatchStatus>,
+        cursor: Option<&BatchCursor>,
+        limit: usize,
+    ) -> impl std::future::Future<Output = Result<Vec<BatchRecord>, StoreError>> + Send;
+}
+
+/// Position in a `list_batches` listing: the last record already returned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchCursor {
+    pub created_at: u64,
+    pub id: String,
+}
+
+impl BatchCursor {
+    pub fn after(record: &BatchRecord) -> Self {
+        Self {
+            created_at: record.created_at,
+            id: record.id.clone(),
+        }
+    }
+
+    /// Opaque wire form: `<created_at>-<id>`.
+    pub fn encode(&self) -> String {
+        format!("{}-{}", self.created_at, self.id)
+    }
+
+    pub fn decode(s: &str) -> Option<Self> {
+        let (created_at, id) = s.split_once('-')?;
+        Some(Self {
+            created_at: created_at.parse().ok()?,
+            id: id.to_string(),
+        })
+    }
+
+    /// Whether `record` sorts after this cursor in newest-first order.
+    fn precedes(&self, record: &BatchRecord) -> bool {
+        (record.created_at, record.id.as_str()) < (self.created_at, self.id.as_str())
+    }
 }
 
 pub trait IdempotencyStore: Send + Sync + 'static {
@@ -87,16 +204,166 @@ pub trait IdempotencyStore: Send + Sync + 'static {
         key: &str,
         result: &str,
     ) -> impl std::future::Future<Output = Result<Option<String>, StoreError>> + Send;
+
+    /// Returns the result stored for a live (unexpired) key.
+    fn get_result(
+        &self,
+        key: &str,
+    ) -> impl std::future::Future<Output = Result<Option<String>, StoreError>> + Send;
+
+    /// Replaces the result of a live key, keeping its expiry. No-op if the
+    /// key is absent or expired.
+    fn update_result(
+        &self,
+        key: &str,
+        result: &str,
+    ) -> impl std::future::Future<Output = Result<(), StoreError>> + Send;
+
+    /// Drops a key, so the next `check_and_set` on it sets it afresh.
+    fn release(&self, key: &str) -> impl std::future::Future<Output = Result<(), StoreError>> + Send;
+}
+
+/// Outcome of a rate-limit check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateDecision {
+    pub allowed: bool,
+    /// Seconds until the key's window resets; reported as `Retry-After`.
+    pub retry_after_secs: u64,
+}
+
+/// How `check_rate` counts requests (`VM31_RATE_LIMIT_ALGO`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RateLimitAlgo {
+    /// At most `limit` requests per window (the default).
+    FixedWindow,
+    /// Buckets of `limit × burst` tokens, refilled at `limit` per window
+    /// and spent one per request, so bursts above the steady rate pass.
+    TokenBucket { burst: f64 },
+}
+
+impl RateLimitAlgo {
+    /// Bucket capacity and refill rate (tokens/sec) for a limit and window.
+    fn bucket(burst: f64, limit: u32, window_secs: u64) -> (f64, f64) {
+        let capacity = (f64::from(limit) * burst).max(1.0);
+        (capacity, f64::from(limit) / window_secs.max(1) as f64)
+    }
+}
+
+/// Refills `bucket` (tokens, last refill) up to `now` and takes `cost`
+/// tokens, or none if fewer are left.
+fn take_tokens(bucket: &mut (f64, Instant), cost: f64, capacity: f64, rate: f64, now: Instant) -> RateDecision {
+    let (tokens, last_refill) = bucket;
+    let elapsed = now.saturating_duration_since(*last_refill).as_secs_f64();
+    *tokens = (*tokens + elapsed * rate).min(capacity);
+    *last_refill = now;
+    let allowed = *tokens >= cost;
+    if allowed {
+        *tokens -= cost;
+    }
+    // Time until `cost` whole tokens are back
+    let wait = ((cost - *tokens).max(0.0) / rate).ceil();
+    RateDecision { allowed, retry_after_secs: (wait as u64).max(1) }
 }
 
 pub trait RateLimitStore: Send + Sync + 'static {
-    /// Returns `true` if the request is allowed, `false` if rate-limited.
+    /// Counts a request against `key` and reports whether it is allowed.
     fn check_rate(
         &self,
         key: &str,
         limit: u32,
         window_secs: u64,
+    ) -> impl std::future::Future<Output = Result<RateDecision, StoreError>> + Send {
+        self.check_rate_n(key, 1, limit, window_secs)
+    }
+
+    /// Counts `cost` requests against `key` at once, e.g. one per item of a
+    /// batched lookup. They are allowed only if all of them fit.
+    fn check_rate_n(
+        &self,
+        key: &str,
+        cost: u32,
+        limit: u32,
+        window_secs: u64,
+    ) -> impl std::future::Future<Output = Result<RateDecision, StoreError>> + Send;
+}
+
+pub trait NonceGuardStore: Send + Sync + 'static {
+    /// Records an ECIES `(ephemeral_pubkey, nonce)` pair.
+    /// Returns `true` if the pair is fresh, `false` if it was already seen within `ttl_secs`.
+    /// At most `max_entries` pairs are retained; the oldest are dropped first.
+    fn check_and_record_nonce(
+        &self,
+        pair_key: &str,
+        ttl_secs: u64,
+        max_entries: usize,
+    ) -> impl std::future::Future<Output = Result<bool, StoreError>> + Send;
+
+    /// Forgets a pair recorded by `check_and_record_nonce`, for a submission
+    /// that was rejected after its nonce was recorded.
+    fn forget_nonce(&self, pair_key: &str) -> impl std::future::Future<Output = Result<(), StoreError>> + Send;
+}
+
+/// UTC day number (days since the Unix epoch) containing `epoch_secs`.
+pub fn utc_day(epoch_secs: u64) -> u64 {
+    epoch_secs / 86_400
+}
+
+pub trait VolumeStore: Send + Sync + 'static {
+    /// Adds `amount` to the running total for `key` on UTC day `day` if the
+    /// total stays within `cap`. Returns `false`, leaving the total as is,
+    /// when it would not. Totals start over from zero each day.
+    fn check_and_add_volume(
+        &self,
+        key: &str,
+        day: u64,
+        amount: u64,
+        cap: u64,
     ) -> impl std::future::Future<Output = Result<bool, StoreError>> + Send;
+
+    /// Takes `amount` back off `key`'s total for `day`, undoing a
+    /// `check_and_add_volume` whose submission was then rejected.
+    fn subtract_volume(
+        &self,
+        key: &str,
+        day: u64,
+        amount: u64,
+    ) -> impl std::future::Future<Output = Result<(), StoreError>> + Send;
+}
+
+/// Reverse index from a note's owner pubkey to its store keys, so a wallet
+/// that lost its local state can rediscover its notes.
+pub trait OwnerIndexStore: Send + Sync + 'static {
+    /// Records that the note stored under `commitment` belongs to `owner`.
+    fn index_note_owner(
+        &self,
+        owner: &[u32; 4],
+        commitment: &str,
+    ) -> impl std::future::Future<Output = Result<(), StoreError>> + Send;
+
+    /// Store keys of `owner`'s notes, in the order they were indexed.
+    fn notes_by_owner(
+        &self,
+        owner: &[u32; 4],
+    ) -> impl std::future::Future<Output = Result<Vec<String>, StoreError>> + Send;
+}
+
+pub trait DeadLetterStore: Send + Sync + 'static {
+    /// Stores a failed batch's transactions under `dlq:{batch_id}`.
+    fn save_dead_letter(
+        &self,
+        entry: DeadLetter,
+    ) -> impl std::future::Future<Output = Result<(), StoreError>> + Send;
+
+    /// Lists dead-lettered batches, most recent failure first.
+    fn list_dead_letters(
+        &self,
+    ) -> impl std::future::Future<Output = Result<Vec<DeadLetterSummary>, StoreError>> + Send;
+
+    /// Removes and returns the entry for `batch_id`.
+    fn take_dead_letter(
+        &self,
+        batch_id: &str,
+    ) -> impl std::future::Future<Output = Result<Option<DeadLetter>, StoreError>> + Send;
 }
 
 // ---------------------------------------------------------------------------
@@ -118,6 +385,39 @@ pub struct NoteRecord {
     /// Used for ordering when multiple deposits are in the same batch.
     #[serde(default)]
     pub note_index_in_batch: usize,
+    /// API-key-scoped digest of the client's `client_ref`, if supplied.
+    #[serde(default)]
+    pub client_ref: Option<String>,
+    /// Number of backfill passes that failed to match this note on-chain.
+    #[serde(default)]
+    pub backfill_attempts: u32,
+    /// Epoch of the last failed backfill attempt (0 = never attempted).
+    #[serde(default)]
+    pub last_backfill_attempt: u64,
+}
+
+impl NoteRecord {
+    /// A pending note is stuck when it has no on-chain commitment digest and
+    /// has been pending for at least `stuck_after_secs`. Backfill can never
+    /// match such a note, so it needs manual intervention.
+    pub fn is_stuck(&self, now: u64, stuck_after_secs: u64) -> bool {
+        self.merkle_root == [0; 8]
+            && self.commitment_digest.is_none()
+            && now.saturating_sub(self.created_at) >= stuck_after_secs
+    }
+
+    /// Whether backfill should try this note again at `now`.
+    ///
+    /// Each failed attempt doubles the wait, starting at `base_secs` and
+    /// capped at `max_secs`. Notes never attempted are always due.
+    pub fn backfill_due(&self, now: u64, base_secs: u64, max_secs: u64) -> bool {
+        if self.backfill_attempts == 0 {
+            return true;
+        }
+        let shift = (self.backfill_attempts - 1).min(32);
+        let backoff = base_secs.saturating_mul(1u64 << shift).min(max_secs);
+        now.saturating_sub(self.last_backfill_attempt) >= backoff
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -146,6 +446,16 @@ pub trait NoteStore: Send + Sync + 'static {
     fn list_pending_notes(
         &self,
     ) -> impl std::future::Future<Output = Result<Vec<NoteRecord>, StoreError>> + Send;
+
+    /// One page of `list_pending_notes`: up to about `limit` notes after
+    /// `cursor` (`None` starts from the beginning), plus the cursor for the
+    /// next page, `None` once the listing is complete. Pending notes that
+    /// exist for the whole listing are returned at least once.
+    fn list_pending_notes_page(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> impl std::future::Future<Output = Result<(Vec<NoteRecord>, Option<String>), StoreError>> + Send;
 }
 
 /// Extra fields to set when updating batch status.
@@ -178,10 +488,9 @@ impl std::error::Error for StoreError {}
 // Storage encryption layer (defense-in-depth for privacy gap #1)
 // ---------------------------------------------------------------------------
 
-/// Encrypts/decrypts NoteRecord values at rest using AES-256-GCM.
-/// The commitment field (used as lookup key) remains plaintext; only
-/// the note content is encrypted. Derive the key from VM31_STORAGE_KEY
-/// via HKDF-SHA256.
+/// Encrypts/decrypts store records at rest using AES-256-GCM.
+/// Lookup keys (commitments, batch ids) remain plaintext; only the record
+/// content is encrypted. Derive the key from VM31_STORAGE_KEY via HKDF-SHA256.
 pub struct StorageEncryption {
     cipher: Aes256Gcm,
 }
@@ -200,9 +509,19 @@ impl StorageEncryption {
     }
 
     /// Encrypt a NoteRecord → opaque bytes.
+    pub fn encrypt_note(&self, _commitment: &str, record: &NoteRecord) -> Result<Vec<u8>, StoreError> {
+        self.seal(record)
+    }
+
+    /// Decrypt opaque bytes → NoteRecord.
+    pub fn decrypt_note(&self, _commitment: &str, data: &[u8]) -> Result<NoteRecord, StoreError> {
+        self.open(data)
+    }
+
+    /// Serializes and encrypts a record → opaque bytes.
     /// Uses a random 12-byte nonce prepended to the ciphertext (nonce || ct).
     /// Each encryption gets a fresh nonce — safe for re-encryption of updates.
-    pub fn encrypt_note(&self, _commitment: &str, record: &NoteRecord) -> Result<Vec<u8>, StoreError> {
+    pub(crate) fn seal<T: Serialize>(&self, record: &T) -> Result<Vec<u8>, StoreError> {
         use rand::RngCore;
         let plaintext = serde_json::to_vec(record)
             .map_err(|e| StoreError::Backend(format!("serialize: {e}")))?;
@@ -219,11 +538,11 @@ impl StorageEncryption {
         Ok(out)
     }
 
-    /// Decrypt opaque bytes → NoteRecord.
+    /// Decrypts and deserializes a record.
     /// Expects format: [nonce(12) || ciphertext].
-    pub fn decrypt_note(&self, _commitment: &str, data: &[u8]) -> Result<NoteRecord, StoreError> {
+    pub(crate) fn open<T: DeserializeOwned>(&self, data: &[u8]) -> Result<T, StoreError> {
         if data.len() < 12 {
-            return Err(StoreError::Backend("encrypted note too short (missing nonce)".into()));
+            return Err(StoreError::Backend("encrypted record too short (missing nonce)".into()));
         }
         let (nonce_bytes, ciphertext) = data.split_at(12);
         let nonce = Nonce::from_slice(nonce_bytes);
@@ -245,23 +564,152 @@ const MAX_IDEMPOTENCY_ENTRIES: usize = 50_000;
 const IDEMPOTENCY_TTL_SECS: u64 = 3600;
 /// Rate limit entries expire after 1 hour (much longer than any window).
 const RATE_LIMIT_EVICTION_SECS: u64 = 3600;
+/// Redis sorted set holding seen ECIES `(ephemeral_pubkey, nonce)` pairs (score = epoch).
+#[cfg(feature = "redis")]
+const NONCE_GUARD_REDIS_KEY: &str = "ecies:nonces";
+/// Redis sorted set of batch ids (score = `created_at`) backing `list_batches`.
+#[cfg(feature = "redis")]
+const BATCH_INDEX_REDIS_KEY: &str = "batches:by_created";
+/// Prefix of the Redis set of note keys per owner pubkey (hex).
+#[cfg(feature = "redis")]
+const OWNER_INDEX_PREFIX: &str = "notes:owner:";
+
+/// A record in the in-memory maps: shared as-is, or sealed by
+/// `StorageEncryption` when VM31_STORAGE_KEY is configured.
+enum Held<T> {
+    Plain(Arc<T>),
+    Sealed(Vec<u8>),
+}
+
+/// A held batch. Status and creation time stay in the clear for listing
+/// and eviction.
+struct HeldBatch {
+    status: BatchStatus,
+    created_at: u64,
+    record: Held<BatchRecord>,
+}
+
+/// Seen ECIES `(ephemeral_pubkey, nonce)` pairs in insertion order, so the
+/// oldest can be dropped in O(1) once the guard is full.
+#[derive(Default)]
+struct NonceWindow {
+    /// Pair → epoch it was (last) recorded.
+    seen: HashMap<String, u64>,
+    /// `(pair, recorded_at)` oldest first. A pair re-recorded after expiry
+    /// leaves a stale entry behind, skipped when it reaches the front.
+    order: VecDeque<(String, u64)>,
+}
+
+impl NonceWindow {
+    /// Records `pair` unless it was seen within `ttl_secs`, then drops
+    /// expired pairs and the oldest beyond `max_entries`.
+    fn check_and_record(&mut self, pair: &str, now: u64, ttl_secs: u64, max_entries: usize) -> bool {
+        let fresh = self.seen.get(pair).is_none_or(|seen| now.saturating_sub(*seen) >= ttl_secs);
+        if fresh {
+            self.seen.insert(pair.to_string(), now);
+            self.order.push_back((pair.to_string(), now));
+        }
+        while let Some((key, recorded)) = self.order.front() {
+            if self.order.len() <= max_entries && now.saturating_sub(*recorded) < ttl_secs {
+                break;
+            }
+            if self.seen.get(key) == Some(recorded) {
+                self.seen.remove(key);
+            }
+            self.order.pop_front();
+        }
+        fresh
+    }
+
+    /// Drops `pair`; its `order` entry is skipped as stale when it reaches
+    /// the front.
+    fn forget(&mut self, pair: &str) {
+        self.seen.remove(pair);
+    }
+
+    fn len(&self) -> usize {
+        self.seen.len()
+    }
+}
+
+/// A held dead letter. Its listing summary (batch id, reason, time, tx
+/// count) stays in the clear; the transactions, spending keys included, are
+/// sealed with the rest of the record.
+struct HeldDeadLetter {
+    summary: DeadLetterSummary,
+    record: Held<DeadLetter>,
+}
+
+/// A held note. Whether it still awaits its merkle root stays in the clear
+/// so pending notes can be found without decrypting every record.
+struct HeldNote {
+    pending: bool,
+    record: Held<NoteRecord>,
+}
 
 pub struct InMemoryStore {
-    batches: DashMap<String, BatchRecord>,
+    batches: DashMap<String, HeldBatch>,
     idempotency: DashMap<String, (String, u64)>, // (result, created_epoch)
     rate_limits: DashMap<String, (u32, u64)>,     // (count, window_start_epoch)
-    notes: DashMap<String, NoteRecord>,
-    /// Encrypted note storage: commitment → AES-256-GCM ciphertext.
-    /// Used when VM31_STORAGE_KEY is configured (defense-in-depth, gap #1).
-    encrypted_notes: DashMap<String, Vec<u8>>,
-    /// Storage encryption layer (None if VM31_STORAGE_KEY not set).
+    token_buckets: DashMap<String, (f64, Instant)>, // (tokens, last_refill)
+    rate_limit_algo: RateLimitAlgo,
+    /// Unsealed notes are shared so read-heavy paths (`get_note_shared`,
+    /// `pending_notes_shared`) hand out a refcount instead of copying paths
+    /// and strings.
+    notes: DashMap<String, HeldNote>,
+    /// Storage encryption layer (None if VM31_STORAGE_KEY not set). When set,
+    /// batches and notes are only held sealed (defense-in-depth, gap #1).
     storage_encryption: Option<StorageEncryption>,
+    /// Seen ECIES `(ephemeral_pubkey, nonce)` pairs.
+    seen_nonces: std::sync::Mutex<NonceWindow>,
+    /// Recently accepted withdrawal bindings → epoch the entry expires.
+    recent_bindings: DashMap<String, u64>,
+    /// Deposit volume per key → (UTC day, total that day).
+    volumes: DashMap<String, (u64, u64)>,
+    /// Owner pubkey → store keys of its notes (`VM31_NOTE_OWNER_INDEX`).
+    /// Not evicted, like the notes themselves.
+    owner_notes: DashMap<[u32; 4], Vec<String>>,
+    /// Scoped client_ref digest → batch id, for client-side outcome lookups.
+    /// Entries are dropped once their batch is evicted.
+    client_refs: DashMap<String, String>,
+    /// Per-key concurrency semaphores bounding in-flight requests.
+    /// Idle semaphores are dropped by the eviction task.
+    inflight: DashMap<String, Arc<Semaphore>>,
+    /// Failed batches awaiting inspection or replay, by batch id.
+    /// Not evicted; bounded by `MAX_DEAD_LETTERS`.
+    dead_letters: DashMap<String, HeldDeadLetter>,
+    /// Every batch save and status update, for live status streams.
+    batch_events: broadcast::Sender<BatchEvent>,
+    /// TEST ONLY: fail this many upcoming `save_batch` calls.
+    #[cfg(test)]
+    pub(crate) failing_batch_saves: AtomicU64,
     eviction_counter: AtomicU64,
+    /// Cumulative eviction counts (exposed in `/status`).
+    pub eviction_metrics: EvictionMetrics,
+    /// Result of the most recent eviction pass.
+    last_eviction: std::sync::Mutex<Option<EvictionSummary>>,
+    /// When true, the nonce guard consults Redis so seen pairs survive restarts.
+    #[cfg(feature = "redis")]
+    nonce_guard_persistent: bool,
     /// Optional Redis write-through for crash recovery.
     /// When set, every batch/note mutation is mirrored to Redis.
     /// On startup, existing data is loaded from Redis into the in-memory maps.
     #[cfg(feature = "redis")]
     redis_backend: Option<RedisStore>,
+    /// When true, failed Redis write-throughs are logged and the in-memory
+    /// write still succeeds; when false, the failure is returned to the caller.
+    #[cfg(feature = "redis")]
+    redis_degrade_on_error: bool,
+    /// False while Redis write-throughs are failing (reported in `/status`).
+    #[cfg(feature = "redis")]
+    redis_healthy: AtomicBool,
+    /// Optional Postgres write-through, mirroring batches and notes the same
+    /// way as Redis. Failures are logged and the in-memory write stands.
+    #[cfg(feature = "postgres")]
+    postgres_backend: Option<PostgresStore>,
+    /// False while Postgres write-throughs are failing (reported in `/status`).
+    #[cfg(feature = "postgres")]
+    postgres_healthy: AtomicBool,
 }
 
 impl InMemoryStore {
@@ -270,16 +718,45 @@ impl InMemoryStore {
             batches: DashMap::new(),
             idempotency: DashMap::new(),
             rate_limits: DashMap::new(),
+            token_buckets: DashMap::new(),
+            rate_limit_algo: RateLimitAlgo::FixedWindow,
             notes: DashMap::new(),
-            encrypted_notes: DashMap::new(),
             storage_encryption: None,
+            seen_nonces: std::sync::Mutex::new(NonceWindow::default()),
+            recent_bindings: DashMap::new(),
+            volumes: DashMap::new(),
+            owner_notes: DashMap::new(),
+            client_refs: DashMap::new(),
+            inflight: DashMap::new(),
+            dead_letters: DashMap::new(),
+            batch_events: broadcast::channel(BATCH_EVENT_CAPACITY).0,
+            #[cfg(test)]
+            failing_batch_saves: AtomicU64::new(0),
             eviction_counter: AtomicU64::new(0),
+            eviction_metrics: EvictionMetrics::default(),
+            last_eviction: std::sync::Mutex::new(None),
+            #[cfg(feature = "redis")]
+            nonce_guard_persistent: false,
             #[cfg(feature = "redis")]
             redis_backend: None,
+            #[cfg(feature = "redis")]
+            redis_degrade_on_error: true,
+            #[cfg(feature = "redis")]
+            redis_healthy: AtomicBool::new(true),
+            #[cfg(feature = "postgres")]
+            postgres_backend: None,
+            #[cfg(feature = "postgres")]
+            postgres_healthy: AtomicBool::new(true),
         }
     }
 
-    /// Create with optional at-rest encryption for note records.
+    /// Sets how `check_rate` counts requests.
+    pub fn with_rate_limit_algo(mut self, algo: RateLimitAlgo) -> Self {
+        self.rate_limit_algo = algo;
+        self
+    }
+
+    /// Create with optional at-rest encryption for batch and note records.
     pub fn with_encryption(storage_key: Option<&[u8; 32]>) -> Self {
         let mut store = Self::new();
         if let Some(key) = storage_key {
@@ -301,6 +778,125 @@ impl InMemoryStore {
         Ok(store)
     }
 
+    /// Mirrors batch and note writes to `backend`. Call `load_from_postgres()`
+    /// on startup to hydrate the in-memory maps.
+    #[cfg(feature = "postgres")]
+    pub fn with_postgres_backend(mut self, backend: PostgresStore) -> Self {
+        self.postgres_backend = Some(backend);
+        self
+    }
+
+    /// Checks that the Redis backend is reachable (no-op without one).
+    #[cfg(feature = "redis")]
+    pub async fn ping_redis(&self) -> Result<(), StoreError> {
+        let redis = match &self.redis_backend {
+            Some(r) => r,
+            None => return Ok(()),
+        };
+        let mut conn = redis.conn().await?;
+        redis::cmd("PING")
+            .query_async::<_, String>(&mut conn)
+            .await
+            .map(|_| ())
+            .map_err(|e| StoreError::Backend(format!("redis PING: {e}")))
+    }
+
+    /// Checks the backing store is reachable; always Ok when purely in-memory.
+    pub async fn ping(&self) -> Result<(), StoreError> {
+        #[cfg(feature = "postgres")]
+        if let Some(ref pg) = self.postgres_backend {
+            pg.ping().await?;
+        }
+        #[cfg(feature = "redis")]
+        {
+            self.ping_redis().await
+        }
+        #[cfg(not(feature = "redis"))]
+        {
+            Ok(())
+        }
+    }
+
+    /// Logs a failed Postgres write-through; the in-memory write stands.
+    #[cfg(feature = "postgres")]
+    fn postgres_write_through(&self, result: Result<(), StoreError>, what: &str) {
+        match result {
+            Ok(()) => {
+                if !self.postgres_healthy.swap(true, Ordering::Relaxed) {
+                    info!(op = what, "postgres write-through recovered");
+                }
+            }
+            Err(e) => {
+                if self.postgres_healthy.swap(false, Ordering::Relaxed) {
+                    warn!(op = what, error = %e, "postgres write-through failing — running on in-memory state");
+                }
+                warn!(op = what, error = %e, "postgres write-through failed");
+            }
+        }
+    }
+
+    /// Applies the degradation policy to a write-through result.
+    #[cfg(feature = "redis")]
+    fn redis_write_through(&self, result: Result<(), StoreError>, what: &str) -> Result<(), StoreError> {
+        match result {
+            Ok(()) => {
+                if !self.redis_healthy.swap(true, Ordering::Relaxed) {
+                    info!(op = what, "redis write-through recovered");
+                }
+                Ok(())
+            }
+            Err(e) => {
+                if self.redis_healthy.swap(false, Ordering::Relaxed) {
+                    warn!(op = what, error = %e, "redis write-through failing — running on in-memory state");
+                }
+                if self.redis_degrade_on_error {
+                    warn!(op = what, error = %e, "redis write-through failed");
+                    Ok(())
+                } else {
+                    Err(e)
+                }
+            }
+        }
+    }
+
+    /// Active storage backend, for `/status`.
+    pub fn backend_name(&self) -> &'static str {
+        #[cfg(feature = "postgres")]
+        if self.postgres_backend.is_some() {
+            return if self.postgres_healthy.load(Ordering::Relaxed) {
+                "postgres"
+            } else {
+                "postgres_degraded"
+            };
+        }
+        #[cfg(feature = "redis")]
+        if self.redis_backend.is_some() {
+            return if self.redis_healthy.load(Ordering::Relaxed) {
+                "redis"
+            } else {
+                "redis_degraded"
+            };
+        }
+        "memory"
+    }
+
+    /// Load all active batches and notes from Postgres into the in-memory maps.
+    /// Call this once at startup before accepting requests.
+    #[cfg(feature = "postgres")]
+    pub async fn load_from_postgres(&self) -> Result<(usize, usize), StoreError> {
+        let Some(ref pg) = self.postgres_backend else {
+            return Ok((0, 0));
+        };
+        let (batches, notes) = pg.load_active().await?;
+        for rec in &batches {
+            self.batches.insert(rec.id.clone(), self.hold_batch(rec)?);
+        }
+        for (commitment, rec) in &notes {
+            self.notes.insert(commitment.clone(), self.hold_note(rec)?);
+        }
+        Ok((batches.len(), notes.len()))
+    }
+
     /// Load all active batches and notes from Redis into the in-memory maps.
     /// Call this once at startup before accepting requests.
     #[cfg(feature = "redis")]
@@ -331,7 +927,7 @@ impl InMemoryStore {
                     // Only load active batches (skip finalized/failed)
                     if matches!(rec.status, BatchStatus::Pending | BatchStatus::Proving | BatchStatus::Submitting) {
                         let id = key.strip_prefix("batch:").unwrap_or(key);
-                        self.batches.insert(id.to_string(), rec);
+                        self.batches.insert(id.to_string(), self.hold_batch(&rec)?);
                         batch_count += 1;
                     }
                 }
@@ -355,81 +951,434 @@ impl InMemoryStore {
             if let Some(json) = val {
                 if let Ok(rec) = serde_json::from_str::<NoteRecord>(&json) {
                     let commitment = key.strip_prefix("note:").unwrap_or(key);
-                    self.notes.insert(commitment.to_string(), rec);
+                    self.notes.insert(commitment.to_string(), self.hold_note(&rec)?);
                     note_count += 1;
                 }
             }
         }
 
+        // Owner index sets, for deployments with VM31_NOTE_OWNER_INDEX
+        let owner_keys: Vec<String> = redis::cmd("KEYS")
+            .arg(format!("{OWNER_INDEX_PREFIX}*"))
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| StoreError::Backend(format!("redis KEYS {OWNER_INDEX_PREFIX}* : {e}")))?;
+        for key in &owner_keys {
+            let Some(owner) = key.strip_prefix(OWNER_INDEX_PREFIX).and_then(parse_pubkey) else {
+                continue;
+            };
+            let mut commitments: Vec<String> = redis::cmd("SMEMBERS")
+                .arg(key)
+                .query_async(&mut conn)
+                .await
+                .map_err(|e| StoreError::Backend(e.to_string()))?;
+            commitments.sort_unstable();
+            self.owner_notes.insert(owner, commitments);
+        }
+
+        // Dead letters are kept until replayed, whatever their batch status
+        let dlq_keys: Vec<String> = redis::cmd("KEYS")
+            .arg("dlq:*")
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| StoreError::Backend(format!("redis KEYS dlq:* : {e}")))?;
+        for key in &dlq_keys {
+            let val: Option<String> = redis::cmd("GET")
+                .arg(key)
+                .query_async(&mut conn)
+                .await
+                .map_err(|e| StoreError::Backend(e.to_string()))?;
+            let Some(value) = val else { continue };
+            match self.dead_letter_from_value(&value) {
+                Ok(held) => {
+                    self.dead_letters.insert(held.summary.batch_id.clone(), held);
+                }
+                Err(e) => warn!(key = %key, error = %e, "skipping unreadable dead letter in redis"),
+            }
+        }
+        if !self.dead_letters.is_empty() {
+            info!(count = self.dead_letters.len(), "dead-lettered batches restored from redis");
+        }
+
         Ok((batch_count, note_count))
     }
 
     /// Spawns a background task that periodically evicts expired entries.
-    pub fn spawn_eviction_task(self: &Arc<Self>) {
+    ///
+    /// A pass evicting at least `pressure_threshold` entries is logged at info
+    /// as a memory-pressure signal; smaller passes stay at debug.
+    pub fn spawn_eviction_task(self: &Arc<Self>, pressure_threshold: usize) {
         let store = Arc::clone(self);
         tokio::spawn(async move {
             let mut interval = tokio::time::interval(Duration::from_secs(300));
             loop {
                 interval.tick().await;
-                store.evict_expired();
+                let summary = store.evict_expired();
+                if summary.total() >= pressure_threshold {
+                    info!(
+                        evicted_idem = summary.idempotency,
+                        evicted_rl = summary.rate_limits,
+                        evicted_batches = summary.batches,
+                        evicted_inflight = summary.inflight,
+                        idempotency_entries = store.idempotency.len(),
+                        rate_limit_entries = store.rate_limits.len(),
+                        batch_entries = store.batches.len(),
+                        "store under pressure: large eviction pass"
+                    );
+                }
             }
         });
     }
 
-    fn evict_expired(&self) {
-        let now = now_epoch();
-
-        // Evict expired idempotency entries
-        let before = self.idempotency.len();
-        self.idempotency.retain(|_, (_, created)| {
-            now.saturating_sub(*created) < IDEMPOTENCY_TTL_SECS
-        });
-        let evicted_idem = before - self.idempotency.len();
-
-        // Evict expired rate limit entries
-        let before = self.rate_limits.len();
-        self.rate_limits.retain(|_, (_, window_start)| {
-            now.saturating_sub(*window_start) < RATE_LIMIT_EVICTION_SECS
-        });
-        let evicted_rl = before - self.rate_limits.len();
-
-        // Evict old finalized/failed batches (>24h)
-        let before = self.batches.len();
-        self.batches.retain(|_, rec| {
-            matches!(rec.status, BatchStatus::Pending | BatchStatus::Proving | BatchStatus::Submitting)
-                || now.saturating_sub(rec.created_at) < 86400
-        });
-        let evicted_batches = before - self.batches.len();
-
-        if evicted_idem + evicted_rl + evicted_batches > 0 {
-            debug!(
-                evicted_idem, evicted_rl, evicted_batches,
-                "store eviction complete"
-            );
-        }
+    /// Result of the most recent eviction pass, if one has run.
+    pub fn last_eviction(&self) -> Option<EvictionSummary> {
+        self.last_eviction.lock().ok().and_then(|g| g.clone())
     }
-}
 
-impl BatchStore for InMemoryStore {
-    async fn save_batch(&self, id: &str, batch: &BatchRecord) -> Result<(), StoreError> {
-        self.batches.insert(id.to_string(), batch.clone());
-        // Write-through to Redis for crash recovery
-        #[cfg(feature = "redis")]
-        if let Some(ref redis) = self.redis_backend {
-            if let Err(e) = BatchStore::save_batch(redis, id, batch).await {
-                warn!(batch_id = id, error = %e, "redis write-through failed for batch save");
+    /// Bumps the backfill backoff metadata of a note that is still pending.
+    /// No-op if the note was filled (or removed) in the meantime.
+    pub async fn record_backfill_miss(&self, commitment: &str, now: u64) -> Result<(), StoreError> {
+        let updated = match self.notes.get(commitment) {
+            Some(entry) if entry.pending => {
+                let mut note = NoteRecord::clone(&self.unhold(&entry.record)?);
+                note.backfill_attempts = note.backfill_attempts.saturating_add(1);
+                note.last_backfill_attempt = now;
+                note
             }
-        }
-        Ok(())
+            _ => return Ok(()),
+        };
+        self.save_note(commitment, &updated).await
     }
 
-    async fn get_batch(&self, id: &str) -> Result<Option<BatchRecord>, StoreError> {
-        Ok(self.batches.get(id).map(|r| r.value().clone()))
+    /// Like `get_note`, but shares the stored record instead of copying it.
+    pub fn get_note_shared(&self, commitment: &str) -> Result<Option<Arc<NoteRecord>>, StoreError> {
+        let Some(entry) = self.notes.get(commitment) else {
+            return Ok(None);
+        };
+        self.unhold(&entry.record).map(Some).map_err(|e| {
+            warn!(commitment = commitment, error = %e, "failed to decrypt note");
+            e
+        })
     }
 
-    async fn update_status(
+    /// Like `list_pending_notes`, but shares the stored records. Notes that
+    /// fail to decrypt are logged and skipped.
+    pub fn pending_notes_shared(&self) -> Vec<Arc<NoteRecord>> {
+        self.notes
+            .iter()
+            .filter(|entry| entry.pending)
+            .filter_map(|entry| match self.unhold(&entry.record) {
+                Ok(note) => Some(note),
+                Err(e) => {
+                    warn!(commitment = entry.key().as_str(), error = %e, "failed to decrypt note");
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Like `list_pending_notes_page`, but shares the stored records. Pages
+    /// run in commitment order and the cursor is the last commitment
+    /// returned, so notes leaving the pending set between pages don't shift
+    /// later ones. Only the page's own records are decrypted.
+    pub fn pending_notes_page_shared(
         &self,
-        id: &str,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> (Vec<Arc<NoteRecord>>, Option<String>) {
+        let limit = limit.max(1);
+        let mut keys: Vec<String> = self
+            .notes
+            .iter()
+            .filter(|entry| entry.pending && cursor.map_or(true, |c| entry.key().as_str() > c))
+            .map(|entry| entry.key().clone())
+            .collect();
+        keys.sort_unstable();
+        let next = (keys.len() > limit).then(|| keys[limit - 1].clone());
+        keys.truncate(limit);
+        let notes = keys
+            .iter()
+            .filter_map(|key| {
+                let entry = self.notes.get(key)?;
+                match self.unhold(&entry.record) {
+                    Ok(note) => Some(note),
+                    Err(e) => {
+                        warn!(commitment = key.as_str(), error = %e, "failed to decrypt note");
+                        None
+                    }
+                }
+            })
+            .collect();
+        (notes, next)
+    }
+
+    fn hold_batch(&self, record: &BatchRecord) -> Result<HeldBatch, StoreError> {
+        Ok(HeldBatch {
+            status: record.status.clone(),
+            created_at: record.created_at,
+            record: self.hold(record)?,
+        })
+    }
+
+    fn hold_note(&self, record: &NoteRecord) -> Result<HeldNote, StoreError> {
+        Ok(HeldNote {
+            pending: record.merkle_root == [0; 8],
+            record: self.hold(record)?,
+        })
+    }
+
+    fn hold_dead_letter(&self, entry: DeadLetter) -> Result<HeldDeadLetter, StoreError> {
+        let summary = entry.summary();
+        let record = match &self.storage_encryption {
+            Some(enc) => Held::Sealed(enc.seal(&entry)?),
+            None => Held::Plain(Arc::new(entry)),
+        };
+        Ok(HeldDeadLetter { summary, record })
+    }
+
+    /// A held dead letter as written to Redis: JSON, or the sealed record
+    /// hex-encoded when storage encryption is on.
+    #[cfg(feature = "redis")]
+    fn dead_letter_value(&self, held: &HeldDeadLetter) -> Result<String, StoreError> {
+        match &held.record {
+            Held::Plain(entry) => serde_json::to_string(&**entry).map_err(|e| StoreError::Backend(e.to_string())),
+            Held::Sealed(data) => Ok(hex::encode(data)),
+        }
+    }
+
+    /// Reads back a `dead_letter_value`.
+    #[cfg(feature = "redis")]
+    fn dead_letter_from_value(&self, value: &str) -> Result<HeldDeadLetter, StoreError> {
+        let entry: DeadLetter = match &self.storage_encryption {
+            Some(enc) => enc.open(&hex::decode(value).map_err(|e| StoreError::Backend(format!("dead letter hex: {e}")))?)?,
+            None => serde_json::from_str(value).map_err(|e| StoreError::Backend(e.to_string()))?,
+        };
+        self.hold_dead_letter(entry)
+    }
+
+    /// Wraps a record for the in-memory maps, sealing it when storage
+    /// encryption is on.
+    fn hold<T: Serialize + Clone>(&self, record: &T) -> Result<Held<T>, StoreError> {
+        match &self.storage_encryption {
+            Some(enc) => enc.seal(record).map(Held::Sealed),
+            None => Ok(Held::Plain(Arc::new(record.clone()))),
+        }
+    }
+
+    fn unhold<T: DeserializeOwned>(&self, held: &Held<T>) -> Result<Arc<T>, StoreError> {
+        match (held, &self.storage_encryption) {
+            (Held::Plain(record), _) => Ok(Arc::clone(record)),
+            (Held::Sealed(data), Some(enc)) => enc.open(data).map(Arc::new),
+            (Held::Sealed(_), None) => Err(StoreError::Backend("sealed record but no storage key".into())),
+        }
+    }
+
+    /// Records which batch a scoped client_ref landed in.
+    pub fn index_client_ref(&self, scoped_ref: &str, batch_id: &str) {
+        self.client_refs
+            .insert(scoped_ref.to_string(), batch_id.to_string());
+    }
+
+    /// Returns the batch id for a scoped client_ref, if indexed.
+    pub fn find_batch_by_client_ref(&self, scoped_ref: &str) -> Option<String> {
+        self.client_refs.get(scoped_ref).map(|r| r.value().clone())
+    }
+
+    /// At-rest encryption layer, when `VM31_STORAGE_KEY` is configured.
+    pub fn storage_encryption(&self) -> Option<&StorageEncryption> {
+        self.storage_encryption.as_ref()
+    }
+
+    /// True when no batches or notes are held (a fresh store).
+    pub fn has_no_records(&self) -> bool {
+        self.batches.is_empty() && self.notes.is_empty()
+    }
+
+    /// Batch ids, for snapshot export (records are fetched one at a time).
+    pub fn batch_ids(&self) -> Vec<String> {
+        self.batches.iter().map(|e| e.key().clone()).collect()
+    }
+
+    /// Note commitments, for snapshot export.
+    pub fn note_commitments(&self) -> Vec<String> {
+        self.notes.iter().map(|e| e.key().clone()).collect()
+    }
+
+    /// Live idempotency entries as `(key, result, created_epoch)`.
+    pub fn idempotency_entries(&self) -> Vec<(String, String, u64)> {
+        let now = now_epoch();
+        self.idempotency
+            .iter()
+            .filter(|e| now.saturating_sub(e.value().1) < IDEMPOTENCY_TTL_SECS)
+            .map(|e| (e.key().clone(), e.value().0.clone(), e.value().1))
+            .collect()
+    }
+
+    /// Restores an idempotency entry with its original creation time, so it
+    /// expires when it would have in the exporting store.
+    pub fn restore_idempotency(&self, key: &str, result: &str, created_at: u64) {
+        self.idempotency
+            .insert(key.to_string(), (result.to_string(), created_at));
+    }
+
+    /// `(scoped client_ref, batch id)` pairs.
+    pub fn client_ref_entries(&self) -> Vec<(String, String)> {
+        self.client_refs
+            .iter()
+            .map(|e| (e.key().clone(), e.value().clone()))
+            .collect()
+    }
+
+    /// Status changes of all batches from now on.
+    pub fn subscribe_batch_events(&self) -> broadcast::Receiver<BatchEvent> {
+        self.batch_events.subscribe()
+    }
+
+    fn publish_batch_event(&self, id: &str, status: &BatchStatus) {
+        // Errs only when nobody is subscribed
+        let _ = self.batch_events.send(BatchEvent {
+            batch_id: id.to_string(),
+            status: status.clone(),
+        });
+    }
+
+    /// Try to take one of `limit` concurrent in-flight slots for `key`.
+    /// Returns `None` if the key already has `limit` requests in flight.
+    /// The slot is released when the returned permit is dropped.
+    pub fn try_acquire_inflight(&self, key: &str, limit: usize) -> Option<OwnedSemaphorePermit> {
+        let sem = self
+            .inflight
+            .entry(key.to_string())
+            .or_insert_with(|| Arc::new(Semaphore::new(limit)))
+            .value()
+            .clone();
+        sem.try_acquire_owned().ok()
+    }
+
+    /// Records a withdrawal binding for `window_secs` from `now`. Returns
+    /// `false` if it was already recorded and hasn't expired.
+    pub fn check_and_record_binding(&self, key: &str, window_secs: u64, now: u64) -> bool {
+        use dashmap::mapref::entry::Entry;
+        match self.recent_bindings.entry(key.to_string()) {
+            Entry::Occupied(occ) if *occ.get() > now => false,
+            Entry::Occupied(mut occ) => {
+                occ.insert(now + window_secs);
+                true
+            }
+            Entry::Vacant(vac) => {
+                vac.insert(now + window_secs);
+                true
+            }
+        }
+    }
+
+    /// Forgets a binding recorded by `check_and_record_binding`.
+    pub fn forget_binding(&self, key: &str) {
+        self.recent_bindings.remove(key);
+    }
+
+    fn evict_expired(&self) -> EvictionSummary {
+        let now = now_epoch();
+
+        // Evict expired idempotency entries
+        let before = self.idempotency.len();
+        self.idempotency.retain(|_, (_, created)| {
+            now.saturating_sub(*created) < IDEMPOTENCY_TTL_SECS
+        });
+        let evicted_idem = before - self.idempotency.len();
+        let before = self.recent_bindings.len();
+        self.recent_bindings.retain(|_, expires| *expires > now);
+        let evicted_idem = evicted_idem + before - self.recent_bindings.len();
+
+        // Evict expired rate limit entries
+        let before = self.rate_limits.len();
+        self.rate_limits.retain(|_, (_, window_start)| {
+            now.saturating_sub(*window_start) < RATE_LIMIT_EVICTION_SECS
+        });
+        let evicted_rl = before - self.rate_limits.len();
+        let before = self.token_buckets.len();
+        self.token_buckets.retain(|_, (_, last_refill)| {
+            last_refill.elapsed() < Duration::from_secs(RATE_LIMIT_EVICTION_SECS)
+        });
+        let evicted_rl = evicted_rl + before - self.token_buckets.len();
+        // Volume totals from before yesterday can't be read again
+        let today = utc_day(now);
+        let before = self.volumes.len();
+        self.volumes.retain(|_, (day, _)| *day + 1 >= today);
+        let evicted_rl = evicted_rl + before - self.volumes.len();
+
+        // Evict old finalized/failed batches (>24h)
+        let before = self.batches.len();
+        self.batches.retain(|_, held| {
+            matches!(held.status, BatchStatus::Pending | BatchStatus::Proving | BatchStatus::Submitting)
+                || now.saturating_sub(held.created_at) < 86400
+        });
+        let evicted_batches = before - self.batches.len();
+        self.client_refs
+            .retain(|_, batch_id| self.batches.contains_key(batch_id));
+
+        // Drop idle in-flight semaphores. Outstanding permits hold a clone of
+        // the Arc, so a strong count of 1 means no request is in flight.
+        let before = self.inflight.len();
+        self.inflight.retain(|_, sem| Arc::strong_count(sem) > 1);
+        let evicted_inflight = before - self.inflight.len();
+
+        if evicted_idem + evicted_rl + evicted_batches + evicted_inflight > 0 {
+            debug!(
+                evicted_idem, evicted_rl, evicted_batches, evicted_inflight,
+                "store eviction complete"
+            );
+        }
+
+        let summary = EvictionSummary {
+            idempotency: evicted_idem,
+            rate_limits: evicted_rl,
+            batches: evicted_batches,
+            inflight: evicted_inflight,
+            at: now,
+        };
+        self.eviction_metrics.record(&summary);
+        if let Ok(mut last) = self.last_eviction.lock() {
+            *last = Some(summary.clone());
+        }
+        summary
+    }
+}
+
+impl BatchStore for InMemoryStore {
+    async fn save_batch(&self, id: &str, batch: &BatchRecord) -> Result<(), StoreError> {
+        #[cfg(test)]
+        if self
+            .failing_batch_saves
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1))
+            .is_ok()
+        {
+            return Err(StoreError::Backend("injected save failure".into()));
+        }
+        self.batches.insert(id.to_string(), self.hold_batch(batch)?);
+        // Write-through to Redis for crash recovery
+        #[cfg(feature = "redis")]
+        if let Some(ref redis) = self.redis_backend {
+            self.redis_write_through(BatchStore::save_batch(redis, id, batch).await, "batch_save")?;
+        }
+        #[cfg(feature = "postgres")]
+        if let Some(ref pg) = self.postgres_backend {
+            self.postgres_write_through(BatchStore::save_batch(pg, id, batch).await, "batch_save");
+        }
+        self.publish_batch_event(id, &batch.status);
+        Ok(())
+    }
+
+    async fn get_batch(&self, id: &str) -> Result<Option<BatchRecord>, StoreError> {
+        self.batches
+            .get(id)
+            .map(|entry| self.unhold(&entry.record).map(Arc::unwrap_or_clone))
+            .transpose()
+    }
+
+    async fn update_status(
+        &self,
+        id: &str,
         status: BatchStatus,
         extra: StatusUpdate,
     ) -> Result<(), StoreError> {
@@ -437,7 +1386,7 @@ impl BatchStore for InMemoryStore {
             .batches
             .get_mut(id)
             .ok_or_else(|| StoreError::NotFound(id.into()))?;
-        let rec = entry.value_mut();
+        let mut rec = Arc::unwrap_or_clone(self.unhold(&entry.record)?);
         rec.status = status.clone();
         if let Some(v) = extra.proof_hash.clone() {
             rec.proof_hash = Some(v);
@@ -451,15 +1400,44 @@ impl BatchStore for InMemoryStore {
         if let Some(v) = extra.error.clone() {
             rec.error = Some(v);
         }
+        *entry = self.hold_batch(&rec)?;
         // Write-through to Redis for crash recovery
         #[cfg(feature = "redis")]
         if let Some(ref redis) = self.redis_backend {
-            if let Err(e) = BatchStore::update_status(redis, id, status, extra).await {
-                warn!(batch_id = id, error = %e, "redis write-through failed for status update");
-            }
+            self.redis_write_through(
+                BatchStore::update_status(redis, id, status.clone(), extra.clone()).await,
+                "status_update",
+            )?;
+        }
+        // The full record, so a row missed while Postgres was down is recreated
+        #[cfg(feature = "postgres")]
+        if let Some(ref pg) = self.postgres_backend {
+            self.postgres_write_through(BatchStore::save_batch(pg, id, &rec).await, "status_update");
         }
+        self.publish_batch_event(id, &status);
         Ok(())
     }
+
+    async fn list_batches(
+        &self,
+        status: Option<BatchStatus>,
+        cursor: Option<&BatchCursor>,
+        limit: usize,
+    ) -> Result<Vec<BatchRecord>, StoreError> {
+        let mut batches = Vec::new();
+        for entry in self.batches.iter() {
+            if status.as_ref().is_some_and(|s| entry.status != *s) {
+                continue;
+            }
+            let rec = Arc::unwrap_or_clone(self.unhold(&entry.record)?);
+            if cursor.map_or(true, |c| c.precedes(&rec)) {
+                batches.push(rec);
+            }
+        }
+        batches.sort_unstable_by(|a, b| (b.created_at, &b.id).cmp(&(a.created_at, &a.id)));
+        batches.truncate(limit);
+        Ok(batches)
+    }
 }
 
 impl IdempotencyStore for InMemoryStore {
@@ -499,10 +1477,46 @@ impl IdempotencyStore for InMemoryStore {
 
         Ok(outcome)
     }
+
+    async fn get_result(&self, key: &str) -> Result<Option<String>, StoreError> {
+        let now = now_epoch();
+        Ok(self
+            .idempotency
+            .get(key)
+            .filter(|e| now.saturating_sub(e.1) < IDEMPOTENCY_TTL_SECS)
+            .map(|e| e.0.clone()))
+    }
+
+    async fn update_result(&self, key: &str, result: &str) -> Result<(), StoreError> {
+        let now = now_epoch();
+        if let Some(mut entry) = self.idempotency.get_mut(key) {
+            if now.saturating_sub(entry.1) < IDEMPOTENCY_TTL_SECS {
+                entry.0 = result.to_string();
+            }
+        }
+        Ok(())
+    }
+
+    async fn release(&self, key: &str) -> Result<(), StoreError> {
+        self.idempotency.remove(key);
+        Ok(())
+    }
 }
 
 impl RateLimitStore for InMemoryStore {
-    async fn check_rate(&self, key: &str, limit: u32, window_secs: u64) -> Result<bool, StoreError> {
+    async fn check_rate_n(
+        &self,
+        key: &str,
+        cost: u32,
+        limit: u32,
+        window_secs: u64,
+    ) -> Result<RateDecision, StoreError> {
+        if let RateLimitAlgo::TokenBucket { burst } = self.rate_limit_algo {
+            let (capacity, rate) = RateLimitAlgo::bucket(burst, limit, window_secs);
+            let now = Instant::now();
+            let mut bucket = self.token_buckets.entry(key.to_string()).or_insert((capacity, now));
+            return Ok(take_tokens(&mut bucket, f64::from(cost), capacity, rate, now));
+        }
         let now = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .unwrap_or_default()
@@ -520,61 +1534,196 @@ impl RateLimitStore for InMemoryStore {
             *window_start = now;
         }
 
-        if *count >= limit {
-            return Ok(false);
+        let retry_after_secs = (*window_start + window_secs).saturating_sub(now).max(1);
+        if count.saturating_add(cost) > limit {
+            return Ok(RateDecision { allowed: false, retry_after_secs });
         }
-        *count += 1;
-        Ok(true)
+        *count += cost;
+        Ok(RateDecision { allowed: true, retry_after_secs })
     }
 }
 
-impl NoteStore for InMemoryStore {
-    async fn save_note(&self, commitment: &str, record: &NoteRecord) -> Result<(), StoreError> {
-        // If storage encryption is enabled, encrypt the record at rest
-        if let Some(ref enc) = self.storage_encryption {
-            let ciphertext = enc.encrypt_note(commitment, record)?;
-            self.encrypted_notes.insert(commitment.to_string(), ciphertext);
+impl VolumeStore for InMemoryStore {
+    async fn check_and_add_volume(
+        &self,
+        key: &str,
+        day: u64,
+        amount: u64,
+        cap: u64,
+    ) -> Result<bool, StoreError> {
+        let mut entry = self.volumes.entry(key.to_string()).or_insert((day, 0));
+        let (entry_day, total) = entry.value_mut();
+        if *entry_day != day {
+            *entry_day = day;
+            *total = 0;
+        }
+        match total.checked_add(amount) {
+            Some(next) if next <= cap => {
+                *total = next;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn subtract_volume(&self, key: &str, day: u64, amount: u64) -> Result<(), StoreError> {
+        if let Some(mut entry) = self.volumes.get_mut(key) {
+            let (entry_day, total) = entry.value_mut();
+            if *entry_day == day {
+                *total = total.saturating_sub(amount);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl OwnerIndexStore for InMemoryStore {
+    async fn index_note_owner(&self, owner: &[u32; 4], commitment: &str) -> Result<(), StoreError> {
+        {
+            let mut keys = self.owner_notes.entry(*owner).or_default();
+            if !keys.iter().any(|k| k == commitment) {
+                keys.push(commitment.to_string());
+            }
         }
-        // Always store in plaintext map too (for in-memory operations)
-        self.notes.insert(commitment.to_string(), record.clone());
-        // Write-through to Redis for crash recovery
         #[cfg(feature = "redis")]
         if let Some(ref redis) = self.redis_backend {
-            if let Err(e) = NoteStore::save_note(redis, commitment, record).await {
-                warn!(commitment = commitment, error = %e, "redis write-through failed for note save");
-            }
+            self.redis_write_through(redis.index_note_owner(owner, commitment).await, "owner_index")?;
         }
         Ok(())
     }
 
-    async fn get_note(&self, commitment: &str) -> Result<Option<NoteRecord>, StoreError> {
-        // Try plaintext first (fast path)
-        if let Some(r) = self.notes.get(commitment) {
-            return Ok(Some(r.value().clone()));
-        }
-        // Fallback: try decrypting from encrypted store
-        if let Some(ref enc) = self.storage_encryption {
-            if let Some(ct) = self.encrypted_notes.get(commitment) {
-                match enc.decrypt_note(commitment, ct.value()) {
-                    Ok(record) => return Ok(Some(record)),
+    async fn notes_by_owner(&self, owner: &[u32; 4]) -> Result<Vec<String>, StoreError> {
+        Ok(self.owner_notes.get(owner).map(|keys| keys.clone()).unwrap_or_default())
+    }
+}
+
+impl NonceGuardStore for InMemoryStore {
+    async fn check_and_record_nonce(
+        &self,
+        pair_key: &str,
+        ttl_secs: u64,
+        max_entries: usize,
+    ) -> Result<bool, StoreError> {
+        // Durable check first: a pair seen before a restart is still a replay.
+        #[cfg(feature = "redis")]
+        if self.nonce_guard_persistent {
+            if let Some(ref redis) = self.redis_backend {
+                match NonceGuardStore::check_and_record_nonce(redis, pair_key, ttl_secs, max_entries)
+                    .await
+                {
+                    Ok(false) => return Ok(false),
+                    Ok(true) => {}
                     Err(e) => {
-                        warn!(commitment = commitment, error = %e, "failed to decrypt note");
-                        return Err(e);
+                        warn!(error = %e, "redis nonce guard unavailable, using in-memory guard only");
                     }
                 }
             }
         }
-        Ok(None)
+
+        let mut seen = self.seen_nonces.lock().unwrap_or_else(|p| p.into_inner());
+        Ok(seen.check_and_record(pair_key, now_epoch(), ttl_secs, max_entries))
+    }
+
+    async fn forget_nonce(&self, pair_key: &str) -> Result<(), StoreError> {
+        self.seen_nonces.lock().unwrap_or_else(|p| p.into_inner()).forget(pair_key);
+        #[cfg(feature = "redis")]
+        if self.nonce_guard_persistent {
+            if let Some(ref redis) = self.redis_backend {
+                redis.forget_nonce(pair_key).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl NoteStore for InMemoryStore {
+    async fn save_note(&self, commitment: &str, record: &NoteRecord) -> Result<(), StoreError> {
+        // Sealed if storage encryption is enabled
+        self.notes.insert(commitment.to_string(), self.hold_note(record)?);
+        // Write-through to Redis for crash recovery
+        #[cfg(feature = "redis")]
+        if let Some(ref redis) = self.redis_backend {
+            self.redis_write_through(NoteStore::save_note(redis, commitment, record).await, "note_save")?;
+        }
+        #[cfg(feature = "postgres")]
+        if let Some(ref pg) = self.postgres_backend {
+            self.postgres_write_through(NoteStore::save_note(pg, commitment, record).await, "note_save");
+        }
+        Ok(())
+    }
+
+    async fn get_note(&self, commitment: &str) -> Result<Option<NoteRecord>, StoreError> {
+        Ok(self.get_note_shared(commitment)?.map(Arc::unwrap_or_clone))
     }
 
     async fn list_pending_notes(&self) -> Result<Vec<NoteRecord>, StoreError> {
         Ok(self
-            .notes
-            .iter()
-            .filter(|entry| entry.value().merkle_root == [0; 8])
-            .map(|entry| entry.value().clone())
+            .pending_notes_shared()
+            .into_iter()
+            .map(Arc::unwrap_or_clone)
             .collect())
     }
+
+    async fn list_pending_notes_page(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<(Vec<NoteRecord>, Option<String>), StoreError> {
+        let (notes, next) = self.pending_notes_page_shared(cursor, limit);
+        Ok((notes.into_iter().map(Arc::unwrap_or_clone).collect(), next))
+    }
+}
+
+impl DeadLetterStore for InMemoryStore {
+    async fn save_dead_letter(&self, entry: DeadLetter) -> Result<(), StoreError> {
+        if self.dead_letters.len() >= MAX_DEAD_LETTERS && !self.dead_letters.contains_key(&entry.batch_id) {
+            return Err(StoreError::Backend(format!(
+                "dead-letter queue full ({MAX_DEAD_LETTERS} batches)"
+            )));
+        }
+        // Sealed when storage encryption is enabled, here and in Redis
+        let held = self.hold_dead_letter(entry)?;
+        // Kept in memory even if the write-through fails
+        #[cfg(feature = "redis")]
+        let result = match self.redis_backend {
+            Some(ref redis) => {
+                let write = match self.dead_letter_value(&held) {
+                    Ok(value) => redis.put_dead_letter(&held.summary.batch_id, &value).await,
+                    Err(e) => Err(e),
+                };
+                self.redis_write_through(write, "dlq_save")
+            }
+            None => Ok(()),
+        };
+        self.dead_letters.insert(held.summary.batch_id.clone(), held);
+        #[cfg(feature = "redis")]
+        result?;
+        Ok(())
+    }
+
+    async fn list_dead_letters(&self) -> Result<Vec<DeadLetterSummary>, StoreError> {
+        let mut list: Vec<_> = self.dead_letters.iter().map(|e| e.summary.clone()).collect();
+        list.sort_unstable_by(|a, b| (b.failed_at, &b.batch_id).cmp(&(a.failed_at, &a.batch_id)));
+        Ok(list)
+    }
+
+    async fn take_dead_letter(&self, batch_id: &str) -> Result<Option<DeadLetter>, StoreError> {
+        let Some((_, held)) = self.dead_letters.remove(batch_id) else {
+            return Ok(None);
+        };
+        let entry = match (held.record, &self.storage_encryption) {
+            // Never cloned out of the map, so this is the only reference
+            (Held::Plain(entry), _) => Arc::try_unwrap(entry)
+                .map_err(|_| StoreError::Backend("dead letter still shared".into()))?,
+            (Held::Sealed(data), Some(enc)) => enc.open(&data)?,
+            (Held::Sealed(_), None) => return Err(StoreError::Backend("sealed record but no storage key".into())),
+        };
+        #[cfg(feature = "redis")]
+        if let Some(ref redis) = self.redis_backend {
+            self.redis_write_through(redis.delete_dead_letter(batch_id).await, "dlq_take")?;
+        }
+        Ok(Some(entry))
+    }
 }
 
 // ---------------------------------------------------------------------------
@@ -584,14 +1733,58 @@ impl NoteStore for InMemoryStore {
 #[cfg(feature = "redis")]
 pub struct RedisStore {
     client: redis::Client,
+    rate_limit_algo: RateLimitAlgo,
 }
 
+/// Atomic token-bucket refill-and-consume. KEYS[1] = bucket hash;
+/// ARGV = capacity, refill rate (tokens/sec), now (ms), ttl (secs).
+/// Returns `{allowed, retry_after_secs}`.
+#[cfg(feature = "redis")]
+const TOKEN_BUCKET_LUA: &str = r#"
+local capacity = tonumber(ARGV[1])
+local rate = tonumber(ARGV[2])
+local now = tonumber(ARGV[3])
+local state = redis.call('HMGET', KEYS[1], 'tokens', 'ts')
+local tokens = tonumber(state[1]) or capacity
+local ts = tonumber(state[2]) or now
+tokens = math.min(capacity, tokens + math.max(0, now - ts) / 1000 * rate)
+local cost = tonumber(ARGV[5])
+local allowed = 0
+if tokens >= cost then
+    tokens = tokens - cost
+    allowed = 1
+end
+redis.call('HSET', KEYS[1], 'tokens', tostring(tokens), 'ts', tostring(now))
+redis.call('EXPIRE', KEYS[1], ARGV[4])
+return {allowed, math.max(1, math.ceil(math.max(0, cost - tokens) / rate))}
+"#;
+
+/// Atomic capped add. KEYS[1] = day counter; ARGV = amount, cap, ttl (secs).
+/// Returns 1 and adds `amount` if the total stays within `cap`, else 0.
+#[cfg(feature = "redis")]
+const CAPPED_ADD_LUA: &str = r#"
+local total = tonumber(redis.call('GET', KEYS[1]) or '0')
+local amount = tonumber(ARGV[1])
+if total + amount > tonumber(ARGV[2]) then
+    return 0
+end
+redis.call('INCRBY', KEYS[1], ARGV[1])
+redis.call('EXPIRE', KEYS[1], ARGV[3])
+return 1
+"#;
+
 #[cfg(feature = "redis")]
 impl RedisStore {
     pub fn new(url: &str) -> Result<Self, StoreError> {
         let client =
             redis::Client::open(url).map_err(|e| StoreError::Backend(e.to_string()))?;
-        Ok(Self { client })
+        Ok(Self { client, rate_limit_algo: RateLimitAlgo::FixedWindow })
+    }
+
+    /// Sets how `check_rate` counts requests.
+    pub fn with_rate_limit_algo(mut self, algo: RateLimitAlgo) -> Self {
+        self.rate_limit_algo = algo;
+        self
     }
 
     async fn conn(&self) -> Result<redis::aio::MultiplexedConnection, StoreError> {
@@ -613,6 +1806,21 @@ impl BatchStore for RedisStore {
             .arg(&json)
             .arg("EX")
             .arg(86400u64) // 24h TTL
+            .query_async::<_, ()>(&mut conn)
+            .await
+            .map_err(|e| StoreError::Backend(e.to_string()))?;
+        // Time index for list_batches; trimmed to the batch TTL
+        redis::cmd("ZADD")
+            .arg(BATCH_INDEX_REDIS_KEY)
+            .arg(batch.created_at)
+            .arg(id)
+            .query_async::<_, ()>(&mut conn)
+            .await
+            .map_err(|e| StoreError::Backend(e.to_string()))?;
+        redis::cmd("ZREMRANGEBYSCORE")
+            .arg(BATCH_INDEX_REDIS_KEY)
+            .arg("-inf")
+            .arg(format!("({}", now_epoch().saturating_sub(86400)))
             .query_async(&mut conn)
             .await
             .map_err(|e| StoreError::Backend(e.to_string()))
@@ -660,6 +1868,38 @@ impl BatchStore for RedisStore {
         }
         self.save_batch(id, &rec).await
     }
+
+    async fn list_batches(
+        &self,
+        status: Option<BatchStatus>,
+        cursor: Option<&BatchCursor>,
+        limit: usize,
+    ) -> Result<Vec<BatchRecord>, StoreError> {
+        let mut conn = self.conn().await?;
+        // Newest first; equal scores come back in descending id order
+        let max = cursor.map_or_else(|| "+inf".to_string(), |c| c.created_at.to_string());
+        let ids: Vec<String> = redis::cmd("ZREVRANGEBYSCORE")
+            .arg(BATCH_INDEX_REDIS_KEY)
+            .arg(max)
+            .arg("-inf")
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| StoreError::Backend(e.to_string()))?;
+        let mut batches = Vec::with_capacity(limit.min(ids.len()));
+        for id in &ids {
+            if batches.len() >= limit {
+                break;
+            }
+            // Expired records can linger in the index until the next trim
+            let Some(rec) = self.get_batch(id).await? else { continue };
+            if cursor.map_or(true, |c| c.precedes(&rec))
+                && status.as_ref().map_or(true, |s| rec.status == *s)
+            {
+                batches.push(rec);
+            }
+        }
+        Ok(batches)
+    }
 }
 
 #[cfg(feature = "redis")]
@@ -688,32 +1928,217 @@ impl IdempotencyStore for RedisStore {
             Ok(existing)
         }
     }
-}
 
-#[cfg(feature = "redis")]
-impl RateLimitStore for RedisStore {
-    async fn check_rate(&self, key: &str, limit: u32, window_secs: u64) -> Result<bool, StoreError> {
+    async fn get_result(&self, key: &str) -> Result<Option<String>, StoreError> {
         let mut conn = self.conn().await?;
-        let redis_key = format!("rl:{key}");
-        let count: u32 = redis::cmd("INCR")
-            .arg(&redis_key)
+        redis::cmd("GET")
+            .arg(format!("idem:{key}"))
             .query_async(&mut conn)
             .await
-            .map_err(|e| StoreError::Backend(e.to_string()))?;
-        // Always refresh EXPIRE to prevent orphaned keys from a failed first EXPIRE.
-        // Using EXPIRE (not EXPIREAT) ensures the window extends from now.
-        let _: () = redis::cmd("EXPIRE")
-            .arg(&redis_key)
-            .arg(window_secs)
+            .map_err(|e| StoreError::Backend(e.to_string()))
+    }
+
+    async fn update_result(&self, key: &str, result: &str) -> Result<(), StoreError> {
+        let mut conn = self.conn().await?;
+        // XX: only overwrite an existing key; KEEPTTL: keep its expiry
+        let _: Option<String> = redis::cmd("SET")
+            .arg(format!("idem:{key}"))
+            .arg(result)
+            .arg("XX")
+            .arg("KEEPTTL")
             .query_async(&mut conn)
             .await
             .map_err(|e| StoreError::Backend(e.to_string()))?;
-        Ok(count <= limit)
+        Ok(())
+    }
+
+    async fn release(&self, key: &str) -> Result<(), StoreError> {
+        let mut conn = self.conn().await?;
+        redis::cmd("DEL")
+            .arg(format!("idem:{key}"))
+            .query_async::<_, ()>(&mut conn)
+            .await
+            .map_err(|e| StoreError::Backend(e.to_string()))
     }
 }
 
 #[cfg(feature = "redis")]
-impl NoteStore for RedisStore {
+impl RateLimitStore for RedisStore {
+    async fn check_rate_n(
+        &self,
+        key: &str,
+        cost: u32,
+        limit: u32,
+        window_secs: u64,
+    ) -> Result<RateDecision, StoreError> {
+        let mut conn = self.conn().await?;
+        if let RateLimitAlgo::TokenBucket { burst } = self.rate_limit_algo {
+            let (capacity, rate) = RateLimitAlgo::bucket(burst, limit, window_secs);
+            let now_ms = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64;
+            // An idle bucket is full again after capacity / rate seconds
+            let ttl = (capacity / rate).ceil() as u64 + 1;
+            let (allowed, retry_after_secs): (u32, u64) = redis::Script::new(TOKEN_BUCKET_LUA)
+                .key(format!("tb:{key}"))
+                .arg(capacity)
+                .arg(rate)
+                .arg(now_ms)
+                .arg(ttl)
+                .arg(cost)
+                .invoke_async(&mut conn)
+                .await
+                .map_err(|e| StoreError::Backend(e.to_string()))?;
+            return Ok(RateDecision { allowed: allowed == 1, retry_after_secs });
+        }
+        let redis_key = format!("rl:{key}");
+        let count: u32 = redis::cmd("INCRBY")
+            .arg(&redis_key)
+            .arg(cost)
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| StoreError::Backend(e.to_string()))?;
+        // Always refresh EXPIRE to prevent orphaned keys from a failed first EXPIRE.
+        // Using EXPIRE (not EXPIREAT) ensures the window extends from now.
+        let _: () = redis::cmd("EXPIRE")
+            .arg(&redis_key)
+            .arg(window_secs)
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| StoreError::Backend(e.to_string()))?;
+        // The EXPIRE above just restarted the window
+        Ok(RateDecision { allowed: count <= limit, retry_after_secs: window_secs.max(1) })
+    }
+}
+
+#[cfg(feature = "redis")]
+impl VolumeStore for RedisStore {
+    async fn check_and_add_volume(
+        &self,
+        key: &str,
+        day: u64,
+        amount: u64,
+        cap: u64,
+    ) -> Result<bool, StoreError> {
+        let mut conn = self.conn().await?;
+        // One counter per day, kept a day past its end for late readers
+        let added: u32 = redis::Script::new(CAPPED_ADD_LUA)
+            .key(format!("vol:{key}:{day}"))
+            .arg(amount)
+            .arg(cap)
+            .arg(2 * 86_400)
+            .invoke_async(&mut conn)
+            .await
+            .map_err(|e| StoreError::Backend(e.to_string()))?;
+        Ok(added == 1)
+    }
+
+    async fn subtract_volume(&self, key: &str, day: u64, amount: u64) -> Result<(), StoreError> {
+        let mut conn = self.conn().await?;
+        redis::cmd("DECRBY")
+            .arg(format!("vol:{key}:{day}"))
+            .arg(amount)
+            .query_async::<_, ()>(&mut conn)
+            .await
+            .map_err(|e| StoreError::Backend(e.to_string()))
+    }
+}
+
+#[cfg(feature = "redis")]
+impl OwnerIndexStore for RedisStore {
+    async fn index_note_owner(&self, owner: &[u32; 4], commitment: &str) -> Result<(), StoreError> {
+        let mut conn = self.conn().await?;
+        let key = format!("{OWNER_INDEX_PREFIX}{}", pubkey_hex(owner));
+        redis::pipe()
+            .atomic()
+            .cmd("SADD")
+            .arg(&key)
+            .arg(commitment)
+            .ignore()
+            // Outlives the newest of its note records (7-day TTL)
+            .cmd("EXPIRE")
+            .arg(&key)
+            .arg(86400u64 * 7)
+            .ignore()
+            .query_async::<_, ()>(&mut conn)
+            .await
+            .map_err(|e| StoreError::Backend(e.to_string()))
+    }
+
+    async fn notes_by_owner(&self, owner: &[u32; 4]) -> Result<Vec<String>, StoreError> {
+        let mut conn = self.conn().await?;
+        let mut keys: Vec<String> = redis::cmd("SMEMBERS")
+            .arg(format!("{OWNER_INDEX_PREFIX}{}", pubkey_hex(owner)))
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| StoreError::Backend(e.to_string()))?;
+        // Sets are unordered
+        keys.sort_unstable();
+        Ok(keys)
+    }
+}
+
+#[cfg(feature = "redis")]
+impl NonceGuardStore for RedisStore {
+    async fn check_and_record_nonce(
+        &self,
+        pair_key: &str,
+        ttl_secs: u64,
+        max_entries: usize,
+    ) -> Result<bool, StoreError> {
+        let mut conn = self.conn().await?;
+        let now = now_epoch();
+        // Drop pairs older than the envelope max-age
+        let _: () = redis::cmd("ZREMRANGEBYSCORE")
+            .arg(NONCE_GUARD_REDIS_KEY)
+            .arg("-inf")
+            .arg(format!("({}", now.saturating_sub(ttl_secs)))
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| StoreError::Backend(e.to_string()))?;
+        // ZADD NX returns 1 only if the member was newly added
+        let added: u32 = redis::cmd("ZADD")
+            .arg(NONCE_GUARD_REDIS_KEY)
+            .arg("NX")
+            .arg(now)
+            .arg(pair_key)
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| StoreError::Backend(e.to_string()))?;
+        if added == 0 {
+            return Ok(false);
+        }
+        // Bound the set size: keep only the newest max_entries members
+        let _: () = redis::cmd("ZREMRANGEBYRANK")
+            .arg(NONCE_GUARD_REDIS_KEY)
+            .arg(0)
+            .arg(-(max_entries as i64) - 1)
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| StoreError::Backend(e.to_string()))?;
+        let _: () = redis::cmd("EXPIRE")
+            .arg(NONCE_GUARD_REDIS_KEY)
+            .arg(ttl_secs)
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| StoreError::Backend(e.to_string()))?;
+        Ok(true)
+    }
+
+    async fn forget_nonce(&self, pair_key: &str) -> Result<(), StoreError> {
+        let mut conn = self.conn().await?;
+        redis::cmd("ZREM")
+            .arg(NONCE_GUARD_REDIS_KEY)
+            .arg(pair_key)
+            .query_async::<_, ()>(&mut conn)
+            .await
+            .map_err(|e| StoreError::Backend(e.to_string()))
+    }
+}
+
+#[cfg(feature = "redis")]
+impl NoteStore for RedisStore {
     async fn save_note(&self, commitment: &str, record: &NoteRecord) -> Result<(), StoreError> {
         let mut conn = self.conn().await?;
         let json =
@@ -781,13 +2206,461 @@ impl NoteStore for RedisStore {
         }
         Ok(notes)
     }
+
+    async fn list_pending_notes_page(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<(Vec<NoteRecord>, Option<String>), StoreError> {
+        let mut conn = self.conn().await?;
+        // COUNT is a hint: a page may hold more or fewer members, and a
+        // member may repeat if the set is rehashed mid-scan
+        let (next, keys): (String, Vec<String>) = redis::cmd("SSCAN")
+            .arg("notes:pending")
+            .arg(cursor.unwrap_or("0"))
+            .arg("COUNT")
+            .arg(limit.max(1))
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| StoreError::Backend(e.to_string()))?;
+        let mut notes = Vec::with_capacity(keys.len());
+        for key in &keys {
+            if let Some(record) = self.get_note(key).await? {
+                if record.merkle_root == [0; 8] {
+                    notes.push(record);
+                }
+            }
+        }
+        Ok((notes, (next != "0").then_some(next)))
+    }
+}
+
+#[cfg(feature = "redis")]
+impl RedisStore {
+    /// Stores a dead letter's value as given: JSON, or a sealed record
+    /// from an `InMemoryStore` with storage encryption.
+    async fn put_dead_letter(&self, batch_id: &str, value: &str) -> Result<(), StoreError> {
+        let mut conn = self.conn().await?;
+        redis::cmd("SET")
+            .arg(format!("dlq:{batch_id}"))
+            .arg(value)
+            .arg("EX")
+            .arg(86400u64 * 7) // 7-day TTL, as for note records
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| StoreError::Backend(e.to_string()))
+    }
+
+    async fn delete_dead_letter(&self, batch_id: &str) -> Result<(), StoreError> {
+        let mut conn = self.conn().await?;
+        redis::cmd("DEL")
+            .arg(format!("dlq:{batch_id}"))
+            .query_async::<_, ()>(&mut conn)
+            .await
+            .map_err(|e| StoreError::Backend(e.to_string()))
+    }
+}
+
+#[cfg(feature = "redis")]
+impl DeadLetterStore for RedisStore {
+    async fn save_dead_letter(&self, entry: DeadLetter) -> Result<(), StoreError> {
+        let json = serde_json::to_string(&entry).map_err(|e| StoreError::Backend(e.to_string()))?;
+        self.put_dead_letter(&entry.batch_id, &json).await
+    }
+
+    async fn list_dead_letters(&self) -> Result<Vec<DeadLetterSummary>, StoreError> {
+        let mut conn = self.conn().await?;
+        let keys: Vec<String> = redis::cmd("KEYS")
+            .arg("dlq:*")
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| StoreError::Backend(e.to_string()))?;
+        let mut list = Vec::with_capacity(keys.len());
+        for key in &keys {
+            let val: Option<String> = redis::cmd("GET")
+                .arg(key)
+                .query_async(&mut conn)
+                .await
+                .map_err(|e| StoreError::Backend(e.to_string()))?;
+            if let Some(entry) = val.and_then(|json| serde_json::from_str::<DeadLetter>(&json).ok()) {
+                list.push(entry.summary());
+            }
+        }
+        list.sort_unstable_by(|a, b| (b.failed_at, &b.batch_id).cmp(&(a.failed_at, &a.batch_id)));
+        Ok(list)
+    }
+
+    async fn take_dead_letter(&self, batch_id: &str) -> Result<Option<DeadLetter>, StoreError> {
+        let mut conn = self.conn().await?;
+        let key = format!("dlq:{batch_id}");
+        let val: Option<String> = redis::cmd("GET")
+            .arg(&key)
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| StoreError::Backend(e.to_string()))?;
+        redis::cmd("DEL")
+            .arg(&key)
+            .query_async::<_, ()>(&mut conn)
+            .await
+            .map_err(|e| StoreError::Backend(e.to_string()))?;
+        val.map(|json| serde_json::from_str(&json).map_err(|e| StoreError::Backend(e.to_string())))
+            .transpose()
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Postgres implementation (feature-gated)
+// ---------------------------------------------------------------------------
+
+/// Relational backend for batches, notes, idempotency and rate limits.
+/// Records are stored as JSON (same encoding as `RedisStore`); expiry is an
+/// `expires_at` epoch column checked on read and reclaimed by `purge_expired`.
+/// The relayer uses it as a write-through layer behind `InMemoryStore`
+/// (`VM31_POSTGRES_URL`), like Redis.
+#[cfg(feature = "postgres")]
+pub struct PostgresStore {
+    pool: sqlx::PgPool,
+}
+
+#[cfg(feature = "postgres")]
+fn pg_err(e: impl std::fmt::Display) -> StoreError {
+    StoreError::Backend(format!("postgres: {e}"))
+}
+
+#[cfg(feature = "postgres")]
+impl PostgresStore {
+    /// Connects and applies pending migrations from `migrations/`.
+    pub async fn connect(url: &str) -> Result<Self, StoreError> {
+        let pool = sqlx::postgres::PgPoolOptions::new()
+            .max_connections(16)
+            .connect(url)
+            .await
+            .map_err(pg_err)?;
+        sqlx::migrate!("./migrations")
+            .run(&pool)
+            .await
+            .map_err(pg_err)?;
+        Ok(Self { pool })
+    }
+
+    /// Checks the database is reachable.
+    pub async fn ping(&self) -> Result<(), StoreError> {
+        sqlx::query("SELECT 1").execute(&self.pool).await.map(|_| ()).map_err(pg_err)
+    }
+
+    /// Active batches and every note, for hydrating `InMemoryStore` at startup.
+    pub async fn load_active(&self) -> Result<(Vec<BatchRecord>, Vec<(String, NoteRecord)>), StoreError> {
+        let batches: Vec<(serde_json::Value,)> = sqlx::query_as(
+            "SELECT record FROM vm31_batches \
+             WHERE record->>'status' IN ('pending', 'proving', 'submitting')",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(pg_err)?;
+        let notes: Vec<(String, serde_json::Value)> =
+            sqlx::query_as("SELECT commitment, record FROM vm31_notes")
+                .fetch_all(&self.pool)
+                .await
+                .map_err(pg_err)?;
+        let batches = batches
+            .into_iter()
+            .map(|(json,)| serde_json::from_value(json).map_err(|e| StoreError::Backend(e.to_string())))
+            .collect::<Result<_, _>>()?;
+        let notes = notes
+            .into_iter()
+            .map(|(commitment, json)| {
+                serde_json::from_value(json)
+                    .map(|rec| (commitment, rec))
+                    .map_err(|e| StoreError::Backend(e.to_string()))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok((batches, notes))
+    }
+
+    /// Deletes expired idempotency keys and rate-limit buckets.
+    /// Returns the number of rows removed.
+    pub async fn purge_expired(&self) -> Result<u64, StoreError> {
+        let now = now_epoch() as i64;
+        let idem = sqlx::query("DELETE FROM vm31_idempotency WHERE expires_at <= $1")
+            .bind(now)
+            .execute(&self.pool)
+            .await
+            .map_err(pg_err)?;
+        let rl = sqlx::query("DELETE FROM vm31_rate_limits WHERE expires_at <= $1")
+            .bind(now)
+            .execute(&self.pool)
+            .await
+            .map_err(pg_err)?;
+        Ok(idem.rows_affected() + rl.rows_affected())
+    }
+}
+
+#[cfg(feature = "postgres")]
+impl BatchStore for PostgresStore {
+    async fn save_batch(&self, id: &str, batch: &BatchRecord) -> Result<(), StoreError> {
+        let json = serde_json::to_value(batch).map_err(|e| StoreError::Backend(e.to_string()))?;
+        sqlx::query(
+            "INSERT INTO vm31_batches (id, record, updated_at) VALUES ($1, $2, $3) \
+             ON CONFLICT (id) DO UPDATE SET record = EXCLUDED.record, updated_at = EXCLUDED.updated_at",
+        )
+        .bind(id)
+        .bind(json)
+        .bind(now_epoch() as i64)
+        .execute(&self.pool)
+        .await
+        .map_err(pg_err)?;
+        Ok(())
+    }
+
+    async fn get_batch(&self, id: &str) -> Result<Option<BatchRecord>, StoreError> {
+        let row: Option<(serde_json::Value,)> =
+            sqlx::query_as("SELECT record FROM vm31_batches WHERE id = $1")
+                .bind(id)
+                .fetch_optional(&self.pool)
+                .await
+                .map_err(pg_err)?;
+        row.map(|(json,)| serde_json::from_value(json).map_err(|e| StoreError::Backend(e.to_string())))
+            .transpose()
+    }
+
+    async fn update_status(
+        &self,
+        id: &str,
+        status: BatchStatus,
+        extra: StatusUpdate,
+    ) -> Result<(), StoreError> {
+        // Row lock so concurrent updates don't overwrite each other's fields
+        let mut tx = self.pool.begin().await.map_err(pg_err)?;
+        let row: Option<(serde_json::Value,)> =
+            sqlx::query_as("SELECT record FROM vm31_batches WHERE id = $1 FOR UPDATE")
+                .bind(id)
+                .fetch_optional(&mut *tx)
+                .await
+                .map_err(pg_err)?;
+        let (json,) = row.ok_or_else(|| StoreError::NotFound(id.into()))?;
+        let mut rec: BatchRecord =
+            serde_json::from_value(json).map_err(|e| StoreError::Backend(e.to_string()))?;
+        rec.status = status;
+        if let Some(v) = extra.proof_hash {
+            rec.proof_hash = Some(v);
+        }
+        if let Some(v) = extra.batch_id_onchain {
+            rec.batch_id_onchain = Some(v);
+        }
+        if let Some(v) = extra.tx_hash {
+            rec.tx_hash = Some(v);
+        }
+        if let Some(v) = extra.error {
+            rec.error = Some(v);
+        }
+        let json = serde_json::to_value(&rec).map_err(|e| StoreError::Backend(e.to_string()))?;
+        sqlx::query("UPDATE vm31_batches SET record = $2, updated_at = $3 WHERE id = $1")
+            .bind(id)
+            .bind(json)
+            .bind(now_epoch() as i64)
+            .execute(&mut *tx)
+            .await
+            .map_err(pg_err)?;
+        tx.commit().await.map_err(pg_err)
+    }
+
+    async fn list_batches(
+        &self,
+        status: Option<BatchStatus>,
+        cursor: Option<&BatchCursor>,
+        limit: usize,
+    ) -> Result<Vec<BatchRecord>, StoreError> {
+        let status = status
+            .map(|s| serde_json::to_value(s).map_err(|e| StoreError::Backend(e.to_string())))
+            .transpose()?
+            .and_then(|v| v.as_str().map(String::from));
+        let rows: Vec<(serde_json::Value,)> = sqlx::query_as(
+            "SELECT record FROM vm31_batches \
+             WHERE ($1::TEXT IS NULL OR record->>'status' = $1) \
+               AND ($2::BIGINT IS NULL OR ((record->>'created_at')::BIGINT, id) < ($2, $3)) \
+             ORDER BY (record->>'created_at')::BIGINT DESC, id DESC \
+             LIMIT $4",
+        )
+        .bind(status)
+        .bind(cursor.map(|c| c.created_at as i64))
+        .bind(cursor.map(|c| c.id.clone()))
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(pg_err)?;
+        rows.into_iter()
+            .map(|(json,)| serde_json::from_value(json).map_err(|e| StoreError::Backend(e.to_string())))
+            .collect()
+    }
+}
+
+#[cfg(feature = "postgres")]
+impl IdempotencyStore for PostgresStore {
+    async fn check_and_set(&self, key: &str, result: &str) -> Result<Option<String>, StoreError> {
+        let now = now_epoch() as i64;
+        // Inserts, or replaces an expired entry; returns no row if a live entry exists
+        let set: Option<(String,)> = sqlx::query_as(
+            "INSERT INTO vm31_idempotency (key, result, expires_at) VALUES ($1, $2, $3) \
+             ON CONFLICT (key) DO UPDATE SET result = EXCLUDED.result, expires_at = EXCLUDED.expires_at \
+             WHERE vm31_idempotency.expires_at <= $4 \
+             RETURNING key",
+        )
+        .bind(key)
+        .bind(result)
+        .bind(now + IDEMPOTENCY_TTL_SECS as i64)
+        .bind(now)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(pg_err)?;
+        if set.is_some() {
+            return Ok(None);
+        }
+        let existing: Option<(String,)> =
+            sqlx::query_as("SELECT result FROM vm31_idempotency WHERE key = $1")
+                .bind(key)
+                .fetch_optional(&self.pool)
+                .await
+                .map_err(pg_err)?;
+        Ok(existing.map(|(r,)| r))
+    }
+
+    async fn get_result(&self, key: &str) -> Result<Option<String>, StoreError> {
+        let row: Option<(String,)> = sqlx::query_as(
+            "SELECT result FROM vm31_idempotency WHERE key = $1 AND expires_at > $2",
+        )
+        .bind(key)
+        .bind(now_epoch() as i64)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(pg_err)?;
+        Ok(row.map(|(r,)| r))
+    }
+
+    async fn update_result(&self, key: &str, result: &str) -> Result<(), StoreError> {
+        sqlx::query("UPDATE vm31_idempotency SET result = $2 WHERE key = $1 AND expires_at > $3")
+            .bind(key)
+            .bind(result)
+            .bind(now_epoch() as i64)
+            .execute(&self.pool)
+            .await
+            .map_err(pg_err)?;
+        Ok(())
+    }
+
+    async fn release(&self, key: &str) -> Result<(), StoreError> {
+        sqlx::query("DELETE FROM vm31_idempotency WHERE key = $1")
+            .bind(key)
+            .execute(&self.pool)
+            .await
+            .map_err(pg_err)?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "postgres")]
+impl RateLimitStore for PostgresStore {
+    async fn check_rate_n(
+        &self,
+        key: &str,
+        cost: u32,
+        limit: u32,
+        window_secs: u64,
+    ) -> Result<RateDecision, StoreError> {
+        let now = now_epoch() as i64;
+        // Fixed window: an expired bucket restarts at `cost` with a fresh expiry
+        let (count, expires_at): (i32, i64) = sqlx::query_as(
+            "INSERT INTO vm31_rate_limits (key, count, expires_at) VALUES ($1, $4, $2) \
+             ON CONFLICT (key) DO UPDATE SET \
+                 count = CASE WHEN vm31_rate_limits.expires_at <= $3 THEN $4 ELSE vm31_rate_limits.count + $4 END, \
+                 expires_at = CASE WHEN vm31_rate_limits.expires_at <= $3 THEN $2 ELSE vm31_rate_limits.expires_at END \
+             RETURNING count, expires_at",
+        )
+        .bind(key)
+        .bind(now + window_secs as i64)
+        .bind(now)
+        .bind(cost as i32)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(pg_err)?;
+        Ok(RateDecision {
+            allowed: count as i64 <= i64::from(limit),
+            retry_after_secs: (expires_at - now).max(1) as u64,
+        })
+    }
+}
+
+#[cfg(feature = "postgres")]
+impl NoteStore for PostgresStore {
+    async fn save_note(&self, commitment: &str, record: &NoteRecord) -> Result<(), StoreError> {
+        let json = serde_json::to_value(record).map_err(|e| StoreError::Backend(e.to_string()))?;
+        sqlx::query(
+            "INSERT INTO vm31_notes (commitment, record, pending, created_at) VALUES ($1, $2, $3, $4) \
+             ON CONFLICT (commitment) DO UPDATE SET record = EXCLUDED.record, pending = EXCLUDED.pending",
+        )
+        .bind(commitment)
+        .bind(json)
+        .bind(record.merkle_root == [0; 8])
+        .bind(record.created_at as i64)
+        .execute(&self.pool)
+        .await
+        .map_err(pg_err)?;
+        Ok(())
+    }
+
+    async fn get_note(&self, commitment: &str) -> Result<Option<NoteRecord>, StoreError> {
+        let row: Option<(serde_json::Value,)> =
+            sqlx::query_as("SELECT record FROM vm31_notes WHERE commitment = $1")
+                .bind(commitment)
+                .fetch_optional(&self.pool)
+                .await
+                .map_err(pg_err)?;
+        row.map(|(json,)| serde_json::from_value(json).map_err(|e| StoreError::Backend(e.to_string())))
+            .transpose()
+    }
+
+    async fn list_pending_notes(&self) -> Result<Vec<NoteRecord>, StoreError> {
+        // Served by the partial index on `pending`
+        let rows: Vec<(serde_json::Value,)> = sqlx::query_as(
+            "SELECT record FROM vm31_notes WHERE pending ORDER BY created_at",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(pg_err)?;
+        rows.into_iter()
+            .map(|(json,)| serde_json::from_value(json).map_err(|e| StoreError::Backend(e.to_string())))
+            .collect()
+    }
+
+    async fn list_pending_notes_page(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<(Vec<NoteRecord>, Option<String>), StoreError> {
+        let limit = limit.max(1);
+        // Keyset pagination on the primary key
+        let rows: Vec<(String, serde_json::Value)> = sqlx::query_as(
+            "SELECT commitment, record FROM vm31_notes WHERE pending AND commitment > $1 \
+             ORDER BY commitment LIMIT $2",
+        )
+        .bind(cursor.unwrap_or(""))
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(pg_err)?;
+        let next = (rows.len() == limit).then(|| rows[limit - 1].0.clone());
+        let notes = rows
+            .into_iter()
+            .map(|(_, json)| serde_json::from_value(json).map_err(|e| StoreError::Backend(e.to_string())))
+            .collect::<Result<_, _>>()?;
+        Ok((notes, next))
+    }
 }
 
 // ---------------------------------------------------------------------------
 // Factory
 // ---------------------------------------------------------------------------
 
-fn now_epoch() -> u64 {
+pub(crate) fn now_epoch() -> u64 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .unwrap_or_default()
@@ -801,22 +2674,77 @@ fn now_epoch() -> u64 {
 /// are mirrored to Redis, and on startup `load_from_redis()` hydrates the
 /// in-memory maps from Redis state.
 ///
-/// Without Redis, the store is purely in-memory (lost on restart).
-pub fn build_store(config: &RelayerConfig) -> Arc<InMemoryStore> {
+/// Redis is pinged at startup. If it is unreachable, startup fails when
+/// `VM31_REDIS_REQUIRED=true`; otherwise the store falls back to in-memory
+/// only with a loud warning.
+///
+/// With the `postgres` feature and `VM31_POSTGRES_URL`, Postgres plays the
+/// same write-through role (migrations are applied on connect). An
+/// unreachable database falls back to in-memory only with a warning.
+///
+/// Without either, the store is purely in-memory (lost on restart).
+pub async fn build_store(config: &RelayerConfig) -> Result<Arc<InMemoryStore>, StoreError> {
+    #[cfg(feature = "postgres")]
+    if let Some(ref url) = config.postgres_url {
+        match PostgresStore::connect(url).await {
+            Ok(pg) => {
+                return Ok(Arc::new(
+                    InMemoryStore::with_encryption(config.storage_key.as_ref())
+                        .with_rate_limit_algo(config.rate_limit_algo)
+                        .with_postgres_backend(pg),
+                ));
+            }
+            Err(e) => {
+                warn!(
+                    error = %e,
+                    "POSTGRES UNREACHABLE — falling back to in-memory only; state will NOT survive restart"
+                );
+            }
+        }
+    }
+    #[cfg(not(feature = "postgres"))]
+    if config.postgres_url.is_some() {
+        warn!("VM31_POSTGRES_URL set but built without the postgres feature — using in-memory store only");
+    }
     #[cfg(feature = "redis")]
     {
         if let Some(ref redis_url) = config.redis_url {
-            match InMemoryStore::with_redis_backend(config.storage_key.as_ref(), redis_url) {
-                Ok(store) => {
-                    return Arc::new(store);
+            let connected = match InMemoryStore::with_redis_backend(config.storage_key.as_ref(), redis_url) {
+                Ok(store) => store.ping_redis().await.map(|_| store),
+                Err(e) => Err(e),
+            };
+            match connected {
+                Ok(mut store) => {
+                    store.nonce_guard_persistent = config.nonce_guard_persistent;
+                    store.redis_degrade_on_error = config.redis_degrade_on_error;
+                    return Ok(Arc::new(store.with_rate_limit_algo(config.rate_limit_algo)));
+                }
+                Err(e) if config.redis_required => {
+                    return Err(StoreError::Backend(format!(
+                        "Redis unreachable and VM31_REDIS_REQUIRED=true: {e}"
+                    )));
                 }
                 Err(e) => {
-                    warn!(error = %e, "failed to connect to Redis, falling back to in-memory only");
+                    warn!(
+                        error = %e,
+                        "REDIS UNREACHABLE — falling back to in-memory only; state will NOT survive restart"
+                    );
                 }
             }
         }
     }
-    Arc::new(InMemoryStore::with_encryption(config.storage_key.as_ref()))
+    #[cfg(not(feature = "redis"))]
+    if config.redis_url.is_some() {
+        if config.redis_required {
+            return Err(StoreError::Backend(
+                "VM31_REDIS_REQUIRED=true but built without the redis feature".into(),
+            ));
+        }
+        warn!("REDIS_URL set but built without the redis feature — using in-memory store only");
+    }
+    Ok(Arc::new(
+        InMemoryStore::with_encryption(config.storage_key.as_ref()).with_rate_limit_algo(config.rate_limit_algo),
+    ))
 }
 
 #[cfg(feature = "redis")]
@@ -824,6 +2752,11 @@ pub fn build_redis_store(url: &str) -> Result<Arc<RedisStore>, StoreError> {
     Ok(Arc::new(RedisStore::new(url)?))
 }
 
+#[cfg(feature = "postgres")]
+pub async fn build_postgres_store(url: &str) -> Result<Arc<PostgresStore>, StoreError> {
+    Ok(Arc::new(PostgresStore::connect(url).await?))
+}
+
 // ---------------------------------------------------------------------------
 // Tests
 // ---------------------------------------------------------------------------
@@ -862,15 +2795,148 @@ mod tests {
 
         let result = store.check_and_set("tx-abc", "batch-2").await.unwrap();
         assert_eq!(result.unwrap(), "batch-1");
+
+        store.update_result("tx-abc", "cancelled").await.unwrap();
+        assert_eq!(store.get_result("tx-abc").await.unwrap().as_deref(), Some("cancelled"));
+        // Updating an absent key does not create it
+        store.update_result("tx-missing", "cancelled").await.unwrap();
+        assert_eq!(store.get_result("tx-missing").await.unwrap(), None);
     }
 
     #[tokio::test]
     async fn test_in_memory_rate_limit() {
         let store = InMemoryStore::new();
         for _ in 0..3 {
-            assert!(store.check_rate("key-1", 3, 60).await.unwrap());
+            assert!(store.check_rate("key-1", 3, 60).await.unwrap().allowed);
+        }
+        let denied = store.check_rate("key-1", 3, 60).await.unwrap();
+        assert!(!denied.allowed);
+        assert!((1..=60).contains(&denied.retry_after_secs));
+    }
+
+    #[test]
+    fn test_token_bucket_allows_burst_then_enforces_rate() {
+        // 30/min with a 2x burst: 60 tokens, one back every 2s
+        let (capacity, rate) = RateLimitAlgo::bucket(2.0, 30, 60);
+        assert_eq!((capacity, rate), (60.0, 0.5));
+        let start = Instant::now();
+        let mut bucket = (capacity, start);
+
+        for _ in 0..60 {
+            assert!(take_tokens(&mut bucket, 1.0, capacity, rate, start).allowed);
+        }
+        let denied = take_tokens(&mut bucket, 1.0, capacity, rate, start);
+        assert!(!denied.allowed);
+        assert_eq!(denied.retry_after_secs, 2);
+
+        // Drained: one request per refill interval, no more
+        let mut at = start;
+        for _ in 0..5 {
+            at += Duration::from_secs(2);
+            assert!(take_tokens(&mut bucket, 1.0, capacity, rate, at).allowed);
+            assert!(!take_tokens(&mut bucket, 1.0, capacity, rate, at).allowed);
+        }
+        // Idle time refills only up to capacity
+        at += Duration::from_secs(3600);
+        let admitted = (0..100).filter(|_| take_tokens(&mut bucket, 1.0, capacity, rate, at).allowed).count();
+        assert_eq!(admitted, 60);
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_token_bucket_store() {
+        let store = InMemoryStore::new().with_rate_limit_algo(RateLimitAlgo::TokenBucket { burst: 1.5 });
+        for _ in 0..15 {
+            assert!(store.check_rate("key-1", 10, 60).await.unwrap().allowed);
+        }
+        let denied = store.check_rate("key-1", 10, 60).await.unwrap();
+        assert!(!denied.allowed);
+        assert!((1..=6).contains(&denied.retry_after_secs));
+        // Buckets are per key
+        assert!(store.check_rate("key-2", 10, 60).await.unwrap().allowed);
+    }
+
+    #[tokio::test]
+    async fn test_check_rate_n_counts_cost_all_or_nothing() {
+        for algo in [RateLimitAlgo::FixedWindow, RateLimitAlgo::TokenBucket { burst: 1.0 }] {
+            let store = InMemoryStore::new().with_rate_limit_algo(algo);
+            assert!(store.check_rate_n("key-1", 8, 10, 60).await.unwrap().allowed);
+            // 3 more don't fit; a denied call spends nothing
+            assert!(!store.check_rate_n("key-1", 3, 10, 60).await.unwrap().allowed);
+            assert!(store.check_rate_n("key-1", 2, 10, 60).await.unwrap().allowed);
+            assert!(!store.check_rate("key-1", 10, 60).await.unwrap().allowed, "{algo:?}");
         }
-        assert!(!store.check_rate("key-1", 3, 60).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_dead_letters_listed_newest_first_and_bounded() {
+        let store = InMemoryStore::new();
+        let entry = |id: String, failed_at| DeadLetter {
+            batch_id: id,
+            reason: "proving failed".into(),
+            failed_at,
+            transactions: vec![],
+            client_refs: vec![],
+        };
+        store.save_dead_letter(entry("old".into(), 10)).await.unwrap();
+        store.save_dead_letter(entry("new".into(), 20)).await.unwrap();
+        let ids: Vec<_> = store.list_dead_letters().await.unwrap().into_iter().map(|s| s.batch_id).collect();
+        assert_eq!(ids, ["new", "old"]);
+
+        for i in 2..MAX_DEAD_LETTERS {
+            store.save_dead_letter(entry(format!("b-{i}"), 30)).await.unwrap();
+        }
+        assert!(store.save_dead_letter(entry("overflow".into(), 40)).await.is_err());
+        // Taking one frees a slot
+        assert_eq!(store.take_dead_letter("old").await.unwrap().unwrap().failed_at, 10);
+        assert!(store.take_dead_letter("old").await.unwrap().is_none());
+        store.save_dead_letter(entry("overflow".into(), 40)).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_dead_letters_sealed_with_storage_key() {
+        use crate::routes::SubmitRequest;
+
+        let store = InMemoryStore::with_encryption(Some(&[4u8; 32]));
+        let entry = DeadLetter {
+            batch_id: "b-1".into(),
+            reason: "proving failed".into(),
+            failed_at: 10,
+            transactions: vec![SubmitRequest::Deposit {
+                amount: 100_000,
+                asset_id: 0,
+                recipient_pubkey: [1, 2, 3, 4],
+                recipient_viewing_key: [5, 6, 7, 8],
+                blinding: Some([9, 10, 11, 12]),
+                client_ref: None,
+            }],
+            client_refs: vec![None],
+        };
+        store.save_dead_letter(entry).await.unwrap();
+
+        {
+            let held = store.dead_letters.get("b-1").unwrap();
+            let Held::Sealed(data) = &held.record else {
+                panic!("dead letter held in the clear");
+            };
+            assert!(serde_json::from_slice::<serde_json::Value>(data).is_err());
+            // What goes to Redis is the sealed record too
+            #[cfg(feature = "redis")]
+            {
+                let value = store.dead_letter_value(&held).unwrap();
+                assert!(serde_json::from_str::<serde_json::Value>(&value).is_err());
+                assert!(!value.contains("recipient_viewing_key"));
+                let restored = store.dead_letter_from_value(&value).unwrap();
+                assert_eq!(restored.summary.tx_count, 1);
+            }
+        }
+
+        // Listing reads only the summary; taking opens the record
+        assert_eq!(store.list_dead_letters().await.unwrap()[0].reason, "proving failed");
+        let taken = store.take_dead_letter("b-1").await.unwrap().unwrap();
+        assert!(matches!(
+            taken.transactions[..],
+            [SubmitRequest::Deposit { blinding: Some([9, 10, 11, 12]), .. }]
+        ));
     }
 
     #[tokio::test]
@@ -887,6 +2953,9 @@ mod tests {
             created_at: 1700000000,
             commitment_digest: None,
             note_index_in_batch: 0,
+            client_ref: None,
+            backfill_attempts: 0,
+            last_backfill_attempt: 0,
         };
         store.save_note("abc123", &record).await.unwrap();
 
@@ -912,6 +2981,9 @@ mod tests {
             created_at: 1700000000,
             commitment_digest: None,
             note_index_in_batch: 0,
+            client_ref: None,
+            backfill_attempts: 0,
+            last_backfill_attempt: 0,
         };
         store.save_note("pending1", &pending).await.unwrap();
 
@@ -927,6 +2999,9 @@ mod tests {
             created_at: 1700000000,
             commitment_digest: Some([1, 2, 3, 4, 5, 6, 7, 8]),
             note_index_in_batch: 0,
+            client_ref: None,
+            backfill_attempts: 0,
+            last_backfill_attempt: 0,
         };
         store.save_note("done1", &finalized).await.unwrap();
 
@@ -934,4 +3009,465 @@ mod tests {
         assert_eq!(pending_notes.len(), 1);
         assert_eq!(pending_notes[0].commitment, "pending1");
     }
+
+    #[tokio::test]
+    async fn test_pending_notes_pages_cover_all_without_duplicates() {
+        let store = InMemoryStore::with_encryption(Some(&[7u8; 32]));
+        for i in 0..10 {
+            let commitment = format!("p{i:02}");
+            store.save_note(&commitment, &note_with_path(&commitment, 0)).await.unwrap();
+        }
+        let mut done = note_with_path("done", 1);
+        done.merkle_root = [1; 8];
+        store.save_note("done", &done).await.unwrap();
+
+        let mut seen = Vec::new();
+        let mut cursor = None;
+        let mut pages = 0;
+        loop {
+            let (page, next) = store.list_pending_notes_page(cursor.as_deref(), 3).await.unwrap();
+            assert!(page.len() <= 3);
+            pages += 1;
+            for note in page {
+                // Backfilled mid-listing: leaving the pending set must not
+                // shift later pages
+                let mut filled = note.clone();
+                filled.merkle_root = [2; 8];
+                store.save_note(&note.commitment, &filled).await.unwrap();
+                seen.push(note.commitment);
+            }
+            match next {
+                Some(c) => cursor = Some(c),
+                None => break,
+            }
+        }
+        assert_eq!(pages, 4);
+        let expected: Vec<String> = (0..10).map(|i| format!("p{i:02}")).collect();
+        assert_eq!(seen, expected);
+        assert!(store.list_pending_notes().await.unwrap().is_empty());
+
+        // An exactly full last page ends the listing without an empty page
+        let store = InMemoryStore::new();
+        for commitment in ["q0", "q1"] {
+            store.save_note(commitment, &note_with_path(commitment, 0)).await.unwrap();
+        }
+        let (page, next) = store.list_pending_notes_page(None, 2).await.unwrap();
+        assert_eq!((page.len(), next), (2, None));
+    }
+
+    fn note_with_path(commitment: &str, depth: usize) -> NoteRecord {
+        NoteRecord {
+            commitment: commitment.into(),
+            merkle_path: MerklePathRecord { siblings: vec![[7; 8]; depth], index: 0 },
+            merkle_root: [0; 8],
+            batch_id: "batch-1".into(),
+            created_at: 1700000000,
+            commitment_digest: None,
+            note_index_in_batch: 0,
+            client_ref: None,
+            backfill_attempts: 0,
+            last_backfill_attempt: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_shared_note_accessors_do_not_copy() {
+        let store = InMemoryStore::new();
+        store.save_note("c1", &note_with_path("c1", 20)).await.unwrap();
+
+        let a = store.get_note_shared("c1").unwrap().unwrap();
+        let b = store.pending_notes_shared().pop().unwrap();
+        assert!(Arc::ptr_eq(&a, &b));
+
+        // Writers copy on write; a held record keeps its snapshot
+        store.record_backfill_miss("c1", 42).await.unwrap();
+        assert_eq!(a.backfill_attempts, 0);
+        let after = store.get_note_shared("c1").unwrap().unwrap();
+        assert_eq!((after.backfill_attempts, after.last_backfill_attempt), (1, 42));
+        assert_eq!(store.get_note("c1").await.unwrap().unwrap().backfill_attempts, 1);
+    }
+
+    #[tokio::test]
+    async fn test_encrypted_store_holds_only_sealed_records() {
+        let store = InMemoryStore::with_encryption(Some(&[7; 32]));
+        let mut batch = BatchRecord::new("batch-1".into(), 4);
+        batch.tx_hash = Some("0xfeed".into());
+        store.save_batch("batch-1", &batch).await.unwrap();
+        store.save_note("c-pending", &note_with_path("c-pending", 20)).await.unwrap();
+        let mut filled = note_with_path("c-filled", 20);
+        filled.merkle_root = [9; 8];
+        store.save_note("c-filled", &filled).await.unwrap();
+
+        // Raw map values are nonce || ciphertext, not JSON
+        for entry in store.notes.iter() {
+            let Held::Sealed(data) = &entry.record else { panic!("{} held in the clear", entry.key()) };
+            assert!(serde_json::from_slice::<serde_json::Value>(data).is_err());
+            assert!(!data.windows(entry.key().len()).any(|w| w == entry.key().as_bytes()));
+        }
+        let raw = store.batches.get("batch-1").unwrap();
+        let Held::Sealed(data) = &raw.record else { panic!("batch held in the clear") };
+        assert!(!data.windows(6).any(|w| w == b"0xfeed"));
+        drop(raw);
+
+        // Getters decrypt, and the pending filter still works
+        assert_eq!(store.get_batch("batch-1").await.unwrap().unwrap().tx_hash.as_deref(), Some("0xfeed"));
+        assert_eq!(store.get_note("c-filled").await.unwrap().unwrap().merkle_root, [9; 8]);
+        let pending = store.list_pending_notes().await.unwrap();
+        assert_eq!(pending.iter().map(|n| n.commitment.as_str()).collect::<Vec<_>>(), ["c-pending"]);
+
+        // Updates re-seal the record
+        store
+            .update_status("batch-1", BatchStatus::Finalized, StatusUpdate::default())
+            .await
+            .unwrap();
+        assert_eq!(store.get_batch("batch-1").await.unwrap().unwrap().status, BatchStatus::Finalized);
+        let page = store.list_batches(Some(BatchStatus::Finalized), None, 10).await.unwrap();
+        assert_eq!(page.len(), 1);
+        store.record_backfill_miss("c-pending", 42).await.unwrap();
+        assert_eq!(store.get_note("c-pending").await.unwrap().unwrap().backfill_attempts, 1);
+    }
+
+    /// `cargo test --release bench_pending_notes -- --ignored --nocapture`
+    #[tokio::test]
+    #[ignore]
+    async fn bench_pending_notes_shared_vs_cloned() {
+        const NOTES: usize = 50_000;
+        const DEPTH: usize = 20;
+        let store = InMemoryStore::new();
+        for i in 0..NOTES {
+            let c = format!("c{i:08}");
+            store.save_note(&c, &note_with_path(&c, DEPTH)).await.unwrap();
+        }
+
+        let started = std::time::Instant::now();
+        let cloned = store.list_pending_notes().await.unwrap();
+        let cloned_elapsed = started.elapsed();
+        let started = std::time::Instant::now();
+        let shared = store.pending_notes_shared();
+        let shared_elapsed = started.elapsed();
+        assert_eq!(cloned.len(), shared.len());
+
+        // Per note, a clone allocates the path plus both strings
+        let copied_bytes: usize = cloned
+            .iter()
+            .map(|n| n.merkle_path.siblings.len() * 32 + n.commitment.len() + n.batch_id.len())
+            .sum();
+        println!(
+            "{NOTES} pending notes: cloned {cloned_elapsed:?} ({} allocations, {copied_bytes} bytes), \
+             shared {shared_elapsed:?} (0 record allocations)",
+            NOTES * 3,
+        );
+    }
+
+    #[test]
+    fn test_inflight_limit_and_idle_eviction() {
+        let store = InMemoryStore::new();
+        let p1 = store.try_acquire_inflight("key:a", 2).unwrap();
+        let _p2 = store.try_acquire_inflight("key:a", 2).unwrap();
+        assert!(store.try_acquire_inflight("key:a", 2).is_none());
+        // Other keys have their own budget
+        assert!(store.try_acquire_inflight("key:b", 2).is_some());
+
+        drop(p1);
+        assert!(store.try_acquire_inflight("key:a", 2).is_some());
+
+        // key:b is idle (its permit was dropped), key:a still has one in flight
+        let summary = store.evict_expired();
+        assert!(store.inflight.contains_key("key:a"));
+        assert!(!store.inflight.contains_key("key:b"));
+        assert_eq!(summary.inflight, 1);
+        assert_eq!(store.last_eviction().unwrap().inflight, 1);
+        assert_eq!(store.eviction_metrics.passes.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_note_is_stuck_only_without_digest_past_threshold() {
+        let mut note = NoteRecord {
+            commitment: "c".into(),
+            merkle_path: MerklePathRecord { siblings: vec![], index: 0 },
+            merkle_root: [0; 8],
+            batch_id: "batch-1".into(),
+            created_at: 1_000,
+            commitment_digest: None,
+            note_index_in_batch: 0,
+            client_ref: None,
+            backfill_attempts: 0,
+            last_backfill_attempt: 0,
+        };
+        assert!(!note.is_stuck(1_500, 3600));
+        assert!(note.is_stuck(1_000 + 3600, 3600));
+
+        note.commitment_digest = Some([1; 8]);
+        assert!(!note.is_stuck(1_000 + 3600, 3600));
+    }
+
+    #[test]
+    fn test_backfill_backoff_doubles_and_caps() {
+        let mut note = NoteRecord {
+            commitment: "c".into(),
+            merkle_path: MerklePathRecord { siblings: vec![], index: 0 },
+            merkle_root: [0; 8],
+            batch_id: "batch-1".into(),
+            created_at: 1_000,
+            commitment_digest: None,
+            note_index_in_batch: 0,
+            client_ref: None,
+            backfill_attempts: 0,
+            last_backfill_attempt: 0,
+        };
+        assert!(note.backfill_due(1_000, 15, 100));
+
+        note.backfill_attempts = 1;
+        note.last_backfill_attempt = 1_000;
+        assert!(!note.backfill_due(1_014, 15, 100));
+        assert!(note.backfill_due(1_015, 15, 100));
+
+        note.backfill_attempts = 3; // 15 * 4 = 60
+        assert!(!note.backfill_due(1_059, 15, 100));
+        assert!(note.backfill_due(1_060, 15, 100));
+
+        note.backfill_attempts = 40; // capped at max
+        assert!(note.backfill_due(1_100, 15, 100));
+    }
+
+    #[tokio::test]
+    async fn test_nonce_guard_rejects_replay_and_bounds_size() {
+        let store = InMemoryStore::new();
+        assert!(store.check_and_record_nonce("pair-1", 3600, 2).await.unwrap());
+        assert!(!store.check_and_record_nonce("pair-1", 3600, 2).await.unwrap());
+
+        assert!(store.check_and_record_nonce("pair-2", 3600, 2).await.unwrap());
+        assert!(store.check_and_record_nonce("pair-3", 3600, 2).await.unwrap());
+        assert_eq!(store.seen_nonces.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_nonce_window_drops_oldest_and_expired_pairs() {
+        let mut w = NonceWindow::default();
+        assert!(w.check_and_record("a", 0, 10, 2));
+        assert!(w.check_and_record("b", 1, 10, 2));
+        // Full: the oldest pair goes first
+        assert!(w.check_and_record("c", 2, 10, 2));
+        assert!(w.check_and_record("a", 3, 10, 2));
+        assert!(!w.check_and_record("c", 4, 10, 2));
+
+        // "c" expired and is recorded again; its stale entry and the
+        // now-expired "a" are dropped
+        assert!(w.check_and_record("c", 13, 10, 2));
+        assert_eq!((w.len(), w.order.len()), (1, 1));
+        assert!(!w.check_and_record("c", 14, 10, 2));
+    }
+
+    #[tokio::test]
+    async fn test_owner_index_returns_only_that_owners_notes() {
+        let store = InMemoryStore::new();
+        let (alice, bob) = ([1, 2, 3, 4], [5, 6, 7, 8]);
+        for (owner, commitment) in [(alice, "a1"), (bob, "b1"), (alice, "a2")] {
+            store.save_note(commitment, &note_with_path(commitment, 0)).await.unwrap();
+            store.index_note_owner(&owner, commitment).await.unwrap();
+        }
+        // Re-indexing the same note doesn't list it twice
+        store.index_note_owner(&alice, "a1").await.unwrap();
+
+        assert_eq!(store.notes_by_owner(&alice).await.unwrap(), ["a1", "a2"]);
+        assert_eq!(store.notes_by_owner(&bob).await.unwrap(), ["b1"]);
+        assert!(store.notes_by_owner(&[9; 4]).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_volume_cap_resets_at_utc_day_boundary() {
+        let store = InMemoryStore::new();
+        assert_eq!(utc_day(86_399), 0);
+        assert_eq!(utc_day(86_400), 1);
+        let day = utc_day(now_epoch());
+
+        assert!(store.check_and_add_volume("k", day, 60, 100).await.unwrap());
+        assert!(store.check_and_add_volume("k", day, 40, 100).await.unwrap());
+        assert!(!store.check_and_add_volume("k", day, 1, 100).await.unwrap());
+        assert!(!store.check_and_add_volume("k", day, u64::MAX, u64::MAX).await.unwrap());
+        assert!(store.check_and_add_volume("other", day, 100, 100).await.unwrap());
+
+        assert!(store.check_and_add_volume("k", day + 1, 100, 100).await.unwrap());
+        // Eviction keeps yesterday's and today's totals only
+        store.volumes.insert("stale".into(), (0, 5));
+        store.evict_expired();
+        assert!(!store.volumes.contains_key("stale"));
+        assert!(store.volumes.contains_key("k"));
+    }
+
+    /// Connects to `VM31_TEST_DATABASE_URL`; Postgres tests are skipped when unset.
+    #[cfg(feature = "postgres")]
+    async fn test_postgres() -> Option<PostgresStore> {
+        let url = std::env::var("VM31_TEST_DATABASE_URL").ok()?;
+        Some(PostgresStore::connect(&url).await.expect("connect to test database"))
+    }
+
+    #[cfg(feature = "postgres")]
+    #[tokio::test]
+    async fn test_postgres_batch_and_idempotency() {
+        let Some(store) = test_postgres().await else { return };
+        let id = uuid::Uuid::new_v4().to_string();
+        store.save_batch(&id, &BatchRecord::new(id.clone(), 3)).await.unwrap();
+        store
+            .update_status(
+                &id,
+                BatchStatus::Finalized,
+                StatusUpdate {
+                    tx_hash: Some("0xabc".into()),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        let fetched = store.get_batch(&id).await.unwrap().unwrap();
+        assert_eq!(fetched.status, BatchStatus::Finalized);
+        assert_eq!(fetched.tx_hash.as_deref(), Some("0xabc"));
+        let finalized = store
+            .list_batches(Some(BatchStatus::Finalized), None, 100)
+            .await
+            .unwrap();
+        assert!(finalized.iter().all(|b| b.status == BatchStatus::Finalized));
+        assert!(matches!(
+            store.update_status("missing", BatchStatus::Failed, StatusUpdate::default()).await,
+            Err(StoreError::NotFound(_))
+        ));
+
+        let key = format!("idem-{id}");
+        assert!(store.check_and_set(&key, "first").await.unwrap().is_none());
+        assert_eq!(store.check_and_set(&key, "second").await.unwrap().as_deref(), Some("first"));
+        store.update_result(&key, &id).await.unwrap();
+        assert_eq!(store.get_result(&key).await.unwrap(), Some(id.clone()));
+        store.update_result("idem-missing", "x").await.unwrap();
+        assert_eq!(store.get_result("idem-missing").await.unwrap(), None);
+    }
+
+    #[cfg(feature = "postgres")]
+    #[tokio::test]
+    async fn test_postgres_rate_limit_and_pending_notes() {
+        let Some(store) = test_postgres().await else { return };
+        let key = format!("rl-{}", uuid::Uuid::new_v4());
+        assert!(store.check_rate(&key, 2, 60).await.unwrap().allowed);
+        assert!(store.check_rate(&key, 2, 60).await.unwrap().allowed);
+        let denied = store.check_rate(&key, 2, 60).await.unwrap();
+        assert!(!denied.allowed);
+        assert!((1..=60).contains(&denied.retry_after_secs));
+
+        let commitment = uuid::Uuid::new_v4().simple().to_string();
+        let mut note = NoteRecord {
+            commitment: commitment.clone(),
+            merkle_path: MerklePathRecord { siblings: vec![], index: 0 },
+            merkle_root: [0; 8],
+            batch_id: "b".into(),
+            created_at: now_epoch(),
+            commitment_digest: None,
+            note_index_in_batch: 0,
+            client_ref: None,
+            backfill_attempts: 0,
+            last_backfill_attempt: 0,
+        };
+        store.save_note(&commitment, &note).await.unwrap();
+        let pending = store.list_pending_notes().await.unwrap();
+        assert!(pending.iter().any(|n| n.commitment == commitment));
+
+        note.merkle_root = [1; 8];
+        store.save_note(&commitment, &note).await.unwrap();
+        let pending = store.list_pending_notes().await.unwrap();
+        assert!(!pending.iter().any(|n| n.commitment == commitment));
+        assert_eq!(store.get_note(&commitment).await.unwrap().unwrap().merkle_root, [1; 8]);
+    }
+
+
+    #[cfg(feature = "postgres")]
+    #[tokio::test]
+    async fn test_postgres_write_through_restores_after_restart() {
+        let Some(pg) = test_postgres().await else { return };
+        let store = InMemoryStore::with_encryption(Some(&[7; 32])).with_postgres_backend(pg);
+        assert_eq!(store.backend_name(), "postgres");
+        let id = uuid::Uuid::new_v4().to_string();
+        store.save_batch(&id, &BatchRecord::new(id.clone(), 2)).await.unwrap();
+        store.update_status(&id, BatchStatus::Proving, StatusUpdate::default()).await.unwrap();
+        let commitment = uuid::Uuid::new_v4().simple().to_string();
+        let note = NoteRecord {
+            commitment: commitment.clone(),
+            merkle_path: MerklePathRecord { siblings: vec![], index: 0 },
+            merkle_root: [0; 8],
+            batch_id: id.clone(),
+            created_at: now_epoch(),
+            commitment_digest: None,
+            note_index_in_batch: 0,
+            client_ref: None,
+            backfill_attempts: 0,
+            last_backfill_attempt: 0,
+        };
+        store.save_note(&commitment, &note).await.unwrap();
+
+        // A fresh process hydrates from the database
+        let restarted = InMemoryStore::with_encryption(Some(&[7; 32]))
+            .with_postgres_backend(test_postgres().await.unwrap());
+        restarted.load_from_postgres().await.unwrap();
+        assert_eq!(restarted.get_batch(&id).await.unwrap().unwrap().status, BatchStatus::Proving);
+        assert_eq!(restarted.get_note(&commitment).await.unwrap().unwrap().batch_id, id);
+    }
+
+    async fn seed_batches(store: &InMemoryStore) {
+        let statuses = [
+            BatchStatus::Pending,
+            BatchStatus::Proving,
+            BatchStatus::Submitting,
+            BatchStatus::Finalized,
+            BatchStatus::Failed,
+        ];
+        // Two batches per timestamp so pages must break ties by id
+        for i in 0..10u64 {
+            let mut rec = BatchRecord::new(format!("batch-{i}"), 1);
+            rec.created_at = 1_000 + i / 2;
+            rec.status = statuses[i as usize % statuses.len()].clone();
+            store.save_batch(&rec.id.clone(), &rec).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_list_batches_cursor_pagination() {
+        let store = InMemoryStore::new();
+        seed_batches(&store).await;
+
+        let mut seen = Vec::new();
+        let mut cursor: Option<BatchCursor> = None;
+        loop {
+            let page = store.list_batches(None, cursor.as_ref(), 3).await.unwrap();
+            assert!(page.len() <= 3);
+            if page.is_empty() {
+                break;
+            }
+            cursor = page.last().map(BatchCursor::after);
+            seen.extend(page.into_iter().map(|b| b.id));
+        }
+        let expected: Vec<String> = (0..10).rev().map(|i| format!("batch-{i}")).collect();
+        assert_eq!(seen, expected);
+
+        // Exact page boundary: the cursor after the last record yields nothing
+        let last = BatchCursor { created_at: 1_000, id: "batch-0".into() };
+        assert!(store.list_batches(None, Some(&last), 3).await.unwrap().is_empty());
+        assert_eq!(BatchCursor::decode(&last.encode()), Some(last));
+        assert_eq!(BatchCursor::decode("12-ab-cd").unwrap().id, "ab-cd");
+        assert!(BatchCursor::decode("nope").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_list_batches_filters_by_status() {
+        let store = InMemoryStore::new();
+        seed_batches(&store).await;
+        for status in [
+            BatchStatus::Pending,
+            BatchStatus::Proving,
+            BatchStatus::Submitting,
+            BatchStatus::Finalized,
+            BatchStatus::Failed,
+        ] {
+            let page = store.list_batches(Some(status.clone()), None, 100).await.unwrap();
+            assert_eq!(page.len(), 2, "{status:?}");
+            assert!(page.iter().all(|b| b.status == status));
+            assert!(page[0].created_at >= page[1].created_at);
+        }
+    }
 }
@@ -0,0 +1,259 @@
+//! Tracing setup, and the spans that follow a submission through its batch.
+//!
+//! Spans are plain `tracing` spans. Built with the `otel` feature and with
+//! `VM31_OTLP_ENDPOINT` set, they are also exported over OTLP. A `/submit`
+//! span continues the client's W3C `traceparent`. A batch is one trace from
+//! the moment the queue seals it, linked to the submit spans of its
+//! transactions, with the prover's stages as child spans.
+
+use std::future::Future;
+use std::time::Instant;
+
+use axum::http::HeaderMap;
+use tracing::{field, info_span, Instrument, Span};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+/// Prover stages traced as children of the batch span.
+#[derive(Debug, Clone, Copy)]
+pub enum BatchStage {
+    Validate,
+    Prove,
+    Submit,
+    Bridge,
+}
+
+impl BatchStage {
+    fn name(self) -> &'static str {
+        match self {
+            BatchStage::Validate => "validate",
+            BatchStage::Prove => "prove",
+            BatchStage::Submit => "submit_onchain",
+            BatchStage::Bridge => "bridge",
+        }
+    }
+}
+
+/// The exported span a queued transaction was submitted under, so its batch
+/// can link back to it. Empty unless spans are exported.
+#[derive(Debug, Clone, Default)]
+pub struct SpanLink {
+    #[cfg(feature = "otel")]
+    context: Option<opentelemetry::trace::SpanContext>,
+}
+
+impl SpanLink {
+    pub fn current() -> Self {
+        #[cfg(feature = "otel")]
+        {
+            use opentelemetry::trace::TraceContextExt;
+            use tracing_opentelemetry::OpenTelemetrySpanExt;
+            let cx = Span::current().context();
+            let context = cx.span().span_context().clone();
+            Self { context: context.is_valid().then_some(context) }
+        }
+        #[cfg(not(feature = "otel"))]
+        {
+            Self::default()
+        }
+    }
+}
+
+/// Span for one submission, continuing the client's trace when the request
+/// carries a `traceparent` header.
+pub fn submit_span(headers: &HeaderMap) -> Span {
+    let span = info_span!("submit");
+    #[cfg(feature = "otel")]
+    {
+        use opentelemetry::trace::TraceContextExt;
+        use tracing_opentelemetry::OpenTelemetrySpanExt;
+        let remote = opentelemetry::global::get_text_map_propagator(|p| p.extract(&HeaderExtractor(headers)));
+        if remote.span().span_context().is_valid() {
+            span.set_parent(remote);
+        }
+    }
+    #[cfg(not(feature = "otel"))]
+    let _ = headers;
+    span
+}
+
+/// Root span for a sealed batch, linked to its transactions' submit spans.
+pub fn batch_span(
+    batch_id: &str,
+    asset_ids: &str,
+    tx_count: usize,
+    links: impl IntoIterator<Item = SpanLink>,
+) -> Span {
+    // Sealing can happen inside the submit that filled the batch; the batch
+    // must not become part of that one client's trace.
+    let span = info_span!(parent: None, "batch", batch_id = %batch_id, tx_count, asset_ids = %asset_ids);
+    #[cfg(feature = "otel")]
+    {
+        use tracing_opentelemetry::OpenTelemetrySpanExt;
+        for link in links {
+            if let Some(context) = link.context {
+                span.add_link(context);
+            }
+        }
+    }
+    #[cfg(not(feature = "otel"))]
+    let _ = links;
+    span
+}
+
+/// Runs `fut` as `stage` of the current batch, recording its wall time as
+/// `duration_ms`.
+pub async fn in_stage<F: Future>(stage: BatchStage, tx_count: usize, fut: F) -> F::Output {
+    let span = info_span!(
+        "batch_stage",
+        otel.name = stage.name(),
+        tx_count,
+        duration_ms = field::Empty,
+    );
+    let started = Instant::now();
+    let out = fut.instrument(span.clone()).await;
+    span.record("duration_ms", started.elapsed().as_millis() as u64);
+    out
+}
+
+/// Keeps the OTLP exporter running; flushes buffered spans on drop.
+pub struct Telemetry {
+    #[cfg(feature = "otel")]
+    provider: Option<opentelemetry_sdk::trace::TracerProvider>,
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        #[cfg(feature = "otel")]
+        if let Some(provider) = self.provider.take() {
+            if let Err(e) = provider.shutdown() {
+                eprintln!("[vm31-relayer] OTLP exporter shutdown failed: {e}");
+            }
+        }
+    }
+}
+
+/// Installs the global subscriber (env-filtered log output, e.g.
+/// RUST_LOG=vm31_relayer=debug,info) plus OTLP export to `otlp_endpoint`.
+pub fn init(otlp_endpoint: Option<&str>) -> Telemetry {
+    let filter = tracing_subscriber::EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| "vm31_relayer=info,tower_http=info".into());
+    let registry = tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer());
+
+    #[cfg(feature = "otel")]
+    {
+        use opentelemetry::trace::TracerProvider as _;
+        let provider = otlp_endpoint.and_then(|endpoint| match otlp_provider(endpoint) {
+            Ok(provider) => Some(provider),
+            Err(e) => {
+                eprintln!("[vm31-relayer] OTLP exporter disabled: {e}");
+                None
+            }
+        });
+        let otel = provider
+            .as_ref()
+            .map(|p| tracing_opentelemetry::layer().with_tracer(p.tracer("vm31-relayer")));
+        registry.with(otel).init();
+        if let (Some(endpoint), Some(_)) = (otlp_endpoint, &provider) {
+            tracing::info!(endpoint, "exporting traces over OTLP");
+        }
+        Telemetry { provider }
+    }
+    #[cfg(not(feature = "otel"))]
+    {
+        registry.init();
+        if otlp_endpoint.is_some() {
+            tracing::warn!("VM31_OTLP_ENDPOINT set but built without the otel feature — traces are not exported");
+        }
+        Telemetry {}
+    }
+}
+
+#[cfg(feature = "otel")]
+fn otlp_provider(endpoint: &str) -> Result<opentelemetry_sdk::trace::TracerProvider, opentelemetry::trace::TraceError> {
+    use opentelemetry_otlp::WithExportConfig;
+    opentelemetry::global::set_text_map_propagator(opentelemetry_sdk::propagation::TraceContextPropagator::new());
+    opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(opentelemetry_otlp::new_exporter().tonic().with_endpoint(endpoint))
+        .with_trace_config(opentelemetry_sdk::trace::Config::default().with_resource(
+            opentelemetry_sdk::Resource::new([opentelemetry::KeyValue::new("service.name", "vm31-relayer")]),
+        ))
+        .install_batch(opentelemetry_sdk::runtime::Tokio)
+}
+
+#[cfg(feature = "otel")]
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+#[cfg(feature = "otel")]
+impl opentelemetry::propagation::Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|v| v.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|k| k.as_str()).collect()
+    }
+}
+
+#[cfg(all(test, feature = "otel"))]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::{SpanId, TraceId, TracerProvider as _};
+    use opentelemetry_sdk::testing::trace::InMemorySpanExporter;
+
+    #[test]
+    fn test_batch_trace_links_submits_and_parents_stages() {
+        opentelemetry::global::set_text_map_propagator(opentelemetry_sdk::propagation::TraceContextPropagator::new());
+        let exporter = InMemorySpanExporter::default();
+        let provider = opentelemetry_sdk::trace::TracerProvider::builder()
+            .with_simple_exporter(exporter.clone())
+            .build();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+        let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
+
+        tracing::subscriber::with_default(subscriber, || {
+            let mut headers = HeaderMap::new();
+            headers.insert(
+                "traceparent",
+                "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01".parse().unwrap(),
+            );
+            let submit = submit_span(&headers);
+            // Sealed while the filling submit is still in progress
+            let batch = submit.in_scope(|| batch_span("b-1", "0", 1, [SpanLink::current()]));
+            drop(submit);
+            rt.block_on(
+                async {
+                    in_stage(BatchStage::Validate, 1, async {}).await;
+                    in_stage(BatchStage::Prove, 1, async {}).await;
+                }
+                .instrument(batch),
+            );
+        });
+        let _ = provider.force_flush();
+
+        let spans = exporter.get_finished_spans().unwrap();
+        let find = |name: &str| spans.iter().find(|s| s.name == name).unwrap_or_else(|| panic!("no {name} span"));
+        let (submit, batch) = (find("submit"), find("batch"));
+
+        // The client's trace continues into the submit span
+        assert_eq!(
+            submit.span_context.trace_id(),
+            TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap()
+        );
+        assert_eq!(submit.parent_span_id, SpanId::from_hex("00f067aa0ba902b7").unwrap());
+
+        // The batch is its own root, linked back to the submission
+        assert_eq!(batch.parent_span_id, SpanId::INVALID);
+        assert!(batch.links.iter().any(|l| l.span_context == submit.span_context));
+
+        for stage in ["validate", "prove"] {
+            let span = find(stage);
+            assert_eq!(span.parent_span_id, batch.span_context.span_id(), "{stage}");
+            assert_eq!(span.span_context.trace_id(), batch.span_context.trace_id());
+        }
+    }
+}
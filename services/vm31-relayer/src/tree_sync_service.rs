@@ -3,11 +3,25 @@
 //! Periodically polls the pool contract for NoteInserted events, maintains
 //! a local PoseidonMerkleTreeM31, and backfills pending NoteRecords with
 //! real merkle proofs.
+//!
+//! Events are applied append-only, so each sync first checks that the local
+//! root is still in the pool's root history. Each confirmed tree is kept as
+//! an on-disk checkpoint. If the local root is gone but an older checkpoint's
+//! root is still known, the chain reorged after that checkpoint: the tree is
+//! rolled back to it and re-synced. If no checkpoint is known, the root may
+//! simply have rolled off the history window while we were offline, so the
+//! tree syncs forward first and is rebuilt from genesis only if the result
+//! still isn't known on-chain. After a rollback or rebuild, orphaned roots
+//! are dropped from `KnownRoots` and backfilled notes whose leaf moved or
+//! vanished go back to pending.
 
+use std::collections::VecDeque;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
+use arc_swap::ArcSwap;
 use tokio::sync::Mutex;
 use tracing::{debug, info, warn};
 
@@ -16,26 +30,220 @@ use stwo_ml::prelude::M31;
 use stwo_ml::privacy::pool_client::{PoolClient, PoolClientConfig};
 use stwo_ml::privacy::tree_sync::TreeSync;
 
-use crate::store::{InMemoryStore, MerklePathRecord, NoteStore};
+use crate::circuit_breaker::CircuitBreaker;
+use crate::rpc_pool::{with_failover, RpcPool};
+use crate::store::{now_epoch, InMemoryStore, MerklePathRecord, NoteRecord, NoteStore};
 
 // ---------------------------------------------------------------------------
 // Types
 // ---------------------------------------------------------------------------
 
+/// Pending notes backfilled per tree lock unless configured otherwise.
+pub const DEFAULT_BACKFILL_PAGE_SIZE: usize = 256;
+
+/// Confirmed trees kept on disk as rollback points for a reorg.
+const MAX_TREE_CHECKPOINTS: usize = 8;
+
 /// Proof result returned by on-demand lookups.
 pub struct ProofResult {
+    /// The on-chain commitment digest the proof is for.
+    pub leaf: [u32; 8],
     pub siblings: Vec<[u32; 8]>,
     pub index: usize,
     pub root: [u32; 8],
 }
 
+/// Error returned by manually triggered tree operations.
+#[derive(Debug, Clone)]
+pub enum TreeSyncError {
+    /// Another sync or backfill currently holds the mutation lock.
+    InProgress,
+    Failed(String),
+    /// The last successful sync is older than the configured maximum, so the
+    /// local tree may be behind the chain. `None` if no sync has succeeded yet.
+    Stale { last_sync_age_secs: Option<u64> },
+    /// The local root is not (yet) known on-chain — the tree is ahead of the
+    /// chain's root history, or diverged from it — so a path against it would
+    /// be rejected at withdrawal time.
+    Unconfirmed,
+}
+
+impl std::fmt::Display for TreeSyncError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TreeSyncError::InProgress => write!(f, "tree sync already in progress"),
+            TreeSyncError::Failed(msg) => write!(f, "tree sync failed: {msg}"),
+            TreeSyncError::Stale { last_sync_age_secs: Some(age) } => {
+                write!(f, "local tree is stale (last successful sync {age}s ago)")
+            }
+            TreeSyncError::Stale { last_sync_age_secs: None } => {
+                write!(f, "local tree has not synced yet")
+            }
+            TreeSyncError::Unconfirmed => {
+                write!(f, "local tree root is not yet confirmed on-chain")
+            }
+        }
+    }
+}
+
+impl std::error::Error for TreeSyncError {}
+
+/// Bounded set of recent known roots, used by the prover to skip the
+/// `is_known_root` RPC for roots already verified.
+///
+/// Filled by tree sync with each local root its sync cross-checked against
+/// the on-chain root, tagged with the tree's leaf count, and by the prover
+/// with roots the RPC confirmed. The pool keeps the last `root_history`
+/// roots and every root update appends at least one leaf, so a root is
+/// evicted once the tree has grown `root_history` leaves past it: it may
+/// already have rolled off on-chain history. `capacity` only caps memory.
+///
+/// Reads load a snapshot and never wait on a writer; writers swap in an
+/// updated copy of the (at most `capacity`) roots.
+pub struct KnownRoots {
+    capacity: usize,
+    root_history: u64,
+    roots: ArcSwap<RootWindow>,
+}
+
+#[derive(Clone, Default)]
+struct RootWindow {
+    /// Largest leaf count tree sync has reported.
+    leaves: u64,
+    /// Roots with the leaf count they were current at, oldest first.
+    roots: VecDeque<([u32; 8], u64)>,
+}
+
+impl RootWindow {
+    fn evict_expired(&mut self, root_history: u64) {
+        let leaves = self.leaves;
+        self.roots.retain(|&(_, at)| leaves.saturating_sub(at) < root_history);
+    }
+}
+
+impl KnownRoots {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            root_history: u64::MAX,
+            roots: ArcSwap::from_pointee(RootWindow::default()),
+        }
+    }
+
+    /// Sets the pool contract's root-history size, in root updates.
+    pub fn with_root_history(mut self, root_history: u64) -> Self {
+        self.root_history = root_history;
+        self
+    }
+
+    /// Records `root` as the root of a tree with `leaves` leaves, and drops
+    /// roots the tree has since grown `root_history` leaves past.
+    pub fn insert_at(&self, root: [u32; 8], leaves: u64) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.roots.rcu(|window| {
+            let mut window = RootWindow::clone(window);
+            window.leaves = window.leaves.max(leaves);
+            window.evict_expired(self.root_history);
+            self.push(&mut window, root, leaves);
+            window
+        });
+    }
+
+    /// Records a root the RPC confirmed. Its position in the history is
+    /// unknown, so it is only trusted until tree sync sees the next leaf.
+    pub fn insert(&self, root: [u32; 8]) {
+        if self.capacity == 0 || self.contains(&root) {
+            return;
+        }
+        self.roots.rcu(|window| {
+            let mut window = RootWindow::clone(window);
+            let at = window.leaves.saturating_sub(self.root_history.saturating_sub(1));
+            self.push(&mut window, root, at);
+            window
+        });
+    }
+
+    /// Adds `root`, evicting the oldest beyond capacity. A repeat keeps the
+    /// newer of the two leaf counts.
+    fn push(&self, window: &mut RootWindow, root: [u32; 8], leaves: u64) {
+        if let Some(entry) = window.roots.iter_mut().find(|(r, _)| *r == root) {
+            entry.1 = entry.1.max(leaves);
+            return;
+        }
+        if window.leaves.saturating_sub(leaves) >= self.root_history {
+            return;
+        }
+        if window.roots.len() >= self.capacity {
+            window.roots.pop_front();
+        }
+        window.roots.push_back((root, leaves));
+    }
+
+    /// Lock-free; safe to call from `spawn_blocking`.
+    pub fn contains(&self, root: &[u32; 8]) -> bool {
+        self.roots.load().roots.iter().any(|(r, _)| r == root)
+    }
+
+    /// Drops every root, e.g. after a reorg orphaned some of them.
+    pub fn clear(&self) {
+        self.roots.store(Arc::new(RootWindow::default()));
+    }
+}
+
+/// A copy of the tree cache taken when the tree's root was confirmed on-chain.
+#[derive(Debug, Clone)]
+struct TreeCheckpoint {
+    leaves: usize,
+    root: [u32; 8],
+    path: PathBuf,
+}
+
+/// What a sync did to the local tree to get back onto the canonical chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TreeRepair {
+    /// Rolled back to the checkpoint at this many leaves.
+    RolledBack { leaves: usize },
+    /// No checkpoint was on the canonical chain; replayed from genesis.
+    Rebuilt,
+}
+
 /// Background service that keeps the local merkle tree in sync with the
 /// on-chain pool and backfills pending note records.
 pub struct TreeSyncService {
     tree: Mutex<TreeSync>,
+    /// On-disk tree cache, discarded when a reorg forces a rebuild.
+    cache_path: PathBuf,
+    /// Rollback points, oldest first (at most `MAX_TREE_CHECKPOINTS`).
+    checkpoints: std::sync::Mutex<VecDeque<TreeCheckpoint>>,
+    /// Single-flight guard held for the whole sync/backfill cycle.
+    /// `tree` is only locked briefly around the swap, so without this a manual
+    /// resync could run concurrently with the background loop and clobber the
+    /// tree (and its on-disk cache) with a stale copy.
+    mutation_lock: Mutex<()>,
     pool_config: PoolClientConfig,
     store: Arc<InMemoryStore>,
     sync_interval: Duration,
+    /// Per-note backfill backoff: (base, ceiling) in seconds.
+    backfill_backoff: (u64, u64),
+    /// Pending notes backfilled per tree lock.
+    backfill_page_size: usize,
+    /// Verified roots shared with the prover's root check fast path.
+    known_roots: Option<Arc<KnownRoots>>,
+    /// Epoch seconds of the last successful `sync_once` (0 = never).
+    last_sync_ok: AtomicU64,
+    /// On-demand proofs are refused when the last successful sync is older
+    /// than this (0 = no limit).
+    max_proof_staleness_secs: u64,
+    /// Spreads sync RPC calls across weighted endpoints.
+    rpc_pool: Option<Arc<RpcPool>>,
+    /// Skips syncs while pool RPC is down (shared with the prover).
+    rpc_breaker: Option<Arc<CircuitBreaker>>,
+    /// Whether the current local root was confirmed on-chain by the last sync.
+    root_confirmed: AtomicBool,
+    /// Only serve and backfill paths while `root_confirmed` holds.
+    confirmed_roots_only: bool,
 }
 
 impl TreeSyncService {
@@ -43,11 +251,13 @@ impl TreeSyncService {
     ///
     /// `cache_path` — on-disk JSON cache for incremental sync (default: ~/.vm31/tree_cache.json).
     /// `sync_interval_secs` — polling interval for on-chain events.
+    /// `backfill_backoff` — (base, max) seconds between retries of a note that failed to match.
     pub fn new(
         pool_config: PoolClientConfig,
         store: Arc<InMemoryStore>,
         cache_path: Option<String>,
         sync_interval_secs: u64,
+        backfill_backoff: (u64, u64),
     ) -> Result<Self, String> {
         let path = cache_path
             .map(PathBuf::from)
@@ -64,12 +274,151 @@ impl TreeSyncService {
 
         Ok(Self {
             tree: Mutex::new(tree),
+            cache_path: path,
+            checkpoints: std::sync::Mutex::new(VecDeque::new()),
+            mutation_lock: Mutex::new(()),
             pool_config,
             store,
             sync_interval: Duration::from_secs(sync_interval_secs),
+            backfill_backoff,
+            backfill_page_size: DEFAULT_BACKFILL_PAGE_SIZE,
+            known_roots: None,
+            last_sync_ok: AtomicU64::new(0),
+            max_proof_staleness_secs: 0,
+            rpc_pool: None,
+            rpc_breaker: None,
+            root_confirmed: AtomicBool::new(false),
+            confirmed_roots_only: false,
         })
     }
 
+    /// Refuse on-demand proofs once the last successful sync is older than
+    /// `secs` (0 = no limit).
+    pub fn with_max_proof_staleness(mut self, secs: u64) -> Self {
+        self.max_proof_staleness_secs = secs;
+        self
+    }
+
+    /// Only hand out paths against a root the pool reports as known.
+    ///
+    /// When the local tree is ahead of the chain (events from a block the
+    /// pool's root history doesn't include yet) or behind a root the chain
+    /// has since reorged away, proofs are refused with `Unconfirmed` and
+    /// pending notes wait for a sync that reconciles the two.
+    pub fn with_confirmed_roots_only(mut self, enabled: bool) -> Self {
+        self.confirmed_roots_only = enabled;
+        self
+    }
+
+    /// Seconds since the last successful sync, or `None` if none yet.
+    pub fn last_sync_age_secs(&self) -> Option<u64> {
+        match self.last_sync_ok.load(Ordering::Relaxed) {
+            0 => None,
+            at => Some(now_epoch().saturating_sub(at)),
+        }
+    }
+
+    fn check_fresh(&self) -> Result<(), TreeSyncError> {
+        if self.max_proof_staleness_secs == 0 {
+            return Ok(());
+        }
+        let age = self.last_sync_age_secs();
+        match age {
+            Some(a) if a <= self.max_proof_staleness_secs => Ok(()),
+            _ => Err(TreeSyncError::Stale {
+                last_sync_age_secs: age,
+            }),
+        }
+    }
+
+    /// Backfill pending notes `size` at a time, releasing the tree lock
+    /// between pages.
+    pub fn with_backfill_page_size(mut self, size: usize) -> Self {
+        self.backfill_page_size = size.max(1);
+        self
+    }
+
+    /// Publishes each verified root of the local tree into `known_roots`.
+    pub fn with_known_roots(mut self, known_roots: Arc<KnownRoots>) -> Self {
+        self.known_roots = Some(known_roots);
+        self
+    }
+
+    /// Syncs against an endpoint picked from `pool` each cycle.
+    pub fn with_rpc_pool(mut self, pool: Arc<RpcPool>) -> Self {
+        self.rpc_pool = Some(pool);
+        self
+    }
+
+    /// Skips sync RPC while `breaker` is open.
+    pub fn with_circuit_breaker(mut self, breaker: Arc<CircuitBreaker>) -> Self {
+        self.rpc_breaker = Some(breaker);
+        self
+    }
+
+    /// Puts the tree back after a sync, whatever its result. After a repair,
+    /// roots and note paths from the orphaned branch are dropped even if the
+    /// re-sync then failed: they are wrong against the repaired tree either
+    /// way. Returns how many notes were reset.
+    async fn install_tree(
+        &self,
+        tree: TreeSync,
+        repair: Option<TreeRepair>,
+        checkpoint: Option<TreeCheckpoint>,
+        confirmed: bool,
+    ) -> u32 {
+        let leaves = tree.size();
+        let tree = {
+            let mut guard = self.tree.lock().await;
+            *guard = tree;
+            guard
+        };
+        // A failed sync may have advanced the tree part way; don't vouch for it
+        self.root_confirmed.store(confirmed, Ordering::Relaxed);
+        self.update_checkpoints(repair, checkpoint);
+        if repair.is_none() {
+            return 0;
+        }
+        // Roots from the orphaned branch must not pass the prover's fast path
+        if let Some(known) = &self.known_roots {
+            known.clear();
+        }
+        let reset = invalidate_moved_notes(&self.store, |d| tree.find_commitment(d)).await;
+        warn!(
+            total_leaves = leaves,
+            notes_reset = reset,
+            "tree repaired after reorg; moved notes returned to pending"
+        );
+        reset
+    }
+
+    /// Drops checkpoints a repair orphaned and records `checkpoint`, deleting
+    /// the files of those that fall out of the window.
+    fn update_checkpoints(&self, repair: Option<TreeRepair>, checkpoint: Option<TreeCheckpoint>) {
+        let mut checkpoints = lock_checkpoints(&self.checkpoints);
+        let mut dropped: Vec<TreeCheckpoint> = Vec::new();
+        checkpoints.retain(|c| {
+            let keep = match repair {
+                Some(TreeRepair::RolledBack { leaves }) => c.leaves <= leaves,
+                Some(TreeRepair::Rebuilt) => false,
+                None => true,
+            };
+            if !keep {
+                dropped.push(c.clone());
+            }
+            keep
+        });
+        if let Some(checkpoint) = checkpoint {
+            checkpoints.push_back(checkpoint);
+        }
+        while checkpoints.len() > MAX_TREE_CHECKPOINTS {
+            dropped.extend(checkpoints.pop_front());
+        }
+        for c in dropped {
+            let _ = std::fs::remove_file(&c.path);
+        }
+    }
+
     /// Run the sync → backfill loop forever.
     pub async fn run(&self) {
         info!(interval_secs = self.sync_interval.as_secs(), "tree sync loop started");
@@ -78,22 +427,70 @@ impl TreeSyncService {
         loop {
             interval.tick().await;
 
+            let _guard = self.mutation_lock.lock().await;
+
             if let Err(e) = self.sync_once().await {
                 warn!(error = %e, "tree sync tick failed");
             }
 
-            if let Err(e) = self.backfill_pending().await {
+            if let Err(e) = self.backfill_pending(None).await {
                 warn!(error = %e, "backfill tick failed");
             }
         }
     }
 
+    /// Manually triggered sync followed by a backfill pass.
+    /// Returns `InProgress` instead of waiting if another cycle holds the lock.
+    pub async fn resync_now(&self) -> Result<u32, TreeSyncError> {
+        let _guard = self
+            .mutation_lock
+            .try_lock()
+            .map_err(|_| TreeSyncError::InProgress)?;
+        self.sync_once().await.map_err(TreeSyncError::Failed)?;
+        self.backfill_pending(None).await.map_err(TreeSyncError::Failed)
+    }
+
+    /// Sync, then backfill only `batch_id`'s notes, ignoring their backoff so
+    /// clients can fetch paths right after the batch finalizes.
+    /// Returns `InProgress` instead of waiting if another cycle holds the lock.
+    pub async fn backfill_batch_now(&self, batch_id: &str) -> Result<u32, TreeSyncError> {
+        let _guard = self
+            .mutation_lock
+            .try_lock()
+            .map_err(|_| TreeSyncError::InProgress)?;
+        self.sync_once().await.map_err(TreeSyncError::Failed)?;
+        self.backfill_pending(Some(batch_id))
+            .await
+            .map_err(TreeSyncError::Failed)
+    }
+
+    /// Manually triggered backfill pass against the current local tree.
+    /// Returns `InProgress` instead of waiting if another cycle holds the lock.
+    pub async fn backfill_now(&self) -> Result<u32, TreeSyncError> {
+        let _guard = self
+            .mutation_lock
+            .try_lock()
+            .map_err(|_| TreeSyncError::InProgress)?;
+        self.backfill_pending(None).await.map_err(TreeSyncError::Failed)
+    }
+
     /// Single sync: fetch on-chain events, append to local tree, verify root.
     ///
     /// Takes the tree out of the mutex, runs the blocking sync in spawn_blocking,
     /// then puts the (potentially updated) tree back.
+    ///
+    /// Callers must hold `mutation_lock`.
     async fn sync_once(&self) -> Result<(), String> {
-        let pool_cfg = self.pool_config.clone();
+        let permit = match self.rpc_breaker.as_deref().map(CircuitBreaker::try_acquire) {
+            Some(Err(secs)) => return Err(format!("pool RPC circuit open; next probe in {secs}s")),
+            Some(Ok(permit)) => Some(permit),
+            None => None,
+        };
+        let endpoint = self.rpc_pool.as_ref().map(|pool| pool.pick());
+        let pool_cfg = match (&self.rpc_pool, endpoint) {
+            (Some(pool), Some(idx)) => pool.config_for(idx, &self.pool_config),
+            _ => self.pool_config.clone(),
+        };
 
         // Take the tree out so we can move it into spawn_blocking
         let tree = {
@@ -101,29 +498,113 @@ impl TreeSyncService {
             std::mem::replace(&mut *guard, TreeSync::new())
         };
 
-        let (tree, result) = tokio::task::spawn_blocking(move || {
-            let pool = PoolClient::new(pool_cfg);
+        let cache_path = self.cache_path.clone();
+        let checkpoints: Vec<TreeCheckpoint> = lock_checkpoints(&self.checkpoints).iter().cloned().collect();
+        let (tree, repair, checkpoint, outcome) = tokio::task::spawn_blocking(move || {
             let mut tree = tree;
-            let result = tree.sync(&pool);
-            (tree, result)
+            let mut repair = None;
+            // Backups in `verify_rpc_urls` are tried in order if a sync fails;
+            // each attempt resumes from wherever the tree got to
+            let outcome = with_failover(&pool_cfg, |cfg| {
+                let pool = PoolClient::new(cfg);
+                let is_known = |root: &[u32; 8]| {
+                    pool.is_known_root(&root.map(M31::from_u32_unchecked))
+                        .map_err(|e| format!("reorg check: {e}"))
+                };
+                // Set when the local root is unknown and no checkpoint is
+                // known either: expiry if a forward sync lands on a known
+                // root, a reorg older than every checkpoint otherwise
+                let mut unanchored = false;
+                match root_orphaned(&tree, &pool) {
+                    Ok(false) => {}
+                    Ok(true) => match find_fork_point(&checkpoints, is_known) {
+                        Ok(Some(fork)) => match restore_checkpoint(fork, &cache_path) {
+                            Ok(restored) => {
+                                warn!(from = tree.size(), to = fork.leaves, "chain reorged; rolled tree back to last common checkpoint");
+                                tree = restored;
+                                repair = Some(TreeRepair::RolledBack { leaves: fork.leaves });
+                            }
+                            Err(e) => {
+                                warn!(error = %e, "checkpoint unusable; syncing forward instead");
+                                unanchored = true;
+                            }
+                        },
+                        Ok(None) => unanchored = true,
+                        Err(e) => return ((Err(e), false), false),
+                    },
+                    Err(e) => return ((Err(e), false), false),
+                }
+                let sync = |tree: &mut TreeSync| {
+                    let result = tree.sync(&pool).map_err(|e| format!("{e}"));
+                    let confirmed = match &result {
+                        Ok(r) => r.root_verified || root_confirmed(tree, &pool),
+                        Err(_) => false,
+                    };
+                    (result, confirmed)
+                };
+                let (mut result, mut confirmed) = sync(&mut tree);
+                if unanchored && result.is_ok() {
+                    if confirmed {
+                        info!(leaves = tree.size(), "local root had rolled off the root history; synced forward without a rebuild");
+                    } else {
+                        warn!(leaves = tree.size(), "local tree diverged below every checkpoint; rebuilding from genesis");
+                        match rebuild_tree(&cache_path) {
+                            Ok(fresh) => tree = fresh,
+                            Err(e) => return ((Err(e), false), false),
+                        }
+                        repair = Some(TreeRepair::Rebuilt);
+                        (result, confirmed) = sync(&mut tree);
+                    }
+                }
+                let ok = result.is_ok();
+                ((result, confirmed), ok)
+            });
+            let newest = match repair {
+                Some(TreeRepair::RolledBack { leaves }) => Some(leaves),
+                Some(TreeRepair::Rebuilt) => None,
+                None => checkpoints.last().map(|c| c.leaves),
+            };
+            let (result, confirmed) = &outcome.value;
+            let checkpoint = if result.is_ok() && *confirmed && newest.is_none_or(|n| tree.size() > n) {
+                save_checkpoint(&tree, &cache_path)
+                    .map_err(|e| warn!(error = %e, "failed to checkpoint tree"))
+                    .ok()
+            } else {
+                None
+            };
+            (tree, repair, checkpoint, outcome)
         })
         .await
         .map_err(|e| format!("join error: {e}"))?;
+        let primary_ok = outcome.primary_ok();
+        let ((result, confirmed), attempt) = (outcome.value, outcome.attempt);
 
-        // Put the tree back regardless of sync result
-        {
-            let mut guard = self.tree.lock().await;
-            *guard = tree;
+        let root = tree.root();
+        self.install_tree(tree, repair, checkpoint, confirmed).await;
+
+        if let (Some(pool), Some(idx)) = (&self.rpc_pool, endpoint) {
+            pool.record(idx, primary_ok);
+        }
+        if let Some(permit) = permit {
+            permit.record(result.is_ok());
         }
+        let result = result?;
+        self.last_sync_ok.store(now_epoch(), Ordering::Relaxed);
 
-        let result = result.map_err(|e| format!("{e}"))?;
+        if confirmed {
+            if let Some(known) = &self.known_roots {
+                known.insert_at(root.map(|m| m.0), result.total_leaves as u64);
+            }
+        }
 
         if result.events_added > 0 {
             info!(
                 total_leaves = result.total_leaves,
                 events_added = result.events_added,
                 root_verified = result.root_verified,
+                root_confirmed = confirmed,
                 cross_verified = result.cross_verified,
+                failover_attempt = attempt,
                 "tree synced"
             );
         } else {
@@ -137,18 +618,64 @@ impl TreeSyncService {
     }
 
     /// Backfill pending note records (merkle_root == [0;8]) with real proofs.
-    async fn backfill_pending(&self) -> Result<(), String> {
-        let pending = self
-            .store
-            .list_pending_notes()
-            .await
-            .map_err(|e| format!("list pending: {e}"))?;
+    /// Returns the number of notes filled.
+    ///
+    /// Pending notes are read `backfill_page_size` at a time and the tree
+    /// lock is released between pages, so a large backlog doesn't stall
+    /// proof lookups. Notes that fail to match back off exponentially
+    /// (metadata is stored on the note record), and within a page the most
+    /// recently created notes are tried first. With `batch_id`, only that
+    /// batch's notes are tried, backoff or not.
+    ///
+    /// Callers must hold `mutation_lock`.
+    async fn backfill_pending(&self, batch_id: Option<&str>) -> Result<u32, String> {
+        if self.confirmed_roots_only && !self.root_confirmed.load(Ordering::Relaxed) {
+            // Not a miss: the notes may well be in the tree, just not under a
+            // root a withdrawal could use yet.
+            debug!("local root unconfirmed; deferring backfill");
+            return Ok(0);
+        }
+
+        let now = now_epoch();
+        let mut filled = 0u32;
+        let mut cursor = None;
+        loop {
+            let (page, next) = self
+                .store
+                .pending_notes_page_shared(cursor.as_deref(), self.backfill_page_size);
+            filled += self.backfill_page(page, batch_id, now).await;
+            match next {
+                Some(c) => cursor = Some(c),
+                None => break,
+            }
+        }
+
+        if filled > 0 {
+            info!(filled, "backfilled note merkle paths");
+        }
 
+        Ok(filled)
+    }
+
+    /// Backfills one page of pending notes under a single tree lock.
+    async fn backfill_page(
+        &self,
+        mut pending: Vec<Arc<NoteRecord>>,
+        batch_id: Option<&str>,
+        now: u64,
+    ) -> u32 {
+        let (base_secs, max_secs) = self.backfill_backoff;
+        let total = pending.len();
+        match batch_id {
+            Some(id) => pending.retain(|n| n.batch_id == id),
+            None => pending.retain(|n| n.backfill_due(now, base_secs, max_secs)),
+        }
         if pending.is_empty() {
-            return Ok(());
+            return 0;
         }
+        pending.sort_by(|a, b| b.created_at.cmp(&a.created_at));
 
-        debug!(count = pending.len(), "backfilling pending notes");
+        debug!(count = pending.len(), skipped = total - pending.len(), "backfilling pending notes");
 
         let tree = self.tree.lock().await;
         let mut filled = 0u32;
@@ -156,7 +683,7 @@ impl TreeSyncService {
         for note in &pending {
             // Try to match the note to an on-chain leaf.
             // The commitment_digest field is the on-chain Poseidon hash.
-            // If not set, try to find by the FNV commitment key stored in note.commitment.
+            // If not set, try to find by the SHA-256 commitment key stored in note.commitment.
             let digest = match note.commitment_digest {
                 Some(raw) => {
                     let d: Digest = [
@@ -181,6 +708,7 @@ impl TreeSyncService {
                     // This note needs the frontend or prover to supply the on-chain
                     // commitment before we can backfill.
                     debug!(commitment = %note.commitment, "skipping note without commitment_digest");
+                    self.record_miss(&note.commitment, now).await;
                     continue;
                 }
             };
@@ -188,7 +716,8 @@ impl TreeSyncService {
             let leaf_index = match leaf_index {
                 Some(idx) => idx,
                 None => {
-                    // Not yet on-chain (or not synced far enough). Will retry next tick.
+                    // Not yet on-chain (or not synced far enough). Retry after backoff.
+                    self.record_miss(&note.commitment, now).await;
                     continue;
                 }
             };
@@ -218,7 +747,7 @@ impl TreeSyncService {
                 root[7].0,
             ];
 
-            let mut updated = note.clone();
+            let mut updated = NoteRecord::clone(note);
             updated.merkle_path = MerklePathRecord {
                 siblings,
                 index: proof.index,
@@ -232,37 +761,195 @@ impl TreeSyncService {
             }
         }
 
-        if filled > 0 {
-            info!(filled, "backfilled note merkle paths");
-        }
+        filled
+    }
 
-        Ok(())
+    async fn record_miss(&self, commitment: &str, now: u64) {
+        if let Err(e) = self.store.record_backfill_miss(commitment, now).await {
+            warn!(commitment = %commitment, error = %e, "failed to record backfill attempt");
+        }
     }
 
     /// On-demand proof lookup. Returns proof if the commitment is in the synced tree.
+    /// Fails with `Stale` rather than serve a proof against a root the chain
+    /// may have moved past (see `with_max_proof_staleness`), and with
+    /// `Unconfirmed` if the local root isn't known on-chain
+    /// (see `with_confirmed_roots_only`).
     ///
     /// `commitment_hex` — 0x-prefixed hex of the 8 × u32 Poseidon digest.
-    pub async fn get_proof(&self, commitment_hex: &str) -> Option<ProofResult> {
-        let digest = parse_commitment_hex(commitment_hex)?;
+    pub async fn get_proof(&self, commitment_hex: &str) -> Result<Option<ProofResult>, TreeSyncError> {
+        Ok(self.get_proofs(&[commitment_hex]).await?.pop().flatten())
+    }
+
+    /// `get_proof` for several commitments under a single tree lock, so all
+    /// proofs share one root. Index-aligned with `commitment_hexes`.
+    pub async fn get_proofs(
+        &self,
+        commitment_hexes: &[&str],
+    ) -> Result<Vec<Option<ProofResult>>, TreeSyncError> {
+        let digests: Vec<Option<Digest>> =
+            commitment_hexes.iter().map(|hex| parse_commitment_hex(hex)).collect();
+        if digests.iter().all(Option::is_none) {
+            return Ok(digests.iter().map(|_| None).collect());
+        }
+        self.check_fresh()?;
+        if self.confirmed_roots_only && !self.root_confirmed.load(Ordering::Relaxed) {
+            return Err(TreeSyncError::Unconfirmed);
+        }
 
         let tree = self.tree.lock().await;
-        let leaf_index = tree.find_commitment(&digest)?;
-        let proof = tree.prove(leaf_index).ok()?;
         let root = tree.root();
+        let root = [
+            root[0].0, root[1].0, root[2].0, root[3].0, root[4].0, root[5].0, root[6].0,
+            root[7].0,
+        ];
+        Ok(digests
+            .into_iter()
+            .map(|digest| {
+                let digest = digest?;
+                let proof = tree.prove(tree.find_commitment(&digest)?).ok()?;
+                Some(ProofResult {
+                    leaf: digest.map(|m| m.0),
+                    siblings: proof
+                        .siblings
+                        .iter()
+                        .map(|s| [s[0].0, s[1].0, s[2].0, s[3].0, s[4].0, s[5].0, s[6].0, s[7].0])
+                        .collect(),
+                    index: proof.index,
+                    root,
+                })
+            })
+            .collect())
+    }
+}
 
-        Some(ProofResult {
-            siblings: proof
-                .siblings
-                .iter()
-                .map(|s| [s[0].0, s[1].0, s[2].0, s[3].0, s[4].0, s[5].0, s[6].0, s[7].0])
-                .collect(),
-            index: proof.index,
-            root: [
-                root[0].0, root[1].0, root[2].0, root[3].0, root[4].0, root[5].0, root[6].0,
-                root[7].0,
-            ],
-        })
+/// True if the local tree has leaves but its root is no longer in the
+/// pool's root history: either events it was built from were reorged out, or
+/// the root rolled off the history window.
+fn root_orphaned(tree: &TreeSync, pool: &PoolClient) -> Result<bool, String> {
+    if tree.size() == 0 {
+        return Ok(false);
+    }
+    let known = pool
+        .is_known_root(&tree.root())
+        .map_err(|e| format!("reorg check: {e}"))?;
+    if !known {
+        warn!(
+            leaves = tree.size(),
+            "local root unknown on-chain (reorg, or offline past the root history)"
+        );
+    }
+    Ok(!known)
+}
+
+fn lock_checkpoints(
+    checkpoints: &std::sync::Mutex<VecDeque<TreeCheckpoint>>,
+) -> std::sync::MutexGuard<'_, VecDeque<TreeCheckpoint>> {
+    checkpoints.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// The newest checkpoint (`checkpoints` is oldest first) whose root the pool
+/// still knows. The pool's root history drops roots oldest first, so a known
+/// checkpoint under an unknown tip means the chain forked after it: it is the
+/// last common ancestor. `None` if no checkpoint is known.
+fn find_fork_point<'a>(
+    checkpoints: &'a [TreeCheckpoint],
+    mut is_known: impl FnMut(&[u32; 8]) -> Result<bool, String>,
+) -> Result<Option<&'a TreeCheckpoint>, String> {
+    for checkpoint in checkpoints.iter().rev() {
+        if is_known(&checkpoint.root)? {
+            return Ok(Some(checkpoint));
+        }
+    }
+    Ok(None)
+}
+
+/// Where the checkpoint of a tree with `leaves` leaves is kept.
+fn checkpoint_path(cache_path: &std::path::Path, leaves: usize) -> PathBuf {
+    let mut path = cache_path.as_os_str().to_owned();
+    path.push(format!(".checkpoint-{leaves}"));
+    PathBuf::from(path)
+}
+
+/// Copies the tree cache aside as a rollback point for `tree`.
+fn save_checkpoint(tree: &TreeSync, cache_path: &std::path::Path) -> Result<TreeCheckpoint, String> {
+    let leaves = tree.size();
+    let path = checkpoint_path(cache_path, leaves);
+    std::fs::copy(cache_path, &path).map_err(|e| format!("copy {}: {e}", path.display()))?;
+    Ok(TreeCheckpoint { leaves, root: tree.root().map(|m| m.0), path })
+}
+
+/// Replaces the tree cache with `checkpoint` and loads it, checking it still
+/// holds the tree the checkpoint was taken of.
+fn restore_checkpoint(checkpoint: &TreeCheckpoint, cache_path: &std::path::Path) -> Result<TreeSync, String> {
+    std::fs::copy(&checkpoint.path, cache_path)
+        .map_err(|e| format!("restore {}: {e}", checkpoint.path.display()))?;
+    let tree = TreeSync::load_or_create(cache_path).map_err(|e| format!("failed to load checkpoint: {e}"))?;
+    if tree.size() != checkpoint.leaves || tree.root().map(|m| m.0) != checkpoint.root {
+        return Err(format!("checkpoint {} does not match its root", checkpoint.path.display()));
+    }
+    Ok(tree)
+}
+
+/// True if the pool reports the local root as known. An RPC failure counts
+/// as unconfirmed: it is retried on the next sync.
+fn root_confirmed(tree: &TreeSync, pool: &PoolClient) -> bool {
+    match pool.is_known_root(&tree.root()) {
+        Ok(known) => known,
+        Err(e) => {
+            debug!(error = %e, "root confirmation check failed");
+            false
+        }
+    }
+}
+
+/// Discards the cached tree so the next sync replays every canonical event.
+fn rebuild_tree(cache_path: &std::path::Path) -> Result<TreeSync, String> {
+    match std::fs::remove_file(cache_path) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(format!("failed to discard tree cache: {e}")),
+    }
+    TreeSync::load_or_create(cache_path).map_err(|e| format!("failed to recreate tree cache: {e}"))
+}
+
+/// Resets backfilled notes whose commitment is no longer at the leaf index
+/// their path was built for (`leaf_index` looks up the canonical tree), so
+/// backfill re-proves them. Returns how many were reset.
+async fn invalidate_moved_notes(
+    store: &InMemoryStore,
+    leaf_index: impl Fn(&Digest) -> Option<usize>,
+) -> u32 {
+    let mut reset = 0u32;
+    for commitment in store.note_commitments() {
+        let note = match store.get_note_shared(&commitment) {
+            Ok(Some(n)) => n,
+            Ok(None) => continue,
+            Err(e) => {
+                warn!(commitment = %commitment, error = %e, "failed to load note for reorg check");
+                continue;
+            }
+        };
+        let Some(raw) = note.commitment_digest else {
+            continue;
+        };
+        if note.merkle_root == [0; 8] {
+            continue;
+        }
+        if leaf_index(&raw.map(M31::from_u32_unchecked)) == Some(note.merkle_path.index) {
+            continue;
+        }
+        let mut updated = NoteRecord::clone(&note);
+        updated.merkle_path = MerklePathRecord { siblings: vec![], index: 0 };
+        updated.merkle_root = [0; 8];
+        updated.backfill_attempts = 0;
+        updated.last_backfill_attempt = 0;
+        match store.save_note(&commitment, &updated).await {
+            Ok(()) => reset += 1,
+            Err(e) => warn!(commitment = %commitment, error = %e, "failed to reset reorged note"),
+        }
     }
+    reset
 }
 
 /// Parse "0xABCDEF..." (64 hex chars after prefix) into [M31; 8].
@@ -294,6 +981,367 @@ mod tests {
         assert_eq!(digest[7].0, 0xff);
     }
 
+    fn make_service() -> TreeSyncService {
+        TreeSyncService {
+            tree: Mutex::new(TreeSync::new()),
+            cache_path: std::env::temp_dir().join("vm31-test-tree-cache.json"),
+            checkpoints: std::sync::Mutex::new(VecDeque::new()),
+            mutation_lock: Mutex::new(()),
+            pool_config: PoolClientConfig {
+                rpc_url: "http://localhost:5050".into(),
+                pool_address: "0x1".into(),
+                network: "sepolia".into(),
+                verify_rpc_urls: vec![],
+            },
+            store: Arc::new(InMemoryStore::new()),
+            sync_interval: Duration::from_secs(15),
+            backfill_backoff: (15, 3600),
+            backfill_page_size: DEFAULT_BACKFILL_PAGE_SIZE,
+            known_roots: None,
+            last_sync_ok: AtomicU64::new(0),
+            max_proof_staleness_secs: 0,
+            rpc_pool: None,
+            rpc_breaker: None,
+            root_confirmed: AtomicBool::new(false),
+            confirmed_roots_only: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_proof_refused_when_tree_stale() {
+        let svc = make_service().with_max_proof_staleness(60);
+        let hex = "0x0000002a000000630000000700000001000000020000000300000004000000ff";
+        assert!(matches!(
+            svc.get_proof(hex).await,
+            Err(TreeSyncError::Stale { last_sync_age_secs: None })
+        ));
+
+        svc.last_sync_ok.store(now_epoch() - 120, Ordering::Relaxed);
+        assert!(matches!(
+            svc.get_proof(hex).await,
+            Err(TreeSyncError::Stale { last_sync_age_secs: Some(age) }) if age >= 120
+        ));
+
+        svc.last_sync_ok.store(now_epoch(), Ordering::Relaxed);
+        assert!(svc.get_proof(hex).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_unconfirmed_root_defers_proofs_and_backfill() {
+        // Ahead: the local tree holds events whose root the pool doesn't
+        // know yet. Proofs are refused and notes wait without backing off.
+        let svc = make_service().with_confirmed_roots_only(true);
+        let hex = "0x0000002a000000630000000700000001000000020000000300000004000000ff";
+        assert!(matches!(svc.get_proof(hex).await, Err(TreeSyncError::Unconfirmed)));
+
+        let note = NoteRecord {
+            commitment: "n1".into(),
+            merkle_path: MerklePathRecord { siblings: vec![], index: 0 },
+            merkle_root: [0; 8],
+            batch_id: "batch-a".into(),
+            created_at: now_epoch(),
+            commitment_digest: Some([1; 8]),
+            note_index_in_batch: 0,
+            client_ref: None,
+            backfill_attempts: 0,
+            last_backfill_attempt: 0,
+        };
+        svc.store.save_note("n1", &note).await.unwrap();
+        assert_eq!(svc.backfill_pending(None).await.unwrap(), 0);
+        let n1 = svc.store.get_note("n1").await.unwrap().unwrap();
+        assert_eq!(n1.backfill_attempts, 0);
+
+        // Behind (or caught up): the local root is in the pool's history, so
+        // paths against it are served; commitments the tree hasn't synced yet
+        // are simply not found and stay pending.
+        svc.root_confirmed.store(true, Ordering::Relaxed);
+        assert!(svc.get_proof(hex).await.unwrap().is_none());
+        assert_eq!(svc.backfill_pending(None).await.unwrap(), 0);
+        let n1 = svc.store.get_note("n1").await.unwrap().unwrap();
+        assert_eq!(n1.backfill_attempts, 1);
+    }
+
+    #[tokio::test]
+    async fn test_unconfirmed_root_served_when_not_required() {
+        let svc = make_service();
+        let hex = "0x0000002a000000630000000700000001000000020000000300000004000000ff";
+        assert!(svc.get_proof(hex).await.unwrap().is_none());
+    }
+
+    #[test]
+    fn test_known_roots_bounded_and_deduplicated() {
+        let known = KnownRoots::new(2);
+        known.insert([1; 8]);
+        known.insert([1; 8]);
+        known.insert([2; 8]);
+        assert!(known.contains(&[1; 8]));
+        known.insert([3; 8]);
+        assert!(!known.contains(&[1; 8]));
+        assert!(known.contains(&[2; 8]) && known.contains(&[3; 8]));
+
+        let disabled = KnownRoots::new(0);
+        disabled.insert([1; 8]);
+        assert!(!disabled.contains(&[1; 8]));
+    }
+
+    #[tokio::test]
+    async fn test_manual_backfill_rejected_while_sync_in_progress() {
+        let svc = make_service();
+        {
+            let _held = svc.mutation_lock.lock().await;
+            assert!(matches!(svc.backfill_now().await, Err(TreeSyncError::InProgress)));
+        }
+        assert_eq!(svc.backfill_now().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_batch_backfill_scoped_to_batch() {
+        let svc = make_service();
+        {
+            let _held = svc.mutation_lock.lock().await;
+            assert!(matches!(
+                svc.backfill_batch_now("batch-a").await,
+                Err(TreeSyncError::InProgress)
+            ));
+        }
+
+        let now = now_epoch();
+        for (commitment, batch) in [("a1", "batch-a"), ("b1", "batch-b")] {
+            let note = NoteRecord {
+                commitment: commitment.into(),
+                merkle_path: MerklePathRecord { siblings: vec![], index: 0 },
+                merkle_root: [0; 8],
+                batch_id: batch.into(),
+                created_at: now,
+                commitment_digest: None,
+                note_index_in_batch: 0,
+                client_ref: None,
+                backfill_attempts: 1,
+                // Still backing off: a scoped pass must try it anyway
+                last_backfill_attempt: now,
+            };
+            svc.store.save_note(commitment, &note).await.unwrap();
+        }
+
+        assert_eq!(svc.backfill_pending(Some("batch-a")).await.unwrap(), 0);
+        let a1 = svc.store.get_note("a1").await.unwrap().unwrap();
+        let b1 = svc.store.get_note("b1").await.unwrap().unwrap();
+        assert_eq!((a1.backfill_attempts, b1.backfill_attempts), (2, 1));
+    }
+
+    #[tokio::test]
+    async fn test_backfill_pages_through_every_pending_note() {
+        let svc = make_service().with_backfill_page_size(2);
+        for i in 0..5 {
+            let note = NoteRecord {
+                commitment: format!("n{i}"),
+                merkle_path: MerklePathRecord { siblings: vec![], index: 0 },
+                merkle_root: [0; 8],
+                batch_id: "batch-a".into(),
+                created_at: now_epoch(),
+                commitment_digest: Some([i + 1; 8]),
+                note_index_in_batch: i as usize,
+                client_ref: None,
+                backfill_attempts: 0,
+                last_backfill_attempt: 0,
+            };
+            svc.store.save_note(&note.commitment, &note).await.unwrap();
+        }
+
+        // Not in the (empty) tree: each note is tried exactly once
+        assert_eq!(svc.backfill_pending(None).await.unwrap(), 0);
+        for i in 0..5 {
+            let note = svc.store.get_note(&format!("n{i}")).await.unwrap().unwrap();
+            assert_eq!(note.backfill_attempts, 1, "n{i}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reorg_resets_moved_notes() {
+        let store = InMemoryStore::new();
+        let leaf = |n: u32| [n; 8];
+        // Orphaned branch: A, B, C at leaves 0..3, all backfilled against it
+        let orphaned = [leaf(1), leaf(2), leaf(3)];
+        for (i, digest) in orphaned.iter().enumerate() {
+            let note = NoteRecord {
+                commitment: format!("n{i}"),
+                merkle_path: MerklePathRecord { siblings: vec![[9; 8]], index: i },
+                merkle_root: [5; 8],
+                batch_id: "batch-a".into(),
+                created_at: now_epoch(),
+                commitment_digest: Some(*digest),
+                note_index_in_batch: i,
+                client_ref: None,
+                backfill_attempts: 2,
+                last_backfill_attempt: now_epoch(),
+            };
+            store.save_note(&format!("n{i}"), &note).await.unwrap();
+        }
+
+        // Canonical branch keeps A, replaces B's slot with X and re-includes
+        // B after it; C is dropped
+        let canonical = [leaf(1), leaf(7), leaf(2)];
+        let lookup = |d: &Digest| canonical.iter().position(|l| *l == d.map(|m| m.0));
+        assert_eq!(invalidate_moved_notes(&store, lookup).await, 2);
+
+        let a = store.get_note("n0").await.unwrap().unwrap();
+        assert_eq!((a.merkle_root, a.merkle_path.index), ([5; 8], 0));
+        let mut pending: Vec<_> = store
+            .list_pending_notes()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|n| (n.commitment, n.backfill_attempts, n.merkle_path.siblings.len()))
+            .collect();
+        pending.sort();
+        assert_eq!(pending, vec![("n1".into(), 0, 0), ("n2".into(), 0, 0)]);
+
+        // Converged: a second pass over the same canonical tree is a no-op
+        assert_eq!(invalidate_moved_notes(&store, lookup).await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_failed_resync_after_reorg_still_resets_notes() {
+        let svc = make_service();
+        let note = NoteRecord {
+            commitment: "n0".into(),
+            merkle_path: MerklePathRecord { siblings: vec![[9; 8]], index: 4 },
+            merkle_root: [5; 8],
+            batch_id: "batch-a".into(),
+            created_at: now_epoch(),
+            commitment_digest: Some([3; 8]),
+            note_index_in_batch: 0,
+            client_ref: None,
+            backfill_attempts: 2,
+            last_backfill_attempt: now_epoch(),
+        };
+        svc.store.save_note("n0", &note).await.unwrap();
+
+        // Without a repair the note's path is left alone
+        assert_eq!(svc.install_tree(TreeSync::new(), None, None, false).await, 0);
+        assert!(svc.store.list_pending_notes().await.unwrap().is_empty());
+
+        // Rolled back to genesis and the re-sync failed: the tree holds none of
+        // the orphaned leaves, so the note goes back to pending
+        let repair = Some(TreeRepair::RolledBack { leaves: 0 });
+        assert_eq!(svc.install_tree(TreeSync::new(), repair, None, false).await, 1);
+        let pending = svc.store.list_pending_notes().await.unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!((pending[0].merkle_root, pending[0].backfill_attempts), ([0; 8], 0));
+        assert!(!svc.root_confirmed.load(Ordering::Relaxed));
+    }
+
+    /// Roots after each leaf of an event stream (a stand-in for the tree).
+    fn stream_roots(events: &[u32]) -> Vec<[u32; 8]> {
+        let mut acc = [0u32; 8];
+        events
+            .iter()
+            .map(|&e| {
+                for (i, a) in acc.iter_mut().enumerate() {
+                    *a = a.wrapping_mul(31).wrapping_add(e ^ i as u32);
+                }
+                acc
+            })
+            .collect()
+    }
+
+    fn checkpoints_of(roots: &[[u32; 8]], at: &[usize]) -> Vec<TreeCheckpoint> {
+        at.iter()
+            .map(|&leaves| TreeCheckpoint { leaves, root: roots[leaves - 1], path: PathBuf::from(format!("ckpt-{leaves}")) })
+            .collect()
+    }
+
+    #[test]
+    fn test_fork_point_found_between_diverging_streams() {
+        // The chain's history holds its last 6 roots
+        let canonical = stream_roots(&[1, 2, 3, 4, 5, 6, 7, 8]);
+        let history = &canonical[canonical.len() - 6..];
+        let is_known = |root: &[u32; 8]| Ok(history.contains(root));
+
+        // Local tree followed a branch that diverged after leaf 4
+        let local = stream_roots(&[1, 2, 3, 4, 9, 10]);
+        assert!(!history.contains(local.last().unwrap()));
+        let checkpoints = checkpoints_of(&local, &[2, 4, 5, 6]);
+        let fork = find_fork_point(&checkpoints, is_known).unwrap().unwrap();
+        assert_eq!(fork.leaves, 4);
+        assert_eq!(fork.root, canonical[3]);
+
+        // Forked before every checkpoint: no common ancestor to roll back to
+        let local = stream_roots(&[1, 11, 12, 13]);
+        assert!(find_fork_point(&checkpoints_of(&local, &[2, 3, 4]), is_known).unwrap().is_none());
+
+        // Canonical prefix whose roots all rolled off the history: also
+        // unanchored, and a forward sync (not a rebuild) tells it apart
+        let checkpoints = checkpoints_of(&canonical, &[1, 2]);
+        assert!(find_fork_point(&checkpoints, is_known).unwrap().is_none());
+        assert!(is_known(&canonical[7]).unwrap());
+
+        let failing = |_: &[u32; 8]| Err::<bool, _>("timeout".to_string());
+        assert!(find_fork_point(&checkpoints, failing).is_err());
+    }
+
+    #[test]
+    fn test_rollback_drops_orphaned_checkpoints() {
+        let svc = make_service();
+        let dir = std::env::temp_dir();
+        let roots = stream_roots(&[1, 2, 3, 4, 5]);
+        let mut saved = Vec::new();
+        for leaves in [2, 3, 5] {
+            let path = dir.join(format!("vm31-test-ckpt-{}-{leaves}", uuid::Uuid::new_v4()));
+            std::fs::write(&path, b"{}").unwrap();
+            saved.push(TreeCheckpoint { leaves, root: roots[leaves - 1], path });
+        }
+        for c in &saved {
+            svc.update_checkpoints(None, Some(c.clone()));
+        }
+
+        svc.update_checkpoints(Some(TreeRepair::RolledBack { leaves: 3 }), None);
+        let kept: Vec<_> = lock_checkpoints(&svc.checkpoints).iter().map(|c| c.leaves).collect();
+        assert_eq!(kept, vec![2, 3]);
+        assert!(saved[1].path.exists() && !saved[2].path.exists());
+
+        svc.update_checkpoints(Some(TreeRepair::Rebuilt), None);
+        assert!(lock_checkpoints(&svc.checkpoints).is_empty());
+        assert!(!saved[0].path.exists() && !saved[1].path.exists());
+    }
+
+    #[test]
+    fn test_known_roots_evicted_by_leaf_distance() {
+        let known = KnownRoots::new(64).with_root_history(3);
+        known.insert_at([1; 8], 10);
+        known.insert_at([2; 8], 11);
+        known.insert_at([3; 8], 12);
+        assert!(known.contains(&[1; 8]));
+        // Many syncs without new leaves keep the window as it is
+        for _ in 0..10 {
+            known.insert_at([3; 8], 12);
+        }
+        assert!(known.contains(&[1; 8]));
+
+        // Three leaves past root 1: it may have rolled off on-chain
+        known.insert_at([4; 8], 13);
+        assert!(!known.contains(&[1; 8]));
+        assert!(known.contains(&[2; 8]) && known.contains(&[4; 8]));
+        // A batch of leaves in one update skips several roots at once
+        known.insert_at([5; 8], 20);
+        assert!(!known.contains(&[2; 8]) && !known.contains(&[4; 8]));
+
+        // An RPC-confirmed root lasts only until the tree grows
+        known.insert([6; 8]);
+        assert!(known.contains(&[6; 8]));
+        known.insert_at([7; 8], 21);
+        assert!(!known.contains(&[6; 8]));
+        assert!(known.contains(&[5; 8]) && known.contains(&[7; 8]));
+    }
+
+    #[test]
+    fn test_known_roots_clear() {
+        let known = KnownRoots::new(4);
+        known.insert([1; 8]);
+        known.clear();
+        assert!(!known.contains(&[1; 8]));
+    }
+
     #[test]
     fn test_parse_commitment_hex_invalid() {
         assert!(parse_commitment_hex("0x1234").is_none());